@@ -0,0 +1,58 @@
+// Generated by bindings/idl/generate.py from bindings/idl/cassette_loader.wit. Do not edit.
+
+#ifndef CASSETTE_LOADER_H
+#define CASSETTE_LOADER_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Cassette Cassette;
+
+// How duplicate events are filtered from responses
+typedef enum {
+    // Drop events already returned since the last REQ or CLOSE
+    CASSETTE_DEDUP_POLICY_PER_SUBSCRIPTION = 0,
+    // Return every event the cassette produces
+    CASSETTE_DEDUP_POLICY_NONE = 1,
+} CassetteDedupPolicy;
+
+// Fallible functions return 0 on success and -1 on error, writing their result
+// through `out`. list<string> results are JSON arrays. Returned strings are owned
+// by the caller and released with cassette_string_free.
+
+// Message of the last error on this thread, or NULL
+const char *cassette_last_error(void);
+
+// Release a string returned by the loader
+void cassette_string_free(char *s);
+
+// Release a cassette handle
+void cassette_free(Cassette *self);
+
+// Load a cassette from a .cassette/.wasm file
+int32_t cassette_load(const char *path, bool debug, Cassette **out);
+
+// Send any NIP-01 message; REQ responses are collected until EOSE
+int32_t cassette_send(Cassette *self, const char *message, char **out);
+
+// All events matching a filter (JSON object), as event JSON
+int32_t cassette_events(Cassette *self, const char *filter, char **out);
+
+// Number of events matching a filter (JSON object)
+int32_t cassette_count(Cassette *self, const char *filter, uint64_t *out);
+
+// NIP-11 relay information document
+int32_t cassette_info(Cassette *self, char **out);
+
+// Set how duplicate events are filtered from responses
+void cassette_set_dedup(Cassette *self, CassetteDedupPolicy policy);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // CASSETTE_LOADER_H
@@ -5,6 +5,7 @@ import (
 	"fmt"
 	"strings"
 	"sync"
+	"time"
 
 	"github.com/bytecodealliance/wasmtime-go/v23"
 )
@@ -16,6 +17,14 @@ type SendResult struct {
 	Multiple []string
 }
 
+// Page is one page of a REQ's results from ReqPage
+type Page struct {
+	// Event JSON strings, in the order the cassette serves them
+	Events []string
+	// Where the next page starts; nil on the last page
+	NextCursor *uint64
+}
+
 // EventTracker manages event deduplication
 type EventTracker struct {
 	mu         sync.Mutex
@@ -161,7 +170,7 @@ func LoadCassette(path string, debug bool) (*Cassette, error) {
 	// Get exported functions
 	exports := make(map[string]*wasmtime.Func)
 	requiredFuncs := []string{"send", "info", "dealloc_string"}
-	optionalFuncs := []string{"describe"}
+	optionalFuncs := []string{"describe", "set_current_time", "req_page"}
 	
 	for _, name := range requiredFuncs {
 		fn := instance.GetFunc(store, name)
@@ -180,7 +189,7 @@ func LoadCassette(path string, debug bool) (*Cassette, error) {
 		}
 	}
 
-	return &Cassette{
+	cassette := &Cassette{
 		engine:       engine,
 		store:        store,
 		instance:     instance,
@@ -188,7 +197,21 @@ func LoadCassette(path string, debug bool) (*Cassette, error) {
 		eventTracker: NewEventTracker(),
 		exports:      exports,
 		debug:        debug,
-	}, nil
+	}
+	if err := cassette.setCurrentTime(); err != nil {
+		return nil, fmt.Errorf("failed to set the cassette's clock: %w", err)
+	}
+	return cassette, nil
+}
+
+// setCurrentTime gives cassettes that hide expired events (NIP-40) the host's clock
+func (c *Cassette) setCurrentTime() error {
+	setCurrentTime, ok := c.exports["set_current_time"]
+	if !ok {
+		return nil
+	}
+	_, err := setCurrentTime.Call(c.store, time.Now().Unix())
+	return err
 }
 
 // Describe returns the cassette description
@@ -283,6 +306,10 @@ func (c *Cassette) Scrub(message string) (*SendResult, error) {
 	c.mu.Lock()
 	defer c.mu.Unlock()
 
+	if err := c.setCurrentTime(); err != nil {
+		return nil, err
+	}
+
 	// Parse message to determine type
 	var isReqMessage bool
 	var subscriptionID string
@@ -339,6 +366,61 @@ func (c *Cassette) Send(message string) (*SendResult, error) {
 	return c.Scrub(message)
 }
 
+// ReqPage returns one page of up to pageSize results (0 for the cassette's default)
+// of a REQ message, starting at cursor: 0 for the first page, then each page's
+// NextCursor. Each call runs the query afresh, so no subscription is held open.
+// Fails for cassettes built before the req_page export.
+func (c *Cassette) ReqPage(message string, cursor uint64, pageSize uint32) (*Page, error) {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+
+	reqPageFunc, ok := c.exports["req_page"]
+	if !ok {
+		return nil, fmt.Errorf("cassette has no req_page export; use Scrub() instead")
+	}
+	if err := c.setCurrentTime(); err != nil {
+		return nil, err
+	}
+
+	msgPtr, err := c.memory.WriteString(message)
+	if err != nil {
+		return nil, err
+	}
+	result, err := reqPageFunc.Call(c.store, msgPtr, int32(len(message)), int64(cursor), int32(pageSize))
+	if deallocFunc, ok := c.exports["dealloc_string"]; ok {
+		deallocFunc.Call(c.store, msgPtr, int32(len(message)))
+	}
+	if err != nil {
+		return nil, err
+	}
+
+	resultPtr := result.(int32)
+	if resultPtr == 0 {
+		return nil, fmt.Errorf("req_page() returned null pointer")
+	}
+	response, err := c.memory.ReadString(resultPtr)
+	if err != nil {
+		return nil, err
+	}
+	if deallocFunc, ok := c.exports["dealloc_string"]; ok {
+		deallocFunc.Call(c.store, resultPtr, int32(len(response)))
+	}
+
+	var page struct {
+		Events     []json.RawMessage `json:"events"`
+		NextCursor *uint64           `json:"next_cursor"`
+	}
+	if err := json.Unmarshal([]byte(response), &page); err != nil {
+		// A CLOSED or NOTICE refusing the query
+		return nil, fmt.Errorf("cassette refused the query: %s", response)
+	}
+	events := make([]string, len(page.Events))
+	for i, event := range page.Events {
+		events[i] = string(event)
+	}
+	return &Page{Events: events, NextCursor: page.NextCursor}, nil
+}
+
 // sendSingle performs a single send call
 func (c *Cassette) sendSingle(message string) (string, error) {
 	// Write message to memory
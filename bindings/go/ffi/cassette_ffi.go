@@ -0,0 +1,119 @@
+// Generated by bindings/idl/generate.py from bindings/idl/cassette_loader.wit. Do not edit.
+
+// Package ffi is a thin cgo wrapper over the cassette-loader cdylib.
+package ffi
+
+/*
+#cgo CFLAGS: -I${SRCDIR}/../../c/include
+#cgo LDFLAGS: -lcassette_loader
+#include <stdlib.h>
+#include "cassette_loader.h"
+*/
+import "C"
+
+import (
+	"encoding/json"
+	"errors"
+	"unsafe"
+)
+
+// DedupPolicy: How duplicate events are filtered from responses
+type DedupPolicy uint32
+
+const (
+	// Drop events already returned since the last REQ or CLOSE
+	DedupPolicyPerSubscription DedupPolicy = 0
+	// Return every event the cassette produces
+	DedupPolicyNone DedupPolicy = 1
+)
+
+// Cassette wraps a loaded cassette handle
+type Cassette struct {
+	handle *C.Cassette
+}
+
+func lastError() error {
+	if msg := C.cassette_last_error(); msg != nil {
+		return errors.New(C.GoString(msg))
+	}
+	return errors.New("unknown error")
+}
+
+func takeString(s *C.char) string {
+	defer C.cassette_string_free(s)
+	return C.GoString(s)
+}
+
+// Close releases the cassette handle
+func (c *Cassette) Close() {
+	if c.handle != nil {
+		C.cassette_free(c.handle)
+		c.handle = nil
+	}
+}
+
+// Load: Load a cassette from a .cassette/.wasm file
+func Load(path string, debug bool) (*Cassette, error) {
+	cPath := C.CString(path)
+	defer C.free(unsafe.Pointer(cPath))
+	var out *C.Cassette
+	if C.cassette_load(cPath, C.bool(debug), &out) != 0 {
+		return nil, lastError()
+	}
+	return &Cassette{handle: out}, nil
+}
+
+// Send: Send any NIP-01 message; REQ responses are collected until EOSE
+func (c *Cassette) Send(message string) ([]string, error) {
+	cMessage := C.CString(message)
+	defer C.free(unsafe.Pointer(cMessage))
+	var out *C.char
+	if C.cassette_send(c.handle, cMessage, &out) != 0 {
+		return nil, lastError()
+	}
+	var items []string
+	if err := json.Unmarshal([]byte(takeString(out)), &items); err != nil {
+		return nil, err
+	}
+	return items, nil
+}
+
+// Events: All events matching a filter (JSON object), as event JSON
+func (c *Cassette) Events(filter string) ([]string, error) {
+	cFilter := C.CString(filter)
+	defer C.free(unsafe.Pointer(cFilter))
+	var out *C.char
+	if C.cassette_events(c.handle, cFilter, &out) != 0 {
+		return nil, lastError()
+	}
+	var items []string
+	if err := json.Unmarshal([]byte(takeString(out)), &items); err != nil {
+		return nil, err
+	}
+	return items, nil
+}
+
+// Count: Number of events matching a filter (JSON object)
+func (c *Cassette) Count(filter string) (uint64, error) {
+	cFilter := C.CString(filter)
+	defer C.free(unsafe.Pointer(cFilter))
+	var out C.uint64_t
+	if C.cassette_count(c.handle, cFilter, &out) != 0 {
+		return 0, lastError()
+	}
+	return uint64(out), nil
+}
+
+// Info: NIP-11 relay information document
+func (c *Cassette) Info() (string, error) {
+	var out *C.char
+	if C.cassette_info(c.handle, &out) != 0 {
+		return "", lastError()
+	}
+	return takeString(out), nil
+}
+
+// SetDedup: Set how duplicate events are filtered from responses
+func (c *Cassette) SetDedup(policy DedupPolicy) {
+	C.cassette_set_dedup(c.handle, C.CassetteDedupPolicy(policy))
+}
@@ -0,0 +1,549 @@
+#!/usr/bin/env python3
+"""Generate the C header and Python/Go/Swift wrappers from cassette_loader.wit.
+
+Usage:
+    python3 bindings/idl/generate.py          # write generated files
+    python3 bindings/idl/generate.py --check  # fail if generated files are stale
+
+Only the subset of WIT used by cassette_loader.wit is understood: one
+interface containing enums and a single resource whose functions take
+string/bool/u64/enum parameters and return nothing, or a result<T, string>
+where T is string, u64, list<string> or the resource itself.
+"""
+
+import re
+import sys
+from dataclasses import dataclass, field
+from pathlib import Path
+from typing import List, Optional, Tuple
+
+ROOT = Path(__file__).resolve().parent.parent
+WIT = Path(__file__).resolve().parent / "cassette_loader.wit"
+GENERATED = "Generated by bindings/idl/generate.py from bindings/idl/cassette_loader.wit. Do not edit."
+
+
+@dataclass
+class Enum:
+    name: str
+    doc: str
+    cases: List[Tuple[str, str]]
+
+
+@dataclass
+class Func:
+    name: str
+    doc: str
+    static: bool
+    params: List[Tuple[str, str]]
+    ok: Optional[str]  # ok type of result<_, string>, or None for no return value
+
+
+@dataclass
+class Resource:
+    name: str
+    funcs: List[Func] = field(default_factory=list)
+
+
+def parse(text: str) -> Tuple[List[Enum], Resource]:
+    enums: List[Enum] = []
+    resource: Optional[Resource] = None
+    doc: List[str] = []
+    current_enum: Optional[Enum] = None
+
+    for raw in text.splitlines():
+        line = raw.strip()
+        if line.startswith("///"):
+            doc.append(line[3:].strip())
+            continue
+        if not line or line.startswith("//") or line.startswith("package") or line.startswith("interface"):
+            continue
+
+        if m := re.match(r"enum ([\w-]+) \{", line):
+            current_enum = Enum(m.group(1), " ".join(doc), [])
+            enums.append(current_enum)
+        elif m := re.match(r"resource ([\w-]+) \{", line):
+            resource = Resource(m.group(1))
+        elif line == "}":
+            current_enum = None
+        elif current_enum is not None:
+            current_enum.cases.append((line.rstrip(","), " ".join(doc)))
+        elif m := re.match(r"([\w-]+): (static )?func\((.*)\)(?: -> (.+))?;", line):
+            if resource is None:
+                raise ValueError(f"function outside resource: {line}")
+            params = [tuple(p.strip().split(": ")) for p in m.group(3).split(",") if p.strip()]
+            ok = None
+            if m.group(4):
+                result = re.fullmatch(r"result<(.+), string>", m.group(4))
+                if not result:
+                    raise ValueError(f"unsupported return type: {m.group(4)}")
+                ok = result.group(1)
+            resource.funcs.append(Func(m.group(1), " ".join(doc), bool(m.group(2)), params, ok))
+        else:
+            raise ValueError(f"unsupported WIT line: {line}")
+        doc = []
+
+    if resource is None:
+        raise ValueError("no resource found")
+    return enums, resource
+
+
+def snake(name: str) -> str:
+    return name.replace("-", "_")
+
+
+def camel(name: str) -> str:
+    return "".join(part.capitalize() for part in name.split("-"))
+
+
+def lower_camel(name: str) -> str:
+    c = camel(name)
+    return c[0].lower() + c[1:]
+
+
+# --- C header -----------------------------------------------------------------
+
+def c_enum_type(enum: Enum) -> str:
+    return f"Cassette{camel(enum.name)}"
+
+
+def c_header(enums: List[Enum], res: Resource) -> str:
+    handle = camel(res.name)
+    enum_names = {e.name: c_enum_type(e) for e in enums}
+    lines = [
+        f"// {GENERATED}",
+        "",
+        "#ifndef CASSETTE_LOADER_H",
+        "#define CASSETTE_LOADER_H",
+        "",
+        "#include <stdbool.h>",
+        "#include <stdint.h>",
+        "",
+        "#ifdef __cplusplus",
+        'extern "C" {',
+        "#endif",
+        "",
+        f"typedef struct {handle} {handle};",
+        "",
+    ]
+    for enum in enums:
+        lines.append(f"// {enum.doc}")
+        lines.append("typedef enum {")
+        for i, (case, doc) in enumerate(enum.cases):
+            lines.append(f"    // {doc}")
+            lines.append(f"    CASSETTE_{snake(enum.name).upper()}_{snake(case).upper()} = {i},")
+        lines.append(f"}} {c_enum_type(enum)};")
+        lines.append("")
+
+    lines += [
+        "// Fallible functions return 0 on success and -1 on error, writing their result",
+        "// through `out`. list<string> results are JSON arrays. Returned strings are owned",
+        "// by the caller and released with cassette_string_free.",
+        "",
+        "// Message of the last error on this thread, or NULL",
+        "const char *cassette_last_error(void);",
+        "",
+        "// Release a string returned by the loader",
+        "void cassette_string_free(char *s);",
+        "",
+        f"// Release a {res.name} handle",
+        f"void {snake(res.name)}_free({handle} *self);",
+        "",
+    ]
+
+    for func in res.funcs:
+        args = [] if func.static else [f"{handle} *self"]
+        for name, ty in func.params:
+            if ty == "string":
+                args.append(f"const char *{snake(name)}")
+            elif ty == "bool":
+                args.append(f"bool {snake(name)}")
+            elif ty == "u64":
+                args.append(f"uint64_t {snake(name)}")
+            elif ty in enum_names:
+                args.append(f"{enum_names[ty]} {snake(name)}")
+            else:
+                raise ValueError(f"unsupported parameter type: {ty}")
+        if func.ok is None:
+            ret = "void"
+        else:
+            ret = "int32_t"
+            out = {"string": "char **out", "list<string>": "char **out", "u64": "uint64_t *out",
+                   res.name: f"{handle} **out"}.get(func.ok)
+            if out is None:
+                raise ValueError(f"unsupported result type: {func.ok}")
+            args.append(out)
+        lines.append(f"// {func.doc}")
+        lines.append(f"{ret} {snake(res.name)}_{snake(func.name)}({', '.join(args)});")
+        lines.append("")
+
+    lines += [
+        "#ifdef __cplusplus",
+        "}",
+        "#endif",
+        "",
+        "#endif // CASSETTE_LOADER_H",
+        "",
+    ]
+    return "\n".join(lines)
+
+
+# --- Python (ctypes) ----------------------------------------------------------
+
+def python_wrapper(enums: List[Enum], res: Resource) -> str:
+    enum_names = {e.name for e in enums}
+    cls = camel(res.name)
+    lines = [
+        f'"""{GENERATED}',
+        "",
+        "Thin ctypes wrapper over the cassette-loader cdylib. Set CASSETTE_LOADER_LIB",
+        "to the library path if it is not on the default search path.",
+        '"""',
+        "",
+        "import ctypes",
+        "import ctypes.util",
+        "import json",
+        "import os",
+        "from enum import IntEnum",
+        "from typing import List",
+        "",
+        "",
+        "class CassetteError(Exception):",
+        "    pass",
+        "",
+    ]
+    for enum in enums:
+        lines += ["", f"class {camel(enum.name)}(IntEnum):", f'    """{enum.doc}"""', ""]
+        for i, (case, _) in enumerate(enum.cases):
+            lines.append(f"    {snake(case).upper()} = {i}")
+        lines.append("")
+
+    lines += [
+        "",
+        "_library = None",
+        "",
+        "",
+        "def _lib():",
+        "    global _library",
+        "    if _library is None:",
+        "        path = os.environ.get(\"CASSETTE_LOADER_LIB\") or ctypes.util.find_library(\"cassette_loader\")",
+        "        lib = ctypes.CDLL(path or \"libcassette_loader.so\")",
+        "        lib.cassette_last_error.restype = ctypes.c_char_p",
+        "        lib.cassette_string_free.argtypes = [ctypes.c_void_p]",
+        f"        lib.{snake(res.name)}_free.argtypes = [ctypes.c_void_p]",
+    ]
+    for func in res.funcs:
+        argtypes = [] if func.static else ["ctypes.c_void_p"]
+        for _, ty in func.params:
+            argtypes.append({"string": "ctypes.c_char_p", "bool": "ctypes.c_bool", "u64": "ctypes.c_uint64"}.get(
+                ty, "ctypes.c_uint32" if ty in enum_names else None))
+        if func.ok is not None:
+            argtypes.append("ctypes.POINTER(ctypes.c_uint64)" if func.ok == "u64" else "ctypes.POINTER(ctypes.c_void_p)")
+        name = f"{snake(res.name)}_{snake(func.name)}"
+        lines.append(f"        lib.{name}.argtypes = [{', '.join(argtypes)}]")
+        lines.append(f"        lib.{name}.restype = {'None' if func.ok is None else 'ctypes.c_int32'}")
+    lines += [
+        "        _library = lib",
+        "    return _library",
+        "",
+        "",
+        "def _check(status):",
+        "    if status != 0:",
+        "        message = _lib().cassette_last_error()",
+        "        raise CassetteError(message.decode() if message else \"unknown error\")",
+        "",
+        "",
+        "def _take_string(ptr):",
+        "    try:",
+        "        return ctypes.string_at(ptr.value).decode()",
+        "    finally:",
+        "        _lib().cassette_string_free(ptr)",
+        "",
+        "",
+        f"class {cls}:",
+        "    def __init__(self, handle):",
+        "        self._handle = handle",
+        "",
+        "    def close(self):",
+        "        if self._handle:",
+        f"            _lib().{snake(res.name)}_free(self._handle)",
+        "            self._handle = None",
+        "",
+        "    def __enter__(self):",
+        "        return self",
+        "",
+        "    def __exit__(self, *exc):",
+        "        self.close()",
+        "",
+        "    def __del__(self):",
+        "        self.close()",
+    ]
+
+    for func in res.funcs:
+        py_types = {"string": "str", "bool": "bool", "u64": "int"}
+        params = [f"{snake(n)}: {py_types.get(t, camel(t))}" for n, t in func.params]
+        call_args = [] if func.static else ["self._handle"]
+        for n, t in func.params:
+            call_args.append(f"{snake(n)}.encode()" if t == "string" else
+                             f"int({snake(n)})" if t in enum_names else snake(n))
+        ret = {"string": "str", "list<string>": "List[str]", "u64": "int", res.name: f'"{cls}"', None: "None"}[func.ok]
+        c_name = f"{snake(res.name)}_{snake(func.name)}"
+        lines.append("")
+        if func.static:
+            lines.append("    @classmethod")
+            lines.append(f"    def {snake(func.name)}(cls, {', '.join(params)}) -> {ret}:")
+        else:
+            lines.append(f"    def {snake(func.name)}({', '.join(['self'] + params)}) -> {ret}:")
+        lines.append(f'        """{func.doc}"""')
+        if func.ok is None:
+            lines.append(f"        _lib().{c_name}({', '.join(call_args)})")
+            continue
+        lines.append("        out = ctypes.c_uint64()" if func.ok == "u64" else "        out = ctypes.c_void_p()")
+        lines.append(f"        _check(_lib().{c_name}({', '.join(call_args + ['ctypes.byref(out)'])}))")
+        if func.ok == "u64":
+            lines.append("        return out.value")
+        elif func.ok == "string":
+            lines.append("        return _take_string(out)")
+        elif func.ok == "list<string>":
+            lines.append("        return json.loads(_take_string(out))")
+        else:
+            lines.append("        return cls(out.value)")
+    lines.append("")
+    return "\n".join(lines)
+
+
+# --- Go (cgo) -----------------------------------------------------------------
+
+def go_wrapper(enums: List[Enum], res: Resource) -> str:
+    enum_names = {e.name: camel(e.name) for e in enums}
+    handle = camel(res.name)
+    lines = [
+        f"// {GENERATED}",
+        "",
+        "// Package ffi is a thin cgo wrapper over the cassette-loader cdylib.",
+        "package ffi",
+        "",
+        "/*",
+        "#cgo CFLAGS: -I${SRCDIR}/../../c/include",
+        "#cgo LDFLAGS: -lcassette_loader",
+        "#include <stdlib.h>",
+        '#include "cassette_loader.h"',
+        "*/",
+        'import "C"',
+        "",
+        "import (",
+        '\t"encoding/json"',
+        '\t"errors"',
+        '\t"unsafe"',
+        ")",
+        "",
+    ]
+    for enum in enums:
+        name = camel(enum.name)
+        lines += [f"// {name}: {enum.doc}", f"type {name} uint32", "", "const ("]
+        for i, (case, doc) in enumerate(enum.cases):
+            lines.append(f"\t// {doc}")
+            lines.append(f"\t{name}{camel(case)} {name} = {i}")
+        lines += [")", ""]
+
+    lines += [
+        f"// {handle} wraps a loaded cassette handle",
+        f"type {handle} struct {{",
+        f"\thandle *C.{handle}",
+        "}",
+        "",
+        "func lastError() error {",
+        "\tif msg := C.cassette_last_error(); msg != nil {",
+        "\t\treturn errors.New(C.GoString(msg))",
+        "\t}",
+        '\treturn errors.New("unknown error")',
+        "}",
+        "",
+        "func takeString(s *C.char) string {",
+        "\tdefer C.cassette_string_free(s)",
+        "\treturn C.GoString(s)",
+        "}",
+        "",
+        "// Close releases the cassette handle",
+        f"func (c *{handle}) Close() {{",
+        "\tif c.handle != nil {",
+        f"\t\tC.{snake(res.name)}_free(c.handle)",
+        "\t\tc.handle = nil",
+        "\t}",
+        "}",
+    ]
+
+    for func in res.funcs:
+        go_name = camel(func.name)
+        params, setup, call_args = [], [], ([] if func.static else ["c.handle"])
+        for n, t in func.params:
+            pn = lower_camel(n)
+            if t == "string":
+                params.append(f"{pn} string")
+                setup += [f"\tc{camel(n)} := C.CString({pn})", f"\tdefer C.free(unsafe.Pointer(c{camel(n)}))"]
+                call_args.append(f"c{camel(n)}")
+            elif t == "bool":
+                params.append(f"{pn} bool")
+                call_args.append(f"C.bool({pn})")
+            elif t == "u64":
+                params.append(f"{pn} uint64")
+                call_args.append(f"C.uint64_t({pn})")
+            else:
+                params.append(f"{pn} {enum_names[t]}")
+                call_args.append(f"C.{c_enum_type(next(e for e in enums if e.name == t))}({pn})")
+        ret = {"string": "(string, error)", "list<string>": "([]string, error)", "u64": "(uint64, error)",
+               res.name: f"(*{handle}, error)", None: ""}[func.ok]
+        receiver = "" if func.static else f"(c *{handle}) "
+        lines += ["", f"// {go_name}: {func.doc}", f"func {receiver}{go_name}({', '.join(params)}) {ret} {{".replace(")  {", ") {")]
+        lines += setup
+        c_call = f"C.{snake(res.name)}_{snake(func.name)}"
+        if func.ok is None:
+            lines.append(f"\t{c_call}({', '.join(call_args)})")
+        else:
+            out_type = {"string": "*C.char", "list<string>": "*C.char", "u64": "C.uint64_t", res.name: f"*C.{handle}"}[func.ok]
+            zero = {"string": '""', "list<string>": "nil", "u64": "0", res.name: "nil"}[func.ok]
+            lines += [f"\tvar out {out_type}",
+                      f"\tif {c_call}({', '.join(call_args + ['&out'])}) != 0 {{",
+                      f"\t\treturn {zero}, lastError()",
+                      "\t}"]
+            if func.ok == "string":
+                lines.append("\treturn takeString(out), nil")
+            elif func.ok == "u64":
+                lines.append("\treturn uint64(out), nil")
+            elif func.ok == "list<string>":
+                lines += ["\tvar items []string",
+                          "\tif err := json.Unmarshal([]byte(takeString(out)), &items); err != nil {",
+                          "\t\treturn nil, err",
+                          "\t}",
+                          "\treturn items, nil"]
+            else:
+                lines.append(f"\treturn &{handle}{{handle: out}}, nil")
+        lines.append("}")
+    lines.append("")
+    return "\n".join(lines)
+
+
+# --- Swift --------------------------------------------------------------------
+
+def swift_wrapper(enums: List[Enum], res: Resource) -> str:
+    enum_names = {e.name: camel(e.name) for e in enums}
+    handle = camel(res.name)
+    lines = [
+        f"// {GENERATED}",
+        "",
+        "import Foundation",
+        "import CCassetteLoader",
+        "",
+        "public struct CassetteError: Error {",
+        "    public let message: String",
+        "}",
+        "",
+    ]
+    for enum in enums:
+        lines += [f"/// {enum.doc}", f"public enum {camel(enum.name)}: UInt32 {{"]
+        for i, (case, doc) in enumerate(enum.cases):
+            lines.append(f"    /// {doc}")
+            lines.append(f"    case {lower_camel(case)} = {i}")
+        lines += ["}", ""]
+
+    lines += [
+        "private func lastError() -> CassetteError {",
+        '    CassetteError(message: cassette_last_error().map { String(cString: $0) } ?? "unknown error")',
+        "}",
+        "",
+        "private func takeString(_ s: UnsafeMutablePointer<CChar>?) -> String {",
+        "    defer { cassette_string_free(s) }",
+        '    return s.map { String(cString: $0) } ?? ""',
+        "}",
+        "",
+        f"public final class {handle} {{",
+        "    private let handle: OpaquePointer",
+        "",
+        "    private init(handle: OpaquePointer) {",
+        "        self.handle = handle",
+        "    }",
+        "",
+        "    deinit {",
+        f"        {snake(res.name)}_free(handle)",
+        "    }",
+    ]
+    for func in res.funcs:
+        params, call_args = [], ([] if func.static else ["handle"])
+        for n, t in func.params:
+            swift_type = {"string": "String", "bool": "Bool", "u64": "UInt64"}.get(t, enum_names.get(t))
+            params.append(f"{lower_camel(n)}: {swift_type}")
+            if t in enum_names:
+                enum = next(e for e in enums if e.name == t)
+                call_args.append(f"{c_enum_type(enum)}(rawValue: {lower_camel(n)}.rawValue)")
+            else:
+                call_args.append(lower_camel(n))
+        ret = {"string": " -> String", "list<string>": " -> [String]", "u64": " -> UInt64",
+               res.name: f" -> {handle}", None: ""}[func.ok]
+        throws = "" if func.ok is None else " throws"
+        static = "static " if func.static else ""
+        c_call = f"{snake(res.name)}_{snake(func.name)}"
+        lines += ["", f"    /// {func.doc}", f"    public {static}func {lower_camel(func.name)}({', '.join(params)}){throws}{ret} {{"]
+        if func.ok is None:
+            lines.append(f"        {c_call}({', '.join(call_args)})")
+        else:
+            out_decl = {"string": "var out: UnsafeMutablePointer<CChar>? = nil",
+                        "list<string>": "var out: UnsafeMutablePointer<CChar>? = nil",
+                        "u64": "var out: UInt64 = 0",
+                        res.name: "var out: OpaquePointer? = nil"}[func.ok]
+            lines += [f"        {out_decl}",
+                      f"        guard {c_call}({', '.join(call_args + ['&out'])}) == 0 else {{ throw lastError() }}"]
+            if func.ok == "string":
+                lines.append("        return takeString(out)")
+            elif func.ok == "u64":
+                lines.append("        return out")
+            elif func.ok == "list<string>":
+                lines += ["        let json = takeString(out)",
+                          "        return try JSONDecoder().decode([String].self, from: Data(json.utf8))"]
+            else:
+                lines.append(f"        return {handle}(handle: out!)")
+        lines.append("    }")
+    lines += ["}", ""]
+    return "\n".join(lines)
+
+
+MODULE_MAP = f"""// {GENERATED}
+
+module CCassetteLoader {{
+    header "../c/include/cassette_loader.h"
+    link "cassette_loader"
+    export *
+}}
+"""
+
+
+def outputs() -> List[Tuple[Path, str]]:
+    enums, res = parse(WIT.read_text())
+    return [
+        (ROOT / "c" / "include" / "cassette_loader.h", c_header(enums, res)),
+        (ROOT / "py" / "cassette_ffi.py", python_wrapper(enums, res)),
+        (ROOT / "go" / "ffi" / "cassette_ffi.go", go_wrapper(enums, res)),
+        (ROOT / "swift" / "CassetteLoader.swift", swift_wrapper(enums, res)),
+        (ROOT / "swift" / "module.modulemap", MODULE_MAP),
+    ]
+
+
+def main() -> int:
+    check = "--check" in sys.argv[1:]
+    stale = []
+    for path, content in outputs():
+        if check:
+            if not path.exists() or path.read_text() != content:
+                stale.append(path)
+        else:
+            path.parent.mkdir(parents=True, exist_ok=True)
+            path.write_text(content)
+            print(f"wrote {path.relative_to(ROOT.parent)}")
+    if stale:
+        for path in stale:
+            print(f"stale: {path.relative_to(ROOT.parent)}", file=sys.stderr)
+        print("run python3 bindings/idl/generate.py to regenerate", file=sys.stderr)
+        return 1
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -12,6 +12,7 @@ import {
   CassetteLoadError,
   CassetteLoadResult,
   CassetteMetadata,
+  CassettePage,
   CassetteSource,
   EventTracker
 } from './types.js';
@@ -37,6 +38,7 @@ export type {
   CassetteLoaderOptions,
   CassetteLoadResult,
   CassetteMetadata,
+  CassettePage,
   CassetteSource,
   EventTracker
 };
@@ -2,6 +2,7 @@ import {
   Cassette, 
   CassetteLoaderOptions, 
   CassetteLoadResult, 
+  CassettePage,
   CassetteSource,
   CassetteLoadError
 } from './types.js';
@@ -77,6 +78,18 @@ class CoreCassetteInterface {
     this.memory = this.exports.memory as WebAssembly.Memory;
     this.memoryManager = createMemoryManager(instance, debug);
     this.logger = createLogger(debug, 'CoreCassetteInterface');
+    this.syncClock();
+  }
+  
+  /**
+   * Give cassettes that hide expired events (NIP-40) the host's clock, in unix
+   * seconds. WebAssembly has no clock of its own: until a cassette is given the
+   * time, it treats nothing as expired.
+   */
+  private syncClock(): void {
+    if (typeof this.exports.set_current_time === 'function') {
+      (this.exports.set_current_time as Function)(BigInt(Math.floor(Date.now() / 1000)));
+    }
   }
   
   /**
@@ -126,6 +139,7 @@ class CoreCassetteInterface {
   // Universal scrub method for all NIP-01 messages
   scrub(messageStr: string): string | string[] {
     this.logger.log(`Processing message: ${messageStr.substring(0, 100)}${messageStr.length > 100 ? '...' : ''}`);
+    this.syncClock();
     
     // Try to use the new 'scrub' function first, fall back to 'send' for backward compatibility
     if (typeof this.exports.scrub !== 'function') {
@@ -304,6 +318,65 @@ class CoreCassetteInterface {
     return JSON.stringify({ supported_nips: [] });
   }
   
+  /**
+   * Decrypt the events of a cassette recorded with `--encrypt` using the recipient's
+   * secret key (64 hex characters). Returns false for a wrong key; cassettes that
+   * aren't encrypted have nothing to unlock and return true.
+   */
+  unlock(secretKey: string): boolean {
+    if (typeof this.exports.unlock !== 'function') {
+      return true;
+    }
+    const keyPtr = this.memoryManager.writeString(secretKey);
+    try {
+      return (this.exports.unlock as Function)(keyPtr, secretKey.length) === 1;
+    } finally {
+      this.memoryManager.deallocateString(keyPtr);
+    }
+  }
+  
+  /**
+   * One page of up to `pageSize` results (0 for the cassette's default) of a REQ
+   * message, starting at `cursor`: 0 for the first page, then each page's
+   * `nextCursor`. Each call runs the query afresh, so no subscription is held open.
+   */
+  reqPage(messageStr: string, cursor = 0, pageSize = 0): CassettePage {
+    if (typeof this.exports.req_page !== 'function') {
+      throw new Error('Cassette has no req_page export; use scrub() instead');
+    }
+    this.syncClock();
+    const messageLength = new TextEncoder().encode(messageStr).length;
+    const messagePtr = this.memoryManager.writeString(messageStr);
+    if (messagePtr === 0) {
+      throw new Error('Failed to allocate memory for message');
+    }
+    let resultPtr = 0;
+    try {
+      resultPtr = (this.exports.req_page as Function)(messagePtr, messageLength, BigInt(cursor), pageSize);
+    } finally {
+      this.memoryManager.deallocateString(messagePtr);
+    }
+    if (resultPtr === 0) {
+      throw new Error('req_page() returned null pointer');
+    }
+    
+    let response: string;
+    try {
+      response = this.memoryManager.readString(resultPtr);
+    } finally {
+      this.memoryManager.deallocateString(resultPtr);
+    }
+    const page = JSON.parse(response);
+    if (Array.isArray(page)) {
+      // A CLOSED or NOTICE refusing the query
+      throw new Error(`Cassette refused the query: ${page[page.length - 1]}`);
+    }
+    return {
+      events: (page.events || []).map((event: unknown) => JSON.stringify(event)),
+      nextCursor: typeof page.next_cursor === 'number' ? page.next_cursor : null
+    };
+  }
+  
   describe(): string {
     this.logger.log('Getting cassette description');
     // Since we're removing the describe function from cassettes,
@@ -314,7 +387,9 @@ class CoreCassetteInterface {
       return JSON.stringify({
         name: infoObj.name || 'Unknown Cassette',
         description: infoObj.description || 'No description available',
-        version: '1.0.0'
+        version: '1.0.0',
+        examples: infoObj.cassette?.examples || [],
+        meta: infoObj.cassette?.meta || {}
       });
     } catch (e) {
       return JSON.stringify({
@@ -609,7 +684,9 @@ export async function loadCassette(
         scrub: (messageStr: string) => coreInterface.scrub(messageStr),
         send: (messageStr: string) => coreInterface.send(messageStr), // deprecated
         getSchema: () => coreInterface.getSchema(),
-        info: () => coreInterface.info()
+        info: () => coreInterface.info(),
+        unlock: (secretKey: string) => coreInterface.unlock(secretKey),
+        reqPage: (messageStr: string, cursor?: number, pageSize?: number) => coreInterface.reqPage(messageStr, cursor, pageSize)
       },
       eventTracker: opts.deduplicateEvents !== false ? createEventTracker() : undefined,
       // Add memory stats method
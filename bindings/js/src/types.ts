@@ -7,9 +7,21 @@ export interface CassetteMetadata {
   version: string;
   author?: string;
   supportedKinds?: number[];
+  /** Example messages known to return events from this cassette */
+  examples?: CassetteExample[];
+  /** Custom key/values embedded with `record --meta` */
+  meta?: Record<string, string>;
   [key: string]: any;
 }
 
+/**
+ * A ready-to-send query derived from the cassette's own events at record time
+ */
+export interface CassetteExample {
+  description: string;
+  message: any[];
+}
+
 /**
  * Interface for tracking unique events to avoid duplicates
  */
@@ -134,6 +146,19 @@ export interface Cassette {
      * Get NIP-11 relay information (optional)
      */
     info?: () => string;
+    
+    /**
+     * Decrypt the events of a cassette recorded with `--encrypt` using the
+     * recipient's hex secret key; false when the key is wrong (optional)
+     */
+    unlock?: (secretKey: string) => boolean;
+    
+    /**
+     * One page of up to `pageSize` results (0 for the cassette's default) of a REQ
+     * message, starting at `cursor` (0 for the first page, then each page's
+     * `nextCursor`). Throws for cassettes built before the `req_page` export (optional)
+     */
+    reqPage?: (messageStr: string, cursor?: number, pageSize?: number) => CassettePage;
   };
   
   /**
@@ -249,6 +274,17 @@ export interface CassetteLoadResult {
  */
 export type CassetteSource = File | string | ArrayBuffer | Uint8Array;
 
+/**
+ * One page of a REQ's results from `reqPage`
+ */
+export interface CassettePage {
+  /** Event JSON strings, in the order the cassette serves them */
+  events: string[];
+  
+  /** Where the next page starts; null on the last page */
+  nextCursor: number | null;
+}
+
 /**
  * Error thrown when loading a cassette fails
  */
@@ -277,4 +313,7 @@ export interface CassetteMethods {
   
   /** Get NIP-11 relay information (optional) */
   info?: () => string;
+  
+  /** One page of a REQ's results, starting at a cursor (optional) */
+  reqPage?: (messageStr: string, cursor?: number, pageSize?: number) => CassettePage;
 } 
\ No newline at end of file
@@ -0,0 +1,39 @@
+const { loadCassette } = require('../dist/src/index.js');
+
+// A module exporting memory, set_current_time(i64) and the mutable global `now`
+// it stores the time in, assembled by hand so the test needs no cassette build
+const CLOCK_MODULE = new Uint8Array([
+  0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+  // type: (i64) -> ()
+  0x01, 0x05, 0x01, 0x60, 0x01, 0x7e, 0x00,
+  // function 0 has type 0
+  0x03, 0x02, 0x01, 0x00,
+  // one page of memory
+  0x05, 0x03, 0x01, 0x00, 0x01,
+  // global 0: mut i64 = 0
+  0x06, 0x06, 0x01, 0x7e, 0x01, 0x42, 0x00, 0x0b,
+  // exports: memory, set_current_time, now
+  0x07, 0x23, 0x03,
+  0x06, ...Buffer.from('memory'), 0x02, 0x00,
+  0x10, ...Buffer.from('set_current_time'), 0x00, 0x00,
+  0x03, ...Buffer.from('now'), 0x03, 0x00,
+  // set_current_time: global.set 0 (local.get 0)
+  0x0a, 0x08, 0x01, 0x06, 0x00, 0x20, 0x00, 0x24, 0x00, 0x0b,
+]);
+
+describe('NIP-40 clock', () => {
+  test('loading a cassette gives it the current time', async () => {
+    const before = BigInt(Math.floor(Date.now() / 1000));
+    const result = await loadCassette(CLOCK_MODULE, 'clock.wasm', { exposeExports: true });
+    expect(result.success).toBe(true);
+
+    const now = result.cassette.exports.now.value;
+    expect(now >= before).toBe(true);
+    expect(now <= BigInt(Math.floor(Date.now() / 1000))).toBe(true);
+  });
+
+  test('reqPage needs the req_page export', async () => {
+    const result = await loadCassette(CLOCK_MODULE, 'clock.wasm');
+    expect(() => result.cassette.methods.reqPage('["REQ","page",{}]')).toThrow(/no req_page export/);
+  });
+});
@@ -0,0 +1,117 @@
+"""Generated by bindings/idl/generate.py from bindings/idl/cassette_loader.wit. Do not edit.
+
+Thin ctypes wrapper over the cassette-loader cdylib. Set CASSETTE_LOADER_LIB
+to the library path if it is not on the default search path.
+"""
+
+import ctypes
+import ctypes.util
+import json
+import os
+from enum import IntEnum
+from typing import List
+
+
+class CassetteError(Exception):
+    pass
+
+
+class DedupPolicy(IntEnum):
+    """How duplicate events are filtered from responses"""
+
+    PER_SUBSCRIPTION = 0
+    NONE = 1
+
+
+_library = None
+
+
+def _lib():
+    global _library
+    if _library is None:
+        path = os.environ.get("CASSETTE_LOADER_LIB") or ctypes.util.find_library("cassette_loader")
+        lib = ctypes.CDLL(path or "libcassette_loader.so")
+        lib.cassette_last_error.restype = ctypes.c_char_p
+        lib.cassette_string_free.argtypes = [ctypes.c_void_p]
+        lib.cassette_free.argtypes = [ctypes.c_void_p]
+        lib.cassette_load.argtypes = [ctypes.c_char_p, ctypes.c_bool, ctypes.POINTER(ctypes.c_void_p)]
+        lib.cassette_load.restype = ctypes.c_int32
+        lib.cassette_send.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.POINTER(ctypes.c_void_p)]
+        lib.cassette_send.restype = ctypes.c_int32
+        lib.cassette_events.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.POINTER(ctypes.c_void_p)]
+        lib.cassette_events.restype = ctypes.c_int32
+        lib.cassette_count.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.POINTER(ctypes.c_uint64)]
+        lib.cassette_count.restype = ctypes.c_int32
+        lib.cassette_info.argtypes = [ctypes.c_void_p, ctypes.POINTER(ctypes.c_void_p)]
+        lib.cassette_info.restype = ctypes.c_int32
+        lib.cassette_set_dedup.argtypes = [ctypes.c_void_p, ctypes.c_uint32]
+        lib.cassette_set_dedup.restype = None
+        _library = lib
+    return _library
+
+
+def _check(status):
+    if status != 0:
+        message = _lib().cassette_last_error()
+        raise CassetteError(message.decode() if message else "unknown error")
+
+
+def _take_string(ptr):
+    try:
+        return ctypes.string_at(ptr.value).decode()
+    finally:
+        _lib().cassette_string_free(ptr)
+
+
+class Cassette:
+    def __init__(self, handle):
+        self._handle = handle
+
+    def close(self):
+        if self._handle:
+            _lib().cassette_free(self._handle)
+            self._handle = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def __del__(self):
+        self.close()
+
+    @classmethod
+    def load(cls, path: str, debug: bool) -> "Cassette":
+        """Load a cassette from a .cassette/.wasm file"""
+        out = ctypes.c_void_p()
+        _check(_lib().cassette_load(path.encode(), debug, ctypes.byref(out)))
+        return cls(out.value)
+
+    def send(self, message: str) -> List[str]:
+        """Send any NIP-01 message; REQ responses are collected until EOSE"""
+        out = ctypes.c_void_p()
+        _check(_lib().cassette_send(self._handle, message.encode(), ctypes.byref(out)))
+        return json.loads(_take_string(out))
+
+    def events(self, filter: str) -> List[str]:
+        """All events matching a filter (JSON object), as event JSON"""
+        out = ctypes.c_void_p()
+        _check(_lib().cassette_events(self._handle, filter.encode(), ctypes.byref(out)))
+        return json.loads(_take_string(out))
+
+    def count(self, filter: str) -> int:
+        """Number of events matching a filter (JSON object)"""
+        out = ctypes.c_uint64()
+        _check(_lib().cassette_count(self._handle, filter.encode(), ctypes.byref(out)))
+        return out.value
+
+    def info(self) -> str:
+        """NIP-11 relay information document"""
+        out = ctypes.c_void_p()
+        _check(_lib().cassette_info(self._handle, ctypes.byref(out)))
+        return _take_string(out)
+
+    def set_dedup(self, policy: DedupPolicy) -> None:
+        """Set how duplicate events are filtered from responses"""
+        _lib().cassette_set_dedup(self._handle, int(policy))
@@ -39,6 +39,13 @@ class CassetteInfo:
     event_count: int
 
 
+@dataclass
+class Page:
+    """One page of a REQ's results from Cassette.req_page"""
+    events: List[str]
+    next_cursor: Optional[int]
+
+
 class WasmMemoryManager:
     """Handles memory interactions between Python and WebAssembly"""
     
@@ -260,9 +267,17 @@ class Cassette:
         # Initialize memory manager
         self.memory_manager = WasmMemoryManager(self.memory, self.instance, self.store, debug)
         
+        self._set_current_time()
+        
         # Load cassette info
         self.info = self._load_info()
         
+    def _set_current_time(self) -> None:
+        """Give cassettes that hide expired events (NIP-40) the host's clock"""
+        exports = self.instance.exports(self.store)
+        if 'set_current_time' in exports:
+            exports['set_current_time'](self.store, int(time.time()))
+        
     def _generate_id(self) -> str:
         """Generate a unique ID for this cassette instance"""
         timestamp = int(time.time() * 1000)
@@ -313,6 +328,7 @@ class Cassette:
         """
         is_req_message = False
         subscription_id = ""
+        self._set_current_time()
         
         try:
             # Parse message to check type
@@ -344,6 +360,39 @@ class Cassette:
         # For non-REQ messages, use single call
         return self._send_single(message)
     
+    def req_page(self, message: str, cursor: int = 0, page_size: int = 0) -> Page:
+        """One page of up to page_size results (0 for the cassette's default) of a REQ
+        
+        Starts at cursor: 0 for the first page, then each page's next_cursor. Each call
+        runs the query afresh, so no subscription is held open. Raises for cassettes
+        built before the req_page export.
+        """
+        exports = self.instance.exports(self.store)
+        if 'req_page' not in exports:
+            raise RuntimeError("Cassette has no req_page export; use scrub() instead")
+        self._set_current_time()
+        
+        msg_ptr = self.memory_manager.write_string(message)
+        if msg_ptr == 0:
+            raise RuntimeError("Failed to allocate memory for message")
+        try:
+            result_ptr = exports['req_page'](self.store, msg_ptr, len(message.encode('utf-8')), cursor, page_size)
+        finally:
+            self.memory_manager.deallocate_string(msg_ptr)
+        if result_ptr == 0:
+            raise RuntimeError("req_page() returned null pointer")
+        
+        response = self.memory_manager.read_string(result_ptr)
+        self.memory_manager.deallocate_string(result_ptr)
+        page = json.loads(response)
+        if isinstance(page, list):
+            # A CLOSED or NOTICE refusing the query
+            raise RuntimeError(f"Cassette refused the query: {page[-1]}")
+        return Page(
+            events=[json.dumps(event, separators=(',', ':'), ensure_ascii=False) for event in page.get('events', [])],
+            next_cursor=page.get('next_cursor'),
+        )
+    
     def _send_single(self, message: str) -> str:
         """Send a single message and return the response"""
         # Write message to memory
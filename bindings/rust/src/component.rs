@@ -0,0 +1,143 @@
+//! Component-model cassettes
+//!
+//! `cassette record --component` builds a WebAssembly component exporting the
+//! WIT world in `wit/cassette.wit`: `req`, `event`, `close` and `info`, each
+//! taking and returning strings. wasmtime lifts and lowers the strings, so
+//! there are no pointers, allocators or MSGB headers for a host to get right.
+//! [`ComponentCassette`] loads these; [`Cassette::load`](crate::Cassette::load)
+//! recognizes them and points here.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Config, Engine, Store};
+
+use crate::SendResult;
+
+mod wit {
+    wasmtime::component::bindgen!({ world: "cassette", path: "../../wit" });
+}
+
+/// Whether WebAssembly bytes hold a component rather than a core module
+pub fn is_component(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\0asm") && bytes.get(6..8) == Some(&[1, 0])
+}
+
+/// A component-model cassette
+pub struct ComponentCassette {
+    store: Store<()>,
+    cassette: wit::Cassette,
+}
+
+impl ComponentCassette {
+    /// Load a component cassette from a file
+    pub fn load(path: &str) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", path))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Load a component cassette from its bytes (binary or WAT)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(&engine, bytes).context("not a cassette component")?;
+        let mut store = Store::new(&engine, ());
+        let cassette = wit::Cassette::instantiate(&mut store, &component, &Linker::new(&engine))
+            .context("component doesn't export the cassette world")?;
+        Ok(Self { store, cassette })
+    }
+
+    /// The next message answering a REQ or COUNT
+    pub fn req(&mut self, message: &str) -> Result<String> {
+        self.cassette.call_req(&mut self.store, message)
+    }
+
+    /// The OK message answering an EVENT
+    pub fn event(&mut self, message: &str) -> Result<String> {
+        self.cassette.call_event(&mut self.store, message)
+    }
+
+    /// The CLOSED message answering a CLOSE
+    pub fn close(&mut self, message: &str) -> Result<String> {
+        self.cassette.call_close(&mut self.store, message)
+    }
+
+    /// The NIP-11 relay information document
+    pub fn info(&mut self) -> Result<String> {
+        self.cassette.call_info(&mut self.store)
+    }
+
+    /// Answer any NIP-01 message like [`Cassette::scrub`](crate::Cassette::scrub): a REQ
+    /// is called until EOSE or CLOSED and answered with every message, anything else
+    /// with its single answer
+    pub fn scrub(&mut self, message: &str) -> Result<SendResult> {
+        let parsed: Vec<Value> = serde_json::from_str(message).context("invalid message JSON")?;
+        match parsed.first().and_then(|command| command.as_str()) {
+            Some("REQ") => {
+                let subscription_id = parsed.get(1).and_then(|id| id.as_str()).unwrap_or("");
+                let mut responses = Vec::new();
+                loop {
+                    let response = self.req(message)?;
+                    let command = serde_json::from_str::<Vec<Value>>(&response).ok()
+                        .and_then(|parsed| parsed.first()?.as_str().map(str::to_string));
+                    match command.as_deref() {
+                        Some("EVENT") => responses.push(response),
+                        Some("EOSE") | Some("CLOSED") => {
+                            responses.push(response);
+                            return Ok(SendResult::Multiple(responses));
+                        }
+                        // The cassette stopped without ending the subscription
+                        _ => break,
+                    }
+                }
+                responses.push(json!(["EOSE", subscription_id]).to_string());
+                Ok(SendResult::Multiple(responses))
+            }
+            Some("EVENT") => self.event(message).map(SendResult::Single),
+            Some("CLOSE") => self.close(message).map(SendResult::Single),
+            _ => self.req(message).map(SendResult::Single),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers every REQ with EOSE, and every EVENT and CLOSE with CLOSED
+    const FIXTURE: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (data (i32.const 0) "\64\00\00\00\12\00\00\00\c8\00\00\00\0e\00\00\00\2c\01\00\00\13\00\00\00")
+            (data (i32.const 100) "{\"name\":\"fixture\"}")
+            (data (i32.const 200) "[\"EOSE\",\"sub\"]")
+            (data (i32.const 300) "[\"CLOSED\",\"sub\",\"\"]")
+            (func (export "info") (result i32) i32.const 0)
+            (func (export "req") (param i32 i32) (result i32) i32.const 8)
+            (func (export "close") (param i32 i32) (result i32) i32.const 16)
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 1024))
+        (core instance $i (instantiate $m))
+        (func (export "req") (param "message" string) (result string)
+            (canon lift (core func $i "req") (memory (core memory $i "memory")) (realloc (core func $i "realloc"))))
+        (func (export "event") (param "message" string) (result string)
+            (canon lift (core func $i "close") (memory (core memory $i "memory")) (realloc (core func $i "realloc"))))
+        (func (export "close") (param "message" string) (result string)
+            (canon lift (core func $i "close") (memory (core memory $i "memory")) (realloc (core func $i "realloc"))))
+        (func (export "info") (result string)
+            (canon lift (core func $i "info") (memory (core memory $i "memory")))))"#;
+
+    #[test]
+    fn test_component_cassette() {
+        let mut cassette = ComponentCassette::from_bytes(FIXTURE.as_bytes()).unwrap();
+        assert_eq!(cassette.info().unwrap(), r#"{"name":"fixture"}"#);
+        match cassette.scrub(r#"["REQ","sub",{}]"#).unwrap() {
+            SendResult::Multiple(responses) => assert_eq!(responses, vec![r#"["EOSE","sub"]"#]),
+            other => panic!("expected the REQ's messages, got {:?}", other),
+        }
+        assert!(matches!(cassette.scrub(r#"["CLOSE","sub"]"#).unwrap(), SendResult::Single(closed) if closed.starts_with(r#"["CLOSED""#)));
+
+        assert!(!is_component(b"\0asm\x01\0\0\0"));
+        assert!(is_component(b"\0asm\x0d\0\x01\0"));
+    }
+}
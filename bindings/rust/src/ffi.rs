@@ -0,0 +1,181 @@
+//! C ABI for the loader, backing the generated C header and the
+//! Python/Go/Swift wrappers (see bindings/idl/cassette_loader.wit).
+//!
+//! Conventions shared with the generator:
+//! - fallible functions return 0 on success and -1 on error, writing their
+//!   result through the trailing `out` pointer; `cassette_last_error` holds the
+//!   message of the last failure on the calling thread
+//! - `list<string>` results are returned as a JSON array string
+//! - strings returned through `out` are owned by the caller and released with
+//!   `cassette_string_free`; handles are released with `cassette_free`
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use crate::{Cassette, DedupPolicy, SendResult};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+const OK: i32 = 0;
+const ERR: i32 = -1;
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Run a fallible call, storing its error for `cassette_last_error`
+fn ffi_result<T>(out: *mut T, f: impl FnOnce() -> anyhow::Result<T>) -> i32 {
+    if out.is_null() {
+        set_last_error("out pointer is null".to_string());
+        return ERR;
+    }
+    match f() {
+        Ok(value) => {
+            unsafe { out.write(value) };
+            OK
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            ERR
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> anyhow::Result<&'a str> {
+    if s.is_null() {
+        anyhow::bail!("{} is null", name);
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| anyhow::anyhow!("{} is not valid UTF-8", name))
+}
+
+unsafe fn cassette_arg<'a>(handle: *mut Cassette) -> anyhow::Result<&'a mut Cassette> {
+    handle.as_mut().ok_or_else(|| anyhow::anyhow!("cassette handle is null"))
+}
+
+fn into_c_string(s: String) -> anyhow::Result<*mut c_char> {
+    Ok(CString::new(s)?.into_raw())
+}
+
+fn into_c_json_list(items: Vec<String>) -> anyhow::Result<*mut c_char> {
+    into_c_string(serde_json::to_string(&items)?)
+}
+
+/// Message of the last error on this thread, or null. Valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn cassette_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Release a string returned by the loader
+#[no_mangle]
+pub unsafe extern "C" fn cassette_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Release a cassette handle
+#[no_mangle]
+pub unsafe extern "C" fn cassette_free(handle: *mut Cassette) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cassette_load(path: *const c_char, debug: bool, out: *mut *mut Cassette) -> i32 {
+    ffi_result(out, || {
+        let path = str_arg(path, "path")?;
+        Ok(Box::into_raw(Box::new(Cassette::load(path, debug)?)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cassette_send(handle: *mut Cassette, message: *const c_char, out: *mut *mut c_char) -> i32 {
+    ffi_result(out, || {
+        let cassette = cassette_arg(handle)?;
+        let responses = match cassette.scrub(str_arg(message, "message")?)? {
+            SendResult::Multiple(responses) => responses,
+            SendResult::Single(response) => vec![response],
+        };
+        into_c_json_list(responses)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cassette_events(handle: *mut Cassette, filter: *const c_char, out: *mut *mut c_char) -> i32 {
+    ffi_result(out, || {
+        let cassette = cassette_arg(handle)?;
+        into_c_json_list(cassette.events(str_arg(filter, "filter")?)?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cassette_count(handle: *mut Cassette, filter: *const c_char, out: *mut u64) -> i32 {
+    ffi_result(out, || {
+        let cassette = cassette_arg(handle)?;
+        cassette.count(str_arg(filter, "filter")?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cassette_info(handle: *mut Cassette, out: *mut *mut c_char) -> i32 {
+    ffi_result(out, || {
+        let cassette = cassette_arg(handle)?;
+        into_c_string(cassette.info()?)
+    })
+}
+
+/// Policy values match `CassetteDedupPolicy` in the generated header
+#[no_mangle]
+pub unsafe extern "C" fn cassette_set_dedup(handle: *mut Cassette, policy: u32) {
+    if let Some(cassette) = handle.as_mut() {
+        cassette.set_dedup_policy(match policy {
+            1 => DedupPolicy::None,
+            _ => DedupPolicy::PerSubscription,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every exported symbol must be declared in the generated header and vice versa
+    #[test]
+    fn test_header_matches_exports() {
+        let header = include_str!("../../c/include/cassette_loader.h");
+        let source = include_str!("ffi.rs");
+
+        let exported: Vec<&str> = source.lines()
+            .filter_map(|line| line.split("extern \"C\" fn ").nth(1))
+            .filter_map(|rest| rest.split('(').next())
+            .collect();
+        let declared: Vec<&str> = header.lines()
+            .filter(|line| !line.trim_start().starts_with("//") && !line.trim_start().starts_with('*'))
+            .filter_map(|line| line.split_whitespace().find(|word| word.trim_start_matches('*').starts_with("cassette_")))
+            .filter_map(|word| word.trim_start_matches('*').split('(').next())
+            .collect();
+
+        for name in &exported {
+            assert!(declared.contains(name), "{} is exported but missing from the header", name);
+        }
+        for name in &declared {
+            assert!(exported.contains(name), "{} is declared in the header but not exported", name);
+        }
+    }
+
+    #[test]
+    fn test_errors_are_reported() {
+        let path = CString::new("/nonexistent.cassette").unwrap();
+        let mut handle: *mut Cassette = ptr::null_mut();
+        let status = unsafe { cassette_load(path.as_ptr(), false, &mut handle) };
+        assert_eq!(status, ERR);
+        assert!(handle.is_null());
+        assert!(!cassette_last_error().is_null());
+    }
+}
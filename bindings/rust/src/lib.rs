@@ -1,9 +1,18 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use anyhow::{Result, Context};
 use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
 use wasmtime::*;
 
+pub mod ffi;
+pub mod component;
+pub mod signature;
+
+pub use component::ComponentCassette;
+pub use signature::SignedManifest;
+
 /// Result type for send method - either single response or multiple responses
 #[derive(Debug)]
 pub enum SendResult {
@@ -11,6 +20,69 @@ pub enum SendResult {
     Multiple(Vec<String>),
 }
 
+/// A NIP-01 `["OK", <event id>, <accepted>, <message>]` answer to an EVENT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OkMessage {
+    pub event_id: String,
+    pub accepted: bool,
+    pub message: String,
+}
+
+impl OkMessage {
+    /// Parse a response, returning None unless it is an OK message
+    pub fn parse(response: &str) -> Option<Self> {
+        let parsed: Vec<Value> = serde_json::from_str(response).ok()?;
+        if parsed.first()? != "OK" {
+            return None;
+        }
+        Some(Self {
+            event_id: parsed.get(1)?.as_str()?.to_string(),
+            accepted: parsed.get(2)?.as_bool()?,
+            message: parsed.get(3).and_then(|m| m.as_str()).unwrap_or("").to_string(),
+        })
+    }
+}
+
+/// A NIP-01 `["CLOSED", <subscription id>, <reason>]` message ending a subscription
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosedMessage {
+    pub subscription_id: String,
+    pub reason: String,
+}
+
+impl ClosedMessage {
+    /// Parse a response, returning None unless it is a CLOSED message
+    pub fn parse(response: &str) -> Option<Self> {
+        let parsed: Vec<Value> = serde_json::from_str(response).ok()?;
+        if parsed.first()? != "CLOSED" {
+            return None;
+        }
+        Some(Self {
+            subscription_id: parsed.get(1)?.as_str()?.to_string(),
+            reason: parsed.get(2).and_then(|r| r.as_str()).unwrap_or("").to_string(),
+        })
+    }
+}
+
+/// One page of a REQ's results from [`Cassette::req_page`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    /// Event JSON strings, in the order the cassette serves them
+    pub events: Vec<String>,
+    /// Where the next page starts; `None` on the last page
+    pub next_cursor: Option<u64>,
+}
+
+/// How duplicate events are filtered from responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupPolicy {
+    /// Drop events already returned since the last REQ or CLOSE
+    #[default]
+    PerSubscription,
+    /// Return every event the cassette produces
+    None,
+}
+
 /// Event tracker for deduplication
 #[derive(Debug, Clone)]
 pub struct EventTracker {
@@ -106,44 +178,170 @@ impl MemoryManager {
     }
 }
 
+/// A cassette module compiled once and instantiated as often as needed
+///
+/// Compiling is the slow part of `Cassette::load`; servers that answer many
+/// queries keep a `CompiledCassette` around and only pay for instantiation.
+#[derive(Clone)]
+pub struct CompiledCassette {
+    engine: Engine,
+    module: Module,
+    path: PathBuf,
+    // Sidecar payload, read and verified by the first instance that needs it
+    payload: Arc<Mutex<Option<Arc<String>>>>,
+}
+
+impl CompiledCassette {
+    /// Compile a cassette from a WASM file
+    pub fn compile(path: &str) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", path))?;
+        if component::is_component(&bytes) {
+            anyhow::bail!("{} is a component cassette; load it with ComponentCassette", path);
+        }
+        let engine = Engine::default();
+        let module = Module::from_binary(&engine, &bytes)?;
+        Ok(Self {
+            engine,
+            module,
+            path: PathBuf::from(path),
+            payload: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Create a fresh instance with its own memory and subscription state
+    pub fn instantiate(&self, debug: bool) -> Result<Cassette> {
+        let mut cassette = Cassette::from_module(&self.engine, &self.module, debug)?;
+        if cassette.load_payload_func.is_some() {
+            let payload = self.payload(&mut cassette)?;
+            cassette.load_payload(&payload)?;
+        }
+        Ok(cassette)
+    }
+
+    fn payload(&self, cassette: &mut Cassette) -> Result<Arc<String>> {
+        let mut cached = self.payload.lock().unwrap();
+        if let Some(payload) = cached.as_ref() {
+            return Ok(payload.clone());
+        }
+        let payload = Arc::new(read_payload(&self.path, &cassette.info()?)?);
+        *cached = Some(payload.clone());
+        Ok(payload)
+    }
+}
+
+/// NIP-01 result order: newest first, and the lowest id first within the same second
+fn newest_first(a: &Value, b: &Value) -> std::cmp::Ordering {
+    let created_at = |e: &Value| e.get("created_at").and_then(|c| c.as_i64()).unwrap_or(0);
+    created_at(b).cmp(&created_at(a))
+        .then_with(|| a.get("id").and_then(Value::as_str).cmp(&b.get("id").and_then(Value::as_str)))
+}
+
+/// The verb of a NIP-01 message, such as `REQ` or `CLOSE`
+fn message_type(message: &str) -> Option<String> {
+    serde_json::from_str::<Value>(message).ok()?.get(0)?.as_str().map(str::to_string)
+}
+
+/// Read the sidecar payload described under `cassette.payload` in a cassette's info
+/// from next to the cassette, checking it against the embedded sha256
+pub fn read_payload(cassette_path: &Path, info: &str) -> Result<String> {
+    let info: Value = serde_json::from_str(info).context("invalid cassette info")?;
+    let metadata = info.pointer("/cassette/payload").context("cassette info doesn't describe its payload")?;
+    let file = metadata.get("file").and_then(|f| f.as_str()).context("payload metadata has no file")?;
+    let expected = metadata.get("sha256").and_then(|s| s.as_str()).context("payload metadata has no sha256")?;
+
+    let path = cassette_path.parent().unwrap_or(Path::new(".")).join(file);
+    let bytes = std::fs::read(&path).with_context(|| format!("failed to read payload {}", path.display()))?;
+    let actual: String = Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect();
+    if actual != expected {
+        anyhow::bail!("payload {} has sha256 {}, the cassette expects {}", path.display(), actual, expected);
+    }
+    String::from_utf8(bytes).context("payload is not UTF-8")
+}
+
+/// Newest guest ABI this loader speaks. v1 cassettes export `req`, `close` and
+/// `describe`; v2 cassettes take every message through `scrub` (or `send`) and
+/// declare their version through an `abi_version` export.
+pub const ABI_VERSION: u32 = 2;
+
 /// Cassette loader
 pub struct Cassette {
     store: Store<()>,
     instance: Instance,
     memory_manager: MemoryManager,
     event_tracker: EventTracker,
+    dedup_policy: DedupPolicy,
     scrub_func: TypedFunc<(i32, i32), i32>,
+    close_func: Option<TypedFunc<(i32, i32), i32>>,
+    abi_version: u32,
     info_func: Option<TypedFunc<(), i32>>,
     dealloc_func: Option<TypedFunc<(i32, i32), ()>>,
     get_size_func: Option<TypedFunc<i32, i32>>,
+    load_payload_func: Option<TypedFunc<(i32, i32), i32>>,
+    unlock_func: Option<TypedFunc<(i32, i32), i32>>,
+    provenance_func: Option<TypedFunc<(i32, i32), i32>>,
+    warm_func: Option<TypedFunc<(), i32>>,
+    set_current_time_func: Option<TypedFunc<i64, ()>>,
+    req_page_func: Option<TypedFunc<(i32, i32, i64, i32), i32>>,
+    last_panic_func: Option<TypedFunc<(), i32>>,
+    result_begin_func: Option<TypedFunc<(i32, i32, i32), i32>>,
+    result_next_chunk_func: Option<TypedFunc<(), i32>>,
+    result_end_func: Option<TypedFunc<(), ()>>,
+    chunk_bytes: u32,
+    /// Time given to the cassette instead of the host's clock
+    clock: Option<i64>,
     debug: bool,
 }
 
 impl Cassette {
     /// Load a cassette from a WASM file
     pub fn load(path: &str, debug: bool) -> Result<Self> {
-        let engine = Engine::default();
-        let module = Module::from_file(&engine, path)?;
-        let mut store = Store::new(&engine, ());
-        let instance = Instance::new(&mut store, &module, &[])?;
+        CompiledCassette::compile(path)?.instantiate(debug)
+    }
+
+    fn from_module(engine: &Engine, module: &Module, debug: bool) -> Result<Self> {
+        let mut store = Store::new(engine, ());
+        let instance = Instance::new(&mut store, module, &[])?;
 
         let memory_manager = MemoryManager::new(&mut store, &instance)?;
 
         // Get scrub function (or fall back to send for backward compatibility)
         let scrub_func = match instance.get_typed_func::<(i32, i32), i32>(&mut store, "scrub") {
-            Ok(func) => func,
-            Err(_) => {
+            Ok(func) => Ok(func),
+            Err(_) => instance.get_typed_func::<(i32, i32), i32>(&mut store, "send").map(|func| {
                 if debug {
                     eprintln!("WARNING: Using deprecated 'send' function. Cassette should implement 'scrub' instead.");
                 }
+                func
+            }),
+        };
+
+        // Cassettes declare their ABI version; older ones are placed by their exports
+        let abi_version = match instance.get_typed_func::<(), i32>(&mut store, "abi_version") {
+            Ok(abi_version) => abi_version.call(&mut store, ())? as u32,
+            Err(_) if scrub_func.is_ok() => 2,
+            Err(_) => 1,
+        };
+        if !(1..=ABI_VERSION).contains(&abi_version) {
+            anyhow::bail!("cassette uses guest ABI v{}, this loader speaks v1 to v{}", abi_version, ABI_VERSION);
+        }
+        if debug {
+            eprintln!("[Cassette] Guest ABI v{}", abi_version);
+        }
+
+        // v1 cassettes answer REQ through `req` and CLOSE through `close`
+        let (scrub_func, close_func) = match abi_version {
+            1 => (
                 instance
-                    .get_typed_func::<(i32, i32), i32>(&mut store, "send")
-                    .context("Neither scrub nor send function found in cassette")?
-            }
+                    .get_typed_func::<(i32, i32), i32>(&mut store, "req")
+                    .context("v1 cassette has no req function")?,
+                instance.get_typed_func::<(i32, i32), i32>(&mut store, "close").ok(),
+            ),
+            _ => (scrub_func.context("Neither scrub nor send function found in cassette")?, None),
         };
 
         let info_func = instance
             .get_typed_func::<(), i32>(&mut store, "info")
+            .or_else(|_| instance.get_typed_func::<(), i32>(&mut store, "describe"))
             .ok();
 
         let dealloc_func = instance
@@ -154,19 +352,147 @@ impl Cassette {
             .get_typed_func::<i32, i32>(&mut store, "get_allocation_size")
             .ok();
 
+        // Only cassettes recorded with a sidecar payload export this
+        let load_payload_func = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "load_payload")
+            .ok();
+
+        // Only cassettes recorded with --encrypt export this
+        let unlock_func = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "unlock")
+            .ok();
+
+        // Only cassettes dubbed with --provenance list where their events came from
+        let provenance_func = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "provenance")
+            .ok();
+
+        let warm_func = instance
+            .get_typed_func::<(), i32>(&mut store, "warm")
+            .ok();
+
+        // Cassettes that hide expired events (NIP-40) take the time from the host
+        let set_current_time_func = instance
+            .get_typed_func::<i64, ()>(&mut store, "set_current_time")
+            .ok();
+
+        let req_page_func = instance
+            .get_typed_func::<(i32, i32, i64, i32), i32>(&mut store, "req_page")
+            .ok();
+
+        // A panic traps the call; the cassette keeps its message as a NOTICE
+        let last_panic_func = instance
+            .get_typed_func::<(), i32>(&mut store, "last_panic")
+            .ok();
+
+        // Cassettes that can hand out a whole answer in bounded chunks
+        let result_begin_func = instance
+            .get_typed_func::<(i32, i32, i32), i32>(&mut store, "result_begin")
+            .ok();
+        let result_next_chunk_func = instance
+            .get_typed_func::<(), i32>(&mut store, "result_next_chunk")
+            .ok();
+        let result_end_func = instance
+            .get_typed_func::<(), ()>(&mut store, "result_end")
+            .ok();
+
         Ok(Self {
             store,
             instance,
             memory_manager,
             event_tracker: EventTracker::new(),
+            dedup_policy: DedupPolicy::default(),
             scrub_func,
+            close_func,
+            abi_version,
             info_func,
             dealloc_func,
             get_size_func,
+            load_payload_func,
+            unlock_func,
+            provenance_func,
+            warm_func,
+            set_current_time_func,
+            req_page_func,
+            last_panic_func,
+            result_begin_func,
+            result_next_chunk_func,
+            result_end_func,
+            chunk_bytes: 0,
+            clock: None,
             debug,
         })
     }
 
+    /// Parse the cassette's events ahead of the first query, returning how many it holds.
+    /// The parsed events stay in the instance, so later queries on it skip parsing.
+    /// `None` for cassettes built before the `warm` export.
+    pub fn warm(&mut self) -> Result<Option<u64>> {
+        let Some(warm) = &self.warm_func else { return Ok(None) };
+        match warm.call(&mut self.store, ())? {
+            count if count < 0 => anyhow::bail!("cassette failed to load its events"),
+            count => Ok(Some(count as u64)),
+        }
+    }
+
+    // Hand the verified sidecar payload to the guest
+    fn load_payload(&mut self, payload: &str) -> Result<()> {
+        let Some(load_payload) = &self.load_payload_func else { return Ok(()) };
+        let ptr = self.memory_manager.write_string(&mut self.store, payload)?;
+        let status = load_payload.call(&mut self.store, (ptr, payload.len() as i32))?;
+        if let Some(dealloc) = &self.dealloc_func {
+            let _ = dealloc.call(&mut self.store, (ptr, payload.len() as i32));
+        }
+        if status != 1 {
+            anyhow::bail!("cassette rejected its payload");
+        }
+        Ok(())
+    }
+
+    /// Whether the cassette's events are encrypted (`record --encrypt`) and need [`unlock`](Self::unlock)
+    pub fn is_encrypted(&self) -> bool {
+        self.unlock_func.is_some()
+    }
+
+    /// Decrypt an encrypted cassette's events with the recipient's secret key (64 hex
+    /// characters); until then every query fails. A no-op for cassettes that aren't encrypted.
+    pub fn unlock(&mut self, secret_key: &str) -> Result<()> {
+        let Some(unlock) = &self.unlock_func else { return Ok(()) };
+        let ptr = self.memory_manager.write_string(&mut self.store, secret_key)?;
+        let status = unlock.call(&mut self.store, (ptr, secret_key.len() as i32))?;
+        if let Some(dealloc) = &self.dealloc_func {
+            let _ = dealloc.call(&mut self.store, (ptr, secret_key.len() as i32));
+        }
+        if status != 1 {
+            anyhow::bail!("wrong key: the cassette's events did not decrypt");
+        }
+        Ok(())
+    }
+
+    /// The dub input an event came from, as `{"name", "sha256"}` JSON, for cassettes
+    /// dubbed with `--provenance`; `None` for other cassettes and events the table doesn't list
+    pub fn provenance(&mut self, event_id: &str) -> Result<Option<String>> {
+        let Some(provenance) = &self.provenance_func else { return Ok(None) };
+        let ptr = self.memory_manager.write_string(&mut self.store, event_id)?;
+        let result_ptr = provenance.call(&mut self.store, (ptr, event_id.len() as i32))?;
+        if let Some(dealloc) = &self.dealloc_func {
+            let _ = dealloc.call(&mut self.store, (ptr, event_id.len() as i32));
+        }
+        if result_ptr == 0 {
+            return Ok(None);
+        }
+        let source = self.memory_manager.read_string(&mut self.store, result_ptr)?;
+        if let Some(dealloc) = &self.dealloc_func {
+            let _ = dealloc.call(&mut self.store, (result_ptr, source.len() as i32));
+        }
+        Ok(Some(source))
+    }
+
+    /// The guest ABI version the cassette is spoken to with
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
     /// Get cassette description by synthesizing from info
     pub fn describe(&mut self) -> Result<String> {
         match self.info() {
@@ -193,18 +519,77 @@ impl Cassette {
                         }
                         
                         if parts.is_empty() {
-                            Ok("No description available".to_string())
-                        } else {
-                            Ok(parts.join(" - "))
+                            parts.push("No description available".to_string());
                         }
+                        
+                        if let Some(meta) = info.pointer("/cassette/meta").and_then(|m| m.as_object()) {
+                            for (key, value) in meta {
+                                parts.push(format!("{}: {}", key, value.as_str().map_or_else(|| value.to_string(), String::from)));
+                            }
+                        }
+                        
+                        parts.push(format!("ABI v{}", self.abi_version));
+                        
+                        if let Some(example) = info.pointer("/cassette/examples/0/message") {
+                            parts.push(format!("Try: {}", example));
+                        }
+                        
+                        Ok(parts.join(" - "))
                     }
-                    Err(_) => Ok("Invalid cassette info format".to_string())
+                    Err(_) => Ok(format!("Invalid cassette info format - ABI v{}", self.abi_version))
                 }
             }
-            Err(_) => Ok("No cassette info available".to_string())
+            Err(_) => Ok(format!("No cassette info available - ABI v{}", self.abi_version))
         }
     }
 
+    /// Custom key/values embedded at record time with `record --meta`
+    pub fn meta(&mut self) -> Result<BTreeMap<String, String>> {
+        let info: Value = serde_json::from_str(&self.info()?)?;
+        let meta = info.pointer("/cassette/meta").and_then(|m| m.as_object()).cloned().unwrap_or_default();
+        Ok(meta.into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_str()?.to_string())))
+            .collect())
+    }
+
+    /// Check the manifest embedded by `record --sign`: its signature, and that the events the
+    /// cassette holds hash to the signed value. Fails for unsigned cassettes.
+    pub fn verify_manifest(&mut self) -> Result<SignedManifest> {
+        let info: Value = serde_json::from_str(&self.info()?).context("invalid cassette info")?;
+        let manifest = info.pointer("/cassette/signed_manifest")
+            .context("cassette has no signed manifest")?;
+        let signed = signature::check_manifest(manifest)?;
+
+        // Every event counts, including ones past their NIP-40 expiration
+        self.clock = Some(0);
+        let events = self.events("{}");
+        self.clock = None;
+        let events: Vec<Value> = events?.iter()
+            .map(|event| serde_json::from_str(event))
+            .collect::<Result<_, _>>()?;
+
+        if events.len() as u64 != signed.events {
+            anyhow::bail!("cassette holds {} events, the manifest signs for {}", events.len(), signed.events);
+        }
+        let hash = signature::event_set_hash(&events);
+        if hash != signed.payload {
+            anyhow::bail!("event set hashes to {}, the manifest signs {}", hash, signed.payload);
+        }
+        Ok(signed)
+    }
+
+    /// Example messages embedded at record time as `(description, message)`, each known to return events
+    pub fn examples(&mut self) -> Result<Vec<(String, String)>> {
+        let info: Value = serde_json::from_str(&self.info()?)?;
+        let examples = info.pointer("/cassette/examples").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        Ok(examples.iter()
+            .filter_map(|example| Some((
+                example.get("description")?.as_str()?.to_string(),
+                example.get("message")?.to_string(),
+            )))
+            .collect())
+    }
+
     /// Scrub/query the cassette with any NIP-01 message
     /// For REQ messages, returns a Vec of responses. For other messages, returns a single response.
     pub fn scrub(&mut self, message: &str) -> Result<SendResult> {
@@ -251,6 +636,137 @@ impl Cassette {
         }
     }
 
+    /// Scrub with a callback for each response as it is produced, so callers can forward
+    /// events while a REQ streams. Returning false from `on_message` cancels a REQ: the
+    /// subscription is closed inside the cassette and no further events are read.
+    /// Returns false if the stream was cancelled.
+    pub fn scrub_streaming(&mut self, message: &str, mut on_message: impl FnMut(String) -> bool) -> Result<bool> {
+        let subscription_id = match serde_json::from_str::<Vec<Value>>(message) {
+            Ok(msg_data) if msg_data.len() >= 2 && msg_data[0].as_str() == Some("REQ") => {
+                msg_data[1].as_str().unwrap_or("").to_string()
+            }
+            _ => {
+                return match self.scrub(message)? {
+                    SendResult::Single(response) => Ok(response.is_empty() || on_message(response)),
+                    SendResult::Multiple(responses) => Ok(responses.into_iter().all(on_message)),
+                };
+            }
+        };
+
+        self.event_tracker.reset();
+        self._stream_req(message, &subscription_id, on_message)
+    }
+
+    /// Largest chunk, in bytes, that cassettes with chunked results hand a REQ's answer
+    /// over in (0, the default, leaves it to the cassette)
+    pub fn set_chunk_bytes(&mut self, chunk_bytes: u32) {
+        self.chunk_bytes = chunk_bytes;
+    }
+
+    /// Set how duplicate events are filtered from responses
+    pub fn set_dedup_policy(&mut self, policy: DedupPolicy) {
+        self.dedup_policy = policy;
+    }
+
+    /// Get all events matching a filter (JSON object) as event JSON strings
+    pub fn events(&mut self, filter: &str) -> Result<Vec<String>> {
+        let filter: Value = serde_json::from_str(filter).context("invalid filter JSON")?;
+        let req = json!(["REQ", "loader-events", filter]).to_string();
+
+        let responses = match self.scrub(&req)? {
+            SendResult::Multiple(responses) => responses,
+            SendResult::Single(response) => vec![response],
+        };
+        if let Some(closed) = responses.iter().find_map(|response| ClosedMessage::parse(response)) {
+            anyhow::bail!("cassette closed the query: {}", closed.reason);
+        }
+
+        Ok(responses.iter()
+            .filter_map(|response| serde_json::from_str::<Vec<Value>>(response).ok())
+            .filter(|parsed| parsed.len() >= 3 && parsed[0] == "EVENT")
+            .map(|parsed| parsed[2].to_string())
+            .collect())
+    }
+
+    /// The current version of the addressable (NIP-33) event `kind:pubkey:d` as event JSON,
+    /// or `None` when the cassette doesn't have it
+    pub fn addressable(&mut self, kind: u64, pubkey: &str, d: &str) -> Result<Option<String>> {
+        let filter = json!({"kinds": [kind], "authors": [pubkey], "#d": [d]}).to_string();
+        // Newest wins; on a created_at tie the lowest id does (NIP-01)
+        Ok(self.events(&filter)?
+            .into_iter()
+            .filter_map(|event| serde_json::from_str::<Value>(&event).ok().map(|parsed| (parsed, event)))
+            .min_by(|(a, _), (b, _)| {
+                let created_at = |e: &Value| e.get("created_at").and_then(|c| c.as_i64()).unwrap_or(0);
+                let id = |e: &Value| e.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+                created_at(b).cmp(&created_at(a)).then_with(|| id(a).cmp(&id(b)))
+            })
+            .map(|(_, event)| event))
+    }
+
+    /// Count events matching a filter (JSON object), using NIP-45 COUNT when the cassette supports it
+    pub fn count(&mut self, filter: &str) -> Result<u64> {
+        let filter_value: Value = serde_json::from_str(filter).context("invalid filter JSON")?;
+        let count_msg = json!(["COUNT", "loader-count", filter_value]).to_string();
+
+        if let SendResult::Single(response) = self.scrub(&count_msg)? {
+            if let Some(closed) = ClosedMessage::parse(&response) {
+                anyhow::bail!("cassette closed the count: {}", closed.reason);
+            }
+            if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&response) {
+                if parsed.len() >= 3 && parsed[0] == "COUNT" {
+                    if let Some(count) = parsed[2].get("count").and_then(|c| c.as_u64()) {
+                        return Ok(count);
+                    }
+                }
+            }
+        }
+
+        Ok(self.events(filter)?.len() as u64)
+    }
+
+    /// Offer an event (JSON object) to the cassette, returning its OK answer
+    pub fn publish(&mut self, event: &str) -> Result<OkMessage> {
+        let event: Value = serde_json::from_str(event).context("invalid event JSON")?;
+        let response = match self.scrub(&json!(["EVENT", event]).to_string())? {
+            SendResult::Single(response) => response,
+            SendResult::Multiple(responses) => responses.into_iter().next().unwrap_or_default(),
+        };
+        OkMessage::parse(&response).with_context(|| format!("expected an OK message, got {}", response))
+    }
+
+    /// One page of up to `page_size` results (0 for the cassette's default) of a REQ
+    /// message, starting at `cursor`: 0 for the first page, then each page's
+    /// `next_cursor`. Each call runs the query afresh, so no subscription is held open.
+    /// Fails for cassettes built before the `req_page` export.
+    pub fn req_page(&mut self, message: &str, cursor: u64, page_size: u32) -> Result<Page> {
+        let Some(req_page) = self.req_page_func.clone() else {
+            anyhow::bail!("cassette has no req_page export; use scrub() instead");
+        };
+        self._set_current_time()?;
+        let msg_ptr = self.memory_manager.write_string(&mut self.store, message)?;
+        let result_ptr = req_page.call(&mut self.store, (msg_ptr, message.len() as i32, cursor as i64, page_size as i32))
+            .or_else(|trap| self._recover(trap))?;
+        if let Some(dealloc) = &self.dealloc_func {
+            let _ = dealloc.call(&mut self.store, (msg_ptr, message.len() as i32));
+        }
+        if result_ptr == 0 {
+            anyhow::bail!("req_page() returned null pointer");
+        }
+
+        let response = self._take_result(result_ptr)?;
+        if let Some(closed) = ClosedMessage::parse(&response) {
+            anyhow::bail!("cassette closed the query: {}", closed.reason);
+        }
+        let page: Value = serde_json::from_str(&response).context("invalid page JSON")?;
+        let events = page.get("events").and_then(|e| e.as_array())
+            .with_context(|| format!("expected a page, got {}", response))?;
+        Ok(Page {
+            events: events.iter().map(|event| event.to_string()).collect(),
+            next_cursor: page.get("next_cursor").and_then(|c| c.as_u64()),
+        })
+    }
+
     /// Deprecated: Use scrub() instead
     pub fn send(&mut self, message: &str) -> Result<SendResult> {
         if self.debug {
@@ -260,12 +776,58 @@ impl Cassette {
     }
 
     // Private method for single send call
+    // Give cassettes that hide expired events (NIP-40) the host's clock
+    fn _set_current_time(&mut self) -> Result<()> {
+        if let Some(set_current_time) = &self.set_current_time_func {
+            let now = self.clock.unwrap_or_else(|| std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64));
+            set_current_time.call(&mut self.store, now)?;
+        }
+        Ok(())
+    }
+
+    // Answer a trapped call with the cassette's internal error NOTICE when it recorded a panic
+    fn _recover(&mut self, trap: anyhow::Error) -> Result<i32> {
+        let Some(last_panic) = &self.last_panic_func else { return Err(trap) };
+        match last_panic.call(&mut self.store, ()) {
+            Ok(ptr) if ptr != 0 => {
+                if self.debug {
+                    eprintln!("[Cassette] Call trapped: {}", trap);
+                }
+                Ok(ptr)
+            }
+            _ => Err(trap),
+        }
+    }
+
+    // Read a response and hand its memory back to the cassette
+    fn _take_result(&mut self, result_ptr: i32) -> Result<String> {
+        let result_str = self.memory_manager.read_string(&mut self.store, result_ptr)?;
+        if let Some(dealloc) = &self.dealloc_func {
+            let size = if let Some(get_size) = &self.get_size_func {
+                get_size.call(&mut self.store, result_ptr).unwrap_or(result_str.len() as i32)
+            } else {
+                result_str.len() as i32
+            };
+            let _ = dealloc.call(&mut self.store, (result_ptr, size));
+        }
+        Ok(result_str)
+    }
+
     fn _send_single(&mut self, message: &str) -> Result<String> {
+        self._set_current_time()?;
+
         // Write message to memory
         let msg_ptr = self.memory_manager.write_string(&mut self.store, message)?;
 
-        // Call scrub function
-        let result_ptr = self.scrub_func.call(&mut self.store, (msg_ptr, message.len() as i32))?;
+        // Call scrub function, or a v1 cassette's close for CLOSE
+        let func = match &self.close_func {
+            Some(close) if message_type(message).as_deref() == Some("CLOSE") => close,
+            _ => &self.scrub_func,
+        };
+        let result_ptr = func.call(&mut self.store, (msg_ptr, message.len() as i32))
+            .or_else(|trap| self._recover(trap))?;
 
         // Deallocate message
         if let Some(dealloc) = &self.dealloc_func {
@@ -276,18 +838,7 @@ impl Cassette {
             return Ok(json!(["NOTICE", "scrub() returned null pointer"]).to_string());
         }
 
-        // Read result
-        let result_str = self.memory_manager.read_string(&mut self.store, result_ptr)?;
-
-        // Deallocate result
-        if let Some(dealloc) = &self.dealloc_func {
-            let size = if let Some(get_size) = &self.get_size_func {
-                get_size.call(&mut self.store, result_ptr).unwrap_or(result_str.len() as i32)
-            } else {
-                result_str.len() as i32
-            };
-            let _ = dealloc.call(&mut self.store, (result_ptr, size));
-        }
+        let result_str = self._take_result(result_ptr)?;
 
         // Process results
         self._process_results(&result_str)
@@ -322,7 +873,7 @@ impl Cassette {
                         }
 
                         // Filter duplicate events
-                        if msg_type == "EVENT" && parsed.len() >= 3 {
+                        if msg_type == "EVENT" && parsed.len() >= 3 && self.dedup_policy == DedupPolicy::PerSubscription {
                             if let Some(event) = parsed[2].as_object() {
                                 if let Some(event_id) = event.get("id").and_then(|v| v.as_str()) {
                                     if !self.event_tracker.add_and_check(event_id) {
@@ -350,7 +901,7 @@ impl Cassette {
 
         // Single message - check for duplicate
         if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(result_str) {
-            if parsed.len() >= 3 && parsed[0] == "EVENT" {
+            if parsed.len() >= 3 && parsed[0] == "EVENT" && self.dedup_policy == DedupPolicy::PerSubscription {
                 if let Some(event) = parsed[2].as_object() {
                     if let Some(event_id) = event.get("id").and_then(|v| v.as_str()) {
                         if !self.event_tracker.add_and_check(event_id) {
@@ -369,11 +920,22 @@ impl Cassette {
 
     // Private method to collect all events for REQ messages  
     fn _collect_all_events_for_req(&mut self, message: &str, subscription_id: &str) -> Result<Vec<String>> {
+        let mut results = Vec::new();
+        self._stream_req(message, subscription_id, |response| {
+            results.push(response);
+            true
+        })?;
+        Ok(results)
+    }
+
+    // Call the cassette until EOSE or CLOSED, handing each response to `on_message`
+    fn _stream_req(&mut self, message: &str, subscription_id: &str, mut on_message: impl FnMut(String) -> bool) -> Result<bool> {
         if self.debug {
             eprintln!("[Cassette] Collecting all events for REQ subscription: {}", subscription_id);
         }
-
-        let mut results = Vec::new();
+        if self.result_begin_func.is_some() {
+            return self._stream_req_chunked(message, subscription_id, on_message);
+        }
 
         // Keep calling until we get EOSE or terminating condition
         loop {
@@ -393,23 +955,23 @@ impl Cassette {
                     let msg_type = parsed[0].as_str().unwrap_or("");
 
                     match msg_type {
-                        "EOSE" => {
-                            if self.debug {
-                                eprintln!("[Cassette] Received EOSE for subscription {}", subscription_id);
-                            }
-                            results.push(response);
-                            break;
-                        }
-                        "CLOSED" => {
+                        "EOSE" | "CLOSED" => {
                             if self.debug {
-                                eprintln!("[Cassette] Received CLOSED for subscription {}", subscription_id);
+                                eprintln!("[Cassette] Received {} for subscription {}", msg_type, subscription_id);
                             }
-                            results.push(response);
-                            break;
+                            on_message(response);
+                            return Ok(true);
                         }
                         _ => {
-                            // Add the response to results
-                            results.push(response);
+                            if !on_message(response) {
+                                // Drop the cassette's cursor for this subscription
+                                if self.debug {
+                                    eprintln!("[Cassette] Subscription {} cancelled, closing", subscription_id);
+                                }
+                                self._send_single(&json!(["CLOSE", subscription_id]).to_string())?;
+                                self.event_tracker.reset();
+                                return Ok(false);
+                            }
                         }
                     }
                 }
@@ -422,24 +984,62 @@ impl Cassette {
             }
         }
 
-        // Check if we have an EOSE message
-        let has_eose = results.iter().any(|r| {
-            if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(r) {
-                parsed.len() >= 1 && parsed[0].as_str() == Some("EOSE")
-            } else {
-                false
-            }
-        });
+        // The cassette stopped without terminating the subscription, so add an EOSE
+        on_message(json!(["EOSE", subscription_id]).to_string());
+        Ok(true)
+    }
 
-        // If no EOSE, add one
-        if !has_eose {
-            results.push(json!(["EOSE", subscription_id]).to_string());
-        }
 
-        Ok(results)
-    }
 
+    // Have the cassette queue the REQ's whole answer and read it back a bounded chunk
+    // at a time, which ends with its EOSE or CLOSED
+    fn _stream_req_chunked(&mut self, message: &str, subscription_id: &str, mut on_message: impl FnMut(String) -> bool) -> Result<bool> {
+        let (Some(result_begin), Some(result_next_chunk)) = (self.result_begin_func.clone(), self.result_next_chunk_func.clone()) else {
+            anyhow::bail!("cassette has no chunked result exports");
+        };
+        self._set_current_time()?;
+        let msg_ptr = self.memory_manager.write_string(&mut self.store, message)?;
+        let queued = match result_begin.call(&mut self.store, (msg_ptr, message.len() as i32, self.chunk_bytes as i32)) {
+            Ok(queued) => queued,
+            Err(trap) => {
+                let notice = self._recover(trap)?;
+                on_message(self._take_result(notice)?);
+                return Ok(true);
+            }
+        };
+        if let Some(dealloc) = &self.dealloc_func {
+            let _ = dealloc.call(&mut self.store, (msg_ptr, message.len() as i32));
+        }
+        if queued < 0 {
+            anyhow::bail!("result_begin() couldn't read the REQ");
+        }
+        if self.debug {
+            eprintln!("[Cassette] {} messages queued for subscription {}", queued, subscription_id);
+        }
 
+        loop {
+            let chunk_ptr = result_next_chunk.call(&mut self.store, ())
+                .or_else(|trap| self._recover(trap))?;
+            if chunk_ptr == 0 {
+                return Ok(true);
+            }
+            let chunk = self._take_result(chunk_ptr)?;
+            for line in chunk.lines() {
+                // Duplicates come back empty
+                let response = self._process_results(line)?;
+                if response.is_empty() {
+                    continue;
+                }
+                if !on_message(response) {
+                    if let Some(result_end) = &self.result_end_func {
+                        result_end.call(&mut self.store, ())?;
+                    }
+                    self.event_tracker.reset();
+                    return Ok(false);
+                }
+            }
+        }
+    }
 
     /// Get NIP-11 relay information
     pub fn info(&mut self) -> Result<String> {
@@ -462,4 +1062,154 @@ impl Cassette {
 
         Ok(info_str)
     }
-}
\ No newline at end of file
+}
+
+/// One cassette of a segmented archive, instantiated the first time a query reaches it
+struct Segment {
+    path: PathBuf,
+    since: Option<i64>,
+    until: Option<i64>,
+    /// Kinds the segment holds, when the manifest lists them
+    kinds: Option<HashSet<i64>>,
+    cassette: Option<Cassette>,
+}
+
+impl Segment {
+    /// Whether a filter's `since`/`until` window overlaps this segment and its `kinds` are in it
+    fn reachable(&self, filter: &Value) -> bool {
+        let since = filter.get("since").and_then(|s| s.as_i64());
+        let until = filter.get("until").and_then(|u| u.as_i64());
+        let kinds_match = match (filter.get("kinds").and_then(|k| k.as_array()), &self.kinds) {
+            (Some(wanted), Some(held)) => wanted.iter().filter_map(|k| k.as_i64()).any(|kind| held.contains(&kind)),
+            _ => true,
+        };
+        kinds_match
+            && !matches!((since, self.until), (Some(since), Some(end)) if since > end)
+            && !matches!((until, self.since), (Some(until), Some(start)) if until < start)
+    }
+
+    fn cassette(&mut self, debug: bool) -> Result<&mut Cassette> {
+        if self.cassette.is_none() {
+            let cassette = Cassette::load(&self.path.to_string_lossy(), debug)
+                .with_context(|| format!("failed to load segment {}", self.path.display()))?;
+            self.cassette = Some(cassette);
+        }
+        Ok(self.cassette.as_mut().unwrap())
+    }
+}
+
+/// An archive recorded with `--segment-bytes`, `--split-events` or `--split-by`,
+/// queried as one cassette
+///
+/// The `<name>.segments.json` manifest lists segment cassettes with their time
+/// windows and kinds. Queries only instantiate the segments their time range
+/// and kinds can reach, and a `limit` stops once no segment left can hold an
+/// event newer than the ones collected. Segments split by kind overlap in time,
+/// so that point can come later than with time-ordered segments.
+pub struct SegmentedCassette {
+    segments: Vec<Segment>,
+    debug: bool,
+}
+
+impl SegmentedCassette {
+    /// Open a segment manifest; segment files are resolved next to it
+    pub fn open(manifest_path: &str, debug: bool) -> Result<Self> {
+        let manifest_path = Path::new(manifest_path);
+        let manifest: Value = serde_json::from_str(&std::fs::read_to_string(manifest_path)?)
+            .context("invalid segment manifest")?;
+        let dir = manifest_path.parent().unwrap_or(Path::new("."));
+
+        let mut segments = Vec::new();
+        for entry in manifest.get("segments").and_then(|s| s.as_array()).context("manifest has no segments")? {
+            let file = entry.get("file").and_then(|f| f.as_str()).context("segment without a file")?;
+            segments.push(Segment {
+                path: dir.join(file),
+                since: entry.get("since").and_then(|s| s.as_i64()),
+                until: entry.get("until").and_then(|u| u.as_i64()),
+                kinds: entry.get("kinds").and_then(|k| k.as_array())
+                    .map(|kinds| kinds.iter().filter_map(|k| k.as_i64()).collect()),
+                cassette: None,
+            });
+        }
+        // Newest first, whatever order the manifest was written in
+        segments.sort_by(|a, b| b.until.cmp(&a.until));
+
+        Ok(Self { segments, debug })
+    }
+
+    /// Number of segments in the archive
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Get all events matching a filter (JSON object) as event JSON strings, newest first
+    pub fn events(&mut self, filter: &str) -> Result<Vec<String>> {
+        let filter_value: Value = serde_json::from_str(filter).context("invalid filter JSON")?;
+        let limit = filter_value.get("limit").and_then(|l| l.as_u64()).map(|l| l as usize);
+
+        let mut events = Vec::new();
+        for segment in self.segments.iter_mut().filter(|s| s.reachable(&filter_value)) {
+            // Segments come newest `until` first: once `limit` events are newer than
+            // everything this segment holds, none of the rest can displace them
+            if let Some(limit) = limit.filter(|&limit| events.len() >= limit) {
+                events.sort_by(newest_first);
+                events.truncate(limit);
+                let oldest_kept = events.last().and_then(|e| e.get("created_at")).and_then(|c| c.as_i64());
+                if limit == 0 || matches!((segment.until, oldest_kept), (Some(until), Some(oldest)) if until < oldest) {
+                    break;
+                }
+            }
+            events.extend(segment.cassette(self.debug)?.events(filter)?
+                .into_iter()
+                .filter_map(|event| serde_json::from_str::<Value>(&event).ok()));
+        }
+
+        events.sort_by(newest_first);
+        if let Some(limit) = limit {
+            events.truncate(limit);
+        }
+        Ok(events.iter().map(|event| event.to_string()).collect())
+    }
+
+    /// Count events matching a filter (JSON object) across the segments it can reach
+    pub fn count(&mut self, filter: &str) -> Result<u64> {
+        let filter_value: Value = serde_json::from_str(filter).context("invalid filter JSON")?;
+        if filter_value.get("limit").is_some() {
+            return Ok(self.events(filter)?.len() as u64);
+        }
+
+        let mut total = 0;
+        for segment in self.segments.iter_mut().filter(|s| s.reachable(&filter_value)) {
+            total += segment.cassette(self.debug)?.count(filter)?;
+        }
+        Ok(total)
+    }
+
+    /// Answer a REQ with the events of every filter, deduplicated and newest first, then EOSE
+    pub fn scrub(&mut self, message: &str) -> Result<SendResult> {
+        let parsed: Vec<Value> = serde_json::from_str(message).context("invalid message JSON")?;
+        if parsed.len() < 3 || parsed[0] != "REQ" {
+            anyhow::bail!("segmented archives only answer REQ messages");
+        }
+        let subscription_id = parsed[1].as_str().unwrap_or("");
+
+        let mut seen = HashSet::new();
+        let mut events = Vec::new();
+        for filter in &parsed[2..] {
+            for event in self.events(&filter.to_string())? {
+                let event: Value = serde_json::from_str(&event)?;
+                let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+                if seen.insert(id) {
+                    events.push(event);
+                }
+            }
+        }
+
+        events.sort_by(newest_first);
+        let mut responses: Vec<String> = events.into_iter()
+            .map(|event| json!(["EVENT", subscription_id, event]).to_string())
+            .collect();
+        responses.push(json!(["EOSE", subscription_id]).to_string());
+        Ok(SendResult::Multiple(responses))
+    }
+}
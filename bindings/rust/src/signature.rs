@@ -0,0 +1,143 @@
+//! Signed cassette manifests
+//!
+//! `cassette record --sign <key>` embeds a NIP-98-style event under
+//! `cassette.signed_manifest` in the NIP-11 info: a kind 27235 event by the
+//! creator whose `payload` tag is the sha256 of the cassette's event set,
+//! with the cassette name in its `u` tag and the number of events in an
+//! `events` tag. The event set is hashed in a canonical form so any host can
+//! recompute it from the events the cassette serves: each event reduced to
+//! its seven NIP-01 fields and serialized with sorted keys, newest first
+//! (ties by id), one per line. [`Cassette::verify_manifest`](crate::Cassette::verify_manifest)
+//! checks the signature and the hash before a cassette is trusted.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// Kind of the manifest event (NIP-98 HTTP auth)
+pub const MANIFEST_KIND: i64 = 27235;
+
+/// `method` tag of a manifest
+pub const MANIFEST_METHOD: &str = "RECORD";
+
+/// What a valid manifest vouches for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedManifest {
+    /// Creator's pubkey (hex)
+    pub pubkey: String,
+    /// When the cassette was signed
+    pub created_at: i64,
+    /// Cassette name, from the `u` tag
+    pub name: String,
+    /// sha256 of the canonical event set (hex)
+    pub payload: String,
+    /// Number of events signed for
+    pub events: u64,
+}
+
+/// sha256 of an event set in canonical form, whatever order the events come in
+pub fn event_set_hash<'a>(events: impl IntoIterator<Item = &'a Value>) -> String {
+    let mut events: Vec<Value> = events.into_iter()
+        .map(|event| json!({
+            "id": event.get("id"),
+            "pubkey": event.get("pubkey"),
+            "created_at": event.get("created_at"),
+            "kind": event.get("kind"),
+            "tags": event.get("tags"),
+            "content": event.get("content"),
+            "sig": event.get("sig"),
+        }))
+        .collect();
+    events.sort_by(crate::newest_first);
+    let mut hasher = Sha256::new();
+    for event in &events {
+        hasher.update(event.to_string().as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// The NIP-01 id of an event's fields
+pub fn event_id(pubkey: &str, created_at: i64, kind: i64, tags: &Value, content: &str) -> String {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    hex::encode(Sha256::digest(serialized.as_bytes()))
+}
+
+/// Unsigned manifest event fields (`pubkey`, `created_at`, `kind`, `tags`, `content`)
+pub fn manifest_template(pubkey: &str, created_at: i64, name: &str, payload: &str, events: usize) -> Value {
+    json!({
+        "pubkey": pubkey,
+        "created_at": created_at,
+        "kind": MANIFEST_KIND,
+        "tags": [
+            ["u", format!("cassette:{}", name)],
+            ["method", MANIFEST_METHOD],
+            ["payload", payload],
+            ["events", events.to_string()],
+        ],
+        "content": "",
+    })
+}
+
+fn tag<'a>(manifest: &'a Value, name: &str) -> Option<&'a str> {
+    manifest.get("tags")?.as_array()?.iter()
+        .filter_map(|tag| tag.as_array())
+        .find(|tag| tag.first().and_then(|n| n.as_str()) == Some(name))?
+        .get(1)?.as_str()
+}
+
+/// Check a manifest event's id and signature and read what it vouches for
+pub fn check_manifest(manifest: &Value) -> Result<SignedManifest> {
+    use k256::schnorr::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+    let field = |name: &str| manifest.get(name).ok_or_else(|| anyhow!("manifest has no {}", name));
+    let pubkey = field("pubkey")?.as_str().ok_or_else(|| anyhow!("manifest pubkey is not a string"))?;
+    let created_at = field("created_at")?.as_i64().ok_or_else(|| anyhow!("manifest created_at is not a number"))?;
+    let kind = field("kind")?.as_i64().ok_or_else(|| anyhow!("manifest kind is not a number"))?;
+    let content = field("content")?.as_str().unwrap_or("");
+    let id = field("id")?.as_str().unwrap_or("");
+    let sig = field("sig")?.as_str().unwrap_or("");
+    if kind != MANIFEST_KIND || tag(manifest, "method") != Some(MANIFEST_METHOD) {
+        bail!("not a cassette manifest (kind {})", kind);
+    }
+    if event_id(pubkey, created_at, kind, field("tags")?, content) != id {
+        bail!("manifest id does not match its content");
+    }
+
+    let key = hex::decode(pubkey).ok()
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| anyhow!("invalid manifest pubkey"))?;
+    let signature = hex::decode(sig).ok()
+        .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| anyhow!("invalid manifest signature encoding"))?;
+    let message = hex::decode(id).map_err(|_| anyhow!("invalid manifest id encoding"))?;
+    key.verify_prehash(&message, &signature).map_err(|_| anyhow!("invalid manifest signature"))?;
+
+    Ok(SignedManifest {
+        pubkey: pubkey.to_string(),
+        created_at,
+        name: tag(manifest, "u").map(|u| u.strip_prefix("cassette:").unwrap_or(u).to_string()).unwrap_or_default(),
+        payload: tag(manifest, "payload").ok_or_else(|| anyhow!("manifest has no payload tag"))?.to_string(),
+        events: tag(manifest, "events").and_then(|n| n.parse().ok()).unwrap_or(0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_set_hash() {
+        let a = json!({"id": "aa", "pubkey": "p", "created_at": 2, "kind": 1, "tags": [], "content": "a", "sig": "s"});
+        let b = json!({"kind": 1, "id": "bb", "pubkey": "p", "created_at": 1, "tags": [], "content": "b", "sig": "s", "seen_on": ["wss://x"]});
+        let b_served = json!({"id": "bb", "pubkey": "p", "created_at": 1, "kind": 1, "tags": [], "content": "b", "sig": "s"});
+        // Order, key order and non-NIP-01 fields don't change the hash; content does
+        assert_eq!(event_set_hash([&a, &b]), event_set_hash([&b_served, &a]));
+        let tampered = json!({"id": "bb", "pubkey": "p", "created_at": 1, "kind": 1, "tags": [], "content": "c", "sig": "s"});
+        assert_ne!(event_set_hash([&a, &b]), event_set_hash([&a, &tampered]));
+
+        let template = manifest_template("00", 1, "archive", "ff", 2);
+        let unsigned = json!({"id": "00", "sig": "00", "pubkey": "00", "created_at": 1, "kind": MANIFEST_KIND, "tags": template["tags"], "content": ""});
+        assert!(check_manifest(&unsigned).unwrap_err().to_string().contains("id does not match"));
+    }
+}
@@ -0,0 +1,79 @@
+// Generated by bindings/idl/generate.py from bindings/idl/cassette_loader.wit. Do not edit.
+
+import Foundation
+import CCassetteLoader
+
+public struct CassetteError: Error {
+    public let message: String
+}
+
+/// How duplicate events are filtered from responses
+public enum DedupPolicy: UInt32 {
+    /// Drop events already returned since the last REQ or CLOSE
+    case perSubscription = 0
+    /// Return every event the cassette produces
+    case none = 1
+}
+
+private func lastError() -> CassetteError {
+    CassetteError(message: cassette_last_error().map { String(cString: $0) } ?? "unknown error")
+}
+
+private func takeString(_ s: UnsafeMutablePointer<CChar>?) -> String {
+    defer { cassette_string_free(s) }
+    return s.map { String(cString: $0) } ?? ""
+}
+
+public final class Cassette {
+    private let handle: OpaquePointer
+
+    private init(handle: OpaquePointer) {
+        self.handle = handle
+    }
+
+    deinit {
+        cassette_free(handle)
+    }
+
+    /// Load a cassette from a .cassette/.wasm file
+    public static func load(path: String, debug: Bool) throws -> Cassette {
+        var out: OpaquePointer? = nil
+        guard cassette_load(path, debug, &out) == 0 else { throw lastError() }
+        return Cassette(handle: out!)
+    }
+
+    /// Send any NIP-01 message; REQ responses are collected until EOSE
+    public func send(message: String) throws -> [String] {
+        var out: UnsafeMutablePointer<CChar>? = nil
+        guard cassette_send(handle, message, &out) == 0 else { throw lastError() }
+        let json = takeString(out)
+        return try JSONDecoder().decode([String].self, from: Data(json.utf8))
+    }
+
+    /// All events matching a filter (JSON object), as event JSON
+    public func events(filter: String) throws -> [String] {
+        var out: UnsafeMutablePointer<CChar>? = nil
+        guard cassette_events(handle, filter, &out) == 0 else { throw lastError() }
+        let json = takeString(out)
+        return try JSONDecoder().decode([String].self, from: Data(json.utf8))
+    }
+
+    /// Number of events matching a filter (JSON object)
+    public func count(filter: String) throws -> UInt64 {
+        var out: UInt64 = 0
+        guard cassette_count(handle, filter, &out) == 0 else { throw lastError() }
+        return out
+    }
+
+    /// NIP-11 relay information document
+    public func info() throws -> String {
+        var out: UnsafeMutablePointer<CChar>? = nil
+        guard cassette_info(handle, &out) == 0 else { throw lastError() }
+        return takeString(out)
+    }
+
+    /// Set how duplicate events are filtered from responses
+    public func setDedup(policy: DedupPolicy) {
+        cassette_set_dedup(handle, CassetteDedupPolicy(rawValue: policy.rawValue))
+    }
+}
@@ -0,0 +1,65 @@
+//! Wasm guest exposing the cassette-tools handlers to the test harness
+//!
+//! Everything cassette-tools exports (`set_info`, `enable_auth`, `handle_auth`,
+//! `handle_count`, the allocation helpers) is linked in unchanged. `scrub` and
+//! `info` route messages through the library handlers over a fixed event set,
+//! applying the default `FilterLimits`, the same way a generated cassette does.
+
+use cassette_tools::nips::{nip11, nip45, nip50};
+use cassette_tools::{ptr_to_string, string_to_ptr, EventBasedHandler, FilterLimits, RelayHandler};
+use serde_json::{json, Value};
+
+const EVENTS: &str = include_str!("../../fixtures/events.json");
+
+#[no_mangle]
+pub extern "C" fn scrub(ptr: *const u8, len: usize) -> *mut u8 {
+    string_to_ptr(route(&ptr_to_string(ptr, len)))
+}
+
+#[no_mangle]
+pub extern "C" fn info() -> *mut u8 {
+    #[allow(static_mut_refs)]
+    let stored = unsafe { nip11::RELAY_INFO_JSON.clone() };
+    let mut info: serde_json::Map<String, Value> = stored
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    info.insert("supported_nips".to_string(), json!(cassette_tools::nips::build_supported_nips()));
+    string_to_ptr(Value::Object(info).to_string())
+}
+
+fn route(message: &str) -> String {
+    let arr = match serde_json::from_str::<Value>(message) {
+        Ok(Value::Array(arr)) if !arr.is_empty() => arr,
+        Ok(_) => return json!(["NOTICE", "Message must be a non-empty array"]).to_string(),
+        Err(e) => return json!(["NOTICE", format!("Invalid JSON: {}", e)]).to_string(),
+    };
+    let events: Vec<Value> = serde_json::from_str(EVENTS).unwrap_or_default();
+    let subscription_id = arr.get(1).and_then(|s| s.as_str()).unwrap_or("");
+    let filters = arr.get(2..).unwrap_or_default();
+
+    if matches!(arr[0].as_str(), Some("REQ") | Some("COUNT")) {
+        if let Some(closed) = FilterLimits::default().closed_message(subscription_id, filters) {
+            return closed;
+        }
+    }
+
+    match arr[0].as_str() {
+        Some("COUNT") => {
+            let count = nip45::count_events_with_filters(&events, filters);
+            json!(["COUNT", subscription_id, {"count": count}]).to_string()
+        }
+        Some("REQ") if filters.iter().any(|f| f.get("search").is_some()) => {
+            // handle_search returns one event per line; wrap them like EventBasedHandler does
+            let results: Vec<Value> = nip50::handle_search(&events, filters)
+                .lines()
+                .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+                .map(|event| json!(["EVENT", subscription_id, event]))
+                .collect();
+            json!({"events": results, "eose": ["EOSE", subscription_id]}).to_string()
+        }
+        _ => match EventBasedHandler::new(EVENTS).handle_message(message) {
+            Ok(response) => response,
+            Err(e) => json!(["NOTICE", e.to_string()]).to_string(),
+        },
+    }
+}
@@ -0,0 +1,274 @@
+//! Wasm guest test harness for cassette-tools
+//!
+//! Native `cargo test` in cassette-tools exercises the NIP handlers with a
+//! host allocator and 64-bit pointers. This harness compiles them into a real
+//! wasm32 guest (see `guest/`) and drives the exports through wasmtime, so
+//! pointer handling, MSGB framing and static state are tested the way a
+//! cassette actually runs.
+//!
+//! Cases live in `cases/*.json`. Each file is a list of cases; each case runs
+//! its steps in order against a fresh guest instance:
+//!
+//! ```json
+//! {
+//!   "name": "COUNT by kind",
+//!   "steps": [
+//!     { "call": "scrub", "input": ["COUNT", "c1", {"kinds": [1]}], "expect": ["COUNT", "c1", {"count": 3}] }
+//!   ]
+//! }
+//! ```
+//!
+//! `input` is serialized to JSON unless it is a string, which is passed as-is;
+//! steps without `input` call the export with no arguments. `expect` matches
+//! the decoded response (see [`matches`]); `status` instead compares the raw
+//! i32 return value for exports that do not return a string.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use wasmtime::{Engine, Instance, Memory, Module, Store, Val};
+
+const MSGB_SIGNATURE: &[u8; 4] = b"MSGB";
+
+/// Build the guest crate for wasm32 once per test process and return the module path
+pub fn guest_wasm() -> Result<PathBuf> {
+    static WASM: OnceLock<std::result::Result<PathBuf, String>> = OnceLock::new();
+    WASM.get_or_init(|| build_guest().map_err(|e| format!("{:#}", e)))
+        .clone()
+        .map_err(|e| anyhow!(e))
+}
+
+fn build_guest() -> Result<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = root.join("target").join("guest");
+    let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .args(["build", "--quiet", "--release", "--target", "wasm32-unknown-unknown", "--manifest-path"])
+        .arg(root.join("guest").join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .context("Failed to run cargo for the wasm guest")?;
+    if !status.success() {
+        bail!("Guest build failed (is the wasm32-unknown-unknown target installed?)");
+    }
+    Ok(target_dir.join("wasm32-unknown-unknown/release/cassette_tools_test_guest.wasm"))
+}
+
+/// Result of calling a guest export
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// The export returned a null pointer
+    Null,
+    /// The export returned an MSGB string
+    Text(String),
+}
+
+/// The compiled guest module; compile once, instantiate per case
+pub struct GuestModule {
+    engine: Engine,
+    module: Module,
+}
+
+impl GuestModule {
+    pub fn load(path: &Path) -> Result<Self> {
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, path)
+            .with_context(|| format!("Failed to load guest module {}", path.display()))?;
+        Ok(Self { engine, module })
+    }
+
+    /// Create a fresh instance with its own memory and static state
+    pub fn instantiate(&self) -> Result<Guest> {
+        let mut store = Store::new(&self.engine, ());
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("Guest does not export memory"))?;
+        Ok(Guest { store, instance, memory })
+    }
+}
+
+/// A running instance of the guest module
+pub struct Guest {
+    store: Store<()>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Guest {
+    /// Call an export, passing `input` as a (ptr, len) pair, and return the raw i32 result
+    pub fn call_raw(&mut self, export: &str, input: Option<&str>) -> Result<i32> {
+        let func = self.instance.get_func(&mut self.store, export)
+            .ok_or_else(|| anyhow!("Guest does not export `{}`", export))?;
+
+        let mut args = Vec::new();
+        let mut allocation = None;
+        if let Some(input) = input {
+            let ptr = self.call_i32("alloc_buffer", &[Val::I32(input.len() as i32)])?;
+            if ptr == 0 && !input.is_empty() {
+                bail!("alloc_buffer returned null for {} bytes", input.len());
+            }
+            self.memory.write(&mut self.store, ptr as usize, input.as_bytes())?;
+            args = vec![Val::I32(ptr), Val::I32(input.len() as i32)];
+            allocation = Some((ptr, input.len() as i32));
+        }
+
+        let mut results = [Val::I32(0)];
+        func.call(&mut self.store, &args, &mut results)
+            .with_context(|| format!("Guest trapped in `{}`", export))?;
+
+        if let Some((ptr, len)) = allocation {
+            self.call_i32("dealloc_buffer", &[Val::I32(ptr), Val::I32(len)]).ok();
+        }
+        results[0].i32().ok_or_else(|| anyhow!("`{}` did not return an i32", export))
+    }
+
+    /// Call an export that returns an MSGB string pointer, freeing the string afterwards
+    pub fn call(&mut self, export: &str, input: Option<&str>) -> Result<Response> {
+        let ptr = self.call_raw(export, input)?;
+        if ptr == 0 {
+            return Ok(Response::Null);
+        }
+
+        let mut header = [0u8; 8];
+        self.memory.read(&self.store, ptr as usize, &mut header)
+            .context("Response pointer is out of bounds")?;
+        if &header[..4] != MSGB_SIGNATURE {
+            bail!("Response from `{}` is missing the MSGB signature", export);
+        }
+        let len = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+        let mut bytes = vec![0u8; len];
+        self.memory.read(&self.store, ptr as usize + 8, &mut bytes)
+            .context("Response length runs past guest memory")?;
+
+        if self.instance.get_func(&mut self.store, "dealloc_string").is_some() {
+            self.call_i32("dealloc_string", &[Val::I32(ptr), Val::I32(len as i32 + 8)]).ok();
+        }
+        Ok(Response::Text(String::from_utf8(bytes).context("Response is not valid UTF-8")?))
+    }
+
+    fn call_i32(&mut self, export: &str, args: &[Val]) -> Result<i32> {
+        let func = self.instance.get_func(&mut self.store, export)
+            .ok_or_else(|| anyhow!("Guest does not export `{}`", export))?;
+        let mut results = vec![Val::I32(0); func.ty(&self.store).results().len()];
+        func.call(&mut self.store, args, &mut results)?;
+        Ok(results.first().and_then(|v| v.i32()).unwrap_or(0))
+    }
+}
+
+/// A table-driven case: steps run in order against one guest instance
+#[derive(Debug, Deserialize)]
+pub struct Case {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Step {
+    pub call: String,
+    #[serde(default)]
+    pub input: Option<Value>,
+    /// Expected decoded response; `null` expects a null pointer
+    #[serde(default)]
+    pub expect: Option<Value>,
+    /// Expected raw i32 return value, for exports that return a status code
+    #[serde(default)]
+    pub status: Option<i32>,
+}
+
+/// Load the cases from a JSON file under `cases/`
+pub fn load_cases(file: &str) -> Result<Vec<Case>> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("cases").join(file);
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Run one case against a fresh guest, describing the first failing step
+pub fn run_case(module: &GuestModule, case: &Case) -> Result<()> {
+    let mut guest = module.instantiate()?;
+    for (i, step) in case.steps.iter().enumerate() {
+        let input = step.input.as_ref().map(|v| match v {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        });
+
+        if let Some(status) = step.status {
+            let actual = guest.call_raw(&step.call, input.as_deref())?;
+            if actual != status {
+                bail!("step {} ({}): expected status {}, got {}", i + 1, step.call, status, actual);
+            }
+            continue;
+        }
+
+        let actual = match guest.call(&step.call, input.as_deref())? {
+            Response::Null => Value::Null,
+            Response::Text(text) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+        };
+        if let Some(expect) = &step.expect {
+            if !matches(expect, &actual) {
+                bail!("step {} ({}): expected {}, got {}", i + 1, step.call, expect, actual);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run every case in a file, reporting all failures together
+pub fn run_cases(file: &str) -> Result<usize> {
+    let module = GuestModule::load(&guest_wasm()?)?;
+    let cases = load_cases(file)?;
+    let failures: Vec<String> = cases.iter()
+        .filter_map(|case| run_case(&module, case).err().map(|e| format!("{}: {:#}", case.name, e)))
+        .collect();
+    if !failures.is_empty() {
+        bail!("{} of {} cases in {} failed:\n  {}", failures.len(), cases.len(), file, failures.join("\n  "));
+    }
+    Ok(cases.len())
+}
+
+/// Match a response against an expected pattern
+///
+/// - `"*"` matches any value
+/// - objects match when every expected key matches; extra keys are ignored
+/// - `{"$len": n}` matches an array of length n
+/// - `{"$contains": "text"}` matches a string containing `text`
+/// - arrays match element-wise and must have the same length
+/// - anything else must be equal
+pub fn matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::String(s), _) if s == "*" => true,
+        (Value::Object(pattern), _) if pattern.contains_key("$len") => {
+            actual.as_array().map(|a| a.len() as u64) == pattern["$len"].as_u64()
+        }
+        (Value::Object(pattern), Value::String(text)) if pattern.contains_key("$contains") => {
+            pattern["$contains"].as_str().is_some_and(|needle| text.contains(needle))
+        }
+        (Value::Object(pattern), Value::Object(object)) => pattern.iter()
+            .all(|(key, value)| object.get(key).is_some_and(|actual| matches(value, actual))),
+        (Value::Array(pattern), Value::Array(items)) => {
+            pattern.len() == items.len() && pattern.iter().zip(items).all(|(p, a)| matches(p, a))
+        }
+        _ => expected == actual,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches() {
+        let response = json!({"events": [["EVENT", "s", {"id": "a", "kind": 1}]], "eose": ["EOSE", "s"]});
+        assert!(matches(&json!({"events": {"$len": 1}}), &response));
+        assert!(matches(&json!({"events": [["EVENT", "*", {"kind": 1}]]}), &response));
+        assert!(!matches(&json!({"events": [["EVENT", "s", {"kind": 7}]]}), &response));
+        assert!(!matches(&json!({"events": []}), &response));
+        assert!(matches(&json!(["NOTICE", {"$contains": "bad"}]), &json!(["NOTICE", "a bad filter"])));
+        assert!(matches(&Value::Null, &Value::Null));
+    }
+}
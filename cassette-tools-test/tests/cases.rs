@@ -0,0 +1,35 @@
+//! Runs the table-driven cases in `cases/` against the wasm32 guest
+
+use cassette_tools_test::run_cases;
+
+fn run(file: &str) {
+    match run_cases(file) {
+        Ok(count) => println!("{}: {} cases passed", file, count),
+        Err(e) => panic!("{:#}", e),
+    }
+}
+
+#[test]
+fn req_cases() {
+    run("req.json");
+}
+
+#[test]
+fn count_cases() {
+    run("count.json");
+}
+
+#[test]
+fn auth_cases() {
+    run("auth.json");
+}
+
+#[test]
+fn info_cases() {
+    run("info.json");
+}
+
+#[test]
+fn limits_cases() {
+    run("limits.json");
+}
@@ -0,0 +1,92 @@
+//! Bounded chunks of a response
+//!
+//! Answering a REQ with tens of thousands of results in one string needs all
+//! of it in guest and host memory at once. The `result_begin(ptr, len,
+//! chunk_bytes)` export queues a message's whole answer as a
+//! [`ChunkedResponse`] instead, and each `result_next_chunk()` call
+//! serializes only the next chunk: newline-separated messages totalling at
+//! most `chunk_bytes` bytes, except that a single larger message still gets a
+//! chunk of its own. It answers null once the response is drained, and
+//! `result_end()` drops whatever a host stops reading.
+
+use serde::Serialize;
+use serde_json::json;
+
+/// Chunk size when the host asks for 0 bytes
+pub const DEFAULT_CHUNK_BYTES: usize = 256 * 1024;
+
+/// A response handed out a chunk at a time
+pub struct ChunkedResponse {
+    messages: Box<dyn Iterator<Item = String>>,
+    // The message that didn't fit in the previous chunk
+    held: Option<String>,
+    chunk_bytes: usize,
+}
+
+impl ChunkedResponse {
+    /// Chunks of `messages` of at most `chunk_bytes` bytes (0 for the default)
+    pub fn new<I>(messages: I, chunk_bytes: usize) -> Self
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'static,
+    {
+        Self {
+            messages: Box::new(messages.into_iter()),
+            held: None,
+            chunk_bytes: if chunk_bytes == 0 { DEFAULT_CHUNK_BYTES } else { chunk_bytes },
+        }
+    }
+
+    /// A REQ's answer: an EVENT message for each result, then EOSE. Each EVENT is
+    /// serialized only when its chunk is.
+    pub fn req<E: Serialize + 'static>(subscription_id: &str, events: Vec<E>, chunk_bytes: usize) -> Self {
+        let subscription_id = subscription_id.to_string();
+        let eose = json!(["EOSE", subscription_id]).to_string();
+        let messages = events.into_iter()
+            .map(move |event| json!(["EVENT", subscription_id, event]).to_string())
+            .chain(std::iter::once(eose));
+        Self::new(messages, chunk_bytes)
+    }
+}
+
+impl Iterator for ChunkedResponse {
+    type Item = String;
+
+    /// The next chunk, `None` once every message was handed out
+    fn next(&mut self) -> Option<String> {
+        let mut chunk = self.held.take().or_else(|| self.messages.next())?;
+        for message in self.messages.by_ref() {
+            if chunk.len() + 1 + message.len() > self.chunk_bytes {
+                self.held = Some(message);
+                break;
+            }
+            chunk.push('\n');
+            chunk.push_str(&message);
+        }
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_chunks() {
+        let events: Vec<Value> = (0..3).map(|i| json!({"id": i})).collect();
+        let event = r#"["EVENT","s",{"id":0}]"#.len();
+
+        // Two events fit in a chunk, the third shares the next one with EOSE
+        let chunks: Vec<String> = ChunkedResponse::req("s", events.clone(), 2 * event + 1).collect();
+        assert_eq!(chunks, vec![
+            "[\"EVENT\",\"s\",{\"id\":0}]\n[\"EVENT\",\"s\",{\"id\":1}]".to_string(),
+            "[\"EVENT\",\"s\",{\"id\":2}]\n[\"EOSE\",\"s\"]".to_string(),
+        ]);
+
+        // Messages larger than a chunk still come out whole, one per chunk
+        assert_eq!(ChunkedResponse::req("s", events.clone(), 1).count(), 4);
+        assert_eq!(ChunkedResponse::req("s", events, 0).count(), 1, "0 asks for the default chunk size");
+        assert_eq!(ChunkedResponse::new(Vec::new(), 0).next(), None);
+    }
+}
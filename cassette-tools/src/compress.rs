@@ -0,0 +1,54 @@
+//! zstd-compressed event payloads
+//!
+//! Cassettes recorded with `--compress` embed their events as one zstd frame
+//! instead of plain JSON, which shrinks archives of long-form content several
+//! times over. The whole frame is decompressed on the first query, not per
+//! query, and parsed as it streams out of the decoder, so the decompressed
+//! JSON is never held in memory next to the parsed events. Decoding uses `ruzstd`, which is pure
+//! Rust and builds for `wasm32-unknown-unknown`.
+
+use crate::error::CassetteError;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+/// Parse JSON straight out of a zstd frame
+pub fn read_json<T: DeserializeOwned>(compressed: &[u8]) -> Result<T, CassetteError> {
+    let mut source = compressed;
+    let decoder = ruzstd::StreamingDecoder::new(&mut source)
+        .map_err(|e| CassetteError::Internal(format!("Invalid zstd frame: {}", e)))?;
+    serde_json::from_reader(decoder)
+        .map_err(|e| CassetteError::Internal(format!("Failed to load compressed events: {}", e)))
+}
+
+/// The `compression` entry cassettes report in their info
+pub fn stats(compressed_bytes: usize, uncompressed_bytes: usize) -> Value {
+    let ratio = if compressed_bytes == 0 { 0.0 } else { uncompressed_bytes as f64 / compressed_bytes as f64 };
+    json!({
+        "algorithm": "zstd",
+        "compressed_bytes": compressed_bytes,
+        "uncompressed_bytes": uncompressed_bytes,
+        "ratio": (ratio * 100.0).round() / 100.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NostrEvent;
+
+    #[test]
+    fn test_read_json() {
+        let events = json!([{
+            "id": "a".repeat(64), "pubkey": "b".repeat(64), "created_at": 1, "kind": 30023,
+            "tags": [["d", "post"]], "content": "long form ".repeat(500), "sig": "c".repeat(128),
+        }]).to_string();
+        let compressed = zstd::encode_all(events.as_bytes(), 3).unwrap();
+        assert!(compressed.len() * 10 < events.len());
+
+        let parsed: Vec<NostrEvent> = read_json(&compressed).unwrap();
+        assert_eq!(parsed[0].kind, 30023);
+        assert!(read_json::<Vec<NostrEvent>>(events.as_bytes()).is_err(), "plain JSON is not a zstd frame");
+
+        assert_eq!(stats(100, 450)["ratio"], 4.5);
+    }
+}
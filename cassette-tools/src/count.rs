@@ -0,0 +1,79 @@
+//! COUNT results (NIP-45)
+//!
+//! A cassette answers COUNT with `{"count": n, "approximate": false}`.
+//! Counts taken by checking events are exact; `approximate` is true only when
+//! the number comes from sizes recorded at build time, which can't see events
+//! hidden at query time. A filter with the `"by_kind": true` extension also
+//! asks how many of the matching events have each kind, answered as
+//! `"by_kind": {"<kind>": n}`, which adds up to `count`.
+
+use crate::Filter;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// The filter extension asking for a per-kind breakdown
+pub const BY_KIND: &str = "by_kind";
+
+/// The body of a COUNT response
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Count {
+    pub count: u64,
+    pub approximate: bool,
+    /// Matching events per kind, when a filter asked for it
+    pub by_kind: Option<BTreeMap<i64, u64>>,
+}
+
+impl Count {
+    /// An exact count of nothing yet, broken down by kind when any of the filters asks for it
+    pub fn for_filters(filters: &[Filter]) -> Self {
+        Self {
+            by_kind: filters.iter().any(wants_by_kind).then(BTreeMap::new),
+            ..Self::default()
+        }
+    }
+
+    /// Count one matching event
+    pub fn add(&mut self, kind: i64) {
+        self.count += 1;
+        if let Some(by_kind) = &mut self.by_kind {
+            *by_kind.entry(kind).or_default() += 1;
+        }
+    }
+
+    /// `{"count": n, "approximate": bool}`, plus `by_kind` when asked for
+    pub fn to_json(&self) -> Value {
+        let mut body = json!({"count": self.count, "approximate": self.approximate});
+        if let Some(by_kind) = &self.by_kind {
+            let by_kind: Map<String, Value> = by_kind.iter()
+                .map(|(kind, count)| (kind.to_string(), json!(count)))
+                .collect();
+            body["by_kind"] = Value::Object(by_kind);
+        }
+        body
+    }
+}
+
+/// Whether a filter sets the `by_kind` extension
+pub fn wants_by_kind(filter: &Filter) -> bool {
+    filter.extensions.get(BY_KIND).and_then(Value::as_bool).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let plain = Filter::from_json(&json!({"kinds": [1]})).unwrap();
+        let mut count = Count::for_filters(std::slice::from_ref(&plain));
+        count.add(1);
+        assert_eq!(count.to_json(), json!({"count": 1, "approximate": false}));
+
+        let by_kind = Filter::from_json(&json!({"kinds": [7], "by_kind": true})).unwrap();
+        let mut count = Count::for_filters(&[plain, by_kind]);
+        for kind in [1, 7, 1] {
+            count.add(kind);
+        }
+        assert_eq!(count.to_json(), json!({"count": 3, "approximate": false, "by_kind": {"1": 2, "7": 1}}));
+    }
+}
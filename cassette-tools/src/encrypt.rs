@@ -0,0 +1,164 @@
+//! Encrypted event payloads
+//!
+//! Cassettes recorded with `--encrypt <npub>` embed their events sealed to one
+//! recipient, so a private archive can be passed around without its contents
+//! being readable. Sealing follows NIP-44 v2: an ephemeral key and the
+//! recipient's pubkey give a conversation key (ECDH, then HKDF-extract with
+//! the `nip44-v2` salt), a random 32-byte nonce expands it into ChaCha20 and
+//! HMAC-SHA256 keys, and the MAC covers the nonce and ciphertext. The blob is
+//! laid out as
+//!
+//! ```text
+//! version (1) | ephemeral x-only pubkey (32) | nonce (32) | ciphertext | mac (32)
+//! ```
+//!
+//! Unlike NIP-44 messages, payloads are not padded and have no size limit:
+//! archives are far larger than chat messages and their size is no secret.
+//! Everything here is pure Rust and builds for `wasm32-unknown-unknown`.
+
+use crate::error::CassetteError;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{PublicKey, SecretKey};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+/// Version byte of sealed payloads (NIP-44 v2 keys and cipher)
+pub const VERSION: u8 = 2;
+
+/// Name the scheme is reported under in a cassette's info
+pub const SCHEME: &str = "nip44-v2";
+
+const HEADER_BYTES: usize = 1 + 32 + 32;
+const MAC_BYTES: usize = 32;
+
+fn secret_key(secret: &[u8; 32]) -> Result<SecretKey, CassetteError> {
+    SecretKey::from_slice(secret).map_err(|_| CassetteError::Internal("Invalid secret key".to_string()))
+}
+
+/// x-only (BIP-340) pubkey of a secret key
+pub fn x_only_pubkey(secret: &[u8; 32]) -> Result<[u8; 32], CassetteError> {
+    let point = secret_key(secret)?.public_key().to_encoded_point(true);
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&point.as_bytes()[1..33]);
+    Ok(x)
+}
+
+/// NIP-44 conversation key between a secret key and an x-only pubkey
+fn conversation_key(secret: &[u8; 32], x_only: &[u8; 32]) -> Result<[u8; 32], CassetteError> {
+    let mut compressed = [0u8; 33];
+    compressed[0] = 0x02;
+    compressed[1..].copy_from_slice(x_only);
+    let public = PublicKey::from_sec1_bytes(&compressed)
+        .map_err(|_| CassetteError::Internal("Invalid recipient pubkey".to_string()))?;
+    let shared = k256::ecdh::diffie_hellman(secret_key(secret)?.to_nonzero_scalar(), public.as_affine());
+    let (key, _) = Hkdf::<Sha256>::extract(Some(SCHEME.as_bytes()), shared.raw_secret_bytes());
+    Ok(key.into())
+}
+
+/// ChaCha20 key, ChaCha20 nonce and HMAC key of one payload
+fn message_keys(conversation_key: &[u8; 32], nonce: &[u8; 32]) -> ([u8; 32], [u8; 12], [u8; 32]) {
+    let mut keys = [0u8; 76];
+    Hkdf::<Sha256>::from_prk(conversation_key)
+        .expect("a 32-byte key is a valid PRK")
+        .expand(nonce, &mut keys)
+        .expect("76 bytes is a valid HKDF-SHA256 length");
+    let (mut cipher_key, mut cipher_nonce, mut mac_key) = ([0u8; 32], [0u8; 12], [0u8; 32]);
+    cipher_key.copy_from_slice(&keys[0..32]);
+    cipher_nonce.copy_from_slice(&keys[32..44]);
+    mac_key.copy_from_slice(&keys[44..76]);
+    (cipher_key, cipher_nonce, mac_key)
+}
+
+fn mac(key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(nonce);
+    mac.update(ciphertext);
+    mac
+}
+
+/// Seal `plaintext` to `recipient` (x-only pubkey) with a fresh ephemeral secret key and nonce
+pub fn seal(plaintext: &[u8], recipient: &[u8; 32], ephemeral_secret: &[u8; 32], nonce: &[u8; 32]) -> Result<Vec<u8>, CassetteError> {
+    let (cipher_key, cipher_nonce, mac_key) = message_keys(&conversation_key(ephemeral_secret, recipient)?, nonce);
+    let mut ciphertext = plaintext.to_vec();
+    ChaCha20::new(&cipher_key.into(), &cipher_nonce.into()).apply_keystream(&mut ciphertext);
+
+    let tag = mac(&mac_key, nonce, &ciphertext).finalize().into_bytes();
+
+    let mut sealed = Vec::with_capacity(HEADER_BYTES + ciphertext.len() + MAC_BYTES);
+    sealed.push(VERSION);
+    sealed.extend_from_slice(&x_only_pubkey(ephemeral_secret)?);
+    sealed.extend_from_slice(nonce);
+    sealed.extend_from_slice(&ciphertext);
+    sealed.extend_from_slice(&tag);
+    Ok(sealed)
+}
+
+/// Open a sealed payload with the recipient's secret key
+pub fn open(sealed: &[u8], secret: &[u8; 32]) -> Result<Vec<u8>, CassetteError> {
+    if sealed.len() < HEADER_BYTES + MAC_BYTES {
+        return Err(CassetteError::Internal("Encrypted payload is truncated".to_string()));
+    }
+    if sealed[0] != VERSION {
+        return Err(CassetteError::Internal(format!("Unknown encryption version {}", sealed[0])));
+    }
+    let mut ephemeral = [0u8; 32];
+    ephemeral.copy_from_slice(&sealed[1..33]);
+    let mut nonce = [0u8; 32];
+    nonce.copy_from_slice(&sealed[33..HEADER_BYTES]);
+    let (ciphertext, tag) = sealed[HEADER_BYTES..].split_at(sealed.len() - HEADER_BYTES - MAC_BYTES);
+
+    let (cipher_key, cipher_nonce, mac_key) = message_keys(&conversation_key(secret, &ephemeral)?, &nonce);
+    mac(&mac_key, &nonce, ciphertext).verify_slice(tag)
+        .map_err(|_| CassetteError::Internal("Wrong key or tampered payload".to_string()))?;
+    let mut plaintext = ciphertext.to_vec();
+    ChaCha20::new(&cipher_key.into(), &cipher_nonce.into()).apply_keystream(&mut plaintext);
+    Ok(plaintext)
+}
+
+/// Open a sealed payload with a secret key given as 64 hex characters
+pub fn unlock(sealed: &[u8], hex_key: &str) -> Result<Vec<u8>, CassetteError> {
+    let secret: [u8; 32] = hex::decode(hex_key.trim()).ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| CassetteError::Internal("Unlock key must be 64 hex characters".to_string()))?;
+    open(sealed, &secret)
+}
+
+/// The `encryption` entry cassettes report in their info
+pub fn info(recipient: &str) -> Value {
+    json!({
+        "scheme": SCHEME,
+        "recipient": recipient,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let recipient_secret = [7u8; 32];
+        let recipient = x_only_pubkey(&recipient_secret).unwrap();
+        let events = br#"[{"id":"aa","kind":1,"content":"private"}]"#;
+
+        let sealed = seal(events, &recipient, &[9u8; 32], &[1u8; 32]).unwrap();
+        assert_eq!(sealed.len(), HEADER_BYTES + events.len() + MAC_BYTES);
+        assert!(!sealed.windows(7).any(|w| w == b"private"), "no plaintext in the payload");
+        assert_eq!(open(&sealed, &recipient_secret).unwrap(), events);
+        assert_eq!(unlock(&sealed, &hex::encode(recipient_secret)).unwrap(), events);
+
+        // The ephemeral key can't read what it sealed for someone else, nor can a stranger
+        assert!(open(&sealed, &[9u8; 32]).is_err());
+        assert!(open(&sealed, &[8u8; 32]).is_err());
+        assert!(unlock(&sealed, "not hex").is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[HEADER_BYTES] ^= 1;
+        assert!(open(&tampered, &recipient_secret).is_err());
+        assert!(open(&sealed[..40], &recipient_secret).is_err());
+    }
+}
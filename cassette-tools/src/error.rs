@@ -0,0 +1,137 @@
+//! Errors raised while answering relay messages
+//!
+//! Every fallible function in this crate returns a [`CassetteError`], whose
+//! variant says what went wrong: a message that isn't NIP-01, a filter or
+//! event the cassette won't take, or a failure inside the cassette itself.
+//! The variant also decides how the client hears about it. Errors tied to a
+//! subscription end it with a `CLOSED` message, errors tied to an EVENT are
+//! an `OK` message refusing it, and anything else is a `NOTICE`. The reason in
+//! `CLOSED` and `OK` messages carries the matching NIP-01 prefix
+//! (`invalid:`, `duplicate:`, `restricted:`, `blocked:`, `auth-required:` or
+//! `error:`).
+
+use crate::messages;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CassetteError {
+    /// The message isn't JSON
+    #[error("Failed to parse JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// JSON that isn't a NIP-01 client message
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+    /// A command the cassette doesn't handle
+    #[error("Unsupported command: {0}")]
+    UnsupportedCommand(String),
+    /// A REQ or COUNT filter the cassette can't run
+    #[error("{0}")]
+    InvalidFilter(String),
+    /// An EVENT that isn't a well-formed, correctly hashed event
+    #[error("{0}")]
+    InvalidEvent(String),
+    /// An EVENT the cassette already has, or has a newer version of
+    #[error("{0}")]
+    Duplicate(String),
+    /// A request over one of the cassette's limits
+    #[error("{0}")]
+    Restricted(String),
+    /// A request the cassette refuses outright, such as a write to a read-only cassette
+    #[error("{0}")]
+    Blocked(String),
+    /// A request that needs the client to authenticate first (NIP-42)
+    #[error("{0}")]
+    AuthRequired(String),
+    /// A failure inside the cassette, such as embedded events that don't load
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl CassetteError {
+    /// Why a cassette that doesn't accept writes rejects an EVENT
+    pub fn read_only() -> Self {
+        Self::Blocked("this cassette is read-only".to_string())
+    }
+
+    /// The NIP-01 machine-readable prefix for this kind of error
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Json(_) | Self::InvalidMessage(_) | Self::InvalidFilter(_) | Self::InvalidEvent(_) => "invalid",
+            Self::UnsupportedCommand(_) => "unsupported",
+            Self::Duplicate(_) => "duplicate",
+            Self::Restricted(_) => "restricted",
+            Self::Blocked(_) => "blocked",
+            Self::AuthRequired(_) => "auth-required",
+            Self::Internal(_) => "error",
+        }
+    }
+
+    /// The reason for a `CLOSED` or `OK` message, e.g. `invalid: 'since' filter must be an integer timestamp`
+    pub fn reason(&self) -> String {
+        format!("{}: {}", self.prefix(), self)
+    }
+
+    /// `["NOTICE", <message>]`
+    pub fn notice(&self) -> Value {
+        json!(["NOTICE", self.to_string()])
+    }
+
+    /// The handler response telling the client about this error, given the
+    /// message that caused it: `{"closed": ...}` for a REQ, COUNT or CLOSE,
+    /// `{"ok": ...}` for an EVENT, and `{"notice": ...}` when the message is
+    /// malformed or names no subscription or event
+    pub fn response_to(&self, message: &str) -> String {
+        let parsed: Option<Value> = serde_json::from_str(message).ok();
+        let command = parsed.as_ref().and_then(|m| m.get(0)).and_then(|c| c.as_str());
+        let malformed = matches!(self, Self::Json(_) | Self::InvalidMessage(_) | Self::UnsupportedCommand(_));
+
+        let response = match (command, parsed.as_ref()) {
+            (Some("REQ" | "COUNT" | "CLOSE"), Some(message)) if !malformed => {
+                match message.get(1).and_then(|id| id.as_str()) {
+                    Some(subscription_id) => json!({ "closed": messages::closed(subscription_id, &self.reason()) }),
+                    None => json!({ "notice": self.notice() }),
+                }
+            }
+            (Some("EVENT"), Some(message)) if !malformed => {
+                match message.get(1).and_then(|event| event.get("id")).and_then(|id| id.as_str()) {
+                    Some(event_id) => json!({ "ok": messages::ok(event_id, false, &self.reason()) }),
+                    None => json!({ "notice": self.notice() }),
+                }
+            }
+            _ => json!({ "notice": self.notice() }),
+        };
+        response.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cassette_error_responses() {
+        let bad_filter = CassetteError::InvalidFilter("'kinds' filter must be an array of integers".to_string());
+        assert_eq!(bad_filter.reason(), "invalid: 'kinds' filter must be an array of integers");
+        assert_eq!(
+            bad_filter.response_to(r#"["REQ","sub",{"kinds":["1"]}]"#),
+            r#"{"closed":["CLOSED","sub","invalid: 'kinds' filter must be an array of integers"]}"#
+        );
+        assert_eq!(
+            CassetteError::Duplicate("already have this event".to_string()).response_to(r#"["EVENT",{"id":"e1"}]"#),
+            r#"{"ok":["OK","e1",false,"duplicate: already have this event"]}"#
+        );
+
+        assert_eq!(CassetteError::read_only().reason(), messages::READ_ONLY);
+
+        // Without a subscription or event to answer, the client gets a NOTICE
+        assert_eq!(
+            CassetteError::UnsupportedCommand("AUTH".to_string()).response_to(r#"["AUTH","challenge"]"#),
+            r#"{"notice":["NOTICE","Unsupported command: AUTH"]}"#
+        );
+        let json = CassetteError::from(serde_json::from_str::<Value>("[").unwrap_err());
+        assert!(json.response_to("[").starts_with(r#"{"notice":["NOTICE","Failed to parse JSON:"#));
+        let internal = CassetteError::Internal("events failed to load".to_string());
+        assert_eq!(internal.response_to(r#"["REQ",7]"#), r#"{"notice":["NOTICE","events failed to load"]}"#);
+    }
+}
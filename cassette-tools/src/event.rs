@@ -0,0 +1,153 @@
+//! Typed Nostr events
+//!
+//! `NostrEvent` is the NIP-01 event with its seven fields, serialized exactly
+//! as it appears on the wire. It recomputes its id from the other fields and,
+//! with the `verify` feature, checks its schnorr signature; cassettes only
+//! compile that in when recorded with `--verify`, since the CLI already
+//! checked every event at record time.
+
+use crate::error::CassetteError;
+use crate::filter::Event;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NostrEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: i64,
+    pub kind: i64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl NostrEvent {
+    /// Parse an event from JSON, requiring every field with its NIP-01 type
+    pub fn from_value(value: &Value) -> Result<Self, CassetteError> {
+        Self::deserialize(value).map_err(|e| CassetteError::InvalidEvent(e.to_string()))
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    /// The NIP-01 id: sha256 of `[0, pubkey, created_at, kind, tags, content]`
+    pub fn compute_id(&self) -> String {
+        let serialized = json!([0, self.pubkey, self.created_at, self.kind, self.tags, self.content]).to_string();
+        hex::encode(Sha256::digest(serialized.as_bytes()))
+    }
+
+    /// Whether `id` hashes from the other fields
+    pub fn verify_id(&self) -> bool {
+        self.compute_id() == self.id
+    }
+
+    /// Check the schnorr signature over `id` against `pubkey`
+    #[cfg(feature = "verify")]
+    pub fn verify_signature(&self) -> Result<(), CassetteError> {
+        use k256::schnorr::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+        let invalid = |reason: &str| CassetteError::InvalidEvent(reason.to_string());
+        let pubkey = hex::decode(&self.pubkey).ok()
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| invalid("invalid pubkey"))?;
+        let signature = hex::decode(&self.sig).ok()
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or_else(|| invalid("invalid signature encoding"))?;
+        // BIP-340 signs the 32 id bytes as they are
+        let message = hex::decode(&self.id).ok()
+            .filter(|bytes| bytes.len() == 32)
+            .ok_or_else(|| invalid("invalid id encoding"))?;
+        pubkey.verify_prehash(&message, &signature)
+            .map_err(|_| invalid("invalid signature"))
+    }
+}
+
+impl Event for NostrEvent {
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+
+    fn pubkey(&self) -> Option<&str> {
+        Some(&self.pubkey)
+    }
+
+    fn kind(&self) -> Option<i64> {
+        Some(self.kind)
+    }
+
+    fn created_at(&self) -> Option<i64> {
+        Some(self.created_at)
+    }
+
+    fn content(&self) -> Option<&str> {
+        Some(&self.content)
+    }
+
+    fn tag_values(&self, name: &str) -> Vec<&str> {
+        self.tags.iter()
+            .filter(|tag| tag.first().is_some_and(|n| n == name))
+            .filter_map(|tag| tag.get(1).map(String::as_str))
+            .collect()
+    }
+}
+
+/// Give a test event its pubkey and id, and with the `verify` feature a signature,
+/// all from a fixed key
+#[cfg(all(test, feature = "writable"))]
+pub(crate) fn sign_for_test(event: &mut NostrEvent) {
+    #[cfg(feature = "verify")]
+    {
+        use k256::schnorr::{signature::hazmat::PrehashSigner, Signature, SigningKey};
+
+        let key = SigningKey::from_bytes(&[7u8; 32]).unwrap();
+        event.pubkey = hex::encode(key.verifying_key().to_bytes());
+        event.id = event.compute_id();
+        let signature: Signature = key.sign_prehash(&hex::decode(&event.id).unwrap()).unwrap();
+        event.sig = hex::encode(signature.to_bytes());
+    }
+    #[cfg(not(feature = "verify"))]
+    {
+        event.pubkey = "a".repeat(64);
+        event.id = event.compute_id();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nostr_event() {
+        let value = json!({
+            "kind": 7,
+            "id": "2a6b2a94d05974d2e390c95856df86f3742c0a1b65d6cca9f6705b41ae9ace47",
+            "pubkey": "8ac20c99a80a2c09e785533106b26a285ba4580d8f86030223cf4f2805414655",
+            "created_at": 1753605454,
+            "tags": [["e", "cdfb95a8409b30b156bf35d98e3bf5b5c3669e522b3b82450f8c94d43c5f7db9"], ["p", "3731e169d0b3816f4a4a9ac84c66aa423d4f4ea0f78b56a1df90609b85be0885"]],
+            "content": "+",
+            "sig": "dab8f13d9c6eb6dd238a9e847d2b57ae43d8f5787b50f34e7c1a99909b1375c4acef3ce668b5085ae1bd0dd37c4e55de78dc43818823b6a2b0a86237f79ee1b5"
+        });
+        let event = NostrEvent::from_value(&value).unwrap();
+        assert_eq!(event.to_value(), value);
+        assert!(event.verify_id());
+        assert_eq!(event.tag_values("p"), vec!["3731e169d0b3816f4a4a9ac84c66aa423d4f4ea0f78b56a1df90609b85be0885"]);
+
+        let forged = NostrEvent { content: "-".to_string(), ..event.clone() };
+        assert!(!forged.verify_id());
+
+        #[cfg(feature = "verify")]
+        {
+            assert!(event.verify_signature().is_ok());
+            let bad_sig = NostrEvent { sig: "00".repeat(64), ..event.clone() };
+            assert!(bad_sig.verify_signature().is_err());
+        }
+
+        let mut missing = value.clone();
+        missing.as_object_mut().unwrap().remove("sig");
+        assert!(NostrEvent::from_value(&missing).is_err());
+        assert!(NostrEvent::from_value(&json!({"kind": "1"})).is_err());
+    }
+}
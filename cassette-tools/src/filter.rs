@@ -0,0 +1,258 @@
+//! NIP-01 filters
+//!
+//! One parser and one matcher for everything that answers REQ and COUNT: the
+//! generated cassettes, `EventBasedHandler`, the NIP-45 and NIP-50 helpers and
+//! the CLI. Events are read through the small [`Event`] trait, so typed event
+//! structs and raw JSON values are matched the same way, and sorted the same
+//! way by [`newest_first`].
+
+use crate::error::CassetteError;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Read access to the event fields a filter looks at
+pub trait Event {
+    fn id(&self) -> Option<&str>;
+    fn pubkey(&self) -> Option<&str>;
+    fn kind(&self) -> Option<i64>;
+    fn created_at(&self) -> Option<i64>;
+    fn content(&self) -> Option<&str>;
+    /// First values of the tags named `name`
+    fn tag_values(&self, name: &str) -> Vec<&str>;
+}
+
+impl Event for Value {
+    fn id(&self) -> Option<&str> {
+        self.get("id")?.as_str()
+    }
+
+    fn pubkey(&self) -> Option<&str> {
+        self.get("pubkey")?.as_str()
+    }
+
+    fn kind(&self) -> Option<i64> {
+        self.get("kind")?.as_i64()
+    }
+
+    fn created_at(&self) -> Option<i64> {
+        self.get("created_at")?.as_i64()
+    }
+
+    fn content(&self) -> Option<&str> {
+        self.get("content")?.as_str()
+    }
+
+    fn tag_values(&self, name: &str) -> Vec<&str> {
+        self.get("tags").and_then(|t| t.as_array()).into_iter().flatten()
+            .filter_map(|tag| tag.as_array())
+            .filter(|tag| tag.first().and_then(|n| n.as_str()) == Some(name))
+            .filter_map(|tag| tag.get(1)?.as_str())
+            .collect()
+    }
+}
+
+/// NIP-01 result order: newest first, and the lowest id first among events created
+/// in the same second, so a `limit` always keeps the same events
+pub fn newest_first<E: Event + ?Sized>(a: &E, b: &E) -> Ordering {
+    b.created_at().cmp(&a.created_at()).then_with(|| a.id().cmp(&b.id()))
+}
+
+/// A parsed REQ/COUNT filter
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    /// Event ids or id prefixes
+    pub ids: Option<Vec<String>>,
+    /// Pubkeys or pubkey prefixes
+    pub authors: Option<Vec<String>>,
+    pub kinds: Option<Vec<i64>>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<usize>,
+    /// NIP-50 search query, matched when the `nip50` feature is enabled
+    pub search: Option<String>,
+    /// `#x` (any of the values) and `&x` (all of the values, NIP-119) tag filters, keyed with
+    /// their prefix; tag names may be any length and case (`#e`, `#A`, `#alt`) and match exactly
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// Fields this crate doesn't interpret, such as the CLI's `languages` extension
+    pub extensions: Map<String, Value>,
+}
+
+impl Filter {
+    /// Parse a filter object, rejecting fields of the wrong type
+    pub fn from_json(value: &Value) -> Result<Self, CassetteError> {
+        let invalid = |reason: &str| CassetteError::InvalidFilter(reason.to_string());
+        let object = value.as_object().ok_or_else(|| invalid("Filter must be an object"))?;
+        let mut filter = Filter::default();
+
+        for (key, value) in object {
+            match key.as_str() {
+                "ids" => filter.ids = Some(strings(key, value)?),
+                "authors" => filter.authors = Some(strings(key, value)?),
+                "kinds" => {
+                    let kinds = value.as_array()
+                        .and_then(|kinds| kinds.iter().map(|k| k.as_i64()).collect::<Option<Vec<_>>>())
+                        .ok_or_else(|| invalid("'kinds' filter must be an array of integers"))?;
+                    filter.kinds = Some(kinds);
+                }
+                "since" => filter.since = Some(value.as_i64().ok_or_else(|| invalid("'since' filter must be an integer timestamp"))?),
+                "until" => filter.until = Some(value.as_i64().ok_or_else(|| invalid("'until' filter must be an integer timestamp"))?),
+                "limit" => {
+                    let limit = value.as_u64().ok_or_else(|| invalid("'limit' filter must be a positive integer"))?;
+                    filter.limit = Some(limit as usize);
+                }
+                "search" => filter.search = Some(value.as_str().ok_or_else(|| invalid("'search' filter must be a string"))?.to_string()),
+                _ if key.len() > 1 && (key.starts_with('#') || key.starts_with('&')) => {
+                    filter.tags.insert(key.clone(), strings(key, value)?);
+                }
+                _ => {
+                    filter.extensions.insert(key.clone(), value.clone());
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// The `#x` tag filters as (tag name, values)
+    pub fn any_tags(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.tags.iter()
+            .filter_map(|(key, values)| Some((key.strip_prefix('#')?, values.as_slice())))
+    }
+
+    /// Check an event against every condition of the filter
+    pub fn matches<E: Event + ?Sized>(&self, event: &E) -> bool {
+        fn listed(list: &Option<Vec<String>>, value: Option<&str>) -> bool {
+            match list {
+                Some(list) => value.is_some_and(|value| list.iter().any(|v| value.starts_with(v.as_str()))),
+                None => true,
+            }
+        }
+
+        if !listed(&self.ids, event.id()) || !listed(&self.authors, event.pubkey()) {
+            return false;
+        }
+        if let Some(kinds) = &self.kinds {
+            if !event.kind().is_some_and(|kind| kinds.contains(&kind)) {
+                return false;
+            }
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Some(created_at) = event.created_at() else {
+                return false;
+            };
+            if self.since.is_some_and(|since| created_at < since) || self.until.is_some_and(|until| created_at > until) {
+                return false;
+            }
+        }
+
+        for (key, values) in &self.tags {
+            let present = event.tag_values(&key[1..]);
+            let matched = if key.starts_with('&') {
+                values.iter().all(|v| present.contains(&v.as_str()))
+            } else {
+                values.iter().any(|v| present.contains(&v.as_str()))
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        #[cfg(feature = "nip50")]
+        if let Some(search) = &self.search {
+            let query = crate::nips::nip50::parse_search_query(search);
+            if crate::nips::nip50::score_content(event.content().unwrap_or(""), &query) <= 0.0 {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Check whether any of the filters matches (NIP-01: filters are OR'd together)
+    pub fn matches_any<E: Event + ?Sized>(filters: &[Filter], event: &E) -> bool {
+        filters.iter().any(|filter| filter.matches(event))
+    }
+
+    /// The events a REQ with these filters returns, from events already in
+    /// [`newest_first`] order: each filter keeps its own `limit` newest matches,
+    /// and an event is returned once when any filter keeps it (NIP-01)
+    pub fn select<E: Event>(filters: &[Filter], events: impl IntoIterator<Item = E>) -> Vec<E> {
+        let mut kept = vec![0usize; filters.len()];
+        events.into_iter()
+            .filter(|event| {
+                let mut keep = false;
+                for (filter, count) in filters.iter().zip(kept.iter_mut()) {
+                    if filter.limit.is_some_and(|limit| *count >= limit) || !filter.matches(event) {
+                        continue;
+                    }
+                    *count += 1;
+                    keep = true;
+                }
+                keep
+            })
+            .collect()
+    }
+}
+
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, CassetteError> {
+    value.as_array()
+        .and_then(|values| values.iter().map(|v| v.as_str().map(str::to_string)).collect())
+        .ok_or_else(|| CassetteError::InvalidFilter(format!("'{}' filter must be an array of strings", key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter() {
+        let event = json!({
+            "id": "e1", "pubkey": "alice", "created_at": 100, "kind": 1, "content": "hello bitcoin",
+            "tags": [["t", "nostr"], ["t", "zaps"], ["p", "bob"]]
+        });
+
+        let filter = |value: Value| Filter::from_json(&value).unwrap();
+        assert!(filter(json!({})).matches(&event));
+        assert!(filter(json!({"ids": ["e1"], "authors": ["alice"], "kinds": [1, 7]})).matches(&event));
+        assert!(filter(json!({"ids": ["e"], "authors": ["ali"]})).matches(&event), "ids and authors match by prefix");
+        assert!(!filter(json!({"authors": ["lice"]})).matches(&event));
+        assert!(filter(json!({"since": 100, "until": 100})).matches(&event));
+        assert!(!filter(json!({"since": 101})).matches(&event));
+        assert!(filter(json!({"#t": ["zaps", "art"], "#p": ["bob"]})).matches(&event));
+        assert!(!filter(json!({"#e": ["root"]})).matches(&event));
+        assert!(filter(json!({"&t": ["nostr", "zaps"]})).matches(&event));
+        assert!(!filter(json!({"&t": ["nostr", "art"]})).matches(&event));
+
+        let labelled = json!({"tags": [["A", "30023:alice:post"], ["alt", "a reply"], ["client", "x"]]});
+        assert!(filter(json!({"#A": ["30023:alice:post"]})).matches(&labelled));
+        assert!(!filter(json!({"#a": ["30023:alice:post"]})).matches(&labelled), "tag names are case-sensitive");
+        assert!(filter(json!({"#alt": ["a reply"], "&client": ["x"]})).matches(&labelled));
+        assert!(!filter(json!({"#al": ["a reply"]})).matches(&labelled));
+        assert_eq!(filter(json!({"#alt": ["a reply"]})).any_tags().collect::<Vec<_>>(), vec![("alt", &["a reply".to_string()][..])]);
+
+        let mut events = [json!({"id": "b", "created_at": 1}), json!({"id": "c", "created_at": 2}), json!({"id": "a", "created_at": 1})];
+        events.sort_by(newest_first);
+        assert_eq!(events.iter().map(|e| e.id().unwrap()).collect::<Vec<_>>(), vec!["c", "a", "b"]);
+
+        let events: Vec<Value> = (0..6).rev().map(|t| json!({"id": format!("e{}", t), "created_at": t, "kind": t % 2})).collect();
+        let ids = |events: Vec<Value>| events.iter().map(|e| e.id().unwrap().to_string()).collect::<Vec<_>>();
+        let limited = [filter(json!({"kinds": [0], "limit": 2})), filter(json!({"kinds": [1], "limit": 1})), filter(json!({"ids": ["e4"]}))];
+        assert_eq!(ids(Filter::select(&limited, events.clone())), vec!["e5", "e4", "e2"]);
+        assert_eq!(ids(Filter::select(&[filter(json!({"limit": 0}))], events)), Vec::<String>::new());
+
+        let extended = filter(json!({"kinds": [1], "languages": ["en"], "limit": 5}));
+        assert_eq!(extended.limit, Some(5));
+        assert_eq!(extended.extensions["languages"], json!(["en"]));
+        assert_eq!(extended.any_tags().count(), 0);
+
+        assert!(Filter::from_json(&json!({"kinds": ["1"]})).is_err());
+        assert!(Filter::from_json(&json!({"#t": "nostr"})).is_err());
+        assert!(Filter::from_json(&json!([1])).is_err());
+
+        let filters = [filter(json!({"kinds": [7]})), filter(json!({"#p": ["bob"]}))];
+        assert!(Filter::matches_any(&filters, &event));
+        assert!(!Filter::matches_any(&filters[..1], &event));
+    }
+}
@@ -0,0 +1,210 @@
+//! Build-time event indexes
+//!
+//! Without an index a cassette checks every embedded event against every
+//! filter. The CLI builds an inverted index at record time (kind, author and
+//! single-letter tag value to the positions of the events carrying them) and
+//! embeds it next to the events; the query path narrows a filter down to the
+//! candidate positions and only runs the full filter match on those.
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use crate::Filter;
+
+/// Positions of the events with each kind, author and tag value
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EventIndex {
+    /// Number of events the positions refer to
+    pub events: usize,
+    #[serde(serialize_with = "ordered")]
+    pub kinds: HashMap<i64, Vec<u32>>,
+    #[serde(serialize_with = "ordered")]
+    pub authors: HashMap<String, Vec<u32>>,
+    /// Tag name → first tag value → positions, for single-letter tags
+    #[serde(serialize_with = "ordered_tags")]
+    pub tags: HashMap<String, HashMap<String, Vec<u32>>>,
+}
+
+// Keys are written in order so the same events always embed the same index
+fn ordered<K: Ord + Serialize, V: Serialize, S: Serializer>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn ordered_tags<S: Serializer>(tags: &HashMap<String, HashMap<String, Vec<u32>>>, serializer: S) -> Result<S::Ok, S::Error> {
+    tags.iter()
+        .map(|(name, values)| (name, values.iter().collect::<BTreeMap<_, _>>()))
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+impl EventIndex {
+    /// Index events in the order they are embedded
+    pub fn from_events(events: &[Value]) -> Self {
+        let mut index = Self { events: events.len(), ..Self::default() };
+
+        for (position, event) in events.iter().enumerate() {
+            let position = position as u32;
+            if let Some(kind) = event.get("kind").and_then(|k| k.as_i64()) {
+                index.kinds.entry(kind).or_default().push(position);
+            }
+            if let Some(pubkey) = event.get("pubkey").and_then(|p| p.as_str()) {
+                index.authors.entry(pubkey.to_string()).or_default().push(position);
+            }
+
+            let tags = event.get("tags").and_then(|t| t.as_array());
+            for tag in tags.into_iter().flatten().filter_map(|t| t.as_array()) {
+                let (Some(name), Some(value)) = (
+                    tag.first().and_then(|n| n.as_str()),
+                    tag.get(1).and_then(|v| v.as_str()),
+                ) else {
+                    continue;
+                };
+                if name.chars().count() != 1 {
+                    continue;
+                }
+                let positions = index.tags.entry(name.to_string()).or_default()
+                    .entry(value.to_string()).or_default();
+                // An event repeating a tag value is listed once
+                if positions.last() != Some(&position) {
+                    positions.push(position);
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Parse an index embedded by the CLI; `None` when there is none
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(json).ok().filter(|index| index.events > 0)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Sorted positions of the events that can match a filter with these constraints
+    ///
+    /// `tags` holds the `#x` filters as (tag name, values). Constraints the index
+    /// doesn't cover are left to the caller's full match, so the result may hold
+    /// events that don't match; `None` means nothing narrows the filter down.
+    pub fn candidates<'a>(
+        &self,
+        kinds: Option<&[i64]>,
+        authors: Option<&[String]>,
+        tags: impl IntoIterator<Item = (&'a str, &'a [String])>,
+    ) -> Option<Vec<u32>> {
+        let mut constraints: Vec<Vec<u32>> = Vec::new();
+
+        if let Some(kinds) = kinds {
+            constraints.push(union(kinds.iter().filter_map(|kind| self.kinds.get(kind))));
+        }
+        if let Some(authors) = authors {
+            // Authors may be given as prefixes, which are matched against every indexed pubkey
+            constraints.push(union(authors.iter().flat_map(|author| match self.authors.get(author) {
+                Some(positions) => vec![positions],
+                None => self.authors.iter()
+                    .filter(|(pubkey, _)| pubkey.starts_with(author.as_str()))
+                    .map(|(_, positions)| positions)
+                    .collect(),
+            })));
+        }
+        for (name, values) in tags {
+            if name.chars().count() != 1 {
+                continue;
+            }
+            let Some(by_value) = self.tags.get(name) else {
+                // Nothing carries this tag at all
+                return Some(Vec::new());
+            };
+            constraints.push(union(values.iter().filter_map(|value| by_value.get(value))));
+        }
+
+        // Intersect starting from the most selective constraint
+        constraints.sort_by_key(|positions| positions.len());
+        let mut constraints = constraints.into_iter();
+        let mut candidates = constraints.next()?;
+        for positions in constraints {
+            if candidates.is_empty() {
+                break;
+            }
+            candidates.retain(|position| positions.binary_search(position).is_ok());
+        }
+        Some(candidates)
+    }
+
+    /// [`candidates`](Self::candidates) for a parsed filter
+    pub fn filter_candidates(&self, filter: &Filter) -> Option<Vec<u32>> {
+        self.candidates(filter.kinds.as_deref(), filter.authors.as_deref(), filter.any_tags())
+    }
+
+    /// Sorted positions of exactly the events matching a filter, when the index
+    /// covers all of its constraints (kinds, authors and single-letter `#x` tags)
+    ///
+    /// `None` when the filter also has ids, since, until, search, `&x` or
+    /// multi-letter tags. Extensions are left to the caller.
+    pub fn exact_positions(&self, filter: &Filter) -> Option<Vec<u32>> {
+        let covered = filter.ids.is_none()
+            && filter.since.is_none()
+            && filter.until.is_none()
+            && filter.search.is_none()
+            && filter.tags.keys().all(|key| key.strip_prefix('#').is_some_and(|name| name.chars().count() == 1));
+        if !covered {
+            return None;
+        }
+        Some(self.filter_candidates(filter).unwrap_or_else(|| (0..self.events as u32).collect()))
+    }
+}
+
+/// Sorted, deduplicated union of position lists
+fn union<'a>(lists: impl Iterator<Item = &'a Vec<u32>>) -> Vec<u32> {
+    let mut positions: Vec<u32> = lists.flatten().copied().collect();
+    positions.sort_unstable();
+    positions.dedup();
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_index() {
+        let events = vec![
+            json!({"kind": 1, "pubkey": "alice", "tags": [["e", "root"], ["t", "nostr"]]}),
+            json!({"kind": 7, "pubkey": "bob", "tags": [["e", "root"], ["e", "root"]]}),
+            json!({"kind": 1, "pubkey": "bob", "tags": [["t", "nostr"], ["word", "x"]]}),
+        ];
+        let index = EventIndex::from_json(&EventIndex::from_events(&events).to_json()).unwrap();
+        assert_eq!(index.tags["e"]["root"], vec![0, 1]);
+        assert!(!index.tags.contains_key("word"));
+        let json = EventIndex::from_events(&events).to_json();
+        assert!(json.find("\"alice\"").unwrap() < json.find("\"bob\"").unwrap());
+        assert!(json.find("\"e\"").unwrap() < json.find("\"t\"").unwrap());
+
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let bob = strings(&["bob"]);
+        let root = strings(&["root"]);
+        let nostr = strings(&["nostr"]);
+
+        assert_eq!(index.candidates(Some(&[1]), None, []), Some(vec![0, 2]));
+        assert_eq!(index.candidates(Some(&[1, 7]), Some(&bob), []), Some(vec![1, 2]));
+        assert_eq!(index.candidates(None, Some(&bob), [("e", root.as_slice())]), Some(vec![1]));
+        assert_eq!(index.candidates(None, Some(&strings(&["al", "b"])), []), Some(vec![0, 1, 2]));
+        assert_eq!(index.candidates(Some(&[7]), None, [("t", nostr.as_slice())]), Some(vec![]));
+        assert_eq!(index.candidates(None, None, [("q", root.as_slice())]), Some(vec![]));
+
+        // Unindexed constraints leave the filter to a full scan
+        assert_eq!(index.candidates(None, None, [("word", nostr.as_slice())]), None);
+        assert_eq!(index.candidates(None, None, []), None);
+        assert_eq!(EventIndex::from_json("{}"), None);
+
+        let filter = |json: Value| Filter::from_json(&json).unwrap();
+        assert_eq!(index.exact_positions(&filter(json!({"kinds": [1], "#t": ["nostr"]}))), Some(vec![0, 2]));
+        assert_eq!(index.exact_positions(&filter(json!({}))), Some(vec![0, 1, 2]));
+        assert_eq!(index.exact_positions(&filter(json!({"authors": ["al"]}))), Some(vec![0]));
+        assert_eq!(index.exact_positions(&filter(json!({"kinds": [1], "since": 10}))), None);
+        assert_eq!(index.exact_positions(&filter(json!({"#word": ["x"]}))), None);
+    }
+}
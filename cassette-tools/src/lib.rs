@@ -1,11 +1,49 @@
+#[cfg(feature = "schema")]
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
 
 // Constants for string handling
 const MSGB_SIGNATURE: [u8; 4] = [0x4D, 0x53, 0x47, 0x42]; // "MSGB"
 const MAX_STRING_LENGTH: usize = 10_000_000; // 10MB safety limit
 
-/// Allocate a buffer of specified size
+/// Guest interface version of cassettes built with these tools.
+/// v1 cassettes export `req`, `close` and `describe` and answer with NUL-terminated
+/// strings; v2 cassettes take every NIP-01 message through `send`/`scrub`, answer
+/// with MSGB strings and export `info`.
+pub const ABI_VERSION: u32 = 2;
+
+/// Report the guest interface version, so loaders don't have to guess it from export names
+#[no_mangle]
+pub extern "C" fn abi_version() -> u32 {
+    ABI_VERSION
+}
+
+// Length of every buffer handed to the host, keyed by address, so each one is
+// freed with exactly the layout it was allocated with
+static ALLOCATIONS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+fn allocations() -> MutexGuard<'static, BTreeMap<usize, usize>> {
+    ALLOCATIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Hand a buffer over to the host until it is released
+fn register(buffer: Box<[u8]>) -> *mut u8 {
+    let len = buffer.len();
+    let ptr = Box::into_raw(buffer) as *mut u8;
+    allocations().insert(ptr as usize, len);
+    ptr
+}
+
+// Free a buffer made by `register`; pointers it didn't hand out are left alone
+fn release(ptr: *mut u8) {
+    if let Some(len) = allocations().remove(&(ptr as usize)) {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) });
+    }
+}
+
+/// Allocate a zeroed buffer of specified size
 /// This function is called from JavaScript to allocate memory in the WebAssembly module
 /// for storing strings or other data.
 #[no_mangle]
@@ -15,18 +53,7 @@ pub fn alloc_buffer(size: usize) -> *mut u8 {
         return std::ptr::null_mut();
     }
     
-    // Create a new buffer with exact capacity
-    let mut buffer = Vec::with_capacity(size);
-    // Set the length to initialize memory
-    unsafe { buffer.set_len(size); }
-    
-    // Get raw pointer
-    let ptr = buffer.as_mut_ptr();
-    
-    // Prevent Rust from freeing the memory when this function returns
-    std::mem::forget(buffer);
-    
-    ptr
+    register(vec![0u8; size].into_boxed_slice())
 }
 
 /// Convert a Rust string to a pointer that can be returned to WebAssembly
@@ -64,13 +91,7 @@ pub fn string_to_ptr(s: String) -> *mut u8 {
     // Copy the string bytes
     buffer[8..8+bytes_len].copy_from_slice(&bytes);
     
-    // Get raw pointer to the buffer
-    let ptr = buffer.as_mut_ptr();
-    
-    // Prevent Rust from freeing the memory
-    std::mem::forget(buffer);
-    
-    ptr
+    register(buffer.into_boxed_slice())
 }
 
 /// Convert a buffer in WebAssembly memory to a Rust string
@@ -118,17 +139,10 @@ pub fn ptr_to_string(ptr: *const u8, len: usize) -> String {
 /// 
 /// Args:
 ///   ptr: Pointer to the memory location
-///   len: Length of the allocation
+///   len: Ignored; the allocation table knows the length
 #[no_mangle]
-pub fn dealloc_buffer(ptr: *mut u8, len: usize) {
-    if ptr.is_null() || len == 0 {
-        return;
-    }
-    
-    unsafe {
-        // Recreate the Vec and let it drop
-        Vec::from_raw_parts(ptr, len, len);
-    }
+pub fn dealloc_buffer(ptr: *mut u8, _len: usize) {
+    release(ptr);
 }
 
 /// Deallocate a string that was allocated with string_to_ptr
@@ -136,29 +150,10 @@ pub fn dealloc_buffer(ptr: *mut u8, len: usize) {
 /// 
 /// Args:
 ///   ptr: Pointer to the memory location
-///   len: Length hint (can be 0, in which case we try to determine it)
+///   len: Ignored; the allocation table knows the length, so hosts may pass 0
 #[no_mangle]
-pub extern "C" fn dealloc_string(ptr: *mut u8, len: usize) {
-    if ptr.is_null() {
-        return;
-    }
-    
-    // If len is 0, try to determine the actual allocation size
-    let actual_len = if len == 0 {
-        get_allocation_size(ptr)
-    } else {
-        len
-    };
-    
-    // If we still don't have a valid length, we can't safely deallocate
-    if actual_len == 0 {
-        return;
-    }
-    
-    unsafe {
-        // Deallocate the entire buffer
-        let _ = Vec::from_raw_parts(ptr, actual_len, actual_len);
-    }
+pub extern "C" fn dealloc_string(ptr: *mut u8, _len: usize) {
+    release(ptr);
 }
 
 /// Get the length of a string at a given pointer
@@ -235,55 +230,41 @@ pub fn get_string_ptr(ptr: *const u8) -> *const u8 {
     }
 }
 
-/// Get the total allocation size for a string pointer
-/// This includes the MSGB header (8 bytes) plus the string data length
+/// Get the total allocation size for a pointer from string_to_ptr or alloc_buffer
+/// For strings this includes the MSGB header (8 bytes) plus the string data length
 /// 
 /// Args:
 ///   ptr: Pointer to the memory location
 ///
-/// Returns: Total allocation size, or 0 if unknown
+/// Returns: Total allocation size, or 0 if the pointer isn't a live allocation
 #[no_mangle]
 pub extern "C" fn get_allocation_size(ptr: *const u8) -> usize {
-    if ptr.is_null() {
-        return 0;
-    }
-    
-    // Check if this has our MSGB signature
-    let has_signature = unsafe {
-        if ptr as usize + 4 > usize::MAX {
-            return 0;
-        }
-        let bytes = std::slice::from_raw_parts(ptr, 4);
-        bytes.len() == 4 && 
-        bytes[0] == MSGB_SIGNATURE[0] && 
-        bytes[1] == MSGB_SIGNATURE[1] && 
-        bytes[2] == MSGB_SIGNATURE[2] && 
-        bytes[3] == MSGB_SIGNATURE[3]
-    };
-    
-    if has_signature {
-        // Read the embedded length from MSGB header
-        unsafe {
-            let mut length_bytes = [0u8; 4];
-            let bytes = std::slice::from_raw_parts(ptr.add(4), 4);
-            length_bytes.copy_from_slice(bytes);
-            let string_length = u32::from_le_bytes(length_bytes) as usize;
-            
-            // Validate the length
-            if string_length > MAX_STRING_LENGTH {
-                return 0;
-            }
-            
-            // Return total size: signature (4) + length (4) + data
-            8 + string_length
-        }
-    } else {
-        // Without MSGB signature, we can't determine the allocation size
-        0
+    allocations().get(&(ptr as usize)).copied().unwrap_or(0)
+}
+
+/// Take over a buffer `alloc_buffer` handed to the host, so a large input is kept
+/// without copying it; the host's later `dealloc_string` leaves it alone. `None`
+/// for pointers the table didn't hand out or of another length
+pub fn claim_buffer(ptr: *mut u8, len: usize) -> Option<Box<[u8]>> {
+    let mut allocations = allocations();
+    if allocations.get(&(ptr as usize)) != Some(&len) {
+        return None;
     }
+    allocations.remove(&(ptr as usize));
+    Some(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) })
+}
+
+/// Read back a string made by `string_to_ptr` and free it, for guests that
+/// answer through another interface than pointers (component cassettes)
+pub fn take_string(ptr: *mut u8) -> String {
+    let size = get_allocation_size(ptr);
+    let string = ptr_to_string(ptr, size);
+    dealloc_string(ptr, size);
+    string
 }
 
 /// JSON Schema for a Cassette
+#[cfg(feature = "schema")]
 #[derive(Serialize, Deserialize)]
 pub struct CassetteSchema {
     pub title: String,
@@ -302,9 +283,77 @@ pub mod wasm_interface;
 /// Modular NIP support
 pub mod nips;
 
+/// Filter complexity guardrails
+pub mod limits;
+pub use limits::FilterLimits;
+
+/// NIP-01 filter parsing and matching
+pub mod filter;
+pub use filter::{newest_first, Event, Filter};
+
+/// Typed NIP-01 events
+pub mod event;
+pub use event::NostrEvent;
+
+/// NIP-01 CLOSED and OK messages
+pub mod messages;
+
+/// Errors and the NIP-01 messages that report them
+pub mod error;
+pub use error::CassetteError;
+
+/// Build-time event indexes
+pub mod index;
+pub use index::EventIndex;
+
+/// Build-time full-text index over event content
+pub mod text_index;
+pub use text_index::TextIndex;
+
+/// COUNT results with an optional per-kind breakdown
+pub mod count;
+pub use count::Count;
+
+/// Latest versions of replaceable and addressable events
+pub mod replaceable;
+pub use replaceable::LatestVersions;
+
+/// Cursor-based pages of REQ results
+pub mod page;
+pub use page::Page;
+
+/// Bounded chunks of a response
+pub mod chunks;
+pub use chunks::ChunkedResponse;
+
+/// Which input of a dub each event came from
+pub mod provenance;
+pub use provenance::Provenance;
+
+/// Signature checks over a cassette's own events
+#[cfg(feature = "verify")]
+pub mod verify;
+#[cfg(feature = "verify")]
+pub use verify::Verification;
+
+/// zstd-compressed event payloads
+#[cfg(feature = "compress")]
+pub mod compress;
+
+/// Event payloads sealed to a recipient
+#[cfg(feature = "encrypt")]
+pub mod encrypt;
+
+/// In-memory storage for writable cassettes
+#[cfg(feature = "writable")]
+pub mod store;
+#[cfg(feature = "writable")]
+pub use store::EventStore;
+
 // The macro is automatically exported at crate root due to #[macro_export]
 // No need to re-export it
 
+#[cfg(feature = "schema")]
 impl Default for CassetteSchema {
     fn default() -> Self {
         Self {
@@ -318,10 +367,12 @@ impl Default for CassetteSchema {
     }
 }
 
-/// Result type for relay operations
-pub type RelayResult = Result<String, String>;
+/// Result type for relay operations; `respond` turns the error into the
+/// NOTICE, CLOSED or OK message the client gets
+pub type RelayResult = Result<String, CassetteError>;
 
 /// Trait that all cassettes must implement
+#[cfg(feature = "schema")]
 pub trait Cassette {
     /// Returns a description of the cassette
     fn describe() -> String;
@@ -338,6 +389,12 @@ pub trait Cassette {
 
 /// Trait for handling relay operations
 pub trait RelayHandler {
+    /// Handle a JSON-formatted relay message, answering errors with the
+    /// NIP-01 message that reports them
+    fn respond(&self, message: &str) -> String {
+        self.handle_message(message).unwrap_or_else(|e| e.response_to(message))
+    }
+    
     /// Handle a JSON-formatted relay message
     fn handle_message(&self, message: &str) -> RelayResult {
         let parsed: Result<Value, _> = serde_json::from_str(message);
@@ -350,26 +407,27 @@ pub trait RelayHandler {
                             match cmd {
                                 "REQ" => self.handle_req(message),
                                 "CLOSE" => self.handle_close(message),
-                                _ => Err(format!("Unsupported command: {}", cmd)),
+                                "EVENT" => self.handle_event(message),
+                                _ => Err(CassetteError::UnsupportedCommand(cmd.to_string())),
                             }
                         } else {
-                            Err("Invalid message format: first element must be a string".to_string())
+                            Err(CassetteError::InvalidMessage("first element must be a string".to_string()))
                         }
                     } else {
-                        Err("Empty message array".to_string())
+                        Err(CassetteError::InvalidMessage("empty message array".to_string()))
                     }
                 } else {
-                    Err("Message must be a JSON array".to_string())
+                    Err(CassetteError::InvalidMessage("message must be a JSON array".to_string()))
                 }
             },
-            Err(e) => Err(format!("Failed to parse JSON: {}", e)),
+            Err(e) => Err(e.into()),
         }
     }
     
     /// Handle a REQ command
     fn handle_req(&self, req_json: &str) -> RelayResult;
     
-    /// Handle a CLOSE command
+    /// Handle a CLOSE command, confirming it with a CLOSED message
     fn handle_close(&self, close_json: &str) -> RelayResult {
         // Default implementation for CLOSE
         let parsed: Result<Value, _> = serde_json::from_str(close_json);
@@ -380,23 +438,34 @@ pub trait RelayHandler {
                     if arr.len() >= 2 && arr[0].as_str() == Some("CLOSE") {
                         let subscription_id = arr[1].as_str().unwrap_or("");
                         Ok(json!({
-                            "notice": ["NOTICE", format!("Subscription {} closed", subscription_id)]
+                            "closed": messages::closed(subscription_id, "")
                         }).to_string())
                     } else {
-                        Err("Invalid CLOSE message format".to_string())
+                        Err(CassetteError::InvalidMessage("expected [\"CLOSE\", <subscription id>]".to_string()))
                     }
                 } else {
-                    Err("CLOSE message must be a JSON array".to_string())
+                    Err(CassetteError::InvalidMessage("CLOSE message must be a JSON array".to_string()))
                 }
             },
-            Err(e) => Err(format!("Failed to parse CLOSE JSON: {}", e)),
+            Err(e) => Err(e.into()),
         }
     }
+    
+    /// Handle an EVENT command with an OK message; cassettes are read-only by default
+    fn handle_event(&self, event_json: &str) -> RelayResult {
+        let parsed: Value = serde_json::from_str(event_json)?;
+        if parsed.get(1).and_then(|event| event.get("id")).and_then(|id| id.as_str()).is_none() {
+            return Err(CassetteError::InvalidMessage("expected [\"EVENT\", <event with an id>]".to_string()));
+        }
+        Err(CassetteError::read_only())
+    }
 }
 
 /// EventBasedHandler implements RelayHandler using a static list of events
 pub struct EventBasedHandler {
     events_json: String,
+    #[cfg(feature = "writable")]
+    store: Option<std::cell::RefCell<EventStore>>,
 }
 
 impl EventBasedHandler {
@@ -404,15 +473,38 @@ impl EventBasedHandler {
     pub fn new(events_json: &str) -> Self {
         Self {
             events_json: events_json.to_string(),
+            #[cfg(feature = "writable")]
+            store: None,
+        }
+    }
+    
+    /// Like `new`, but EVENT messages are stored and served to later REQs
+    #[cfg(feature = "writable")]
+    pub fn writable(events_json: &str) -> Self {
+        let recorded: Vec<NostrEvent> = serde_json::from_str(events_json).unwrap_or_default();
+        Self {
+            events_json: events_json.to_string(),
+            store: Some(std::cell::RefCell::new(EventStore::over(&recorded))),
         }
     }
+    
+    /// The events embedded at build time, plus any written since
+    fn all_events(&self) -> Result<Vec<NostrEvent>, serde_json::Error> {
+        #[allow(unused_mut)]
+        let mut events: Vec<NostrEvent> = serde_json::from_str(&self.events_json)?;
+        #[cfg(feature = "writable")]
+        if let Some(store) = &self.store {
+            events.extend(store.borrow().events().iter().cloned());
+        }
+        Ok(events)
+    }
 }
 
 impl RelayHandler for EventBasedHandler {
     fn handle_req(&self, req_json: &str) -> RelayResult {
         // Validate that the request isn't empty
         if req_json.trim().is_empty() {
-            return Err("Empty request received".to_string());
+            return Err(CassetteError::InvalidMessage("empty request".to_string()));
         }
 
         // Parse the incoming request JSON with detailed error handling
@@ -424,20 +516,20 @@ impl RelayHandler for EventBasedHandler {
                 if let Some(array) = req.as_array() {
                     // Validate REQ message structure
                     if array.len() < 2 {
-                        return Err(format!("REQ message too short. Expected at least 2 elements, got {}", array.len()).to_string());
+                        return Err(CassetteError::InvalidMessage(format!("REQ message too short. Expected at least 2 elements, got {}", array.len())));
                     }
                     
                     // Check if first element is "REQ"
                     if array[0].as_str() != Some("REQ") {
-                        return Err(format!("Invalid message type. Expected 'REQ', got '{}'", 
-                            array[0].as_str().unwrap_or("non-string value")).to_string());
+                        return Err(CassetteError::InvalidMessage(format!("Expected 'REQ', got '{}'",
+                            array[0].as_str().unwrap_or("non-string value"))));
                     }
                     
                     // Get subscription ID (second element) with validation
                     let subscription_id = match array[1].as_str() {
                         Some(id) if !id.trim().is_empty() => id,
-                        Some(_) => return Err("Subscription ID cannot be empty or whitespace".to_string()),
-                        None => return Err("Subscription ID must be a string".to_string())
+                        Some(_) => return Err(CassetteError::InvalidMessage("subscription ID cannot be empty or whitespace".to_string())),
+                        None => return Err(CassetteError::InvalidMessage("subscription ID must be a string".to_string()))
                     };
                     
                     // Log the subscription ID for debugging
@@ -447,9 +539,18 @@ impl RelayHandler for EventBasedHandler {
                     if array.len() < 3 {
                         // No filters provided, which is valid - we'll return all events
                         // Process without filters
-                        let events: Result<Vec<Value>, _> = serde_json::from_str(&self.events_json);
+                        let events = self.all_events();
                     
                         if let Ok(events) = events {
+                            let mut events = replaceable::latest_versions(events);
+                            events.sort_by(newest_first);
+                            #[cfg(feature = "nip09")]
+                            let events = nips::nip09::remove_deleted(events);
+                            #[cfg(feature = "nip40")]
+                            let events: Vec<NostrEvent> = events.into_iter()
+                                .filter(|event| !nips::nip40::is_expired_now(event))
+                                .collect();
+                            
                             // Convert to EVENT messages without filtering
                             let events: Vec<Value> = events.into_iter()
                                 .map(|event| {
@@ -469,269 +570,37 @@ impl RelayHandler for EventBasedHandler {
                                 "eose": ["EOSE", subscription_id]
                             }).to_string());
                         } else {
-                            return Err("Error parsing embedded events JSON".to_string());
+                            return Err(CassetteError::Internal("embedded events failed to load".to_string()));
                         }
                     }
                     
-                    // Try to get filters from the request
-                    let mut kind_filter: Option<Vec<i64>> = None;
-                    let mut author_filter: Option<Vec<String>> = None;
-                    let mut tags_filter: Option<Vec<(String, String)>> = None;
-                    let mut and_tags_filter: Option<Vec<(String, Vec<String>)>> = None;
-                    let mut since_filter: Option<i64> = None;
-                    let mut until_filter: Option<i64> = None;
-                    let mut limit_filter: Option<usize> = None;
-                    let mut ids_filter: Option<Vec<String>> = None;
-                    
-                    // Process filters (starting from index 2)
+                    // Parse the filters (starting from index 2); a bad filter ends the subscription
+                    let mut filters = Vec::new();
                     for i in 2..array.len() {
-                        if let Some(filter) = array[i].as_object() {
-                            // Look for kind filter
-                            if let Some(kinds) = filter.get("kinds") {
-                                if let Some(kinds_array) = kinds.as_array() {
-                                    kind_filter = Some(
-                                        kinds_array.iter()
-                                            .filter_map(|k| k.as_i64())
-                                            .collect()
-                                    );
-                                } else {
-                                    return Err("'kinds' filter must be an array".to_string());
-                                }
-                            }
-                            
-                            // Look for author filter
-                            if let Some(authors) = filter.get("authors") {
-                                if let Some(authors_array) = authors.as_array() {
-                                    author_filter = Some(
-                                        authors_array.iter()
-                                            .filter_map(|a| a.as_str().map(String::from))
-                                            .collect()
-                                    );
-                                } else {
-                                    return Err("'authors' filter must be an array".to_string());
-                                }
-                            }
-                            
-                            // Look for ids filter (event IDs)
-                            if let Some(ids) = filter.get("ids") {
-                                if let Some(ids_array) = ids.as_array() {
-                                    ids_filter = Some(
-                                        ids_array.iter()
-                                            .filter_map(|id| id.as_str().map(String::from))
-                                            .collect()
-                                    );
-                                } else {
-                                    return Err("'ids' filter must be an array".to_string());
-                                }
-                            }
-                            
-                            // Look for since filter
-                            if let Some(since_val) = filter.get("since") {
-                                since_filter = since_val.as_i64();
-                                if since_filter.is_none() {
-                                    return Err("'since' filter must be an integer timestamp".to_string());
-                                }
-                            }
-                            
-                            // Look for until filter
-                            if let Some(until_val) = filter.get("until") {
-                                until_filter = until_val.as_i64();
-                                if until_filter.is_none() {
-                                    return Err("'until' filter must be an integer timestamp".to_string());
-                                }
-                            }
-                            
-                            // Look for limit filter
-                            if let Some(limit_val) = filter.get("limit") {
-                                limit_filter = limit_val.as_u64().map(|l| l as usize);
-                                if limit_filter.is_none() {
-                                    return Err("'limit' filter must be a positive integer".to_string());
-                                }
-                            }
-                            
-                            // Implementing "tags" correctly based on NIP-01
-                            let tag_filters: Vec<(String, String)> = filter.keys()
-                                .filter(|k| k.starts_with('#'))
-                                .filter_map(|k| {
-                                    let tag_key = k.trim_start_matches('#');
-                                    if let Some(values) = filter.get(k).and_then(|v| v.as_array()) {
-                                        Some(values.iter()
-                                            .filter_map(|v| v.as_str().map(|value| (tag_key.to_string(), value.to_string())))
-                                            .collect::<Vec<_>>())
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .flatten()
-                                .collect();
-                            
-                            if !tag_filters.is_empty() {
-                                tags_filter = Some(tag_filters);
-                            }
-                            
-                            // AND tag filtering (NIP-119)
-                            let and_tag_filters: Vec<(String, Vec<String>)> = filter.keys()
-                                .filter(|k| k.starts_with('&'))
-                                .filter_map(|k| {
-                                    let tag_key = k.trim_start_matches('&');
-                                    if let Some(values) = filter.get(k).and_then(|v| v.as_array()) {
-                                        let tag_values: Vec<String> = values.iter()
-                                            .filter_map(|v| v.as_str().map(String::from))
-                                            .collect();
-                                        
-                                        if !tag_values.is_empty() {
-                                            Some((tag_key.to_string(), tag_values))
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect();
-                            
-                            if !and_tag_filters.is_empty() {
-                                and_tags_filter = Some(and_tag_filters);
-                            }
-                        } else {
-                            return Err(format!("Filter at position {} must be an object", i).to_string());
+                        match &array[i] {
+                            filter if filter.is_object() => filters.push(Filter::from_json(filter)?),
+                            _ => return Err(CassetteError::InvalidFilter(format!("Filter at position {} must be an object", i))),
                         }
                     }
                     
-                    // Parse the events embedded at build time
-                    let events: Result<Vec<Value>, _> = serde_json::from_str(&self.events_json);
+                    // Parse the events embedded at build time, and any written since
+                    let events = self.all_events();
                     
                     if let Ok(events) = events {
-                        // Apply all filters in sequence
-                        let mut filtered_events = events;
-                        
-                        // Filter by ids if specified
-                        if let Some(ids) = ids_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(id) = event.get("id").and_then(|id| id.as_str()) {
-                                        ids.contains(&id.to_string())
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
-                        
-                        // Filter by kinds if specified
-                        if let Some(kinds) = kind_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(kind) = event.get("kind").and_then(|k| k.as_i64()) {
-                                        kinds.contains(&kind)
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
-                        
-                        // Filter by authors if specified
-                        if let Some(authors) = author_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(pubkey) = event.get("pubkey").and_then(|p| p.as_str()) {
-                                        authors.contains(&pubkey.to_string())
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
-                        
-                        // Filter by since timestamp
-                        if let Some(since) = since_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-                                        created_at >= since
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
-                        
-                        // Filter by until timestamp
-                        if let Some(until) = until_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-                                        created_at <= until
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
-                        
-                        // Filter by tags if specified
-                        if let Some(tag_filters) = tags_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(tags) = event.get("tags").and_then(|t| t.as_array()) {
-                                        tag_filters.iter().all(|(key, value)| {
-                                            tags.iter().any(|tag| {
-                                                if let Some(tag_array) = tag.as_array() {
-                                                    if tag_array.len() >= 2 {
-                                                        let tag_type = tag_array[0].as_str().unwrap_or("");
-                                                        let tag_value = tag_array[1].as_str().unwrap_or("");
-                                                        tag_type == key && tag_value == value
-                                                    } else {
-                                                        false
-                                                    }
-                                                } else {
-                                                    false
-                                                }
-                                            })
-                                        })
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
-                        
-                        // Filter by AND tags if specified
-                        if let Some(and_tag_filters) = and_tags_filter {
-                            filtered_events = filtered_events.into_iter()
-                                .filter(|event| {
-                                    if let Some(tags) = event.get("tags").and_then(|t| t.as_array()) {
-                                        and_tag_filters.iter().all(|(key, values)| {
-                                            values.iter().all(|value| {
-                                                tags.iter().any(|tag| {
-                                                    if let Some(tag_array) = tag.as_array() {
-                                                        if tag_array.len() >= 2 {
-                                                            let tag_type = tag_array[0].as_str().unwrap_or("");
-                                                            let tag_value = tag_array[1].as_str().unwrap_or("");
-                                                            tag_type == key && tag_value == value
-                                                        } else {
-                                                            false
-                                                        }
-                                                    } else {
-                                                        false
-                                                    }
-                                                })
-                                            })
-                                        })
-                                    } else {
-                                        false
-                                    }
-                                })
-                                .collect();
-                        }
+                        // Older versions of replaceable events, deletion requests (NIP-09)
+                        // and expiration tags (NIP-40) hide events
+                        let mut events = replaceable::latest_versions(events);
+                        events.sort_by(newest_first);
+                        #[cfg(feature = "nip09")]
+                        let events = nips::nip09::remove_deleted(events);
+                        #[cfg(feature = "nip40")]
+                        let events: Vec<NostrEvent> = events.into_iter()
+                            .filter(|event| !nips::nip40::is_expired_now(event))
+                            .collect();
                         
-                        // Apply limit filter if specified
-                        if let Some(limit) = limit_filter {
-                            if limit < filtered_events.len() {
-                                filtered_events = filtered_events.into_iter().take(limit).collect();
-                            }
-                        }
+                        // An event is returned when any filter matches it; each filter's
+                        // limit caps how many events that filter contributes
+                        let filtered_events = Filter::select(&filters, events);
                         
                         // Convert filtered events to EVENT messages
                         let events: Vec<Value> = filtered_events.into_iter()
@@ -752,14 +621,14 @@ impl RelayHandler for EventBasedHandler {
                             "eose": ["EOSE", subscription_id]
                         }).to_string());
                     } else {
-                        return Err("Error parsing embedded events JSON".to_string());
+                        return Err(CassetteError::Internal("embedded events failed to load".to_string()));
                     }
                 } else {
-                    return Err("Request must be a JSON array".to_string());
+                    return Err(CassetteError::InvalidMessage("request must be a JSON array".to_string()));
                 }
             }
             Err(e) => {
-                return Err(format!("Invalid JSON in request: {}", e).to_string());
+                return Err(e.into());
             }
         }
     }
@@ -767,7 +636,7 @@ impl RelayHandler for EventBasedHandler {
     fn handle_close(&self, close_json: &str) -> RelayResult {
         // Validate that the request isn't empty
         if close_json.trim().is_empty() {
-            return Err("Empty close request received".to_string());
+            return Err(CassetteError::InvalidMessage("empty close request".to_string()));
         }
 
         // Parse the incoming close JSON with detailed error handling
@@ -780,26 +649,37 @@ impl RelayHandler for EventBasedHandler {
                     if array.len() >= 2 && array[0].as_str() == Some("CLOSE") {
                         let subscription_id = array[1].as_str().unwrap_or("");
                         
-                        // Return a successful close message
+                        // Confirm the close with a CLOSED message
                         return Ok(json!({
-                            "notice": ["NOTICE", format!("Closed subscription {}", subscription_id)]
+                            "closed": messages::closed(subscription_id, "")
                         }).to_string());
                     } else {
                         // If CLOSE message doesn't match expected format, return a NOTICE
-                        return Err("Invalid close message format. Expected ['CLOSE', subscription_id]".to_string());
+                        return Err(CassetteError::InvalidMessage("expected ['CLOSE', subscription_id]".to_string()));
                     }
                 } else {
                     // The request is valid JSON but not an array
-                    return Err("Invalid close message format. Expected a JSON array".to_string());
+                    return Err(CassetteError::InvalidMessage("CLOSE message must be a JSON array".to_string()));
                 }
             },
             Err(e) => {
-                // If JSON parsing failed, return a detailed error message
-                let error_msg = format!("Invalid JSON in close request: {}", e.to_string());
-                return Err(error_msg);
+                // If JSON parsing failed, the NOTICE carries the parser's message
+                return Err(e.into());
             }
         }
     }
+    
+    #[cfg(feature = "writable")]
+    fn handle_event(&self, event_json: &str) -> RelayResult {
+        let parsed: Value = serde_json::from_str(event_json)?;
+        let event = parsed.get(1)
+            .ok_or_else(|| CassetteError::InvalidMessage("expected [\"EVENT\", <event>]".to_string()))?;
+        let event_id = event.get("id").and_then(|id| id.as_str()).unwrap_or("");
+        
+        let store = self.store.as_ref().ok_or_else(CassetteError::read_only)?;
+        store.borrow_mut().insert(NostrEvent::from_value(event)?)?;
+        Ok(json!({ "ok": messages::ok(event_id, true, "") }).to_string())
+    }
 }
 
 /// Macro to re-export NIP-11 info function from cassette-tools
@@ -822,11 +702,12 @@ pub extern "C" fn info() -> *mut u8 {
 }
 
 /// CassetteMacros provides macros to make implementation easier
+#[cfg(feature = "schema")]
 #[macro_export]
 macro_rules! cassette_module {
     ($struct_name:ident, $title:expr, $description:expr) => {
         use cassette_tools::{Cassette, CassetteSchema, RelayHandler, RelayResult};
-        use cassette_tools::nip01::{ClientReq, RelayEvent, RelayNotice, RelayEose};
+        use cassette_tools::nip01::{ClientReq, RelayClosed, RelayEvent, RelayNotice, RelayEose};
         use serde_json::{json, Value, from_str, to_string};
         use wasm_bindgen::prelude::*;
 
@@ -885,7 +766,8 @@ macro_rules! cassette_module {
                             "oneOf": [
                                 from_str(&<RelayEvent as Cassette>::get_schema_json()).unwrap_or(json!({})),
                                 from_str(&<RelayNotice as Cassette>::get_schema_json()).unwrap_or(json!({})),
-                                from_str(&<RelayEose as Cassette>::get_schema_json()).unwrap_or(json!({}))
+                                from_str(&<RelayEose as Cassette>::get_schema_json()).unwrap_or(json!({})),
+                                from_str(&<RelayClosed as Cassette>::get_schema_json()).unwrap_or(json!({}))
                             ]
                         }
                     },
@@ -897,7 +779,7 @@ macro_rules! cassette_module {
                                 {"type": "string", "description": "Subscription ID to close"}
                             ]
                         },
-                        "output": from_str(&<RelayNotice as Cassette>::get_schema_json()).unwrap_or(json!({}))
+                        "output": from_str(&<RelayClosed as Cassette>::get_schema_json()).unwrap_or(json!({}))
                     }
                 });
                 
@@ -913,30 +795,23 @@ macro_rules! cassette_module {
             pub fn req(request_json: &str) -> String {
                 // Create an instance and handle the request
                 let instance = Self::new();
-                match instance.handle_req(request_json) {
-                    Ok(response) => response,
-                    Err(err) => json!({
-                        "notice": ["NOTICE", err]
-                    }).to_string()
-                }
+                instance.handle_req(request_json)
+                    .unwrap_or_else(|err| err.response_to(request_json))
             }
 
             #[wasm_bindgen]
             pub fn close(close_json: &str) -> String {
                 // Create an instance and handle the close
                 let instance = Self::new();
-                match instance.handle_close(close_json) {
-                    Ok(response) => response,
-                    Err(err) => json!({
-                        "notice": ["NOTICE", err]
-                    }).to_string()
-                }
+                instance.handle_close(close_json)
+                    .unwrap_or_else(|err| err.response_to(close_json))
             }
         }
     };
 }
 
 /// NIP-01 Implementation
+#[cfg(feature = "schema")]
 pub mod nip01 {
     use super::*;
 
@@ -1007,14 +882,14 @@ pub mod nip01 {
                             }
                         },
                         "patternProperties": {
-                            "^#[a-zA-Z]$": {
+                            "^#.+$": {
                                 "type": "array",
                                 "items": {
                                     "type": "string"
                                 },
-                                "description": "A list of tag values, where specific tags (#e, #p) have designated meanings"
+                                "description": "A list of tag values for the tag named after the #, of any length and case (#e, #A, #alt), where specific tags (#e, #p) have designated meanings"
                             },
-                            "^&[a-zA-Z]$": {
+                            "^&.+$": {
                                 "type": "array",
                                 "items": {
                                     "type": "string"
@@ -1132,6 +1007,74 @@ pub mod nip01 {
             }
         }
     }
+    
+    /// Relay Closed message (CLOSED)
+    pub struct RelayClosed;
+    
+    impl Cassette for RelayClosed {
+        fn describe() -> String {
+            "NIP-01 Relay Closed (CLOSED) message".to_string()
+        }
+        
+        fn get_schema() -> CassetteSchema {
+            CassetteSchema {
+                title: "Relay Closed".to_string(),
+                description: "A message sent by relays when a subscription is ended on the relay side.".to_string(),
+                schema_type: "array".to_string(),
+                properties: json!({}),
+                required: vec![],
+                items: Some(json!([
+                    {
+                        "const": "CLOSED"
+                    },
+                    {
+                        "type": "string",
+                        "description": "The subscription ID"
+                    },
+                    {
+                        "type": "string",
+                        "description": "A reason with a machine-readable prefix, e.g. 'invalid: ' or 'error: '"
+                    }
+                ])),
+            }
+        }
+    }
+    
+    /// Relay OK message (OK)
+    pub struct RelayOk;
+    
+    impl Cassette for RelayOk {
+        fn describe() -> String {
+            "NIP-01 Relay OK (OK) message".to_string()
+        }
+        
+        fn get_schema() -> CassetteSchema {
+            CassetteSchema {
+                title: "Relay OK".to_string(),
+                description: "A message sent by relays to accept or reject an EVENT.".to_string(),
+                schema_type: "array".to_string(),
+                properties: json!({}),
+                required: vec![],
+                items: Some(json!([
+                    {
+                        "const": "OK"
+                    },
+                    {
+                        "type": "string",
+                        "description": "The event ID"
+                    },
+                    {
+                        "type": "boolean",
+                        "description": "Whether the event was accepted"
+                    },
+                    {
+                        "type": "string",
+                        "description": "A message with a machine-readable prefix, e.g. 'blocked: '"
+                    }
+                ])),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1154,7 +1097,42 @@ mod tests {
         assert_eq!(result, test_str);
 
         // Clean up
-        unsafe { dealloc_string(ptr, str_len + 8) };
+        dealloc_string(ptr, str_len + 8);
+    }
+
+    #[test]
+    fn test_take_string() {
+        assert_eq!(take_string(string_to_ptr("[\"EOSE\",\"sub\"]".to_string())), "[\"EOSE\",\"sub\"]");
+        assert_eq!(take_string(std::ptr::null_mut()), "");
+    }
+
+    #[test]
+    fn test_allocation_table() {
+        let ptr = alloc_buffer(10);
+        assert_eq!(get_allocation_size(ptr), 10);
+        assert!(unsafe { std::slice::from_raw_parts(ptr, 10) }.iter().all(|b| *b == 0));
+        // The length hint doesn't matter, and a second free is a no-op
+        dealloc_string(ptr, 0);
+        assert_eq!(get_allocation_size(ptr), 0);
+        dealloc_string(ptr, 10);
+
+        let ptr = string_to_ptr("abc".to_string());
+        assert_eq!(get_allocation_size(ptr), 11);
+        dealloc_buffer(ptr, 1);
+        assert_eq!(get_allocation_size(ptr), 0);
+
+        // A claimed buffer belongs to the guest; the host's free doesn't touch it
+        let ptr = alloc_buffer(3);
+        assert_eq!(claim_buffer(ptr, 2), None);
+        let claimed = claim_buffer(ptr, 3).unwrap();
+        dealloc_string(ptr, 3);
+        assert_eq!(&claimed[..], &[0, 0, 0]);
+
+        // Pointers the table didn't hand out are left alone
+        let mut local = [0u8; 4];
+        dealloc_string(local.as_mut_ptr(), 4);
+        assert_eq!(claim_buffer(local.as_mut_ptr(), 4), None);
+        assert_eq!(alloc_buffer(MAX_STRING_LENGTH + 1), std::ptr::null_mut());
     }
 
     #[test]
@@ -1173,7 +1151,7 @@ mod tests {
         assert_eq!(result, test_str);
 
         // Clean up
-        unsafe { dealloc_string(ptr, 8) };
+        dealloc_string(ptr, 8);
     }
 
     #[test]
@@ -1192,7 +1170,7 @@ mod tests {
         assert_eq!(result, test_str);
 
         // Clean up
-        unsafe { dealloc_string(ptr, str_len + 8) };
+        dealloc_string(ptr, str_len + 8);
     }
 
     #[test]
@@ -1205,7 +1183,7 @@ mod tests {
         assert_eq!(signature, &MSGB_SIGNATURE);
 
         // Clean up
-        unsafe { dealloc_string(ptr, test_str.len() + 8) };
+        dealloc_string(ptr, test_str.len() + 8);
     }
 
     #[test]
@@ -1224,7 +1202,7 @@ mod tests {
         assert_eq!(result, test_str);
 
         // Clean up
-        unsafe { dealloc_string(ptr, str_len + 8) };
+        dealloc_string(ptr, str_len + 8);
     }
 
     #[test]
@@ -1263,6 +1241,67 @@ mod tests {
         }
 
         // Clean up
-        unsafe { dealloc_buffer(ptr, size) };
+        dealloc_buffer(ptr, size);
+    }
+
+    #[test]
+    fn test_event_based_handler_serves_latest_versions() {
+        let event = |id: &str, kind: i64, created_at: i64| NostrEvent {
+            id: id.to_string(),
+            pubkey: "alice".to_string(),
+            created_at,
+            kind,
+            tags: vec![],
+            content: String::new(),
+            sig: String::new(),
+        };
+        let events = vec![event("old", 0, 10), event("new", 0, 20), event("note", 1, 5)];
+        let handler = EventBasedHandler::new(&serde_json::to_string(&events).unwrap());
+
+        for request in [r#"["REQ","sub"]"#, r#"["REQ","sub",{"authors":["alice"]}]"#] {
+            let response: Value = serde_json::from_str(&handler.handle_req(request).unwrap()).unwrap();
+            let ids: Vec<&str> = response["events"].as_array().unwrap().iter()
+                .map(|message| message[2]["id"].as_str().unwrap())
+                .collect();
+            assert_eq!(ids, vec!["new", "note"]);
+        }
+    }
+
+    #[test]
+    fn test_closed_and_ok_messages() {
+        let handler = EventBasedHandler::new("[]");
+        let response = |message: &str| serde_json::from_str::<Value>(&handler.respond(message)).unwrap();
+
+        assert_eq!(response(r#"["CLOSE","sub"]"#), json!({"closed": ["CLOSED", "sub", ""]}));
+        assert_eq!(response(r#"["REQ","sub",{"kinds":["1"]}]"#)["closed"][2], "invalid: 'kinds' filter must be an array of integers");
+        assert_eq!(response(r#"["REQ","sub",[]]"#)["closed"][0], "CLOSED");
+        assert_eq!(response(r#"["EVENT",{"id":"e1"}]"#), json!({"ok": ["OK", "e1", false, messages::READ_ONLY]}));
+        assert_eq!(response(r#"["AUTH","challenge"]"#), json!({"notice": ["NOTICE", "Unsupported command: AUTH"]}));
+        assert!(matches!(handler.handle_req(r#"["REQ","sub",{"since":"yesterday"}]"#), Err(CassetteError::InvalidFilter(_))));
+    }
+
+    #[cfg(feature = "writable")]
+    #[test]
+    fn test_writable_event_based_handler() {
+        let mut event = NostrEvent {
+            id: String::new(),
+            pubkey: String::new(),
+            created_at: 10,
+            kind: 1,
+            tags: vec![],
+            content: "written".to_string(),
+            sig: String::new(),
+        };
+        crate::event::sign_for_test(&mut event);
+        let publish = json!(["EVENT", event]).to_string();
+
+        let handler = EventBasedHandler::writable("[]");
+        let ok: Value = serde_json::from_str(&handler.handle_message(&publish).unwrap()).unwrap();
+        assert_eq!(ok, json!({"ok": ["OK", event.id, true, ""]}));
+        let again: Value = serde_json::from_str(&handler.respond(&publish)).unwrap();
+        assert_eq!(again["ok"][2], false);
+
+        let response: Value = serde_json::from_str(&handler.handle_req(r#"["REQ","sub",{"kinds":[1]}]"#).unwrap()).unwrap();
+        assert_eq!(response["events"][0][2]["id"], event.id.as_str());
     }
 }
@@ -0,0 +1,128 @@
+//! Filter complexity guardrails
+//!
+//! Cassettes scan their embedded events for every filter, so a REQ with
+//! hundreds of filters or tag values turns into a combinatorial scan on the
+//! server hosting the cassette. These limits reject such requests up front
+//! with a NIP-01 `CLOSED` message and are advertised in the NIP-11 document.
+
+use serde::{Deserialize, Serialize};
+use crate::error::CassetteError;
+use serde_json::{json, Map, Value};
+
+/// Limits on the filters a single REQ or COUNT may carry. A limit of 0 disables that check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterLimits {
+    /// Maximum filters per REQ/COUNT
+    pub max_filters: usize,
+    /// Maximum tag filters (`#e`, `#p`, ...) per filter
+    pub max_tag_filters: usize,
+    /// Maximum values in any filter list (ids, authors, kinds, tag values)
+    pub max_filter_values: usize,
+}
+
+impl Default for FilterLimits {
+    fn default() -> Self {
+        Self {
+            max_filters: 10,
+            max_tag_filters: 10,
+            max_filter_values: 1000,
+        }
+    }
+}
+
+impl FilterLimits {
+    /// No limits at all
+    pub const UNLIMITED: FilterLimits = FilterLimits {
+        max_filters: 0,
+        max_tag_filters: 0,
+        max_filter_values: 0,
+    };
+
+    /// Parse limits embedded by the CLI; missing fields keep their defaults
+    pub fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).unwrap_or_default()
+    }
+
+    /// Check the filters of a REQ/COUNT against the limits
+    pub fn check(&self, filters: &[Value]) -> Result<(), CassetteError> {
+        if exceeds(filters.len(), self.max_filters) {
+            return Err(CassetteError::Restricted(format!("too many filters ({} > {})", filters.len(), self.max_filters)));
+        }
+
+        for filter in filters.iter().filter_map(|f| f.as_object()) {
+            let tag_filters = filter.keys().filter(|k| k.starts_with('#')).count();
+            if exceeds(tag_filters, self.max_tag_filters) {
+                return Err(CassetteError::Restricted(format!(
+                    "too many tag filters ({} > {})",
+                    tag_filters, self.max_tag_filters
+                )));
+            }
+
+            for (key, value) in filter {
+                if let Some(values) = value.as_array() {
+                    if exceeds(values.len(), self.max_filter_values) {
+                        return Err(CassetteError::Restricted(format!(
+                            "too many values in '{}' ({} > {})",
+                            key, values.len(), self.max_filter_values
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The `CLOSED` message for a REQ/COUNT that exceeds a limit, if any
+    pub fn closed_message(&self, subscription_id: &str, filters: &[Value]) -> Option<String> {
+        self.check(filters)
+            .err()
+            .map(|e| crate::messages::closed(subscription_id, &e.reason()).to_string())
+    }
+
+    /// Merge the enabled limits into a NIP-11 `limitation` object
+    pub fn advertise(&self, limitation: &mut Map<String, Value>) {
+        for (key, limit) in [
+            ("max_filters", self.max_filters),
+            ("max_tag_filters", self.max_tag_filters),
+            ("max_filter_values", self.max_filter_values),
+        ] {
+            if limit > 0 {
+                limitation.insert(key.to_string(), json!(limit));
+            } else {
+                limitation.remove(key);
+            }
+        }
+    }
+}
+
+fn exceeds(count: usize, limit: usize) -> bool {
+    limit > 0 && count > limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_limits() {
+        let limits = FilterLimits { max_filters: 2, max_tag_filters: 1, max_filter_values: 3 };
+
+        assert!(limits.check(&[json!({"kinds": [1, 2, 3]}), json!({"#e": ["a"]})]).is_ok());
+        assert!(limits.check(&[json!({}), json!({}), json!({})]).unwrap_err().to_string().contains("too many filters"));
+        assert!(limits.check(&[json!({"#e": ["a"], "#p": ["b"]})]).unwrap_err().to_string().contains("tag filters"));
+        assert!(limits.check(&[json!({"authors": ["a", "b", "c", "d"]})]).unwrap_err().to_string().contains("'authors'"));
+        assert!(FilterLimits::UNLIMITED.check(&vec![json!({"kinds": vec![1; 5000]}); 50]).is_ok());
+
+        let closed = limits.closed_message("sub", &[json!({}), json!({}), json!({})]).unwrap();
+        assert!(closed.starts_with(r#"["CLOSED","sub","restricted:"#));
+
+        let parsed = FilterLimits::from_json(r#"{"max_filters": 0}"#);
+        assert_eq!(parsed, FilterLimits { max_filters: 0, ..FilterLimits::default() });
+
+        let mut limitation = Map::new();
+        parsed.advertise(&mut limitation);
+        assert_eq!(Value::Object(limitation), json!({"max_tag_filters": 10, "max_filter_values": 1000}));
+    }
+}
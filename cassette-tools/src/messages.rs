@@ -0,0 +1,33 @@
+//! Relay-to-client `CLOSED` and `OK` messages (NIP-01)
+//!
+//! `CLOSED` ends a subscription from the relay side, for a REQ or COUNT the
+//! cassette won't or can't answer, or to confirm a CLOSE. `OK` answers an
+//! EVENT. Both carry a reason starting with one of the NIP-01 prefixes
+//! (`invalid:`, `error:`, `blocked:`, `restricted:`, `duplicate:`, ...); an
+//! empty reason is fine for a plain CLOSE or an accepted event.
+
+use serde_json::{json, Value};
+
+/// Why a cassette that doesn't accept writes rejects an EVENT
+pub const READ_ONLY: &str = "blocked: this cassette is read-only";
+
+/// `["CLOSED", <subscription id>, <reason>]`
+pub fn closed(subscription_id: &str, reason: &str) -> Value {
+    json!(["CLOSED", subscription_id, reason])
+}
+
+/// `["OK", <event id>, <accepted>, <message>]`
+pub fn ok(event_id: &str, accepted: bool, message: &str) -> Value {
+    json!(["OK", event_id, accepted, message])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(closed("sub", "invalid: bad filter").to_string(), r#"["CLOSED","sub","invalid: bad filter"]"#);
+        assert_eq!(ok("e1", false, "blocked: read-only").to_string(), r#"["OK","e1",false,"blocked: read-only"]"#);
+    }
+}
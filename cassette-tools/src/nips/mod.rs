@@ -5,9 +5,15 @@
 
 
 // Optional NIPs (feature-gated)
+#[cfg(feature = "nip09")]
+pub mod nip09;
+
 #[cfg(feature = "nip11")]
 pub mod nip11;
 
+#[cfg(feature = "nip40")]
+pub mod nip40;
+
 #[cfg(feature = "nip42")]
 pub mod nip42;
 
@@ -21,9 +27,15 @@ pub mod nip50;
 pub fn build_supported_nips() -> Vec<u32> {
     let mut nips = vec![1]; // Always support NIP-01 (built into RelayHandler)
     
+    #[cfg(feature = "nip09")]
+    nips.push(9);
+    
     #[cfg(feature = "nip11")]
     nips.push(11);
     
+    #[cfg(feature = "nip40")]
+    nips.push(40);
+    
     #[cfg(feature = "nip42")]
     nips.push(42);
     
@@ -40,8 +52,12 @@ pub fn build_supported_nips() -> Vec<u32> {
 pub fn supports_nip(nip: u32) -> bool {
     match nip {
         1 => true, // NIP-01 is always supported
+        #[cfg(feature = "nip09")]
+        9 => true,
         #[cfg(feature = "nip11")]
         11 => true,
+        #[cfg(feature = "nip40")]
+        40 => true,
         #[cfg(feature = "nip42")]
         42 => true,
         #[cfg(feature = "nip45")]
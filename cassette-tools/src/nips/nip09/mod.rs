@@ -0,0 +1,116 @@
+//! NIP-09: Event deletion requests
+//!
+//! A kind 5 event asks for the events it references to be deleted: `e` tags
+//! name events by id, `a` tags name addressable events by `kind:pubkey:d-tag`
+//! and cover every version up to the deletion's `created_at`. Only the author
+//! of an event can delete it, and deletion requests themselves can't be
+//! deleted. The CLI drops deleted events when it records a cassette; cassettes
+//! check again at query time so events deleted by a later dub stay hidden.
+
+use crate::filter::Event;
+use std::collections::{HashMap, HashSet};
+
+/// Kind of a deletion request
+pub const DELETION_KIND: i64 = 5;
+
+/// The deletions requested by a set of events
+#[derive(Debug, Clone, Default)]
+pub struct Deletions {
+    /// (author, event id) pairs
+    ids: HashSet<(String, String)>,
+    /// Address -> newest deletion timestamp; the author is part of the address
+    addresses: HashMap<String, i64>,
+}
+
+impl Deletions {
+    /// Collect the `e` and `a` references of every kind 5 event
+    pub fn from_events<'a, E: Event + 'a>(events: impl IntoIterator<Item = &'a E>) -> Self {
+        let mut deletions = Self::default();
+        for event in events {
+            let (Some(DELETION_KIND), Some(author)) = (event.kind(), event.pubkey()) else {
+                continue;
+            };
+            for id in event.tag_values("e") {
+                deletions.ids.insert((author.to_string(), id.to_string()));
+            }
+            let deleted_at = event.created_at().unwrap_or(i64::MAX);
+            for address in event.tag_values("a") {
+                // Authors can only delete their own addressable events
+                if address.split(':').nth(1) != Some(author) {
+                    continue;
+                }
+                let newest = deletions.addresses.entry(address.to_string()).or_insert(deleted_at);
+                *newest = (*newest).max(deleted_at);
+            }
+        }
+        deletions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.addresses.is_empty()
+    }
+
+    /// Whether the event's author asked for it to be deleted
+    pub fn is_deleted<E: Event + ?Sized>(&self, event: &E) -> bool {
+        let (Some(kind), Some(author)) = (event.kind(), event.pubkey()) else {
+            return false;
+        };
+        if kind == DELETION_KIND || self.is_empty() {
+            return false;
+        }
+
+        if let Some(id) = event.id() {
+            if self.ids.contains(&(author.to_string(), id.to_string())) {
+                return true;
+            }
+        }
+
+        let d_tag = event.tag_values("d").first().copied().unwrap_or("");
+        let address = format!("{}:{}:{}", kind, author, d_tag);
+        match (self.addresses.get(&address), event.created_at()) {
+            (Some(deleted_at), Some(created_at)) => created_at <= *deleted_at,
+            _ => false,
+        }
+    }
+}
+
+/// Drop the events deleted by kind 5 events in the same set, keeping the deletion requests
+pub fn remove_deleted<E: Event>(events: Vec<E>) -> Vec<E> {
+    let deletions = Deletions::from_events(&events);
+    if deletions.is_empty() {
+        return events;
+    }
+    events.into_iter().filter(|event| !deletions.is_deleted(event)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_deletions() {
+        let events = vec![
+            json!({"id": "note", "pubkey": "alice", "kind": 1, "created_at": 10, "tags": []}),
+            json!({"id": "other", "pubkey": "bob", "kind": 1, "created_at": 10, "tags": []}),
+            json!({"id": "article-v1", "pubkey": "alice", "kind": 30023, "created_at": 10, "tags": [["d", "post"]]}),
+            json!({"id": "article-v2", "pubkey": "alice", "kind": 30023, "created_at": 30, "tags": [["d", "post"]]}),
+            json!({"id": "del", "pubkey": "alice", "kind": 5, "created_at": 20, "tags": [
+                ["e", "note"], ["e", "other"], ["a", "30023:alice:post"], ["a", "1:bob:"]
+            ]}),
+            json!({"id": "del-del", "pubkey": "alice", "kind": 5, "created_at": 25, "tags": [["e", "del"]]}),
+        ];
+
+        let deletions = Deletions::from_events(&events);
+        let deleted = |id: &str| deletions.is_deleted(events.iter().find(|e| e["id"] == id).unwrap());
+        assert!(deleted("note"));
+        assert!(!deleted("other"), "only the author can delete an event");
+        assert!(deleted("article-v1"));
+        assert!(!deleted("article-v2"), "versions newer than the deletion stay");
+        assert!(!deleted("del"), "deletion requests can't be deleted");
+
+        let kept: Vec<Value> = remove_deleted(events);
+        let ids: Vec<&str> = kept.iter().map(|e| e["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["other", "article-v2", "del", "del-del"]);
+    }
+}
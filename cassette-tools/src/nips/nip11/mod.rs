@@ -1,8 +1,14 @@
 //! NIP-11: Relay Information Document
 //! 
-//! Provides relay metadata and capability discovery through supported_nips
+//! Provides relay metadata and capability discovery through supported_nips.
+//! A cassette builds its document from what was embedded at record time;
+//! `supported_nips`, `software` and `version` always come from the build.
+//! Hosts can add or replace the other fields when loading a cassette by
+//! passing a [`RelayInfo`] to the `set_info` export, which [`RelayInfo::overlay`]
+//! applies on top.
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 /// Relay information structure as defined by NIP-11
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -24,6 +30,7 @@ pub struct RelayInfo {
     pub contact: Option<String>,
     
     /// List of supported NIPs
+    #[serde(default)]
     pub supported_nips: Vec<u32>,
     
     /// Software identifier
@@ -53,6 +60,52 @@ pub struct RelayInfo {
     /// Relay icon URL
     #[serde(skip_serializing_if = "Option::is_none")]
     pub icon: Option<String>,
+    
+    /// ISO 3166-1 alpha-2 codes of the countries whose laws may affect the relay
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_countries: Option<Vec<String>>,
+    
+    /// IETF language tags of the content the relay is meant for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_tags: Option<Vec<String>>,
+    
+    /// URL of the relay's posting policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub posting_policy: Option<String>,
+    
+    /// URL where the relay's fees are paid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payments_url: Option<String>,
+    
+    /// Admission, subscription and publication fees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fees: Option<Fees>,
+}
+
+impl RelayInfo {
+    /// Copy the fields set here over a NIP-11 document
+    ///
+    /// `supported_nips`, `software` and `version` are left as they are, and a
+    /// `limitation` is merged into the document's one key by key.
+    pub fn overlay(&self, document: &mut Map<String, Value>) {
+        let Ok(Value::Object(fields)) = serde_json::to_value(self) else {
+            return;
+        };
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("supported_nips" | "software" | "version", _) => {}
+                ("limitation", Value::Object(limits)) => {
+                    let limitation = document.entry(key).or_insert_with(|| Value::Object(Map::new()));
+                    if let Some(limitation) = limitation.as_object_mut() {
+                        limitation.extend(limits);
+                    }
+                }
+                (_, value) => {
+                    document.insert(key, value);
+                }
+            }
+        }
+    }
 }
 
 /// Relay limitation parameters
@@ -70,6 +123,14 @@ pub struct RelayLimitation {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_limit: Option<u32>,
     
+    /// Maximum tag filters per filter (see `FilterLimits`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tag_filters: Option<u32>,
+    
+    /// Maximum values in any filter list (see `FilterLimits`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_filter_values: Option<u32>,
+    
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_subid_length: Option<u32>,
     
@@ -93,14 +154,22 @@ pub struct RelayLimitation {
     
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at_upper_limit: Option<i64>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restricted_writes: Option<bool>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_limit: Option<u32>,
 }
 
 /// Event retention policy
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RetentionPolicy {
+    /// The kinds the policy applies to (all when missing)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub kinds: Option<Vec<u32>>,
+    pub kinds: Option<Vec<RetainedKinds>>,
     
+    /// Seconds events are kept; 0 means they aren't stored
     #[serde(skip_serializing_if = "Option::is_none")]
     pub time: Option<u32>,
     
@@ -108,6 +177,14 @@ pub struct RetentionPolicy {
     pub count: Option<u32>,
 }
 
+/// A kind, or an inclusive `[first, last]` range of kinds, in a retention policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RetainedKinds {
+    Kind(u32),
+    Range([u32; 2]),
+}
+
 /// Payment information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentsInfo {
@@ -115,35 +192,53 @@ pub struct PaymentsInfo {
     pub url: Option<String>,
     
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub amount: Option<PaymentAmount>,
+    pub amount: Option<Fees>,
 }
 
-/// Payment amount structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PaymentAmount {
+/// The NIP-11 `fees` object
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fees {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub admission: Option<Vec<PaymentOption>>,
+    pub admission: Option<Vec<Fee>>,
     
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub subscription: Option<Vec<PaymentOption>>,
+    pub subscription: Option<Vec<Fee>>,
     
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub publication: Option<Vec<PaymentOption>>,
+    pub publication: Option<Vec<Fee>>,
 }
 
-/// Individual payment option
+/// One fee, e.g. `{"amount": 1000, "unit": "msats", "period": 2628003}`
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PaymentOption {
+pub struct Fee {
     pub amount: u64,
     pub unit: String,
     
+    /// Seconds a subscription lasts
     #[serde(skip_serializing_if = "Option::is_none")]
     pub period: Option<u32>,
+    
+    /// Kinds a publication fee applies to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<Vec<u32>>,
 }
 
+/// Former name of [`Fees`]
+pub type PaymentAmount = Fees;
+
+/// Former name of [`Fee`]
+pub type PaymentOption = Fee;
+
 /// Static relay info storage for WASM cassettes
 pub static mut RELAY_INFO_JSON: Option<String> = None;
 
+/// The relay info the host passed to `set_info`, if any
+pub fn host_info() -> Option<RelayInfo> {
+    // Cassettes are single-threaded and only set_info writes it
+    let json = unsafe { (*std::ptr::addr_of!(RELAY_INFO_JSON)).clone() }?;
+    serde_json::from_str(&json).ok()
+}
+
 /// Initialize relay info from JSON string (called by CLI)
 #[no_mangle]
 pub extern "C" fn set_info(json_ptr: *const u8, json_len: usize) -> i32 {
@@ -176,6 +271,8 @@ impl Default for RelayLimitation {
             max_subscriptions: Some(20),
             max_filters: Some(10),
             max_limit: Some(1000),
+            max_tag_filters: Some(10),
+            max_filter_values: Some(1000),
             max_subid_length: Some(100),
             max_event_tags: Some(100),
             max_content_length: Some(65536),
@@ -184,6 +281,8 @@ impl Default for RelayLimitation {
             payment_required: Some(false),
             created_at_lower_limit: None,
             created_at_upper_limit: None,
+            restricted_writes: None,
+            default_limit: None,
         }
     }
 }
@@ -198,6 +297,14 @@ pub struct RelayInfoBuilder {
     pub supported_nips: Vec<u32>,
     pub software: Option<String>,
     pub version: Option<String>,
+    pub limitation: Option<RelayLimitation>,
+    pub retention: Option<Vec<RetentionPolicy>>,
+    pub icon: Option<String>,
+    pub relay_countries: Option<Vec<String>>,
+    pub language_tags: Option<Vec<String>>,
+    pub posting_policy: Option<String>,
+    pub payments_url: Option<String>,
+    pub fees: Option<Fees>,
 }
 
 impl RelayInfoBuilder {
@@ -230,6 +337,57 @@ impl RelayInfoBuilder {
         self
     }
 
+    /// Replace the default limitation
+    pub fn with_limitation(mut self, limitation: RelayLimitation) -> Self {
+        self.limitation = Some(limitation);
+        self
+    }
+
+    pub fn with_retention(mut self, retention: Vec<RetentionPolicy>) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn with_icon(mut self, icon: String) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    pub fn with_relay_countries(mut self, countries: Vec<String>) -> Self {
+        self.relay_countries = Some(countries);
+        self
+    }
+
+    pub fn with_language_tags(mut self, language_tags: Vec<String>) -> Self {
+        self.language_tags = Some(language_tags);
+        self
+    }
+
+    pub fn with_posting_policy(mut self, posting_policy: String) -> Self {
+        self.posting_policy = Some(posting_policy);
+        self
+    }
+
+    pub fn with_payments_url(mut self, payments_url: String) -> Self {
+        self.payments_url = Some(payments_url);
+        self
+    }
+
+    pub fn with_fees(mut self, fees: Fees) -> Self {
+        self.fees = Some(fees);
+        self
+    }
+
+    pub fn with_software(mut self, software: String) -> Self {
+        self.software = Some(software);
+        self
+    }
+
+    pub fn with_version(mut self, version: String) -> Self {
+        self.version = Some(version);
+        self
+    }
+
     pub fn build(self) -> RelayInfo {
         RelayInfo {
             name: self.name,
@@ -239,15 +397,68 @@ impl RelayInfoBuilder {
             supported_nips: self.supported_nips,
             software: self.software,
             version: self.version,
-            limitation: Some(RelayLimitation::default()),
-            retention: None,
+            limitation: Some(self.limitation.unwrap_or_default()),
+            retention: self.retention,
             relay_urls: None,
             payments_info: None,
-            icon: None,
+            icon: self.icon,
+            relay_countries: self.relay_countries,
+            language_tags: self.language_tags,
+            posting_policy: self.posting_policy,
+            payments_url: self.payments_url,
+            fees: self.fees,
         }
     }
 }
 
 // Export function for WASM cassettes to provide relay information
 // Note: This function is disabled in favor of the template's custom info function
-// which includes embedded relay metadata
\ No newline at end of file
+// which includes embedded relay metadata
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_relay_info() {
+        let info = RelayInfoBuilder::new()
+            .with_name("archive".to_string())
+            .with_limitation(RelayLimitation { max_limit: Some(500), ..RelayLimitation::default() })
+            .with_retention(vec![RetentionPolicy {
+                kinds: Some(vec![RetainedKinds::Kind(1), RetainedKinds::Range([40, 49])]),
+                time: Some(3600),
+                count: None,
+            }])
+            .with_relay_countries(vec!["CA".to_string()])
+            .with_fees(serde_json::from_value(json!({"admission": [{"amount": 1000, "unit": "msats"}]})).unwrap())
+            .build();
+        let document = serde_json::to_value(&info).unwrap();
+        assert_eq!(document["retention"], json!([{"kinds": [1, [40, 49]], "time": 3600}]));
+        assert_eq!(document["relay_countries"], json!(["CA"]));
+        assert_eq!(document["fees"], json!({"admission": [{"amount": 1000, "unit": "msats"}]}));
+        assert_eq!(document["limitation"]["max_limit"], 500);
+
+        // Host-supplied fields go over the built document, except what the build decides
+        let host: RelayInfo = serde_json::from_value(json!({
+            "name": "renamed",
+            "software": "elsewhere",
+            "limitation": {"auth_required": true},
+            "posting_policy": "https://example.com/policy"
+        })).unwrap();
+        let mut document = json!({
+            "name": "archive",
+            "software": "@sandwichfarm/cassette",
+            "supported_nips": [1, 11],
+            "limitation": {"max_filters": 10}
+        });
+        host.overlay(document.as_object_mut().unwrap());
+        assert_eq!(document, json!({
+            "name": "renamed",
+            "software": "@sandwichfarm/cassette",
+            "supported_nips": [1, 11],
+            "limitation": {"max_filters": 10, "auth_required": true},
+            "posting_policy": "https://example.com/policy"
+        }));
+    }
+}
@@ -0,0 +1,80 @@
+//! NIP-40: Expiration timestamp
+//!
+//! An event tagged `["expiration", "<unix seconds>"]` is not served once that
+//! time has passed. Cassettes keep expired events and hide them at query time,
+//! so the same cassette answers differently as its events expire. The
+//! `wasm32-unknown-unknown` target has no clock: hosts pass the time in through
+//! the cassette's `set_current_time` export before querying, and until they do
+//! nothing is treated as expired. Native code falls back to the system clock.
+
+use crate::filter::Event;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// `CURRENT_TIME` before the host sets it
+const UNSET: i64 = i64::MIN;
+
+static CURRENT_TIME: AtomicI64 = AtomicI64::new(UNSET);
+
+/// Set the time, in unix seconds, that expirations are compared against
+pub fn set_current_time(now: i64) {
+    CURRENT_TIME.store(now, Ordering::Relaxed);
+}
+
+/// The time set by the host, else the system clock where there is one
+pub fn current_time() -> Option<i64> {
+    match CURRENT_TIME.load(Ordering::Relaxed) {
+        UNSET => system_time(),
+        now => Some(now),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn system_time() -> Option<i64> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_secs() as i64)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn system_time() -> Option<i64> {
+    None
+}
+
+/// The event's expiration timestamp, if it has a valid one
+pub fn expiration<E: Event + ?Sized>(event: &E) -> Option<i64> {
+    event.tag_values("expiration").first()?.trim().parse().ok()
+}
+
+/// Whether the event has expired at `now`
+pub fn is_expired<E: Event + ?Sized>(event: &E, now: i64) -> bool {
+    expiration(event).is_some_and(|expires_at| expires_at <= now)
+}
+
+/// Whether the event has expired at the current time; never when the time is unknown
+pub fn is_expired_now<E: Event + ?Sized>(event: &E) -> bool {
+    current_time().is_some_and(|now| is_expired(event, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_expiration() {
+        let expiring = json!({"kind": 1, "tags": [["expiration", "1700000000"]]});
+        assert_eq!(expiration(&expiring), Some(1700000000));
+        assert!(!is_expired(&expiring, 1699999999));
+        assert!(is_expired(&expiring, 1700000000));
+
+        let permanent = json!({"kind": 1, "tags": [["t", "nostr"]]});
+        assert!(!is_expired(&permanent, i64::MAX));
+        let malformed = json!({"kind": 1, "tags": [["expiration", "soon"]]});
+        assert_eq!(expiration(&malformed), None);
+        assert!(!is_expired(&malformed, i64::MAX));
+
+        assert!(current_time().is_some(), "native builds fall back to the system clock");
+        assert!(is_expired_now(&expiring));
+    }
+}
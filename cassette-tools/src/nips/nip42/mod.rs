@@ -1,10 +1,137 @@
 //! NIP-42: Authentication of clients to relays
 //! 
 //! This module implements authentication support for cassettes
+//!
+//! A relay that wants clients to authenticate sends `["AUTH", <challenge>]`
+//! when they connect. The client answers with `["AUTH", <event>]`, a signed
+//! kind 22242 event carrying the challenge and the relay's URL in its tags.
+//! [`AuthSession`] holds one connection's side of this exchange: it checks
+//! AUTH events with [`validate_auth_event`], remembers the pubkeys that
+//! authenticated, and closes REQs with `auth-required:` until one has.
 
+use crate::error::CassetteError;
+use crate::event::NostrEvent;
+use crate::filter::Event;
+use crate::messages;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
+/// Kind of the event a client authenticates with
+pub const AUTH_KIND: i64 = 22242;
+
+/// How far, in seconds, an AUTH event's `created_at` may be from the current time
+pub const DEFAULT_MAX_AGE: i64 = 600;
+
+/// Check an AUTH event against the challenge sent on this connection and the
+/// URL of this relay. The signature is checked with the `verify` feature;
+/// without it only the id is.
+pub fn validate_auth_event(
+    event: &NostrEvent,
+    challenge: &str,
+    relay_url: &str,
+    now: i64,
+    max_age: i64,
+) -> Result<(), CassetteError> {
+    let invalid = |reason: &str| Err(CassetteError::InvalidEvent(reason.to_string()));
+
+    if event.kind != AUTH_KIND {
+        return invalid("auth event must be kind 22242");
+    }
+    if !event.verify_id() {
+        return invalid("event id does not match its content");
+    }
+    #[cfg(feature = "verify")]
+    event.verify_signature()?;
+
+    if event.tag_values("challenge").first() != Some(&challenge) {
+        return invalid("challenge tag does not match");
+    }
+    let relay = event.tag_values("relay").first().map(|url| normalize_relay_url(url));
+    if relay.as_deref() != Some(normalize_relay_url(relay_url).as_str()) {
+        return invalid("relay tag does not name this relay");
+    }
+    if (event.created_at - now).abs() > max_age {
+        return invalid("created_at is too far from the current time");
+    }
+    Ok(())
+}
+
+/// Relay URLs compare without case and without a trailing slash
+fn normalize_relay_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_lowercase()
+}
+
+/// The authentication state of one client connection
+#[derive(Debug, Clone)]
+pub struct AuthSession {
+    challenge: String,
+    relay_url: String,
+    max_age: i64,
+    pubkeys: Vec<String>,
+}
+
+impl AuthSession {
+    /// A session for a connection that was sent `challenge` by the relay at `relay_url`
+    pub fn new(challenge: &str, relay_url: &str) -> Self {
+        Self {
+            challenge: challenge.to_string(),
+            relay_url: relay_url.to_string(),
+            max_age: DEFAULT_MAX_AGE,
+            pubkeys: Vec::new(),
+        }
+    }
+
+    /// Accept AUTH events up to `seconds` away from the current time
+    pub fn with_max_age(mut self, seconds: i64) -> Self {
+        self.max_age = seconds;
+        self
+    }
+
+    /// `["AUTH", <challenge>]`, sent when the client connects
+    pub fn challenge_message(&self) -> Value {
+        json!(["AUTH", self.challenge])
+    }
+
+    /// Answer `["AUTH", <event>]` with an OK message, authenticating the
+    /// event's pubkey when it passes [`validate_auth_event`]
+    pub fn handle_auth(&mut self, message: &Value, now: i64) -> Value {
+        let event = message.get(1).cloned().unwrap_or(Value::Null);
+        let event_id = event.get("id").and_then(|id| id.as_str()).unwrap_or("").to_string();
+
+        let result = NostrEvent::from_value(&event).and_then(|event| {
+            validate_auth_event(&event, &self.challenge, &self.relay_url, now, self.max_age)?;
+            Ok(event.pubkey)
+        });
+        match result {
+            Ok(pubkey) => {
+                if !self.pubkeys.contains(&pubkey) {
+                    self.pubkeys.push(pubkey);
+                }
+                messages::ok(&event_id, true, "")
+            }
+            Err(e) => messages::ok(&event_id, false, &e.reason()),
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        !self.pubkeys.is_empty()
+    }
+
+    /// The pubkeys that authenticated on this connection, in order
+    pub fn pubkeys(&self) -> &[String] {
+        &self.pubkeys
+    }
+
+    /// Refuse a REQ or COUNT until the client has authenticated
+    pub fn check(&self) -> Result<(), CassetteError> {
+        if self.is_authenticated() {
+            Ok(())
+        } else {
+            Err(CassetteError::AuthRequired("this relay only serves authenticated clients".to_string()))
+        }
+    }
+}
+
 /// AUTH challenge from relay
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthChallenge {
@@ -103,4 +230,85 @@ pub extern "C" fn handle_auth(challenge_ptr: *const u8, challenge_len: usize) ->
             std::ptr::null_mut()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELAY: &str = "wss://cassettes.example.com";
+
+    fn auth_event(challenge: &str, relay: &str, created_at: i64) -> NostrEvent {
+        let mut event = NostrEvent {
+            id: String::new(),
+            pubkey: String::new(),
+            created_at,
+            kind: AUTH_KIND,
+            tags: vec![
+                vec!["relay".to_string(), relay.to_string()],
+                vec!["challenge".to_string(), challenge.to_string()],
+            ],
+            content: String::new(),
+            sig: String::new(),
+        };
+        sign(&mut event);
+        event
+    }
+
+    #[cfg(feature = "verify")]
+    fn sign(event: &mut NostrEvent) {
+        use k256::schnorr::{signature::hazmat::PrehashSigner, Signature, SigningKey};
+
+        let key = SigningKey::from_bytes(&[7u8; 32]).unwrap();
+        event.pubkey = hex::encode(key.verifying_key().to_bytes());
+        event.id = event.compute_id();
+        let signature: Signature = key.sign_prehash(&hex::decode(&event.id).unwrap()).unwrap();
+        event.sig = hex::encode(signature.to_bytes());
+    }
+
+    #[cfg(not(feature = "verify"))]
+    fn sign(event: &mut NostrEvent) {
+        event.pubkey = "a".repeat(64);
+        event.id = event.compute_id();
+    }
+
+    #[test]
+    fn test_validate_auth_event() {
+        let now = 1_700_000_000;
+        let valid = |event: &NostrEvent| validate_auth_event(event, "c1", RELAY, now, DEFAULT_MAX_AGE);
+
+        assert!(valid(&auth_event("c1", RELAY, now)).is_ok());
+        assert!(valid(&auth_event("c1", "WSS://cassettes.example.com/", now - 60)).is_ok());
+        assert!(valid(&auth_event("c2", RELAY, now)).is_err(), "another connection's challenge");
+        assert!(valid(&auth_event("c1", "wss://elsewhere.example.com", now)).is_err());
+        assert!(valid(&auth_event("c1", RELAY, now - 3600)).is_err(), "too old");
+
+        let mut note = auth_event("c1", RELAY, now);
+        note.kind = 1;
+        sign(&mut note);
+        assert!(valid(&note).is_err());
+        let mut tampered = auth_event("c1", RELAY, now);
+        tampered.created_at += 1;
+        assert!(valid(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_auth_session() {
+        let now = 1_700_000_000;
+        let mut session = AuthSession::new("c1", RELAY);
+        assert_eq!(session.challenge_message(), json!(["AUTH", "c1"]));
+        assert_eq!(session.check().unwrap_err().reason(), "auth-required: this relay only serves authenticated clients");
+
+        let stale = auth_event("c1", RELAY, now - 3600);
+        let refused = session.handle_auth(&json!(["AUTH", stale]), now);
+        assert_eq!(refused[2], false);
+        assert!(refused[3].as_str().unwrap().starts_with("invalid:"));
+        assert!(!session.is_authenticated());
+
+        let event = auth_event("c1", RELAY, now);
+        assert_eq!(session.handle_auth(&json!(["AUTH", event]), now), json!(["OK", event.id, true, ""]));
+        assert!(session.check().is_ok());
+        assert_eq!(session.pubkeys(), std::slice::from_ref(&event.pubkey));
+        assert_eq!(session.handle_auth(&json!(["AUTH", "not an event"]), now)[2], false);
+    }
+}
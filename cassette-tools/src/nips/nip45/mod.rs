@@ -5,6 +5,7 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use crate::Filter;
 
 /// COUNT request
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,70 +84,10 @@ fn count_events_placeholder(filters: &[Value]) -> u64 {
     42
 }
 
-/// Count events with specific filtering logic
+/// Count events matching any of the filters, each event once
+///
+/// Filters that don't parse match nothing.
 pub fn count_events_with_filters(events: &[Value], filters: &[Value]) -> u64 {
-    let mut count = 0u64;
-    
-    for event in events {
-        for filter in filters {
-            if event_matches_filter(event, filter) {
-                count += 1;
-                break; // Count each event only once even if it matches multiple filters
-            }
-        }
-    }
-    
-    count
+    let filters: Vec<Filter> = filters.iter().filter_map(|f| Filter::from_json(f).ok()).collect();
+    events.iter().filter(|event| Filter::matches_any(&filters, *event)).count() as u64
 }
-
-/// Check if an event matches a filter
-fn event_matches_filter(event: &Value, filter: &Value) -> bool {
-    let filter_obj = match filter.as_object() {
-        Some(obj) => obj,
-        None => return false,
-    };
-    
-    // Check kinds
-    if let Some(kinds) = filter_obj.get("kinds").and_then(|k| k.as_array()) {
-        if let Some(event_kind) = event.get("kind").and_then(|k| k.as_i64()) {
-            if !kinds.iter().any(|k| k.as_i64() == Some(event_kind)) {
-                return false;
-            }
-        }
-    }
-    
-    // Check authors
-    if let Some(authors) = filter_obj.get("authors").and_then(|a| a.as_array()) {
-        if let Some(event_author) = event.get("pubkey").and_then(|p| p.as_str()) {
-            if !authors.iter().any(|a| {
-                if let Some(author_prefix) = a.as_str() {
-                    event_author.starts_with(author_prefix)
-                } else {
-                    false
-                }
-            }) {
-                return false;
-            }
-        }
-    }
-    
-    // Check timestamps
-    if let Some(since) = filter_obj.get("since").and_then(|s| s.as_i64()) {
-        if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-            if created_at < since {
-                return false;
-            }
-        }
-    }
-    
-    if let Some(until) = filter_obj.get("until").and_then(|u| u.as_i64()) {
-        if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-            if created_at > until {
-                return false;
-            }
-        }
-    }
-    
-    // All checks passed
-    true
-}
\ No newline at end of file
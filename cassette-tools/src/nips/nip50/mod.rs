@@ -1,10 +1,14 @@
 //! NIP-50: Search Capability
 //! 
-//! Provides search functionality for events using text queries
+//! Provides search functionality for events using text queries. Every term of
+//! a query has to match a token of the content (see [`crate::text_index`]),
+//! `term*` matches by prefix, and results are ranked by relevance unless the
+//! query asks for `order:created_at`.
 
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use std::collections::HashMap;
+use crate::text_index::{term_matches, tokenize};
+use crate::Filter;
 
 /// Search filter extensions as defined by NIP-50
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,12 +32,16 @@ pub struct SearchExtensions {
     /// Include/exclude NSFW content
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nsfw: Option<bool>,
+    
+    /// Result order: "relevance" (the default) or "created_at" (newest first)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
 }
 
 /// Parsed search query with extensions
 #[derive(Debug, Clone)]
 pub struct SearchQuery {
-    /// Main search terms
+    /// Main search terms, as content tokens; a trailing `*` matches by prefix
     pub terms: Vec<String>,
     /// Search extensions
     pub extensions: SearchExtensions,
@@ -57,6 +65,7 @@ pub fn parse_search_query(query: &str) -> SearchQuery {
         language: None,
         sentiment: None,
         nsfw: None,
+        order: None,
     };
     
     // Split query into words and handle extensions
@@ -83,55 +92,76 @@ pub fn parse_search_query(query: &str) -> SearchQuery {
             if let Some(nsfw_str) = word.strip_prefix("nsfw:") {
                 extensions.nsfw = nsfw_str.parse().ok();
             }
+        } else if word.starts_with("order:") {
+            if let Some(order) = word.strip_prefix("order:") {
+                if ["relevance", "created_at"].contains(&order) {
+                    extensions.order = Some(order.to_string());
+                }
+            }
         } else {
-            // Regular search term
-            terms.push(word.to_lowercase());
+            // Regular search term, split into tokens the way content is
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(word) => (word, true),
+                None => (word, false),
+            };
+            let mut tokens = tokenize(word);
+            if prefix {
+                if let Some(last) = tokens.last_mut() {
+                    last.push('*');
+                }
+            }
+            terms.extend(tokens);
         }
     }
     
     SearchQuery { terms, extensions }
 }
 
+impl SearchQuery {
+    /// Whether results are ordered newest first rather than by relevance
+    pub fn by_created_at(&self) -> bool {
+        self.extensions.order.as_deref() == Some("created_at")
+    }
+}
+
 /// Score an event against search terms
 pub fn score_event(event: &Value, query: &SearchQuery) -> f32 {
+    score_content(event.get("content").and_then(|c| c.as_str()).unwrap_or(""), query)
+}
+
+/// Score event content against search terms
+///
+/// Content scores 0 unless every term matches one of its tokens. Each token
+/// equal to a term counts 1 and each one only sharing a `term*` prefix counts
+/// 0.5, and the sum is divided by the square root of the number of tokens so
+/// short, focused notes rank above long ones mentioning the terms in passing.
+pub fn score_content(content: &str, query: &SearchQuery) -> f32 {
     let mut score = 0.0;
     
     if query.terms.is_empty() {
         return 0.0;
     }
     
-    // Get event content for searching
-    let content = event.get("content")
-        .and_then(|c| c.as_str())
-        .unwrap_or("")
-        .to_lowercase();
+    let tokens = tokenize(content);
     
-    // Simple scoring: count term matches in content
     for term in &query.terms {
-        let matches = content.matches(term).count() as f32;
-        score += matches;
-        
-        // Bonus for exact phrase matches
-        if content.contains(term) {
-            score += 0.5;
-        }
-        
-        // Bonus for matches at word boundaries
-        let words: Vec<&str> = content.split_whitespace().collect();
-        for word in words {
-            if word == term {
-                score += 1.0;
-            } else if word.contains(term) {
-                score += 0.3;
-            }
+        let exact = term.trim_end_matches('*');
+        let term_score: f32 = tokens.iter()
+            .filter(|token| term_matches(term, token))
+            .map(|token| if token.as_str() == exact { 1.0 } else { 0.5 })
+            .sum();
+        if term_score == 0.0 {
+            return 0.0;
         }
+        score += term_score;
     }
+    score /= (tokens.len() as f32).sqrt();
     
     // Apply extension filters (return 0 if doesn't match)
     if let Some(ref domain) = query.extensions.domain {
         // Check NIP-05 domain - would need additional metadata
         // For now, skip events that don't match (simplified)
-        if !content.contains(domain) {
+        if !content.to_lowercase().contains(domain) {
             return 0.0;
         }
     }
@@ -164,8 +194,13 @@ pub fn search_events(events: &[Value], query_str: &str, limit: Option<usize>) ->
         }
     }
     
-    // Sort by score (descending)
-    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    // Sort by score (descending), or newest first when asked
+    if query.by_created_at() {
+        results.sort_by(|a, b| crate::newest_first(&a.event, &b.event));
+    } else {
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| crate::newest_first(&a.event, &b.event)));
+    }
     
     // Apply limit and return events
     let limited_results = if let Some(limit) = limit {
@@ -186,70 +221,24 @@ pub fn handle_search(events: &[Value], filters: &[Value]) -> String {
     let mut all_results = Vec::new();
     
     for filter in filters {
-        if let Some(filter_obj) = filter.as_object() {
-            // Check if this filter has a search field
-            if let Some(search_query) = filter_obj.get("search").and_then(|s| s.as_str()) {
-                // Get limit if specified
-                let limit = filter_obj.get("limit").and_then(|l| l.as_u64()).map(|l| l as usize);
-                
-                // Filter events by other criteria first (kinds, authors, etc.)
-                let mut filtered_events = events.to_vec();
-                
-                // Apply kinds filter
-                if let Some(kinds) = filter_obj.get("kinds").and_then(|k| k.as_array()) {
-                    let kind_numbers: Vec<u64> = kinds.iter()
-                        .filter_map(|k| k.as_u64())
-                        .collect();
-                    filtered_events.retain(|event| {
-                        if let Some(kind) = event.get("kind").and_then(|k| k.as_u64()) {
-                            kind_numbers.contains(&kind)
-                        } else {
-                            false
-                        }
-                    });
-                }
-                
-                // Apply authors filter
-                if let Some(authors) = filter_obj.get("authors").and_then(|a| a.as_array()) {
-                    let author_pubkeys: Vec<String> = authors.iter()
-                        .filter_map(|a| a.as_str())
-                        .map(|s| s.to_string())
-                        .collect();
-                    filtered_events.retain(|event| {
-                        if let Some(pubkey) = event.get("pubkey").and_then(|p| p.as_str()) {
-                            author_pubkeys.iter().any(|author| pubkey.starts_with(author))
-                        } else {
-                            false
-                        }
-                    });
-                }
-                
-                // Apply time filters
-                if let Some(since) = filter_obj.get("since").and_then(|s| s.as_u64()) {
-                    filtered_events.retain(|event| {
-                        if let Some(created_at) = event.get("created_at").and_then(|t| t.as_u64()) {
-                            created_at >= since
-                        } else {
-                            false
-                        }
-                    });
-                }
-                
-                if let Some(until) = filter_obj.get("until").and_then(|u| u.as_u64()) {
-                    filtered_events.retain(|event| {
-                        if let Some(created_at) = event.get("created_at").and_then(|t| t.as_u64()) {
-                            created_at <= until
-                        } else {
-                            false
-                        }
-                    });
-                }
-                
-                // Perform search on filtered events
-                let search_results = search_events(&filtered_events, search_query, limit);
-                all_results.extend(search_results);
-            }
-        }
+        let filter = match Filter::from_json(filter) {
+            Ok(filter) => filter,
+            Err(e) => return json!(["NOTICE", format!("Invalid filter: {}", e)]).to_string(),
+        };
+        // Only filters with a search field take part
+        let Some(search_query) = &filter.search else {
+            continue;
+        };
+        
+        // Narrow by the other criteria (kinds, authors, tags, time) first
+        let filtered_events: Vec<Value> = events.iter()
+            .filter(|event| filter.matches(*event))
+            .cloned()
+            .collect();
+        
+        // Perform search on filtered events
+        let search_results = search_events(&filtered_events, search_query, filter.limit);
+        all_results.extend(search_results);
     }
     
     // Remove duplicates (in case multiple filters matched the same event)
@@ -280,6 +269,22 @@ mod tests {
         let query = parse_search_query("bitcoin lightning domain:example.com");
         assert_eq!(query.terms, vec!["bitcoin", "lightning"]);
         assert_eq!(query.extensions.domain, Some("example.com".to_string()));
+        
+        let query = parse_search_query("Zap* e-cash order:created_at");
+        assert_eq!(query.terms, vec!["zap*", "e", "cash"]);
+        assert!(query.by_created_at());
+    }
+    
+    #[test]
+    fn test_score_content() {
+        let query = parse_search_query("bitcoin light*");
+        assert_eq!(score_content("bitcoin only", &query), 0.0);
+        assert_eq!(score_content("bitcoiners like lightning", &query), 0.0);
+        
+        let focused = score_content("Bitcoin lightning", &query);
+        let exact = score_content("Bitcoin light", &query);
+        let rambling = score_content("so I was thinking about bitcoin and the lightning network today", &query);
+        assert!(exact > focused && focused > rambling && rambling > 0.0);
     }
     
     #[test]
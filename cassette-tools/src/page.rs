@@ -0,0 +1,62 @@
+//! Cursor-based pages of REQ results
+//!
+//! Cassettes stream a REQ one message per call until EOSE. For very large
+//! result sets hosts can call the `req_page(ptr, len, cursor, page_size)`
+//! export instead, which answers with a [`Page`]: up to `page_size` events
+//! starting at `cursor`, and the cursor of the page after it. Cursors are
+//! offsets into the results in the order the cassette serves them, so the
+//! first page is at 0 and the last page has no next cursor.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Events per page when the host asks for a page size of 0
+pub const DEFAULT_PAGE_SIZE: usize = 500;
+
+/// One page of a REQ's results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    pub events: Vec<Value>,
+    /// Where the next page starts; `None` on the last page
+    pub next_cursor: Option<u64>,
+}
+
+impl Page {
+    /// The page of `results` starting at `cursor`
+    pub fn of<E: Serialize>(results: &[E], cursor: u64, page_size: usize) -> Self {
+        let page_size = if page_size == 0 { DEFAULT_PAGE_SIZE } else { page_size };
+        let start = usize::try_from(cursor).unwrap_or(usize::MAX).min(results.len());
+        let end = start.saturating_add(page_size).min(results.len());
+        Self {
+            events: results[start..end].iter()
+                .filter_map(|event| serde_json::to_value(event).ok())
+                .collect(),
+            next_cursor: (end < results.len()).then_some(end as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pages() {
+        let results: Vec<Value> = (0..5).map(|i| json!({"id": i})).collect();
+
+        let first = Page::of(&results, 0, 2);
+        assert_eq!(first.events, vec![json!({"id": 0}), json!({"id": 1})]);
+        assert_eq!(first.next_cursor, Some(2));
+        let last = Page::of(&results, 4, 2);
+        assert_eq!(last.events, vec![json!({"id": 4})]);
+        assert_eq!(last.next_cursor, None);
+
+        assert!(Page::of(&results, 99, 2).events.is_empty());
+        assert_eq!(Page::of(&results, 0, 0).events.len(), 5, "0 asks for the default page size");
+        assert_eq!(
+            serde_json::to_string(&Page::of(&results, 3, 1)).unwrap(),
+            r#"{"events":[{"id":3}],"next_cursor":4}"#
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Which input of a dub each event came from
+//!
+//! `dub --provenance` embeds a table next to the events rather than tagging
+//! them, so ids and signatures stay intact. Each source lists the ids of the
+//! events the dub took from it:
+//!
+//! ```text
+//! {"sources": [{"name": "a.cassette", "sha256": "...", "events": ["<id>", ...]}, ...]}
+//! ```
+//!
+//! An event several inputs held is listed under the one it was taken from,
+//! the first of them on the command line.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// A provenance table with its ids indexed for lookups
+pub struct Provenance {
+    sources: Vec<Value>,
+    by_id: HashMap<String, usize>,
+}
+
+impl Provenance {
+    /// Parse a table embedded by the CLI; `None` when there is none
+    pub fn from_json(json: &str) -> Option<Self> {
+        let table: Value = serde_json::from_str(json).ok()?;
+        let mut sources = Vec::new();
+        let mut by_id = HashMap::new();
+        for (index, source) in table.get("sources")?.as_array()?.iter().enumerate() {
+            for id in source.get("events").and_then(|e| e.as_array()).into_iter().flatten() {
+                if let Some(id) = id.as_str() {
+                    by_id.entry(id.to_string()).or_insert(index);
+                }
+            }
+            let mut source = source.clone();
+            if let Some(source) = source.as_object_mut() {
+                source.remove("events");
+            }
+            sources.push(source);
+        }
+        Some(Self { sources, by_id })
+    }
+
+    /// The source an event came from, without its id list
+    pub fn source(&self, event_id: &str) -> Option<&Value> {
+        self.by_id.get(event_id).and_then(|index| self.sources.get(*index))
+    }
+}
+
+/// Build the table from each source's description and the ids taken from it
+pub fn table(sources: Vec<(Value, Vec<String>)>) -> Value {
+    let sources: Vec<Value> = sources.into_iter()
+        .map(|(mut source, events)| {
+            source["events"] = json!(events);
+            source
+        })
+        .collect();
+    json!({ "sources": sources })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance() {
+        let table = table(vec![
+            (json!({"name": "a.cassette", "sha256": "aa"}), vec!["1".to_string(), "2".to_string()]),
+            (json!({"name": "b.cassette", "sha256": "bb"}), vec!["3".to_string()]),
+        ]);
+        let provenance = Provenance::from_json(&table.to_string()).unwrap();
+        assert_eq!(provenance.source("2"), Some(&json!({"name": "a.cassette", "sha256": "aa"})));
+        assert_eq!(provenance.source("3").and_then(|s| s["name"].as_str()), Some("b.cassette"));
+        assert_eq!(provenance.source("4"), None);
+        assert!(Provenance::from_json("{}").is_none());
+    }
+}
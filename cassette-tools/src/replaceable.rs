@@ -0,0 +1,127 @@
+//! Replaceable and addressable events (NIP-01)
+//!
+//! Kinds 0, 3 and 10000-19999 keep one event per pubkey and kind; kinds
+//! 30000-39999 keep one per pubkey, kind and `d` tag. Only the latest version
+//! is ever served: the newest `created_at`, and on a tie the lowest id. The CLI
+//! drops older versions when it records a cassette, and cassettes check again
+//! at query time so versions that reach them some other way never leak out.
+
+use crate::filter::Event;
+use std::collections::HashMap;
+
+/// Kinds that keep only their latest event per pubkey
+pub fn is_replaceable(kind: i64) -> bool {
+    kind == 0 || kind == 3 || (10000..20000).contains(&kind)
+}
+
+/// Kinds that keep only their latest event per pubkey and `d` tag
+pub fn is_addressable(kind: i64) -> bool {
+    (30000..40000).contains(&kind)
+}
+
+/// The slot a replaceable event occupies: `kind:pubkey`, plus `:d-tag` for
+/// addressable kinds; `None` for regular events
+pub fn address<E: Event + ?Sized>(event: &E) -> Option<String> {
+    let kind = event.kind()?;
+    let pubkey = event.pubkey()?;
+    if is_replaceable(kind) {
+        Some(format!("{}:{}", kind, pubkey))
+    } else if is_addressable(kind) {
+        let d_tag = event.tag_values("d").first().copied().unwrap_or("");
+        Some(format!("{}:{}:{}", kind, pubkey, d_tag))
+    } else {
+        None
+    }
+}
+
+/// The version (created_at, id) that wins: newest first, then lowest id
+fn supersedes(candidate: (i64, &str), current: (i64, &str)) -> bool {
+    candidate.0 > current.0 || (candidate.0 == current.0 && candidate.1 < current.1)
+}
+
+/// The latest version of every replaceable and addressable event in a set
+#[derive(Debug, Clone, Default)]
+pub struct LatestVersions {
+    latest: HashMap<String, (i64, String)>,
+}
+
+impl LatestVersions {
+    pub fn from_events<'a, E: Event + 'a>(events: impl IntoIterator<Item = &'a E>) -> Self {
+        let mut versions = Self::default();
+        for event in events {
+            versions.insert(event);
+        }
+        versions
+    }
+
+    /// Record an event, returning the id of the version it replaced. Regular
+    /// events and versions older than the current one are ignored.
+    pub fn insert<E: Event + ?Sized>(&mut self, event: &E) -> Option<String> {
+        let address = address(event)?;
+        let version = (event.created_at().unwrap_or(0), event.id().unwrap_or(""));
+        match self.latest.get_mut(&address) {
+            Some(current) if supersedes(version, (current.0, &current.1)) => {
+                let replaced = std::mem::replace(&mut current.1, version.1.to_string());
+                current.0 = version.0;
+                Some(replaced)
+            }
+            Some(_) => None,
+            None => {
+                self.latest.insert(address, (version.0, version.1.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Whether the event is the latest version of its address; regular events always are
+    pub fn is_latest<E: Event + ?Sized>(&self, event: &E) -> bool {
+        let Some(address) = address(event) else {
+            return true;
+        };
+        match self.latest.get(&address) {
+            Some((created_at, id)) => event.created_at().unwrap_or(0) == *created_at && event.id().unwrap_or("") == id,
+            None => true,
+        }
+    }
+}
+
+/// Drop every version of a replaceable or addressable event but the latest
+pub fn latest_versions<E: Event>(events: Vec<E>) -> Vec<E> {
+    let versions = LatestVersions::from_events(&events);
+    events.into_iter().filter(|event| versions.is_latest(event)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_latest_versions() {
+        let events = vec![
+            json!({"id": "profile-old", "pubkey": "alice", "kind": 0, "created_at": 10, "tags": []}),
+            json!({"id": "profile-new", "pubkey": "alice", "kind": 0, "created_at": 20, "tags": []}),
+            json!({"id": "bob-profile", "pubkey": "bob", "kind": 0, "created_at": 5, "tags": []}),
+            json!({"id": "relays-b", "pubkey": "alice", "kind": 10002, "created_at": 30, "tags": []}),
+            json!({"id": "relays-a", "pubkey": "alice", "kind": 10002, "created_at": 30, "tags": []}),
+            json!({"id": "post-v1", "pubkey": "alice", "kind": 30023, "created_at": 10, "tags": [["d", "post"]]}),
+            json!({"id": "post-v2", "pubkey": "alice", "kind": 30023, "created_at": 40, "tags": [["d", "post"]]}),
+            json!({"id": "other-post", "pubkey": "alice", "kind": 30023, "created_at": 1, "tags": [["d", "other"]]}),
+            json!({"id": "note", "pubkey": "alice", "kind": 1, "created_at": 1, "tags": []}),
+        ];
+
+        assert_eq!(address(&events[5]).as_deref(), Some("30023:alice:post"));
+        assert_eq!(address(&events[8]), None);
+
+        let kept: Vec<Value> = latest_versions(events.clone());
+        let ids: Vec<&str> = kept.iter().map(|e| e["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["profile-new", "bob-profile", "relays-a", "post-v2", "other-post", "note"]);
+
+        let mut versions = LatestVersions::default();
+        assert_eq!(versions.insert(&events[0]), None);
+        assert_eq!(versions.insert(&events[1]).as_deref(), Some("profile-old"));
+        assert_eq!(versions.insert(&events[0]), None, "older versions don't replace newer ones");
+        assert!(versions.is_latest(&events[1]));
+        assert!(!versions.is_latest(&events[0]));
+    }
+}
@@ -0,0 +1,138 @@
+//! Events written to a cassette at run time
+//!
+//! With the `writable` feature a cassette accepts EVENT messages instead of
+//! rejecting them: accepted events are kept in an in-memory buffer inside the
+//! instance and served to later REQ and COUNT messages alongside the events
+//! recorded into the cassette. The buffer lives as long as the instance does;
+//! nothing is written back to the `.wasm` file.
+//!
+//! The store knows the ids and replaceable versions of the recorded events, so
+//! an event the cassette already holds is rejected as a duplicate and an older
+//! version of a replaceable or addressable event is rejected in favour of the
+//! one it has. A newer version is accepted and replaces the old one, whether
+//! that was recorded or written earlier. With the `verify` feature the
+//! signature is checked too, so nobody can replace another author's events.
+
+use crate::error::CassetteError;
+use crate::event::NostrEvent;
+use crate::replaceable::LatestVersions;
+use std::collections::HashSet;
+
+/// The in-memory buffer of a writable cassette
+#[derive(Debug, Clone, Default)]
+pub struct EventStore {
+    events: Vec<NostrEvent>,
+    ids: HashSet<String>,
+    versions: LatestVersions,
+}
+
+impl EventStore {
+    /// An empty buffer over the events recorded into the cassette
+    pub fn over(recorded: &[NostrEvent]) -> Self {
+        Self {
+            events: Vec::new(),
+            ids: recorded.iter().map(|event| event.id.clone()).collect(),
+            versions: LatestVersions::from_events(recorded),
+        }
+    }
+
+    /// Store an event, or say why the OK message rejects it: an
+    /// `InvalidEvent` or a `Duplicate`
+    pub fn insert(&mut self, event: NostrEvent) -> Result<(), CassetteError> {
+        if !event.verify_id() {
+            return Err(CassetteError::InvalidEvent("event id does not match its content".to_string()));
+        }
+        #[cfg(feature = "verify")]
+        event.verify_signature()?;
+        if self.ids.contains(&event.id) {
+            return Err(CassetteError::Duplicate("already have this event".to_string()));
+        }
+
+        let replaced = self.versions.insert(&event);
+        if !self.versions.is_latest(&event) {
+            return Err(CassetteError::Duplicate("already have a newer version of this event".to_string()));
+        }
+        if let Some(replaced) = replaced {
+            self.events.retain(|stored| stored.id != replaced);
+        }
+
+        self.ids.insert(event.id.clone());
+        self.events.push(event);
+        Ok(())
+    }
+
+    /// The events written so far, oldest write first
+    pub fn events(&self) -> &[NostrEvent] {
+        &self.events
+    }
+
+    /// Whether the event, recorded or written, is the latest version of its address
+    pub fn is_latest(&self, event: &NostrEvent) -> bool {
+        self.versions.is_latest(event)
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: i64, created_at: i64, tags: Vec<Vec<String>>) -> NostrEvent {
+        let mut event = NostrEvent {
+            id: String::new(),
+            pubkey: String::new(),
+            created_at,
+            kind,
+            tags,
+            content: format!("{}@{}", kind, created_at),
+            sig: String::new(),
+        };
+        crate::event::sign_for_test(&mut event);
+        event
+    }
+
+    #[test]
+    fn test_event_store() {
+        let recorded_profile = event(0, 10, vec![]);
+        let mut store = EventStore::over(std::slice::from_ref(&recorded_profile));
+
+        assert_eq!(store.insert(recorded_profile.clone()).unwrap_err().reason(), "duplicate: already have this event");
+        let mut tampered = event(1, 5, vec![]);
+        tampered.content = "edited".to_string();
+        assert!(store.insert(tampered).unwrap_err().reason().starts_with("invalid:"));
+
+        #[cfg(feature = "verify")]
+        {
+            let mut forged = event(0, 60, vec![]);
+            forged.sig = "00".repeat(64);
+            assert!(store.insert(forged).unwrap_err().reason().starts_with("invalid:"), "a forged profile can't replace the recorded one");
+        }
+
+        let note = event(1, 20, vec![]);
+        assert!(store.insert(note.clone()).is_ok());
+        assert!(store.insert(note).unwrap_err().reason().starts_with("duplicate:"));
+
+        // A newer profile hides the recorded one; an older one is turned away
+        let newer_profile = event(0, 30, vec![]);
+        assert!(store.insert(newer_profile.clone()).is_ok());
+        assert!(!store.is_latest(&recorded_profile));
+        assert!(store.insert(event(0, 5, vec![])).unwrap_err().reason().starts_with("duplicate:"));
+
+        // Written versions are replaced in the buffer itself
+        let draft = vec![vec!["d".to_string(), "post".to_string()]];
+        assert!(store.insert(event(30023, 40, draft.clone())).is_ok());
+        let published = event(30023, 50, draft);
+        assert!(store.insert(published.clone()).is_ok());
+
+        let ids: Vec<&str> = store.events().iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(store.len(), 3);
+        assert_eq!(ids[1..], [newer_profile.id.as_str(), published.id.as_str()]);
+    }
+}
@@ -0,0 +1,130 @@
+//! Build-time full-text index over event content (NIP-50)
+//!
+//! Search terms are matched against the tokens of an event's `content`:
+//! lowercase runs of letters and digits, so `Bitcoin's` holds the tokens
+//! `bitcoin` and `s`. A term matches a token it equals, and a term ending in
+//! `*` matches every token starting with it. Cassettes recorded with
+//! `--nip-50` embed a [`TextIndex`] from each token to the positions of the
+//! events containing it, and a search only scores the events holding a token
+//! for every term instead of scanning all content.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The lowercase runs of letters and digits in `text`
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+/// Whether a query term matches a token: equal, or sharing its prefix for a `term*`
+pub fn term_matches(term: &str, token: &str) -> bool {
+    match term.strip_suffix('*') {
+        Some(prefix) => token.starts_with(prefix),
+        None => token == term,
+    }
+}
+
+/// Positions of the events containing each content token
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextIndex {
+    /// Number of events the positions refer to
+    pub events: usize,
+    pub tokens: BTreeMap<String, Vec<u32>>,
+}
+
+impl TextIndex {
+    /// Index the content of events in the order they are embedded
+    pub fn from_events(events: &[Value]) -> Self {
+        let mut index = Self { events: events.len(), ..Self::default() };
+
+        for (position, event) in events.iter().enumerate() {
+            let position = position as u32;
+            let content = event.get("content").and_then(|c| c.as_str()).unwrap_or("");
+            for token in tokenize(content) {
+                let positions = index.tokens.entry(token).or_default();
+                // An event repeating a token is listed once
+                if positions.last() != Some(&position) {
+                    positions.push(position);
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Parse an index embedded by the CLI; `None` when there is none
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(json).ok().filter(|index| index.events > 0)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Sorted positions of the events with a matching token for every term
+    ///
+    /// No terms match nothing, as a search without terms does.
+    pub fn search(&self, terms: &[String]) -> Vec<u32> {
+        let mut matches: Option<Vec<u32>> = None;
+
+        for term in terms {
+            let mut positions: Vec<u32> = match term.strip_suffix('*') {
+                Some(prefix) => self.tokens.range(prefix.to_string()..)
+                    .take_while(|(token, _)| token.starts_with(prefix))
+                    .flat_map(|(_, positions)| positions.iter().copied())
+                    .collect(),
+                None => self.tokens.get(term).cloned().unwrap_or_default(),
+            };
+            positions.sort_unstable();
+            positions.dedup();
+
+            matches = Some(match matches {
+                Some(mut matches) => {
+                    matches.retain(|position| positions.binary_search(position).is_ok());
+                    matches
+                }
+                None => positions,
+            });
+            if matches.as_ref().is_some_and(|matches| matches.is_empty()) {
+                break;
+            }
+        }
+
+        matches.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_text_index() {
+        assert_eq!(tokenize("Bitcoin's LIGHTNING, zaps!"), vec!["bitcoin", "s", "lightning", "zaps"]);
+        assert!(term_matches("light*", "lightning"));
+        assert!(!term_matches("light", "lightning"));
+
+        let events = vec![
+            json!({"content": "Bitcoin and lightning"}),
+            json!({"content": "lightning lightning storm"}),
+            json!({"content": "bitcoiners unite"}),
+            json!({"kind": 3}),
+        ];
+        let index = TextIndex::from_json(&TextIndex::from_events(&events).to_json()).unwrap();
+        assert_eq!(index.tokens["lightning"], vec![0, 1]);
+
+        let terms = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(index.search(&terms(&["lightning"])), vec![0, 1]);
+        assert_eq!(index.search(&terms(&["bitcoin"])), vec![0]);
+        assert_eq!(index.search(&terms(&["bitcoin*"])), vec![0, 2]);
+        assert_eq!(index.search(&terms(&["bitcoin*", "lightning"])), vec![0]);
+        assert_eq!(index.search(&terms(&["storm", "bitcoin"])), Vec::<u32>::new());
+        assert_eq!(index.search(&[]), Vec::<u32>::new());
+        assert_eq!(TextIndex::from_json("{}"), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! Signature checks a cassette runs over its own events
+//!
+//! The `verify` feature checks schnorr signatures in pure Rust, so it builds
+//! into cassettes as well as hosts. A cassette recorded with `--verify`
+//! exports `verify_events()`, which checks the id and signature of every
+//! event it holds and answers with a [`Verification`], and
+//! `set_verify_on_query(enabled)`, which makes later queries skip events that
+//! fail the same checks (`--verify=query` starts with it on). Consumers who
+//! don't trust where a cassette came from can check it without tooling of
+//! their own.
+
+use crate::error::CassetteError;
+use crate::event::NostrEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The result of checking a set of events
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    /// Number of events checked
+    pub checked: usize,
+    /// The events that failed, in the order they were checked
+    pub invalid: Vec<InvalidSignature>,
+}
+
+/// An event whose id or signature doesn't check out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvalidSignature {
+    pub id: String,
+    pub reason: String,
+}
+
+impl Verification {
+    /// Check every event
+    pub fn of<'a>(events: impl IntoIterator<Item = &'a NostrEvent>) -> Self {
+        let mut verification = Self::default();
+        for event in events {
+            verification.checked += 1;
+            if let Err(e) = check(event) {
+                verification.invalid.push(InvalidSignature { id: event.id.clone(), reason: e.to_string() });
+            }
+        }
+        verification
+    }
+
+    /// Whether every event checked out
+    pub fn is_valid(&self) -> bool {
+        self.invalid.is_empty()
+    }
+}
+
+/// Check that an event's id hashes from its fields and its signature is the pubkey's
+pub fn check(event: &NostrEvent) -> Result<(), CassetteError> {
+    if !event.verify_id() {
+        return Err(CassetteError::InvalidEvent("event id does not match its content".to_string()));
+    }
+    event.verify_signature()
+}
+
+/// The outcome of signature checks already done, so queries check each event once
+#[derive(Debug, Clone, Default)]
+pub struct SignatureCache {
+    checked: HashMap<(String, String), bool>,
+}
+
+impl SignatureCache {
+    /// Whether the event checks out; the id is hashed again every time, so a
+    /// forged event can't reuse the outcome of the event whose id it copies
+    pub fn is_valid(&mut self, event: &NostrEvent) -> bool {
+        if !event.verify_id() {
+            return false;
+        }
+        *self.checked.entry((event.id.clone(), event.sig.clone()))
+            .or_insert_with(|| event.verify_signature().is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_verification() {
+        let event = NostrEvent::from_value(&json!({
+            "kind": 7,
+            "id": "2a6b2a94d05974d2e390c95856df86f3742c0a1b65d6cca9f6705b41ae9ace47",
+            "pubkey": "8ac20c99a80a2c09e785533106b26a285ba4580d8f86030223cf4f2805414655",
+            "created_at": 1753605454,
+            "tags": [["e", "cdfb95a8409b30b156bf35d98e3bf5b5c3669e522b3b82450f8c94d43c5f7db9"], ["p", "3731e169d0b3816f4a4a9ac84c66aa423d4f4ea0f78b56a1df90609b85be0885"]],
+            "content": "+",
+            "sig": "dab8f13d9c6eb6dd238a9e847d2b57ae43d8f5787b50f34e7c1a99909b1375c4acef3ce668b5085ae1bd0dd37c4e55de78dc43818823b6a2b0a86237f79ee1b5"
+        })).unwrap();
+        let bad_sig = NostrEvent { sig: "00".repeat(64), ..event.clone() };
+        let forged = NostrEvent { content: "-".to_string(), ..event.clone() };
+
+        let verification = Verification::of([&event, &bad_sig, &forged]);
+        assert_eq!(verification.checked, 3);
+        assert!(!verification.is_valid());
+        assert_eq!(verification.invalid.len(), 2);
+        assert!(verification.invalid[0].reason.starts_with("invalid signature"));
+        assert_eq!(verification.invalid[1].reason, "event id does not match its content");
+        assert!(Verification::of([&event]).is_valid());
+
+        let mut cache = SignatureCache::default();
+        assert!(cache.is_valid(&event));
+        assert!(!cache.is_valid(&forged), "same id, other content");
+        assert!(!cache.is_valid(&bad_sig));
+        assert!(cache.is_valid(&event));
+    }
+}
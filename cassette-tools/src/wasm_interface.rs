@@ -60,6 +60,17 @@ pub trait StandardCassetteInterface {
     /// Returns: Pointer to the response string in WebAssembly memory
     fn close(close_ptr: *const u8, close_len: usize) -> *mut u8;
     
+    /// Answer a REQ with one page of its results (see `crate::page`)
+    /// 
+    /// Args:
+    ///   request_ptr: A pointer to the REQ message string in WebAssembly memory
+    ///   request_len: The length of the REQ message string
+    ///   cursor: Where the page starts, 0 for the first page
+    ///   page_size: Most events in the page, 0 for the default
+    /// 
+    /// Returns: Pointer to the page, or the CLOSED message refusing the REQ
+    fn req_page(request_ptr: *const u8, request_len: usize, cursor: u64, page_size: u32) -> *mut u8;
+    
     /// Allocate memory for a string
     /// 
     /// Args:
@@ -146,6 +157,12 @@ pub mod docs {
   "message": ["EVENT|NOTICE", "subscription_id", {...event data...}]
 }"#;
 
+    /// Describes the expected format for req_page() function responses
+    pub const REQ_PAGE_RESPONSE_FORMAT: &str = r#"{
+  "events": [{...event data...}],
+  "next_cursor": 500
+}"#;
+
     /// Describes the expected format for close() function requests
     pub const CLOSE_REQUEST_FORMAT: &str = r#"["CLOSE", "subscription_id"]"#;
     
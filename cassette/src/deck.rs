@@ -0,0 +1,204 @@
+//! Running a relay-mode deck
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long `start` waits for the deck to accept connections
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Options for a deck, started with [`DeckBuilder::start`]
+#[derive(Debug, Clone)]
+pub struct DeckBuilder {
+    output: PathBuf,
+    name: Option<String>,
+    bind: String,
+    port: u16,
+    event_limit: Option<usize>,
+    nip_45: bool,
+    nip_50: bool,
+    peers: Vec<String>,
+    binary: PathBuf,
+}
+
+impl DeckBuilder {
+    /// Base name of the cassettes the deck writes
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn bind(mut self, address: &str) -> Self {
+        self.bind = address.to_string();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Events per cassette before the deck rotates to a new one
+    pub fn event_limit(mut self, limit: usize) -> Self {
+        self.event_limit = Some(limit);
+        self
+    }
+
+    /// Answer COUNT messages
+    pub fn nip_45(mut self, enabled: bool) -> Self {
+        self.nip_45 = enabled;
+        self
+    }
+
+    /// Answer `search` filters
+    pub fn nip_50(mut self, enabled: bool) -> Self {
+        self.nip_50 = enabled;
+        self
+    }
+
+    /// Replicate accepted events to another deck
+    pub fn peer(mut self, url: &str) -> Self {
+        self.peers.push(url.to_string());
+        self
+    }
+
+    /// The `cassette` CLI to run the deck with
+    pub fn binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.binary = path.into();
+        self
+    }
+
+    fn deck_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "deck".to_string(),
+            "--mode".to_string(), "relay".to_string(),
+            "--output".to_string(), self.output.display().to_string(),
+            "--bind".to_string(), self.bind.clone(),
+            "--port".to_string(), self.port.to_string(),
+        ];
+        if let Some(name) = &self.name {
+            args.extend(["--name".to_string(), name.clone()]);
+        }
+        if let Some(limit) = self.event_limit {
+            args.extend(["--event-limit".to_string(), limit.to_string()]);
+        }
+        if self.nip_45 {
+            args.push("--nip-45".to_string());
+        }
+        if self.nip_50 {
+            args.push("--nip-50".to_string());
+        }
+        for peer in &self.peers {
+            args.extend(["--peer".to_string(), peer.clone()]);
+        }
+        args
+    }
+
+    /// Start the deck and wait until it accepts connections
+    pub fn start(self) -> Result<Deck> {
+        fs::create_dir_all(&self.output)?;
+        let child = Command::new(&self.binary)
+            .args(self.deck_args())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.binary.display()))?;
+        let mut deck = Deck { child, output: self.output, address: format!("{}:{}", self.bind, self.port) };
+
+        let started = Instant::now();
+        while TcpStream::connect(&deck.address).is_err() {
+            if let Some(status) = deck.child.try_wait()? {
+                bail!("cassette deck exited with {}", status);
+            }
+            if started.elapsed() > STARTUP_TIMEOUT {
+                bail!("cassette deck did not come up on {}", deck.address);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(deck)
+    }
+}
+
+/// A relay-mode deck: a writable Nostr relay that archives the events it
+/// accepts into rotating cassettes. The deck is stopped when dropped.
+#[derive(Debug)]
+pub struct Deck {
+    child: Child,
+    output: PathBuf,
+    address: String,
+}
+
+impl Deck {
+    /// A deck serving on 127.0.0.1:7777 and writing its cassettes to `output`
+    pub fn builder(output: impl Into<PathBuf>) -> DeckBuilder {
+        DeckBuilder {
+            output: output.into(),
+            name: None,
+            bind: "127.0.0.1".to_string(),
+            port: 7777,
+            event_limit: None,
+            nip_45: false,
+            nip_50: false,
+            peers: Vec::new(),
+            binary: crate::default_binary(),
+        }
+    }
+
+    /// WebSocket URL clients connect to
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output
+    }
+
+    /// The cassettes the deck has written so far, oldest name first
+    pub fn cassettes(&self) -> Result<Vec<PathBuf>> {
+        let mut cassettes: Vec<PathBuf> = fs::read_dir(&self.output)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "cassette"))
+            .collect();
+        cassettes.sort();
+        Ok(cassettes)
+    }
+
+    /// Stop the deck and wait for it to exit
+    pub fn stop(mut self) -> Result<()> {
+        self.child.kill()?;
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+impl Drop for Deck {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deck_args() {
+        let builder = Deck::builder("/tmp/deck").port(7001).nip_45(true).peer("ws://127.0.0.1:7002");
+        assert_eq!(
+            builder.deck_args(),
+            vec![
+                "deck", "--mode", "relay", "--output", "/tmp/deck", "--bind", "127.0.0.1", "--port", "7001",
+                "--nip-45", "--peer", "ws://127.0.0.1:7002",
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let missing = Deck::builder(dir.path()).binary("/nonexistent/cassette");
+        assert!(missing.start().is_err());
+    }
+}
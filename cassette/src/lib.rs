@@ -0,0 +1,50 @@
+//! Nostr event cassettes from Rust
+//!
+//! One dependency for applications that work with cassettes:
+//!
+//! - [`Cassette`] loads a `.cassette` file and answers NIP-01 messages
+//!   (re-exported from `cassette-loader`, along with [`SegmentedCassette`] and
+//!   [`CompiledCassette`])
+//! - [`Filter`], [`Event`] and [`NostrEvent`] parse filters and events and match
+//!   them the same way cassettes do (re-exported from `cassette-tools`)
+//! - [`CassetteWriter`] records events onto a new cassette
+//! - [`Deck`] runs a writable relay that archives what it receives into cassettes
+//!
+//! Recording compiles a wasm module and the deck is a long-running server, so
+//! [`CassetteWriter`] and [`Deck`] drive the `cassette` CLI: it must be on `PATH`,
+//! named by the `CASSETTE_BIN` environment variable, or passed to `binary()`.
+//!
+//! ```no_run
+//! use cassette::{Cassette, CassetteWriter, Filter, NostrEvent};
+//! use serde_json::json;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let events: Vec<NostrEvent> = Vec::new();
+//! let path = CassetteWriter::new("notes").nip_45(true).events(events).write("notes.cassette")?;
+//!
+//! let mut cassette = Cassette::load(path.to_str().unwrap(), false)?;
+//! let filter = json!({"kinds": [1], "limit": 10});
+//! for event in cassette.events(&filter.to_string())? {
+//!     let event = NostrEvent::from_value(&serde_json::from_str(&event)?).map_err(anyhow::Error::msg)?;
+//!     assert!(Filter::from_json(&filter).map_err(anyhow::Error::msg)?.matches(&event));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::PathBuf;
+
+mod deck;
+mod writer;
+
+pub use cassette_loader::{Cassette, ClosedMessage, CompiledCassette, DedupPolicy, OkMessage, Page, SegmentedCassette, SendResult};
+pub use cassette_tools::{Event, Filter, NostrEvent};
+pub use deck::{Deck, DeckBuilder};
+pub use writer::CassetteWriter;
+
+/// The `cassette` CLI to run when no binary was given explicitly
+fn default_binary() -> PathBuf {
+    std::env::var_os("CASSETTE_BIN")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("cassette"))
+}
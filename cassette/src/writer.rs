@@ -0,0 +1,199 @@
+//! Recording events onto a new cassette
+
+use crate::NostrEvent;
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Builder for a cassette holding a set of events
+///
+/// The events go through `cassette record`, so they are validated, replaceable
+/// events are resolved and NIP-09 deletions applied exactly as on the command line.
+#[derive(Debug, Clone)]
+pub struct CassetteWriter {
+    name: String,
+    description: Option<String>,
+    author: Option<String>,
+    nip_45: bool,
+    nip_50: bool,
+    minimal: bool,
+    keep_deleted: bool,
+    writable: bool,
+    compress: bool,
+    binary: PathBuf,
+    events: Vec<NostrEvent>,
+}
+
+impl CassetteWriter {
+    /// Start a cassette; `name` is embedded in its description and relay info
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: None,
+            author: None,
+            nip_45: false,
+            nip_50: false,
+            minimal: false,
+            keep_deleted: false,
+            writable: false,
+            compress: false,
+            binary: crate::default_binary(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Answer COUNT messages
+    pub fn nip_45(mut self, enabled: bool) -> Self {
+        self.nip_45 = enabled;
+        self
+    }
+
+    /// Answer `search` filters
+    pub fn nip_50(mut self, enabled: bool) -> Self {
+        self.nip_50 = enabled;
+        self
+    }
+
+    /// Size-optimized build without schema machinery or debug logging
+    pub fn minimal(mut self, enabled: bool) -> Self {
+        self.minimal = enabled;
+        self
+    }
+
+    /// Keep events deleted by kind 5 deletion requests
+    pub fn keep_deleted(mut self, enabled: bool) -> Self {
+        self.keep_deleted = enabled;
+        self
+    }
+
+    /// Accept EVENT messages into the running instance (see `record --writable`)
+    pub fn writable(mut self, enabled: bool) -> Self {
+        self.writable = enabled;
+        self
+    }
+
+    /// Embed the events zstd-compressed
+    pub fn compress(mut self, enabled: bool) -> Self {
+        self.compress = enabled;
+        self
+    }
+
+    /// The `cassette` CLI to record with
+    pub fn binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.binary = path.into();
+        self
+    }
+
+    pub fn event(mut self, event: NostrEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    pub fn events(mut self, events: impl IntoIterator<Item = NostrEvent>) -> Self {
+        self.events.extend(events);
+        self
+    }
+
+    /// Arguments for `cassette record`, reading ndjson from stdin into `output_dir`
+    fn record_args(&self, output_dir: &Path) -> Vec<String> {
+        let mut args = vec![
+            "record".to_string(),
+            "--name".to_string(), self.name.clone(),
+            "--output".to_string(), output_dir.display().to_string(),
+            "--no-bindings".to_string(),
+        ];
+        if let Some(description) = &self.description {
+            args.extend(["--description".to_string(), description.clone()]);
+        }
+        if let Some(author) = &self.author {
+            args.extend(["--author".to_string(), author.clone()]);
+        }
+        for (flag, enabled) in [
+            ("--nip-45", self.nip_45),
+            ("--nip-50", self.nip_50),
+            ("--minimal", self.minimal),
+            ("--keep-deleted", self.keep_deleted),
+            ("--writable", self.writable),
+            ("--compress", self.compress),
+        ] {
+            if enabled {
+                args.push(flag.to_string());
+            }
+        }
+        args
+    }
+
+    /// Record the cassette and write it to `path`, returning the path
+    pub fn write(self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let path = path.as_ref().to_path_buf();
+        if self.events.is_empty() {
+            bail!("a cassette needs at least one event");
+        }
+
+        // record names its output after the sanitized cassette name, so build in a
+        // scratch directory and copy the one cassette it writes
+        let build_dir = tempfile::tempdir()?;
+        let mut child = Command::new(&self.binary)
+            .args(self.record_args(build_dir.path()))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.binary.display()))?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        for event in &self.events {
+            writeln!(stdin, "{}", serde_json::to_string(event)?)?;
+        }
+        drop(stdin);
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!("cassette record failed:\n{}", String::from_utf8_lossy(&output.stderr));
+        }
+
+        let recorded = fs::read_dir(build_dir.path())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|p| p.extension().is_some_and(|ext| ext == "cassette"))
+            .ok_or_else(|| anyhow!("cassette record wrote no cassette"))?;
+        fs::copy(&recorded, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_args() {
+        let writer = CassetteWriter::new("My Notes")
+            .description("notes")
+            .nip_45(true)
+            .keep_deleted(true)
+            .binary("/opt/cassette");
+        assert_eq!(writer.binary, PathBuf::from("/opt/cassette"));
+        assert_eq!(
+            writer.record_args(Path::new("/tmp/build")),
+            vec![
+                "record", "--name", "My Notes", "--output", "/tmp/build", "--no-bindings",
+                "--description", "notes", "--nip-45", "--keep-deleted",
+            ]
+        );
+
+        let empty = CassetteWriter::new("empty").binary("/nonexistent/cassette");
+        assert!(empty.write("/tmp/empty.cassette").is_err());
+    }
+}
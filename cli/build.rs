@@ -3,6 +3,16 @@ use std::fs;
 use std::path::Path;
 
 fn main() {
+    // A prebuilt player module is embedded for `record --fast` when one is given
+    println!("cargo:rerun-if-env-changed=CASSETTE_PLAYER_WASM");
+    println!("cargo:rustc-check-cfg=cfg(embedded_player)");
+    if let Ok(player) = env::var("CASSETTE_PLAYER_WASM") {
+        let player = fs::canonicalize(&player).expect("CASSETTE_PLAYER_WASM doesn't point to a file");
+        println!("cargo:rerun-if-changed={}", player.display());
+        println!("cargo:rustc-cfg=embedded_player");
+        println!("cargo:rustc-env=CASSETTE_PLAYER_PATH={}", player.display());
+    }
+    
     // Only embed cassette-tools if we're building with the deck feature
     if env::var("CARGO_FEATURE_DECK").is_ok() {
         println!("cargo:rerun-if-changed=../cassette-tools/src/");
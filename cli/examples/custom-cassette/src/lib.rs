@@ -1,4 +1,4 @@
-use cassette_tools::{Cassette, CassetteSchema, RelayHandler, RelayResult, implement_info};
+use cassette_tools::{Cassette, CassetteError, CassetteSchema, Filter, RelayHandler, RelayResult, implement_info};
 use cassette_tools::nip01::{ClientReq, RelayEvent, RelayNotice};
 use wasm_bindgen::prelude::*;
 use serde_json::{json, Value, from_str, to_string};
@@ -64,12 +64,8 @@ impl CustomCassette {
     pub fn req(request_json: &str) -> String {
         // Create a new instance and handle the request
         let instance = Self::new();
-        match instance.handle_req(request_json) {
-            Ok(response) => response,
-            Err(err) => json!({
-                "notice": ["NOTICE", err]
-            }).to_string()
-        }
+        instance.handle_req(request_json)
+            .unwrap_or_else(|err| err.response_to(request_json))
     }
     
     /// Handle NIP-01 CLOSE message
@@ -77,12 +73,8 @@ impl CustomCassette {
     pub fn close(close_json: &str) -> String {
         // Create a new instance and handle the close
         let instance = Self::new();
-        match instance.handle_close(close_json) {
-            Ok(response) => response,
-            Err(err) => json!({
-                "notice": ["NOTICE", err]
-            }).to_string()
-        }
+        instance.handle_close(close_json)
+            .unwrap_or_else(|err| err.response_to(close_json))
     }
 }
 
@@ -164,20 +156,11 @@ impl RelayHandler for CustomCassette {
                     let mut custom_tags = Vec::new();
                     let mut timestamp = Utc::now().timestamp() as u64;
                     
-                    for i in 2..array.len() {
-                        if let Some(filter) = array[i].as_object() {
-                            // Look for custom tags in the filter
-                            for (key, value) in filter.iter() {
-                                if key == "#custom" {
-                                    if let Some(values) = value.as_array() {
-                                        for v in values {
-                                            if let Some(tag_value) = v.as_str() {
-                                                custom_tags.push(tag_value.to_string());
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                    for filter in &array[2..] {
+                        // A bad filter ends the subscription with a CLOSED message
+                        let filter = Filter::from_json(filter)?;
+                        if let Some(values) = filter.tags.get("#custom") {
+                            custom_tags.extend(values.iter().cloned());
                         }
                     }
                     
@@ -264,10 +247,10 @@ impl RelayHandler for CustomCassette {
             }
             
             // If request doesn't match expected format, return a NOTICE
-            return Err("Invalid request format. Expected NIP-01 REQ message.".to_string());
+            return Err(CassetteError::InvalidMessage("expected a NIP-01 REQ message".to_string()));
         } else {
             // If JSON parsing failed, return an error notice
-            return Err("Invalid JSON in request".to_string());
+            return Err(CassetteError::InvalidMessage("request is not valid JSON".to_string()));
         }
     }
     
@@ -1,4 +1,4 @@
-use cassette_tools::{string_to_ptr, ptr_to_string, implement_info};
+use cassette_tools::{string_to_ptr, ptr_to_string, implement_info, Filter};
 use serde_json::{json, Value};
 
 // Implement NIP-11 info function (relay info will be set dynamically by CLI)
@@ -78,13 +78,21 @@ pub extern "C" fn req(ptr: *const u8, len: usize) -> *mut u8 {
         }
     };
     
+    let mut filters = Vec::new();
+    for filter in &arr[2..] {
+        match Filter::from_json(filter) {
+            Ok(filter) => filters.push(filter),
+            Err(e) => return string_to_ptr(json!(["NOTICE", format!("Invalid filter: {}", e)]).to_string()),
+        }
+    }
+    
     // Parse our test events
     let events: Vec<Value> = serde_json::from_str(EVENTS).unwrap();
     
-    // Build response with EVENT messages
+    // Build response with EVENT messages for the events any filter matches
     let mut response_events = Vec::new();
     
-    for event in events {
+    for event in events.into_iter().filter(|event| Filter::matches_any(&filters, event)) {
         response_events.push(json!(["EVENT", subscription_id, event]));
     }
     
@@ -0,0 +1,84 @@
+//! Appending events to an existing cassette
+//!
+//! `record --append` rebuilds a cassette from the events it already serves
+//! plus the new input. The cassette's events were checked when it was
+//! recorded, so only the new ones go through the validation policy. New
+//! events whose id the cassette already has are dropped, and replaceable
+//! and addressable events keep only their latest version across both sets,
+//! so a newer profile in the input replaces the archived one. Deletion
+//! requests in the input are honoured when the merged events are built.
+
+use cassette_tools::replaceable;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// How the new events combined with the cassette's
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppendStats {
+    /// Events the cassette already served
+    pub existing: usize,
+    /// New events that made it into the merged set
+    pub added: usize,
+    /// New events whose id the cassette already had
+    pub duplicates: usize,
+    /// Events of either set replaced by a newer version
+    pub superseded: usize,
+}
+
+impl AppendStats {
+    /// One line for the record output
+    pub fn summary(&self) -> String {
+        format!(
+            "➕ Appending {} new events to {} existing ({} duplicates, {} superseded versions dropped)",
+            self.added, self.existing, self.duplicates, self.superseded
+        )
+    }
+}
+
+fn id(event: &Value) -> &str {
+    event.get("id").and_then(|i| i.as_str()).unwrap_or("")
+}
+
+/// Merge new events into a cassette's, dropping duplicates and stale versions
+pub fn merge(existing: Vec<Value>, new: Vec<Value>) -> (Vec<Value>, AppendStats) {
+    let mut stats = AppendStats { existing: existing.len(), ..Default::default() };
+    let mut seen: HashSet<String> = existing.iter().map(|event| id(event).to_string()).collect();
+
+    let mut events = existing;
+    let existing_count = events.len();
+    for event in new {
+        if seen.insert(id(&event).to_string()) {
+            events.push(event);
+        } else {
+            stats.duplicates += 1;
+        }
+    }
+    let merged = events.len();
+
+    let new_ids: HashSet<String> = events[existing_count..].iter().map(|event| id(event).to_string()).collect();
+    let events = replaceable::latest_versions(events);
+    stats.superseded = merged - events.len();
+    stats.added = events.iter().filter(|event| new_ids.contains(id(event))).count();
+    (events, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(id: &str, kind: i64, created_at: i64) -> Value {
+        json!({"id": id, "pubkey": "alice", "kind": kind, "created_at": created_at, "tags": [], "content": "", "sig": ""})
+    }
+
+    #[test]
+    fn test_merge() {
+        let existing = vec![event("a", 1, 1), event("profile-old", 0, 10), event("list-new", 3, 30)];
+        let new = vec![event("a", 1, 1), event("b", 1, 2), event("profile-new", 0, 20), event("list-old", 3, 5)];
+
+        let (events, stats) = merge(existing, new);
+        let ids: Vec<&str> = events.iter().map(id).collect();
+        assert_eq!(ids, vec!["a", "list-new", "b", "profile-new"]);
+        assert_eq!(stats, AppendStats { existing: 3, added: 2, duplicates: 1, superseded: 2 });
+    }
+}
@@ -0,0 +1,72 @@
+//! NIP-42 authentication for `cassette listen --require-auth`
+//!
+//! Each connection gets a fresh challenge as soon as it opens. Until the
+//! client answers it with a valid AUTH event, REQ and COUNT messages are
+//! closed with `auth-required:`; afterwards they are served as usual. The
+//! session itself lives in `cassette_tools::nips::nip42::AuthSession`.
+
+use cassette_tools::nips::nip42::AuthSession;
+use serde_json::{json, Value};
+
+/// How the server expects clients to authenticate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthPolicy {
+    /// The URL AUTH events must name in their `relay` tag
+    pub relay_url: String,
+}
+
+impl AuthPolicy {
+    /// A session for a new connection, with a random challenge
+    pub fn session(&self) -> AuthSession {
+        AuthSession::new(&new_challenge(), &self.relay_url)
+    }
+}
+
+/// 32 random bytes, hex-encoded
+pub fn new_challenge() -> String {
+    hex::encode(secp256k1::rand::random::<[u8; 32]>())
+}
+
+/// Announce NIP-42 and `auth_required` in a NIP-11 document
+pub fn advertise(info: &str) -> String {
+    let Ok(Value::Object(mut document)) = serde_json::from_str::<Value>(info) else {
+        return info.to_string();
+    };
+
+    let nips = document.entry("supported_nips").or_insert_with(|| json!([]));
+    if let Some(nips) = nips.as_array_mut() {
+        if !nips.iter().any(|n| n.as_u64() == Some(42)) {
+            nips.push(json!(42));
+            nips.sort_by_key(|n| n.as_u64());
+        }
+    }
+
+    let limitation = document.entry("limitation").or_insert_with(|| json!({}));
+    if let Some(limitation) = limitation.as_object_mut() {
+        limitation.insert("auth_required".to_string(), json!(true));
+    }
+
+    Value::Object(document).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_policy() {
+        let a = new_challenge();
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, new_challenge());
+
+        let policy = AuthPolicy { relay_url: "ws://127.0.0.1:7777".to_string() };
+        let session = policy.session();
+        assert!(!session.is_authenticated());
+        assert_eq!(session.challenge_message()[0], "AUTH");
+
+        let info: Value = serde_json::from_str(&advertise(r#"{"supported_nips":[1,50],"limitation":{"max_limit":500}}"#)).unwrap();
+        assert_eq!(info["supported_nips"], json!([1, 42, 50]));
+        assert_eq!(info["limitation"], json!({"max_limit": 500, "auth_required": true}));
+        assert_eq!(advertise("not json"), "not json");
+    }
+}
@@ -0,0 +1,221 @@
+//! Timing a cassette's query engine for `cassette bench`
+//!
+//! A benchmark compiles the module once, then times fresh instances (with the
+//! payload attached, as a loader would) and each query of a corpus: the REQ is
+//! sent and its answer drained up to EOSE, then the subscription is closed, on
+//! one instance kept for the whole run. Warm-up rounds are run and discarded
+//! before each query is timed. Latencies are reported as a distribution
+//! (percentiles by nearest rank) with the throughput they add up to.
+
+use crate::conformance::Query;
+use crate::guest::GuestApi;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use wasmtime::Store;
+
+/// Subscription id the queries run under
+const SUBSCRIPTION: &str = "bench";
+
+/// Summary of a set of timings, in microseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Distribution {
+    pub runs: usize,
+    pub min_us: u64,
+    pub mean_us: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+    pub total_us: u64,
+}
+
+impl Distribution {
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut micros: Vec<u64> = samples.iter().map(|sample| sample.as_micros() as u64).collect();
+        micros.sort_unstable();
+        let total: u64 = micros.iter().sum();
+        // Nearest rank: the smallest sample at or above the percentile
+        let rank = |percentile: u64| micros[((micros.len() as u64 * percentile).div_ceil(100) as usize).max(1) - 1];
+        Self {
+            runs: micros.len(),
+            min_us: micros[0],
+            mean_us: total / micros.len() as u64,
+            p50_us: rank(50),
+            p90_us: rank(90),
+            p99_us: rank(99),
+            max_us: micros[micros.len() - 1],
+            total_us: total,
+        }
+    }
+
+    /// Runs per second at the measured total
+    pub fn per_second(&self) -> f64 {
+        if self.total_us == 0 {
+            return 0.0;
+        }
+        self.runs as f64 * 1_000_000.0 / self.total_us as f64
+    }
+}
+
+/// A duration in microseconds, shown in the largest unit that keeps it above 1
+pub fn micros(us: u64) -> String {
+    match us {
+        0..=999 => format!("{}µs", us),
+        1_000..=999_999 => format!("{:.2}ms", us as f64 / 1_000.0),
+        _ => format!("{:.2}s", us as f64 / 1_000_000.0),
+    }
+}
+
+/// Timings of one query
+#[derive(Debug, Serialize)]
+pub struct QueryBench {
+    #[serde(flatten)]
+    pub query: Query,
+    /// Events one run returns
+    pub events: usize,
+    pub latency: Distribution,
+}
+
+/// Everything a benchmark measured
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub cassette: String,
+    pub compile_us: u64,
+    pub instantiate: Distribution,
+    pub queries: Vec<QueryBench>,
+}
+
+impl Report {
+    /// Queries and events per second over every timed query run
+    pub fn throughput(&self) -> (f64, f64) {
+        let total_us: u64 = self.queries.iter().map(|query| query.latency.total_us).sum();
+        if total_us == 0 {
+            return (0.0, 0.0);
+        }
+        let runs: usize = self.queries.iter().map(|query| query.latency.runs).sum();
+        let events: usize = self.queries.iter().map(|query| query.events * query.latency.runs).sum();
+        let seconds = total_us as f64 / 1_000_000.0;
+        (runs as f64 / seconds, events as f64 / seconds)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (queries_per_second, events_per_second) = self.throughput();
+        json!({
+            "cassette": self.cassette,
+            "compile_us": self.compile_us,
+            "instantiate": self.instantiate,
+            "queries": self.queries,
+            "throughput": {
+                "queries_per_second": queries_per_second,
+                "events_per_second": events_per_second,
+            },
+        })
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let distribution = |d: &Distribution| format!(
+            "p50 {:>9}  p90 {:>9}  p99 {:>9}  max {:>9}",
+            micros(d.p50_us), micros(d.p90_us), micros(d.p99_us), micros(d.max_us)
+        );
+        let mut lines = vec![
+            format!("Cassette:     {}", self.cassette),
+            format!("Compile:      {}", micros(self.compile_us)),
+            format!("Instantiate:  {}  ({} runs)", distribution(&self.instantiate), self.instantiate.runs),
+            String::new(),
+        ];
+        for query in &self.queries {
+            lines.push(format!("{}  ({} events)", query.query.label, query.events));
+            lines.push(format!("  {}  {:.0} q/s", distribution(&query.latency), query.latency.per_second()));
+        }
+        let (queries_per_second, events_per_second) = self.throughput();
+        lines.push(String::new());
+        lines.push(format!("Throughput:   {:.0} queries/s, {:.0} events/s", queries_per_second, events_per_second));
+        lines
+    }
+}
+
+/// Send a REQ, drain its answer up to EOSE and close it; returns the events received
+pub fn run_query(store: &mut Store<()>, guest: &GuestApi, filters: &[Value]) -> Result<usize> {
+    let mut message = vec![json!("REQ"), json!(SUBSCRIPTION)];
+    message.extend(filters.iter().cloned());
+    let mut replies = guest.replies(store, &Value::Array(message).to_string())?;
+    let mut events = 0;
+    while let Some(reply) = replies.next(guest, store)? {
+        let message: Value = serde_json::from_str(&reply)?;
+        match message.get(0).and_then(|t| t.as_str()) {
+            Some("EVENT") => events += 1,
+            Some("EOSE") | Some("CLOSED") => break,
+            Some("NOTICE") if reply.contains("No more events") => break,
+            _ => {}
+        }
+    }
+    guest.send(store, &json!(["CLOSE", SUBSCRIPTION]).to_string())?;
+    Ok(events)
+}
+
+/// Time `iterations` runs of `run` after `warmup` untimed ones; returns the timings
+/// and the result of the last run
+pub fn time<T>(warmup: usize, iterations: usize, mut run: impl FnMut() -> Result<T>) -> Result<(Vec<Duration>, Option<T>)> {
+    for _ in 0..warmup {
+        run()?;
+    }
+    let mut samples = Vec::with_capacity(iterations);
+    let mut last = None;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let result = run()?;
+        samples.push(start.elapsed());
+        last = Some(result);
+    }
+    Ok((samples, last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution() {
+        let samples: Vec<Duration> = (1..=100).rev().map(Duration::from_micros).collect();
+        let distribution = Distribution::from_samples(&samples);
+        assert_eq!(distribution, Distribution {
+            runs: 100,
+            min_us: 1,
+            mean_us: 50,
+            p50_us: 50,
+            p90_us: 90,
+            p99_us: 99,
+            max_us: 100,
+            total_us: 5050,
+        });
+        assert!((distribution.per_second() - 100.0 * 1_000_000.0 / 5050.0).abs() < 1e-9);
+        assert_eq!(Distribution::from_samples(&[Duration::from_millis(3)]).p99_us, 3000);
+        assert_eq!(Distribution::from_samples(&[]), Distribution::default());
+
+        assert_eq!(micros(250), "250µs");
+        assert_eq!(micros(1_500), "1.50ms");
+        assert_eq!(micros(2_000_000), "2.00s");
+
+        let mut calls = 0;
+        let (samples, last) = time(2, 3, || { calls += 1; Ok(calls) }).unwrap();
+        assert_eq!((samples.len(), last, calls), (3, Some(5), 5));
+
+        let report = Report {
+            cassette: "x.cassette".to_string(),
+            compile_us: 0,
+            instantiate: Distribution::default(),
+            queries: vec![QueryBench {
+                query: Query { label: "all".to_string(), filters: vec![json!({})] },
+                events: 10,
+                latency: Distribution { runs: 4, total_us: 2_000_000, ..Distribution::default() },
+            }],
+        };
+        assert_eq!(report.throughput(), (2.0, 20.0));
+        assert_eq!(report.lines().last().unwrap(), "Throughput:   2 queries/s, 20 events/s");
+    }
+}
@@ -0,0 +1,233 @@
+//! Per-relay cursors for deck record mode and `play --resume`
+//!
+//! The deck remembers the newest event (created_at and id) it has seen from
+//! each source relay and, after a restart, resumes the relay's subscription
+//! with `since` set to it. A cursor only reaches the file once a rotation has
+//! compiled every event behind it into a cassette, so a crash with a full
+//! buffer re-downloads those events instead of skipping them.
+//!
+//! `play` keeps how far into its playlist each target relay got: the number
+//! of events the relay answered and the id of the last one. The playlist is
+//! the same for the same cassettes and order options, so a resumed playback
+//! starts each relay after its last answered event, once that event's id
+//! confirms the playlist hasn't changed. Progress is saved every
+//! `PLAY_SAVE_EVERY` answers and when a relay finishes or drops, so a crash
+//! resends at most that many events, which relays ignore as duplicates.
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Answers between two saves of a playback's progress
+pub const PLAY_SAVE_EVERY: usize = 100;
+
+/// Checkpoint file used when `--checkpoint` is not given
+pub fn default_checkpoint_path(output_dir: &Path) -> PathBuf {
+    output_dir.join("relay-cursors.json")
+}
+
+/// Newest event seen from a relay
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayCursor {
+    pub created_at: i64,
+    pub event_id: String,
+}
+
+impl RelayCursor {
+    fn is_after(&self, other: &RelayCursor) -> bool {
+        (self.created_at, &self.event_id) > (other.created_at, &other.event_id)
+    }
+}
+
+/// Cursors seen by the recorders and the ones saved to disk
+pub struct RelayCursors {
+    path: PathBuf,
+    seen: Mutex<BTreeMap<String, RelayCursor>>,
+    saved: Mutex<BTreeMap<String, RelayCursor>>,
+}
+
+impl RelayCursors {
+    /// Read the checkpoint file; a missing file means there is nothing to resume
+    pub fn load(path: &Path) -> Result<Self> {
+        let saved: BTreeMap<String, RelayCursor> = read_checkpoint(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            seen: Mutex::new(saved.clone()),
+            saved: Mutex::new(saved),
+        })
+    }
+
+    /// Saved cursor to resume a relay from
+    pub fn resume_from(&self, relay: &str) -> Option<RelayCursor> {
+        self.saved.lock().unwrap().get(relay).cloned()
+    }
+
+    /// Record an event from a relay once it is in the recording buffer
+    pub fn observe(&self, relay: &str, cursor: RelayCursor) {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.get(relay).map_or(true, |current| cursor.is_after(current)) {
+            seen.insert(relay.to_string(), cursor);
+        }
+    }
+
+    /// Cursors covering everything buffered so far, taken when a rotation cuts the buffer
+    pub fn snapshot(&self) -> BTreeMap<String, RelayCursor> {
+        self.seen.lock().unwrap().clone()
+    }
+
+    /// Save a snapshot once the events behind it are in a cassette
+    pub fn commit(&self, snapshot: &BTreeMap<String, RelayCursor>) -> Result<()> {
+        let mut saved = self.saved.lock().unwrap();
+        for (relay, cursor) in snapshot {
+            if saved.get(relay).map_or(true, |current| cursor.is_after(current)) {
+                saved.insert(relay.clone(), cursor.clone());
+            }
+        }
+        write_checkpoint(&self.path, &*saved)
+    }
+}
+
+/// A checkpoint file's per-relay entries; a missing file has none
+fn read_checkpoint<T: DeserializeOwned>(path: &Path) -> Result<BTreeMap<String, T>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid checkpoint file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read checkpoint {}", path.display())),
+    }
+}
+
+fn write_checkpoint<T: Serialize>(path: &Path, entries: &BTreeMap<String, T>) -> Result<()> {
+    // Write next to the checkpoint and rename, so a crash never leaves a torn file
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(entries)?)
+        .with_context(|| format!("Failed to write checkpoint {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace checkpoint {}", path.display()))
+}
+
+/// Progress file of `play` when `--checkpoint` is not given, next to the first cassette
+pub fn default_play_checkpoint_path(cassette: &Path) -> PathBuf {
+    let mut name = cassette.as_os_str().to_owned();
+    name.push(".play.json");
+    PathBuf::from(name)
+}
+
+/// How far a relay got through a playlist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayProgress {
+    /// Events at the start of the playlist the relay answered, or was skipped for
+    pub acknowledged: usize,
+    /// Id of the last of them
+    pub last_id: String,
+}
+
+/// Progress of every relay of a playback
+pub struct PlayCheckpoint {
+    path: PathBuf,
+    progress: Mutex<BTreeMap<String, PlayProgress>>,
+}
+
+impl PlayCheckpoint {
+    /// Read the progress file to resume from it
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self { path: path.to_path_buf(), progress: Mutex::new(read_checkpoint(path)?) })
+    }
+
+    /// A fresh playback, replacing whatever progress the file holds on the first save
+    pub fn start(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), progress: Mutex::default() }
+    }
+
+    /// Index in `events` to resume a relay at; refuses progress that doesn't fit the
+    /// playlist, since resuming it would skip the wrong events
+    pub fn resume_at(&self, relay: &str, events: &[Value]) -> Result<usize> {
+        let Some(progress) = self.progress.lock().unwrap().get(relay).cloned() else { return Ok(0) };
+        if progress.acknowledged == 0 {
+            return Ok(0);
+        }
+        let last = events.get(progress.acknowledged - 1).and_then(|event| event.get("id")?.as_str());
+        if last != Some(progress.last_id.as_str()) {
+            return Err(anyhow!(
+                "The checkpoint {} doesn't match this playlist for {} (event {} was {}); play the same \
+                 cassettes with the same order options, or start over without --resume",
+                self.path.display(), relay, progress.acknowledged, progress.last_id
+            ));
+        }
+        Ok(progress.acknowledged)
+    }
+
+    /// Note that a relay answered (or was skipped for) every event up to `acknowledged`
+    pub fn advance(&self, relay: &str, acknowledged: usize, last_id: &str) {
+        self.progress.lock().unwrap()
+            .insert(relay.to_string(), PlayProgress { acknowledged, last_id: last_id.to_string() });
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let progress = self.progress.lock().unwrap().clone();
+        write_checkpoint(&self.path, &progress)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursors_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_checkpoint_path(dir.path());
+        let cursor = |created_at: i64, id: &str| RelayCursor { created_at, event_id: id.to_string() };
+
+        let cursors = RelayCursors::load(&path).unwrap();
+        assert_eq!(cursors.resume_from("wss://a"), None);
+        cursors.observe("wss://a", cursor(100, "bb"));
+        cursors.observe("wss://a", cursor(90, "ff"));
+        cursors.observe("wss://b", cursor(50, "aa"));
+        let snapshot = cursors.snapshot();
+
+        // Seen after the rotation cut, so not covered by the cassette being compiled
+        cursors.observe("wss://a", cursor(200, "cc"));
+        assert_eq!(cursors.resume_from("wss://a"), None, "nothing is saved before a rotation");
+        cursors.commit(&snapshot).unwrap();
+
+        let restarted = RelayCursors::load(&path).unwrap();
+        assert_eq!(restarted.resume_from("wss://a"), Some(cursor(100, "bb")));
+        assert_eq!(restarted.resume_from("wss://b"), Some(cursor(50, "aa")));
+
+        // An older snapshot never moves a saved cursor back
+        restarted.commit(&BTreeMap::from([("wss://a".to_string(), cursor(10, "00"))])).unwrap();
+        assert_eq!(RelayCursors::load(&path).unwrap().resume_from("wss://a"), Some(cursor(100, "bb")));
+    }
+
+    #[test]
+    fn test_play_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_play_checkpoint_path(&dir.path().join("notes.cassette"));
+        assert_eq!(path, dir.path().join("notes.cassette.play.json"));
+        let events: Vec<Value> = ["c", "b", "a"].iter().map(|id| serde_json::json!({"id": id})).collect();
+
+        let playback = PlayCheckpoint::start(&path);
+        playback.advance("wss://a", 2, "b");
+        playback.advance("wss://b", 3, "a");
+        playback.save().unwrap();
+
+        let resumed = PlayCheckpoint::load(&path).unwrap();
+        assert_eq!(resumed.resume_at("wss://a", &events).unwrap(), 2);
+        assert_eq!(resumed.resume_at("wss://b", &events).unwrap(), 3);
+        assert_eq!(resumed.resume_at("wss://new", &events).unwrap(), 0);
+        assert!(resumed.resume_at("wss://a", &events[1..]).is_err(), "another playlist");
+        assert!(resumed.resume_at("wss://b", &events[..2]).is_err(), "a shorter playlist");
+
+        PlayCheckpoint::start(&path).save().unwrap();
+        assert_eq!(PlayCheckpoint::load(&path).unwrap().resume_at("wss://a", &events).unwrap(), 0);
+    }
+}
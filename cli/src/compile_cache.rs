@@ -0,0 +1,109 @@
+//! Persistent build cache for cassette compilation
+//!
+//! Each cassette is its own crate, generated in a fresh temp directory, and
+//! used to be built with a fresh target directory too: every `record`
+//! compiled cassette-tools, serde and the rest from scratch. Builds now share
+//! one cargo target directory under `~/.cache/cassette/target`, so only the
+//! cassette crate itself is compiled once the dependencies are warm. Its
+//! artifacts are removed after each build since they embed the events and
+//! are never reused. Builds with the embedded cassette-tools (the `deck`
+//! feature) extract it once per CLI version next to the target directory,
+//! since cargo keys cached path dependencies by their location. Cargo locks
+//! the target directory, so concurrent builds wait for each other. A
+//! `RUSTC_WRAPPER` such as sccache is picked up by cargo as usual.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// `$XDG_CACHE_HOME/cassette`, else `~/.cache/cassette`
+pub fn cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("cassette")
+}
+
+/// Cargo target directory shared by cassette builds
+pub fn target_dir() -> PathBuf {
+    cache_dir().join("target")
+}
+
+/// Where the embedded cassette-tools of this CLI version are extracted
+pub fn tools_dir() -> PathBuf {
+    cache_dir().join(format!("cassette-tools-{}", env!("CARGO_PKG_VERSION")))
+}
+
+/// Extract cassette-tools into the cache with `extract` unless this version already is
+pub fn cached_tools(extract: impl FnOnce(&Path) -> Result<()>) -> Result<PathBuf> {
+    let dir = tools_dir();
+    if !dir.join("Cargo.toml").exists() {
+        let parent = cache_dir();
+        std::fs::create_dir_all(&parent)?;
+        // Extract beside the final location and move it in, so an interrupted extraction leaves nothing behind
+        let partial = tempfile::tempdir_in(&parent)?;
+        extract(partial.path())?;
+        let partial = partial.into_path();
+        if std::fs::rename(&partial, &dir).is_err() {
+            // Another build got there first
+            std::fs::remove_dir_all(&partial)?;
+        }
+    }
+    Ok(dir)
+}
+
+/// Remove a cassette crate's own artifacts from the shared target directory
+pub fn prune(target_dir: &Path, crate_name: &str) {
+    let lib_name = crate_name.replace('-', "_");
+    let release = target_dir.join("wasm32-unknown-unknown").join("release");
+    let is_artifact = |file: &str| {
+        [crate_name, lib_name.as_str()].iter().any(|name| {
+            file.strip_prefix(name).is_some_and(|rest| rest.starts_with('.') || rest.starts_with('-'))
+        })
+    };
+    for dir in [release.clone(), release.join("deps"), release.join(".fingerprint"), release.join("incremental")] {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if !is_artifact(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let path = entry.path();
+            let _ = if path.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune() {
+        let target = tempfile::tempdir().unwrap();
+        let release = target.path().join("wasm32-unknown-unknown").join("release");
+        let files = [
+            "my_archive.wasm",
+            "my_archive.d",
+            "deps/my_archive-1a2b3c.wasm",
+            ".fingerprint/my-archive-1a2b3c/lib-my_archive",
+            "deps/serde-9f8e7d.rlib",
+            "deps/my_archive_old-000000.wasm",
+            ".fingerprint/cassette-tools-4d5e6f/lib-cassette_tools",
+        ];
+        for file in files {
+            let path = release.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, b"").unwrap();
+        }
+
+        prune(target.path(), "my-archive");
+        let left: Vec<&str> = files.into_iter().filter(|file| release.join(file).exists()).collect();
+        assert_eq!(left, vec![
+            "deps/serde-9f8e7d.rlib",
+            "deps/my_archive_old-000000.wasm",
+            ".fingerprint/cassette-tools-4d5e6f/lib-cassette_tools",
+        ]);
+    }
+}
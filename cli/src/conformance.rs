@@ -0,0 +1,229 @@
+//! Query corpus and response diffing for `cassette conformance`
+//!
+//! The same REQ/COUNT queries go to a cassette and to a reference relay
+//! holding the same events. REQ results are compared as sets of event ids
+//! (ordering aside) and COUNT results by value; anything else is a
+//! divergence in the cassette's NIP-01/NIP-45 handling.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Number of distinct kinds, authors and tag values sampled into the default corpus
+const SAMPLES: usize = 3;
+
+/// A query sent to both sides: one or more filters
+#[derive(Debug, Clone, Serialize)]
+pub struct Query {
+    pub label: String,
+    pub filters: Vec<Value>,
+}
+
+impl Query {
+    fn new(label: impl Into<String>, filters: Vec<Value>) -> Self {
+        Self { label: label.into(), filters }
+    }
+}
+
+/// Build a query corpus that exercises each filter field against the events in the cassette
+pub fn default_corpus(events: &[Value]) -> Vec<Query> {
+    let mut queries = vec![
+        Query::new("all events", vec![json!({})]),
+        Query::new("limit 10", vec![json!({"limit": 10})]),
+        Query::new("unknown id", vec![json!({"ids": ["0".repeat(64)]})]),
+    ];
+
+    let kinds = most_common(events.iter().filter_map(|e| e.get("kind").and_then(|k| k.as_i64())));
+    let authors = most_common(events.iter().filter_map(|e| e.get("pubkey").and_then(|p| p.as_str()).map(String::from)));
+
+    for kind in &kinds {
+        queries.push(Query::new(format!("kind {}", kind), vec![json!({"kinds": [kind]})]));
+        queries.push(Query::new(format!("kind {} limit 3", kind), vec![json!({"kinds": [kind], "limit": 3})]));
+    }
+    for author in &authors {
+        queries.push(Query::new(format!("author {}…", &author[..author.len().min(8)]), vec![json!({"authors": [author]})]));
+        if let Some(kind) = kinds.first() {
+            queries.push(Query::new(
+                format!("author {}… kind {}", &author[..author.len().min(8)], kind),
+                vec![json!({"authors": [author], "kinds": [kind]})],
+            ));
+        }
+    }
+    if kinds.len() >= 2 {
+        queries.push(Query::new(format!("kinds {} + {}", kinds[0], kinds[1]), vec![json!({"kinds": [kinds[0], kinds[1]]})]));
+    }
+    if let (Some(kind), Some(author)) = (kinds.first(), authors.last()) {
+        queries.push(Query::new("two filters (OR)", vec![json!({"kinds": [kind]}), json!({"authors": [author]})]));
+    }
+
+    let ids: Vec<&str> = events.iter().filter_map(|e| e.get("id").and_then(|i| i.as_str())).take(SAMPLES).collect();
+    if !ids.is_empty() {
+        queries.push(Query::new(format!("{} ids", ids.len()), vec![json!({"ids": ids})]));
+    }
+
+    let mut timestamps: Vec<i64> = events.iter().filter_map(|e| e.get("created_at").and_then(|t| t.as_i64())).collect();
+    timestamps.sort_unstable();
+    if let (Some(&first), Some(&last)) = (timestamps.first(), timestamps.last()) {
+        let median = timestamps[timestamps.len() / 2];
+        queries.push(Query::new("since median", vec![json!({"since": median})]));
+        queries.push(Query::new("until median", vec![json!({"until": median})]));
+        queries.push(Query::new("since = until", vec![json!({"since": median, "until": median})]));
+        queries.push(Query::new("full window limit 5", vec![json!({"since": first, "until": last, "limit": 5})]));
+    }
+
+    for (tag, value) in tag_samples(events) {
+        queries.push(Query::new(format!("#{} {}", tag, value), vec![json!({ format!("#{}", tag): [value] })]));
+    }
+
+    queries
+}
+
+/// Load queries from a JSON file: an array whose items are a filter object or an array of filters
+pub fn load_corpus(path: &Path) -> Result<Vec<Query>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read query file {}", path.display()))?;
+    let items: Vec<Value> = serde_json::from_str(&content)
+        .with_context(|| format!("Query file {} must be a JSON array", path.display()))?;
+
+    items.into_iter().enumerate().map(|(i, item)| {
+        let filters = match item {
+            Value::Object(_) => vec![item],
+            Value::Array(filters) if !filters.is_empty() && filters.iter().all(|f| f.is_object()) => filters,
+            other => return Err(anyhow!("Query {} must be a filter object or an array of filters, got {}", i + 1, other)),
+        };
+        let label = filters.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(" | ");
+        Ok(Query::new(label, filters))
+    }).collect()
+}
+
+fn most_common<T: Ord + Clone>(values: impl Iterator<Item = T>) -> Vec<T> {
+    let mut counts: BTreeMap<T, usize> = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    let mut ranked: Vec<(T, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1));
+    ranked.into_iter().take(SAMPLES).map(|(value, _)| value).collect()
+}
+
+/// Most common single-letter tag values (the tags reference relays reliably index)
+fn tag_samples(events: &[Value]) -> Vec<(String, String)> {
+    let tags = events.iter()
+        .filter_map(|e| e.get("tags").and_then(|t| t.as_array()))
+        .flatten()
+        .filter_map(|tag| {
+            let name = tag.get(0)?.as_str()?;
+            let value = tag.get(1)?.as_str()?;
+            (name.len() == 1 && name.chars().all(|c| c.is_ascii_alphabetic()))
+                .then(|| (name.to_string(), value.to_string()))
+        });
+    most_common(tags)
+}
+
+/// Difference between the events each side returned for a REQ
+#[derive(Debug, Default, Serialize)]
+pub struct ReqDiff {
+    pub cassette: usize,
+    pub relay: usize,
+    /// Returned by the relay but not the cassette
+    pub missing: Vec<String>,
+    /// Returned by the cassette but not the relay
+    pub extra: Vec<String>,
+    /// Ids the cassette returned more than once
+    pub duplicates: Vec<String>,
+}
+
+impl ReqDiff {
+    pub fn is_match(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.duplicates.is_empty()
+    }
+}
+
+/// Compare two REQ results by event id, ignoring order
+pub fn diff_events(cassette: &[Value], relay: &[Value]) -> ReqDiff {
+    let ids = |events: &[Value]| -> Vec<String> {
+        events.iter().filter_map(|e| e.get("id").and_then(|i| i.as_str()).map(String::from)).collect()
+    };
+    let cassette_ids = ids(cassette);
+    let relay_ids: BTreeSet<String> = ids(relay).into_iter().collect();
+
+    let mut seen = BTreeSet::new();
+    let duplicates: BTreeSet<String> = cassette_ids.iter().filter(|id| !seen.insert(id.as_str())).cloned().collect();
+    let cassette_set: BTreeSet<String> = cassette_ids.into_iter().collect();
+
+    ReqDiff {
+        cassette: cassette.len(),
+        relay: relay.len(),
+        missing: relay_ids.difference(&cassette_set).cloned().collect(),
+        extra: cassette_set.difference(&relay_ids).cloned().collect(),
+        duplicates: duplicates.into_iter().collect(),
+    }
+}
+
+/// Result of comparing COUNT responses
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CountDiff {
+    Match { count: u64 },
+    Mismatch { cassette: Option<u64>, relay: u64 },
+    /// The reference relay did not answer COUNT, so there is nothing to compare against
+    Unsupported { reason: String },
+}
+
+impl CountDiff {
+    pub fn compare(cassette: Option<u64>, relay: std::result::Result<u64, String>) -> Self {
+        match relay {
+            Err(reason) => CountDiff::Unsupported { reason },
+            Ok(relay) if cassette == Some(relay) => CountDiff::Match { count: relay },
+            Ok(relay) => CountDiff::Mismatch { cassette, relay },
+        }
+    }
+
+    pub fn is_mismatch(&self) -> bool {
+        matches!(self, CountDiff::Mismatch { .. })
+    }
+}
+
+/// Outcome of one query
+#[derive(Debug, Serialize)]
+pub struct QueryReport {
+    #[serde(flatten)]
+    pub query: Query,
+    pub req: ReqDiff,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<CountDiff>,
+}
+
+impl QueryReport {
+    pub fn is_match(&self) -> bool {
+        self.req.is_match() && !self.count.as_ref().is_some_and(|c| c.is_mismatch())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str, kind: i64, pubkey: &str, created_at: i64) -> Value {
+        json!({"id": id, "kind": kind, "pubkey": pubkey, "created_at": created_at, "tags": [["t", "nostr"]]})
+    }
+
+    #[test]
+    fn test_default_corpus_and_diff() {
+        let events = vec![event("a", 1, "p1", 10), event("b", 1, "p2", 20), event("c", 7, "p1", 30)];
+        let corpus = default_corpus(&events);
+        assert!(corpus.iter().any(|q| q.filters == vec![json!({"kinds": [1]})]));
+        assert!(corpus.iter().any(|q| q.filters == vec![json!({"authors": ["p1"]})]));
+        assert!(corpus.iter().any(|q| q.filters == vec![json!({"#t": ["nostr"]})]));
+        assert!(corpus.iter().any(|q| q.filters == vec![json!({"since": 20})]));
+
+        let diff = diff_events(&[events[1].clone(), events[0].clone(), events[0].clone()], &events);
+        assert_eq!(diff.missing, vec!["c".to_string()]);
+        assert!(diff.extra.is_empty());
+        assert_eq!(diff.duplicates, vec!["a".to_string()]);
+        assert!(!diff.is_match());
+        assert!(diff_events(&[events[2].clone(), events[0].clone()], &[events[0].clone(), events[2].clone()]).is_match());
+    }
+}
@@ -0,0 +1,138 @@
+//! Sensitive-content (NIP-36) policy for served cassettes
+//!
+//! Events carrying a `content-warning` tag can be withheld from every
+//! response, or only served to subscriptions that ask for them by setting
+//! `"include_sensitive": true` in a filter. The field is a host extension:
+//! it is removed before the query reaches the cassettes, and the policy is
+//! advertised under `content_warning` in the NIP-11 document.
+
+use serde_json::{json, Value};
+
+/// Filter field a subscription sets to receive flagged events under `opt-in`
+pub const OPT_IN_FIELD: &str = "include_sensitive";
+
+/// How a server treats events with a `content-warning` tag
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SensitivePolicy {
+    /// Serve flagged events like any other
+    #[default]
+    Serve,
+    /// Never serve flagged events
+    Exclude,
+    /// Serve flagged events only to subscriptions that set `include_sensitive`
+    OptIn,
+}
+
+impl SensitivePolicy {
+    /// Whether flagged events are withheld from a subscription
+    pub fn withholds(&self, opted_in: bool) -> bool {
+        match self {
+            SensitivePolicy::Serve => false,
+            SensitivePolicy::Exclude => true,
+            SensitivePolicy::OptIn => !opted_in,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SensitivePolicy::Serve => "serve",
+            SensitivePolicy::Exclude => "exclude",
+            SensitivePolicy::OptIn => "opt-in",
+        }
+    }
+}
+
+/// Check whether an event is flagged with a NIP-36 `content-warning` tag
+pub fn is_sensitive(event: &Value) -> bool {
+    event.get("tags").and_then(|t| t.as_array()).into_iter().flatten()
+        .filter_map(|tag| tag.as_array())
+        .any(|tag| tag.first().and_then(|n| n.as_str()) == Some("content-warning"))
+}
+
+/// Same as `is_sensitive` for a raw relay message; anything but an EVENT isn't flagged
+pub fn is_sensitive_message(message: &str) -> bool {
+    match serde_json::from_str::<Vec<Value>>(message) {
+        Ok(parsed) if parsed.len() >= 3 && parsed[0].as_str() == Some("EVENT") => is_sensitive(&parsed[2]),
+        _ => false,
+    }
+}
+
+/// Remove the opt-in field from the filters of a REQ or COUNT
+///
+/// Returns the message to forward to the cassettes and whether any filter opted in.
+/// Other messages are returned unchanged.
+pub fn strip_opt_in(message: &str) -> (String, bool) {
+    let Ok(mut parsed) = serde_json::from_str::<Vec<Value>>(message) else {
+        return (message.to_string(), false);
+    };
+    if !matches!(parsed.first().and_then(|m| m.as_str()), Some("REQ") | Some("COUNT")) {
+        return (message.to_string(), false);
+    }
+
+    let mut opted_in = false;
+    let mut stripped = false;
+    for filter in parsed.iter_mut().skip(2).filter_map(|f| f.as_object_mut()) {
+        if let Some(value) = filter.remove(OPT_IN_FIELD) {
+            opted_in |= value.as_bool() == Some(true);
+            stripped = true;
+        }
+    }
+
+    if stripped {
+        (Value::Array(parsed).to_string(), opted_in)
+    } else {
+        (message.to_string(), false)
+    }
+}
+
+/// Add the policy to a cassette's NIP-11 document
+pub fn advertise(info: &str, policy: SensitivePolicy) -> String {
+    let Ok(Value::Object(mut document)) = serde_json::from_str::<Value>(info) else {
+        return info.to_string();
+    };
+
+    let nips = document.entry("supported_nips").or_insert_with(|| json!([]));
+    if let Some(nips) = nips.as_array_mut() {
+        if !nips.iter().any(|n| n.as_u64() == Some(36)) {
+            nips.push(json!(36));
+            nips.sort_by_key(|n| n.as_u64());
+        }
+    }
+
+    let mut content_warning = json!({"policy": policy.name()});
+    if policy == SensitivePolicy::OptIn {
+        content_warning["opt_in_field"] = json!(OPT_IN_FIELD);
+    }
+    document.insert("content_warning".to_string(), content_warning);
+
+    Value::Object(document).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensitive_policy() {
+        let flagged = json!({"id": "a", "tags": [["t", "art"], ["content-warning", "nudity"]]});
+        let plain = json!({"id": "b", "tags": [["t", "art"]]});
+        assert!(is_sensitive(&flagged));
+        assert!(!is_sensitive(&plain));
+        assert!(is_sensitive_message(&json!(["EVENT", "sub", flagged]).to_string()));
+        assert!(!is_sensitive_message(r#"["EOSE","sub"]"#));
+
+        let (request, opted_in) = strip_opt_in(r#"["REQ","sub",{"kinds":[1],"include_sensitive":true},{"kinds":[7]}]"#);
+        assert!(opted_in);
+        assert_eq!(request, r#"["REQ","sub",{"kinds":[1]},{"kinds":[7]}]"#);
+        assert_eq!(strip_opt_in(r#"["REQ","sub",{"kinds":[1]}]"#).1, false);
+
+        assert!(!SensitivePolicy::Serve.withholds(false));
+        assert!(SensitivePolicy::Exclude.withholds(true));
+        assert!(SensitivePolicy::OptIn.withholds(false));
+        assert!(!SensitivePolicy::OptIn.withholds(true));
+
+        let info: Value = serde_json::from_str(&advertise(r#"{"name":"x","supported_nips":[1,50]}"#, SensitivePolicy::OptIn)).unwrap();
+        assert_eq!(info["supported_nips"], json!([1, 36, 50]));
+        assert_eq!(info["content_warning"], json!({"policy": "opt-in", "opt_in_field": "include_sensitive"}));
+    }
+}
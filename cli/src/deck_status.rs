@@ -0,0 +1,241 @@
+//! Runtime status of a running deck and the admin socket that exposes it
+//!
+//! A deck binds a unix socket (by default `deck.sock` in its output
+//! directory). Every connection receives one JSON `DeckStatus` snapshot
+//! followed by a newline, then the deck closes it, so `cassette deck-status`,
+//! `socat` or a cron health check can read it without speaking Nostr.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Errors kept for the status snapshot; older ones are dropped
+const RECENT_ERRORS: usize = 20;
+
+/// Socket path used when `--admin-socket` is not given
+pub fn default_socket_path(output_dir: &Path) -> PathBuf {
+    output_dir.join("deck.sock")
+}
+
+/// Counters and recent errors shared by the deck's tasks
+pub struct DeckMonitor {
+    mode: String,
+    started_at: SystemTime,
+    event_limit: usize,
+    size_limit_bytes: usize,
+    duration: u64,
+    active_connections: AtomicUsize,
+    total_connections: AtomicUsize,
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+/// Decrements the active connection count when the connection ends
+pub struct ConnectionGuard<'a>(&'a DeckMonitor);
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl DeckMonitor {
+    pub fn new(mode: &str, event_limit: usize, size_limit_mb: usize, duration: u64) -> Self {
+        Self {
+            mode: mode.to_string(),
+            started_at: SystemTime::now(),
+            event_limit,
+            size_limit_bytes: size_limit_mb * 1024 * 1024,
+            duration,
+            active_connections: AtomicUsize::new(0),
+            total_connections: AtomicUsize::new(0),
+            errors: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn connection_opened(&self) -> ConnectionGuard<'_> {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(self)
+    }
+
+    pub fn record_error(&self, message: impl Into<String>) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(RecentError { at: unix_time(SystemTime::now()), message: message.into() });
+    }
+
+    /// Build a snapshot from the recording buffer and the loaded cassettes
+    pub fn snapshot(&self, buffer: BufferStatus, buffer_started: SystemTime, cassettes: &[PathBuf]) -> DeckStatus {
+        let elapsed = buffer_started.elapsed().unwrap_or_default().as_secs();
+        DeckStatus {
+            mode: self.mode.clone(),
+            uptime_secs: self.started_at.elapsed().unwrap_or_default().as_secs(),
+            buffer: BufferStatus {
+                event_limit: self.event_limit,
+                size_limit_bytes: self.size_limit_bytes,
+                ..buffer
+            },
+            next_rotation_secs: (self.duration > 0).then(|| self.duration.saturating_sub(elapsed)),
+            cassettes: cassettes.iter().map(|path| CassetteStatus {
+                path: path.display().to_string(),
+                size_bytes: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+            }).collect(),
+            connections: ConnectionStatus {
+                active: self.active_connections.load(Ordering::Relaxed),
+                total: self.total_connections.load(Ordering::Relaxed),
+            },
+            recent_errors: self.errors.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+/// Point-in-time view of a deck, as served on the admin socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeckStatus {
+    pub mode: String,
+    pub uptime_secs: u64,
+    pub buffer: BufferStatus,
+    /// Seconds until the duration limit rotates the buffer (`None` without a duration limit)
+    pub next_rotation_secs: Option<u64>,
+    pub cassettes: Vec<CassetteStatus>,
+    pub connections: ConnectionStatus,
+    pub recent_errors: Vec<RecentError>,
+}
+
+/// Events waiting in the recording buffer for the next rotation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BufferStatus {
+    pub events: usize,
+    pub event_limit: usize,
+    pub bytes: usize,
+    pub size_limit_bytes: usize,
+    /// A rotation is compiling the buffer into a cassette
+    pub compiling: bool,
+}
+
+impl BufferStatus {
+    /// Fill level towards whichever of the event and size limits is closer
+    pub fn fill(&self) -> f64 {
+        let ratio = |value: usize, limit: usize| if limit == 0 { 0.0 } else { value as f64 / limit as f64 };
+        ratio(self.events, self.event_limit).max(ratio(self.bytes, self.size_limit_bytes))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteStatus {
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    pub active: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentError {
+    /// Unix timestamp
+    pub at: u64,
+    pub message: String,
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Bind the admin socket, replacing a stale socket file but refusing to take over from a live deck
+#[cfg(unix)]
+pub async fn bind(path: &Path) -> Result<tokio::net::UnixListener> {
+    if path.exists() {
+        if tokio::net::UnixStream::connect(path).await.is_ok() {
+            return Err(anyhow!("Another deck is already serving status on {}", path.display()));
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale admin socket {}", path.display()))?;
+    }
+    tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind admin socket {}", path.display()))
+}
+
+/// Answer every connection with the current snapshot
+#[cfg(unix)]
+pub async fn serve<F, Fut>(listener: tokio::net::UnixListener, snapshot: F)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = DeckStatus>,
+{
+    use tokio::io::AsyncWriteExt;
+
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let mut body = serde_json::to_vec(&snapshot().await).unwrap_or_default();
+        body.push(b'\n');
+        let _ = stream.write_all(&body).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+/// Read one snapshot from a deck's admin socket
+#[cfg(unix)]
+pub async fn fetch(path: &Path, wait: Duration) -> Result<DeckStatus> {
+    use tokio::io::AsyncReadExt;
+
+    let read = async {
+        let mut stream = tokio::net::UnixStream::connect(path).await
+            .with_context(|| format!("No deck is listening on {}", path.display()))?;
+        let mut body = String::new();
+        stream.read_to_string(&mut body).await?;
+        serde_json::from_str::<DeckStatus>(&body).context("Invalid status from deck")
+    };
+    tokio::time::timeout(wait, read).await
+        .map_err(|_| anyhow!("Timed out waiting for status on {}", path.display()))?
+}
+
+#[cfg(not(unix))]
+pub async fn fetch(_path: &Path, _wait: Duration) -> Result<DeckStatus> {
+    Err(anyhow!("The deck admin socket requires a unix platform"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_admin_socket_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_socket_path(dir.path());
+        let monitor = std::sync::Arc::new(DeckMonitor::new("relay", 100, 1, 60));
+
+        let _conn = monitor.connection_opened();
+        drop(monitor.connection_opened());
+        for i in 0..RECENT_ERRORS + 5 {
+            monitor.record_error(format!("error {}", i));
+        }
+
+        let listener = bind(&path).await.unwrap();
+        let server = monitor.clone();
+        tokio::spawn(async move {
+            serve(listener, || async {
+                let buffer = BufferStatus { events: 25, bytes: 1024, ..Default::default() };
+                server.snapshot(buffer, SystemTime::now(), &[PathBuf::from("missing.cassette")])
+            }).await
+        });
+        assert!(bind(&path).await.is_err(), "a live socket must not be replaced");
+
+        let status = fetch(&path, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(status.mode, "relay");
+        assert_eq!(status.buffer.event_limit, 100);
+        assert_eq!(status.buffer.fill(), 0.25);
+        assert!(status.next_rotation_secs.unwrap() <= 60);
+        assert_eq!(status.cassettes[0].size_bytes, 0);
+        assert_eq!((status.connections.active, status.connections.total), (1, 2));
+        assert_eq!(status.recent_errors.len(), RECENT_ERRORS);
+        assert_eq!(status.recent_errors[0].message, "error 5");
+    }
+}
@@ -0,0 +1,201 @@
+//! Storage backends for the deck's event index
+//!
+//! The deck keeps the ids it has archived into cassettes (with where each came
+//! from) and the current version of every replaceable event in a small
+//! key-value store. The
+//! in-memory backend forgets everything on exit; the redb backend keeps the
+//! index in `<OUTPUT>/deck-index.redb`, so duplicate checks survive restarts
+//! without querying every cassette and the index isn't bounded by memory.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Index file used by the redb backend
+pub fn default_path(output_dir: &Path) -> PathBuf {
+    output_dir.join("deck-index.redb")
+}
+
+/// Tables of the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Table {
+    /// Event id → origin (`client` or the relay it was recorded from)
+    Events,
+    /// Replaceable event address (`kind:pubkey[:d]`) → id of its current version
+    Replaceable,
+}
+
+impl Table {
+    #[cfg_attr(not(feature = "deck-redb"), allow(dead_code))]
+    fn name(&self) -> &'static str {
+        match self {
+            Table::Events => "events",
+            Table::Replaceable => "replaceable",
+        }
+    }
+}
+
+/// String key-value tables behind the deck's event index
+pub trait DeckStorage: Send + Sync {
+    fn get(&self, table: Table, key: &str) -> Result<Option<String>>;
+    fn insert(&mut self, table: Table, key: &str, value: &str) -> Result<()>;
+    /// Insert several entries at once (one transaction where the backend has them)
+    fn insert_all(&mut self, table: Table, entries: &[(String, String)]) -> Result<()> {
+        for (key, value) in entries {
+            self.insert(table, key, value)?;
+        }
+        Ok(())
+    }
+    fn remove(&mut self, table: Table, key: &str) -> Result<()>;
+    fn len(&self, table: Table) -> Result<u64>;
+}
+
+/// Storage backend chosen with `deck --index-store`
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Keep the index in memory, rebuilt empty on every start
+    #[default]
+    Memory,
+    /// Keep the index in a redb file in the output directory
+    Redb,
+}
+
+/// Open the index of a deck writing to `output_dir`
+pub fn open(backend: Backend, output_dir: &Path) -> Result<Box<dyn DeckStorage>> {
+    match backend {
+        Backend::Memory => Ok(Box::new(MemoryStorage::default())),
+        #[cfg(feature = "deck-redb")]
+        Backend::Redb => Ok(Box::new(RedbStorage::open(&default_path(output_dir))?)),
+        #[cfg(not(feature = "deck-redb"))]
+        Backend::Redb => Err(anyhow!(
+            "--index-store redb needs the CLI built with the deck-redb feature (index would be {})",
+            default_path(output_dir).display()
+        )),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    tables: HashMap<Table, HashMap<String, String>>,
+}
+
+impl DeckStorage for MemoryStorage {
+    fn get(&self, table: Table, key: &str) -> Result<Option<String>> {
+        Ok(self.tables.get(&table).and_then(|t| t.get(key)).cloned())
+    }
+
+    fn insert(&mut self, table: Table, key: &str, value: &str) -> Result<()> {
+        self.tables.entry(table).or_default().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, table: Table, key: &str) -> Result<()> {
+        if let Some(t) = self.tables.get_mut(&table) {
+            t.remove(key);
+        }
+        Ok(())
+    }
+
+    fn len(&self, table: Table) -> Result<u64> {
+        Ok(self.tables.get(&table).map_or(0, |t| t.len() as u64))
+    }
+}
+
+#[cfg(feature = "deck-redb")]
+pub struct RedbStorage {
+    db: redb::Database,
+}
+
+#[cfg(feature = "deck-redb")]
+impl RedbStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = redb::Database::create(path)
+            .map_err(|e| anyhow!("Failed to open event index {}: {}", path.display(), e))?;
+        Ok(Self { db })
+    }
+
+    fn definition(table: Table) -> redb::TableDefinition<'static, &'static str, &'static str> {
+        redb::TableDefinition::new(table.name())
+    }
+}
+
+#[cfg(feature = "deck-redb")]
+impl DeckStorage for RedbStorage {
+    fn get(&self, table: Table, key: &str) -> Result<Option<String>> {
+        let txn = self.db.begin_read()?;
+        let table = match txn.open_table(Self::definition(table)) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(table.get(key)?.map(|value| value.value().to_string()))
+    }
+
+    fn insert(&mut self, table: Table, key: &str, value: &str) -> Result<()> {
+        self.insert_all(table, &[(key.to_string(), value.to_string())])
+    }
+
+    fn insert_all(&mut self, table: Table, entries: &[(String, String)]) -> Result<()> {
+        let mut txn = self.db.begin_write()?;
+        // Losing the last writes in a crash only sends those ids back to the cassette scan
+        txn.set_durability(redb::Durability::Eventual);
+        {
+            let mut table = txn.open_table(Self::definition(table))?;
+            for (key, value) in entries {
+                table.insert(key.as_str(), value.as_str())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, table: Table, key: &str) -> Result<()> {
+        let mut txn = self.db.begin_write()?;
+        txn.set_durability(redb::Durability::Eventual);
+        txn.open_table(Self::definition(table))?.remove(key)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn len(&self, table: Table) -> Result<u64> {
+        use redb::ReadableTableMetadata;
+        let txn = self.db.begin_read()?;
+        match txn.open_table(Self::definition(table)) {
+            Ok(table) => Ok(table.len()?),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(storage: &mut dyn DeckStorage) {
+        assert_eq!(storage.get(Table::Events, "a").unwrap(), None);
+        assert_eq!(storage.len(Table::Events).unwrap(), 0);
+        storage.insert(Table::Events, "a", "client").unwrap();
+        storage.insert_all(Table::Replaceable, &[("0:alice".to_string(), "a".to_string())]).unwrap();
+        assert_eq!(storage.get(Table::Events, "a").unwrap().as_deref(), Some("client"));
+        assert_eq!(storage.get(Table::Replaceable, "a").unwrap(), None);
+        storage.remove(Table::Events, "a").unwrap();
+        assert_eq!(storage.get(Table::Events, "a").unwrap(), None);
+        assert_eq!(storage.len(Table::Replaceable).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_deck_storage() {
+        exercise(&mut MemoryStorage::default());
+
+        #[cfg(feature = "deck-redb")]
+        {
+            let dir = tempfile::tempdir().unwrap();
+            let path = default_path(dir.path());
+            exercise(&mut RedbStorage::open(&path).unwrap());
+            // The index outlives the deck process
+            let reopened = RedbStorage::open(&path).unwrap();
+            assert_eq!(reopened.get(Table::Replaceable, "0:alice").unwrap().as_deref(), Some("a"));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Example queries derived from a cassette's own events
+//!
+//! A schema tells a UI what a filter may look like, not what to ask a given
+//! cassette for. At record time the most common kind, the most active author,
+//! the most used tag and an addressable event are turned into messages that
+//! are known to return events, and embedded under `cassette.examples` in the
+//! NIP-11 info so loaders can offer them as one-click queries.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Most frequent key, the smallest one on a tie
+fn most_common<K: Ord + Clone>(counts: &BTreeMap<K, usize>) -> Option<K> {
+    counts.iter()
+        .max_by(|(a_key, a), (b_key, b)| a.cmp(b).then(b_key.cmp(a_key)))
+        .map(|(key, _)| key.clone())
+}
+
+fn example(description: String, message: Value) -> Value {
+    json!({"description": description, "message": message})
+}
+
+/// Example messages for these events; COUNT and search examples only when the cassette supports them
+pub fn example_queries(events: &[Value], count: bool, search: bool) -> Vec<Value> {
+    let mut kinds = BTreeMap::new();
+    let mut authors = BTreeMap::new();
+    let mut tags = BTreeMap::new();
+    let mut words = BTreeMap::new();
+    let mut addressable: Option<(i64, i64, &str, &str)> = None;
+
+    for event in events {
+        let kind = event.get("kind").and_then(|k| k.as_i64());
+        let pubkey = event.get("pubkey").and_then(|p| p.as_str());
+        let created_at = event.get("created_at").and_then(|c| c.as_i64()).unwrap_or(0);
+        if let Some(kind) = kind {
+            *kinds.entry(kind).or_insert(0) += 1;
+        }
+        if let Some(pubkey) = pubkey {
+            *authors.entry(pubkey.to_string()).or_insert(0) += 1;
+        }
+        for tag in event.get("tags").and_then(|t| t.as_array()).into_iter().flatten() {
+            let (Some(name), Some(value)) = (tag.get(0).and_then(|n| n.as_str()), tag.get(1).and_then(|v| v.as_str())) else { continue };
+            if name.len() != 1 || value.is_empty() {
+                continue;
+            }
+            // Topic and other readable tags make better examples than event and pubkey references
+            let readable = name != "e" && name != "p";
+            *tags.entry((readable, name.to_string(), value.to_string())).or_insert(0) += 1;
+            if name == "d" {
+                if let (Some(kind @ 30000..=39999), Some(pubkey)) = (kind, pubkey) {
+                    if addressable.map_or(true, |(_, newest, _, _)| created_at > newest) {
+                        addressable = Some((kind, created_at, pubkey, value));
+                    }
+                }
+            }
+        }
+        if search && matches!(kind, Some(1) | Some(30023)) {
+            let content = event.get("content").and_then(|c| c.as_str()).unwrap_or("");
+            let prose = content.split_whitespace().filter(|token| !token.contains("://") && !token.starts_with("nostr:"));
+            for word in prose.flat_map(|token| token.split(|c: char| !c.is_alphabetic())).filter(|w| w.chars().count() >= 5) {
+                *words.entry(word.to_lowercase()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut examples = Vec::new();
+    if let Some(kind) = most_common(&kinds) {
+        examples.push(example(
+            format!("The 10 latest kind {} events", kind),
+            json!(["REQ", "example-kind", {"kinds": [kind], "limit": 10}]),
+        ));
+    }
+    if let Some(author) = most_common(&authors) {
+        examples.push(example(
+            "Latest events by the most active author".to_string(),
+            json!(["REQ", "example-author", {"authors": [author], "limit": 10}]),
+        ));
+    }
+    // Prefer readable tags: compare on readability first, then on use
+    let tag = tags.iter()
+        .max_by(|((a_readable, ..), a), ((b_readable, ..), b)| a_readable.cmp(b_readable).then(a.cmp(b)))
+        .map(|((_, name, value), _)| (name.clone(), value.clone()));
+    if let Some((name, value)) = tag {
+        examples.push(example(
+            format!("Events tagged {} {}", name, value),
+            json!(["REQ", "example-tag", {format!("#{}", name): [value], "limit": 10}]),
+        ));
+    }
+    if let Some((kind, _, pubkey, d)) = addressable {
+        examples.push(example(
+            format!("The addressable event {}:{}:{}", kind, pubkey, d),
+            json!(["REQ", "example-address", {"kinds": [kind], "authors": [pubkey], "#d": [d]}]),
+        ));
+    }
+    if count {
+        if let Some(kind) = most_common(&kinds) {
+            examples.push(example(
+                format!("How many kind {} events there are", kind),
+                json!(["COUNT", "example-count", {"kinds": [kind]}]),
+            ));
+        }
+    }
+    if let Some(word) = most_common(&words) {
+        examples.push(example(
+            format!("Notes mentioning \"{}\"", word),
+            json!(["REQ", "example-search", {"search": word, "limit": 10}]),
+        ));
+    }
+    examples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_queries() {
+        let events = vec![
+            json!({"kind": 1, "pubkey": "alice", "created_at": 1, "tags": [["t", "nostr"], ["p", "bob"]], "content": "Hello nostr world https://hello.example"}),
+            json!({"kind": 1, "pubkey": "alice", "created_at": 2, "tags": [["p", "bob"], ["p", "bob"]], "content": "Hello again, nostr:npub1hello"}),
+            json!({"kind": 30023, "pubkey": "bob", "created_at": 3, "tags": [["d", "post"]], "content": ""}),
+        ];
+        let examples = example_queries(&events, true, true);
+        let messages: Vec<&Value> = examples.iter().map(|e| &e["message"]).collect();
+        assert_eq!(messages[0], &json!(["REQ", "example-kind", {"kinds": [1], "limit": 10}]));
+        assert_eq!(messages[1][2]["authors"], json!(["alice"]));
+        // `p` is used more often, but `t` is the readable one
+        assert_eq!(messages[2][2]["#t"], json!(["nostr"]));
+        assert_eq!(messages[3][2], json!({"kinds": [30023], "authors": ["bob"], "#d": ["post"]}));
+        assert_eq!(messages[4][0], "COUNT");
+        assert_eq!(messages[5][2]["search"], "hello");
+
+        assert_eq!(example_queries(&events, false, false).len(), 4);
+        assert!(example_queries(&[], true, true).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Writing a cassette's events out for `cassette export`
+//!
+//! Events go out as NDJSON (one event per line), a JSON array, or an SQLite
+//! database. The database has an `events` table with one row per event and a
+//! `tags` table with one row per tag, indexed by name and value, so tag queries
+//! don't need JSON functions; events already in the database are left alone,
+//! which makes exporting several cassettes into one file an append. SQLite is
+//! a C library, so writing it needs the CLI built with the `sqlite` feature.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::io::Write;
+use std::path::Path;
+
+/// Tables and indexes of an exported database
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id TEXT PRIMARY KEY,
+    pubkey TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    sig TEXT NOT NULL,
+    tags TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tags (
+    event_id TEXT NOT NULL REFERENCES events(id),
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT,
+    PRIMARY KEY (event_id, position)
+);
+CREATE INDEX IF NOT EXISTS events_pubkey ON events(pubkey, created_at);
+CREATE INDEX IF NOT EXISTS events_kind ON events(kind, created_at);
+CREATE INDEX IF NOT EXISTS tags_name_value ON tags(name, value);
+";
+
+/// Format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ndjson,
+    Json,
+    Sqlite,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "json" => Ok(Format::Json),
+            "sqlite" | "sqlite3" | "db" => Ok(Format::Sqlite),
+            _ => Err(anyhow!("Unsupported export format '{}', use ndjson, json or sqlite", name)),
+        }
+    }
+
+    /// The format an output file's extension names; NDJSON for anything else
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        Self::parse(extension).unwrap_or(Format::Ndjson)
+    }
+}
+
+/// One event per line
+pub fn write_ndjson<W: Write + ?Sized>(events: &[Value], out: &mut W) -> Result<()> {
+    for event in events {
+        serde_json::to_writer(&mut *out, event)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// A pretty-printed JSON array
+pub fn write_json<W: Write + ?Sized>(events: &[Value], out: &mut W) -> Result<()> {
+    serde_json::to_writer_pretty(&mut *out, events)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// The `(position, name, value)` rows of an event's tags
+pub fn tag_rows(event: &Value) -> Vec<(usize, String, Option<String>)> {
+    event.get("tags")
+        .and_then(|tags| tags.as_array())
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(position, tag)| {
+            let name = tag.get(0)?.as_str()?.to_string();
+            let value = tag.get(1).and_then(|v| v.as_str()).map(str::to_string);
+            Some((position, name, value))
+        })
+        .collect()
+}
+
+/// Insert events into the database at `path`, creating it when missing; returns
+/// how many were new
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(events: &[Value], path: &Path) -> Result<usize> {
+    let mut db = rusqlite::Connection::open(path)
+        .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+    db.execute_batch(SCHEMA)?;
+
+    let transaction = db.transaction()?;
+    let mut inserted = 0;
+    {
+        let mut insert_event = transaction.prepare(
+            "INSERT OR IGNORE INTO events (id, pubkey, created_at, kind, content, sig, tags) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        let mut insert_tag = transaction.prepare(
+            "INSERT OR IGNORE INTO tags (event_id, position, name, value) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let text = |event: &Value, key: &str| event.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        for event in events {
+            let id = text(event, "id");
+            let added = insert_event.execute(rusqlite::params![
+                id,
+                text(event, "pubkey"),
+                event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0),
+                event.get("kind").and_then(|k| k.as_i64()).unwrap_or(0),
+                text(event, "content"),
+                text(event, "sig"),
+                event.get("tags").map_or_else(|| "[]".to_string(), Value::to_string),
+            ])?;
+            if added == 0 {
+                continue;
+            }
+            inserted += 1;
+            for (position, name, value) in tag_rows(event) {
+                insert_tag.execute(rusqlite::params![id, position as i64, name, value])?;
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(inserted)
+}
+
+/// Insert events into the database at `path`, creating it when missing; returns
+/// how many were new
+#[cfg(not(feature = "sqlite"))]
+pub fn write_sqlite(_events: &[Value], path: &Path) -> Result<usize> {
+    Err(anyhow!(
+        "--to sqlite needs the CLI built with the sqlite feature (can't write {})",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_export_formats() {
+        assert_eq!(Format::parse("SQLite").unwrap(), Format::Sqlite);
+        assert!(Format::parse("xml").is_err());
+        assert_eq!(Format::from_path(Path::new("events.ndjson")), Format::Ndjson);
+        assert_eq!(Format::from_path(Path::new("events.json")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("events.db")), Format::Sqlite);
+        assert_eq!(Format::from_path(Path::new("events")), Format::Ndjson);
+
+        let events = vec![
+            json!({"id": "aa", "kind": 1, "tags": [["t", "nostr"], ["e", "bb", "wss://relay"], [], ["client"]]}),
+            json!({"id": "bb", "kind": 0, "tags": []}),
+        ];
+        let mut ndjson = Vec::new();
+        write_ndjson(&events, &mut ndjson).unwrap();
+        let lines: Vec<Value> = String::from_utf8(ndjson).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, events);
+
+        let mut array = Vec::new();
+        write_json(&events, &mut array).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&array).unwrap(), json!(events));
+
+        assert_eq!(tag_rows(&events[0]), vec![
+            (0, "t".to_string(), Some("nostr".to_string())),
+            (1, "e".to_string(), Some("bb".to_string())),
+            (3, "client".to_string(), None),
+        ]);
+        assert!(tag_rows(&events[1]).is_empty());
+    }
+}
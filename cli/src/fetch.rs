@@ -0,0 +1,244 @@
+//! Paginated history download from relays for `record --relays`
+//!
+//! Public relays cap how much a single REQ may return, so the history is
+//! walked backwards in pages: each REQ carries `until` (the oldest
+//! created_at seen so far) and a `limit` sized so the expected response
+//! stays within a byte budget. A CLOSED, a NOTICE naming the subscription or
+//! a timeout in place of EOSE halves the limit and retries the same window;
+//! other NOTICEs are about something else and are ignored.
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+/// Consecutive failed pages at a limit of 1 before giving up on a relay
+const MAX_FAILURES: usize = 5;
+
+/// Page sizing for a history download
+#[derive(Debug, Clone, Copy)]
+pub struct PageOptions {
+    /// Largest limit asked for in one REQ
+    pub max_limit: usize,
+    /// Expected response size per page, used to size the limit from the events seen so far
+    pub byte_budget: usize,
+    /// How long to wait for a page to end
+    pub timeout: Duration,
+}
+
+/// Plans the descending `until` windows of a history download
+pub struct Paginator {
+    base: serde_json::Map<String, Value>,
+    until: i64,
+    since: Option<i64>,
+    limit: usize,
+    // Below the smallest limit the relay refused
+    ceiling: usize,
+    options: PageOptions,
+    seen: HashSet<String>,
+    bytes: usize,
+    failures: usize,
+    done: bool,
+}
+
+impl Paginator {
+    /// Start from the filter's own `until` (or now) and walk back to its `since`
+    pub fn new(filter: &Value, options: PageOptions) -> Self {
+        let mut base = filter.as_object().cloned().unwrap_or_default();
+        let user_limit = base.remove("limit").and_then(|l| l.as_u64()).map(|l| l as usize);
+        let until = base.remove("until").and_then(|u| u.as_i64()).unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+        });
+        let since = base.get("since").and_then(|s| s.as_i64());
+        Self {
+            base,
+            until,
+            since,
+            limit: user_limit.unwrap_or(options.max_limit).clamp(1, options.max_limit.max(1)),
+            ceiling: options.max_limit.max(1),
+            options,
+            seen: HashSet::new(),
+            bytes: 0,
+            failures: 0,
+            done: false,
+        }
+    }
+
+    /// Filter for the next page, or `None` once the window is exhausted
+    pub fn next_filter(&self) -> Option<Value> {
+        if self.done || self.since.map_or(false, |since| self.until < since) {
+            return None;
+        }
+        let mut filter = self.base.clone();
+        filter.insert("until".to_string(), json!(self.until));
+        filter.insert("limit".to_string(), json!(self.limit));
+        Some(Value::Object(filter))
+    }
+
+    /// Take a complete page and return the events not seen on earlier pages
+    pub fn page_done(&mut self, events: Vec<Value>) -> Vec<Value> {
+        self.failures = 0;
+        let oldest = events.iter().filter_map(|e| e.get("created_at").and_then(|c| c.as_i64())).min();
+        let mut fresh = Vec::new();
+        for event in events {
+            let Some(id) = event.get("id").and_then(|i| i.as_str()) else { continue };
+            if self.seen.insert(id.to_string()) {
+                self.bytes += event.to_string().len();
+                fresh.push(event);
+            }
+        }
+
+        match oldest {
+            // `until` is inclusive, so the oldest second is asked again and deduplicated; more
+            // events than a page holds may share it
+            Some(oldest) if !fresh.is_empty() => self.until = self.until.min(oldest),
+            // Nothing new from that second: move past it
+            Some(oldest) if oldest <= self.until => self.until = oldest - 1,
+            // Nothing left, or a relay ignoring `until` that has nothing older to give
+            _ => self.done = true,
+        }
+
+        // Size the next page from the average event seen so far
+        if !self.seen.is_empty() {
+            let average = (self.bytes / self.seen.len()).max(1);
+            self.limit = (self.options.byte_budget / average).clamp(1, self.ceiling);
+        }
+        fresh
+    }
+
+    /// A page ended in a CLOSED, NOTICE or timeout: retry the window with half the limit
+    pub fn page_failed(&mut self, reason: &str) -> Result<()> {
+        if self.limit == 1 {
+            self.failures += 1;
+            if self.failures >= MAX_FAILURES {
+                return Err(anyhow!("relay keeps refusing single-event pages: {}", reason));
+            }
+        }
+        self.limit = (self.limit / 2).max(1);
+        self.ceiling = self.ceiling.min(self.limit);
+        Ok(())
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+/// How a page request ended
+enum PageEnd {
+    Eose(Vec<Value>),
+    Refused(String),
+}
+
+/// Download every event matching `filter` from a relay, newest first
+pub async fn fetch_history(relay_url: &str, filter: &Value, options: PageOptions, verbose: bool) -> Result<Vec<Value>> {
+    let (ws_stream, _) = connect_async(relay_url).await
+        .with_context(|| format!("Failed to connect to {}", relay_url))?;
+    let (mut write, mut read) = ws_stream.split();
+
+    let mut pager = Paginator::new(filter, options);
+    let mut events = Vec::new();
+    let mut page = 0;
+    while let Some(page_filter) = pager.next_filter() {
+        page += 1;
+        // Pages alternate between two subscriptions, each REQ replacing the one before last:
+        // no CLOSE (and no NOTICE some relays answer it with) and nothing stale matches a page
+        let sub_id = if page % 2 == 1 { "fetch-a" } else { "fetch-b" };
+        write.send(Message::Text(json!(["REQ", sub_id, page_filter]).to_string())).await?;
+
+        let end = tokio::time::timeout(options.timeout, read_page(&mut read, sub_id)).await
+            .unwrap_or_else(|_| Ok(PageEnd::Refused("timed out".to_string())))?;
+
+        match end {
+            PageEnd::Eose(page_events) => {
+                let fresh = pager.page_done(page_events);
+                if verbose {
+                    println!("  📄 {} page {}: {} new events (total {})", relay_url, page, fresh.len(), events.len() + fresh.len());
+                }
+                events.extend(fresh);
+            }
+            PageEnd::Refused(reason) => {
+                pager.page_failed(&reason).with_context(|| format!("Giving up on {}", relay_url))?;
+                if verbose {
+                    println!("  ↩️  {} refused page {} ({}), retrying with limit {}", relay_url, page, reason, pager.limit());
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+async fn read_page<R>(read: &mut R, sub_id: &str) -> Result<PageEnd>
+where
+    R: StreamExt<Item = std::result::Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let mut events = Vec::new();
+    while let Some(message) = read.next().await {
+        let Message::Text(text) = message? else { continue };
+        let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&text) else { continue };
+        let for_page = parsed.get(1).and_then(|s| s.as_str()) == Some(sub_id);
+        match parsed.first().and_then(|c| c.as_str()) {
+            Some("EVENT") if for_page && parsed.len() >= 3 => events.push(parsed[2].clone()),
+            Some("EOSE") if for_page => return Ok(PageEnd::Eose(events)),
+            Some("CLOSED") if for_page => {
+                let reason = parsed.get(2).and_then(|r| r.as_str()).unwrap_or("closed");
+                return Ok(PageEnd::Refused(reason.to_string()));
+            }
+            // NOTICEs carry no subscription id; only one naming the page's is taken as a refusal
+            Some("NOTICE") => {
+                if let Some(reason) = parsed.get(1).and_then(|r| r.as_str()).filter(|reason| reason.contains(sub_id)) {
+                    return Ok(PageEnd::Refused(reason.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow!("connection closed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginator_windows() {
+        let options = PageOptions { max_limit: 100, byte_budget: 1000, timeout: Duration::from_secs(1) };
+        let event = |id: &str, created_at: i64| json!({"id": id, "created_at": created_at, "content": "x".repeat(60)});
+        let mut pager = Paginator::new(&json!({"kinds": [1], "until": 500, "since": 100}), options);
+        assert_eq!(pager.next_filter().unwrap(), json!({"kinds": [1], "since": 100, "until": 500, "limit": 100}));
+
+        // A refused page retries the same window with half the limit
+        pager.page_failed("response too large").unwrap();
+        assert_eq!(pager.next_filter().unwrap()["until"], 500);
+        assert_eq!(pager.limit(), 50);
+
+        let fresh = pager.page_done(vec![event("a", 450), event("b", 300)]);
+        assert_eq!(fresh.len(), 2);
+        let next = pager.next_filter().unwrap();
+        assert_eq!(next["until"], 300);
+        assert!(pager.limit() < 50, "~100 byte events fit about 10 per 1000 byte page");
+
+        // A budget that allows more than the relay accepted stays under the refused limit
+        let mut strict = Paginator::new(&json!({}), PageOptions { byte_budget: 100_000, ..options });
+        strict.page_failed("too many").unwrap();
+        strict.page_done(vec![event("c", 10)]);
+        assert_eq!(strict.limit(), 50);
+
+        // A second holding more events than a page is asked again until it adds nothing new
+        assert_eq!(pager.page_done(vec![event("b", 300), event("c", 300)]).len(), 1);
+        assert_eq!(pager.next_filter().unwrap()["until"], 300);
+        assert!(pager.page_done(vec![event("b", 300), event("c", 300)]).is_empty());
+        assert_eq!(pager.next_filter().unwrap()["until"], 299);
+
+        assert!(pager.page_done(Vec::new()).is_empty());
+        assert_eq!(pager.next_filter(), None);
+
+        let mut stubborn = Paginator::new(&json!({"limit": 1}), options);
+        for _ in 0..MAX_FAILURES - 1 {
+            stubborn.page_failed("no").unwrap();
+        }
+        assert!(stubborn.page_failed("no").is_err());
+    }
+}
@@ -0,0 +1,687 @@
+//! One view of a cassette module's exports across guest ABI generations
+//!
+//! ABI v1 cassettes, built before the `send` interface, export `req`, `close`,
+//! `alloc_string` and `describe`; v2 ones export `send`, `alloc_buffer` and
+//! `info`. Both answer with a string in guest memory, either an `MSGB` header
+//! followed by the length and the bytes, or NUL-terminated. Cassettes built
+//! since the `abi_version` export declare their version; older ones are
+//! placed by their export names, and versions newer than
+//! `cassette_tools::ABI_VERSION` are refused. `GuestApi` resolves the exports
+//! once, routes CLOSE to a v1 cassette's `close`, and hides the pointer
+//! handling, so callers only deal in messages. Cassettes recorded with a sidecar payload also export
+//! `load_payload`, which `attach_payload` feeds before the first query;
+//! cassettes recorded with `--encrypt` export `unlock`, which `attach_payload`
+//! calls with the key in `CASSETTE_UNLOCK_KEY`, and
+//! cassettes that hide expired events (NIP-40) export `set_current_time`,
+//! which `send` calls with the host's clock before every message. Current
+//! cassettes also export `req_page`, which answers a REQ a bounded page at a
+//! time instead of one event per call. Cassettes recorded with `--verify`
+//! export `verify_events`, which checks every event's id and signature, and
+//! `result_begin`/`result_next_chunk`/`result_end`, which hand a message's
+//! whole answer out in bounded chunks; `replies` reads through them when the
+//! guest has them, and `GuestEvents` streams a REQ's events out of an
+//! instance it owns.
+//! A panic inside a wasm cassette traps the call; cassettes that export
+//! `last_panic` keep the panic's message, and a trapped call answers with the
+//! `["NOTICE", "internal error: ..."]` it returns instead of failing.
+//! Cassettes dubbed with `--provenance` answer `provenance` with the input an
+//! event came from.
+
+use anyhow::{anyhow, Context, Result};
+use cassette_tools::{Page, Verification};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::Path;
+use wasmtime::{Engine, Instance, Memory, Module, Store, TypedFunc};
+
+/// Environment variable holding the key for cassettes recorded with `--encrypt`
+pub const UNLOCK_KEY_VAR: &str = "CASSETTE_UNLOCK_KEY";
+
+pub struct GuestApi {
+    memory: Memory,
+    send: TypedFunc<(i32, i32), i32>,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+    allocation_size: Option<TypedFunc<i32, i32>>,
+    info: Option<TypedFunc<(), i32>>,
+    set_info: Option<TypedFunc<(i32, i32), i32>>,
+    load_payload: Option<TypedFunc<(i32, i32), i32>>,
+    unlock: Option<TypedFunc<(i32, i32), i32>>,
+    set_current_time: Option<TypedFunc<i64, ()>>,
+    req_page: Option<TypedFunc<(i32, i32, i64, i32), i32>>,
+    verify_events: Option<TypedFunc<(), i32>>,
+    provenance: Option<TypedFunc<(i32, i32), i32>>,
+    // v1 cassettes answer CLOSE through their own export
+    close: Option<TypedFunc<(i32, i32), i32>>,
+    last_panic: Option<TypedFunc<(), i32>>,
+    result_begin: Option<TypedFunc<(i32, i32, i32), i32>>,
+    result_next_chunk: Option<TypedFunc<(), i32>>,
+    result_end: Option<TypedFunc<(), ()>>,
+    abi_version: u32,
+}
+
+/// A message's answer read one message at a time: a bounded chunk per guest call
+/// from guests with the chunked result exports, one call per message from others
+pub struct Replies {
+    message: String,
+    // Messages of the current chunk, for chunked guests
+    chunk: Option<VecDeque<String>>,
+}
+
+impl Replies {
+    /// The next message of the answer; `None` once a chunked answer is drained or
+    /// the guest has nothing to answer
+    pub fn next<T>(&mut self, guest: &GuestApi, store: &mut Store<T>) -> Result<Option<String>> {
+        let Some(chunk) = &mut self.chunk else { return guest.send(store, &self.message) };
+        while chunk.is_empty() {
+            match guest.next_chunk(store)? {
+                Some(next) => chunk.extend(next.lines().map(str::to_string)),
+                None => return Ok(None),
+            }
+        }
+        Ok(chunk.pop_front())
+    }
+}
+
+/// The events answering a REQ, read one at a time from an instance the stream owns,
+/// so a cassette's events can be consumed without holding all of them
+pub struct GuestEvents {
+    store: Store<()>,
+    guest: GuestApi,
+    replies: Replies,
+    done: bool,
+}
+
+impl GuestEvents {
+    pub fn new(mut store: Store<()>, guest: GuestApi, filter: &Value) -> Result<Self> {
+        let replies = guest.replies(&mut store, &serde_json::json!(["REQ", "stream", filter]).to_string())?;
+        Ok(Self { store, guest, replies, done: false })
+    }
+}
+
+/// The next event of an answer, `None` once it ends
+fn next_event<T>(replies: &mut Replies, guest: &GuestApi, store: &mut Store<T>) -> Result<Option<Value>> {
+    while let Some(reply) = replies.next(guest, store)? {
+        let message: Value = serde_json::from_str(&reply)?;
+        match message.get(0).and_then(|t| t.as_str()) {
+            Some("EVENT") => {
+                if let Some(event) = message.get(2) {
+                    return Ok(Some(event.clone()));
+                }
+            }
+            Some("EOSE") | Some("CLOSED") => break,
+            Some("NOTICE") if reply.contains("No more events") => break,
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+impl Iterator for GuestEvents {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = next_event(&mut self.replies, &self.guest, &mut self.store).transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+/// The events of an answer read from an instance the caller keeps using afterwards,
+/// e.g. to `end_result` an answer it stopped reading early
+pub struct ReplyEvents<'a, T> {
+    store: &'a mut Store<T>,
+    guest: &'a GuestApi,
+    replies: Replies,
+    done: bool,
+}
+
+impl<'a, T> ReplyEvents<'a, T> {
+    pub fn new(store: &'a mut Store<T>, guest: &'a GuestApi, replies: Replies) -> Self {
+        Self { store, guest, replies, done: false }
+    }
+}
+
+impl<T> Iterator for ReplyEvents<'_, T> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = next_event(&mut self.replies, self.guest, self.store).transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+impl GuestApi {
+    /// Resolve the exports of an instance for the ABI version it declares, or the
+    /// one its export names point to
+    pub fn detect<T>(store: &mut Store<T>, instance: &Instance) -> Result<Self> {
+        let memory = instance.get_memory(&mut *store, "memory")
+            .ok_or_else(|| anyhow!("Memory export not found"))?;
+        let send = instance.get_typed_func::<(i32, i32), i32>(&mut *store, "send")
+            .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut *store, "scrub"));
+        let abi_version = match instance.get_typed_func::<(), i32>(&mut *store, "abi_version") {
+            Ok(abi_version) => abi_version.call(&mut *store, ())? as u32,
+            Err(_) if send.is_ok() => 2,
+            Err(_) => 1,
+        };
+        if !(1..=cassette_tools::ABI_VERSION).contains(&abi_version) {
+            return Err(anyhow!(
+                "Cassette uses guest ABI v{}, this build understands v1 to v{}",
+                abi_version, cassette_tools::ABI_VERSION
+            ));
+        }
+        let send = match abi_version {
+            1 => instance.get_typed_func::<(i32, i32), i32>(&mut *store, "req")
+                .context("Failed to get req function of a v1 cassette")?,
+            _ => send.context("Failed to get send/scrub function")?,
+        };
+        let close = match abi_version {
+            1 => instance.get_typed_func(&mut *store, "close").ok(),
+            _ => None,
+        };
+        let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "alloc_buffer")
+            .or_else(|_| instance.get_typed_func::<i32, i32>(&mut *store, "alloc_string"))
+            .context("Failed to get allocation function")?;
+        Ok(Self {
+            memory,
+            send,
+            alloc,
+            dealloc: instance.get_typed_func(&mut *store, "dealloc_string").ok(),
+            allocation_size: instance.get_typed_func(&mut *store, "get_allocation_size").ok(),
+            info: instance.get_typed_func(&mut *store, "info")
+                .or_else(|_| instance.get_typed_func(&mut *store, "describe"))
+                .ok(),
+            set_info: instance.get_typed_func(&mut *store, "set_info").ok(),
+            load_payload: instance.get_typed_func(&mut *store, "load_payload").ok(),
+            unlock: instance.get_typed_func(&mut *store, "unlock").ok(),
+            set_current_time: instance.get_typed_func(&mut *store, "set_current_time").ok(),
+            req_page: instance.get_typed_func(&mut *store, "req_page").ok(),
+            verify_events: instance.get_typed_func(&mut *store, "verify_events").ok(),
+            provenance: instance.get_typed_func(&mut *store, "provenance").ok(),
+            close,
+            last_panic: instance.get_typed_func(&mut *store, "last_panic").ok(),
+            result_begin: instance.get_typed_func(&mut *store, "result_begin").ok(),
+            result_next_chunk: instance.get_typed_func(&mut *store, "result_next_chunk").ok(),
+            result_end: instance.get_typed_func(&mut *store, "result_end").ok(),
+            abi_version,
+        })
+    }
+
+    /// A fresh instance of a compiled cassette with its exports resolved
+    pub fn instantiate(engine: &Engine, module: &Module) -> Result<(Store<()>, Self)> {
+        let mut store = Store::new(engine, ());
+        let instance = Instance::new(&mut store, module, &[])?;
+        let api = Self::detect(&mut store, &instance)?;
+        Ok((store, api))
+    }
+
+    /// A fresh instance ready to answer queries, with its sidecar payload attached
+    pub fn open(engine: &Engine, module: &Module, cassette_path: &Path) -> Result<(Store<()>, Self)> {
+        let (mut store, api) = Self::instantiate(engine, module)?;
+        api.attach_payload(&mut store, cassette_path)?;
+        Ok((store, api))
+    }
+
+    /// Hand a sidecar-payload cassette its events after checking them against the
+    /// hash in its info, and unlock an encrypted one with the key in `CASSETTE_UNLOCK_KEY`;
+    /// a no-op for cassettes with plain compiled-in events
+    pub fn attach_payload<T>(&self, store: &mut Store<T>, cassette_path: &Path) -> Result<()> {
+        if self.unlock.is_some() {
+            let key = std::env::var(UNLOCK_KEY_VAR).map_err(|_| anyhow!(
+                "{} is encrypted; set {} to the recipient's nsec, hex secret key or key file",
+                cassette_path.display(), UNLOCK_KEY_VAR
+            ))?;
+            let secret = crate::signing::load_secret(&key)?;
+            self.unlock(store, &hex::encode(secret))
+                .with_context(|| format!("Failed to unlock {}", cassette_path.display()))?;
+        }
+        let Some(load_payload) = &self.load_payload else { return Ok(()) };
+        let info = self.info(store)?
+            .ok_or_else(|| anyhow!("{} expects a payload but has no info", cassette_path.display()))?;
+        let payload = crate::payload::read_verified(cassette_path, &info)?;
+        let (ptr, len) = self.write(store, &payload)?;
+        let status = load_payload.call(&mut *store, (ptr, len))?;
+        self.free(store, ptr, len)?;
+        if status != 1 {
+            return Err(anyhow!("{} rejected its payload", cassette_path.display()));
+        }
+        Ok(())
+    }
+
+    /// Decrypt an encrypted cassette's events with the recipient's hex secret key
+    pub fn unlock<T>(&self, store: &mut Store<T>, secret_key: &str) -> Result<()> {
+        let Some(unlock) = &self.unlock else { return Ok(()) };
+        let (ptr, len) = self.write(store, secret_key)?;
+        let status = unlock.call(&mut *store, (ptr, len))?;
+        self.free(store, ptr, len)?;
+        if status != 1 {
+            return Err(anyhow!("wrong key: the events did not decrypt"));
+        }
+        Ok(())
+    }
+
+    /// Send one client message; `None` when the guest has nothing to answer
+    pub fn send<T>(&self, store: &mut Store<T>, message: &str) -> Result<Option<String>> {
+        if let Some(set_current_time) = &self.set_current_time {
+            set_current_time.call(&mut *store, unix_now())?;
+        }
+        let send = match &self.close {
+            Some(close) if is_close(message) => close,
+            _ => &self.send,
+        };
+        let (ptr, len) = self.write(store, message)?;
+        let result_ptr = send.call(&mut *store, (ptr, len)).or_else(|trap| self.recover(store, trap))?;
+        self.free(store, ptr, len)?;
+        if result_ptr == 0 {
+            return Ok(None);
+        }
+        self.take_string(store, result_ptr).map(Some)
+    }
+
+    /// Read the answer to a message, in chunks of the guest's default size when it can
+    /// hand them out
+    pub fn replies<T>(&self, store: &mut Store<T>, message: &str) -> Result<Replies> {
+        let chunked = self.begin_result(store, message, 0)?.is_some();
+        Ok(Replies { message: message.to_string(), chunk: chunked.then(VecDeque::new) })
+    }
+
+    /// Queue a message's whole answer in the guest for `next_chunk`, in chunks of at
+    /// most `chunk_bytes` (0 for the guest's default); `None` when the guest has no
+    /// `result_begin` export, else the number of messages queued
+    pub fn begin_result<T>(&self, store: &mut Store<T>, message: &str, chunk_bytes: u32) -> Result<Option<u64>> {
+        let Some(result_begin) = &self.result_begin else { return Ok(None) };
+        if let Some(set_current_time) = &self.set_current_time {
+            set_current_time.call(&mut *store, unix_now())?;
+        }
+        let (ptr, len) = self.write(store, message)?;
+        let queued = match result_begin.call(&mut *store, (ptr, len, chunk_bytes as i32)) {
+            Ok(queued) => queued,
+            Err(trap) => {
+                let ptr = self.recover(store, trap)?;
+                return Err(anyhow!("Cassette failed to answer: {}", self.take_string(store, ptr)?));
+            }
+        };
+        self.free(store, ptr, len)?;
+        match queued {
+            queued if queued < 0 => Err(anyhow!("Cassette couldn't read the message")),
+            queued => Ok(Some(queued as u64)),
+        }
+    }
+
+    /// The next chunk of newline-separated messages queued by `begin_result`, `None`
+    /// once it is drained
+    pub fn next_chunk<T>(&self, store: &mut Store<T>) -> Result<Option<String>> {
+        let Some(result_next_chunk) = &self.result_next_chunk else { return Ok(None) };
+        match result_next_chunk.call(&mut *store, ()).or_else(|trap| self.recover(store, trap))? {
+            0 => Ok(None),
+            ptr => self.take_string(store, ptr).map(Some),
+        }
+    }
+
+    /// Drop what is left of the answer queued by `begin_result`
+    pub fn end_result<T>(&self, store: &mut Store<T>) -> Result<()> {
+        if let Some(result_end) = &self.result_end {
+            result_end.call(&mut *store, ())?;
+        }
+        Ok(())
+    }
+
+    /// One page of a REQ's results starting at `cursor`; `None` when the guest has no
+    /// `req_page` export, an error carrying the reason when it refuses the REQ
+    pub fn req_page<T>(&self, store: &mut Store<T>, message: &str, cursor: u64, page_size: u32) -> Result<Option<Page>> {
+        let Some(req_page) = &self.req_page else { return Ok(None) };
+        if let Some(set_current_time) = &self.set_current_time {
+            set_current_time.call(&mut *store, unix_now())?;
+        }
+        let (ptr, len) = self.write(store, message)?;
+        let result_ptr = req_page.call(&mut *store, (ptr, len, cursor as i64, page_size as i32))
+            .or_else(|trap| self.recover(store, trap))?;
+        self.free(store, ptr, len)?;
+        if result_ptr == 0 {
+            return Err(anyhow!("Cassette returned no page"));
+        }
+        let response = self.take_string(store, result_ptr)?;
+        // A page is an object; a refused REQ is answered with a CLOSED or NOTICE message
+        match serde_json::from_str::<Value>(&response)? {
+            Value::Array(_) => Err(anyhow!("Cassette refused the REQ: {}", response)),
+            page => Ok(Some(serde_json::from_value(page).context("Malformed page")?)),
+        }
+    }
+
+    /// Send a COUNT message and read the count, `None` when the guest doesn't answer with one
+    pub fn count<T>(&self, store: &mut Store<T>, message: &str) -> Result<Option<u64>> {
+        Ok(self.send(store, message)?
+            .and_then(|response| serde_json::from_str::<Value>(&response).ok())
+            .filter(|r| r.get(0).and_then(|c| c.as_str()) == Some("COUNT"))
+            .and_then(|r| r.get(2).and_then(|c| c.get("count")).and_then(|c| c.as_u64())))
+    }
+
+    /// Have the guest check the id and signature of every event it holds; `None` when it
+    /// was recorded without `--verify`
+    pub fn verify_events<T>(&self, store: &mut Store<T>) -> Result<Option<Verification>> {
+        let Some(verify_events) = &self.verify_events else { return Ok(None) };
+        let result_ptr = verify_events.call(&mut *store, ()).or_else(|trap| self.recover(store, trap))?;
+        if result_ptr == 0 {
+            return Err(anyhow!("Cassette returned no verification"));
+        }
+        let response = self.take_string(store, result_ptr)?;
+        serde_json::from_str(&response)
+            .map(Some)
+            .map_err(|_| anyhow!("Cassette failed to verify its events: {}", response))
+    }
+
+    /// The guest ABI version the cassette was spoken to with
+    /// The dub input an event came from, as `{"name", "sha256"}` JSON; `None` when the
+    /// cassette has no provenance table or doesn't list the event
+    pub fn provenance<T>(&self, store: &mut Store<T>, event_id: &str) -> Result<Option<String>> {
+        let Some(provenance) = &self.provenance else { return Ok(None) };
+        let (ptr, len) = self.write(store, event_id)?;
+        let result_ptr = provenance.call(&mut *store, (ptr, len))?;
+        self.free(store, ptr, len)?;
+        if result_ptr == 0 {
+            return Ok(None);
+        }
+        self.take_string(store, result_ptr).map(Some)
+    }
+
+    /// Whether the cassette exports a provenance table lookup
+    pub fn has_provenance(&self) -> bool {
+        self.provenance.is_some()
+    }
+
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    /// Built before the `send` interface
+    pub fn is_legacy(&self) -> bool {
+        self.abi_version < 2
+    }
+
+    pub fn has_info(&self) -> bool {
+        self.info.is_some()
+    }
+
+    /// The guest's NIP-11 document, `None` when it exports no info function or returns nothing
+    pub fn info<T>(&self, store: &mut Store<T>) -> Result<Option<String>> {
+        let Some(info) = &self.info else { return Ok(None) };
+        match info.call(&mut *store, ())? {
+            0 => Ok(None),
+            ptr => self.read_string(store, ptr).map(Some),
+        }
+    }
+
+    /// Override the guest's NIP-11 fields; `None` when it has no `set_info`, else its status code
+    pub fn set_info<T>(&self, store: &mut Store<T>, info_json: &str) -> Result<Option<i32>> {
+        let Some(set_info) = &self.set_info else { return Ok(None) };
+        let (ptr, len) = self.write(store, info_json)?;
+        let status = set_info.call(&mut *store, (ptr, len))?;
+        self.free(store, ptr, len)?;
+        Ok(Some(status))
+    }
+
+    /// The guest's internal error NOTICE for a call that trapped, or the trap itself
+    /// when the guest recorded no panic
+    fn recover<T>(&self, store: &mut Store<T>, trap: anyhow::Error) -> Result<i32> {
+        let Some(last_panic) = &self.last_panic else { return Err(trap) };
+        match last_panic.call(&mut *store, ()) {
+            Ok(ptr) if ptr != 0 => Ok(ptr),
+            _ => Err(trap),
+        }
+    }
+
+    fn write<T>(&self, store: &mut Store<T>, text: &str) -> Result<(i32, i32)> {
+        let len = text.len() as i32;
+        let ptr = self.alloc.call(&mut *store, len)?;
+        if ptr == 0 {
+            return Err(anyhow!("Cassette failed to allocate {} bytes", len));
+        }
+        self.memory.write(&mut *store, ptr as usize, text.as_bytes())?;
+        Ok((ptr, len))
+    }
+
+    fn free<T>(&self, store: &mut Store<T>, ptr: i32, len: i32) -> Result<()> {
+        if let Some(dealloc) = &self.dealloc {
+            dealloc.call(&mut *store, (ptr, len))?;
+        }
+        Ok(())
+    }
+
+    /// Read a response and hand its memory back to the guest
+    fn take_string<T>(&self, store: &mut Store<T>, ptr: i32) -> Result<String> {
+        let result = self.read_string(store, ptr)?;
+        if let Some(allocation_size) = &self.allocation_size {
+            let size = allocation_size.call(&mut *store, ptr)?;
+            if size > 0 {
+                let _ = self.free(store, ptr, size);
+            }
+        }
+        Ok(result)
+    }
+
+    fn read_string<T>(&self, store: &mut Store<T>, ptr: i32) -> Result<String> {
+        // Check for MSGB signature
+        let mut header = [0u8; 8];
+        self.memory.read(&*store, ptr as usize, &mut header)?;
+        if &header[..4] == b"MSGB" {
+            let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            let mut string_data = vec![0u8; length];
+            self.memory.read(&*store, ptr as usize + 8, &mut string_data)?;
+            String::from_utf8(string_data).context("Invalid UTF-8 in response")
+        } else {
+            // Fallback: read null-terminated string
+            let data = self.memory.data(&*store).get(ptr as usize..)
+                .ok_or_else(|| anyhow!("Response pointer {} is outside the cassette's memory", ptr))?;
+            let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+            String::from_utf8(data[..end].to_vec()).context("Invalid UTF-8 in response")
+        }
+    }
+}
+
+fn is_close(message: &str) -> bool {
+    serde_json::from_str::<Value>(message)
+        .is_ok_and(|message| message.get(0).and_then(|t| t.as_str()) == Some("CLOSE"))
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `req`/`alloc_string`/`describe` guest answering with NUL-terminated strings
+    const LEGACY_GUEST: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $next (mut i32) (i32.const 1024))
+        (func (export "alloc_string") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 1))))
+            (local.get $ptr))
+        (func (export "req") (param i32 i32) (result i32) (i32.const 16))
+        (func (export "close") (param i32 i32) (result i32) (i32.const 40))
+        (func (export "describe") (result i32) (i32.const 64))
+        (data (i32.const 16) "[\"EOSE\",\"legacy\"]\00")
+        (data (i32.const 40) "[\"CLOSED\",\"legacy\",\"\"]\00")
+        (data (i32.const 64) "{\"name\":\"legacy\"}\00"))"#;
+
+    /// `send`/`alloc_buffer`/`info` guest answering with MSGB strings and counting what it frees
+    const CURRENT_GUEST: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $next (mut i32) (i32.const 1024))
+        (global $freed (mut i32) (i32.const 0))
+        (func (export "alloc_buffer") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+        (func (export "dealloc_string") (param i32 i32)
+            (global.set $freed (i32.add (global.get $freed) (i32.const 1))))
+        (func (export "get_allocation_size") (param i32) (result i32) (i32.const 32))
+        (func (export "freed") (result i32) (global.get $freed))
+        (func (export "send") (param i32 i32) (result i32) (i32.const 16))
+        (func (export "info") (result i32) (i32.const 64))
+        (data (i32.const 16) "MSGB\19\00\00\00[\"COUNT\",\"c\",{\"count\":3}]")
+        (data (i32.const 64) "MSGB\12\00\00\00{\"name\":\"current\"}"))"#;
+
+    #[test]
+    fn test_detects_both_generations() {
+        let engine = Engine::default();
+
+        let legacy = Module::new(&engine, LEGACY_GUEST).unwrap();
+        let (mut store, api) = GuestApi::instantiate(&engine, &legacy).unwrap();
+        assert!(api.is_legacy());
+        assert_eq!(api.abi_version(), 1);
+        assert_eq!(api.send(&mut store, r#"["REQ","legacy",{}]"#).unwrap().unwrap(), r#"["EOSE","legacy"]"#);
+        assert_eq!(api.send(&mut store, r#"["CLOSE","legacy"]"#).unwrap().unwrap(), r#"["CLOSED","legacy",""]"#);
+        assert_eq!(api.info(&mut store).unwrap().unwrap(), r#"{"name":"legacy"}"#);
+        assert_eq!(api.set_info(&mut store, "{}").unwrap(), None);
+
+        let current = Module::new(&engine, CURRENT_GUEST).unwrap();
+        let (mut store, api) = GuestApi::instantiate(&engine, &current).unwrap();
+        assert!(!api.is_legacy());
+        assert_eq!(api.abi_version(), 2);
+        assert_eq!(api.count(&mut store, r#"["COUNT","c",{}]"#).unwrap(), Some(3));
+        assert_eq!(api.info(&mut store).unwrap().unwrap(), r#"{"name":"current"}"#);
+        // Both the request and the response were handed back to the guest
+        let instance = Instance::new(&mut store, &current, &[]).unwrap();
+        let api = GuestApi::detect(&mut store, &instance).unwrap();
+        api.send(&mut store, "[]").unwrap();
+        let freed = instance.get_typed_func::<(), i32>(&mut store, "freed").unwrap();
+        assert_eq!(freed.call(&mut store, ()).unwrap(), 2);
+
+        let no_send = Module::new(&engine, r#"(module (memory (export "memory") 1))"#).unwrap();
+        assert!(GuestApi::instantiate(&engine, &no_send).is_err());
+    }
+
+    #[test]
+    fn test_declared_abi_version() {
+        let engine = Engine::default();
+        let guest = |source: &str, version: i32| Module::new(&engine, source.replacen(
+            "(memory (export \"memory\") 1)",
+            &format!("(memory (export \"memory\") 1) (func (export \"abi_version\") (result i32) (i32.const {}))", version),
+            1,
+        )).unwrap();
+
+        let (_, api) = GuestApi::instantiate(&engine, &guest(CURRENT_GUEST, 2)).unwrap();
+        assert_eq!(api.abi_version(), 2);
+        let (_, api) = GuestApi::instantiate(&engine, &guest(LEGACY_GUEST, 1)).unwrap();
+        assert!(api.is_legacy());
+        // A declared version wins over the export names
+        assert!(GuestApi::instantiate(&engine, &guest(LEGACY_GUEST, 2)).is_err());
+        let newer = GuestApi::instantiate(&engine, &guest(CURRENT_GUEST, 3)).err().unwrap();
+        assert!(newer.to_string().contains("guest ABI v3"));
+    }
+
+    #[test]
+    fn test_panic_notice() {
+        let engine = Engine::default();
+        let guest = |last_panic: &str| Module::new(&engine, CURRENT_GUEST.replacen(
+            r#"(func (export "send") (param i32 i32) (result i32) (i32.const 16))"#,
+            &format!(r#"(func (export "send") (param i32 i32) (result i32) unreachable) {}"#, last_panic),
+            1,
+        )).unwrap();
+
+        let module = guest(r#"(func (export "last_panic") (result i32) (i32.const 128))
+            (data (i32.const 128) "MSGB\31\00\00\00[\"NOTICE\",\"internal error: boom at src/lib.rs:1\"]")"#);
+        let (mut store, api) = GuestApi::instantiate(&engine, &module).unwrap();
+        assert_eq!(api.send(&mut store, "[]").unwrap().unwrap(), r#"["NOTICE","internal error: boom at src/lib.rs:1"]"#);
+
+        // Without a recorded panic the trap itself is the error
+        let (mut store, api) = GuestApi::instantiate(&engine, &guest(r#"(func (export "last_panic") (result i32) (i32.const 0))"#)).unwrap();
+        assert!(api.send(&mut store, "[]").is_err());
+        let (mut store, api) = GuestApi::instantiate(&engine, &guest("")).unwrap();
+        assert!(api.send(&mut store, "[]").is_err());
+    }
+
+    #[test]
+    fn test_chunked_replies() {
+        let engine = Engine::default();
+        // Queues two chunks: two EVENTs, then EOSE
+        let module = Module::new(&engine, CURRENT_GUEST.replacen(
+            r#"(func (export "freed")"#,
+            r#"(global $chunk (mut i32) (i32.const 0))
+               (func (export "result_begin") (param i32 i32 i32) (result i32)
+                   (global.set $chunk (i32.const 0)) (i32.const 3))
+               (func (export "result_next_chunk") (result i32)
+                   (global.set $chunk (i32.add (global.get $chunk) (i32.const 1)))
+                   (if (result i32) (i32.eq (global.get $chunk) (i32.const 1)) (then (i32.const 128))
+                       (else (if (result i32) (i32.eq (global.get $chunk) (i32.const 2)) (then (i32.const 192)) (else (i32.const 0))))))
+               (func (export "result_end") (global.set $chunk (i32.const 2)))
+               (data (i32.const 128) "MSGB\31\00\00\00[\"EVENT\",\"c\",{\"id\":\"a\"}]\n[\"EVENT\",\"c\",{\"id\":\"b\"}]")
+               (data (i32.const 192) "MSGB\0c\00\00\00[\"EOSE\",\"c\"]")
+               (func (export "freed")"#,
+            1,
+        )).unwrap();
+        let (mut store, api) = GuestApi::instantiate(&engine, &module).unwrap();
+        assert_eq!(api.begin_result(&mut store, r#"["REQ","c",{}]"#, 0).unwrap(), Some(3));
+        let mut replies = api.replies(&mut store, r#"["REQ","c",{}]"#).unwrap();
+        let mut messages = vec![];
+        while let Some(message) = replies.next(&api, &mut store).unwrap() {
+            messages.push(message);
+        }
+        assert_eq!(messages, vec![r#"["EVENT","c",{"id":"a"}]"#, r#"["EVENT","c",{"id":"b"}]"#, r#"["EOSE","c"]"#]);
+        api.begin_result(&mut store, "[]", 0).unwrap();
+        api.end_result(&mut store).unwrap();
+        assert_eq!(api.next_chunk(&mut store).unwrap(), None);
+
+        // Guests without the exports answer one message per call
+        let (mut store, api) = GuestApi::instantiate(&engine, &Module::new(&engine, CURRENT_GUEST).unwrap()).unwrap();
+        assert_eq!(api.begin_result(&mut store, "[]", 0).unwrap(), None);
+        let mut replies = api.replies(&mut store, r#"["COUNT","c",{}]"#).unwrap();
+        assert_eq!(replies.next(&api, &mut store).unwrap().unwrap(), r#"["COUNT","c",{"count":3}]"#);
+    }
+
+    #[test]
+    fn test_req_page() {
+        let engine = Engine::default();
+        let (mut store, api) = GuestApi::instantiate(&engine, &Module::new(&engine, CURRENT_GUEST).unwrap()).unwrap();
+        assert_eq!(api.req_page(&mut store, r#"["REQ","p",{}]"#, 0, 10).unwrap(), None);
+
+        let module = Module::new(&engine, CURRENT_GUEST.replacen(
+            r#"(func (export "freed")"#,
+            r#"(func (export "req_page") (param i32 i32 i64 i32) (result i32)
+                   (if (result i32) (i64.eqz (local.get 2)) (then (i32.const 128)) (else (i32.const 192))))
+               (data (i32.const 128) "MSGB\27\00\00\00{\"events\":[{\"id\":\"a\"}],\"next_cursor\":1}")
+               (data (i32.const 192) "MSGB\18\00\00\00[\"CLOSED\",\"p\",\"invalid\"]")
+               (func (export "freed")"#,
+            1,
+        )).unwrap();
+        let (mut store, api) = GuestApi::instantiate(&engine, &module).unwrap();
+        let page = api.req_page(&mut store, r#"["REQ","p",{}]"#, 0, 1).unwrap().unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.next_cursor, Some(1));
+        assert!(api.req_page(&mut store, r#"["REQ","p",{}]"#, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_set_current_time() {
+        let engine = Engine::default();
+        let module = Module::new(&engine, CURRENT_GUEST.replacen(
+            r#"(func (export "freed")"#,
+            r#"(global $now (mut i64) (i64.const 0))
+               (func (export "set_current_time") (param i64) (global.set $now (local.get 0)))
+               (func (export "now") (result i64) (global.get $now))
+               (func (export "freed")"#,
+            1,
+        )).unwrap();
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let api = GuestApi::detect(&mut store, &instance).unwrap();
+        api.send(&mut store, "[]").unwrap();
+        let now = instance.get_typed_func::<(), i64>(&mut store, "now").unwrap();
+        assert!(now.call(&mut store, ()).unwrap() >= unix_now() - 60, "the guest got the host's clock");
+    }
+}
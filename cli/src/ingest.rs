@@ -0,0 +1,297 @@
+//! Streaming `record` input from stdin
+//!
+//! A pipe can carry millions of events, more than fit in memory twice over.
+//! `record` without an input file parses stdin one JSON value at a time: a
+//! bare event, a NIP-01 `["EVENT", ...]` message (with or without a
+//! subscription id) or an array of either, read element by element, in any
+//! mix and with or without newlines between them. Each event is checked
+//! against the validation policy, deduplicated by id and dropped when an
+//! already accepted version of the same replaceable address is newer, and
+//! what survives is appended to an NDJSON spool in a temp directory. Only
+//! ids (hashed to 32 bytes) and replaceable addresses stay in memory, so
+//! duplicates, invalid events and stale versions never reach the compiler.
+
+use crate::validation::{RejectionTally, ValidationPolicy};
+use anyhow::{anyhow, Result};
+use cassette_tools::replaceable::LatestVersions;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// What happened to the events read from the input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Events found in the input
+    pub read: usize,
+    /// Events written to the spool
+    pub accepted: usize,
+    /// Events whose id was already accepted
+    pub duplicates: usize,
+    /// Events refused by the validation policy
+    pub rejected: usize,
+    /// The refusals by rule
+    pub rejections: RejectionTally,
+    /// Replaceable events older than an accepted version
+    pub superseded: usize,
+    /// Input that wasn't JSON or held no events
+    pub unreadable: usize,
+}
+
+impl IngestStats {
+    /// One line for the record output
+    pub fn summary(&self) -> String {
+        format!(
+            "📥 Read {} events: {} accepted, {} duplicates, {} rejected, {} superseded, {} unreadable inputs skipped",
+            self.read, self.accepted, self.duplicates, self.rejected, self.superseded, self.unreadable
+        )
+    }
+}
+
+/// Accepted events spilled to disk, removed when dropped
+pub struct Spool {
+    _dir: TempDir,
+    pub path: PathBuf,
+    pub stats: IngestStats,
+}
+
+/// Parse, check and spool every event of `reader`
+pub fn spool<R: BufRead>(reader: R, policy: &ValidationPolicy, verbose: bool) -> Result<Spool> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("stdin_events.ndjson");
+    let mut spooler = Spooler {
+        writer: BufWriter::new(File::create(&path)?),
+        seen: HashSet::new(),
+        versions: LatestVersions::default(),
+        policy,
+        verbose,
+        stats: IngestStats::default(),
+        failed: None,
+    };
+
+    let mut input = Input { reader, line: 0 };
+    while input.skip_whitespace()? {
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut input);
+        if let Err(e) = Item(&mut spooler).deserialize(&mut deserializer) {
+            if let Some(failed) = spooler.failed.take() {
+                return Err(anyhow!("Failed to spool events: {}", failed));
+            }
+            eprintln!("Warning: Skipping invalid input on line {}: {}", input.line + 1, e);
+            spooler.stats.unreadable += 1;
+            input.skip_line()?;
+        }
+    }
+    spooler.writer.flush()?;
+
+    Ok(Spool { stats: spooler.stats, _dir: dir, path })
+}
+
+/// Where events go as they are parsed
+struct Spooler<'a, W: Write> {
+    writer: W,
+    seen: HashSet<[u8; 32]>,
+    versions: LatestVersions,
+    policy: &'a ValidationPolicy,
+    verbose: bool,
+    stats: IngestStats,
+    // A write error, kept apart from the parse errors that only skip input
+    failed: Option<io::Error>,
+}
+
+impl<W: Write> Spooler<'_, W> {
+    fn push(&mut self, event: Value) -> io::Result<()> {
+        self.stats.read += 1;
+        let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("");
+        let key: [u8; 32] = Sha256::digest(id.as_bytes()).into();
+        if self.seen.contains(&key) {
+            self.stats.duplicates += 1;
+            return Ok(());
+        }
+        if !self.policy.admit_counting(&event, self.verbose, &mut self.stats.rejections) {
+            self.stats.rejected += 1;
+            return Ok(());
+        }
+        // Versions spooled before a newer one arrived are dropped when the cassette is built
+        self.versions.insert(&event);
+        if !self.versions.is_latest(&event) {
+            self.stats.superseded += 1;
+            return Ok(());
+        }
+        self.seen.insert(key);
+        self.stats.accepted += 1;
+        serde_json::to_writer(&mut self.writer, &event)?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Events in an already parsed value: an event, a message or an array of them
+    fn push_value(&mut self, value: Value) -> io::Result<()> {
+        match value {
+            Value::Object(_) => self.push(value),
+            Value::Array(items) if items.first().is_some_and(|first| first.is_string()) => match message_event(items) {
+                Some(event) => self.push(event),
+                None => Ok(()),
+            },
+            Value::Array(items) => items.into_iter().try_for_each(|item| self.push_value(item)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The event of an `["EVENT", event]` or `["EVENT", subscription, event]` message
+fn message_event(mut message: Vec<Value>) -> Option<Value> {
+    if message.first()?.as_str() != Some("EVENT") {
+        return None;
+    }
+    message.pop().filter(|event| event.is_object())
+}
+
+/// One top-level JSON value of the input, spooled without building arrays in memory
+struct Item<'s, 'a, W: Write>(&'s mut Spooler<'a, W>);
+
+impl<'de, W: Write> DeserializeSeed<'de> for Item<'_, '_, W> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<W: Write> Item<'_, '_, W> {
+    fn spool<E: de::Error>(self, value: Value) -> Result<(), E> {
+        self.0.push_value(value).map_err(|e| {
+            let error = E::custom(&e);
+            self.0.failed = Some(e);
+            error
+        })
+    }
+}
+
+impl<'de, W: Write> Visitor<'de> for Item<'_, '_, W> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an event, an EVENT message or an array of them")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        let event = Value::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        self.spool(event)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        match seq.next_element::<Value>()? {
+            None => Ok(()),
+            // A relay message, small enough to read whole
+            Some(Value::String(message_type)) => {
+                let mut message = vec![Value::String(message_type)];
+                while let Some(item) = seq.next_element::<Value>()? {
+                    message.push(item);
+                }
+                self.spool(Value::Array(message))
+            }
+            // An array of events or messages, spooled one element at a time
+            Some(first) => {
+                let Item(spooler) = self;
+                Item(&mut *spooler).spool(first)?;
+                while seq.next_element_seed(Item(&mut *spooler))?.is_some() {}
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The input, counting lines for warnings
+struct Input<R> {
+    reader: R,
+    line: usize,
+}
+
+impl<R: BufRead> Input<R> {
+    /// Skip whitespace between values, false at the end of the input
+    fn skip_whitespace(&mut self) -> io::Result<bool> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(false);
+            }
+            let blank = buffer.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            let more = blank < buffer.len();
+            self.line += buffer[..blank].iter().filter(|b| **b == b'\n').count();
+            self.reader.consume(blank);
+            if more {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Drop the rest of the current line after a parse error
+    fn skip_line(&mut self) -> io::Result<()> {
+        let mut rest = Vec::new();
+        self.reader.read_until(b'\n', &mut rest)?;
+        if rest.ends_with(b"\n") {
+            self.line += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.line += buf[..n].iter().filter(|b| **b == b'\n').count();
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str, kind: i64, created_at: i64) -> String {
+        format!(r#"{{"id":"{}","pubkey":"alice","kind":{},"created_at":{},"tags":[],"content":"","sig":""}}"#, id, kind, created_at)
+    }
+
+    fn spooled_ids(spool: &Spool) -> Vec<String> {
+        std::fs::read_to_string(&spool.path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_spool() {
+        let input = [
+            event("a", 1, 1),
+            format!(r#"["EVENT","sub",{}]"#, event("b", 1, 2)),
+            "not json".to_string(),
+            format!("[\n  {},\n  [\"EVENT\", {}]\n]", event("c", 1, 3), event("a", 1, 1)),
+            event("profile-new", 0, 20),
+            event("profile-old", 0, 10),
+            r#"["EOSE","sub"]"#.to_string(),
+        ].join("\n");
+
+        let spooled = spool(input.as_bytes(), &ValidationPolicy::permissive(), false).unwrap();
+        assert_eq!(spooled_ids(&spooled), vec!["a", "b", "c", "profile-new"]);
+        assert_eq!(spooled.stats, IngestStats {
+            read: 6,
+            accepted: 4,
+            duplicates: 1,
+            rejected: 0,
+            superseded: 1,
+            unreadable: 1,
+            ..Default::default()
+        });
+
+        let notes_only = ValidationPolicy { kinds: Some(HashSet::from([1])), ..ValidationPolicy::permissive() };
+        let spooled = spool(input.as_bytes(), &notes_only, false).unwrap();
+        assert_eq!(spooled_ids(&spooled), vec!["a", "b", "c"]);
+        assert_eq!(spooled.stats.rejected, 2);
+        assert_eq!(spooled.stats.rejections.total(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+//! Reading event files for `record`
+//!
+//! An input file holds events as a JSON array (of events or NIP-01 `EVENT`
+//! messages), as NDJSON with one event or message per line, or as a single
+//! `EVENT` message, and may be gzip or zstd compressed. Compression is
+//! recognised by its magic bytes whatever the file is called. The shape is
+//! guessed from the first line: a complete JSON value with more input after
+//! it means NDJSON, anything else is read as one JSON document. `--format`
+//! skips the guess. A document that doesn't parse is an error quoting the
+//! offending line; NDJSON lines that don't parse are skipped with a warning,
+//! and only a file without a single usable line is an error.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Longest stretch of an offending line quoted in errors
+const QUOTE_CHARS: usize = 120;
+
+/// Shape of an input file, chosen with `record --format`
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Guess from the first line
+    #[default]
+    Auto,
+    /// One JSON document: an array of events or messages, or a single event or message
+    Json,
+    /// One event or message per line
+    Ndjson,
+}
+
+/// Open a file, decompressing gzip and zstd
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let head = reader.fill_buf()?;
+    if head.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))))
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Read every event of an input file
+pub fn read_events(path: &Path, format: InputFormat) -> Result<Vec<Value>> {
+    let mut reader = open(path)?;
+
+    // The first line decides the shape, and is put back in front of the rest
+    let mut first_line = String::new();
+    reader.read_line(&mut first_line)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = match format {
+        InputFormat::Auto if is_ndjson(&first_line, &mut reader)? => InputFormat::Ndjson,
+        InputFormat::Auto => InputFormat::Json,
+        format => format,
+    };
+    let reader = Cursor::new(first_line.into_bytes()).chain(reader);
+
+    let mut events = Vec::new();
+    match format {
+        InputFormat::Ndjson => {
+            let mut first_invalid = None;
+            for (number, line) in reader.lines().enumerate() {
+                let line = line.context("Failed to read line")?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<Value>(line) {
+                    Ok(value) if value.is_object() || value.is_array() => push_events(value, &mut events),
+                    Ok(_) => eprintln!("Warning: Skipping non-event on line {}", number + 1),
+                    Err(e) => {
+                        eprintln!("Warning: Skipping invalid JSON on line {}: {}", number + 1, e);
+                        first_invalid.get_or_insert_with(|| (number + 1, quote(line)));
+                    }
+                }
+            }
+            if events.is_empty() {
+                return Err(match first_invalid {
+                    Some((number, line)) => anyhow!("No valid events found in {}; first invalid line:\n  {} | {}", path.display(), number, line),
+                    None => anyhow!("No valid events found in {}", path.display()),
+                });
+            }
+        }
+        _ => {
+            let value: Value = serde_json::from_reader(reader).map_err(|e| {
+                let line = if e.line() > 0 { offending_line(path, e.line()) } else { None };
+                match line {
+                    Some(line) => anyhow!("Invalid JSON in {} at line {}, column {}: {}\n  {} | {}",
+                        path.display(), e.line(), e.column(), e, e.line(), line),
+                    None => anyhow!("Invalid JSON in {}: {}", path.display(), e),
+                }
+            })?;
+            if !value.is_object() && !value.is_array() {
+                return Err(anyhow!("Expected an event, an EVENT message or an array of them in {}", path.display()));
+            }
+            push_events(value, &mut events);
+        }
+    }
+    Ok(events)
+}
+
+/// Whether the input is line-delimited: its first line is a complete value with more after it
+fn is_ndjson(first_line: &str, rest: &mut Box<dyn BufRead>) -> Result<bool> {
+    if serde_json::from_str::<Value>(first_line.trim()).is_err() {
+        return Ok(false);
+    }
+    loop {
+        let buffer = rest.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(false);
+        }
+        if buffer.iter().any(|b| !b.is_ascii_whitespace()) {
+            return Ok(true);
+        }
+        let len = buffer.len();
+        rest.consume(len);
+    }
+}
+
+/// Events in a value: an event, an `EVENT` message, or an array of either
+fn push_events(value: Value, events: &mut Vec<Value>) {
+    match value {
+        Value::Object(_) => events.push(value),
+        Value::Array(mut items) if items.first().is_some_and(|first| first.is_string()) => {
+            if items[0].as_str() == Some("EVENT") {
+                if let Some(event) = items.pop().filter(|event| event.is_object()) {
+                    events.push(event);
+                }
+            }
+        }
+        Value::Array(items) => items.into_iter().for_each(|item| push_events(item, events)),
+        _ => {}
+    }
+}
+
+/// Line `number` (1-based) of the decompressed file, shortened for an error message
+fn offending_line(path: &Path, number: usize) -> Option<String> {
+    let line = open(path).ok()?.lines().nth(number - 1)?.ok()?;
+    Some(quote(&line))
+}
+
+fn quote(line: &str) -> String {
+    let line = line.trim();
+    if line.chars().count() > QUOTE_CHARS {
+        format!("{}…", line.chars().take(QUOTE_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn ids(events: &[Value]) -> Vec<&str> {
+        events.iter().map(|event| event["id"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_read_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, bytes: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            path
+        };
+
+        let array = r#"[{"id":"a"},["EVENT","sub",{"id":"b"}]]"#;
+        let ndjson = "[\"EVENT\",{\"id\":\"a\"}]\nnot json\n{\"id\":\"b\"}\n";
+
+        let path = write("array.json", array.as_bytes());
+        assert_eq!(ids(&read_events(&path, InputFormat::Auto).unwrap()), vec!["a", "b"]);
+        let path = write("message.json", br#"["EVENT",{"id":"a"}]"#);
+        assert_eq!(ids(&read_events(&path, InputFormat::Auto).unwrap()), vec!["a"]);
+        let path = write("events.jsonl", ndjson.as_bytes());
+        assert_eq!(ids(&read_events(&path, InputFormat::Auto).unwrap()), vec!["a", "b"]);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(ndjson.as_bytes()).unwrap();
+        let path = write("events.jsonl.gz", &gzip.finish().unwrap());
+        assert_eq!(ids(&read_events(&path, InputFormat::Auto).unwrap()), vec!["a", "b"]);
+        let path = write("events.bin", &zstd::encode_all(array.as_bytes(), 3).unwrap());
+        assert_eq!(ids(&read_events(&path, InputFormat::Auto).unwrap()), vec!["a", "b"]);
+
+        // A broken document names the offending line
+        let path = write("broken.json", b"[\n{\"id\":\"a\"},\n{\"id\": b}\n]");
+        let error = read_events(&path, InputFormat::Auto).unwrap_err().to_string();
+        assert!(error.contains("line 3"), "{}", error);
+        assert!(error.contains(r#"3 | {"id": b}"#), "{}", error);
+
+        // Forcing a format skips the guess
+        let path = write("forced.json", ndjson.as_bytes());
+        assert!(read_events(&path, InputFormat::Json).is_err());
+        let path = write("garbage.jsonl", b"nope\nstill nope\n");
+        let error = read_events(&path, InputFormat::Ndjson).unwrap_err().to_string();
+        assert!(error.contains("1 | nope"), "{}", error);
+    }
+}
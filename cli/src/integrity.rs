@@ -0,0 +1,203 @@
+//! Integrity checks for `cassette validate`
+//!
+//! A cassette passes when its module instantiates, exports what its guest ABI
+//! version requires with the signatures loaders call them with, answers
+//! messages through the memory ABI, and serves events that are valid (id,
+//! signature and tags), newest first, each once and only in the latest
+//! version of a replaceable or addressable event. Every check runs and records
+//! what it found, so the report lists everything that is wrong rather than
+//! stopping at the first problem.
+
+use cassette_tools::{newest_first, replaceable};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use wasmtime::{ExternType, FuncType, Module, ValType};
+
+/// Problems listed per check in the human-readable report
+const SHOWN_PROBLEMS: usize = 10;
+
+/// One check and what it found wrong; none means it passed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub problems: Vec<String>,
+}
+
+/// Every check run against a cassette
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn add(&mut self, name: &'static str, problems: Vec<String>) {
+        self.checks.push(Check { name, problems });
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.problems.is_empty())
+    }
+
+    pub fn failed(&self) -> usize {
+        self.checks.iter().filter(|check| !check.problems.is_empty()).count()
+    }
+
+    pub fn to_json(&self, cassette: &str) -> Value {
+        json!({
+            "cassette": cassette,
+            "valid": self.passed(),
+            "checks": self.checks.iter().map(|check| json!({
+                "name": check.name,
+                "ok": check.problems.is_empty(),
+                "problems": check.problems,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// A line per check, with the first problems of the ones that failed
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for check in &self.checks {
+            if check.problems.is_empty() {
+                lines.push(format!("✅ {}", check.name));
+                continue;
+            }
+            lines.push(format!("❌ {} ({} problems)", check.name, check.problems.len()));
+            for problem in check.problems.iter().take(SHOWN_PROBLEMS) {
+                lines.push(format!("   {}", problem));
+            }
+            if check.problems.len() > SHOWN_PROBLEMS {
+                lines.push(format!("   … {} more (see --json)", check.problems.len() - SHOWN_PROBLEMS));
+            }
+        }
+        lines
+    }
+}
+
+/// The exports a guest of this ABI version must have, each with the names loaders
+/// accept in its place
+pub fn required_exports(abi_version: u32) -> Vec<&'static [&'static str]> {
+    match abi_version {
+        1 => vec![&["memory"], &["req"], &["close"], &["alloc_string"], &["describe"]],
+        _ => vec![&["memory"], &["send", "scrub"], &["alloc_buffer", "alloc_string"], &["dealloc_string"], &["info", "describe"]],
+    }
+}
+
+/// Parameters and results loaders call an export with
+fn expected_signature(name: &str) -> Option<(Vec<ValType>, Vec<ValType>)> {
+    use ValType::I32;
+    Some(match name {
+        "send" | "scrub" | "req" | "close" => (vec![I32, I32], vec![I32]),
+        "alloc_buffer" | "alloc_string" | "get_allocation_size" => (vec![I32], vec![I32]),
+        "dealloc_string" => (vec![I32, I32], vec![]),
+        "info" | "describe" | "abi_version" => (vec![], vec![I32]),
+        _ => return None,
+    })
+}
+
+fn signature(ty: &FuncType) -> String {
+    let types = |types: Vec<ValType>| types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ");
+    format!("({}) -> ({})", types(ty.params().collect()), types(ty.results().collect()))
+}
+
+/// Missing exports, and exports whose type isn't the one loaders use
+pub fn check_exports(module: &Module, abi_version: u32) -> Vec<String> {
+    let mut problems = Vec::new();
+    let exports: Vec<(String, ExternType)> = module.exports().map(|e| (e.name().to_string(), e.ty())).collect();
+    for names in required_exports(abi_version) {
+        if !names.iter().any(|name| exports.iter().any(|(export, _)| export == name)) {
+            problems.push(format!("missing export {}", names.join(" or ")));
+        }
+    }
+    for (name, ty) in &exports {
+        match ty {
+            ExternType::Memory(_) if name == "memory" => {}
+            _ if name == "memory" => problems.push("export memory is not a memory".to_string()),
+            ExternType::Func(func) => {
+                let Some((params, results)) = expected_signature(name) else { continue };
+                if !func.params().eq(params.iter().cloned()) || !func.results().eq(results.iter().cloned()) {
+                    let expected = FuncType::new(params, results);
+                    problems.push(format!("export {} is {}, loaders call it as {}", name, signature(func), signature(&expected)));
+                }
+            }
+            _ => {}
+        }
+    }
+    problems
+}
+
+fn id(event: &Value) -> &str {
+    event.get("id").and_then(|i| i.as_str()).unwrap_or("?")
+}
+
+/// Events served out of newest-first order
+pub fn check_order(events: &[Value]) -> Vec<String> {
+    events.windows(2)
+        .filter(|pair| newest_first(&pair[0], &pair[1]) == Ordering::Greater)
+        .map(|pair| format!(
+            "{} (created_at {}) is served after the older {} (created_at {})",
+            id(&pair[1]), pair[1]["created_at"], id(&pair[0]), pair[0]["created_at"]
+        ))
+        .collect()
+}
+
+/// Events served more than once, and replaceable events served in more than one version
+pub fn check_duplicates(events: &[Value]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut ids = HashSet::new();
+    let mut addresses = HashSet::new();
+    for event in events {
+        if !ids.insert(id(event)) {
+            problems.push(format!("{} is served more than once", id(event)));
+            continue;
+        }
+        if let Some(address) = replaceable::address(event) {
+            if !addresses.insert(address.clone()) {
+                problems.push(format!("{} is an outdated version of {}", id(event), address));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::Engine;
+
+    #[test]
+    fn test_integrity_checks() {
+        let engine = Engine::default();
+        let module = Module::new(&engine, r#"(module
+            (memory (export "memory") 1)
+            (func (export "send") (param i32 i32) (result i32) (i32.const 0))
+            (func (export "alloc_buffer") (param i32 i32) (result i32) (i32.const 0))
+            (func (export "info") (result i32) (i32.const 0)))"#).unwrap();
+        assert_eq!(check_exports(&module, 2), vec![
+            "missing export dealloc_string".to_string(),
+            "export alloc_buffer is (i32, i32) -> (i32), loaders call it as (i32) -> (i32)".to_string(),
+        ]);
+        assert_eq!(check_exports(&module, 1).len(), 5, "four v1 exports missing, alloc_buffer mistyped");
+
+        let event = |id: &str, created_at: i64, kind: i64| json!({"id": id, "pubkey": "alice", "created_at": created_at, "kind": kind, "tags": []});
+        let good = vec![event("c", 30, 1), event("b", 20, 0), event("a", 10, 1)];
+        assert!(check_order(&good).is_empty());
+        assert!(check_duplicates(&good).is_empty());
+
+        let bad = vec![event("b", 20, 0), event("c", 30, 1), event("b", 20, 0), event("old", 5, 0)];
+        assert_eq!(check_order(&bad).len(), 1);
+        assert_eq!(check_duplicates(&bad), vec![
+            "b is served more than once".to_string(),
+            "old is an outdated version of 0:alice".to_string(),
+        ]);
+
+        let mut report = Report::default();
+        report.add("ordering", check_order(&bad));
+        report.add("duplicates", Vec::new());
+        assert!(!report.passed());
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.to_json("x.cassette")["checks"][1], json!({"name": "duplicates", "ok": true, "problems": []}));
+        assert_eq!(report.lines()[1], "   c (created_at 30) is served after the older b (created_at 20)");
+    }
+}
@@ -0,0 +1,217 @@
+//! NIP-32 label curation queries
+//!
+//! Labels come from kind 1985 events, whose `l` tags (value, namespace) apply
+//! to the events, pubkeys and addresses named by their `e`, `p` and `a` tags,
+//! and from `l` tags an event carries about itself. A selector such as
+//! `ISO-639-1=en` or `review/quality>=3` keeps the events that have a matching
+//! label, so a curated subset can be sliced out without a client-side join.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Kind of a NIP-32 label event
+pub const LABEL_KIND: i64 = 1985;
+
+/// Namespace of an `l` tag without a mark
+const DEFAULT_NAMESPACE: &str = "ugc";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+}
+
+/// One `--label` argument: `VALUE`, `NAMESPACE=VALUE` or `NAMESPACE<op>NUMBER`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    namespace: Option<String>,
+    comparison: Comparison,
+    value: String,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self> {
+        let Some(at) = selector.find(['<', '>', '=']) else {
+            return Ok(Self { namespace: None, comparison: Comparison::Eq, value: selector.to_string() });
+        };
+
+        let rest = &selector[at..];
+        let (comparison, width) = match (rest.as_bytes()[0], rest.as_bytes().get(1)) {
+            (b'>', Some(b'=')) => (Comparison::Ge, 2),
+            (b'<', Some(b'=')) => (Comparison::Le, 2),
+            (b'>', _) => (Comparison::Gt, 1),
+            (b'<', _) => (Comparison::Lt, 1),
+            _ => (Comparison::Eq, 1),
+        };
+        let namespace = &selector[..at];
+        let value = &selector[at + width..];
+        if namespace.is_empty() || value.is_empty() {
+            return Err(anyhow!("Invalid label selector '{}': expected NAMESPACE=VALUE or NAMESPACE>=NUMBER", selector));
+        }
+        if comparison != Comparison::Eq && value.parse::<f64>().is_err() {
+            return Err(anyhow!("Invalid label selector '{}': {} is not a number", selector, value));
+        }
+
+        Ok(Self { namespace: Some(namespace.to_string()), comparison, value: value.to_string() })
+    }
+
+    fn matches(&self, namespace: &str, value: &str) -> bool {
+        if self.namespace.as_deref().is_some_and(|ns| ns != namespace) {
+            return false;
+        }
+        if self.comparison == Comparison::Eq {
+            return self.value == value;
+        }
+
+        let (Ok(actual), Ok(bound)) = (value.parse::<f64>(), self.value.parse::<f64>()) else {
+            return false;
+        };
+        match self.comparison {
+            Comparison::Ge => actual >= bound,
+            Comparison::Gt => actual > bound,
+            Comparison::Le => actual <= bound,
+            Comparison::Lt => actual < bound,
+            Comparison::Eq => unreachable!(),
+        }
+    }
+}
+
+/// Labels by target (`e:<id>`, `p:<pubkey>`, `a:<coordinate>`), built from kind 1985 events
+#[derive(Debug, Default)]
+pub struct LabelIndex {
+    targets: HashMap<String, Vec<(String, String)>>,
+    labelers: HashSet<String>,
+}
+
+impl LabelIndex {
+    /// Index the label events among `events`; with `labelers`, only labels by those pubkeys count
+    pub fn from_events(events: &[Value], labelers: &[String]) -> Self {
+        let mut index = Self {
+            labelers: labelers.iter().cloned().collect(),
+            ..Self::default()
+        };
+
+        for event in events {
+            if event.get("kind").and_then(|k| k.as_i64()) != Some(LABEL_KIND) || !index.trusts(event) {
+                continue;
+            }
+            let labels = own_labels(event);
+            if labels.is_empty() {
+                continue;
+            }
+            for (name, target) in tags(event) {
+                if matches!(name, "e" | "p" | "a") {
+                    index.targets.entry(format!("{}:{}", name, target))
+                        .or_default()
+                        .extend(labels.iter().cloned());
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Number of labelled targets
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Check whether every selector matches one of the event's labels
+    pub fn matches(&self, event: &Value, selectors: &[Selector]) -> bool {
+        let labels = self.labels_of(event);
+        selectors.iter().all(|selector| {
+            labels.iter().any(|(namespace, value)| selector.matches(namespace, value))
+        })
+    }
+
+    /// The event's labels: those attached to it, its author or its address, plus its self-labels
+    fn labels_of(&self, event: &Value) -> Vec<(String, String)> {
+        let field = |name: &str| event.get(name).and_then(|v| v.as_str()).unwrap_or("");
+        let kind = event.get("kind").and_then(|k| k.as_i64()).unwrap_or(0);
+
+        let mut keys = vec![format!("e:{}", field("id")), format!("p:{}", field("pubkey"))];
+        if (30000..40000).contains(&kind) {
+            let d = tags(event).find(|(name, _)| *name == "d").map_or("", |(_, d)| d);
+            keys.push(format!("a:{}:{}:{}", kind, field("pubkey"), d));
+        }
+
+        let mut labels: Vec<(String, String)> = keys.iter()
+            .filter_map(|key| self.targets.get(key))
+            .flatten()
+            .cloned()
+            .collect();
+        if kind != LABEL_KIND && self.trusts(event) {
+            labels.extend(own_labels(event));
+        }
+        labels
+    }
+
+    fn trusts(&self, event: &Value) -> bool {
+        self.labelers.is_empty()
+            || event.get("pubkey").and_then(|p| p.as_str()).is_some_and(|p| self.labelers.contains(p))
+    }
+}
+
+/// Name and first value of each tag
+fn tags(event: &Value) -> impl Iterator<Item = (&str, &str)> {
+    event.get("tags").and_then(|t| t.as_array()).into_iter().flatten()
+        .filter_map(|tag| tag.as_array())
+        .filter_map(|tag| Some((tag.first()?.as_str()?, tag.get(1)?.as_str()?)))
+}
+
+/// The (namespace, value) of an event's `l` tags
+fn own_labels(event: &Value) -> Vec<(String, String)> {
+    event.get("tags").and_then(|t| t.as_array()).into_iter().flatten()
+        .filter_map(|tag| tag.as_array())
+        .filter(|tag| tag.first().and_then(|n| n.as_str()) == Some("l"))
+        .filter_map(|tag| {
+            let value = tag.get(1)?.as_str()?;
+            let namespace = tag.get(2).and_then(|n| n.as_str()).unwrap_or(DEFAULT_NAMESPACE);
+            Some((namespace.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_label_selectors() {
+        let labels = vec![
+            json!({"kind": 1985, "pubkey": "curator", "tags": [
+                ["L", "ISO-639-1"], ["l", "en", "ISO-639-1"], ["e", "note1"], ["e", "note2"]]}),
+            json!({"kind": 1985, "pubkey": "curator", "tags": [
+                ["L", "review/quality"], ["l", "4", "review/quality"], ["e", "note1"]]}),
+            json!({"kind": 1985, "pubkey": "stranger", "tags": [
+                ["L", "review/quality"], ["l", "5", "review/quality"], ["e", "note2"]]}),
+        ];
+        let note1 = json!({"id": "note1", "pubkey": "alice", "kind": 1, "tags": []});
+        let note2 = json!({"id": "note2", "pubkey": "bob", "kind": 1, "tags": []});
+        let self_labelled = json!({"id": "note3", "pubkey": "carol", "kind": 1, "tags": [["l", "en", "ISO-639-1"]]});
+
+        let english = Selector::parse("ISO-639-1=en").unwrap();
+        let quality = Selector::parse("review/quality>=3").unwrap();
+        assert!(Selector::parse("review/quality>=good").is_err());
+        assert!(Selector::parse("=en").is_err());
+
+        let index = LabelIndex::from_events(&labels, &[]);
+        assert!(index.matches(&note1, &[english.clone(), quality.clone()]));
+        assert!(index.matches(&note2, &[english.clone(), quality.clone()]));
+        assert!(index.matches(&self_labelled, std::slice::from_ref(&english)));
+        assert!(!index.matches(&self_labelled, std::slice::from_ref(&quality)));
+        assert!(!index.matches(&note1, &[Selector::parse("review/quality>4").unwrap()]));
+        assert!(index.matches(&note1, &[Selector::parse("en").unwrap()]));
+
+        // Only the trusted labeler's labels count
+        let index = LabelIndex::from_events(&labels, &["curator".to_string()]);
+        assert!(index.matches(&note1, std::slice::from_ref(&quality)));
+        assert!(!index.matches(&note2, &[quality]));
+        assert!(!index.matches(&self_labelled, &[english]));
+    }
+}
@@ -0,0 +1,170 @@
+//! Language detection for partitioning events into language-tagged segments
+//!
+//! Events are assigned an ISO-639-1 code, preferring explicit NIP-32 labels
+//! (`["l", "en", "ISO-639-1"]`) and falling back to a lightweight content
+//! heuristic (Unicode script ranges, then common stopwords for Latin text).
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Language code for events whose language can't be determined
+pub const UNDETERMINED: &str = "und";
+
+/// NIP-32 label namespace for ISO-639-1 language codes
+const ISO_639_1: &str = "ISO-639-1";
+
+/// Minimum stopword hits before a Latin-script language is accepted
+const MIN_STOPWORD_HITS: usize = 2;
+
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "of", "to", "that", "it", "you", "for", "with", "this", "are", "was", "have"]),
+    ("es", &["el", "los", "las", "que", "y", "es", "por", "con", "para", "una", "del", "pero", "como", "muy"]),
+    ("pt", &["o", "os", "que", "e", "não", "em", "um", "uma", "para", "com", "é", "do", "da", "mas"]),
+    ("fr", &["le", "les", "et", "est", "un", "une", "des", "pas", "pour", "dans", "je", "il", "du", "avec"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ich", "zu", "ein", "eine", "mit", "den", "auf", "auch"]),
+    ("it", &["il", "di", "che", "è", "un", "una", "per", "non", "sono", "della", "con", "anche", "gli", "ma"]),
+    ("nl", &["de", "het", "een", "en", "is", "van", "niet", "ik", "dat", "op", "te", "zijn", "met", "ook"]),
+];
+
+/// Detect an event's language, preferring NIP-32 labels over content heuristics
+pub fn detect_event_language(event: &Value) -> String {
+    if let Some(lang) = label_language(event) {
+        return lang;
+    }
+
+    let content = event.get("content").and_then(|c| c.as_str()).unwrap_or("");
+    detect_content_language(content)
+}
+
+/// Read an ISO-639-1 language label from an event's NIP-32 `l` tags
+pub fn label_language(event: &Value) -> Option<String> {
+    let tags = event.get("tags")?.as_array()?;
+    tags.iter()
+        .filter_map(|tag| tag.as_array())
+        .find(|tag| {
+            tag.first().and_then(|t| t.as_str()) == Some("l")
+                && tag.get(2).and_then(|ns| ns.as_str()) == Some(ISO_639_1)
+        })
+        .and_then(|tag| tag.get(1).and_then(|v| v.as_str()))
+        .map(|lang| lang.to_lowercase())
+}
+
+/// Guess the language of a piece of text
+pub fn detect_content_language(content: &str) -> String {
+    if let Some(lang) = detect_script(content) {
+        return lang.to_string();
+    }
+
+    let lowered = content.to_lowercase();
+    let words: Vec<&str> = lowered
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut best: Option<(&str, usize)> = None;
+    for (lang, stopwords) in STOPWORDS {
+        let hits = words.iter().filter(|w| stopwords.contains(w)).count();
+        if hits >= MIN_STOPWORD_HITS && best.map_or(true, |(_, best_hits)| hits > best_hits) {
+            best = Some((lang, hits));
+        }
+    }
+
+    best.map(|(lang, _)| lang.to_string())
+        .unwrap_or_else(|| UNDETERMINED.to_string())
+}
+
+/// Identify languages with a distinctive script by counting characters per script
+fn detect_script(content: &str) -> Option<&'static str> {
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut kana = 0usize;
+
+    for c in content.chars() {
+        let lang = match c as u32 {
+            0x3040..=0x30FF => {
+                kana += 1;
+                "ja"
+            }
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => "ko",
+            0x4E00..=0x9FFF => "zh",
+            0x0400..=0x04FF => "ru",
+            0x0370..=0x03FF => "el",
+            0x0590..=0x05FF => "he",
+            0x0600..=0x06FF => "ar",
+            0x0900..=0x097F => "hi",
+            0x0E00..=0x0E7F => "th",
+            _ => continue,
+        };
+        *counts.entry(lang).or_insert(0) += 1;
+    }
+
+    // Japanese text mixes kanji with kana, so any kana means Japanese
+    if kana > 0 {
+        let han = counts.remove("zh").unwrap_or(0);
+        *counts.entry("ja").or_insert(0) += han;
+    }
+
+    let (lang, count) = counts.into_iter().max_by_key(|(_, count)| *count)?;
+    let letters = content.chars().filter(|c| c.is_alphabetic()).count();
+
+    // Only trust the script when it dominates the text
+    if count * 2 >= letters {
+        Some(lang)
+    } else {
+        None
+    }
+}
+
+/// Group event ids into segments keyed by detected language
+pub fn build_language_segments(events: &[Value]) -> BTreeMap<String, Vec<String>> {
+    let mut segments: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for event in events {
+        if let Some(id) = event.get("id").and_then(|id| id.as_str()) {
+            segments.entry(detect_event_language(event))
+                .or_default()
+                .push(id.to_string());
+        }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nip32_label_takes_precedence() {
+        let event = json!({
+            "id": "a",
+            "content": "the cat is on the mat",
+            "tags": [["L", "ISO-639-1"], ["l", "DE", "ISO-639-1"]]
+        });
+        assert_eq!(detect_event_language(&event), "de");
+    }
+
+    #[test]
+    fn test_detect_content_language() {
+        assert_eq!(detect_content_language("This is the best day of the year"), "en");
+        assert_eq!(detect_content_language("Esto es para los amigos que no vienen"), "es");
+        assert_eq!(detect_content_language("Das ist nicht der Weg und ich weiß es"), "de");
+        assert_eq!(detect_content_language("Привет, как дела?"), "ru");
+        assert_eq!(detect_content_language("今日はいい天気ですね"), "ja");
+        assert_eq!(detect_content_language("你好世界"), "zh");
+        assert_eq!(detect_content_language("gm"), UNDETERMINED);
+    }
+
+    #[test]
+    fn test_build_language_segments() {
+        let events = vec![
+            json!({"id": "1", "content": "the sun is up and it is warm", "tags": []}),
+            json!({"id": "2", "content": "안녕하세요", "tags": []}),
+            json!({"id": "3", "content": "gm", "tags": []}),
+        ];
+        let segments = build_language_segments(&events);
+        assert_eq!(segments["en"], vec!["1"]);
+        assert_eq!(segments["ko"], vec!["2"]);
+        assert_eq!(segments[UNDETERMINED], vec!["3"]);
+    }
+}
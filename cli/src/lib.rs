@@ -3,64 +3,16 @@
 
 use cassette_tools::CassetteSchema;
 use serde_json::{json, Value};
-use serde::{Deserialize, Serialize};
-use cassette_tools::{string_to_ptr, ptr_to_string};
+use cassette_tools::{string_to_ptr, ptr_to_string, messages, Filter, NostrEvent};
 
 // Include the notes.json file at build time
 const NOTES_JSON: &str = include_str!("../notes.json");
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Note {
-    id: String,
-    pubkey: String,
-    created_at: i64,
-    #[serde(default)]
-    kind: i64,
-    tags: Vec<Vec<String>>,
-    content: String,
-    sig: String,
-}
-
-// Helper function for allocating strings
+// Legacy allocation export; cassette-tools' allocation table also backs
+// dealloc_string and get_allocation_size, so it frees exactly this buffer
 #[no_mangle]
 pub extern "C" fn alloc_string(len: usize) -> *mut u8 {
-    // Allocate memory with additional padding to prevent TypedArray errors
-    let mut buf = Vec::with_capacity(len + 16);
-    buf.resize(len + 16, 0);
-    let ptr = buf.as_mut_ptr();
-    std::mem::forget(buf);
-    ptr
-}
-
-// Helper function for deallocating strings
-#[no_mangle]
-pub extern "C" fn dealloc_string_impl(ptr: *mut u8, len: usize) {
-    unsafe {
-        let len_with_padding = len + 16;
-        let _ = Vec::from_raw_parts(ptr, len, len_with_padding);
-        // Memory will be deallocated when Vec is dropped
-    }
-}
-
-// Add the standard function that the cassette-loader expects
-#[no_mangle]
-pub extern "C" fn dealloc_string(ptr: *mut u8) {
-    // First get the length from the MSGB format (4 bytes header + 4 bytes length)
-    let len = unsafe {
-        if ptr.is_null() {
-            return;
-        }
-        let header = std::slice::from_raw_parts(ptr, 8);
-        if header[0] == b'M' && header[1] == b'S' && header[2] == b'G' && header[3] == b'B' {
-            // MSGB format has length at bytes 4-7
-            u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize
-        } else {
-            // Fallback: use a fixed size if we can't determine length
-            1024
-        }
-    };
-    
-    dealloc_string_impl(ptr, len);
+    cassette_tools::alloc_buffer(len)
 }
 
 // Standardized NIP-01 interface functions
@@ -199,7 +151,7 @@ pub extern "C" fn req(request_ptr: *const u8, request_len: usize) -> *mut u8 {
     };
 
     // Parse notes from the embedded NOTES_JSON
-    let notes: Vec<Note> = match serde_json::from_str(NOTES_JSON) {
+    let notes: Vec<NostrEvent> = match serde_json::from_str(NOTES_JSON) {
         Ok(n) => n,
         Err(e) => {
             // Add more detailed debugging for the JSON parse error
@@ -220,104 +172,13 @@ pub extern "C" fn req(request_ptr: *const u8, request_len: usize) -> *mut u8 {
                 None => return string_to_ptr(json!(["NOTICE", "Invalid subscription ID"]).to_string()),
             };
             
-            let filter = &array[2];
-            if let Some(obj) = filter.as_object() {
-                // Process standard filters first
-                if let Some(kinds) = obj.get("kinds").and_then(|k| k.as_array()) {
-                    filtered_notes.retain(|note| kinds.iter().any(|k| k.as_i64().map_or(false, |k| k == note.kind)));
-                }
-                if let Some(authors) = obj.get("authors").and_then(|a| a.as_array()) {
-                    filtered_notes.retain(|note| authors.iter().any(|a| a.as_str().map_or(false, |a| a == &note.pubkey)));
-                }
-                if let Some(since) = obj.get("since").and_then(|s| s.as_i64()) {
-                    filtered_notes.retain(|note| note.created_at >= since);
-                }
-                if let Some(until) = obj.get("until").and_then(|u| u.as_i64()) {
-                    filtered_notes.retain(|note| note.created_at <= until);
-                }
-                if let Some(limit) = obj.get("limit").and_then(|l| l.as_i64()) {
-                    filtered_notes.truncate(limit as usize);
-                }
-                if let Some(ids) = obj.get("ids").and_then(|i| i.as_array()) {
-                    filtered_notes.retain(|note| ids.iter().any(|id| id.as_str().map_or(false, |id| id == &note.id)));
-                }
-                
-                // Process tag filters with proper validation
-                for (key, value) in obj.iter() {
-                    if key.starts_with('#') && key.len() > 1 {
-                        let tag_name = &key[1..]; // Remove the '#' prefix
-                        
-                        // Validate tag values array
-                        if let Some(tag_values) = value.as_array() {
-                            // Check that all values are strings
-                            let mut all_strings = true;
-                            let mut string_values = Vec::new();
-                            
-                            for val in tag_values {
-                                if let Some(str_val) = val.as_str() {
-                                    string_values.push(str_val.to_string());
-                                } else {
-                                    all_strings = false;
-                                    break;
-                                }
-                            }
-                            
-                            if !all_strings {
-                                return string_to_ptr(json!(["NOTICE", format!("Tag values for {} must be strings", key)]).to_string());
-                            }
-                            
-                            // Apply tag filter with validated string values
-                            filtered_notes.retain(|note| {
-                                let matching_tags: Vec<_> = note.tags.iter()
-                                    .filter(|tag| tag.len() > 0 && tag[0] == tag_name)
-                                    .collect();
-                                
-                                string_values.iter().any(|val| {
-                                    matching_tags.iter().any(|tag| tag.len() > 1 && tag[1] == *val)
-                                })
-                            });
-                        } else {
-                            return string_to_ptr(json!(["NOTICE", format!("Tag filter {} must be an array", key)]).to_string());
-                        }
-                    }
-                    // Process NIP-119 AND tag filters
-                    else if key.starts_with('&') && key.len() > 1 {
-                        let tag_name = &key[1..]; // Remove the '&' prefix
-                        
-                        // Validate tag values array
-                        if let Some(tag_values) = value.as_array() {
-                            // Check that all values are strings
-                            let mut all_strings = true;
-                            let mut string_values = Vec::new();
-                            
-                            for val in tag_values {
-                                if let Some(str_val) = val.as_str() {
-                                    string_values.push(str_val.to_string());
-                                } else {
-                                    all_strings = false;
-                                    break;
-                                }
-                            }
-                            
-                            if !all_strings {
-                                return string_to_ptr(json!(["NOTICE", format!("Tag values for {} must be strings", key)]).to_string());
-                            }
-                            
-                            // Apply AND tag filter with validated string values
-                            filtered_notes.retain(|note| {
-                                let matching_tags: Vec<_> = note.tags.iter()
-                                    .filter(|tag| tag.len() > 0 && tag[0] == tag_name)
-                                    .collect();
-                                
-                                string_values.iter().all(|val| {
-                                    matching_tags.iter().any(|tag| tag.len() > 1 && tag[1] == *val)
-                                })
-                            });
-                        } else {
-                            return string_to_ptr(json!(["NOTICE", format!("Tag filter {} must be an array", key)]).to_string());
-                        }
-                    }
-                }
+            let filter = match Filter::from_json(&array[2]) {
+                Ok(filter) => filter,
+                Err(e) => return string_to_ptr(json!(["NOTICE", format!("Invalid filter: {}", e)]).to_string()),
+            };
+            filtered_notes.retain(|note| filter.matches(note));
+            if let Some(limit) = filter.limit {
+                filtered_notes.truncate(limit);
             }
 
             // Send multiple events in separate EVENT messages
@@ -365,12 +226,11 @@ pub extern "C" fn close(close_ptr: *const u8, close_len: usize) -> *mut u8 {
         Err(e) => return string_to_ptr(json!(["NOTICE", format!("Invalid JSON: {}", e)]).to_string()),
     };
 
-    // Extract the subscription ID (not used currently but kept for future reference)
-    let _subscription_id = close.as_array()
+    let subscription_id = close.as_array()
         .and_then(|a| a.get(1))
         .and_then(|s| s.as_str())
         .unwrap_or("");
 
-    // Return a simple NOTICE response
-    string_to_ptr(json!(["NOTICE", "Subscription closed"]).to_string())
+    // Confirm the close per NIP-01
+    string_to_ptr(messages::closed(subscription_id, "").to_string())
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+//! Provenance records embedded in derived cassettes
+//!
+//! Cassettes produced from other cassettes (dub, slice, record --append) carry a `lineage`
+//! object in their metadata: the operation, the filter applied and one entry
+//! per parent cassette. Dubs record their `exclude` filters too, and each
+//! parent the `filters` given to it alone with `--filter-for`. Parents that were derived themselves keep their own
+//! lineage nested inside, so the full chain survives repeated remixing.
+
+use serde_json::{json, Map, Value};
+
+/// Describe a parent cassette, nesting its own lineage when it has one
+pub fn parent_entry(name: &str, sha256: &str, events: usize, metadata: Option<&Value>) -> Value {
+    let mut entry = json!({
+        "name": name,
+        "sha256": sha256,
+        "events": events
+    });
+    if let Some(lineage) = metadata.and_then(|m| m.get("lineage")) {
+        entry["lineage"] = lineage.clone();
+    }
+    entry
+}
+
+/// Build the lineage record for a cassette derived from `parents`
+pub fn lineage_record(operation: &str, filter: &Map<String, Value>, parents: Vec<Value>, events: usize) -> Value {
+    json!({
+        "operation": operation,
+        "filter": filter,
+        "events": events,
+        "created_at": chrono::Utc::now().timestamp(),
+        "parents": parents
+    })
+}
+
+/// Render a lineage record as an indented provenance tree
+pub fn format_lineage(lineage: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+    format_record(lineage, "", &mut lines);
+    lines
+}
+
+fn format_record(lineage: &Value, prefix: &str, lines: &mut Vec<String>) {
+    let operation = lineage.get("operation").and_then(|o| o.as_str()).unwrap_or("unknown");
+    let mut line = format!("{}{}", prefix, operation);
+
+    if let Some(filter) = lineage.get("filter").and_then(|f| f.as_object()) {
+        if !filter.is_empty() {
+            line.push_str(&format!(" {}", Value::Object(filter.clone())));
+        }
+    }
+    for exclude in lineage.get("exclude").and_then(|e| e.as_array()).into_iter().flatten() {
+        line.push_str(&format!(" exclude {}", exclude));
+    }
+    if let Some(events) = lineage.get("events").and_then(|e| e.as_u64()) {
+        line.push_str(&format!(" → {} events", events));
+    }
+    if let Some(created_at) = lineage.get("created_at").and_then(|t| t.as_i64()) {
+        if let Some(time) = chrono::DateTime::from_timestamp(created_at, 0) {
+            line.push_str(&format!(" ({})", time.format("%Y-%m-%d %H:%M UTC")));
+        }
+    }
+    lines.push(line);
+
+    let parents = lineage.get("parents").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+    for (i, parent) in parents.iter().enumerate() {
+        let last = i + 1 == parents.len();
+        let branch = if last { "└─ " } else { "├─ " };
+        let child_prefix = format!("{}{}", prefix, if last { "   " } else { "│  " });
+
+        let name = parent.get("name").and_then(|n| n.as_str()).unwrap_or("unknown");
+        let sha256 = parent.get("sha256").and_then(|s| s.as_str()).unwrap_or("");
+        let mut line = format!("{}{}{}", prefix, branch, name);
+        if !sha256.is_empty() {
+            line.push_str(&format!(" sha256:{}", &sha256[..sha256.len().min(12)]));
+        }
+        if let Some(events) = parent.get("events").and_then(|e| e.as_u64()) {
+            line.push_str(&format!(" ({} events)", events));
+        }
+        for filter in parent.get("filters").and_then(|f| f.as_array()).into_iter().flatten() {
+            line.push_str(&format!(" {}", filter));
+        }
+        lines.push(line);
+
+        if let Some(parent_lineage) = parent.get("lineage") {
+            format_record(parent_lineage, &child_prefix, lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_lineage() {
+        let mut b = parent_entry("b.cassette", "bbbbbbbbbbbbbbbb", 5, None);
+        b["filters"] = json!([{"kinds": [7]}]);
+        let mut dubbed = lineage_record("dub", &Map::new(), vec![
+            parent_entry("a.cassette", "aaaaaaaaaaaaaaaa", 10, None),
+            b,
+        ], 15);
+        dubbed["exclude"] = json!([{"kinds": [4]}]);
+
+        let mut filter = Map::new();
+        filter.insert("kinds".to_string(), json!([1]));
+        let metadata = json!({"lineage": dubbed});
+        let sliced = lineage_record("slice", &filter, vec![
+            parent_entry("ab.cassette", "cccccccccccccccc", 15, Some(&metadata)),
+        ], 3);
+
+        let lines = format_lineage(&sliced);
+        assert!(lines[0].starts_with("slice {\"kinds\":[1]} → 3 events"));
+        assert_eq!(lines[1], "└─ ab.cassette sha256:cccccccccccc (15 events)");
+        assert!(lines[2].starts_with("   dub exclude {\"kinds\":[4]} → 15 events"));
+        assert_eq!(lines[3], "   ├─ a.cassette sha256:aaaaaaaaaaaa (10 events)");
+        assert_eq!(lines[4], "   └─ b.cassette sha256:bbbbbbbbbbbb (5 events) {\"kinds\":[7]}");
+    }
+}
@@ -1,16 +1,16 @@
 use anyhow::{Context, Result, anyhow};
-use clap::{Parser, Subcommand};
+use clap::{builder::TypedValueParser, Parser, Subcommand};
 use serde_json::{Value, json};
-use cassette_loader::{Cassette, SendResult};
+use cassette_loader::{Cassette, EventTracker, SegmentedCassette, SendResult};
 use std::fs;
-use std::io::{Read, Write, BufReader, BufRead, Seek};
+use std::io::Write;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 use chrono::Utc;
 use std::fs::File;
 use tempfile::{tempdir, TempDir};
 use std::collections::{HashMap, HashSet};
-use wasmtime::{Store, Module, Instance, Memory, TypedFunc, Engine};
+use wasmtime::{Store, Module, Instance, Engine};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, accept_async};
 use futures_util::{StreamExt, SinkExt};
 use std::sync::Arc;
@@ -19,11 +19,53 @@ use tokio::sync::{Mutex, RwLock};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time::{timeout, Duration};
 use glob::glob;
-use secp256k1::{XOnlyPublicKey, Secp256k1, Message as Secp256k1Message};
 use sha2::{Sha256, Digest};
+use cassette_tools::{replaceable, Filter};
 
 mod ui;
+mod append;
 mod deps;
+mod examples;
+mod labels;
+mod language;
+mod checkpoint;
+mod compile_cache;
+mod conformance;
+mod deck_status;
+mod deck_storage;
+mod export;
+mod fetch;
+mod ingest;
+mod input;
+mod integrity;
+mod guest;
+mod lineage;
+mod merge;
+mod mute;
+mod ndb_import;
+mod outbox;
+mod content_warning;
+mod auth;
+mod bench;
+mod payload;
+mod player;
+mod preload;
+mod redact;
+mod relay_info;
+mod report;
+mod reproducible;
+mod replication;
+mod response_validation;
+mod retry;
+mod segments;
+mod signing;
+mod sizes;
+mod timeline;
+mod validation;
+mod verified;
+mod watch;
+mod nip19;
+mod zaps;
 mod embedded_cassette_tools;
 
 /// Sanitize a name for use as a filename
@@ -63,202 +105,26 @@ fn is_cassette_file(path: &std::path::Path) -> bool {
         .map_or(false, |ext| ext == "cassette" || ext == "wasm")
 }
 
-/// Validate a Nostr event using rust-nostr
-/// Returns true if the event is valid, false otherwise
-fn validate_nostr_event(event_json: &Value, verbose: bool) -> bool {
-    // Extract required fields from the event JSON
-    let id = match event_json.get("id").and_then(|v| v.as_str()) {
-        Some(id) => id,
-        None => {
-            if verbose {
-                println!("❌ Event missing 'id' field");
-            }
-            return false;
-        }
-    };
-    
-    let pubkey = match event_json.get("pubkey").and_then(|v| v.as_str()) {
-        Some(pk) => pk,
-        None => {
-            if verbose {
-                println!("❌ Event {} missing 'pubkey' field", id);
-            }
-            return false;
-        }
-    };
-    
-    let sig = match event_json.get("sig").and_then(|v| v.as_str()) {
-        Some(sig) => sig,
-        None => {
-            if verbose {
-                println!("❌ Event {} missing 'sig' field", id);
-            }
-            return false;
-        }
-    };
-    
-    let created_at = match event_json.get("created_at").and_then(|v| v.as_i64()) {
-        Some(ts) => ts,
-        None => {
-            if verbose {
-                println!("❌ Event {} missing 'created_at' field", id);
-            }
-            return false;
-        }
-    };
-    
-    let kind = match event_json.get("kind").and_then(|v| v.as_i64()) {
-        Some(k) => k,
-        None => {
-            if verbose {
-                println!("❌ Event {} missing 'kind' field", id);
-            }
-            return false;
-        }
-    };
-    
-    let content = event_json.get("content").and_then(|v| v.as_str()).unwrap_or("");
-    let tags = event_json.get("tags").and_then(|v| v.as_array()).map(|arr| {
-        arr.iter().filter_map(|tag| {
-            if let Some(tag_arr) = tag.as_array() {
-                let tag_strs: Vec<String> = tag_arr.iter()
-                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                    .collect();
-                if !tag_strs.is_empty() {
-                    Some(tag_strs)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        }).collect::<Vec<_>>()
-    }).unwrap_or_default();
-    
-    // Recreate the event ID to verify it matches
-    // Format: [0, pubkey, created_at, kind, tags, content]
-    let mut id_input = format!("[0,\"{}\",{},{},[", pubkey, created_at, kind);
-    
-    for (i, tag) in tags.iter().enumerate() {
-        if i > 0 {
-            id_input.push(',');
-        }
-        id_input.push_str(&serde_json::to_string(tag).unwrap_or_default());
-    }
-    id_input.push_str("],");
-    id_input.push_str(&serde_json::to_string(content).unwrap_or_default());
-    id_input.push(']');
-    
-    // Calculate SHA256 hash
-    let mut hasher = Sha256::new();
-    hasher.update(id_input.as_bytes());
-    let computed_id = hex::encode(hasher.finalize());
-    
-    // Debug output for troubleshooting
-    if verbose {
-        println!("🔍 Event validation debug:");
-        println!("  Input string: {}", id_input);
-        println!("  Expected ID:  {}", id);
-        println!("  Computed ID:  {}", computed_id);
-        println!("  Match: {}", computed_id == id);
-    }
-    
-    // Verify the ID matches
-    if computed_id != id {
-        if verbose {
-            println!("❌ Event {} has invalid ID (computed: {})", id, computed_id);
-        }
-        return false;
-    }
-    
-    // Verify the signature
-    let secp = Secp256k1::new();
-    
-    // Parse pubkey
-    let pubkey_bytes = match hex::decode(pubkey) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} has invalid pubkey format", id);
-            }
-            return false;
-        }
-    };
-    
-    let xonly_pubkey = match XOnlyPublicKey::from_slice(&pubkey_bytes) {
-        Ok(pk) => pk,
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} has invalid pubkey", id);
-            }
-            return false;
-        }
-    };
-    
-    // Parse signature
-    let sig_bytes = match hex::decode(sig) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} has invalid signature format", id);
-            }
-            return false;
-        }
-    };
-    
-    if sig_bytes.len() != 64 {
-        if verbose {
-            println!("❌ Event {} has invalid signature length", id);
-        }
-        return false;
-    }
-    
-    let signature = match secp256k1::schnorr::Signature::from_slice(&sig_bytes) {
-        Ok(sig) => sig,
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} has invalid signature", id);
-            }
-            return false;
-        }
-    };
-    
-    // Create message hash for verification
-    let id_bytes = match hex::decode(id) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} has invalid ID format", id);
-            }
-            return false;
-        }
-    };
-    
-    let message = match Secp256k1Message::from_slice(&id_bytes) {
-        Ok(msg) => msg,
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} cannot create message from ID", id);
-            }
-            return false;
-        }
-    };
-    
-    // Verify signature
-    match secp.verify_schnorr(&signature, &message, &xonly_pubkey) {
-        Ok(_) => {
-            if verbose {
-                println!("✅ Event {} is valid", id);
-            }
-            true
+/// Cassettes named on the command line, glob patterns (`'archive/*.cassette'`) expanded
+/// to the cassette files they match; plain paths are kept as given
+fn expand_cassette_globs(patterns: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let pattern = pattern.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            paths.push(PathBuf::from(pattern.as_ref()));
+            continue;
         }
-        Err(_) => {
-            if verbose {
-                println!("❌ Event {} has invalid signature", id);
-            }
-            false
+        let matched: Vec<PathBuf> = glob(&pattern)?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file() && is_cassette_file(path))
+            .collect();
+        if matched.is_empty() {
+            return Err(anyhow!("No cassettes match {}", pattern));
         }
+        paths.extend(matched);
     }
+    Ok(paths)
 }
 
 // Macro for debug output that only prints in verbose mode
@@ -298,8 +164,9 @@ fn escape_json_for_raw_string(json: &str) -> String {
     // If we found "### or more, we need to use more # symbols in the raw string
     // For now, we'll use a simpler approach: replace "### with something else
     if max_hashes >= 3 {
-        // Replace "### with "##\u{23} (using unicode escape for #)
-        json.replace("\"###", "\"##\\u{23}")
+        // Replace "### with "##\u0023, the JSON escape for #: a quote followed by #
+        // only occurs inside a string, where the escape decodes back to the same text
+        json.replace("\"###", "\"##\\u0023")
     } else {
         json.to_string()
     }
@@ -330,6 +197,7 @@ mod generator {
     // Load template files
     const TEMPLATE_RS: &str = include_str!("templates/cassette_template.rs");
     const TEMPLATE_CARGO: &str = include_str!("templates/Cargo.toml");
+    const CASSETTE_WIT: &str = include_str!("templates/cassette.wit");
 
     pub struct CassetteGenerator {
         output_dir: PathBuf,
@@ -337,6 +205,8 @@ mod generator {
         project_dir: PathBuf,
         template_vars: HashMap<String, String>,
         verbose: bool,
+        // Shared cargo target directory, or None to build inside the project
+        target_dir: Option<PathBuf>,
     }
 
     impl CassetteGenerator {
@@ -351,6 +221,7 @@ mod generator {
                 project_dir: project_dir.to_path_buf(),
                 template_vars: HashMap::new(),
                 verbose: false,
+                target_dir: Some(super::compile_cache::target_dir()),
             }
         }
 
@@ -361,6 +232,26 @@ mod generator {
         pub fn set_verbose(&mut self, verbose: bool) {
             self.verbose = verbose;
         }
+        
+        /// Build in the shared compile cache (the default) or in a target directory of its own
+        pub fn set_cache(&mut self, enabled: bool) {
+            self.target_dir = enabled.then(super::compile_cache::target_dir);
+        }
+        
+        /// Whether the cassette should be built with the minimal (size-optimized) profile
+        fn is_minimal(&self) -> bool {
+            self.template_vars.get("minimal").map_or(false, |v| v == "true")
+        }
+        
+        /// Whether the cassette should be wrapped as a component exporting the WIT world
+        fn is_component(&self) -> bool {
+            self.template_vars.get("component").map_or(false, |v| v == "true")
+        }
+        
+        /// Whether build paths and parallel codegen should be kept out of the module
+        fn is_deterministic(&self) -> bool {
+            self.template_vars.get("deterministic").map_or(false, |v| v == "true")
+        }
 
         pub fn generate(&self) -> Result<PathBuf> {
             self.generate_with_callback(None::<fn() -> Result<()>>)
@@ -388,6 +279,9 @@ mod generator {
             let tools_dir = if let Some(existing_tools) = existing_tools_dir {
                 // Make sure we use the absolute path
                 fs::canonicalize(existing_tools)?
+            } else if self.target_dir.is_some() {
+                // Cached builds need cassette-tools at the same place every time
+                super::compile_cache::cached_tools(|dir| self.extract_embedded_tools(dir))?
             } else {
                 // Extract to a local directory within the project
                 let tools_dir = self.project_dir.join("cassette-tools");
@@ -398,12 +292,15 @@ mod generator {
             
             // Create the wrapper project with local path dependency
             self.create_embedded_project_files(&src_dir, &tools_dir, &self.project_dir)?;
+            self.write_wit(&self.project_dir)?;
             
             // Build the WASM module
-            let output_path = self.build_wasm(&self.project_dir, None::<fn() -> Result<()>>)?;
+            let output_path = self.build_wasm(&self.project_dir, &tools_dir, None::<fn() -> Result<()>>)?;
+            let output_path = self.componentize(output_path)?;
             
             // Copy to destination
             let dest_path = self.copy_output(output_path)?;
+            self.prune_cache();
             
             Ok(dest_path)
         }
@@ -476,7 +373,9 @@ mod generator {
                 "version": "0.1.0",
                 "description": "Generated Cassette",
                 "cassette_tools_path": tools_dir.display().to_string(),
-                "features_array": self.template_vars.get("features_array").unwrap_or(&"[\"default\"]".to_string())
+                "features_array": self.template_vars.get("features_array").unwrap_or(&"[\"default\"]".to_string()),
+                "minimal": self.is_minimal(),
+                "component": self.is_component()
             });
             
             let cargo_content = handlebars.render_template(TEMPLATE_CARGO, &cargo_data)
@@ -501,15 +400,27 @@ mod generator {
 
             // Create the lib.rs file from template
             self.create_project_files(&src_dir)?;
+            self.write_wit(&self.project_dir)?;
 
             // Build the WASM module with progress callback
-            let output_path = self.build_wasm(&self.project_dir, progress_callback)?;
+            let tools_dir = PathBuf::from(self.get_relative_cassette_tools_path()?);
+            let output_path = self.build_wasm(&self.project_dir, &tools_dir, progress_callback)?;
+            let output_path = self.componentize(output_path)?;
 
             // Copy the output to the destination
             let dest_path = self.copy_output(output_path)?;
+            self.prune_cache();
             
             Ok(dest_path)
         }
+        
+        /// Drop this cassette's artifacts from the shared target directory; they embed its events
+        /// and no later build can use them
+        fn prune_cache(&self) {
+            if let Some(target_dir) = &self.target_dir {
+                super::compile_cache::prune(target_dir, &self.name);
+            }
+        }
 
         fn create_project_files(&self, src_dir: &Path) -> Result<()> {
             // Create Handlebars instance for template rendering
@@ -569,7 +480,9 @@ mod generator {
                 "version": "0.1.0",
                 "description": "Generated Cassette",
                 "cassette_tools_path": cassette_tools_path,
-                "features_array": self.template_vars.get("features_array").unwrap_or(&"[\"default\"]".to_string())
+                "features_array": self.template_vars.get("features_array").unwrap_or(&"[\"default\"]".to_string()),
+                "minimal": self.is_minimal(),
+                "component": self.is_component()
             });
 
             // Render the Cargo.toml template
@@ -632,7 +545,8 @@ mod generator {
             }
         }
 
-        fn build_wasm<F>(&self, project_dir: &Path, mut progress_callback: Option<F>) -> Result<PathBuf> 
+        /// Compile the project; `tools_dir` is the cassette-tools checkout it depends on
+        fn build_wasm<F>(&self, project_dir: &Path, tools_dir: &Path, mut progress_callback: Option<F>) -> Result<PathBuf> 
         where 
             F: FnMut() -> Result<()>
         {
@@ -657,19 +571,36 @@ mod generator {
                 if let Ok(cassette_tools_features) = serde_json::from_str::<Vec<String>>(features_json) {
                     for feature in cassette_tools_features {
                         match feature.as_str() {
+                            "nip09" => features.push("nip09"),
+                            "nip40" => features.push("nip40"),
                             "nip42" => features.push("nip42"),
                             "nip45" => features.push("nip45"), 
                             "nip50" => features.push("nip50"),
+                            "writable" => features.push("writable"),
+                            "verify" => features.push("verify"),
                             _ => {} // Ignore other features
                         }
                     }
                 }
             }
             
+            if self.is_minimal() {
+                features.push("minimal");
+            }
+            
             // Build cargo command with features
             let features_str = features.join(",");
-            let mut child = Command::new("cargo")
-                .args(&["build", "--target", "wasm32-unknown-unknown", "--release", "--features", &features_str])
+            let target_dir = self.target_dir.clone().unwrap_or_else(|| project_dir.join("target"));
+            let mut command = Command::new("cargo");
+            command.args(&["build", "--target", "wasm32-unknown-unknown", "--release", "--features", &features_str]);
+            if self.target_dir.is_some() {
+                debugln!(self.verbose, "  Compile cache: {}", target_dir.display());
+                command.env("CARGO_TARGET_DIR", &target_dir);
+            }
+            if self.is_deterministic() {
+                command.envs(super::reproducible::cargo_env(project_dir, tools_dir, &target_dir));
+            }
+            let mut child = command
                 .spawn()
                 .context("Failed to run cargo build. Make sure Rust and the wasm32-unknown-unknown target are installed.")?;
             
@@ -698,7 +629,7 @@ mod generator {
             }
 
             // Return the path to the generated WASM file
-            let wasm_path = project_dir.join("target/wasm32-unknown-unknown/release")
+            let wasm_path = target_dir.join("wasm32-unknown-unknown/release")
                 .join(format!("{}.wasm", self.name.replace("-", "_")));
 
             if !wasm_path.exists() {
@@ -708,6 +639,50 @@ mod generator {
             Ok(wasm_path)
         }
 
+        // The WIT world component cassettes are generated against
+        fn write_wit(&self, project_dir: &Path) -> Result<()> {
+            if !self.is_component() {
+                return Ok(());
+            }
+            let wit_dir = project_dir.join("wit");
+            fs::create_dir_all(&wit_dir)?;
+            fs::write(wit_dir.join("cassette.wit"), CASSETTE_WIT).context("Failed to write cassette.wit")
+        }
+        
+        // Wrap the core module as a component, lifting the exports wit-bindgen
+        // described in the module into the WIT world
+        fn componentize(&self, wasm_path: PathBuf) -> Result<PathBuf> {
+            if !self.is_component() {
+                return Ok(wasm_path);
+            }
+            debugln!(self.verbose, "  Encoding component...");
+            let module = fs::read(&wasm_path)?;
+            let component = wit_component::ComponentEncoder::default()
+                .module(&module)?
+                .validate(true)
+                .encode()
+                .context("Failed to encode the cassette as a component")?;
+            let component_path = wasm_path.with_extension("component.wasm");
+            fs::write(&component_path, component)?;
+            Ok(component_path)
+        }
+
+        /// Write the template variables into a copy of the precompiled player instead of
+        /// compiling them in (`record --fast`)
+        pub fn generate_fast(&self, player: &[u8]) -> Result<PathBuf> {
+            let fields = super::player::FIELDS.map(|key| self.template_vars.get(key).map_or("", |value| value.as_str()));
+            let recording = super::player::encode_recording(fields)?;
+            let cassette = super::player::inject(player, &recording)?;
+            
+            fs::create_dir_all(&self.output_dir)
+                .context("Failed to create output directory")?;
+            let dest_path = self.output_dir.join(format!("{}.cassette", sanitize_filename(&self.name)));
+            fs::write(&dest_path, cassette)
+                .with_context(|| format!("Failed to write cassette to {:?}", dest_path))?;
+            debugln!(self.verbose, "  ✅ Injected {} bytes of events and indexes into the player", recording.len());
+            Ok(dest_path)
+        }
+
         fn copy_output(&self, wasm_path: PathBuf) -> Result<PathBuf> {
             // Create the output directory if it doesn't exist
             debugln!(self.verbose, "  Creating output directory: {:?}", self.output_dir);
@@ -780,109 +755,445 @@ fn process_info_command(
     let module = Module::from_binary(store.engine(), &wasm_bytes)?;
     let instance = Instance::new(&mut store, &module, &[])?;
     
+    let guest = guest::GuestApi::detect(&mut store, &instance)?;
+    if guest.is_legacy() {
+        eprintln!("Note: this cassette uses the legacy req/alloc_string interface (guest ABI v{})", guest.abi_version());
+    }
+    
     // Set NIP-11 info if provided
-    load_cassette_with_nip11(&mut store, &instance, nip11_args)?;
+    load_cassette_with_nip11(&mut store, &guest, nip11_args)?;
     
     // Check if the cassette exports an info function
-    if let Ok(info_func) = instance.get_typed_func::<(), i32>(&mut store, "info") {
-        // Call the info function
-        let info_ptr = info_func.call(&mut store, ())?;
-        
-        if info_ptr != 0 {
-            // Get memory first
-            let memory = instance.get_memory(&mut store, "memory")
-                .ok_or_else(|| anyhow!("Memory export not found"))?;
-            
-            // Read the info string
-            let info_str = read_string_from_memory(&mut store, &instance, &memory, info_ptr)?;
-            
-            // Pretty print the JSON
-            if let Ok(info_json) = serde_json::from_str::<Value>(&info_str) {
-                println!("{}", serde_json::to_string_pretty(&info_json)?);
-            } else {
-                println!("{}", info_str);
-            }
+    if !guest.has_info() {
+        eprintln!("This cassette does not support NIP-11 (no info function found)");
+    } else if let Some(info_str) = guest.info(&mut store)? {
+        // Pretty print the JSON
+        if let Ok(info_json) = serde_json::from_str::<Value>(&info_str) {
+            println!("{}", serde_json::to_string_pretty(&info_json)?);
         } else {
-            println!("{{}}");
+            println!("{}", info_str);
         }
     } else {
-        eprintln!("This cassette does not support NIP-11 (no info function found)");
+        println!("{{}}");
     }
     
     Ok(())
 }
 
-/// Helper function to get event count for a filter using NIP-45 COUNT
-fn get_event_count_for_filter(
-    store: &mut Store<()>,
-    instance: &Instance,
-    memory: &Memory,
-    alloc_func: &TypedFunc<i32, i32>,
-    dealloc_func: &TypedFunc<(i32, i32), ()>,
-    filter: &serde_json::Map<String, Value>,
-    subscription: &str,
-) -> Result<Option<u64>, anyhow::Error> {
-    // Get the send function for sending COUNT (try 'send' then 'req' for backward compatibility)
-    let send_func = match instance.get_typed_func::<(i32, i32), i32>(&mut *store, "send")
-        .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut *store, "req")) {
-        Ok(func) => func,
-        Err(_) => return Ok(None), // No send/req function available
+/// Process the inspect command - summarize a cassette and optionally its lineage
+/// `cassette verify`: check the manifest signed at record time against the cassette's events
+fn process_verify_command(cassette_path: &PathBuf, expected_pubkey: Option<&str>) -> Result<()> {
+    let mut cassette = Cassette::load(&cassette_path.to_string_lossy(), false)?;
+    let signed = cassette.verify_manifest()
+        .with_context(|| format!("{} failed verification", cassette_path.display()))?;
+    if let Some(expected) = expected_pubkey {
+        if signed.pubkey != expected {
+            return Err(anyhow!("{} is signed by {}, not {}", cassette_path.display(), signed.pubkey, expected));
+        }
+    }
+    let signed_at = chrono::DateTime::from_timestamp(signed.created_at, 0)
+        .map_or_else(|| signed.created_at.to_string(), |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    println!("✅ {}", cassette_path.display());
+    println!("  Signed by: {}", signed.pubkey);
+    println!("  Signed at: {}", signed_at);
+    println!("  Events: {} (sha256 {})", signed.events, signed.payload);
+    Ok(())
+}
+
+fn process_validate_command(cassette_path: &PathBuf, as_json: bool) -> Result<()> {
+    let wasm_bytes = fs::read(cassette_path)
+        .context("Failed to read cassette WASM file")?;
+    let mut report = integrity::Report::default();
+    let finish = |report: integrity::Report, events: Option<usize>| -> Result<()> {
+        if as_json {
+            println!("{}", serde_json::to_string_pretty(&report.to_json(&cassette_path.display().to_string()))?);
+        } else {
+            println!("🔍 {}", cassette_path.display());
+            for line in report.lines() {
+                println!("  {}", line);
+            }
+            if let Some(events) = events {
+                println!("  {} events checked", events);
+            }
+        }
+        if report.passed() {
+            Ok(())
+        } else {
+            Err(anyhow!("{} failed {} of {} checks", cassette_path.display(), report.failed(), report.checks.len()))
+        }
     };
     
-    // Create COUNT message with same filter
-    let count_message = json!(["COUNT", subscription, filter]);
-    let count_string = count_message.to_string();
-    let count_bytes = count_string.as_bytes();
-    
-    // Allocate memory for COUNT request
-    let count_ptr = alloc_func.call(&mut *store, count_bytes.len() as i32)?;
-    if count_ptr == 0 {
-        return Ok(None);
+    // Nothing else can be checked in a module that doesn't compile or instantiate
+    let engine = Engine::default();
+    let module = match Module::from_binary(&engine, &wasm_bytes) {
+        Ok(module) => module,
+        Err(e) => {
+            report.add("module", vec![format!("{:#}", e)]);
+            return finish(report, None);
+        }
+    };
+    report.add("module", Vec::new());
+    let mut store = Store::new(&engine, ());
+    let instance = match Instance::new(&mut store, &module, &[]) {
+        Ok(instance) => instance,
+        Err(e) => {
+            report.add("instantiate", vec![format!("{:#}", e)]);
+            return finish(report, None);
+        }
+    };
+    report.add("instantiate", Vec::new());
+    
+    // Exports are checked for the version the cassette declares, or its export names point to
+    let guest = guest::GuestApi::detect(&mut store, &instance);
+    let abi_version = match &guest {
+        Ok(guest) => guest.abi_version(),
+        Err(_) if module.get_export("send").is_some() || module.get_export("scrub").is_some() => cassette_tools::ABI_VERSION,
+        Err(_) => 1,
+    };
+    report.add("exports", integrity::check_exports(&module, abi_version));
+    
+    // The memory ABI: a message written into guest memory is answered with NIP-01 messages
+    let mut problems = Vec::new();
+    match &guest {
+        Err(e) => problems.push(format!("{:#}", e)),
+        Ok(guest) => {
+            if let Err(e) = guest.attach_payload(&mut store, cassette_path) {
+                problems.push(format!("payload: {:#}", e));
+            }
+            match guest.info(&mut store) {
+                Ok(Some(info)) if serde_json::from_str::<serde_json::Map<String, Value>>(&info).is_ok() => {}
+                Ok(Some(info)) => problems.push(format!("info is not a JSON object: {}", report::preview(&info, 80))),
+                Ok(None) => problems.push("info answered nothing".to_string()),
+                Err(e) => problems.push(format!("info: {:#}", e)),
+            }
+            match guest.send(&mut store, r#"["REQ","validate",{"limit":1}]"#) {
+                Ok(Some(reply)) => {
+                    let kind = serde_json::from_str::<Value>(&reply).ok()
+                        .and_then(|reply| reply.get(0).and_then(|k| k.as_str()).map(String::from));
+                    if !matches!(kind.as_deref(), Some("EVENT" | "EOSE" | "CLOSED" | "NOTICE")) {
+                        problems.push(format!("REQ answered with something other than a NIP-01 message: {}", report::preview(&reply, 80)));
+                    }
+                }
+                Ok(None) => problems.push("REQ answered nothing".to_string()),
+                Err(e) => problems.push(format!("REQ: {:#}", e)),
+            }
+        }
     }
+    report.add("memory ABI", problems);
+    
+    // Events as a REQ for everything serves them, from an instance of their own
+    let events = guest::GuestApi::open(&engine, &module, cassette_path)
+        .and_then(|(store, guest)| guest::GuestEvents::new(store, guest, &json!({})))
+        .and_then(|events| events.collect::<Result<Vec<Value>>>());
+    let events = match events {
+        Ok(events) => events,
+        Err(e) => {
+            report.add("events", vec![format!("{:#}", e)]);
+            return finish(report, None);
+        }
+    };
+    let (_, rejected) = validation::ValidationPolicy::default().partition(events.clone());
+    report.add("signatures", rejected.iter().map(|(id, rejection)| format!("{}: {}", id, rejection)).collect());
+    report.add("ordering", integrity::check_order(&events));
+    report.add("duplicates", integrity::check_duplicates(&events));
     
-    // Write COUNT request to memory
-    memory.write(&mut *store, count_ptr as usize, count_bytes)?;
-    
-    // Call send function with COUNT
-    let result_ptr = send_func.call(&mut *store, (count_ptr, count_bytes.len() as i32))?;
+    finish(report, Some(events.len()))
+}
+
+fn process_inspect_command(cassette_path: &PathBuf, show_lineage: bool, provenance_of: Option<&str>, as_json: bool) -> Result<()> {
+    let wasm_bytes = fs::read(cassette_path)
+        .context("Failed to read cassette WASM file")?;
     
-    // Deallocate request memory
-    dealloc_func.call(&mut *store, (count_ptr, count_bytes.len() as i32))?;
+    let engine = Engine::default();
+    let module = Module::from_binary(&engine, &wasm_bytes)?;
+    // The header is read before the events are attached, so it's reported even when they can't be
+    let (mut store, guest) = guest::GuestApi::instantiate(&engine, &module)?;
+    let info: Value = guest.info(&mut store)?
+        .and_then(|info| serde_json::from_str(&info).ok())
+        .unwrap_or_default();
+    let metadata = info.get("cassette").cloned().unwrap_or_default();
+    
+    // Everything goes into the JSON report; the same facts are printed as lines without --json
+    let mut report = serde_json::Map::new();
+    let mut lines = vec![format!("📼 {}", cassette_path.display())];
+    let sha256 = hex::encode(Sha256::digest(&wasm_bytes));
+    report.insert("path".to_string(), json!(cassette_path.display().to_string()));
+    report.insert("size_bytes".to_string(), json!(wasm_bytes.len()));
+    report.insert("sha256".to_string(), json!(sha256));
+    report.insert("abi_version".to_string(), json!(guest.abi_version()));
+    lines.push(format!("  Size: {:.1} KB", wasm_bytes.len() as f64 / 1024.0));
+    lines.push(format!("  SHA-256: {}", sha256));
+    lines.push(format!("  Guest ABI: v{}", guest.abi_version()));
+    
+    for (label, key) in [("Name", "name"), ("Description", "description"), ("Pubkey", "pubkey"), ("Contact", "contact")] {
+        if let Some(value) = info.get(key).and_then(|v| v.as_str()) {
+            report.insert(key.to_string(), json!(value));
+            lines.push(format!("  {}: {}", label, value));
+        }
+    }
+    if let Some(nips) = info.get("supported_nips") {
+        report.insert("supported_nips".to_string(), nips.clone());
+        lines.push(format!("  Supported NIPs: {}", nips));
+    }
+    if let Some(languages) = info.get("language_tags") {
+        report.insert("language_tags".to_string(), languages.clone());
+        lines.push(format!("  Languages: {}", languages));
+    }
+    if let Some(sampling) = metadata.get("sampling") {
+        report.insert("sampling".to_string(), sampling.clone());
+        lines.push(format!("  Sampling: {}", sampling));
+    }
+    if let Some(signer) = metadata.pointer("/signed_manifest/pubkey").and_then(|p| p.as_str()) {
+        report.insert("signed_by".to_string(), json!(signer));
+        lines.push(format!("  Signed by: {} (check with cassette verify)", signer));
+    }
+    if let Some(meta) = metadata.get("meta").and_then(|m| m.as_object()) {
+        report.insert("meta".to_string(), Value::Object(meta.clone()));
+        for (key, value) in meta {
+            lines.push(format!("  Meta {}: {}", key, value.as_str().map_or_else(|| value.to_string(), String::from)));
+        }
+    }
     
-    if result_ptr == 0 {
-        return Ok(None);
+    // How the events are stored: compressed, in a sidecar, and which indexes were built
+    let compression = metadata.get("compression").cloned().unwrap_or(Value::Null);
+    match compression.get("algorithm").and_then(|a| a.as_str()) {
+        Some(algorithm) => lines.push(format!(
+            "  Compression: {} ({} → {}, {}x)",
+            algorithm,
+            sizes::format_bytes(compression["uncompressed_bytes"].as_u64().unwrap_or(0)),
+            sizes::format_bytes(compression["compressed_bytes"].as_u64().unwrap_or(0)),
+            compression["ratio"],
+        )),
+        None => lines.push("  Compression: none".to_string()),
+    }
+    report.insert("compression".to_string(), compression);
+    if let Some(file) = metadata.pointer("/payload/file").and_then(|f| f.as_str()) {
+        report.insert("payload".to_string(), metadata["payload"].clone());
+        lines.push(format!("  Payload: sidecar {}", file));
     }
+    let indexes = cassette_indexes(&wasm_bytes, &metadata)?;
+    lines.push(format!("  Indexes: {}", match &indexes {
+        Some(indexes) if indexes.is_empty() => "none".to_string(),
+        Some(indexes) => indexes.join(", "),
+        None => "not recorded (built before inspect reported them)".to_string(),
+    }));
+    report.insert("indexes".to_string(), json!(indexes));
     
-    // Read the result
-    let result = read_string_from_memory(&mut *store, instance, memory, result_ptr)?;
+    let print = |report: serde_json::Map<String, Value>, lines: Vec<String>| -> Result<()> {
+        if as_json {
+            println!("{}", serde_json::to_string_pretty(&Value::Object(report))?);
+        } else {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Ok(())
+    };
     
-    // Try to deallocate the result
-    if let Ok(get_size_func) = instance.get_typed_func::<i32, i32>(&mut *store, "get_allocation_size") {
-        let size = get_size_func.call(&mut *store, result_ptr)?;
-        if size > 0 {
-            let _ = dealloc_func.call(&mut *store, (result_ptr, size));
+    if let Some(recipient) = metadata.pointer("/encryption/recipient").and_then(|r| r.as_str()) {
+        report.insert("encrypted_to".to_string(), json!(recipient));
+        lines.push(format!("  Encrypted to: {}", recipient));
+        if std::env::var_os(guest::UNLOCK_KEY_VAR).is_none() {
+            report.insert("events".to_string(), Value::Null);
+            lines.push(format!("  Events: locked (set {} to the recipient's key to read them)", guest::UNLOCK_KEY_VAR));
+            return print(report, lines);
         }
     }
     
-    // Parse COUNT response
-    if let Ok(parsed) = serde_json::from_str::<Value>(&result) {
-        if let Some(arr) = parsed.as_array() {
-            if arr.len() >= 3 && arr[0].as_str() == Some("COUNT") {
-                if let Some(count_obj) = arr.get(2) {
-                    if let Some(count) = count_obj.get("count") {
-                        return Ok(count.as_u64());
+    // A missing sidecar payload or an undecryptable archive leaves the event stats out,
+    // not the header and sizes above
+    let events = extract_all_events_from_cassette(cassette_path, &Nip11Args::default())
+        .and_then(|events| guest.attach_payload(&mut store, cassette_path).map(|()| events));
+    match &events {
+        Ok(events) => {
+            let total_bytes: usize = events.iter().map(sizes::event_bytes).sum();
+            let authors: HashSet<&str> = events.iter().filter_map(|e| e.get("pubkey").and_then(|p| p.as_str())).collect();
+            let times = events.iter().filter_map(|e| e.get("created_at").and_then(|t| t.as_i64()));
+            let (oldest, newest) = (times.clone().min(), times.max());
+            report.insert("events".to_string(), json!(events.len()));
+            report.insert("event_bytes".to_string(), json!(total_bytes));
+            report.insert("authors".to_string(), json!(authors.len()));
+            report.insert("oldest".to_string(), json!(oldest));
+            report.insert("newest".to_string(), json!(newest));
+            lines.push(format!("  Events: {} ({} of JSON)", events.len(), sizes::format_bytes(total_bytes as u64)));
+            lines.push(format!("  Authors: {}", authors.len()));
+            if let (Some(oldest), Some(newest)) = (oldest, newest) {
+                lines.push(format!("  Time range: {} to {}", report::time(oldest), report::time(newest)));
+            }
+            
+            if let Some(verification) = guest.verify_events(&mut store)? {
+                report.insert("signatures".to_string(), json!({
+                    "checked": verification.checked,
+                    "invalid": verification.invalid.iter().map(|i| json!({"id": i.id, "reason": i.reason})).collect::<Vec<_>>(),
+                }));
+                lines.push(format!("  Signatures: {} of {} events verified by the cassette", verification.checked - verification.invalid.len(), verification.checked));
+                for invalid in &verification.invalid {
+                    lines.push(format!("    ❌ {}: {}", invalid.id, invalid.reason));
+                }
+            }
+            if let Some(event_id) = provenance_of {
+                let source = guest.provenance(&mut store, event_id)?;
+                lines.push(match &source {
+                    Some(source) => format!("  Provenance of {}: {}", event_id, source),
+                    None if guest.has_provenance() => format!("  Provenance of {}: not recorded", event_id),
+                    None => format!("  Provenance of {}: the cassette records none (dub --provenance)", event_id),
+                });
+                let source = source.map(|s| serde_json::from_str::<Value>(&s).unwrap_or(Value::String(s)));
+                report.insert("provenance_of".to_string(), json!({"id": event_id, "source": source}));
+            }
+            if guest.has_provenance() {
+                // Events per input, in the order the inputs were given
+                let mut sources: Vec<(String, usize)> = Vec::new();
+                for event in events {
+                    let Some(id) = event.get("id").and_then(|i| i.as_str()) else { continue };
+                    let source = guest.provenance(&mut store, id)?.unwrap_or_else(|| "unrecorded".to_string());
+                    match sources.iter_mut().find(|(s, _)| *s == source) {
+                        Some((_, count)) => *count += 1,
+                        None => sources.push((source, 1)),
+                    }
+                }
+                if sources.iter().any(|(source, _)| source != "unrecorded") {
+                    lines.push("\n🧬 Provenance:".to_string());
+                    let mut by_source = Vec::new();
+                    for (source, count) in sources {
+                        let name = serde_json::from_str::<Value>(&source).ok()
+                            .and_then(|s| s.get("name").and_then(|n| n.as_str()).map(String::from))
+                            .unwrap_or(source);
+                        lines.push(format!("  {}: {} events", name, count));
+                        by_source.push(json!({"name": name, "events": count}));
                     }
+                    report.insert("provenance".to_string(), json!(by_source));
                 }
             }
+            if !events.is_empty() {
+                let histogram = sizes::kind_histogram(events);
+                report.insert("kinds".to_string(), json!(histogram.iter().map(|row| json!({
+                    "kind": row.kind,
+                    "events": row.events,
+                    "bytes": row.bytes,
+                    "largest": row.largest,
+                })).collect::<Vec<_>>()));
+                lines.push("\n📊 Bytes by kind:".to_string());
+                for line in sizes::format_histogram(&histogram, 10) {
+                    lines.push(format!("  {}", line));
+                }
+            }
+        }
+        Err(e) => {
+            report.insert("events".to_string(), Value::Null);
+            report.insert("events_error".to_string(), json!(format!("{:#}", e)));
+            lines.push(format!("  Events: unavailable ({:#})", e));
+        }
+    }
+    
+    if let Some(examples) = metadata.get("examples").and_then(|e| e.as_array()) {
+        report.insert("examples".to_string(), json!(examples));
+        lines.push("\n💡 Example queries:".to_string());
+        for example in examples {
+            let description = example.get("description").and_then(|d| d.as_str()).unwrap_or("");
+            lines.push(format!("  {}\n    {}", description, example.get("message").unwrap_or(&Value::Null)));
+        }
+    }
+    
+    let lineage = metadata.get("lineage");
+    report.insert("lineage".to_string(), lineage.cloned().unwrap_or(Value::Null));
+    if show_lineage {
+        lines.push("\n📜 Lineage:".to_string());
+        match lineage {
+            Some(lineage) => {
+                for line in lineage::format_lineage(lineage) {
+                    lines.push(format!("  {}", line));
+                }
+            }
+            None => lines.push("  No lineage recorded (original recording)".to_string()),
         }
+    } else if let Some(lineage) = lineage {
+        let operation = lineage.get("operation").and_then(|o| o.as_str()).unwrap_or("unknown");
+        let parents = lineage.get("parents").and_then(|p| p.as_array()).map_or(0, |p| p.len());
+        lines.push(format!("  Derived: {} of {} cassette(s) (use --lineage for the full chain)", operation, parents));
     }
     
-    Ok(None)
+    print(report, lines)
+}
+
+/// Indexes a cassette was built with: read from the recording of player-based cassettes,
+/// from the metadata of compiled ones; `None` for cassettes built before it was recorded
+fn cassette_indexes(wasm_bytes: &[u8], metadata: &Value) -> Result<Option<Vec<String>>> {
+    if let Some(fields) = player::recording(wasm_bytes)? {
+        let built = |key: &str| player::FIELDS.iter().position(|f| *f == key)
+            .and_then(|i| fields.get(i))
+            .is_some_and(|field| !matches!(field.trim(), "" | "{}" | "null"));
+        let indexes = [("event_index", "events"), ("text_index", "text"), ("language_segments", "languages")]
+            .into_iter()
+            .filter(|(field, _)| built(field))
+            .map(|(_, name)| name.to_string())
+            .collect();
+        return Ok(Some(indexes));
+    }
+    Ok(metadata.get("indexes").and_then(|i| serde_json::from_value(i.clone()).ok()))
+}
+
+/// Filter for `--d` identifiers or an `--address` coordinate (`kind:pubkey:d-identifier`)
+fn addressable_filter(identifiers: &[String], address: Option<&str>) -> Result<Option<Value>> {
+    if let Some(address) = address {
+        let address = nip19::normalize_coordinate(address)?;
+        // The identifier may itself contain colons
+        let mut parts = address.splitn(3, ':');
+        let (Some(kind), Some(pubkey), Some(identifier)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(anyhow!("--address must be kind:pubkey:d-identifier, got {}", address));
+        };
+        let kind: u64 = kind.parse().with_context(|| format!("Invalid kind in address: {}", kind))?;
+        if !(30000..40000).contains(&kind) {
+            return Err(anyhow!("Kind {} is not addressable (30000-39999)", kind));
+        }
+        let pubkey = nip19::normalize_pubkey(pubkey)?;
+        return Ok(Some(json!({"kinds": [kind], "authors": [pubkey], "#d": [identifier]})));
+    }
+    if identifiers.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(json!({"#d": identifiers})))
+}
+
+/// Filter for `--ids`, `--tag NAME=VALUE[,VALUE...]` (any value matches) and
+/// `--and-tag NAME=VALUE[,VALUE...]` (NIP-119: every value must match); repeating a
+/// tag name adds to its values
+fn tag_filter(ids: &[String], tags: &[String], and_tags: &[String]) -> Result<Option<Value>> {
+    let mut filter = serde_json::Map::new();
+    if !ids.is_empty() {
+        let ids: Vec<&str> = ids.iter().flat_map(|id| id.split(',')).map(str::trim).collect();
+        filter.insert("ids".to_string(), json!(ids));
+    }
+    for (prefix, flag, selectors) in [('#', "--tag", tags), ('&', "--and-tag", and_tags)] {
+        for selector in selectors {
+            let (name, values) = selector.split_once('=')
+                .filter(|(name, values)| !name.is_empty() && !values.is_empty())
+                .ok_or_else(|| anyhow!("{} expects NAME=VALUE, got {}", flag, selector))?;
+            let entry = filter.entry(format!("{}{}", prefix, name)).or_insert_with(|| json!([]));
+            if let Some(entry) = entry.as_array_mut() {
+                entry.extend(values.split(',').map(|value| json!(value.trim())));
+            }
+        }
+    }
+    if filter.is_empty() {
+        return Ok(None);
+    }
+    nip19::normalize_filter(&mut filter)?;
+    Ok(Some(Value::Object(filter)))
 }
 
-/// Process the REQ command - send requests to a cassette and get events
+/// Write one line of streamed output and flush it, returning false once stdout is closed
+fn write_stream_line(out: &mut impl Write, line: &str) -> Result<bool> {
+    match writeln!(out, "{}", line).and_then(|_| out.flush()) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Process the REQ command - send requests to one or more cassettes and get events
 fn process_req_command(
-    cassette_path: &PathBuf,
+    cassette_paths: &[PathBuf],
     subscription: &str,
     filter_args: &[String],
     kinds: &[i64],
@@ -893,10 +1204,29 @@ fn process_req_command(
     output_format: &str,
     interactive: bool,
     verbose: bool,
-    _skip_validation: bool,
+    policy: &validation::ValidationPolicy,
     nip11_args: &Nip11Args,
     search_query: Option<&str>,
+    shuffle_seed: Option<u64>,
+    stream: bool,
+    exclude_sensitive: bool,
+    label_selectors: &[labels::Selector],
+    labelers: &[String],
+    offset: Option<u64>,
+    page_size: Option<u32>,
 ) -> Result<()> {
+    if stream && !matches!(output_format, "nip01" | "ndjson") {
+        return Err(anyhow!("--stream needs nip01 or ndjson output, not {}", output_format));
+    }
+    let cassette_path = cassette_paths.first().ok_or_else(|| anyhow!("No cassettes to scrub"))?;
+    if interactive && cassette_paths.len() > 1 {
+        return Err(anyhow!("--interactive scrubs one cassette at a time"));
+    }
+    let paged = offset.is_some() || page_size.is_some();
+    if paged && (interactive || cassette_paths.len() > 1 || segments::is_manifest(cassette_path)) {
+        return Err(anyhow!("--offset, --cursor and --page-size page through a single cassette, without --interactive"));
+    }
+    
     // Initialize interactive UI if enabled
     let mut play_ui = if interactive {
         let ui = ui::scrub::ScrubUI::new();
@@ -907,10 +1237,6 @@ fn process_req_command(
         None
     };
 
-    // Read the WASM file
-    let wasm_bytes = fs::read(cassette_path)
-        .context("Failed to read cassette WASM file")?;
-    
     // Create a filter object
     let mut filter = serde_json::Map::new();
     
@@ -921,6 +1247,9 @@ fn process_req_command(
     
     // Add authors if specified
     if !authors.is_empty() {
+        let authors = authors.iter()
+            .map(|author| nip19::normalize_pubkey(author))
+            .collect::<Result<Vec<_>>>()?;
         filter.insert("authors".to_string(), json!(authors));
     }
     
@@ -944,126 +1273,278 @@ fn process_req_command(
     
     // Parse any custom filter JSON arguments
     for filter_json in filter_args {
-        let parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
+        let mut parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
             .context("Failed to parse filter JSON")?;
+        nip19::normalize_filter(&mut parsed)?;
         filter.extend(parsed);
     }
     
+    // The cassette can't leave flagged or unlabelled events out, so take the limit
+    // over here to still return a full page of the rest
+    let host_limit = if exclude_sensitive || !label_selectors.is_empty() {
+        filter.remove("limit").and_then(|l| l.as_u64())
+    } else {
+        None
+    };
+    let labelers = labelers.iter()
+        .map(|labeler| nip19::normalize_pubkey(labeler))
+        .collect::<Result<Vec<_>>>()?;
+    let mut label_filter = json!({"kinds": [labels::LABEL_KIND]});
+    if !labelers.is_empty() {
+        label_filter["authors"] = json!(labelers);
+    }
+    let admit = |event: &Value, label_index: &labels::LabelIndex| {
+        !(exclude_sensitive && content_warning::is_sensitive(event))
+            && (label_selectors.is_empty() || label_index.matches(event, label_selectors))
+            && policy.admit(event, verbose)
+    };
+    
     // Create the REQ message
     let req_message = json!(["REQ", subscription, filter]);
     let req_string = req_message.to_string();
     
-    // Initialize wasmtime
-    let mut store = Store::default();
-    let module = Module::from_binary(store.engine(), &wasm_bytes)?;
-    let instance = Instance::new(&mut store, &module, &[])?;
+    // Segmented archives are stitched together by the loader
+    if segments::is_manifest(cassette_path) && cassette_paths.len() == 1 {
+        if play_ui.is_some() {
+            return Err(anyhow!("--interactive isn't supported for segment manifests"));
+        }
+        let mut archive = SegmentedCassette::open(&cassette_path.to_string_lossy(), verbose)?;
+        let label_index = if label_selectors.is_empty() {
+            labels::LabelIndex::default()
+        } else {
+            let label_events = archive.events(&label_filter.to_string())?.iter()
+                .map(|event| serde_json::from_str(event))
+                .collect::<Result<Vec<Value>, _>>()?;
+            labels::LabelIndex::from_events(&label_events, &labelers)
+        };
+        let mut all_events = Vec::new();
+        let events = archive.events(&Value::Object(filter).to_string())?;
+        let parsed = events.iter().map(|event| Ok(serde_json::from_str::<Value>(event)?));
+        for event in policy.verify_ahead(parsed) {
+            let event = event?;
+            if host_limit.is_some_and(|l| all_events.len() as u64 >= l) {
+                break;
+            }
+            if admit(&event, &label_index) {
+                all_events.push(event);
+            }
+        }
+        if let Some(seed) = shuffle_seed {
+            shuffle_events(&mut all_events, seed);
+        }
+        return print_req_events(&all_events, subscription, output_format);
+    }
+    
+    // Several cassettes each get the REQ; their answers are merged newest first and
+    // every event is printed once, the newest version of a replaceable event only
+    if cassette_paths.len() > 1 {
+        let engine = Engine::default();
+        let mut label_events = Vec::new();
+        let mut sources = Vec::new();
+        for path in cassette_paths {
+            if segments::is_manifest(path) {
+                return Err(anyhow!("Segment manifests can only be scrubbed on their own: {}", path.display()));
+            }
+            let wasm_bytes = fs::read(path)
+                .with_context(|| format!("Failed to read cassette {}", path.display()))?;
+            let module = Module::from_binary(&engine, &wasm_bytes)?;
+            if !label_selectors.is_empty() {
+                label_events.extend(collect_cassette_events(path, &module, &engine, "labels", std::slice::from_ref(&label_filter), verbose)?);
+            }
+            let (mut store, guest) = guest::GuestApi::open(&engine, &module, path)?;
+            load_cassette_with_nip11(&mut store, &guest, nip11_args)?;
+            sources.push(guest::GuestEvents::new(store, guest, &Value::Object(filter.clone()))?);
+        }
+        let label_index = labels::LabelIndex::from_events(&label_events, &labelers);
+        
+        let mut merge = merge::Merge::new(sources);
+        let mut all_events = Vec::new();
+        let mut matched = 0;
+        let mut stdout = std::io::stdout().lock();
+        for event in policy.verify_ahead(merge.by_ref().map(|item| item.map(|(_, event)| event))) {
+            let event = event?;
+            if !admit(&event, &label_index) {
+                continue;
+            }
+            matched += 1;
+            if stream {
+                let line = if output_format == "ndjson" {
+                    serde_json::to_string(&event)?
+                } else {
+                    json!(["EVENT", subscription, event]).to_string()
+                };
+                if !write_stream_line(&mut stdout, &line)? {
+                    return Ok(());
+                }
+            } else {
+                all_events.push(event);
+            }
+            if limit.is_some_and(|l| matched >= l) {
+                break;
+            }
+        }
+        if verbose {
+            eprintln!("🔀 Merged {} cassettes: {} events read, {} duplicates and {} older versions dropped",
+                cassette_paths.len(), merge.read.iter().sum::<usize>(), merge.duplicates, merge.superseded);
+        }
+        if stream {
+            if output_format == "nip01" {
+                write_stream_line(&mut stdout, &json!(["EOSE", subscription]).to_string())?;
+            }
+            return Ok(());
+        }
+        drop(stdout);
+        if let Some(seed) = shuffle_seed {
+            shuffle_events(&mut all_events, seed);
+        }
+        return print_req_events(&all_events, subscription, output_format);
+    }
+    
+    // Read the WASM file
+    let wasm_bytes = fs::read(cassette_path)
+        .context("Failed to read cassette WASM file")?;
+    
+    // Initialize wasmtime
+    let mut store = Store::default();
+    let module = Module::from_binary(store.engine(), &wasm_bytes)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    
+    let guest = guest::GuestApi::detect(&mut store, &instance)?;
+    guest.attach_payload(&mut store, cassette_path)?;
     
     // Set NIP-11 info if provided
-    load_cassette_with_nip11(&mut store, &instance, nip11_args)?;
-    
-    // Get memory export
-    let memory = instance
-        .get_memory(&mut store, "memory")
-        .ok_or_else(|| anyhow!("Memory export not found"))?;
-    
-    // Get the send function (try new 'send' first, then fall back to old 'req')
-    let send_func = instance
-        .get_typed_func::<(i32, i32), i32>(&mut store, "send")
-        .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req"))
-        .context("Failed to get send/req function")?;
-    
-    // Get allocation function
-    let alloc_func = instance
-        .get_typed_func::<i32, i32>(&mut store, "alloc_buffer")
-        .or_else(|_| instance.get_typed_func::<i32, i32>(&mut store, "alloc_string"))
-        .context("Failed to get allocation function")?;
-    
-    // Get deallocation function
-    let dealloc_func = instance
-        .get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string")
-        .context("Failed to get deallocation function")?;
+    load_cassette_with_nip11(&mut store, &guest, nip11_args)?;
+    
+    // Index the cassette's label events before reading the labelled ones
+    let label_index = if label_selectors.is_empty() {
+        labels::LabelIndex::default()
+    } else {
+        let label_events = collect_cassette_events(cassette_path, &module, store.engine(), "labels", &[label_filter], verbose)?;
+        labels::LabelIndex::from_events(&label_events, &labelers)
+    };
+    if verbose && !label_selectors.is_empty() {
+        eprintln!("🏷️  Labels indexed for {} targets", label_index.len());
+    }
+    
+    // Paging reads the answer a `req_page` call at a time from the offset, so nothing
+    // before it is replayed; the limit counts the events printed and is left out of the
+    // cassette's query, which would otherwise cut the results before the offset
+    if paged {
+        let limit = filter.remove("limit").and_then(|l| l.as_u64()).or(host_limit);
+        let req_string = json!(["REQ", subscription, filter]).to_string();
+        let mut all_events = Vec::new();
+        let mut printed = 0u64;
+        let mut stdout = std::io::stdout().lock();
+        let mut next = Some(offset.unwrap_or(0));
+        'pages: while let Some(start) = next {
+            let Some(page) = guest.req_page(&mut store, &req_string, start, page_size.unwrap_or(0))? else {
+                return Err(anyhow!("{} has no req_page export to page through; record it again to page it", cassette_path.display()));
+            };
+            if verbose {
+                eprintln!("📄 Page at {}: {} events", start, page.events.len());
+            }
+            let end = start + page.events.len() as u64;
+            next = page.next_cursor;
+            let events = policy.verify_ahead(page.events.into_iter().map(Ok));
+            for (position, event) in (start + 1..).zip(events) {
+                let event = event?;
+                if !admit(&event, &label_index) {
+                    continue;
+                }
+                printed += 1;
+                if stream {
+                    let line = if output_format == "ndjson" {
+                        serde_json::to_string(&event)?
+                    } else {
+                        json!(["EVENT", subscription, event]).to_string()
+                    };
+                    if !write_stream_line(&mut stdout, &line)? {
+                        return Ok(());
+                    }
+                } else {
+                    all_events.push(event);
+                }
+                if limit.is_some_and(|l| printed >= l) {
+                    next = (position < end).then_some(position).or(next);
+                    break 'pages;
+                }
+            }
+        }
+        if stream && output_format == "nip01" {
+            write_stream_line(&mut stdout, &json!(["EOSE", subscription]).to_string())?;
+        }
+        drop(stdout);
+        if !stream {
+            if let Some(seed) = shuffle_seed {
+                shuffle_events(&mut all_events, seed);
+            }
+            print_req_events(&all_events, subscription, output_format)?;
+        }
+        if let Some(cursor) = next {
+            eprintln!("Next page: --cursor {}", cursor);
+        }
+        return Ok(());
+    }
     
     // Try to get total count first for progress bar (NIP-45)
-    let total_count = get_event_count_for_filter(&mut store, &instance, &memory, &alloc_func, &dealloc_func, &filter, subscription).unwrap_or(None);
+    let count_string = json!(["COUNT", subscription, filter]).to_string();
+    let total_count = guest.count(&mut store, &count_string).unwrap_or(None);
     
-    // Allocate memory for the request string
-    let req_bytes = req_string.as_bytes();
-    // Collect all events in a loop
+    // Collect all events in a loop, or write each one out as it arrives when streaming;
+    // cassettes that can hand out their answer in bounded chunks are read that way, and
+    // signatures are checked a batch ahead on every core
     let mut all_events = Vec::new();
     let mut event_count = 0u64;
+    let mut stdout = std::io::stdout().lock();
+    let replies = guest.replies(&mut store, &req_string)?;
+    let mut events = policy.verify_ahead(guest::ReplyEvents::new(&mut store, &guest, replies));
     
-    loop {
-        // Allocate memory for the request string for each call
-        let req_ptr = alloc_func.call(&mut store, req_bytes.len() as i32)?;
-        
-        if req_ptr == 0 {
-            return Err(anyhow!("Failed to allocate memory for request"));
+    for event in events.by_ref() {
+        let event = event?;
+        if !admit(&event, &label_index) {
+            continue;
         }
-        
-        // Write request to memory
-        memory.write(&mut store, req_ptr as usize, req_bytes)?;
-        
-        // Call the send function
-        let result_ptr = send_func.call(&mut store, (req_ptr, req_bytes.len() as i32))?;
-        
-        // Deallocate request memory immediately after use
-        dealloc_func.call(&mut store, (req_ptr, req_bytes.len() as i32))?;
-        
-        if result_ptr == 0 {
-            break; // No more events
+        if host_limit.is_some_and(|l| event_count >= l) {
+            break;
         }
         
-        // Read the result
-        let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
-        
-        // Try to deallocate the result pointer
-        if let Ok(get_size_func) = instance.get_typed_func::<i32, i32>(&mut store, "get_allocation_size") {
-            let size = get_size_func.call(&mut store, result_ptr)?;
-            if size > 0 && dealloc_func.call(&mut store, (result_ptr, size)).is_ok() {
-                // Successfully deallocated result
+        event_count += 1;
+        if stream {
+            let line = if output_format == "ndjson" {
+                serde_json::to_string(&event)?
+            } else {
+                json!(["EVENT", subscription, event]).to_string()
+            };
+            // The reader went away (e.g. `| head`): stop reading the cassette
+            if !write_stream_line(&mut stdout, &line)? {
+                return Ok(());
             }
+            continue;
         }
         
-        // Parse the result
-        let parsed_result: Value = serde_json::from_str(&result)?;
-        
-        if let Some(arr) = parsed_result.as_array() {
-            if arr.len() >= 2 {
-                match arr[0].as_str() {
-                    Some("EVENT") => {
-                        if arr.len() >= 3 {
-                            // Validate event if validation is not skipped
-                            if !_skip_validation {
-                                if !validate_nostr_event(&arr[2], verbose) {
-                                    // Skip invalid event
-                                    continue;
-                                }
-                            }
-                            
-                            event_count += 1;
-                            all_events.push(arr[2].clone());
-                            
-                            // Update interactive UI
-                            if let Some(ref mut ui) = play_ui {
-                                let total_for_ui = total_count.unwrap_or(all_events.len() as u64);
-                                ui.update_playback(total_for_ui, event_count, Some(&arr[2]))?;
-                                std::thread::sleep(std::time::Duration::from_millis(50));
-                            }
-                        }
-                    }
-                    Some("EOSE") => {
-                        break; // End of events
-                    }
-                    Some("NOTICE") => {
-                        // Check for "No more events" message
-                        if result.contains("No more events") {
-                            break;
-                        }
-                    }
-                    _ => {}
-                }
-            }
+        // Update interactive UI
+        if let Some(ref mut ui) = play_ui {
+            let total_for_ui = total_count.unwrap_or(all_events.len() as u64 + 1);
+            ui.update_playback(total_for_ui, event_count, Some(&event))?;
+            std::thread::sleep(std::time::Duration::from_millis(50));
         }
+        all_events.push(event);
+    }
+    drop(events);
+    
+    // Drop whatever a host-side limit left unread
+    guest.end_result(&mut store)?;
+    
+    if stream {
+        if output_format == "nip01" {
+            write_stream_line(&mut stdout, &json!(["EOSE", subscription]).to_string())?;
+        }
+        return Ok(());
+    }
+    drop(stdout);
+    
+    if let Some(seed) = shuffle_seed {
+        shuffle_events(&mut all_events, seed);
     }
     
     // Handle completion and output
@@ -1071,16 +1552,51 @@ fn process_req_command(
         // Interactive mode - show completion screen
         ui.show_completion(all_events.len() as u64)?;
         
-        // Wait for user input
-        use crossterm::event::{self, Event, KeyCode};
+        // Wait for user input, then browse the events on the deck
+        use crossterm::event::{self, Event, KeyCode, KeyEventKind};
         use crossterm::terminal::{enable_raw_mode, disable_raw_mode};
+        use ui::scrub::{DeckAction, ScrubDeck};
         
         enable_raw_mode()?;
-        loop {
+        let browse = loop {
             if let Ok(Event::Key(key)) = event::read() {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => break,
-                    _ => {}
+                if key.kind == KeyEventKind::Press {
+                    break !matches!(key.code, KeyCode::Char('q') | KeyCode::Esc);
+                }
+            }
+        };
+        
+        // An edited filter is run against a fresh instance of the cassette
+        let requery = |filter: &mut Value| -> Result<Vec<Value>> {
+            if let Some(filter) = filter.as_object_mut() {
+                nip19::normalize_filter(filter)?;
+            }
+            let events = collect_cassette_events(cassette_path, &module, store.engine(), subscription, std::slice::from_ref(&*filter), false)?;
+            // Events already shown skip their signature check
+            let mut admitted = Vec::new();
+            for event in policy.verify_ahead(events.into_iter().map(Ok)) {
+                let event = event?;
+                if admit(&event, &label_index) {
+                    admitted.push(event);
+                }
+            }
+            Ok(admitted)
+        };
+        let mut deck = ScrubDeck::new(all_events, Value::Object(filter));
+        if browse {
+            loop {
+                ui.show_deck(&deck)?;
+                let Ok(Event::Key(key)) = event::read() else { continue };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match deck.handle(key) {
+                    DeckAction::Redraw => {}
+                    DeckAction::Requery(mut filter) => match requery(&mut filter) {
+                        Ok(events) => deck.load(filter, events),
+                        Err(e) => deck.notify(format!("Filter failed: {}", e)),
+                    },
+                    DeckAction::Quit => break,
                 }
             }
         }
@@ -1089,42 +1605,58 @@ fn process_req_command(
         ui.cleanup()?;
     } else {
         // Non-interactive mode - just output results
-        match output_format {
-            "nip01" => {
-                // Output as NIP-01 protocol messages
-                for event in &all_events {
-                    let event_msg = json!(["EVENT", subscription, event]);
-                    println!("{}", serde_json::to_string(&event_msg)?);
-                }
-                // Send EOSE at the end
-                let eose_msg = json!(["EOSE", subscription]);
-                println!("{}", serde_json::to_string(&eose_msg)?);
+        print_req_events(&all_events, subscription, output_format)?;
+    }
+    
+    Ok(())
+}
+
+/// Print the events a REQ returned in the requested output format
+fn print_req_events(all_events: &[Value], subscription: &str, output_format: &str) -> Result<()> {
+    match output_format {
+        "nip01" => {
+            // Output as NIP-01 protocol messages
+            for event in all_events {
+                let event_msg = json!(["EVENT", subscription, event]);
+                println!("{}", serde_json::to_string(&event_msg)?);
             }
-            "ndjson" => {
-                for event in &all_events {
-                    println!("{}", serde_json::to_string(&event)?);
-                }
+            // Send EOSE at the end
+            let eose_msg = json!(["EOSE", subscription]);
+            println!("{}", serde_json::to_string(&eose_msg)?);
+        }
+        "ndjson" => {
+            for event in all_events {
+                println!("{}", serde_json::to_string(&event)?);
             }
-            "json" => {
-                if all_events.len() == 1 {
-                    println!("{}", serde_json::to_string_pretty(&all_events[0])?);
-                } else {
-                    println!("{}", serde_json::to_string_pretty(&all_events)?);
-                }
+        }
+        "json" => {
+            if all_events.len() == 1 {
+                println!("{}", serde_json::to_string_pretty(&all_events[0])?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&all_events)?);
             }
-            _ => {
-                eprintln!("Unknown output format: {}. Using nip01.", output_format);
-                // Default to nip01
-                for event in &all_events {
-                    let event_msg = json!(["EVENT", subscription, event]);
-                    println!("{}", serde_json::to_string(&event_msg)?);
-                }
-                let eose_msg = json!(["EOSE", subscription]);
-                println!("{}", serde_json::to_string(&eose_msg)?);
+        }
+        "table" | "csv" | "summary" => {
+            let lines = match output_format {
+                "table" => report::table(all_events),
+                "csv" => report::csv(all_events),
+                _ => report::summary(all_events),
+            };
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        _ => {
+            eprintln!("Unknown output format: {}. Using nip01.", output_format);
+            // Default to nip01
+            for event in all_events {
+                let event_msg = json!(["EVENT", subscription, event]);
+                println!("{}", serde_json::to_string(&event_msg)?);
             }
+            let eose_msg = json!(["EOSE", subscription]);
+            println!("{}", serde_json::to_string(&eose_msg)?);
         }
     }
-    
     Ok(())
 }
 
@@ -1156,6 +1688,9 @@ fn process_count_command(
     
     // Add authors if specified
     if !authors.is_empty() {
+        let authors = authors.iter()
+            .map(|author| nip19::normalize_pubkey(author))
+            .collect::<Result<Vec<_>>>()?;
         filter.insert("authors".to_string(), json!(authors));
     }
     
@@ -1179,8 +1714,9 @@ fn process_count_command(
     
     // Parse any custom filter JSON arguments
     for filter_json in filter_args {
-        let parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
+        let mut parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
             .context("Failed to parse filter JSON")?;
+        nip19::normalize_filter(&mut parsed)?;
         filter.extend(parsed);
     }
     
@@ -1190,68 +1726,28 @@ fn process_count_command(
     
     debugln!(verbose, "Sending COUNT request: {}", count_string);
     
+    // Segmented archives sum the counts of the segments the filter reaches
+    if segments::is_manifest(cassette_path) {
+        let mut archive = SegmentedCassette::open(&cassette_path.to_string_lossy(), verbose)?;
+        let count = archive.count(&Value::Object(filter).to_string())?;
+        println!("{}", serde_json::to_string_pretty(&json!({"count": count, "approximate": false}))?);
+        return Ok(());
+    }
+    
     // Initialize wasmtime
     let mut store = Store::default();
     let module = Module::from_binary(store.engine(), &wasm_bytes)?;
     let instance = Instance::new(&mut store, &module, &[])?;
     
-    // Set NIP-11 info if provided
-    load_cassette_with_nip11(&mut store, &instance, nip11_args)?;
-    
-    // Get memory export
-    let memory = instance
-        .get_memory(&mut store, "memory")
-        .ok_or_else(|| anyhow!("Memory export not found"))?;
-    
-    // Get the send function (COUNT also uses the send function)
-    // Try new 'send' first, then fall back to old 'req' for backward compatibility
-    let send_func = instance
-        .get_typed_func::<(i32, i32), i32>(&mut store, "send")
-        .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req"))
-        .context("Failed to get send/req function")?;
-    
-    // Get allocation function
-    let alloc_func = instance
-        .get_typed_func::<i32, i32>(&mut store, "alloc_buffer")
-        .or_else(|_| instance.get_typed_func::<i32, i32>(&mut store, "alloc_string"))
-        .context("Failed to get allocation function")?;
-    
-    // Get deallocation function
-    let dealloc_func = instance
-        .get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string")
-        .context("Failed to get deallocation function")?;
-    
-    // Allocate memory for the COUNT request
-    let count_bytes = count_string.as_bytes();
-    let count_ptr = alloc_func.call(&mut store, count_bytes.len() as i32)?;
-    
-    if count_ptr == 0 {
-        return Err(anyhow!("Failed to allocate memory for COUNT request"));
-    }
-    
-    // Write COUNT request to memory
-    memory.write(&mut store, count_ptr as usize, count_bytes)?;
-    
-    // Call the send function with COUNT message
-    let result_ptr = send_func.call(&mut store, (count_ptr, count_bytes.len() as i32))?;
-    
-    // Deallocate request memory
-    dealloc_func.call(&mut store, (count_ptr, count_bytes.len() as i32))?;
-    
-    if result_ptr == 0 {
-        return Err(anyhow!("No response from COUNT request"));
-    }
+    let guest = guest::GuestApi::detect(&mut store, &instance)?;
+    guest.attach_payload(&mut store, cassette_path)?;
     
-    // Read the result
-    let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
+    // Set NIP-11 info if provided
+    load_cassette_with_nip11(&mut store, &guest, nip11_args)?;
     
-    // Try to deallocate the result pointer
-    if let Ok(get_size_func) = instance.get_typed_func::<i32, i32>(&mut store, "get_allocation_size") {
-        let size = get_size_func.call(&mut store, result_ptr)?;
-        if size > 0 && dealloc_func.call(&mut store, (result_ptr, size)).is_ok() {
-            // Successfully deallocated result
-        }
-    }
+    // COUNT also goes through the send function
+    let result = guest.send(&mut store, &count_string)?
+        .ok_or_else(|| anyhow!("No response from COUNT request"))?;
     
     debugln!(verbose, "COUNT response: {}", result);
     
@@ -1279,12 +1775,72 @@ fn process_count_command(
     Ok(())
 }
 
+/// Events each input of a parallel dub reads ahead of the merge
+const DUB_PREFETCH_EVENTS: usize = 1024;
+
+/// One input of a dub, opened and ready to hand out its events newest first
+struct DubInput {
+    events: Box<dyn Iterator<Item = Result<Value>> + Send>,
+    name: String,
+    sha256: String,
+    metadata: Option<Value>,
+    /// The player the input was built from, when it was and `eject` asked for it
+    player: Option<Vec<u8>>,
+}
+
+/// Open a dub input: read a player-built cassette's recording straight out of the module,
+/// or instantiate any other cassette to be asked for its events with a REQ
+fn open_dub_input(cassette_path: &PathBuf, nip11_args: &Nip11Args, eject: bool, verbose: bool) -> Result<DubInput> {
+    if !cassette_path.exists() {
+        return Err(anyhow!("Cassette file not found: {}", cassette_path.display()));
+    }
+    
+    // Read the WASM file
+    let wasm_bytes = fs::read(cassette_path)
+        .context("Failed to read cassette WASM file")?;
+    let name = cassette_path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let sha256 = hex::encode(Sha256::digest(&wasm_bytes));
+    
+    if let Some(fields) = player::recording(&wasm_bytes)? {
+        debugln!(verbose, "  Reading the recording of {} without running it", name);
+        let field = |key: &str| player::FIELDS.iter().position(|f| *f == key).and_then(|i| fields.get(i));
+        let now = Utc::now().timestamp();
+        let events: Vec<Value> = serde_json::from_str(field("events_json").map_or("[]", |f| f.as_str()))
+            .context("Invalid events in the cassette's recording")?;
+        // Served the way a REQ would: newest first, without the events that have expired (NIP-40)
+        let mut events: Vec<Value> = events.into_iter().filter(|event| !cassette_tools::nips::nip40::is_expired(event, now)).collect();
+        events.sort_by(|a, b| cassette_tools::newest_first(a, b));
+        let metadata = field("cassette_metadata")
+            .and_then(|metadata| serde_json::from_str::<Value>(metadata).ok())
+            .filter(|metadata| metadata.as_object().is_some_and(|m| !m.is_empty()));
+        let player = if eject { Some(player::eject(&wasm_bytes)?) } else { None };
+        return Ok(DubInput { events: Box::new(events.into_iter().map(Ok)), name, sha256, metadata, player });
+    }
+    
+    let engine = Engine::default();
+    let module = Module::from_binary(&engine, &wasm_bytes)?;
+    let (mut store, guest) = guest::GuestApi::open(&engine, &module, cassette_path)?;
+    
+    // Set NIP-11 info if provided
+    load_cassette_with_nip11(&mut store, &guest, nip11_args)?;
+    
+    let metadata = guest.info(&mut store).ok().flatten()
+        .and_then(|info| serde_json::from_str::<Value>(&info).ok())
+        .and_then(|info| info.get("cassette").cloned());
+    let events = guest::GuestEvents::new(store, guest, &json!({}))?;
+    Ok(DubInput { events: Box::new(events), name, sha256, metadata, player: None })
+}
+
 /// Process the DUB command - combine multiple cassettes into a new one
 fn process_dub_command(
     cassette_paths: &[PathBuf],
     output_path: &PathBuf,
     name: Option<&str>,
     filter_args: &[String],
+    filter_for: &[String],
+    exclude: &[String],
     kinds: &[i64],
     authors: &[String],
     limit: Option<usize>,
@@ -1293,10 +1849,20 @@ fn process_dub_command(
     interactive: bool,
     verbose: bool,
     nip11_args: &Nip11Args,
+    build_args: &BuildArgs,
+    policy: &validation::ValidationPolicy,
+    operation: &str,
+    subtract: bool,
+    provenance: bool,
+    rewrite: bool,
+    jobs: Option<usize>,
 ) -> Result<()> {
     if cassette_paths.is_empty() {
         return Err(anyhow!("No input cassettes specified"));
     }
+    if subtract && cassette_paths.len() < 2 {
+        return Err(anyhow!("--subtract needs a base cassette and at least one to subtract from it"));
+    }
     
     // Initialize interactive UI if enabled
     let mut dub_ui = if interactive {
@@ -1318,214 +1884,212 @@ fn process_dub_command(
     debugln!(verbose, "=== Cassette CLI - Dub Command ===");
     debugln!(verbose, "Combining {} cassettes...", cassette_paths.len());
     
-    // Collect all events from all cassettes
-    let mut all_events = Vec::new();
+    // Create a filter object
+    let mut filter = serde_json::Map::new();
     
-    for (idx, cassette_path) in cassette_paths.iter().enumerate() {
-        debugln!(verbose, "\n📼 Processing cassette {}/{}: {}", 
-            idx + 1, 
-            cassette_paths.len(), 
-            cassette_path.display()
-        );
-        
-        if !cassette_path.exists() {
-            return Err(anyhow!("Cassette file not found: {}", cassette_path.display()));
-        }
-        
-        // Read the WASM file
-        let wasm_bytes = fs::read(cassette_path)
-            .context("Failed to read cassette WASM file")?;
-        
-        // Initialize wasmtime
-        let mut store = Store::default();
-        let module = Module::from_binary(store.engine(), &wasm_bytes)?;
-        let instance = Instance::new(&mut store, &module, &[])?;
-        
-        // Set NIP-11 info if provided
-        load_cassette_with_nip11(&mut store, &instance, nip11_args)?;
-        
-        // Get memory export
-        let memory = instance
-            .get_memory(&mut store, "memory")
-            .ok_or_else(|| anyhow!("Memory export not found"))?;
-        
-        // Get the send function (try 'send' then 'req' for backward compatibility)
-        let send_func = instance
-            .get_typed_func::<(i32, i32), i32>(&mut store, "send")
-            .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req"))
-            .context("Failed to get send/req function")?;
-        
-        // Get allocation function
-        let alloc_func = instance
-            .get_typed_func::<i32, i32>(&mut store, "alloc_buffer")
-            .or_else(|_| instance.get_typed_func::<i32, i32>(&mut store, "alloc_string"))
-            .context("Failed to get allocation function")?;
-        
-        // Get deallocation function
-        let dealloc_func = instance
-            .get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string")
-            .context("Failed to get deallocation function")?;
-        
-        // Create a REQ message to get all events from this cassette
-        let req_message = json!(["REQ", "dub_extract", {}]);
-        let req_string = req_message.to_string();
-        let req_bytes = req_string.as_bytes();
-        
-        // Keep calling req until we get EOSE
-        let mut cassette_events = Vec::new();
-        let mut first_call = true;
-        
-        loop {
-            // Allocate memory for the request string for each call
-            let req_ptr = alloc_func.call(&mut store, req_bytes.len() as i32)?;
-            
-            if req_ptr == 0 {
-                return Err(anyhow!("Failed to allocate memory for request"));
-            }
-            
-            // Write request to memory
-            memory.write(&mut store, req_ptr as usize, req_bytes)?;
-            
-            // Call the send function
-            let result_ptr = send_func.call(&mut store, (req_ptr, req_bytes.len() as i32))?;
-            
-            // Deallocate request memory immediately after use
-            dealloc_func.call(&mut store, (req_ptr, req_bytes.len() as i32))?;
-            
-            if result_ptr == 0 {
-                if first_call {
-                    println!("  No events found in cassette");
-                }
-                break; // No more events
-            }
-            
-            first_call = false;
-            
-            // Read the result
-            let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
-            
-            // We might need to deallocate the result pointer too
-            // Check if there's a get_allocation_size function
-            if let Ok(get_size_func) = instance.get_typed_func::<i32, i32>(&mut store, "get_allocation_size") {
-                let size = get_size_func.call(&mut store, result_ptr)?;
-                if size > 0 && dealloc_func.call(&mut store, (result_ptr, size)).is_ok() {
-                    // Successfully deallocated result
-                }
-            }
-            
-            // Parse the result
-            let parsed_result: Value = serde_json::from_str(&result)?;
-            
-            if let Some(arr) = parsed_result.as_array() {
-                if arr.len() >= 2 {
-                    match arr[0].as_str() {
-                        Some("EVENT") => {
-                            if arr.len() >= 3 {
-                                cassette_events.push(arr[2].clone());
-                                
-                                // Update interactive UI
-                                if let Some(ref mut ui) = dub_ui {
-                                    ui.update_processing(idx, cassette_events.len() as u64, all_events.len() as u64 + cassette_events.len() as u64)?;
-                                    std::thread::sleep(std::time::Duration::from_millis(30));
-                                }
-                            }
-                        }
-                        Some("EOSE") => {
-                            break; // End of events
-                        }
-                        Some("NOTICE") => {
-                            // Just continue, might be "No more events"
-                            if result.contains("No more events") {
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-        }
-        
-        debugln!(verbose, "  Found {} events", cassette_events.len());
-        all_events.extend(cassette_events);
+    if !kinds.is_empty() {
+        filter.insert("kinds".to_string(), json!(kinds));
     }
     
-    debugln!(verbose, "\n📊 Total events collected: {}", all_events.len());
+    if !authors.is_empty() {
+        let authors = authors.iter()
+            .map(|author| nip19::normalize_pubkey(author))
+            .collect::<Result<Vec<_>>>()?;
+        filter.insert("authors".to_string(), json!(authors));
+    }
     
-    // Show mixing phase in interactive mode
-    if let Some(ref ui) = dub_ui {
-        ui.show_mixing(all_events.len() as u64)?;
+    if let Some(l) = limit {
+        filter.insert("limit".to_string(), json!(l));
     }
     
-    // Apply filters if specified
-    if !kinds.is_empty() || !authors.is_empty() || !filter_args.is_empty() || since.is_some() || until.is_some() {
-        debugln!(verbose, "\n🔍 Applying filters...");
-        
-        let mut filtered_events = Vec::new();
-        
-        // Create a filter object
-        let mut filter = serde_json::Map::new();
-        
-        if !kinds.is_empty() {
-            filter.insert("kinds".to_string(), json!(kinds));
-        }
-        
-        if !authors.is_empty() {
-            filter.insert("authors".to_string(), json!(authors));
-        }
-        
-        if let Some(l) = limit {
-            filter.insert("limit".to_string(), json!(l));
-        }
-        
-        if let Some(s) = since {
-            filter.insert("since".to_string(), json!(s));
-        }
-        
-        if let Some(u) = until {
-            filter.insert("until".to_string(), json!(u));
-        }
-        
-        // Parse any custom filter JSON arguments
-        for filter_json in filter_args {
-            let parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
-                .context("Failed to parse filter JSON")?;
-            filter.extend(parsed);
-        }
-        
-        // Apply the filter to each event
-        for event in all_events {
-            if event_matches_filter(&event, &filter) {
-                filtered_events.push(event);
-            }
-        }
-        
-        debugln!(verbose, "  Events after filtering: {}", filtered_events.len());
-        all_events = filtered_events;
+    if let Some(s) = since {
+        filter.insert("since".to_string(), json!(s));
     }
     
-    // Apply limit if specified and not already applied via filter
-    if let Some(l) = limit {
-        if all_events.len() > l {
-            debugln!(verbose, "  Applying limit of {} events", l);
-            all_events.truncate(l);
-        }
+    if let Some(u) = until {
+        filter.insert("until".to_string(), json!(u));
     }
     
-    // Preprocess events to handle replaceable events
-    debugln!(verbose, "\n🔍 Preprocessing events according to NIP-01...");
-    let processed_events = preprocess_events(all_events);
-    debugln!(verbose, "  Final event count: {}", processed_events.len());
+    // Parse any custom filter JSON arguments
+    for filter_json in filter_args {
+        let mut parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
+            .context("Failed to parse filter JSON")?;
+        nip19::normalize_filter(&mut parsed)?;
+        filter.extend(parsed);
+    }
     
-    // Generate the new cassette
-    let cassette_name = sanitize_filename(name.unwrap_or("dubbed_cassette"));
+    let parse_filter = |filter_json: &str| -> Result<(Value, Filter)> {
+        let mut parsed: serde_json::Map<String, Value> = serde_json::from_str(filter_json)
+            .context("Failed to parse filter JSON")?;
+        nip19::normalize_filter(&mut parsed)?;
+        let parsed = Value::Object(parsed);
+        let filter = Filter::from_json(&parsed).map_err(|e| anyhow!("Invalid filter: {}", e))?;
+        Ok((parsed, filter))
+    };
     
-    // Create a temporary directory for building
+    // Filters of single inputs, applied as their events are read
+    let mut input_filters: Vec<Vec<(Value, Filter)>> = cassette_paths.iter().map(|_| Vec::new()).collect();
+    for pair in filter_for.chunks(2) {
+        let [input, filter_json] = pair else { continue };
+        let index = merge::input_index(cassette_paths, input)
+            .ok_or_else(|| anyhow!("--filter-for {}: no input cassette by that name", input))?;
+        input_filters[index].push(parse_filter(filter_json)?);
+    }
+    
+    // Events matching any exclusion are left out, whichever input they come from
+    let exclusions = exclude.iter()
+        .map(|filter_json| parse_filter(filter_json))
+        .collect::<Result<Vec<_>>>()?;
+    
+    // Applied to the events as they stream out of the merge
+    let parsed_filter = if !kinds.is_empty() || !authors.is_empty() || !filter_args.is_empty() || since.is_some() || until.is_some() {
+        Some(Filter::from_json(&Value::Object(filter.clone()))
+            .map_err(|e| anyhow!("Invalid filter: {}", e))?)
+    } else {
+        None
+    };
+    
+    // Open every input as a stream of its events, newest first. Compiling and
+    // instantiating is the slow part, so up to `jobs` inputs are opened at once; each
+    // is then read ahead on a thread of its own while the merge consumes them
+    let jobs = jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())).max(1);
+    let open = |(idx, cassette_path): (usize, &PathBuf)| {
+        debugln!(verbose, "\n📼 Opening cassette {}/{}: {}", 
+            idx + 1, 
+            cassette_paths.len(), 
+            cassette_path.display()
+        );
+        open_dub_input(cassette_path, nip11_args, rewrite, verbose)
+    };
+    let opened = if jobs > 1 && cassette_paths.len() > 1 {
+        debugln!(verbose, "Opening {} cassettes on {} threads", cassette_paths.len(), jobs);
+        use rayon::prelude::*;
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?
+            .install(|| cassette_paths.par_iter().enumerate().map(open).collect::<Result<Vec<_>>>())?
+    } else {
+        cassette_paths.iter().enumerate().map(open).collect::<Result<Vec<_>>>()?
+    };
+    
+    let mut sources: Vec<Box<dyn Iterator<Item = Result<Value>>>> = Vec::new();
+    let mut inputs = Vec::new();
+    // The player of the first input built from it, whose recording can be rewritten
+    let mut player = None;
+    for input in opened {
+        sources.push(if jobs > 1 { Box::new(merge::prefetch(input.events, DUB_PREFETCH_EVENTS)) } else { input.events });
+        player = player.or(input.player);
+        inputs.push((input.name, input.sha256, input.metadata));
+    }
+    
+    // Merge the inputs newest first, dropping duplicates and superseded versions as they
+    // pass, and write the events that match straight to disk; only the newest `limit`
+    // are kept, but every input is still read to the end so the lineage counts are exact
+    debugln!(verbose, "\n🔀 Merging {} cassettes...", cassette_paths.len());
     let temp_dir = tempdir()?;
-    let temp_file = temp_dir.path().join("dubbed_events.json");
+    let temp_file = temp_dir.path().join("dubbed_events.jsonl");
+    let mut writer = std::io::BufWriter::new(File::create(&temp_file)?);
+    let mut merge = merge::Merge::new(sources).with_filters(
+        input_filters.iter().map(|filters| filters.iter().map(|(_, filter)| filter.clone()).collect()).collect(),
+    );
+    if subtract {
+        merge = merge.subtracting();
+    }
+    let mut excluded = 0;
+    let mut matched = 0;
+    let mut written = 0;
+    // Ids of the events taken from each input, for --provenance
+    let mut taken: Vec<Vec<String>> = cassette_paths.iter().map(|_| Vec::new()).collect();
+    
+    while let Some((source, event)) = merge.next().transpose()? {
+        if let Some(ref mut ui) = dub_ui {
+            ui.update_processing(source, merge.read[source] as u64, merge.read.iter().sum::<usize>() as u64)?;
+        }
+        if exclusions.iter().any(|(_, exclusion)| exclusion.matches(&event)) {
+            excluded += 1;
+            continue;
+        }
+        if parsed_filter.as_ref().is_some_and(|filter| !filter.matches(&event)) {
+            continue;
+        }
+        matched += 1;
+        if limit.is_some_and(|l| written >= l) {
+            continue;
+        }
+        serde_json::to_writer(&mut writer, &event)?;
+        writer.write_all(b"\n")?;
+        written += 1;
+        if provenance {
+            if let Some(id) = event.get("id").and_then(|i| i.as_str()) {
+                taken[source].push(id.to_string());
+            }
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+    
+    // Record the parents for the new cassette's lineage
+    let mut parents = Vec::new();
+    let mut parent_metadata = Vec::new();
+    let provenance_sources: Vec<(Value, Vec<String>)> = inputs.iter()
+        .zip(taken)
+        .map(|((parent_name, sha256, _), ids)| (json!({"name": parent_name, "sha256": sha256}), ids))
+        .collect();
+    for (((parent_name, sha256, metadata), read), filters) in inputs.into_iter().zip(merge.read.iter()).zip(input_filters) {
+        debugln!(verbose, "  {}: {} events", parent_name, read);
+        let mut parent = lineage::parent_entry(&parent_name, &sha256, *read, metadata.as_ref());
+        if !filters.is_empty() {
+            parent["filters"] = Value::Array(filters.into_iter().map(|(filter, _)| filter).collect());
+        }
+        parents.push(parent);
+        parent_metadata.push(metadata);
+    }
+    
+    debugln!(verbose, "\n📊 Total events read: {}", merge.read.iter().sum::<usize>());
+    debugln!(verbose, "  Duplicates dropped: {}", merge.duplicates);
+    debugln!(verbose, "  Superseded replaceable events dropped: {}", merge.superseded);
+    if subtract {
+        debugln!(verbose, "  Events of {} the others have: {}", cassette_paths[0].display(), merge.subtracted);
+    }
+    if !exclusions.is_empty() {
+        debugln!(verbose, "  Excluded: {}", excluded);
+    }
+    if parsed_filter.is_some() {
+        debugln!(verbose, "  Events after filtering: {}", matched);
+    }
+    if written < matched {
+        debugln!(verbose, "  Applied limit of {} events", written);
+    }
+    
+    // Show mixing phase in interactive mode
+    if let Some(ref ui) = dub_ui {
+        ui.show_mixing(written as u64)?;
+    }
     
-    // Write events to temp file
-    let events_json = serde_json::to_string(&processed_events)?;
-    fs::write(&temp_file, &events_json)?;
+    // A single parent's metadata (e.g. sampling) still describes the derived cassette
+    let mut build_args = build_args.clone();
+    if let [Some(Value::Object(metadata))] = parent_metadata.as_slice() {
+        build_args.metadata.extend(metadata.clone());
+    }
+    let mut lineage = lineage::lineage_record(operation, &filter, parents, written);
+    if !exclusions.is_empty() {
+        lineage["exclude"] = Value::Array(exclusions.into_iter().map(|(filter, _)| filter).collect());
+    }
+    build_args.metadata.insert("lineage".to_string(), lineage);
+    if provenance {
+        build_args.provenance = Some(cassette_tools::provenance::table(provenance_sources));
+    }
+    
+    // Rewrite the recording of an input's player instead of compiling a new cassette
+    if let Some(player) = player.filter(|_| rewrite) {
+        debugln!(verbose, "\n⚡ Rewriting the recording of the input's player, no compilation needed");
+        build_args.fast = true;
+        build_args.player = Some(player);
+    }
+    if build_args.needs_toolchain() {
+        deps::DependencyCheck::new().check_for_dub()?;
+    }
+    
+    // Generate the new cassette
+    let cassette_name = sanitize_filename(name.unwrap_or("dubbed_cassette"));
     
     // Get the output directory from the output path
     let output_dir = output_path.parent()
@@ -1535,18 +2099,20 @@ fn process_dub_command(
     // Process events to create the new cassette
     process_events(
         temp_file.to_str().unwrap(),
+        input::InputFormat::Ndjson,
         &cassette_name,
         &output_dir,
         false, // no_bindings
         false, // interactive
         false, // verbose
-        true, // validate (enabled by default)
+        policy,
         false, // skip_unicode_check
         false, // _nip_11
         false, // nip_42
         false, // nip_45
         false, // nip_50
-        nip11_args
+        nip11_args,
+        &build_args
     )?;
     
     // Rename the generated file to the specified output name if needed
@@ -1560,7 +2126,7 @@ fn process_dub_command(
     // Handle completion
     if let Some(ui) = dub_ui {
         // Interactive mode - show completion screen
-        ui.show_completion(&output_path.display().to_string(), processed_events.len() as u64)?;
+        ui.show_completion(&output_path.display().to_string(), written as u64)?;
         
         // Wait for user input
         use crossterm::event::{self, Event, KeyCode};
@@ -1584,109 +2150,211 @@ fn process_dub_command(
     Ok(())
 }
 
-/// Helper function to check if an event matches a filter
-fn event_matches_filter(event: &Value, filter: &serde_json::Map<String, Value>) -> bool {
-    // Check kinds
-    if let Some(kinds) = filter.get("kinds").and_then(|k| k.as_array()) {
-        if let Some(event_kind) = event.get("kind").and_then(|k| k.as_i64()) {
-            let kind_match = kinds.iter().any(|k| k.as_i64() == Some(event_kind));
-            if !kind_match {
-                return false;
-            }
-        }
+/// Process the slice command - dub a single cassette through a filter, keeping its identity and metadata
+fn process_slice_command(
+    cassette_path: &PathBuf,
+    output_path: &PathBuf,
+    name: Option<&str>,
+    filter_args: &[String],
+    kinds: &[i64],
+    authors: &[String],
+    limit: Option<usize>,
+    since: Option<i64>,
+    until: Option<i64>,
+    verbose: bool,
+    build_args: &BuildArgs,
+    nip11_args: &Nip11Args,
+) -> Result<()> {
+    if !cassette_path.exists() {
+        return Err(anyhow!("Cassette file not found: {}", cassette_path.display()));
     }
     
-    // Check authors
-    if let Some(authors) = filter.get("authors").and_then(|a| a.as_array()) {
-        if let Some(event_author) = event.get("pubkey").and_then(|p| p.as_str()) {
-            let author_match = authors.iter().any(|a| a.as_str() == Some(event_author));
-            if !author_match {
-                return false;
-            }
-        }
-    }
+    let authors = authors.iter()
+        .map(|author| nip19::normalize_pubkey(author))
+        .collect::<Result<Vec<_>>>()?;
     
-    // Check timestamps
-    if let Some(since) = filter.get("since").and_then(|s| s.as_i64()) {
-        if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-            if created_at < since {
-                return false;
-            }
-        }
-    }
+    // Read the source's relay info so the slice keeps its identity
+    let nip11 = inherit_relay_info(cassette_path, nip11_args)?;
     
-    if let Some(until) = filter.get("until").and_then(|u| u.as_i64()) {
-        if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-            if created_at > until {
-                return false;
-            }
-        }
+    let output_name = name.map(|n| n.to_string()).unwrap_or_else(|| {
+        output_path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "slice".to_string())
+    });
+    
+    println!("🔪 Slicing {} into {}", cassette_path.display(), output_path.display());
+    
+    process_dub_command(
+        std::slice::from_ref(cassette_path),
+        output_path,
+        Some(&output_name),
+        filter_args,
+        &[],
+        &[],
+        kinds,
+        &authors,
+        limit,
+        since,
+        until,
+        false,
+        verbose,
+        &nip11,
+        build_args,
+        &validation::ValidationPolicy::default(),
+        "slice",
+        false,
+        false,
+        build_args.fast,
+        None,
+    )?;
+    
+    println!("✅ Slice saved to: {}", output_path.display());
+    Ok(())
+}
+
+/// Relay info for a cassette derived from `cassette_path`: the source's, where not given
+fn inherit_relay_info(cassette_path: &PathBuf, nip11_args: &Nip11Args) -> Result<Nip11Args> {
+    let mut cassette = Cassette::load(&cassette_path.to_string_lossy(), false)?;
+    let source_info: Value = serde_json::from_str(&cassette.info()?).unwrap_or_default();
+    
+    let mut nip11 = nip11_args.clone();
+    let source_field = |key: &str| source_info.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+    nip11.relay_name = nip11.relay_name.or_else(|| source_field("name"));
+    nip11.relay_description = nip11.relay_description.or_else(|| source_field("description"));
+    nip11.relay_pubkey = nip11.relay_pubkey.or_else(|| source_field("pubkey"));
+    nip11.relay_contact = nip11.relay_contact.or_else(|| source_field("contact"));
+    Ok(nip11)
+}
+
+/// Process the split command - the inverse of dub, cutting one cassette into segment cassettes
+fn process_split_command(
+    cassette_path: &PathBuf,
+    output_dir: &PathBuf,
+    name: Option<&str>,
+    by: Option<segments::SplitBy>,
+    chunk_events: Option<usize>,
+    verbose: bool,
+    build_args: &BuildArgs,
+    nip11_args: &Nip11Args,
+) -> Result<()> {
+    if !cassette_path.exists() {
+        return Err(anyhow!("Cassette file not found: {}", cassette_path.display()));
     }
     
-    // Check tag filters
-    for (key, values) in filter {
-        if key.starts_with('#') && key.len() == 2 {
-            let tag_name = &key[1..];
-            if let Some(tags) = event.get("tags").and_then(|t| t.as_array()) {
-                let has_matching_tag = tags.iter().any(|tag| {
-                    if let Some(tag_arr) = tag.as_array() {
-                        if tag_arr.len() >= 2 {
-                            if let (Some(t_name), Some(t_value)) = (tag_arr[0].as_str(), tag_arr[1].as_str()) {
-                                if t_name == tag_name {
-                                    if let Some(filter_values) = values.as_array() {
-                                        return filter_values.iter().any(|v| v.as_str() == Some(t_value));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    false
-                });
-                
-                if !has_matching_tag {
-                    return false;
-                }
-            }
-        }
+    let mut build_args = build_args.clone();
+    build_args.split_by = by.or(build_args.split_by);
+    build_args.split_events = chunk_events.or(build_args.split_events);
+    if !build_args.split().is_set() {
+        return Err(anyhow!("Nothing to split by: give --by, --chunk-events or --split-size"));
     }
     
-    true
+    // The parts keep the source's identity, like a slice
+    let nip11 = inherit_relay_info(cassette_path, nip11_args)?;
+    
+    let name = name.map(|n| n.to_string()).unwrap_or_else(|| {
+        cassette_path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "split".to_string())
+    });
+    let name = sanitize_filename(&name);
+    fs::create_dir_all(output_dir)?;
+    
+    println!("✂️  Splitting {} into {}", cassette_path.display(), output_dir.display());
+    
+    // Dubbing the one input with segmenting turned on writes the parts and their manifest
+    process_dub_command(
+        std::slice::from_ref(cassette_path),
+        &output_dir.join(format!("{}.cassette", name)),
+        Some(&name),
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        None,
+        None,
+        None,
+        false,
+        verbose,
+        &nip11,
+        &build_args,
+        &validation::ValidationPolicy::default(),
+        "split",
+        false,
+        false,
+        build_args.fast,
+        None,
+    )?;
+    
+    let manifest_path = output_dir.join(format!("{}.segments.json", name));
+    if manifest_path.exists() {
+        println!("✅ Parts and manifest saved to: {}", manifest_path.display());
+    } else {
+        println!("✅ Everything fit in one part: {}", output_dir.join(format!("{}.cassette", name)).display());
+    }
+    Ok(())
 }
 
-/// Read a string from WASM memory using MSGB format
-fn read_string_from_memory(
-    store: &mut Store<()>,
-    _instance: &Instance,
-    memory: &Memory,
-    ptr: i32,
-) -> Result<String> {
-    // Check for MSGB signature
-    let mut sig_bytes = [0u8; 4];
-    memory.read(&mut *store, ptr as usize, &mut sig_bytes)?;
-    
-    if &sig_bytes == b"MSGB" {
-        // Read length
-        let mut len_bytes = [0u8; 4];
-        memory.read(&mut *store, (ptr + 4) as usize, &mut len_bytes)?;
-        let length = u32::from_le_bytes(len_bytes) as usize;
-        
-        // Read string data
-        let mut string_data = vec![0u8; length];
-        memory.read(&mut *store, (ptr + 8) as usize, &mut string_data)?;
-        
-        String::from_utf8(string_data).context("Invalid UTF-8 in response")
-    } else {
-        // Fallback: read null-terminated string
-        let mem_data = memory.data(&*store);
-        let start = ptr as usize;
-        let mut end = start;
-        
-        while end < mem_data.len() && mem_data[end] != 0 {
-            end += 1;
-        }
-        
-        String::from_utf8(mem_data[start..end].to_vec()).context("Invalid UTF-8 in response")
+/// Merge new events into an existing cassette's for `record --append`, keeping its relay info
+/// and metadata; returns the merged events file and the temp directory holding it
+fn prepare_append(
+    cassette_path: &PathBuf,
+    input_path: &std::path::Path,
+    format: input::InputFormat,
+    policy: &validation::ValidationPolicy,
+    nip11_args: &mut Nip11Args,
+    build_args: &mut BuildArgs,
+    verbose: bool,
+) -> Result<(TempDir, PathBuf)> {
+    if !cassette_path.exists() {
+        return Err(anyhow!("Cassette file not found: {}", cassette_path.display()));
     }
+    println!("📼 Appending to {}", cassette_path.display());
+    
+    let wasm_bytes = fs::read(cassette_path).context("Failed to read cassette WASM file")?;
+    let existing = extract_all_events_from_cassette(cassette_path, &Nip11Args::default())?;
+    
+    // Keep the cassette's identity unless it's overridden on the command line
+    let mut cassette = Cassette::load(&cassette_path.to_string_lossy(), false)?;
+    let source_info: Value = serde_json::from_str(&cassette.info()?).unwrap_or_default();
+    let source_field = |key: &str| source_info.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+    nip11_args.relay_name = nip11_args.relay_name.take().or_else(|| source_field("name"));
+    nip11_args.relay_description = nip11_args.relay_description.take().or_else(|| source_field("description"));
+    nip11_args.relay_pubkey = nip11_args.relay_pubkey.take().or_else(|| source_field("pubkey"));
+    nip11_args.relay_contact = nip11_args.relay_contact.take().or_else(|| source_field("contact"));
+    
+    let new_events: Vec<Value> = parse_events_from_file(&input_path.to_string_lossy(), format)?
+        .into_iter()
+        .filter(|event| policy.admit(event, verbose))
+        .collect();
+    let (events, stats) = append::merge(existing, new_events);
+    println!("{}", stats.summary());
+    
+    // Payload and compression describe the old build, not the events, and are set again if still used
+    let metadata = source_info.get("cassette");
+    if let Some(Value::Object(metadata)) = metadata {
+        build_args.metadata.extend(metadata.clone());
+        build_args.metadata.remove("payload");
+        build_args.metadata.remove("compression");
+    }
+    let parent_name = cassette_path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let parents = vec![lineage::parent_entry(
+        &parent_name,
+        &hex::encode(Sha256::digest(&wasm_bytes)),
+        stats.existing,
+        metadata,
+    )];
+    build_args.metadata.insert(
+        "lineage".to_string(),
+        lineage::lineage_record("append", &serde_json::Map::new(), parents, events.len()),
+    );
+    
+    let temp_dir = tempdir()?;
+    let merged_path = temp_dir.path().join("appended_events.json");
+    fs::write(&merged_path, serde_json::to_string(&events)?)?;
+    Ok((temp_dir, merged_path))
 }
 
 #[derive(Parser)]
@@ -1697,7 +2365,7 @@ struct Cli {
 }
 
 /// Common NIP-11 arguments for commands that load cassettes
-#[derive(clap::Args, Clone)]
+#[derive(clap::Args, Clone, Default)]
 struct Nip11Args {
     /// Name for NIP-11
     #[arg(long = "relay-name")]
@@ -1714,15 +2382,488 @@ struct Nip11Args {
     /// Contact for NIP-11
     #[arg(long = "relay-contact")]
     relay_contact: Option<String>,
+    
+    /// Icon URL for NIP-11
+    #[arg(long = "relay-icon", value_name = "URL")]
+    relay_icon: Option<String>,
+    
+    /// Countries whose laws may affect the relay (ISO 3166-1 alpha-2, comma-separated)
+    #[arg(long = "relay-countries", value_name = "CC", value_delimiter = ',')]
+    relay_countries: Vec<String>,
+    
+    /// Languages the relay is meant for (IETF tags, comma-separated)
+    #[arg(long = "language-tags", value_name = "TAG", value_delimiter = ',')]
+    language_tags: Vec<String>,
+    
+    /// URL of the posting policy
+    #[arg(long = "posting-policy", value_name = "URL")]
+    posting_policy: Option<String>,
+    
+    /// URL where fees are paid
+    #[arg(long = "payments-url", value_name = "URL")]
+    payments_url: Option<String>,
+    
+    /// NIP-11 `fees` object as JSON, e.g. '{"admission":[{"amount":1000,"unit":"msats"}]}'
+    #[arg(long, value_name = "JSON")]
+    fees: Option<String>,
+    
+    /// NIP-11 `retention` array as JSON, e.g. '[{"kinds":[0,[30000,39999]],"time":null}]'
+    #[arg(long, value_name = "JSON")]
+    retention: Option<String>,
+    
+    /// NIP-11 `limitation` fields as JSON, merged over the ones the cassette reports
+    #[arg(long, value_name = "JSON")]
+    limitation: Option<String>,
+}
+
+impl Nip11Args {
+    /// The NIP-11 fields given on the command line; the rest are left unset
+    fn relay_info(&self) -> Result<cassette_tools::nips::nip11::RelayInfo> {
+        fn parse<T: serde::de::DeserializeOwned>(flag: &str, json: Option<&String>) -> Result<Option<T>> {
+            json.map(|json| serde_json::from_str(json).with_context(|| format!("Invalid --{} JSON", flag)))
+                .transpose()
+        }
+        let list = |values: &[String]| (!values.is_empty()).then(|| values.to_vec());
+        
+        Ok(cassette_tools::nips::nip11::RelayInfo {
+            name: self.relay_name.clone(),
+            description: self.relay_description.clone(),
+            pubkey: self.relay_pubkey.clone(),
+            contact: self.relay_contact.clone(),
+            icon: self.relay_icon.clone(),
+            relay_countries: list(&self.relay_countries),
+            language_tags: list(&self.language_tags),
+            posting_policy: self.posting_policy.clone(),
+            payments_url: self.payments_url.clone(),
+            fees: parse("fees", self.fees.as_ref())?,
+            retention: parse("retention", self.retention.as_ref())?,
+            limitation: parse("limitation", self.limitation.as_ref())?,
+            ..Default::default()
+        })
+    }
+    
+    /// The given fields as a NIP-11 JSON object, for embedding in a cassette
+    fn document(&self) -> Result<String> {
+        let mut document = serde_json::Map::new();
+        self.relay_info()?.overlay(&mut document);
+        Ok(Value::Object(document).to_string())
+    }
+}
+
+/// Mute-list moderation for commands that serve cassettes
+#[derive(clap::Args, Clone, Default)]
+struct MuteArgs {
+    /// Kind 10000 mute list to apply to all query responses (JSON or NDJSON file)
+    #[arg(long = "mute-list", value_name = "FILE")]
+    mute_list: Option<PathBuf>,
+    
+    /// Operator pubkey whose kind 10000 mute list is applied (looked up in the served cassettes unless --mute-list is given)
+    #[arg(long = "mute-pubkey", value_name = "PUBKEY")]
+    mute_pubkey: Option<String>,
+}
+
+/// Host-side checks on what served cassettes return
+#[derive(clap::Args, Clone, Default)]
+struct ValidationArgs {
+    /// Re-verify every event a cassette returns (id, signature, filter match) and drop violations;
+    /// `--validate-responses=flag` logs violations but still serves the events
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true, default_missing_value = "drop")]
+    validate_responses: Option<response_validation::ValidationMode>,
+}
+
+impl ValidationArgs {
+    fn validator(&self) -> Option<response_validation::ResponseValidator> {
+        self.validate_responses.map(response_validation::ResponseValidator::new)
+    }
+}
+
+/// Which events are accepted, shared by every command that writes or reads events
+#[derive(clap::Args, Clone, Default)]
+struct PolicyArgs {
+    /// Accept events without checking their id, signature or tags
+    #[arg(long)]
+    skip_validation: bool,
+    
+    /// Check ids and tags but not signatures (much faster on large imports)
+    #[arg(long, conflicts_with = "skip_validation")]
+    skip_signatures: bool,
+    
+    /// Reject events whose content is larger than this many bytes (suffixes like 64k allowed)
+    #[arg(long, value_name = "BYTES", value_parser = sizes::parse_bytes)]
+    max_content_bytes: Option<usize>,
+    
+    /// Reject events larger than this many bytes once serialized (suffixes like 64k allowed)
+    #[arg(long, value_name = "BYTES", value_parser = sizes::parse_bytes)]
+    max_event_bytes: Option<usize>,
+    
+    /// Only accept these event kinds (comma-separated)
+    #[arg(long, value_name = "KINDS", value_delimiter = ',')]
+    allow_kinds: Vec<i64>,
+    
+    /// Reject these event kinds (comma-separated)
+    #[arg(long, value_name = "KINDS", value_delimiter = ',')]
+    deny_kinds: Vec<i64>,
+    
+    /// Only accept events by these authors, as hex, npub or nprofile (comma-separated or repeated)
+    #[arg(long, value_name = "PUBKEY", value_delimiter = ',', value_parser = parse_pubkey)]
+    allow_pubkey: Vec<String>,
+    
+    /// Reject events by these authors, as hex, npub or nprofile (comma-separated or repeated)
+    #[arg(long, value_name = "PUBKEY", value_delimiter = ',', value_parser = parse_pubkey)]
+    deny_pubkey: Vec<String>,
+}
+
+fn parse_pubkey(s: &str) -> std::result::Result<String, String> {
+    nip19::normalize_pubkey(s).map_err(|e| e.to_string())
+}
+
+impl PolicyArgs {
+    fn policy(&self) -> validation::ValidationPolicy {
+        let mut policy = if self.skip_validation {
+            validation::ValidationPolicy::permissive()
+        } else {
+            validation::ValidationPolicy::default()
+        };
+        policy.verify_sig &= !self.skip_signatures;
+        policy.max_content_bytes = self.max_content_bytes;
+        policy.max_event_bytes = self.max_event_bytes;
+        if !self.allow_kinds.is_empty() {
+            policy.kinds = Some(self.allow_kinds.iter().copied().collect());
+        }
+        policy.denied_kinds = self.deny_kinds.iter().copied().collect();
+        if !self.allow_pubkey.is_empty() {
+            policy.authors = Some(self.allow_pubkey.iter().cloned().collect());
+        }
+        policy.denied_authors = self.deny_pubkey.iter().cloned().collect();
+        policy
+    }
+}
+
+/// Rewrites applied to events before they are embedded, for privacy-scrubbed public archives
+#[derive(clap::Args, Clone, Default)]
+struct RedactArgs {
+    /// Remove every tag with this name from events (repeatable)
+    #[arg(long, value_name = "NAME")]
+    strip_tag: Vec<String>,
+    
+    /// Replace matches of this regex in event content with [redacted] (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    redact_content: Vec<String>,
+}
+
+impl RedactArgs {
+    fn rules(&self) -> Result<redact::Rules> {
+        redact::Rules::new(&self.strip_tag, &self.redact_content)
+    }
+}
+
+fn parse_meta(s: &str) -> std::result::Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("missing key in '{}'", s));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+impl BuildArgs {
+    /// Whether building needs cargo and the wasm32 target: not for --fast once a player is at hand
+    fn needs_toolchain(&self) -> bool {
+        !(self.fast && (self.player.is_some() || player::available()))
+    }
+
+    /// How the archive is cut into segment cassettes
+    fn split(&self) -> segments::Split {
+        segments::Split { by: self.split_by, max_bytes: self.segment_bytes, max_events: self.split_events }
+    }
+}
+
+/// Warm-up of cassettes before a server accepts connections
+#[derive(clap::Args, Clone, Default)]
+struct PreloadArgs {
+    /// Instantiate cassettes at startup and run a trivial query so the first client query isn't
+    /// slowed down by a cold start; `--preload=N` only warms the N most recently modified
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "0")]
+    preload: Option<usize>,
+}
+
+/// Pacing options for replaying events to relays
+#[derive(clap::Args, Clone, Default)]
+struct PlaybackArgs {
+    /// Space events by their original created_at deltas instead of a fixed throttle
+    #[arg(long)]
+    realtime: bool,
+    
+    /// Replay the original timeline faster or slower, e.g. 10x or 0.5x (implies --realtime)
+    #[arg(long, value_name = "FACTOR", value_parser = parse_speed)]
+    speed: Option<f64>,
+    
+    /// Emit events in a reproducible pseudo-random order derived from this seed
+    #[arg(long, value_name = "SEED", conflicts_with_all = ["realtime", "speed"])]
+    shuffle: Option<u64>,
+}
+
+impl PlaybackArgs {
+    /// Speed factor for timeline pacing, or None for fixed throttling
+    fn timeline_speed(&self) -> Option<f64> {
+        match self.speed {
+            Some(speed) => Some(speed),
+            None if self.realtime => Some(1.0),
+            None => None,
+        }
+    }
+}
+
+fn parse_speed(s: &str) -> std::result::Result<f64, String> {
+    let factor = s.trim().trim_end_matches(['x', 'X']);
+    match factor.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!("invalid speed '{}', expected a positive factor like 10x", s)),
+    }
+}
+
+/// Relays to download a cassette's events from instead of a file
+#[derive(clap::Args, Clone, Default)]
+struct FetchArgs {
+    /// Download the full history matching --filter from these relays (URLs, or nprofile/nevent/naddr
+    /// for their relay hints), paging backwards with `until`
+    #[arg(long, num_args = 1.., value_delimiter = ' ')]
+    relays: Vec<String>,
+    
+    /// Filter JSON for the download (default: everything)
+    #[arg(long, requires = "relays")]
+    filter: Option<String>,
+    
+    /// Largest `limit` asked for in one page
+    #[arg(long, value_name = "N", default_value = "500", requires = "relays")]
+    page_limit: usize,
+    
+    /// Target response size per page in KB; the limit is sized from the events seen so far
+    #[arg(long, value_name = "KB", default_value = "512", requires = "relays")]
+    page_budget: usize,
+    
+    /// Seconds to wait for a page before retrying it with a smaller limit
+    #[arg(long, value_name = "SECS", default_value = "30", requires = "relays")]
+    page_timeout: u64,
+    
+    /// Don't fetch the relays' NIP-11 documents to check their limits before downloading
+    #[arg(long, requires = "relays")]
+    no_probe: bool,
+    
+    /// Download these authors' histories (hex, npub or nprofile) from the write relays of their
+    /// NIP-65 relay lists, looked up on --relays; authors without one are downloaded from --relays
+    #[arg(long, value_name = "PUBKEY", num_args = 1.., value_delimiter = ' ', requires = "relays")]
+    follow_outbox: Vec<String>,
+    
+    /// Write relays used per followed author
+    #[arg(long, value_name = "N", default_value = "3", requires = "follow_outbox")]
+    outbox_relays: usize,
+}
+
+impl FetchArgs {
+    fn page_options(&self) -> fetch::PageOptions {
+        fetch::PageOptions {
+            max_limit: self.page_limit,
+            byte_budget: self.page_budget * 1024,
+            timeout: Duration::from_secs(self.page_timeout),
+        }
+    }
+}
+
+/// Continuous recording from a directory instead of a single input
+#[derive(clap::Args, Clone, Default)]
+struct WatchArgs {
+    /// Keep recording from files dropped into this directory, building a cassette whenever the
+    /// buffered events reach --event-limit, --size-limit or --duration; Ctrl-C builds the rest
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["input_file", "relays", "nostrdb", "append", "interactive"])]
+    watch: Option<PathBuf>,
+    
+    /// Max events per cassette
+    #[arg(long, value_name = "N", default_value = "10000", requires = "watch")]
+    event_limit: usize,
+    
+    /// Max events per cassette in MB of JSON
+    #[arg(long, value_name = "MB", default_value = "100", requires = "watch")]
+    size_limit: usize,
+    
+    /// Seconds after the first buffered event before a cassette is built (0 = no time limit)
+    #[arg(long, value_name = "SECS", default_value = "3600", requires = "watch")]
+    duration: u64,
+    
+    /// Seconds between scans of the watched directory
+    #[arg(long, value_name = "SECS", default_value = "2", requires = "watch")]
+    poll_interval: u64,
+}
+
+impl WatchArgs {
+    fn limits(&self) -> watch::Limits {
+        watch::Limits { events: self.event_limit.max(1), bytes: self.size_limit * 1024 * 1024, seconds: self.duration }
+    }
+}
+
+/// Generator options that control what gets compiled into a cassette
+#[derive(clap::Args, Clone, Default)]
+struct BuildArgs {
+    /// Build a size-optimized cassette (no schema machinery or debug logging, panic=abort, opt-level=z)
+    #[arg(long)]
+    minimal: bool,
+    
+    /// Partition events into language-tagged segments (NIP-32 labels or content detection)
+    /// to serve `languages` filters and per-language COUNT
+    #[arg(long)]
+    languages: bool,
+    
+    /// Keep a uniform random subset of events (fraction between 0 and 1, e.g. 0.1)
+    #[arg(long, value_name = "RATE")]
+    sample: Option<f64>,
+    
+    /// Apply the sample rate within each kind so rare kinds stay represented
+    #[arg(long = "sample-per-kind", requires = "sample")]
+    sample_per_kind: bool,
+    
+    /// Maximum filters per REQ/COUNT before the cassette answers CLOSED (0 = unlimited, default 10)
+    #[arg(long, value_name = "N")]
+    max_filters: Option<usize>,
+    
+    /// Maximum tag filters (#e, #p, ...) per filter (0 = unlimited, default 10)
+    #[arg(long, value_name = "N")]
+    max_tag_filters: Option<usize>,
+    
+    /// Maximum values in any filter list: ids, authors, kinds, tag values (0 = unlimited, default 1000)
+    #[arg(long, value_name = "N")]
+    max_filter_values: Option<usize>,
+    
+    /// Keep only the newest events that fit in this many bytes of serialized JSON (e.g. 500MB)
+    #[arg(long, value_name = "BYTES", value_parser = sizes::parse_bytes)]
+    max_archive_bytes: Option<usize>,
+    
+    /// Split archives larger than this many bytes of serialized JSON (e.g. 50MB) into
+    /// time-ordered segment cassettes plus a <name>.segments.json manifest
+    #[arg(long, visible_alias = "split-size", value_name = "BYTES", value_parser = sizes::parse_bytes)]
+    segment_bytes: Option<usize>,
+    
+    /// Split archives into segment cassettes of at most this many events each
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u64).range(1..).map(|n| n as usize))]
+    split_events: Option<usize>,
+    
+    /// Build one segment per event kind, month or author; --split-size and --split-events
+    /// cut each of them further
+    #[arg(long, value_enum, value_name = "KEY")]
+    split_by: Option<segments::SplitBy>,
+    
+    /// Keep the events out of the wasm module, in a content-addressed <sha256>.payload
+    /// file next to it that loaders verify against the cassette before use
+    #[arg(long)]
+    payload_sidecar: bool,
+    
+    /// Embed the events zstd-compressed; the cassette decompresses and parses all of them on its first query
+    #[arg(long, conflicts_with = "payload_sidecar")]
+    compress: bool,
+    
+    /// Don't embed the kind/author/tag index (smaller cassette, every query scans all events)
+    #[arg(long)]
+    no_index: bool,
+    
+    /// Keep events deleted by kind 5 deletion requests (NIP-09) and don't hide them at query time
+    #[arg(long)]
+    keep_deleted: bool,
+    
+    /// Accept EVENT messages into an in-memory buffer inside the cassette, served to later
+    /// REQ and COUNT messages for as long as the instance lives
+    #[arg(long)]
+    writable: bool,
+    
+    /// Check kind 9735 zap receipts (bolt11 amount and description hash against the zap request,
+    /// signer against the recipient's LNURL server) and list invalid ones in the cassette metadata;
+    /// `--validate-zaps=drop` also leaves them out
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true, default_missing_value = "flag")]
+    validate_zaps: Option<zaps::ZapMode>,
+    
+    /// Compile schnorr verification into the cassette so consumers can check its events
+    /// through the verify_events() export; `--verify=query` also has every query skip
+    /// events whose id or signature doesn't check out
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true, default_missing_value = "exports")]
+    verify: Option<validation::CassetteVerification>,
+    
+    /// Emit a WebAssembly component implementing the `cassette` WIT world (wit/cassette.wit)
+    /// instead of a core module with the MSGB exports
+    #[arg(long, conflicts_with_all = ["payload_sidecar", "segment_bytes", "split_events", "split_by"])]
+    component: bool,
+    
+    /// Build the cassette in milliseconds by injecting the events into the precompiled player
+    /// module instead of compiling it with cargo; the player always serves COUNT, search and
+    /// verify_events()
+    #[arg(long, conflicts_with_all = ["minimal", "component", "payload_sidecar", "compress", "writable", "keep_deleted"])]
+    fast: bool,
+    
+    /// Make the build reproducible: remap build paths out of the module, compile with one
+    /// codegen unit and stamp the lineage with SOURCE_DATE_EPOCH, so the same events give the
+    /// same cassette hash
+    #[arg(long)]
+    deterministic: bool,
+    
+    /// Compile in a fresh target directory instead of the shared cache in ~/.cache/cassette/target
+    #[arg(long)]
+    no_cache: bool,
+    
+    #[command(flatten)]
+    redact: RedactArgs,
+    
+    /// Custom provenance to embed as KEY=VALUE (repeatable), served under cassette.meta in
+    /// the NIP-11 info, e.g. --meta source=wss://relay.example --meta curator=<npub>
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_meta)]
+    meta: Vec<(String, String)>,
+    
+    /// Sign a manifest of the event set (its sha256, your pubkey and the time) with this key, given
+    /// as an nsec, hex or a file holding one; `cassette verify` checks it
+    #[arg(long, value_name = "NSEC|KEYFILE")]
+    sign: Option<String>,
+    
+    /// Encrypt the events to this recipient (npub or hex pubkey, NIP-44 v2 keys and cipher);
+    /// the cassette serves nothing until a host passes the recipient's key to its unlock() export
+    #[arg(long, value_name = "NPUB", conflicts_with_all = ["payload_sidecar", "compress", "fast", "component"])]
+    encrypt: Option<String>,
+    
+    /// Metadata inherited from source cassettes (embedded alongside sampling info)
+    #[arg(skip)]
+    metadata: serde_json::Map<String, Value>,
+    
+    /// Player taken out of a source cassette, used by --fast instead of the embedded or cached one
+    #[arg(skip)]
+    player: Option<Vec<u8>>,
+    
+    /// Which dub input each event came from, served by the provenance() export
+    #[arg(skip)]
+    provenance: Option<Value>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Record Nostr events from a file or piped input to create a cassette
     Record {
-        /// Path to input events.json file (if not provided, reads from stdin)
+        /// Path to input events.json file (if not provided, reads from stdin); JSON arrays, NDJSON
+        /// and NIP-01 EVENT messages are accepted, gzip or zstd compressed or not
         input_file: Option<PathBuf>,
 
+        /// Shape of the input file: auto guesses it from the first line
+        #[arg(long, value_enum, default_value_t = input::InputFormat::Auto, requires = "input_file")]
+        format: input::InputFormat,
+
+        /// Add the events to this cassette instead of starting a new one: its events are extracted,
+        /// merged with the input (duplicates and older replaceable versions dropped) and the cassette
+        /// is rebuilt in place, or under --name/--output when given
+        #[arg(long, value_name = "CASSETTE", conflicts_with_all = ["segment_bytes", "split_events", "split_by"])]
+        append: Option<PathBuf>,
+
+        /// Import the events of a nostrdb cache (the LMDB directory of Damus, notedeck and
+        /// other nostrdb clients) instead of reading a file
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["input_file", "relays"])]
+        nostrdb: Option<PathBuf>,
+
+        /// Keep ephemeral events (kinds 20000-29999) found in the nostrdb cache
+        #[arg(long, requires = "nostrdb")]
+        keep_ephemeral: bool,
+
         /// Name for the generated cassette (used for filename)
         #[arg(short, long)]
         name: Option<String>,
@@ -1749,9 +2890,8 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
         
-        /// Skip validation of Nostr events (validation is enabled by default)
-        #[arg(long)]
-        _skip_validation: bool,
+        #[command(flatten)]
+        policy: PolicyArgs,
         
         /// Skip Unicode character checks that might cause Rust compilation issues
         #[arg(long = "skip-unicode-check")]
@@ -1761,8 +2901,8 @@ enum Commands {
         #[arg(long)]
         _nip_11: bool,
         
-        /// Enable NIP-42 (Authentication)
-        #[arg(long)]
+        /// Enable NIP-42 (Authentication); the --fast player doesn't serve AUTH
+        #[arg(long, conflicts_with = "fast")]
         nip_42: bool,
         
         /// Enable NIP-45 (Event Counts)
@@ -1775,14 +2915,25 @@ enum Commands {
         
         #[command(flatten)]
         nip11: Nip11Args,
+        
+        #[command(flatten)]
+        build: BuildArgs,
+        
+        #[command(flatten)]
+        fetch: FetchArgs,
+        
+        #[command(flatten)]
+        watch: WatchArgs,
     },
     
     /// Combine multiple cassettes into a new cassette (dubbing/mixing)
     Dub {
         /// Input cassette files to combine
+        #[arg(required = true)]
         cassettes: Vec<PathBuf>,
         
         /// Output cassette file path
+        #[arg(required = true)]
         output: Option<PathBuf>,
         
         /// Name for the generated cassette (used for filename)
@@ -1793,11 +2944,34 @@ enum Commands {
         #[arg(short, long, value_name = "JSON")]
         filter: Vec<String>,
         
+        /// Filter JSON for one input, named by its path, file name or stem; several for the
+        /// same input keep events matching any of them (repeatable)
+        #[arg(long, num_args = 2, value_names = ["INPUT", "JSON"])]
+        filter_for: Vec<String>,
+        
+        /// Leave out events matching this filter JSON, whichever input they come from (repeatable)
+        #[arg(long, value_name = "JSON")]
+        exclude: Vec<String>,
+        
+        /// Keep only the events of the first input that the others don't have, for
+        /// incremental deltas
+        #[arg(long)]
+        subtract: bool,
+        
+        /// Record which input each event came from, served by the cassette's provenance()
+        /// export and summarized by inspect
+        #[arg(long)]
+        provenance: bool,
+        
+        /// Cassettes to compile and read at once (default: one per core)
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..).map(|n| n as usize))]
+        jobs: Option<usize>,
+        
         /// Kinds to filter (can be specified multiple times)
         #[arg(short, long)]
         kinds: Vec<i64>,
         
-        /// Authors to filter (can be specified multiple times)
+        /// Authors to filter, as hex, npub or nprofile (can be specified multiple times)
         #[arg(long)]
         authors: Vec<String>,
         
@@ -1819,29 +2993,44 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
         
+        /// Compile the new cassette even when an input was built with --fast, whose player
+        /// could otherwise be reused with the new events
+        #[arg(long)]
+        recompile: bool,
+        
+        #[command(flatten)]
+        policy: PolicyArgs,
+        
+        #[command(flatten)]
+        redact: RedactArgs,
+        
         #[command(flatten)]
         nip11: Nip11Args,
     },
     
-    /// Scrub through cassette events (send REQ messages and get events)
-    Scrub {
-        /// Path to the cassette WASM file
+    /// Extract the events matching a filter into a new cassette
+    Slice {
+        /// Source cassette file
         cassette: Option<PathBuf>,
         
-        /// Subscription ID
-        #[arg(short, long, default_value = "sub1")]
-        subscription: String,
+        /// Output cassette file path
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        
+        /// Name for the generated cassette (defaults to the output file name)
+        #[arg(short, long)]
+        name: Option<String>,
         
         /// Filter JSON (can be specified multiple times)
         #[arg(short, long, value_name = "JSON")]
         filter: Vec<String>,
         
-        /// Kinds to filter (can be specified multiple times)
+        /// Kinds to keep (can be specified multiple times)
         #[arg(short, long)]
         kinds: Vec<i64>,
         
-        /// Authors to filter (can be specified multiple times)
-        #[arg(short, long)]
+        /// Authors to keep, as hex or npub (can be specified multiple times)
+        #[arg(long)]
         authors: Vec<String>,
         
         /// Limit number of events
@@ -1856,32 +3045,209 @@ enum Commands {
         #[arg(long)]
         until: Option<i64>,
         
-        /// Output format: nip01 (default), json, or ndjson
-        #[arg(short, long, default_value = "nip01")]
-        output: String,
-        /// Enable interactive mode with visual feedback
-        #[arg(short = 'i', long)]
-        interactive: bool,
         /// Show verbose output including compilation details
         #[arg(short, long)]
         verbose: bool,
         
-        /// Skip validation of returned Nostr events (validation is enabled by default)
-        #[arg(long)]
-        _skip_validation: bool,
+        #[command(flatten)]
+        build: BuildArgs,
         
-        /// Show NIP-11 relay information instead of playing events
-        #[arg(long)]
-        info: bool,
+        #[command(flatten)]
+        nip11: Nip11Args,
+    },
+    
+    /// Split a cassette into smaller ones by month, kind, author or event count, with a
+    /// <name>.segments.json manifest describing the parts
+    Split {
+        /// Source cassette file
+        cassette: Option<PathBuf>,
         
-        /// Perform COUNT query instead of REQ (NIP-45)
-        #[arg(long)]
+        /// Build one cassette per month, kind or author
+        #[arg(long, value_enum, value_name = "KEY")]
+        by: Option<segments::SplitBy>,
+        
+        /// Cut into cassettes of at most this many events (each --by group, when given)
+        #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u64).range(1..).map(|n| n as usize))]
+        chunk_events: Option<usize>,
+        
+        /// Directory to write the parts and manifest to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        
+        /// Name of the parts and manifest (defaults to the source's file name)
+        #[arg(short, long)]
+        name: Option<String>,
+        
+        /// Show verbose output including compilation details
+        #[arg(short, long)]
+        verbose: bool,
+        
+        #[command(flatten)]
+        build: BuildArgs,
+        
+        #[command(flatten)]
+        nip11: Nip11Args,
+    },
+    
+    /// Inspect a cassette's identity, embedded metadata and provenance
+    Inspect {
+        /// Cassette file to inspect
+        cassette: Option<PathBuf>,
+        
+        /// Show the chain of cassettes this one was derived from
+        #[arg(long)]
+        lineage: bool,
+        
+        /// Show which input of a dub --provenance this event came from
+        #[arg(long, value_name = "EVENT_ID")]
+        provenance: Option<String>,
+        
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Check a cassette's signed manifest: the signature, and that its events hash to the signed value
+    Verify {
+        /// Cassette file to verify
+        cassette: PathBuf,
+        
+        /// Also require the manifest to be signed by this pubkey (hex or npub)
+        #[arg(long, value_parser = parse_pubkey)]
+        pubkey: Option<String>,
+    },
+    
+    /// Check a cassette's integrity: its exports and memory ABI, and that its events are
+    /// valid, newest first and served once
+    Validate {
+        /// Cassette file to validate
+        cassette: Option<PathBuf>,
+        
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Scrub through cassette events (send REQ messages and get events)
+    Scrub {
+        /// Cassette WASM files or glob patterns, or a <name>.segments.json manifest; events
+        /// of several cassettes are merged newest first without duplicates
+        cassettes: Vec<PathBuf>,
+        
+        /// Subscription ID
+        #[arg(short, long, default_value = "sub1")]
+        subscription: String,
+        
+        /// Filter JSON (can be specified multiple times)
+        #[arg(short, long, value_name = "JSON")]
+        filter: Vec<String>,
+        
+        /// Kinds to filter (can be specified multiple times)
+        #[arg(short, long)]
+        kinds: Vec<i64>,
+        
+        /// Authors to filter, as hex, npub or nprofile (can be specified multiple times)
+        #[arg(short, long)]
+        authors: Vec<String>,
+        
+        /// Limit number of events
+        #[arg(short, long)]
+        limit: Option<usize>,
+        
+        /// Since timestamp
+        #[arg(long)]
+        since: Option<i64>,
+        
+        /// Until timestamp
+        #[arg(long)]
+        until: Option<i64>,
+        
+        /// Output format: nip01 (default), json, ndjson, table, csv, or summary
+        #[arg(short, long, default_value = "nip01")]
+        output: String,
+        /// Enable interactive mode with visual feedback
+        #[arg(short = 'i', long)]
+        interactive: bool,
+        /// Show verbose output including compilation details
+        #[arg(short, long)]
+        verbose: bool,
+        
+        #[command(flatten)]
+        policy: PolicyArgs,
+        
+        /// Show NIP-11 relay information instead of playing events
+        #[arg(long)]
+        info: bool,
+        
+        /// Perform COUNT query instead of REQ (NIP-45)
+        #[arg(long)]
         count: bool,
         
         /// Search query for NIP-50 text search
         #[arg(long)]
         search: Option<String>,
         
+        /// Only events with this `d` tag identifier (can be specified multiple times)
+        #[arg(long = "d", value_name = "IDENTIFIER")]
+        d: Vec<String>,
+        
+        /// The addressable event at this coordinate (pubkey as hex or npub), or an naddr
+        #[arg(long, value_name = "KIND:PUBKEY:D", conflicts_with = "d")]
+        address: Option<String>,
+        
+        /// Only events with one of these ids, as hex, note or nevent (repeatable or comma-separated)
+        #[arg(long, value_name = "ID")]
+        ids: Vec<String>,
+        
+        /// Only events with a NAME tag of one of these values, e.g. e=<id> or t=nostr,zaps
+        /// (repeatable)
+        #[arg(long, value_name = "NAME=VALUE")]
+        tag: Vec<String>,
+        
+        /// Only events with a NAME tag of every one of these values (NIP-119), e.g. t=nostr,zaps
+        /// (repeatable)
+        #[arg(long, value_name = "NAME=VALUES")]
+        and_tag: Vec<String>,
+        
+        /// Skip this many of the cassette's results, read through its req_page export
+        #[arg(long, value_name = "N", conflicts_with = "cursor")]
+        offset: Option<u64>,
+        
+        /// Continue from the cursor a previous paged scrub printed as "Next page"
+        #[arg(long, value_name = "CURSOR")]
+        cursor: Option<u64>,
+        
+        /// Events per req_page call when paging (default: the cassette's page size)
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        page_size: Option<u32>,
+        
+        /// Output events in a reproducible pseudo-random order derived from this seed
+        #[arg(long, value_name = "SEED")]
+        shuffle: Option<u64>,
+        
+        /// Print each event as soon as the cassette returns it instead of collecting them first
+        /// (nip01 or ndjson output)
+        #[arg(long, conflicts_with_all = ["interactive", "shuffle"])]
+        stream: bool,
+        
+        /// Leave out events with a NIP-36 content-warning tag (applied before --limit)
+        #[arg(long, conflicts_with = "count")]
+        exclude_sensitive: bool,
+        
+        /// Only events with a NIP-32 label matching NAMESPACE=VALUE, NAMESPACE>=NUMBER (or >, <=, <)
+        /// or a bare VALUE, from kind 1985 events in the cassette or self-labels (repeatable, all must match)
+        #[arg(long, value_name = "SELECTOR", conflicts_with = "count")]
+        label: Vec<String>,
+        
+        /// Only count labels by this pubkey, as hex or npub (repeatable)
+        #[arg(long, value_name = "PUBKEY", requires = "label")]
+        labeler: Vec<String>,
+        
+        /// Remember verified signatures in a <cassette>.verified file next to the cassette,
+        /// so later scrubs skip checking them again
+        #[arg(long)]
+        verified_cache: bool,
+        
         #[command(flatten)]
         nip11: Nip11Args,
     },
@@ -1904,7 +3270,7 @@ enum Commands {
         #[arg(short, long)]
         kinds: Vec<i64>,
         
-        /// Authors to filter (can be specified multiple times)
+        /// Authors to filter, as hex, npub or nprofile (can be specified multiple times)
         #[arg(short, long)]
         authors: Vec<String>,
         
@@ -1932,9 +3298,8 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
         
-        /// Skip validation of returned Nostr events (validation is enabled by default)
-        #[arg(long)]
-        _skip_validation: bool,
+        #[command(flatten)]
+        policy: PolicyArgs,
         
         /// Show NIP-11 relay information instead of playing events
         #[arg(long)]
@@ -1957,17 +3322,22 @@ enum Commands {
         /// Input cassette files to broadcast
         cassettes: Vec<PathBuf>,
         
-        /// Target relay URLs
+        /// Target relay URLs, or nprofile/nevent/naddr for their relay hints
         #[arg(short, long)]
         relays: Vec<String>,
         
+        /// Re-ingest into a deck output directory instead of relays, through the deck's
+        /// EVENT pipeline (validation, dedup against its cassettes, replaceable events, rotation)
+        #[arg(long, value_name = "DIR", conflicts_with = "relays")]
+        to_cassette: Option<PathBuf>,
+        
         /// Maximum concurrent relay connections
         #[arg(short, long, default_value = "5")]
         concurrency: usize,
         
-        /// Delay between event publishes in milliseconds (per relay)
-        #[arg(short, long, default_value = "100")]
-        throttle: u64,
+        /// Delay between event publishes in milliseconds (per relay; default: 100, 0 with --to-cassette)
+        #[arg(short, long)]
+        throttle: Option<u64>,
         
         /// Timeout for relay connections in seconds
         #[arg(long, default_value = "30")]
@@ -1976,6 +3346,26 @@ enum Commands {
         /// Dry run - show what would be sent without actually sending
         #[arg(long)]
         dry_run: bool,
+        
+        /// Don't fetch the relays' NIP-11 documents to check their limits before sending
+        #[arg(long)]
+        no_probe: bool,
+        
+        /// Continue a playback that stopped, from each relay's last answered event in the checkpoint
+        #[arg(long, conflicts_with = "to_cassette")]
+        resume: bool,
+        
+        /// Progress file of the playback (default: <first cassette>.play.json)
+        #[arg(long, value_name = "FILE", conflicts_with = "to_cassette")]
+        checkpoint: Option<PathBuf>,
+        
+        /// Times an event a relay answers "rate-limited:" is sent again before it counts as rejected
+        #[arg(long, value_name = "N", default_value = "5")]
+        max_retries: u32,
+        
+        /// Delay after a relay's first rate limit in milliseconds, doubled on each one after (up to 60s)
+        #[arg(long, value_name = "MS", default_value = "1000")]
+        backoff: u64,
         /// Enable interactive mode with visual feedback
         #[arg(short = 'i', long)]
         interactive: bool,
@@ -1983,6 +3373,9 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
         
+        #[command(flatten)]
+        playback: PlaybackArgs,
+        
         #[command(flatten)]
         nip11: Nip11Args,
     },
@@ -2023,6 +3416,18 @@ enum Commands {
         nip11: Nip11Args,
     },
     
+    /// Write the `record --fast` player module, for release builds to embed through
+    /// CASSETTE_PLAYER_WASM
+    #[command(name = "build-player", hide = true)]
+    BuildPlayer {
+        /// Where to write the player module
+        output: PathBuf,
+        
+        /// Show verbose output including compilation details
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    
     /// Start a WebSocket server to serve cassettes as a Nostr relay
     Listen {
         /// Cassette files to serve (supports globs like "*.wasm" or "dir/*.wasm")
@@ -2055,6 +3460,31 @@ enum Commands {
         /// Show verbose output
         #[arg(short, long)]
         verbose: bool,
+        
+        /// Events with a NIP-36 content-warning tag: serve, exclude, or opt-in
+        /// (served only to filters setting "include_sensitive": true)
+        #[arg(long, value_enum, value_name = "POLICY", default_value = "serve")]
+        sensitive: content_warning::SensitivePolicy,
+        
+        /// Challenge every connection (NIP-42) and close REQs until the client authenticates
+        #[arg(long)]
+        require_auth: bool,
+        
+        /// URL clients must name in their AUTH events (default: the ws:// or wss:// address served)
+        #[arg(long, value_name = "URL", requires = "require_auth")]
+        relay_url: Option<String>,
+        
+        #[command(flatten)]
+        nip11: Nip11Args,
+        
+        #[command(flatten)]
+        mute: MuteArgs,
+        
+        #[command(flatten)]
+        validation: ValidationArgs,
+        
+        #[command(flatten)]
+        preload: PreloadArgs,
     },
     
     /// Run a cassette deck - continuously record and serve cassettes
@@ -2063,7 +3493,7 @@ enum Commands {
         #[arg(short, long, default_value = "relay")]
         mode: String,
         
-        /// Relay URLs to record from (for record mode)
+        /// Relay URLs to record from, or nprofile/nevent/naddr for their relay hints (for record mode)
         #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
         relays: Vec<String>,
         
@@ -2091,10 +3521,20 @@ enum Commands {
         #[arg(short = 's', long, default_value = "100")]
         size_limit: usize,
         
+        /// Stop accepting events once the cassettes in the output directory and the buffer
+        /// take this many bytes (e.g. 10GB)
+        #[arg(long, value_name = "BYTES", value_parser = sizes::parse_bytes)]
+        max_archive_bytes: Option<usize>,
+        
         /// Recording duration per cassette in seconds (0 = no time limit)
         #[arg(short = 'd', long, default_value = "3600")]
         duration: u64,
         
+        /// Seconds before a rotation whose events are also written to the next cassette,
+        /// so slow compiles never leave an ingestion gap (duplicates are removed at query time)
+        #[arg(long, value_name = "SECS", default_value = "0")]
+        rotation_overlap: u64,
+        
         /// Filter JSON for recording
         #[arg(short, long)]
         filter: Option<String>,
@@ -2103,7 +3543,7 @@ enum Commands {
         #[arg(short, long)]
         kinds: Vec<i64>,
         
-        /// Authors to filter
+        /// Authors to filter, as hex, npub or nprofile
         #[arg(short, long)]
         authors: Vec<String>,
         
@@ -2123,108 +3563,291 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
         
-        /// Skip event validation
+        #[command(flatten)]
+        policy: PolicyArgs,
+        
+        /// Unix socket serving status snapshots for `cassette deck-status` (default: <OUTPUT>/deck.sock)
         #[arg(long)]
-        _skip_validation: bool,
+        admin_socket: Option<PathBuf>,
+        
+        /// File keeping the last recorded event per relay, to resume from after a restart
+        /// (record mode, default: <OUTPUT>/relay-cursors.json)
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+        
+        /// Where the index of archived event ids lives: in memory, or in <OUTPUT>/deck-index.redb
+        /// so duplicate checks survive restarts
+        #[arg(long, value_enum, value_name = "BACKEND", default_value = "memory")]
+        index_store: deck_storage::Backend,
+        
+        /// Another deck to forward accepted events to (relay mode, repeatable); two decks
+        /// peering with each other replicate both ways
+        #[arg(long = "peer", value_name = "URL")]
+        peers: Vec<String>,
         
         #[command(flatten)]
         nip11: Nip11Args,
+        
+        #[command(flatten)]
+        mute: MuteArgs,
+        
+        #[command(flatten)]
+        validation: ValidationArgs,
     },
     
-}
-
-/// Helper function to load a cassette and set its NIP-11 info if available
-fn load_cassette_with_nip11(
-    store: &mut Store<()>,
-    instance: &Instance,
-    nip11_args: &Nip11Args,
-) -> Result<()> {
-    // Check if the cassette exports set_info function
-    if let Ok(set_info) = instance.get_typed_func::<(i32, i32), i32>(&mut *store, "set_info") {
-        // Build RelayInfo from CLI arguments
-        let mut relay_info = serde_json::Map::new();
+    /// Show buffer fill, next rotation, cassettes, connections and recent errors of a running deck
+    DeckStatus {
+        /// Admin socket of the deck (default: <OUTPUT>/deck.sock)
+        #[arg(long)]
+        socket: Option<PathBuf>,
         
-        // Add required fields 
-        relay_info.insert("supported_nips".to_string(), json!(Vec::<u32>::new()));
+        /// Output directory the deck was started with
+        #[arg(short, long, default_value = "./deck")]
+        output: PathBuf,
         
-        // Add optional fields if provided
-        if let Some(name) = &nip11_args.relay_name {
-            relay_info.insert("name".to_string(), json!(name));
-        }
-        if let Some(desc) = &nip11_args.relay_description {
-            relay_info.insert("description".to_string(), json!(desc));
-        }
-        if let Some(pubkey) = &nip11_args.relay_pubkey {
-            relay_info.insert("pubkey".to_string(), json!(pubkey));
-        }
-        if let Some(contact) = &nip11_args.relay_contact {
-            relay_info.insert("contact".to_string(), json!(contact));
-        }
+        /// Seconds to wait for the deck to answer
+        #[arg(long, default_value = "5")]
+        timeout: u64,
+        
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Time instantiation and queries of a cassette: latency distribution and throughput
+    Bench {
+        /// Cassette file to benchmark
+        cassette: Option<PathBuf>,
         
-        // Serialize to JSON
-        let json_str = serde_json::to_string(&relay_info)?;
-        let json_bytes = json_str.as_bytes();
+        /// JSON file of queries: an array of filter objects or arrays of filters
+        /// (default: a corpus derived from the cassette's events)
+        #[arg(long)]
+        filters: Option<PathBuf>,
         
-        // Get memory and allocation function
-        let memory = instance
-            .get_memory(&mut *store, "memory")
-            .ok_or_else(|| anyhow!("Memory export not found"))?;
+        /// Timed runs of instantiation and of each query
+        #[arg(short = 'n', long, default_value = "100", value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
         
-        let alloc_func = instance
-            .get_typed_func::<i32, i32>(&mut *store, "alloc_buffer")
-            .or_else(|_| instance.get_typed_func::<i32, i32>(&mut *store, "alloc_string"))?;
+        /// Untimed runs before each measurement
+        #[arg(long, default_value = "10")]
+        warmup: u32,
         
-        // Allocate memory for the JSON string
-        let json_ptr = alloc_func.call(&mut *store, json_bytes.len() as i32)?;
-        if json_ptr == 0 {
-            return Err(anyhow!("Failed to allocate memory for NIP-11 info"));
-        }
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Compare a cassette's REQ/COUNT responses against a reference relay holding the same events
+    Conformance {
+        /// Cassette file to test
+        cassette: PathBuf,
         
-        // Write JSON to memory
-        memory.write(&mut *store, json_ptr as usize, json_bytes)?;
+        /// Reference relay URL (e.g. wss://relay.example.com)
+        #[arg(long)]
+        against: String,
         
-        // Call set_info
-        let result = set_info.call(&mut *store, (json_ptr, json_bytes.len() as i32))?;
+        /// JSON file of queries: an array of filter objects or arrays of filters
+        /// (default: a corpus derived from the cassette's events)
+        #[arg(long)]
+        queries: Option<PathBuf>,
         
-        // Clean up allocated memory
-        if let Ok(dealloc_func) = instance.get_typed_func::<(i32, i32), ()>(&mut *store, "dealloc_string") {
-            dealloc_func.call(&mut *store, (json_ptr, json_bytes.len() as i32))?;
-        }
+        /// Only compare REQ responses
+        #[arg(long)]
+        no_count: bool,
         
-        if result != 0 {
-            eprintln!("Warning: Failed to set NIP-11 info (error code: {})", result);
-        }
-    }
-    
-    Ok(())
-}
-
-
-#[cfg(feature = "deck")]
-/// Initialize persistent embedded tools directory for deck mode
-fn init_embedded_tools_dir(output_dir: &PathBuf) -> Result<PathBuf> {
-    let tools_dir = output_dir.join(".cassette-tools-embedded");
-    fs::create_dir_all(&tools_dir)?;
+        /// Seconds to wait for the relay to answer each query
+        #[arg(long, default_value = "10")]
+        timeout: u64,
+        
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        
+        /// List the ids of mismatched events
+        #[arg(short, long)]
+        verbose: bool,
+    },
     
-    // Extract embedded tools once at startup
-    if !tools_dir.join("Cargo.toml").exists() {
-        use crate::embedded_cassette_tools::get_embedded_tools_dir;
-        let embedded_dir = get_embedded_tools_dir();
+    /// Write a cassette's events out as NDJSON, a JSON array or an SQLite database
+    Export {
+        /// Cassette file, or a <name>.segments.json manifest
+        cassette: Option<PathBuf>,
         
-        // Copy all files from embedded directory to tools_dir
-        let mut generator = generator::CassetteGenerator::new(
-            output_dir.clone(),
-            "temp",
-            &output_dir,
-        );
-        generator.copy_dir_contents(embedded_dir, &tools_dir)?;
-        println!("📦 Extracted embedded cassette-tools to {}", tools_dir.display());
-    }
+        /// Output file; its extension picks the format (.ndjson, .json, .db). Default: NDJSON on stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        
+        /// Output format (ndjson, json or sqlite), optionally followed by the output file
+        #[arg(long, num_args = 1..=2, value_names = ["FORMAT", "PATH"])]
+        to: Vec<String>,
+        
+        /// Filter JSON selecting the exported events
+        #[arg(short, long, value_name = "JSON")]
+        filter: Option<String>,
+        
+        /// Kinds to export (can be specified multiple times)
+        #[arg(short, long)]
+        kinds: Vec<i64>,
+        
+        /// Authors to export, as hex, npub or nprofile (can be specified multiple times)
+        #[arg(short, long)]
+        authors: Vec<String>,
+        
+        /// Since timestamp
+        #[arg(long)]
+        since: Option<i64>,
+        
+        /// Until timestamp
+        #[arg(long)]
+        until: Option<i64>,
+    },
     
-    Ok(tools_dir)
+    /// Statistics about a cassette's events
+    #[command(args_conflicts_with_subcommands = true)]
+    Stats {
+        #[command(subcommand)]
+        command: Option<StatsCommand>,
+        
+        /// Cassette file, or a <name>.segments.json manifest
+        cassette: Option<PathBuf>,
+        
+        /// Bucket width of the series: 1d or 1w
+        #[arg(long, default_value = "1d", value_parser = timeline::parse_bucket)]
+        bucket: timeline::Bucket,
+        
+        /// Authors, kinds and tag names listed
+        #[arg(long, default_value = "10")]
+        top: usize,
+        
+        /// Filter JSON selecting the counted events
+        #[arg(short, long, value_name = "JSON")]
+        filter: Option<String>,
+        
+        /// Kinds to count (can be specified multiple times)
+        #[arg(short, long)]
+        kinds: Vec<i64>,
+        
+        /// Authors to count, as hex, npub or nprofile (can be specified multiple times)
+        #[arg(short, long)]
+        authors: Vec<String>,
+        
+        /// Since timestamp
+        #[arg(long)]
+        since: Option<i64>,
+        
+        /// Until timestamp
+        #[arg(long)]
+        until: Option<i64>,
+        
+        /// Output format: json or csv
+        #[arg(short, long, default_value = "json")]
+        output: String,
+    },
 }
 
-/// Process the deck command in relay mode - run a writable relay with cassette backend
+#[derive(Subcommand)]
+enum StatsCommand {
+    /// Events per day or week, as terminal sparklines or JSON
+    Timeline {
+        /// Cassette file, or a <name>.segments.json manifest
+        cassette: PathBuf,
+        
+        /// Bucket width
+        #[arg(long, value_enum, default_value = "day")]
+        bucket: timeline::Bucket,
+        
+        /// Split the timeline into one series per kind or author
+        #[arg(long, value_enum)]
+        by: Option<timeline::GroupBy>,
+        
+        /// Series kept with --by, the rest are summed into "other"
+        #[arg(long, default_value = "10")]
+        top: usize,
+        
+        /// Filter JSON selecting the counted events
+        #[arg(short, long, value_name = "JSON")]
+        filter: Option<String>,
+        
+        /// Kinds to count (can be specified multiple times)
+        #[arg(short, long)]
+        kinds: Vec<i64>,
+        
+        /// Authors to count, as hex, npub or nprofile (can be specified multiple times)
+        #[arg(short, long)]
+        authors: Vec<String>,
+        
+        /// Since timestamp
+        #[arg(long)]
+        since: Option<i64>,
+        
+        /// Until timestamp
+        #[arg(long)]
+        until: Option<i64>,
+        
+        /// Print the timeline as JSON instead of sparklines
+        #[arg(long)]
+        json: bool,
+        
+        /// Sparkline width in columns; neighbouring buckets are merged to fit
+        #[arg(long, default_value = "60")]
+        width: usize,
+    },
+}
+
+/// Helper function to load a cassette and set its NIP-11 info if available
+fn load_cassette_with_nip11(
+    store: &mut Store<()>,
+    guest: &guest::GuestApi,
+    nip11_args: &Nip11Args,
+) -> Result<()> {
+    // Build RelayInfo from CLI arguments
+    let relay_info = nip11_args.relay_info()?;
+    
+    // Only cassettes exporting set_info take the fields
+    if let Some(result) = guest.set_info(store, &serde_json::to_string(&relay_info)?)? {
+        if result != 0 {
+            eprintln!("Warning: Failed to set NIP-11 info (error code: {})", result);
+        }
+    }
+    
+    Ok(())
+}
+
+
+/// A cassette's NIP-11 document with the fields given on the command line over it
+fn overlay_relay_info(info: &str, relay_info: &cassette_tools::nips::nip11::RelayInfo) -> String {
+    let Ok(Value::Object(mut document)) = serde_json::from_str::<Value>(info) else {
+        return info.to_string();
+    };
+    relay_info.overlay(&mut document);
+    Value::Object(document).to_string()
+}
+
+#[cfg(feature = "deck")]
+/// Initialize persistent embedded tools directory for deck mode
+fn init_embedded_tools_dir(output_dir: &PathBuf) -> Result<PathBuf> {
+    let tools_dir = output_dir.join(".cassette-tools-embedded");
+    fs::create_dir_all(&tools_dir)?;
+    
+    // Extract embedded tools once at startup
+    if !tools_dir.join("Cargo.toml").exists() {
+        use crate::embedded_cassette_tools::get_embedded_tools_dir;
+        let embedded_dir = get_embedded_tools_dir();
+        
+        // Copy all files from embedded directory to tools_dir
+        let mut generator = generator::CassetteGenerator::new(
+            output_dir.clone(),
+            "temp",
+            &output_dir,
+        );
+        generator.copy_dir_contents(embedded_dir, &tools_dir)?;
+        println!("📦 Extracted embedded cassette-tools to {}", tools_dir.display());
+    }
+    
+    Ok(tools_dir)
+}
+
+/// Process the deck command in relay mode - run a writable relay with cassette backend
 async fn process_deck_relay_mode(
     base_name: &str,
     output_dir: &PathBuf,
@@ -2232,19 +3855,28 @@ async fn process_deck_relay_mode(
     bind_address: &str,
     event_limit: usize,
     size_limit: usize,
+    max_archive_bytes: Option<u64>,
     duration: u64,
+    rotation_overlap: Duration,
     _nip_11: bool,
     nip_45: bool,
     nip_50: bool,
     verbose: bool,
-    skip_validation: bool,
+    policy: &validation::ValidationPolicy,
+    admin_socket: &std::path::Path,
+    index_store: deck_storage::Backend,
+    peers: &[String],
     nip11_args: &Nip11Args,
+    mute_args: &MuteArgs,
+    validator: Option<response_validation::ResponseValidator>,
+    shutdown: impl std::future::Future<Output = ()>,
 ) -> Result<()> {
     use std::sync::Arc;
     use tokio::sync::RwLock;
     use tokio::net::TcpListener;
     use std::time::SystemTime;
     
+    tokio::pin!(shutdown);
     println!("🎛️  Starting Cassette Deck in RELAY mode");
     println!("🌐 Accepting events on: {}:{}", bind_address, port);
     println!("📼 Output directory: {}", output_dir.display());
@@ -2265,8 +3897,16 @@ async fn process_deck_relay_mode(
         start_time: SystemTime::now(),
         current_size: 0,
         is_compiling: false,
+        received_at: HashMap::new(),
+        carried_over: HashSet::new(),
+        archived_bytes: sizes::cassette_dir_bytes(output_dir),
+        archive_budget: max_archive_bytes,
     }));
-    let _event_store = Arc::new(RwLock::new(DeckEventStore::new()));
+    let event_store = Arc::new(RwLock::new(DeckEventStore::new(deck_storage::open(index_store, output_dir)?)));
+    if index_store != deck_storage::Backend::Memory {
+        println!("🗂️  Event index: {} ({} archived ids)",
+            deck_storage::default_path(output_dir).display(), event_store.read().await.archived()?);
+    }
     
     // Load existing cassettes from output directory (both .cassette and .wasm for backwards compatibility)
     {
@@ -2341,6 +3981,23 @@ async fn process_deck_relay_mode(
         }
     }
     
+    let replicator = if peers.is_empty() {
+        None
+    } else {
+        let replicator = replication::Replicator::start(replication::load_deck_id(output_dir)?, peers, verbose);
+        println!("🔁 Deck {} replicating to {} peer(s)", replicator.deck_id(), peers.len());
+        Some(Arc::new(replicator))
+    };
+    
+    let mute_list = {
+        let cassettes = active_cassettes.read().await;
+        let paths: Vec<PathBuf> = cassettes.iter().map(|(path, _, _)| path.clone()).collect();
+        load_mute_list(mute_args, &paths)?
+    };
+    
+    let monitor = Arc::new(deck_status::DeckMonitor::new("relay", event_limit, size_limit, duration));
+    spawn_deck_admin_socket(admin_socket, monitor.clone(), recording_state.clone(), active_cassettes.clone()).await?;
+    
     // Start the WebSocket relay server
     let addr = format!("{}:{}", bind_address, port);
     let listener = TcpListener::bind(&addr).await?;
@@ -2353,6 +4010,8 @@ async fn process_deck_relay_mode(
         let output_dir = output_dir.clone();
         let base_name = base_name.to_string();
         let nip11_args = nip11_args.clone();
+        let monitor = monitor.clone();
+        let event_store = event_store.clone();
         #[cfg(feature = "deck")] 
         let embedded_tools_dir = embedded_tools_dir.clone();
         
@@ -2378,9 +4037,14 @@ async fn process_deck_relay_mode(
                         nip_50,
                         &nip11_args,
                         verbose,
+                        rotation_overlap,
+                        None,
+                        &event_store,
+                        &monitor,
                         #[cfg(feature = "deck")] &embedded_tools_dir,
                     ).await {
                         eprintln!("❌ Failed to rotate cassette: {}", e);
+                        monitor.record_error(format!("rotation failed: {}", e));
                     }
                 }
             }
@@ -2388,6 +4052,7 @@ async fn process_deck_relay_mode(
     };
     
     // Accept connections
+    let policy = Arc::new(policy.clone());
     loop {
         tokio::select! {
             Ok((stream, addr)) = listener.accept() => {
@@ -2396,34 +4061,57 @@ async fn process_deck_relay_mode(
                 }
                 let cassettes = active_cassettes.clone();
                 let recording = recording_state.clone();
-                let store = _event_store.clone();
-                let skip_val = skip_validation;
-                tokio::spawn(handle_deck_relay_connection(stream, cassettes, recording, store, skip_val, verbose));
+                let store = event_store.clone();
+                let replicator = replicator.clone();
+                let policy = policy.clone();
+                let mute_list = mute_list.clone();
+                let monitor = monitor.clone();
+                tokio::spawn(async move {
+                    let _connection = monitor.connection_opened();
+                    if let Err(e) = handle_deck_relay_connection(stream, cassettes, recording, store, replicator, mute_list, validator, policy, !rotation_overlap.is_zero(), verbose).await {
+                        monitor.record_error(format!("connection {}: {}", addr, e));
+                    }
+                });
             }
             _ = &mut rotation_handle => {
                 eprintln!("⚠️  Rotation handler stopped");
                 break;
             }
-            _ = tokio::signal::ctrl_c() => {
+            _ = &mut shutdown => {
                 println!("\n⏹️  Shutting down cassette deck...");
+                let _ = fs::remove_file(admin_socket);
+                rotation_handle.abort();
                 
-                // Final rotation if there are pending events
-                let state = recording_state.read().await;
-                if state.event_count > 0 {
-                    println!("💾 Saving final cassette...");
-                    drop(state);
-                    rotate_cassette(
-                        &recording_state,
-                        &active_cassettes,
-                        &output_dir,
-                        &base_name,
-                        _nip_11,
-                        nip_45,
-                        nip_50,
-                        &nip11_args,
-                        verbose,
-                        #[cfg(feature = "deck")] &embedded_tools_dir,
-                    ).await?;
+                // Rotations compile in the background: wait for the one in flight, then
+                // save whatever is still buffered
+                loop {
+                    let (compiling, pending) = {
+                        let state = recording_state.read().await;
+                        (state.is_compiling, state.event_count)
+                    };
+                    if !compiling && pending == 0 {
+                        break;
+                    }
+                    if !compiling {
+                        println!("💾 Saving final cassette...");
+                        rotate_cassette(
+                            &recording_state,
+                            &active_cassettes,
+                            &output_dir,
+                            &base_name,
+                            _nip_11,
+                            nip_45,
+                            nip_50,
+                            &nip11_args,
+                            verbose,
+                            Duration::ZERO,
+                            None,
+                            &event_store,
+                            &monitor,
+                            #[cfg(feature = "deck")] &embedded_tools_dir,
+                        ).await?;
+                    }
+                    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
                 }
                 break;
             }
@@ -2433,7 +4121,6 @@ async fn process_deck_relay_mode(
     Ok(())
 }
 
-
 // Helper function to check if an event exists in any loaded cassette using COUNT
 async fn check_event_exists_in_cassettes(
     cassettes: &Arc<RwLock<Vec<(PathBuf, Module, Engine)>>>,
@@ -2441,56 +4128,96 @@ async fn check_event_exists_in_cassettes(
 ) -> bool {
     let cassettes_guard = cassettes.read().await;
     
-    for (_path, module, engine) in cassettes_guard.iter() {
-        let mut store = Store::new(engine, ());
+    // Query each cassette for this specific event ID using COUNT
+    let count_msg = json!(["COUNT", "check-event", {"ids": [event_id]}]).to_string();
+    cassettes_guard.iter().any(|(path, module, engine)| {
+        guest::GuestApi::open(engine, module, path)
+            .and_then(|(mut store, guest)| guest.count(&mut store, &count_msg))
+            .map_or(false, |count| count.unwrap_or(0) > 0)
+    })
+}
+
+// Stream all events matching a REQ out of a single cassette module, re-sending the REQ until EOSE
+fn collect_cassette_events(
+    path: &std::path::Path,
+    module: &Module,
+    engine: &Engine,
+    sub_id: &str,
+    filters: &[Value],
+    verbose: bool,
+) -> Result<Vec<Value>> {
+    let mut events = Vec::new();
+    let mut store = Store::new(engine, ());
+    let instance = Instance::new(&mut store, module, &[])?;
+    
+    let Ok(guest) = guest::GuestApi::detect(&mut store, &instance) else {
+        return Ok(events);
+    };
+    guest.attach_payload(&mut store, path)?;
+    
+    // Keep querying this cassette until we get EOSE
+    let mut got_eose = false;
+    let mut consecutive_empty_responses = 0;
+    const MAX_EMPTY_RESPONSES: usize = 2;
+    
+    // Send initial REQ to establish subscription
+    let req_msg = if filters.is_empty() {
+        json!(["REQ", sub_id, {}])
+    } else {
+        let mut msg_arr = vec![json!("REQ"), json!(sub_id)];
+        msg_arr.extend(filters.iter().cloned());
+        json!(msg_arr)
+    };
+    
+    while !got_eose && consecutive_empty_responses < MAX_EMPTY_RESPONSES {
+        let events_before = events.len();
         
-        if let Ok(instance) = Instance::new(&mut store, module, &[]) {
-            // Try to query for this specific event ID using COUNT
-            if let Ok(send_func) = instance.get_typed_func::<(i32, i32), i32>(&mut store, "send")
-                .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req")) {
-                if let Ok(alloc_func) = instance.get_typed_func::<i32, i32>(&mut store, "alloc_buffer") {
-                    if let Some(memory) = instance.get_memory(&mut store, "memory") {
-                        let dealloc_func = instance.get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string").ok();
-                        
-                        // Create a COUNT query for this specific event ID
-                        let count_msg = json!(["COUNT", "check-event", {"ids": [event_id]}]);
-                        let msg_bytes = count_msg.to_string().into_bytes();
-                        
-                        if let Ok(msg_ptr) = alloc_func.call(&mut store, msg_bytes.len() as i32) {
-                            if msg_ptr != 0 {
-                                if memory.write(&mut store, msg_ptr as usize, &msg_bytes).is_ok() {
-                                    if let Ok(result_ptr) = send_func.call(&mut store, (msg_ptr, msg_bytes.len() as i32)) {
-                                        if let Some(dealloc) = &dealloc_func {
-                                            let _ = dealloc.call(&mut store, (msg_ptr, msg_bytes.len() as i32));
-                                        }
-                                        
-                                        if result_ptr != 0 {
-                                            if let Ok(result) = read_string_from_memory(&mut store, &instance, &memory, result_ptr) {
-                                                if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&result) {
-                                                    // Check if we got a COUNT response
-                                                    if parsed.len() >= 3 && parsed[0].as_str() == Some("COUNT") {
-                                                        if let Some(count_obj) = parsed.get(2) {
-                                                            if let Some(count) = count_obj.get("count").and_then(|c| c.as_u64()) {
-                                                                if count > 0 {
-                                                                    return true;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
+        // Debug: print what we're sending
+        if verbose {
+            println!("  🔍 Sending to cassette: {}", req_msg);
+        }
+        // For subsequent calls, just send the same REQ to continue streaming
+        let Some(result) = guest.send(&mut store, &req_msg.to_string())? else {
+            break;
+        };
+        
+        // Parse the response
+        if verbose {
+            println!("  🔍 Cassette response: {}", result);
+        }
+        
+        if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&result) {
+            if parsed.len() >= 2 {
+                match parsed[0].as_str() {
+                    Some("EVENT") => {
+                        if let Some(event) = parsed.get(2) {
+                            // Events were checked against the policy when the deck recorded them
+                            if verbose {
+                                println!("  📥 Collected event: {}", 
+                                    event.get("id").and_then(|i| i.as_str()).unwrap_or("?"));
                             }
+                            events.push(event.clone());
                         }
                     }
+                    Some("EOSE") => {
+                        got_eose = true;
+                    }
+                    _ => {
+                        // Ignore other messages during collection
+                    }
                 }
             }
         }
+        
+        // Check if we got any new events in this iteration
+        if events.len() == events_before {
+            consecutive_empty_responses += 1;
+        } else {
+            consecutive_empty_responses = 0;
+        }
     }
     
-    false
+    Ok(events)
 }
 
 // Helper function to handle relay mode connections (writable relay)
@@ -2498,8 +4225,12 @@ async fn handle_deck_relay_connection(
     stream: TcpStream,
     active_cassettes: Arc<RwLock<Vec<(PathBuf, Module, Engine)>>>,
     recording_state: Arc<RwLock<RecordingState>>,
-    _event_store: Arc<RwLock<DeckEventStore>>,
-    skip_validation: bool,
+    event_store: Arc<RwLock<DeckEventStore>>,
+    replicator: Option<Arc<replication::Replicator>>,
+    mute_list: Option<Arc<mute::MuteList>>,
+    validator: Option<response_validation::ResponseValidator>,
+    policy: Arc<validation::ValidationPolicy>,
+    overlapping_rotation: bool,
     verbose: bool,
 ) -> Result<()> {
     use tokio_tungstenite::tungstenite::Message;
@@ -2523,24 +4254,17 @@ async fn handle_deck_relay_connection(
             // Handle HTTP request for NIP-11
             let cassettes = active_cassettes.read().await;
             if let Some((_, module, engine)) = cassettes.first() {
-                let mut store = Store::new(engine, ());
-                let instance = Instance::new(&mut store, module, &[])?;
+                let (mut store, guest) = guest::GuestApi::instantiate(engine, module)?;
                 
-                if let Ok(info_func) = instance.get_typed_func::<(), i32>(&mut store, "info") {
-                    let info_ptr = info_func.call(&mut store, ())?;
-                    if info_ptr != 0 {
-                        let memory = instance.get_memory(&mut store, "memory").unwrap();
-                        let info_str = read_string_from_memory(&mut store, &instance, &memory, info_ptr)?;
-                        
-                        let response = format!(
-                            "HTTP/1.1 200 OK\r\nContent-Type: application/nostr+json\r\nContent-Length: {}\r\n\r\n{}",
-                            info_str.len(),
-                            info_str
-                        );
-                        
-                        stream.try_write(response.as_bytes())?;
-                        return Ok(());
-                    }
+                if let Some(info_str) = guest.info(&mut store)? {
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/nostr+json\r\nContent-Length: {}\r\n\r\n{}",
+                        info_str.len(),
+                        info_str
+                    );
+                    
+                    stream.try_write(response.as_bytes())?;
+                    return Ok(());
                 }
             }
             // If we can't provide NIP-11 info, return 404
@@ -2610,8 +4334,9 @@ async fn handle_deck_relay_connection(
                     "EVENT" => {
                         let event_start = std::time::Instant::now();
                         
-                        // EVENT messages must have exactly 2 elements: ["EVENT", event_object]
-                        if arr.len() != 2 {
+                        // EVENT messages have 2 elements: ["EVENT", event_object], plus the
+                        // provenance when another deck replicates to us
+                        if arr.len() != 2 && arr.len() != 3 {
                             let notice = json!(["NOTICE", "Invalid EVENT message: expected 2 elements"]);
                             write.send(Message::Text(notice.to_string())).await?;
                             continue;
@@ -2626,29 +4351,49 @@ async fn handle_deck_relay_connection(
                             }
                         };
                         
-                        // Validate event if needed
-                        if !skip_validation {
-                            let validation_start = std::time::Instant::now();
-                            if let Err(e) = validate_event(event) {
-                                let validation_duration = validation_start.elapsed();
-                                if verbose {
-                                    println!("⏱️  Event validation took: {:?} (failed)", validation_duration);
-                                }
-                                let notice = json!(["NOTICE", format!("Invalid event: {}", e)]);
+                        let via = match replication::parse_via(arr) {
+                            Ok(via) => via,
+                            Err(e) => {
+                                let notice = json!(["NOTICE", format!("Invalid EVENT: {}", e)]);
                                 write.send(Message::Text(notice.to_string())).await?;
                                 continue;
                             }
+                        };
+                        if replicator.as_ref().is_some_and(|r| r.has_seen(&via)) {
+                            // Our own event coming back around the peer loop
+                            let event_id = event.get("id").and_then(|i| i.as_str()).unwrap_or("");
+                            let ok_msg = json!(["OK", event_id, true, "duplicate: already replicated"]);
+                            write.send(Message::Text(ok_msg.to_string())).await?;
+                            continue;
+                        }
+                        
+                        let validation_start = std::time::Instant::now();
+                        if let Err(e) = policy.check(event) {
                             let validation_duration = validation_start.elapsed();
                             if verbose {
-                                println!("⏱️  Event validation took: {:?}", validation_duration);
+                                println!("⏱️  Event validation took: {:?} (failed)", validation_duration);
                             }
+                            let notice = json!(["NOTICE", format!("Invalid event: {}", e)]);
+                            write.send(Message::Text(notice.to_string())).await?;
+                            continue;
+                        }
+                        let validation_duration = validation_start.elapsed();
+                        if verbose {
+                            println!("⏱️  Event validation took: {:?}", validation_duration);
                         }
                         
                         let event_id = event.get("id").and_then(|i| i.as_str()).unwrap_or("");
                         
-                        // First check if event exists in cassettes
+                        // First check the event index, then the cassettes it doesn't cover
                         let cassette_check_start = std::time::Instant::now();
-                        let exists_in_cassettes = check_event_exists_in_cassettes(&active_cassettes, event_id).await;
+                        let indexed = match event_store.read().await.contains(event_id) {
+                            Ok(indexed) => indexed,
+                            Err(e) => {
+                                eprintln!("⚠️  Event index lookup failed: {}", e);
+                                false
+                            }
+                        };
+                        let exists_in_cassettes = indexed || check_event_exists_in_cassettes(&active_cassettes, event_id).await;
                         let cassette_check_duration = cassette_check_start.elapsed();
                         
                         if verbose {
@@ -2672,16 +4417,31 @@ async fn handle_deck_relay_connection(
                             continue;
                         }
                         
+                        if recording_state.read().await.archive_full() {
+                            let ok_msg = json!(["OK", event_id, false, "blocked: archive size limit reached"]);
+                            write.send(Message::Text(ok_msg.to_string())).await?;
+                            continue;
+                        }
+                        
                         // Try to add event to the store
                         let store_start = std::time::Instant::now();
-                        let (added, replaced) = {
-                            let mut store = _event_store.write().await;
-                            store.add_event(event.clone())
+                        let stored = {
+                            let mut store = event_store.write().await;
+                            store.add_event(event, &replication::origin(&via))
+                        };
+                        let (added, replaced) = match stored {
+                            Ok(stored) => stored,
+                            Err(e) => {
+                                eprintln!("❌ Event index update failed: {}", e);
+                                let ok_msg = json!(["OK", event_id, false, "error: could not index event"]);
+                                write.send(Message::Text(ok_msg.to_string())).await?;
+                                continue;
+                            }
                         };
                         let store_duration = store_start.elapsed();
                         
                         if verbose {
-                            println!("⏱️  In-memory store operation took: {:?}", store_duration);
+                            println!("⏱️  Event index operation took: {:?}", store_duration);
                         }
                         
                         if !added {
@@ -2714,14 +4474,17 @@ async fn handle_deck_relay_connection(
                             // Add to recording state
                             let mut state = recording_state.write().await;
                             state.current_events.push(event.clone());
-                            state.event_count = state.current_events.len();
-                            state.current_size = state.current_events.iter()
-                                .map(|e| serde_json::to_string(e).unwrap_or_default().len())
-                                .sum();
+                            state.received_at.insert(event_id.to_string(), SystemTime::now());
+                            state.recount();
                             
                             if verbose {
                                 println!("📥 EVENT received: {} (total: {})", event_id, state.event_count);
                             }
+                            drop(state);
+                            
+                            if let Some(replicator) = &replicator {
+                                replicator.forward(event, &via);
+                            }
                             
                             // Send OK response with true for successful add
                             let ok_msg = json!(["OK", event_id, true, ""]);
@@ -2764,6 +4527,7 @@ async fn handle_deck_relay_connection(
                         
                         // Validate filters
                         let filters = &arr[2..];
+                        let parsed_filters = parse_filters(filters);
                         for (i, filter) in filters.iter().enumerate() {
                             if !filter.is_object() {
                                 let notice = json!(["NOTICE", format!("Invalid REQ: filter {} must be an object", i + 1)]);
@@ -2794,7 +4558,7 @@ async fn handle_deck_relay_connection(
                         
                         // Add matching events from buffer
                         for event in &current_events {
-                            if event_matches_filters(event, filters) {
+                            if Filter::matches_any(&parsed_filters, event) {
                                 all_collected_events.push(event.clone());
                             }
                         }
@@ -2816,124 +4580,18 @@ async fn handle_deck_relay_connection(
                             if verbose {
                                 println!("📖 Querying cassette {}: {}", path_idx, path.display());
                             }
-                            let mut store = Store::new(engine, ());
-                            let instance = Instance::new(&mut store, module, &[])?;
+                            let mut cassette_events = collect_cassette_events(path, module, engine, sub_id, filters, verbose)?;
+                            if let Some(validator) = &validator {
+                                let source = path.display().to_string();
+                                cassette_events.retain(|event| validator.admit(&source, event, filters));
+                            }
                             
-                            if let Ok(send_func) = instance.get_typed_func::<(i32, i32), i32>(&mut store, "send")
-                .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req")) {
-                                if let Ok(alloc_func) = instance.get_typed_func::<i32, i32>(&mut store, "alloc_buffer") {
-                                    let memory = instance.get_memory(&mut store, "memory").unwrap();
-                                    let dealloc_func = instance.get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string").ok();
-                                    
-                                    // Keep querying this cassette until we get EOSE
-                                    let mut got_eose = false;
-                                    let mut cassette_events = 0;
-                                    let mut consecutive_empty_responses = 0;
-                                    const MAX_EMPTY_RESPONSES: usize = 2;
-                                    
-                                    // Send initial REQ to establish subscription
-                                    let req_msg = if filters.is_empty() {
-                                        json!(["REQ", sub_id, {}])
-                                    } else {
-                                        let mut msg_arr = vec![json!("REQ"), json!(sub_id)];
-                                        msg_arr.extend(filters.iter().cloned());
-                                        json!(msg_arr)
-                                    };
-                                    
-                                    while !got_eose && consecutive_empty_responses < MAX_EMPTY_RESPONSES {
-                                        let events_before = cassette_events;
-                                        // For subsequent calls, just send the same REQ to continue streaming
-                                        let msg_bytes = req_msg.to_string().into_bytes();
-                                        
-                                        // Debug: print what we're sending
-                                        if verbose {
-                                            println!("  🔍 Sending to cassette: {}", req_msg);
-                                        }
-                                        let msg_ptr = alloc_func.call(&mut store, msg_bytes.len() as i32)?;
-                                        
-                                        if msg_ptr == 0 {
-                                            break;
-                                        }
-                                        
-                                        memory.write(&mut store, msg_ptr as usize, &msg_bytes)?;
-                                        let result_ptr = send_func.call(&mut store, (msg_ptr, msg_bytes.len() as i32))?;
-                                        
-                                        if let Some(dealloc) = &dealloc_func {
-                                            dealloc.call(&mut store, (msg_ptr, msg_bytes.len() as i32))?;
-                                        }
-                                        
-                                        if result_ptr == 0 {
-                                            break;
-                                        }
-                                        
-                                        let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
-                                        
-                                        // Try to deallocate the result pointer
-                                        if let Ok(get_size_func) = instance.get_typed_func::<i32, i32>(&mut store, "get_allocation_size") {
-                                            if let Ok(size) = get_size_func.call(&mut store, result_ptr) {
-                                                if let Some(dealloc) = &dealloc_func {
-                                                    let _ = dealloc.call(&mut store, (result_ptr, size));
-                                                }
-                                            }
-                                        }
-                                        
-                                        // Parse the response
-                                        if verbose {
-                                            println!("  🔍 Cassette response: {}", result);
-                                        }
-                                        
-                                        if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&result) {
-                                            if parsed.len() >= 2 {
-                                                match parsed[0].as_str() {
-                                                    Some("EVENT") => {
-                                                        if parsed.len() >= 3 {
-                                                            if let Some(event) = parsed.get(2) {
-                                                                // Validate event if needed
-                                                                if !skip_validation {
-                                                                    if let Err(e) = validate_event(event) {
-                                                                        if verbose {
-                                                                            println!("⚠️  Skipping invalid event from cassette: {}", e);
-                                                                        }
-                                                                        continue;
-                                                                    }
-                                                                }
-                                                                
-                                                                // Collect the event
-                                                                all_collected_events.push(event.clone());
-                                                                cassette_events += 1;
-                                                                
-                                                                if verbose {
-                                                                    println!("  📥 Collected event: {}", 
-                                                                        event.get("id").and_then(|i| i.as_str()).unwrap_or("?"));
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    Some("EOSE") => {
-                                                        got_eose = true;
-                                                    }
-                                                    _ => {
-                                                        // Ignore other messages during collection
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        
-                                        // Check if we got any new events in this iteration
-                                        if cassette_events == events_before {
-                                            consecutive_empty_responses += 1;
-                                        } else {
-                                            consecutive_empty_responses = 0;
-                                        }
-                                    }
-                                    
-                                    if verbose && cassette_events > 0 {
-                                        println!("📊 Found {} events in cassette {}", cassette_events, path_idx);
-                                    }
-                                    
-                                    total_cassette_events += cassette_events;
-                                }
+                            if verbose && !cassette_events.is_empty() {
+                                println!("📊 Found {} events in cassette {}", cassette_events.len(), path_idx);
                             }
+                            
+                            total_cassette_events += cassette_events.len();
+                            all_collected_events.extend(cassette_events);
                         }
                         
                         let cassette_query_duration = cassette_query_start.elapsed();
@@ -2946,105 +4604,36 @@ async fn handle_deck_relay_connection(
                             println!("📊 Total collected events before deduplication: {}", all_collected_events.len());
                         }
                         
-                        // 3. Apply deduplication for replaceable events
-                        let mut events_by_id: HashMap<String, Value> = HashMap::new();
-                        let mut replaceable_events: HashMap<(String, i64), String> = HashMap::new();
-                        let mut param_replaceable_events: HashMap<(String, i64, String), String> = HashMap::new();
-                        
-                        for event in all_collected_events {
-                            let event_id = event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
-                            let event_pubkey = event.get("pubkey").and_then(|p| p.as_str()).unwrap_or("").to_string();
-                            let event_kind = event.get("kind").and_then(|k| k.as_i64()).unwrap_or(0);
-                            let event_created_at = event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0);
-                            
-                            if event_id.is_empty() || event_pubkey.is_empty() {
-                                continue; // Skip invalid events
-                            }
-                            
-                            // Check if it's a replaceable event
-                            if event_kind == 0 || event_kind == 3 || (event_kind >= 10000 && event_kind <= 19999) {
-                                let key = (event_pubkey.clone(), event_kind);
-                                if let Some(existing_id) = replaceable_events.get(&key) {
-                                    if let Some(existing_event) = events_by_id.get(existing_id) {
-                                        if let Some(existing_created_at) = existing_event.get("created_at").and_then(|t| t.as_i64()) {
-                                            if event_created_at > existing_created_at {
-                                                // Replace with newer event
-                                                events_by_id.remove(existing_id);
-                                                events_by_id.insert(event_id.clone(), event);
-                                                replaceable_events.insert(key, event_id);
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    events_by_id.insert(event_id.clone(), event);
-                                    replaceable_events.insert(key, event_id);
-                                }
-                            }
-                            // Check if it's a parameterized replaceable event
-                            else if event_kind >= 30000 && event_kind <= 39999 {
-                                let d_tag = event.get("tags")
-                                    .and_then(|tags| tags.as_array())
-                                    .and_then(|tags_array| {
-                                        tags_array.iter()
-                                            .find(|tag| tag.as_array()
-                                                .and_then(|t| t.get(0).and_then(|t0| t0.as_str()))
-                                                .unwrap_or("") == "d"
-                                            )
-                                            .and_then(|tag| tag.as_array())
-                                            .and_then(|tag_array| tag_array.get(1).and_then(|t1| t1.as_str()))
-                                    })
-                                    .unwrap_or("")
-                                    .to_string();
-                                
-                                let key = (event_pubkey.clone(), event_kind, d_tag);
-                                if let Some(existing_id) = param_replaceable_events.get(&key) {
-                                    if let Some(existing_event) = events_by_id.get(existing_id) {
-                                        if let Some(existing_created_at) = existing_event.get("created_at").and_then(|t| t.as_i64()) {
-                                            if event_created_at > existing_created_at {
-                                                // Replace with newer event
-                                                events_by_id.remove(existing_id);
-                                                events_by_id.insert(event_id.clone(), event);
-                                                param_replaceable_events.insert(key, event_id);
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    events_by_id.insert(event_id.clone(), event);
-                                    param_replaceable_events.insert(key, event_id);
-                                }
+                        // 3. Drop duplicates and older versions of replaceable events
+                        let mut seen_ids = HashSet::new();
+                        all_collected_events.retain(|event| {
+                            match (event.get("id").and_then(|i| i.as_str()), event.get("pubkey").and_then(|p| p.as_str())) {
+                                (Some(id), Some(pubkey)) if !id.is_empty() && !pubkey.is_empty() => seen_ids.insert(id.to_string()),
+                                _ => false, // Skip invalid events
                             }
-                            // Regular event - just check for duplicates
-                            else {
-                                events_by_id.entry(event_id).or_insert(event);
-                            }
-                        }
-                        
-                        // Convert back to a vector
-                        let mut final_events: Vec<Value> = events_by_id.into_values().collect();
+                        });
+                        let mut final_events = replaceable::latest_versions(all_collected_events);
                         
                         if verbose {
                             println!("📊 Events after deduplication: {}", final_events.len());
                         }
                         
-                        // 4. Sort events by created_at (newest first) 
-                        final_events.sort_by(|a, b| {
-                            let a_time = a.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0);
-                            let b_time = b.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0);
-                            b_time.cmp(&a_time)
-                        });
-                        
-                        // 5. Apply limit if specified in any filter
-                        let mut max_limit: Option<u64> = None;
-                        for filter in filters {
-                            if let Some(limit) = filter.get("limit").and_then(|l| l.as_u64()) {
-                                max_limit = Some(max_limit.map_or(limit, |m: u64| m.max(limit)));
+                        // Drop muted content before the limit so it doesn't eat into the page
+                        if let Some(mute) = &mute_list {
+                            final_events.retain(|event| !mute.is_muted(event));
+                            if verbose {
+                                println!("🔇 Events after mute list: {}", final_events.len());
                             }
                         }
                         
-                        if let Some(limit) = max_limit {
-                            final_events.truncate(limit as usize);
+                        // 4. Sort events newest first, lowest id first within the same second (NIP-01)
+                        final_events.sort_by(cassette_tools::newest_first);
+                        
+                        // 5. Apply each filter's limit to its own newest matches
+                        if parsed_filters.iter().any(|filter| filter.limit.is_some()) {
+                            final_events = Filter::select(&parsed_filters, final_events);
                             if verbose {
-                                println!("📊 Applied limit of {}, final event count: {}", limit, final_events.len());
+                                println!("📊 Applied per-filter limits, final event count: {}", final_events.len());
                             }
                         }
                         
@@ -3117,6 +4706,7 @@ async fn handle_deck_relay_connection(
                         };
                         
                         let filters = &arr[2..];
+                        let parsed_filters = parse_filters(filters);
                         for (i, filter) in filters.iter().enumerate() {
                             if !filter.is_object() {
                                 let notice = json!(["NOTICE", format!("Invalid COUNT: filter {} must be an object", i + 1)]);
@@ -3130,6 +4720,9 @@ async fn handle_deck_relay_connection(
                         }
                                     
                                     let mut total_count = 0;
+                                    // Ids of the events counted one by one; overlapping rotations put the
+                                    // same event in the buffer and a cassette, or in two cassettes
+                                    let mut counted = HashSet::new();
                                     
                                     // Count in current buffer
                                     let current_events = {
@@ -3138,62 +4731,48 @@ async fn handle_deck_relay_connection(
                                     };
                                     
                                     for event in &current_events {
-                                        if event_matches_filters(event, filters) {
-                                            total_count += 1;
+                                        if Filter::matches_any(&parsed_filters, event)
+                                            && mute_list.as_ref().map_or(true, |mute| !mute.is_muted(event)) {
+                                            counted.insert(event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string());
                                         }
                                     }
                                     
                                     // Count in cassettes
                                     let cassettes = active_cassettes.read().await;
-                                    for (_path, module, engine) in cassettes.iter() {
+                                    for (path, module, engine) in cassettes.iter() {
+                                        // A cassette's own COUNT can't see the mute list, the response
+                                        // validator or other cassettes, so count the admitted matches by id instead
+                                        if mute_list.is_some() || validator.is_some() || overlapping_rotation {
+                                            let events = collect_cassette_events(path, module, engine, sub_id, filters, verbose)?;
+                                            let source = path.display().to_string();
+                                            counted.extend(events.iter()
+                                                .filter(|event| mute_list.as_ref().map_or(true, |mute| !mute.is_muted(event)))
+                                                .filter(|event| validator.map_or(true, |v| v.admit(&source, event, filters)))
+                                                .filter_map(|event| event.get("id").and_then(|i| i.as_str()).map(|id| id.to_string())));
+                                            continue;
+                                        }
+                                        
                                         let mut store = Store::new(engine, ());
                                         let instance = Instance::new(&mut store, module, &[])?;
                                         
                                         // Send COUNT to cassette
-                                        if let Ok(send_func) = instance.get_typed_func::<(i32, i32), i32>(&mut store, "send")
-                .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req")) {
-                                            if let Ok(alloc_func) = instance.get_typed_func::<i32, i32>(&mut store, "alloc_buffer") {
-                                                let memory = instance.get_memory(&mut store, "memory").unwrap();
-                                                let dealloc_func = instance.get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string").ok();
-                                                
-                                                let count_msg = if filters.is_empty() {
-                                                    json!(["COUNT", sub_id, {}])
-                                                } else {
-                                                    let mut msg_arr = vec![json!("COUNT"), json!(sub_id)];
-                                                    msg_arr.extend(filters.iter().cloned());
-                                                    json!(msg_arr)
-                                                };
-                                                let msg_bytes = count_msg.to_string().into_bytes();
-                                                let msg_ptr = alloc_func.call(&mut store, msg_bytes.len() as i32)?;
-                                                
-                                                if msg_ptr != 0 {
-                                                    memory.write(&mut store, msg_ptr as usize, &msg_bytes)?;
-                                                    let result_ptr = send_func.call(&mut store, (msg_ptr, msg_bytes.len() as i32))?;
-                                                    
-                                                    if let Some(dealloc) = &dealloc_func {
-                                                        dealloc.call(&mut store, (msg_ptr, msg_bytes.len() as i32))?;
-                                                    }
-                                                    
-                                                    if result_ptr != 0 {
-                                                        let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
-                                                        
-                                                        // Parse count response
-                                                        if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&result) {
-                                                            if parsed.len() >= 3 && parsed[0].as_str() == Some("COUNT") {
-                                                                if let Some(count_obj) = parsed[2].as_object() {
-                                                                    if let Some(count) = count_obj.get("count").and_then(|c| c.as_u64()) {
-                                                                        total_count += count as usize;
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
+                                        if let Ok(guest) = guest::GuestApi::detect(&mut store, &instance) {
+                                            guest.attach_payload(&mut store, path)?;
+                                            let count_msg = if filters.is_empty() {
+                                                json!(["COUNT", sub_id, {}])
+                                            } else {
+                                                let mut msg_arr = vec![json!("COUNT"), json!(sub_id)];
+                                                msg_arr.extend(filters.iter().cloned());
+                                                json!(msg_arr)
+                                            };
+                                            if let Some(count) = guest.count(&mut store, &count_msg.to_string())? {
+                                                total_count += count as usize;
                                             }
                                         }
                                     }
                                     
-                                    let count_msg = json!(["COUNT", sub_id, {"count": total_count}]);
+                                    total_count += counted.len();
+                                    let count_msg = json!(["COUNT", sub_id, {"count": total_count, "approximate": false}]);
                                     let count_msg_str = count_msg.to_string();
                                     
                                     if verbose {
@@ -3235,157 +4814,9 @@ async fn handle_deck_relay_connection(
     Ok(())
 }
 
-// Helper function to check if an event matches filters
-fn event_matches_filters(event: &Value, filters: &[Value]) -> bool {
-    // If no filters provided, match nothing
-    if filters.is_empty() {
-        return false;
-    }
-    
-    // NIP-01: filters are OR'd together (any filter match = include event)
-    for filter in filters {
-        if let Some(filter_obj) = filter.as_object() {
-            let mut matches = true;
-            
-            // Check ids (exact match)
-            if let Some(ids) = filter_obj.get("ids").and_then(|i| i.as_array()) {
-                if let Some(event_id) = event.get("id").and_then(|i| i.as_str()) {
-                    let id_matches = ids.iter().any(|id| {
-                        if let Some(id_prefix) = id.as_str() {
-                            event_id.starts_with(id_prefix)
-                        } else {
-                            false
-                        }
-                    });
-                    if !id_matches {
-                        matches = false;
-                    }
-                } else {
-                    matches = false;
-                }
-            }
-            
-            // Check kinds
-            if matches && filter_obj.contains_key("kinds") {
-                if let Some(kinds) = filter_obj.get("kinds").and_then(|k| k.as_array()) {
-                    if let Some(event_kind) = event.get("kind").and_then(|k| k.as_i64()) {
-                        if !kinds.iter().any(|k| k.as_i64() == Some(event_kind)) {
-                            matches = false;
-                        }
-                    } else {
-                        matches = false;
-                    }
-                }
-            }
-            
-            // Check authors (prefix match)
-            if matches && filter_obj.contains_key("authors") {
-                if let Some(authors) = filter_obj.get("authors").and_then(|a| a.as_array()) {
-                    if let Some(event_author) = event.get("pubkey").and_then(|p| p.as_str()) {
-                        let author_matches = authors.iter().any(|a| {
-                            if let Some(author_prefix) = a.as_str() {
-                                event_author.starts_with(author_prefix)
-                            } else {
-                                false
-                            }
-                        });
-                        if !author_matches {
-                            matches = false;
-                        }
-                    } else {
-                        matches = false;
-                    }
-                }
-            }
-            
-            // Check since
-            if matches && filter_obj.contains_key("since") {
-                if let Some(since) = filter_obj.get("since").and_then(|s| s.as_i64()) {
-                    if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-                        if created_at < since {
-                            matches = false;
-                        }
-                    } else {
-                        matches = false;
-                    }
-                }
-            }
-            
-            // Check until
-            if matches && filter_obj.contains_key("until") {
-                if let Some(until) = filter_obj.get("until").and_then(|u| u.as_i64()) {
-                    if let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) {
-                        if created_at > until {
-                            matches = false;
-                        }
-                    } else {
-                        matches = false;
-                    }
-                }
-            }
-            
-            // Check tag filters (e.g., #e, #p, #t)
-            if matches {
-                for (key, values) in filter_obj {
-                    if key.starts_with('#') && key.len() == 2 {
-                        let tag_name = &key[1..];
-                        if let Some(filter_values) = values.as_array() {
-                            if let Some(event_tags) = event.get("tags").and_then(|t| t.as_array()) {
-                                let tag_matches = filter_values.iter().any(|filter_value| {
-                                    if let Some(filter_str) = filter_value.as_str() {
-                                        event_tags.iter().any(|tag| {
-                                            if let Some(tag_array) = tag.as_array() {
-                                                tag_array.len() >= 2 &&
-                                                tag_array[0].as_str() == Some(tag_name) &&
-                                                tag_array[1].as_str() == Some(filter_str)
-                                            } else {
-                                                false
-                                            }
-                                        })
-                                    } else {
-                                        false
-                                    }
-                                });
-                                if !tag_matches {
-                                    matches = false;
-                                }
-                            } else {
-                                matches = false;
-                            }
-                        }
-                    }
-                }
-            }
-            
-            // If all conditions in this filter match, the event matches
-            if matches {
-                return true;
-            }
-        }
-    }
-    
-    // No filters matched
-    false
-}
-
-// Helper function to validate an event
-fn validate_event(event: &Value) -> Result<()> {
-    // Basic validation
-    if !event.is_object() {
-        return Err(anyhow!("Event must be an object"));
-    }
-    
-    // Check required fields
-    let required_fields = ["id", "pubkey", "created_at", "kind", "tags", "content", "sig"];
-    for field in &required_fields {
-        if event.get(field).is_none() {
-            return Err(anyhow!("Missing required field: {}", field));
-        }
-    }
-    
-    // TODO: Add signature verification
-    
-    Ok(())
+/// Parse the filters of a REQ or COUNT; filters that don't parse match nothing
+fn parse_filters(filters: &[Value]) -> Vec<Filter> {
+    filters.iter().filter_map(|filter| Filter::from_json(filter).ok()).collect()
 }
 
 /// Process the deck command in record mode - continuously record from relays and serve cassettes
@@ -3397,7 +4828,9 @@ async fn process_deck_record_mode(
     bind_address: &str,
     event_limit: usize,
     size_limit: usize,
+    max_archive_bytes: Option<u64>,
     duration: u64,
+    rotation_overlap: Duration,
     filter_json: Option<&str>,
     kinds: &[i64],
     authors: &[String],
@@ -3405,14 +4838,35 @@ async fn process_deck_record_mode(
     nip_45: bool,
     nip_50: bool,
     verbose: bool,
-    _skip_validation: bool,
+    policy: &validation::ValidationPolicy,
+    admin_socket: &std::path::Path,
+    checkpoint_path: &std::path::Path,
+    index_store: deck_storage::Backend,
     nip11_args: &Nip11Args,
+    mute_args: &MuteArgs,
+    validator: Option<response_validation::ResponseValidator>,
 ) -> Result<()> {
     use std::sync::Arc;
     use tokio::sync::RwLock;
     use tokio::net::TcpListener;
     use std::time::SystemTime;
     
+    // Relays, authors and filter values may be given as NIP-19 entities
+    let relay_urls = &nip19::resolve_relays(relay_urls)?;
+    let authors = &authors.iter()
+        .map(|author| nip19::normalize_pubkey(author))
+        .collect::<Result<Vec<_>>>()?;
+    let filter_json = match filter_json {
+        Some(json) => {
+            let mut filter: serde_json::Map<String, Value> = serde_json::from_str(json)
+                .context("Failed to parse filter JSON")?;
+            nip19::normalize_filter(&mut filter)?;
+            Some(Value::Object(filter).to_string())
+        }
+        None => None,
+    };
+    let filter_json = filter_json.as_deref();
+    
     println!("🎛️  Starting Cassette Deck");
     println!("📡 Recording from: {:?}", relay_urls);
     println!("🌐 Serving on: {}:{}", bind_address, port);
@@ -3433,20 +4887,44 @@ async fn process_deck_record_mode(
         start_time: SystemTime::now(),
         current_size: 0,
         is_compiling: false,
+        received_at: HashMap::new(),
+        carried_over: HashSet::new(),
+        archived_bytes: sizes::cassette_dir_bytes(output_dir),
+        archive_budget: max_archive_bytes,
     }));
-    let _event_store = Arc::new(RwLock::new(DeckEventStore::new()));
+    let event_store = Arc::new(RwLock::new(DeckEventStore::new(deck_storage::open(index_store, output_dir)?)));
+    if index_store != deck_storage::Backend::Memory {
+        println!("🗂️  Event index: {} ({} archived ids)",
+            deck_storage::default_path(output_dir).display(), event_store.read().await.archived()?);
+    }
+    
+    // Record mode starts without cassettes, so the mute list has to come from a file
+    let mute_list = load_mute_list(mute_args, &[])?;
+    
+    let cursors = Arc::new(checkpoint::RelayCursors::load(checkpoint_path)?);
+    
+    let monitor = Arc::new(deck_status::DeckMonitor::new("record", event_limit, size_limit, duration));
+    spawn_deck_admin_socket(admin_socket, monitor.clone(), recording_state.clone(), active_cassettes.clone()).await?;
     
     // Start the WebSocket server
     let server_handle = {
         let active_cassettes = active_cassettes.clone();
         let addr = format!("{}:{}", bind_address, port);
+        let monitor = monitor.clone();
         tokio::spawn(async move {
             let listener = TcpListener::bind(&addr).await?;
             println!("🌐 Deck server listening on ws://{}", addr);
             
-            while let Ok((stream, _)) = listener.accept().await {
+            while let Ok((stream, peer)) = listener.accept().await {
                 let cassettes = active_cassettes.clone();
-                tokio::spawn(handle_deck_connection(stream, cassettes));
+                let mute_list = mute_list.clone();
+                let monitor = monitor.clone();
+                tokio::spawn(async move {
+                    let _connection = monitor.connection_opened();
+                    if let Err(e) = handle_deck_connection(stream, cassettes, mute_list, validator).await {
+                        monitor.record_error(format!("connection {}: {}", peer, e));
+                    }
+                });
             }
             
             Ok::<(), anyhow::Error>(())
@@ -3464,8 +4942,15 @@ async fn process_deck_record_mode(
         let output_dir = output_dir.to_path_buf();
         let base_name = base_name.to_string();
         let nip11_args = nip11_args.clone();
+        let monitor = monitor.clone();
+        let cursors = cursors.clone();
+        let event_store = event_store.clone();
+        let policy = policy.clone();
         tokio::spawn(async move {
-            let mut relay_since_timestamps: HashMap<String, i64> = HashMap::new();
+            // Pick up where the previous run's last rotation left off
+            let mut relay_since_timestamps: HashMap<String, i64> = relay_urls.iter()
+                .filter_map(|url| cursors.resume_from(url).map(|cursor| (url.clone(), cursor.created_at)))
+                .collect();
             
             loop {
                 // Connect to relays and start recording
@@ -3479,6 +4964,9 @@ async fn process_deck_record_mode(
                     let kinds = kinds.to_vec();
                     let authors = authors.iter().cloned().collect::<Vec<_>>();
                     let since = relay_since_timestamps.get(&url).copied();
+                    let cursors = cursors.clone();
+                    let event_store = event_store.clone();
+                    let policy = policy.clone();
                     
                     let handle = tokio::spawn(async move {
                         record_from_relay(
@@ -3488,6 +4976,10 @@ async fn process_deck_record_mode(
                             &kinds,
                             &authors,
                             since,
+                            &cursors,
+                            &event_store,
+                            &policy,
+                            verbose,
                         ).await
                     });
                     handles.push((url_for_handle, handle));
@@ -3500,6 +4992,9 @@ async fn process_deck_record_mode(
                     let output_dir = output_dir.clone();
                     let base_name = base_name.clone();
                     let nip11_args = nip11_args.clone();
+                    let monitor = monitor.clone();
+                    let cursors = cursors.clone();
+                    let event_store = event_store.clone();
                     #[cfg(feature = "deck")]
                     let embedded_tools_dir = embedded_tools_dir.clone();
                     
@@ -3526,9 +5021,14 @@ async fn process_deck_record_mode(
                                     nip_50,
                                     &nip11_args,
                                     verbose,
+                                    rotation_overlap,
+                                    Some(&cursors),
+                                    &event_store,
+                                    &monitor,
                                     #[cfg(feature = "deck")] &embedded_tools_dir,
                                 ).await {
                                     eprintln!("❌ Failed to rotate cassette: {}", e);
+                                    monitor.record_error(format!("rotation failed: {}", e));
                                 }
                             }
                         }
@@ -3547,9 +5047,11 @@ async fn process_deck_record_mode(
                                 }
                                 Ok(Err(e)) => {
                                     eprintln!("⚠️  Error from {}: {}", url, e);
+                                    monitor.record_error(format!("relay {}: {}", url, e));
                                 }
                                 Err(e) => {
                                     eprintln!("⚠️  Task error for {}: {}", url, e);
+                                    monitor.record_error(format!("relay {}: {}", url, e));
                                 }
                             }
                         }
@@ -3577,6 +5079,7 @@ async fn process_deck_record_mode(
             println!("\n⏹️  Shutting down cassette deck...");
         }
     }
+    let _ = fs::remove_file(admin_socket);
     
     Ok(())
 }
@@ -3588,106 +5091,134 @@ struct RecordingState {
     start_time: SystemTime,
     current_size: usize,
     is_compiling: bool,
+    // When each buffered event arrived, by id, for rotation overlap
+    received_at: HashMap<String, SystemTime>,
+    // Buffered events already written to a cassette by an overlapping rotation; they don't
+    // count towards the rotation limits and are released by the next rotation
+    carried_over: HashSet<String>,
+    // Bytes of the cassettes on disk, and the most they plus the buffer may take
+    archived_bytes: u64,
+    archive_budget: Option<u64>,
+}
+
+impl RecordingState {
+    /// Whether the archive budget leaves no room for more events
+    fn archive_full(&self) -> bool {
+        self.archive_budget.map_or(false, |budget| self.archived_bytes + self.current_size as u64 >= budget)
+    }
+    
+    /// Remove the events a rotation compiled from the buffer. Events that arrived while it was
+    /// compiling are kept, and so are compiled events received within `overlap` of the cut,
+    /// which end up in the next cassette as well
+    fn release_rotated(&mut self, rotated: &[Value], cut: SystemTime, overlap: Duration) {
+        let keep_from = cut.checked_sub(overlap).unwrap_or(UNIX_EPOCH);
+        let mut carried_over = HashSet::new();
+        for event in rotated {
+            let Some(id) = event.get("id").and_then(|i| i.as_str()) else { continue };
+            let recent = self.received_at.get(id).map_or(false, |at| *at >= keep_from);
+            if recent && !self.carried_over.contains(id) {
+                carried_over.insert(id.to_string());
+            }
+        }
+        let rotated_ids: HashSet<&str> = rotated.iter()
+            .filter_map(|e| e.get("id").and_then(|i| i.as_str()))
+            .collect();
+        self.current_events.retain(|e| {
+            let id = e.get("id").and_then(|i| i.as_str()).unwrap_or("");
+            !rotated_ids.contains(id) || carried_over.contains(id)
+        });
+        let remaining: HashSet<String> = self.current_events.iter()
+            .filter_map(|e| e.get("id").and_then(|i| i.as_str()).map(|id| id.to_string()))
+            .collect();
+        self.received_at.retain(|id, _| remaining.contains(id));
+        self.carried_over.retain(|id| remaining.contains(id));
+        self.carried_over.extend(carried_over);
+        self.recount();
+    }
+
+    /// Recompute the rotation counters from the buffered events not yet written to a cassette
+    fn recount(&mut self) {
+        let fresh: Vec<&Value> = self.current_events.iter()
+            .filter(|e| !e.get("id").and_then(|i| i.as_str()).map_or(false, |id| self.carried_over.contains(id)))
+            .collect();
+        self.event_count = fresh.len();
+        self.current_size = fresh.iter()
+            .map(|e| serde_json::to_string(e).unwrap_or_default().len())
+            .sum();
+    }
 }
 
-// Global event store for deck mode with deduplication
+// Event index for deck mode with deduplication, kept in the deck's storage backend
 struct DeckEventStore {
-    // All events by ID
-    events_by_id: HashMap<String, Value>,
-    // Replaceable events indexed by author + kind (for kinds 0, 3, 10000-19999)
-    replaceable_events: HashMap<(String, u64), String>, // (author, kind) -> event_id
-    // Parameterized replaceable events indexed by author + kind + d-tag (for kinds 30000-39999)
-    param_replaceable_events: HashMap<(String, u64, String), String>, // (author, kind, d_tag) -> event_id
+    storage: Box<dyn deck_storage::DeckStorage>,
+    // Buffered ids and their origin; they reach the storage once a rotation has compiled
+    // them, so events lost in a crash or a failed compile can be sent again
+    pending: HashMap<String, String>,
 }
 
 impl DeckEventStore {
-    fn new() -> Self {
-        Self {
-            events_by_id: HashMap::new(),
-            replaceable_events: HashMap::new(),
-            param_replaceable_events: HashMap::new(),
-        }
-    }
-    
-    // Check if event should replace an existing one
-    fn check_replaceable(&self, event: &Value) -> Option<String> {
-        let kind = event.get("kind")?.as_u64()?;
-        let pubkey = event.get("pubkey")?.as_str()?;
-        
-        // Check if it's a replaceable event
-        if kind == 0 || kind == 3 || (10000..=19999).contains(&kind) {
-            // Regular replaceable
-            return self.replaceable_events.get(&(pubkey.to_string(), kind)).cloned();
-        } else if (30000..=39999).contains(&kind) {
-            // Parameterized replaceable - need d-tag
-            let d_tag = event.get("tags")?
-                .as_array()?
-                .iter()
-                .find(|tag| {
-                    tag.as_array()
-                        .and_then(|t| t.get(0))
-                        .and_then(|t| t.as_str())
-                        .map(|t| t == "d")
-                        .unwrap_or(false)
-                })?
-                .as_array()?
-                .get(1)?
-                .as_str()?;
-            
-            return self.param_replaceable_events.get(&(pubkey.to_string(), kind, d_tag.to_string())).cloned();
+    fn new(storage: Box<dyn deck_storage::DeckStorage>) -> Self {
+        Self { storage, pending: HashMap::new() }
+    }
+    
+    fn contains(&self, event_id: &str) -> Result<bool> {
+        Ok(self.pending.contains_key(event_id)
+            || self.storage.get(deck_storage::Table::Events, event_id)?.is_some())
+    }
+    
+    // Number of archived ids in the storage
+    fn archived(&self) -> Result<u64> {
+        self.storage.len(deck_storage::Table::Events)
+    }
+    
+    // Record a buffered event id and where it came from; false if it was already known
+    fn remember(&mut self, event_id: &str, origin: &str) -> Result<bool> {
+        if self.contains(event_id)? {
+            return Ok(false);
+        }
+        self.pending.insert(event_id.to_string(), origin.to_string());
+        Ok(true)
+    }
+    
+    // Move the ids of events a rotation compiled into the storage
+    fn archive(&mut self, events: &[Value]) -> Result<()> {
+        let entries: Vec<(String, String)> = events.iter()
+            .filter_map(|e| e.get("id").and_then(|i| i.as_str()))
+            .filter_map(|id| self.pending.remove(id).map(|origin| (id.to_string(), origin)))
+            .collect();
+        self.storage.insert_all(deck_storage::Table::Events, &entries)
+    }
+    
+    // Drop the ids of events a failed rotation lost
+    fn forget(&mut self, events: &[Value]) {
+        for id in events.iter().filter_map(|e| e.get("id").and_then(|i| i.as_str())) {
+            self.pending.remove(id);
         }
-        
-        None
     }
     
     // Add event to store, returns (added, replaced_event_id)
-    fn add_event(&mut self, event: Value) -> (bool, Option<String>) {
-        let event_id = match event.get("id").and_then(|i| i.as_str()) {
-            Some(id) => id.to_string(),
-            None => return (false, None),
+    fn add_event(&mut self, event: &Value, origin: &str) -> Result<(bool, Option<String>)> {
+        let Some(event_id) = event.get("id").and_then(|i| i.as_str()) else {
+            return Ok((false, None));
         };
         
         // Check if we already have this exact event
-        if self.events_by_id.contains_key(&event_id) {
-            return (false, None);
+        if !self.remember(event_id, origin)? {
+            return Ok((false, None));
         }
         
         // Check if this replaces an existing event
-        let replaced = self.check_replaceable(&event);
-        if let Some(old_id) = &replaced {
-            // Remove the old event
-            self.events_by_id.remove(old_id);
-        }
-        
-        // Update indices
-        if let (Some(kind), Some(pubkey)) = (event.get("kind").and_then(|k| k.as_u64()), 
-                                             event.get("pubkey").and_then(|p| p.as_str())) {
-            if kind == 0 || kind == 3 || (10000..=19999).contains(&kind) {
-                self.replaceable_events.insert((pubkey.to_string(), kind), event_id.clone());
-            } else if (30000..=39999).contains(&kind) {
-                if let Some(d_tag) = event.get("tags")
-                    .and_then(|t| t.as_array())
-                    .and_then(|tags| {
-                        tags.iter().find(|tag| {
-                            tag.as_array()
-                                .and_then(|t| t.get(0))
-                                .and_then(|t| t.as_str())
-                                .map(|t| t == "d")
-                                .unwrap_or(false)
-                        })
-                    })
-                    .and_then(|tag| tag.as_array())
-                    .and_then(|t| t.get(1))
-                    .and_then(|t| t.as_str()) {
-                    self.param_replaceable_events.insert((pubkey.to_string(), kind, d_tag.to_string()), event_id.clone());
-                }
+        let mut replaced = None;
+        if let Some(address) = replaceable::address(event) {
+            replaced = self.storage.get(deck_storage::Table::Replaceable, &address)?;
+            if let Some(old_id) = &replaced {
+                self.pending.remove(old_id);
+                self.storage.remove(deck_storage::Table::Events, old_id)?;
             }
+            self.storage.insert(deck_storage::Table::Replaceable, &address, event_id)?;
         }
         
-        // Add the event
-        self.events_by_id.insert(event_id.clone(), event);
-        
-        (true, replaced)
+        Ok((true, replaced))
     }
 }
 
@@ -3699,6 +5230,10 @@ async fn record_from_relay(
     kinds: &[i64],
     authors: &[String],
     initial_since: Option<i64>,
+    cursors: &checkpoint::RelayCursors,
+    event_store: &Arc<RwLock<DeckEventStore>>,
+    policy: &validation::ValidationPolicy,
+    verbose: bool,
 ) -> Result<i64> {
     use tokio_tungstenite::tungstenite::Message;
     use futures_util::{StreamExt, SinkExt};
@@ -3734,8 +5269,14 @@ async fn record_from_relay(
     let req_message = json!(["REQ", "deck-sub", filter]);
     write.send(Message::Text(req_message.to_string())).await?;
     
+    // The event the checkpoint ends on comes back with `since`, it's already in a cassette
+    let checkpointed_id = cursors.resume_from(relay_url).map(|cursor| cursor.event_id);
+    // Stored history arrives newest first, so its cursor only counts once all of it is buffered
+    let mut history_cursor: Option<checkpoint::RelayCursor> = None;
+    
     // Track if we've received EOSE and latest timestamp
     let mut received_eose = false;
+    let mut archive_full_logged = false;
     let mut last_event_time = std::time::Instant::now();
     let mut latest_timestamp = initial_since.unwrap_or_else(|| {
         std::time::SystemTime::now()
@@ -3758,19 +5299,56 @@ async fn record_from_relay(
                             Some("EVENT") => {
                                 if arr.len() >= 3 {
                                     if let Some(event) = arr.get(2) {
+                                        let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("");
+                                        if checkpointed_id.as_deref() == Some(id) {
+                                            continue;
+                                        }
+                                        if !policy.admit(event, verbose) {
+                                            continue;
+                                        }
+                                        
                                         // Extract created_at timestamp
-                                        if let Some(created_at) = event.get("created_at").and_then(|v| v.as_i64()) {
+                                        let created_at = event.get("created_at").and_then(|v| v.as_i64());
+                                        if let Some(created_at) = created_at {
                                             if created_at > latest_timestamp {
                                                 latest_timestamp = created_at;
                                             }
                                         }
                                         
                                         let event_size = text.len();
-                                        let mut state = recording_state.write().await;
-                                        state.current_events.push(event.clone());
-                                        state.event_count += 1;
-                                        state.current_size += event_size;
+                                        {
+                                            let mut state = recording_state.write().await;
+                                            if state.archive_full() {
+                                                if !archive_full_logged {
+                                                    eprintln!("⚠️  Archive size limit reached, no longer recording from {}", relay_url);
+                                                    archive_full_logged = true;
+                                                }
+                                                continue;
+                                            }
+                                            // Events already recorded from another relay, or archived
+                                            // before a restart, only move the cursor
+                                            let fresh = event_store.write().await.remember(id, relay_url)
+                                                .unwrap_or_else(|e| {
+                                                    eprintln!("⚠️  Event index lookup failed: {}", e);
+                                                    true
+                                                });
+                                            if fresh {
+                                                state.current_events.push(event.clone());
+                                                state.received_at.insert(id.to_string(), SystemTime::now());
+                                                state.event_count += 1;
+                                                state.current_size += event_size;
+                                            }
+                                        }
                                         last_event_time = std::time::Instant::now();
+                                        
+                                        if let Some(created_at) = created_at {
+                                            let cursor = checkpoint::RelayCursor { created_at, event_id: id.to_string() };
+                                            if received_eose {
+                                                cursors.observe(relay_url, cursor);
+                                            } else if history_cursor.as_ref().map_or(true, |c| created_at > c.created_at) {
+                                                history_cursor = Some(cursor);
+                                            }
+                                        }
                                     }
                                 }
                             }
@@ -3778,6 +5356,9 @@ async fn record_from_relay(
                                 if !received_eose {
                                     println!("📍 Received EOSE from {} - continuing to listen for new events", relay_url);
                                     received_eose = true;
+                                    if let Some(cursor) = history_cursor.take() {
+                                        cursors.observe(relay_url, cursor);
+                                    }
                                 }
                                 // Don't break on EOSE - keep the connection alive
                             }
@@ -3846,6 +5427,10 @@ async fn rotate_cassette(
     nip_50: bool,
     nip11_args: &Nip11Args,
     verbose: bool,
+    overlap: Duration,
+    cursors: Option<&Arc<checkpoint::RelayCursors>>,
+    event_store: &Arc<RwLock<DeckEventStore>>,
+    monitor: &Arc<deck_status::DeckMonitor>,
     #[cfg(feature = "deck")] embedded_tools_dir: &Arc<PathBuf>,
 ) -> Result<()> {
     // Check if already compiling
@@ -3858,9 +5443,15 @@ async fn rotate_cassette(
     }
     
     // Get events but DON'T reset state yet - keep events queryable until cassette is compiled
+    // (relay cursors first: every event they cover has already been buffered)
+    let cut = SystemTime::now();
+    let covered_cursors = cursors.map(|c| (c.clone(), c.snapshot()));
+    // Compiled in NIP-01 order, newest first, like recorded cassettes
     let events = {
         let state = recording_state.read().await;
-        state.current_events.clone()
+        let mut events = state.current_events.clone();
+        events.sort_by(cassette_tools::newest_first);
+        events
     };
     
     if events.is_empty() {
@@ -3884,6 +5475,8 @@ async fn rotate_cassette(
     let nip11_args = nip11_args.clone();
     let recording_state_clone = recording_state.clone();
     let event_count = events.len();
+    let rotated = Arc::new(events);
+    let events = rotated.clone();
     
     #[cfg(feature = "deck")]
     let embedded_tools_dir_clone = embedded_tools_dir.clone();
@@ -3906,7 +5499,7 @@ async fn rotate_cassette(
             &project_dir,
         );
         
-        let events_json = serde_json::to_string(&events)?;
+        let events_json = serde_json::to_string(&*events)?;
         println!("🔍 Debug: Serializing {} events for cassette", events.len());
         println!("🔍 Debug: First event sample: {}", 
             events.first()
@@ -3914,21 +5507,14 @@ async fn rotate_cassette(
                 .unwrap_or_default());
         
         generator.set_var("events_json", &events_json);
+        generator.set_var("event_index", &cassette_tools::EventIndex::from_events(&events).to_json());
+        if nip_50 {
+            generator.set_var("text_index", &cassette_tools::TextIndex::from_events(&events).to_json());
+        }
         generator.set_var("features_array", &serde_json::to_string(&features)?);
         generator.set_var("version", env!("CARGO_PKG_VERSION"));
         
-        if let Some(relay_name) = &nip11_args.relay_name {
-            generator.set_var("relay_name", relay_name);
-        }
-        if let Some(desc) = &nip11_args.relay_description {
-            generator.set_var("relay_description", desc);
-        }
-        if let Some(contact) = &nip11_args.relay_contact {
-            generator.set_var("relay_contact", contact);
-        }
-        if let Some(pubkey) = &nip11_args.relay_pubkey {
-            generator.set_var("relay_pubkey", pubkey);
-        }
+        generator.set_var("relay_info", &escape_json_for_raw_string(&nip11_args.document()?));
         
         generator.set_verbose(verbose);
         
@@ -3951,15 +5537,8 @@ async fn rotate_cassette(
             
             // NOW we can clear the buffer since the cassette is ready
             let mut state = recording_state_clone.write().await;
-            // Remove the events we just compiled (with bounds check)
-            let drain_count = event_count.min(state.current_events.len());
-            if drain_count > 0 {
-                state.current_events.drain(0..drain_count);
-            }
-            state.event_count = state.current_events.len();
-            state.current_size = state.current_events.iter()
-                .map(|e| serde_json::to_string(e).unwrap_or_default().len())
-                .sum();
+            state.archived_bytes += fs::metadata(&cassette_path).map_or(0, |m| m.len());
+            state.release_rotated(&events, cut, overlap);
             state.start_time = SystemTime::now();
             state.is_compiling = false;
         });
@@ -3969,31 +5548,43 @@ async fn rotate_cassette(
     
     // Wait for the compilation to complete and log any errors
     let recording_state_for_error = recording_state.clone();
+    let event_store = event_store.clone();
+    let monitor = monitor.clone();
     tokio::spawn(async move {
         match handle.await {
             Ok(Ok(())) => {
-                // Success - already logged
+                // The rotated events are in a cassette now, so a restart can resume after them
+                if let Err(e) = event_store.write().await.archive(&rotated) {
+                    eprintln!("⚠️  Failed to update the event index: {}", e);
+                    monitor.record_error(format!("event index: {}", e));
+                }
+                if let Some((cursors, snapshot)) = covered_cursors {
+                    if let Err(e) = cursors.commit(&snapshot) {
+                        eprintln!("⚠️  Failed to save relay checkpoint: {}", e);
+                        monitor.record_error(format!("checkpoint: {}", e));
+                    }
+                }
             }
             Ok(Err(e)) => {
                 eprintln!("❌ Cassette compilation failed: {}", e);
-                // Reset state on failure to prevent infinite rotation loop
+                monitor.record_error(format!("cassette compilation failed, {} events dropped: {}", event_count, e));
+                // Drop the failed batch to prevent infinite rotation loop; later arrivals stay
                 let mut state = recording_state_for_error.write().await;
-                state.current_events.clear();
-                state.event_count = 0;
-                state.current_size = 0;
+                state.release_rotated(&rotated, cut, Duration::ZERO);
                 state.start_time = SystemTime::now();
                 state.is_compiling = false;
+                event_store.write().await.forget(&rotated);
                 eprintln!("⚠️  Cleared {} events due to compilation failure", event_count);
             }
             Err(e) => {
                 eprintln!("❌ Task join error: {}", e);
-                // Reset state on failure to prevent infinite rotation loop
+                monitor.record_error(format!("cassette compilation task failed, {} events dropped: {}", event_count, e));
+                // Drop the failed batch to prevent infinite rotation loop; later arrivals stay
                 let mut state = recording_state_for_error.write().await;
-                state.current_events.clear();
-                state.event_count = 0;
-                state.current_size = 0;
+                state.release_rotated(&rotated, cut, Duration::ZERO);
                 state.start_time = SystemTime::now();
                 state.is_compiling = false;
+                event_store.write().await.forget(&rotated);
                 eprintln!("⚠️  Cleared {} events due to task failure", event_count);
             }
         }
@@ -4006,6 +5597,8 @@ async fn rotate_cassette(
 async fn handle_deck_connection(
     stream: TcpStream,
     active_cassettes: Arc<RwLock<Vec<(PathBuf, Module, Engine)>>>,
+    mute_list: Option<Arc<mute::MuteList>>,
+    validator: Option<response_validation::ResponseValidator>,
 ) -> Result<()> {
     // Check if this is an HTTP request for NIP-11
     let mut buf = [0u8; 1024];
@@ -4016,23 +5609,16 @@ async fn handle_deck_connection(
         // Handle HTTP request for NIP-11
         let cassettes = active_cassettes.read().await;
         if let Some((_, module, engine)) = cassettes.first() {
-            let mut store = Store::new(engine, ());
-            let instance = Instance::new(&mut store, module, &[])?;
+            let (mut store, guest) = guest::GuestApi::instantiate(engine, module)?;
             
-            if let Ok(info_func) = instance.get_typed_func::<(), i32>(&mut store, "info") {
-                let info_ptr = info_func.call(&mut store, ())?;
-                if info_ptr != 0 {
-                    let memory = instance.get_memory(&mut store, "memory").unwrap();
-                    let info_str = read_string_from_memory(&mut store, &instance, &memory, info_ptr)?;
-                    
-                    let response = format!(
-                        "HTTP/1.1 200 OK\r\nContent-Type: application/nostr+json\r\nContent-Length: {}\r\n\r\n{}",
-                        info_str.len(),
-                        info_str
-                    );
-                    
-                    stream.try_write(response.as_bytes())?;
-                }
+            if let Some(info_str) = guest.info(&mut store)? {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/nostr+json\r\nContent-Length: {}\r\n\r\n{}",
+                    info_str.len(),
+                    info_str
+                );
+                
+                stream.try_write(response.as_bytes())?;
             }
         }
         return Ok(());
@@ -4041,40 +5627,61 @@ async fn handle_deck_connection(
     // Handle WebSocket connection
     let ws_stream = accept_async(stream).await?;
     let (mut write, mut read) = ws_stream.split();
+    // Events carried over by a rotation overlap are in two cassettes; send them once
+    let mut delivered = SubscriptionTracker::default();
     
     while let Some(msg) = read.next().await {
         match msg {
             Ok(Message::Text(text)) => {
+                delivered.observe(&text);
+                
                 // Process message against all active cassettes
                 let cassettes = active_cassettes.read().await;
                 let mut all_responses = Vec::new();
                 
-                for (_path, module, engine) in cassettes.iter() {
+                // With a mute list active, COUNT is answered by counting the unmuted events instead
+                if let Some(mute) = &mute_list {
+                    if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&text) {
+                        if parsed.len() >= 3 && parsed[0].as_str() == Some("COUNT") {
+                            let sub_id = parsed[1].as_str().unwrap_or("");
+                            let mut counted = HashSet::new();
+                            for (path, module, engine) in cassettes.iter() {
+                                // Events were validated when the cassette was recorded
+                                let events = collect_cassette_events(path, module, engine, sub_id, &parsed[2..], false)?;
+                                let source = path.display().to_string();
+                                counted.extend(events.iter()
+                                    .filter(|event| !mute.is_muted(event))
+                                    .filter(|event| validator.map_or(true, |v| v.admit(&source, event, &parsed[2..])))
+                                    .filter_map(|event| event.get("id").and_then(|i| i.as_str()).map(|id| id.to_string())));
+                            }
+                            let count_msg = json!(["COUNT", sub_id, {"count": counted.len(), "approximate": false}]);
+                            write.send(Message::Text(count_msg.to_string())).await?;
+                            continue;
+                        }
+                    }
+                }
+                
+                let filters = response_validation::request_filters(&text);
+                for (path, module, engine) in cassettes.iter() {
                     let mut store = Store::new(engine, ());
                     let instance = Instance::new(&mut store, module, &[])?;
                     
                     // Process the message
-                    if let Ok(send_func) = instance.get_typed_func::<(i32, i32), i32>(&mut store, "send")
-                .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req")) {
-                        if let Ok(alloc_func) = instance.get_typed_func::<i32, i32>(&mut store, "alloc_buffer") {
-                            let memory = instance.get_memory(&mut store, "memory").unwrap();
-                            
-                            let msg_bytes = text.as_bytes();
-                            let msg_ptr = alloc_func.call(&mut store, msg_bytes.len() as i32)?;
-                            
-                            if msg_ptr != 0 {
-                                memory.write(&mut store, msg_ptr as usize, msg_bytes)?;
-                                let result_ptr = send_func.call(&mut store, (msg_ptr, msg_bytes.len() as i32))?;
-                                
-                                if result_ptr != 0 {
-                                    let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
-                                    all_responses.push(result);
-                                }
+                    if let Ok(guest) = guest::GuestApi::detect(&mut store, &instance) {
+                        guest.attach_payload(&mut store, path)?;
+                        if let Some(result) = guest.send(&mut store, &text)? {
+                            if validator.map_or(true, |v| v.admit_message(&path.display().to_string(), &result, &filters)) {
+                                all_responses.push(result);
                             }
                         }
                     }
                 }
                 
+                if let Some(mute) = &mute_list {
+                    all_responses.retain(|response| !mute.is_muted_message(response));
+                }
+                all_responses.retain(|response| delivered.first_delivery(response));
+                
                 // Aggregate and send responses
                 for response in all_responses {
                     write.send(Message::Text(response)).await?;
@@ -4088,6 +5695,87 @@ async fn handle_deck_connection(
     Ok(())
 }
 
+/// Load the operator's mute list from a file or from the served cassettes
+fn load_mute_list(mute_args: &MuteArgs, cassette_paths: &[PathBuf]) -> Result<Option<Arc<mute::MuteList>>> {
+    let pubkey = mute_args.mute_pubkey.as_deref();
+    
+    let events = if let Some(file) = &mute_args.mute_list {
+        parse_events_from_file(&file.to_string_lossy(), input::InputFormat::Auto)
+            .map_err(|e| anyhow!("Failed to read mute list {}: {}", file.display(), e))?
+    } else if let Some(pubkey) = pubkey {
+        let req = json!(["REQ", "mute-list", {"kinds": [mute::MUTE_LIST_KIND], "authors": [pubkey]}]).to_string();
+        let mut events = Vec::new();
+        for path in cassette_paths {
+            let mut cassette = Cassette::load(&path.to_string_lossy(), false)?;
+            if let SendResult::Multiple(messages) = cassette.scrub(&req)? {
+                for message in messages {
+                    if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&message) {
+                        if parsed.len() >= 3 && parsed[0].as_str() == Some("EVENT") {
+                            events.push(parsed[2].clone());
+                        }
+                    }
+                }
+            }
+        }
+        events
+    } else {
+        return Ok(None);
+    };
+    
+    let event = mute::latest_mute_list_event(&events, pubkey)
+        .ok_or_else(|| match pubkey {
+            Some(pk) => anyhow!("No kind {} mute list found for {}", mute::MUTE_LIST_KIND, pk),
+            None => anyhow!("No kind {} mute list found", mute::MUTE_LIST_KIND),
+        })?;
+    
+    let mute_list = mute::MuteList::from_event(event);
+    if mute_list.is_empty() {
+        eprintln!("⚠️  Mute list has no public entries, nothing will be hidden");
+    } else {
+        println!("🔇 Mute list loaded: {} entries", mute_list.len());
+    }
+    
+    Ok(Some(Arc::new(mute_list)))
+}
+
+/// Event ids already sent on each open subscription of a connection, so a query that
+/// fans out across overlapping cassettes delivers every event at most once
+#[derive(Default)]
+struct SubscriptionTracker {
+    subscriptions: HashMap<String, EventTracker>,
+}
+
+impl SubscriptionTracker {
+    /// Follow a client message: a REQ (re)opens its subscription, a CLOSE forgets it
+    fn observe(&mut self, message: &str) {
+        let Ok(parsed) = serde_json::from_str::<Vec<Value>>(message) else { return };
+        let (Some(command), Some(sub_id)) = (
+            parsed.first().and_then(|c| c.as_str()),
+            parsed.get(1).and_then(|s| s.as_str()),
+        ) else {
+            return;
+        };
+        match command {
+            "REQ" => { self.subscriptions.insert(sub_id.to_string(), EventTracker::new()); }
+            "CLOSE" => { self.subscriptions.remove(sub_id); }
+            _ => {}
+        }
+    }
+
+    /// Whether a relay message should go out: false for an EVENT already sent on its subscription
+    fn first_delivery(&self, message: &str) -> bool {
+        let Ok(parsed) = serde_json::from_str::<Vec<Value>>(message) else { return true };
+        if parsed.len() < 3 || parsed[0].as_str() != Some("EVENT") {
+            return true;
+        }
+        let tracker = parsed[1].as_str().and_then(|sub_id| self.subscriptions.get(sub_id));
+        match (tracker, parsed[2].get("id").and_then(|i| i.as_str())) {
+            (Some(tracker), Some(id)) => tracker.add_and_check(id),
+            _ => true,
+        }
+    }
+}
+
 /// Process the listen command - start a WebSocket server for cassettes
 async fn process_listen_command(
     cassette_patterns: &[String],
@@ -4098,6 +5786,13 @@ async fn process_listen_command(
     _tls_cert: Option<&std::path::Path>,
     _tls_key: Option<&std::path::Path>,
     verbose: bool,
+    sensitive: content_warning::SensitivePolicy,
+    require_auth: bool,
+    relay_url: Option<&str>,
+    nip11_args: &Nip11Args,
+    mute_args: &MuteArgs,
+    validator: Option<response_validation::ResponseValidator>,
+    preload: Option<usize>,
 ) -> Result<()> {
     // Expand glob patterns and collect cassette files (supports both .cassette and .wasm)
     let mut cassette_files = Vec::new();
@@ -4128,6 +5823,14 @@ async fn process_listen_command(
     // Store cassette paths (load on-demand to save memory and enable thread safety)
     let cassette_paths: Vec<PathBuf> = cassette_files;
     
+    let mute_list = load_mute_list(mute_args, &cassette_paths)?;
+    
+    // Compile the preloaded cassettes now; the others are still loaded per query
+    let cache = Arc::new(match preload {
+        Some(limit) => preload::CassetteCache::preload(&preload::select(&cassette_paths, limit), verbose)?,
+        None => preload::CassetteCache::default(),
+    });
+    
     // Find available port if not specified
     let port = if let Some(p) = port {
         p
@@ -4141,13 +5844,22 @@ async fn process_listen_command(
     let protocol = if tls { "wss" } else { "ws" };
     let http_protocol = if tls { "https" } else { "http" };
     
+    // AUTH events must name this relay; behind a proxy or on 0.0.0.0 that's --relay-url
+    let auth = require_auth.then(|| auth::AuthPolicy {
+        relay_url: relay_url.map_or_else(|| format!("{}://{}:{}", protocol, bind_address, port), str::to_string),
+    });
+    
     println!("🚀 Cassette relay server started");
     println!("   WebSocket: {}://{}:{}", protocol, bind_address, port);
     println!("   HTTP (NIP-11): {}://{}:{}", http_protocol, bind_address, port);
+    if let Some(auth) = &auth {
+        println!("   🔐 Authentication required (NIP-42) for {}", auth.relay_url);
+    }
     println!("   Press Ctrl+C to stop");
 
     // Create shared state for cassettes (just paths for lazy loading)
     let cassettes = Arc::new(cassette_paths);
+    let relay_info = Arc::new(nip11_args.relay_info()?);
 
     // Connection limiting to prevent OOM
     let active_connections = Arc::new(AtomicUsize::new(0));
@@ -4170,8 +5882,12 @@ async fn process_listen_command(
 
         let cassettes_clone = cassettes.clone();
         let active_connections_clone = active_connections.clone();
+        let mute_list_clone = mute_list.clone();
+        let cache_clone = cache.clone();
+        let auth_clone = auth.clone();
+        let relay_info_clone = relay_info.clone();
         tokio::spawn(async move {
-            let result = handle_connection(stream, cassettes_clone, verbose).await;
+            let result = handle_connection(stream, cassettes_clone, cache_clone, mute_list_clone, sensitive, auth_clone, relay_info_clone, validator, verbose).await;
             active_connections_clone.fetch_sub(1, Ordering::SeqCst);
             if let Err(e) = result {
                 if verbose {
@@ -4207,6 +5923,12 @@ async fn find_available_port(bind_address: &str) -> Result<u16> {
 async fn handle_connection(
     stream: TcpStream,
     cassette_paths: Arc<Vec<PathBuf>>,
+    cache: Arc<preload::CassetteCache>,
+    mute_list: Option<Arc<mute::MuteList>>,
+    sensitive: content_warning::SensitivePolicy,
+    auth: Option<auth::AuthPolicy>,
+    relay_info: Arc<cassette_tools::nips::nip11::RelayInfo>,
+    validator: Option<response_validation::ResponseValidator>,
     verbose: bool,
 ) -> Result<()> {
     
@@ -4224,10 +5946,10 @@ async fn handle_connection(
     
     if is_nip11_request {
         // Serve NIP-11 JSON
-        handle_http_request(stream, cassette_paths, verbose).await
+        handle_http_request(stream, cassette_paths, cache, sensitive, auth.is_some(), &relay_info, verbose).await
     } else {
         // Everything else is WebSocket upgrade
-        handle_websocket_connection(stream, cassette_paths, verbose).await
+        handle_websocket_connection(stream, cassette_paths, cache, mute_list, sensitive, auth, validator, verbose).await
     }
 }
 
@@ -4235,6 +5957,10 @@ async fn handle_connection(
 async fn handle_http_request(
     stream: TcpStream,
     cassette_paths: Arc<Vec<PathBuf>>,
+    cache: Arc<preload::CassetteCache>,
+    sensitive: content_warning::SensitivePolicy,
+    require_auth: bool,
+    relay_info: &cassette_tools::nips::nip11::RelayInfo,
     verbose: bool,
 ) -> Result<()> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -4260,12 +5986,17 @@ async fn handle_http_request(
         // Get NIP-11 info from the first cassette (or merge from all)
         if let Some(path) = cassette_paths.first() {
             // Load cassette on-demand
-            let path_str = path.to_string_lossy();
-            let mut cassette = Cassette::load(&path_str, false)?;
+            let mut cassette = cache.load(path)?;
 
             // Try to get relay info
             match cassette.info() {
                 Ok(info) => {
+                    let info = overlay_relay_info(&info, relay_info);
+                    let info = match sensitive {
+                        content_warning::SensitivePolicy::Serve => info,
+                        policy => content_warning::advertise(&info, policy),
+                    };
+                    let info = if require_auth { auth::advertise(&info) } else { info };
                     // Send HTTP response with NIP-11 info
                     let response = format!(
                         "HTTP/1.1 200 OK\r\n\
@@ -4289,7 +6020,8 @@ async fn handle_http_request(
                 }
                 Err(_) => {
                     // Return empty NIP-11 if info() not available
-                    let info = json!({"supported_nips": []}).to_string();
+                    let info = overlay_relay_info(&json!({"supported_nips": []}).to_string(), relay_info);
+                    let info = if require_auth { auth::advertise(&info) } else { info };
                     let response = format!(
                         "HTTP/1.1 200 OK\r\n\
                         Content-Type: application/nostr+json\r\n\
@@ -4320,87 +6052,65 @@ async fn handle_http_request(
 }
 
 /// Handle WebSocket connections
+///
+/// Messages are answered in order by a worker task while this loop keeps reading, so a
+/// CLOSE that arrives while a REQ is still streaming cancels it straight away.
 async fn handle_websocket_connection(
     stream: TcpStream,
     cassette_paths: Arc<Vec<PathBuf>>,
+    cache: Arc<preload::CassetteCache>,
+    mute_list: Option<Arc<mute::MuteList>>,
+    sensitive: content_warning::SensitivePolicy,
+    auth: Option<auth::AuthPolicy>,
+    validator: Option<response_validation::ResponseValidator>,
     verbose: bool,
 ) -> Result<()> {
+    use std::sync::atomic::AtomicBool;
+
     let ws_stream = accept_async(stream).await?;
-    let (mut write, mut read) = ws_stream.split();
+    let (write, mut read) = ws_stream.split();
+
+    // Cancellation flag of each open subscription
+    let mut cancellations: HashMap<String, Arc<AtomicBool>> = HashMap::new();
+    let (queue, pending) = tokio::sync::mpsc::unbounded_channel();
+    let session = auth.map(|policy| policy.session());
+    let mut worker = tokio::spawn(answer_messages(pending, write, cassette_paths, cache, mute_list, sensitive, session, validator, verbose));
 
     // Handle incoming messages
-    while let Some(msg) = read.next().await {
+    loop {
+        let msg = tokio::select! {
+            msg = read.next() => msg,
+            // The worker only stops early when the client can no longer be written to
+            result = &mut worker => return result?,
+        };
         match msg {
-            Ok(Message::Text(text)) => {
+            Some(Ok(Message::Text(text))) => {
                 if verbose {
                     println!("Received: {}", text);
                 }
-
-                // Process request against all cassettes
-                for path in cassette_paths.iter() {
-                    // Clone text for each cassette query
-                    let text_clone = text.clone();
-
-                    // Load cassette on-demand (memory efficient, automatically freed after use)
-                    let path_str = path.to_string_lossy();
-                    let mut cassette = match Cassette::load(&path_str, false) {
-                        Ok(c) => c,
-                        Err(e) => {
-                            if verbose {
-                                eprintln!("Failed to load cassette {:?}: {}", path, e);
-                            }
-                            continue;
-                        }
-                    };
-
-                    // Use cassette-loader's scrub method which handles looping automatically
-                    // This eliminates the memory leak from manual looping
-                    // Wrap in timeout to prevent infinite loops (30 second timeout)
-                    match timeout(Duration::from_secs(30), tokio::task::spawn_blocking(move || {
-                        cassette.scrub(&text_clone)
-                    })).await {
-                        Ok(Ok(Ok(result))) => {
-                            match result {
-                                SendResult::Multiple(events) => {
-                                    // REQ message - send all events
-                                    for event in events {
-                                        write.send(Message::Text(event)).await?;
-                                    }
-                                }
-                                SendResult::Single(response) => {
-                                    // Other messages - send single response
-                                    if !response.is_empty() {
-                                        write.send(Message::Text(response)).await?;
-                                    }
-                                }
-                            }
-                        }
-                        Ok(Ok(Err(e))) => {
-                            if verbose {
-                                eprintln!("Error processing request: {}", e);
-                            }
-                            let notice = json!(["NOTICE", format!("Error: {}", e)]);
-                            write.send(Message::Text(notice.to_string())).await?;
-                        }
-                        Ok(Err(e)) => {
-                            if verbose {
-                                eprintln!("Task join error: {}", e);
+                
+                let cancelled = Arc::new(AtomicBool::new(false));
+                if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&text) {
+                    match (parsed.first().and_then(|c| c.as_str()), parsed.get(1).and_then(|s| s.as_str())) {
+                        // A REQ reusing an open subscription id replaces it
+                        (Some("REQ"), Some(sub_id)) => {
+                            if let Some(previous) = cancellations.insert(sub_id.to_string(), cancelled.clone()) {
+                                previous.store(true, Ordering::SeqCst);
                             }
-                            let notice = json!(["NOTICE", "Internal error processing request"]);
-                            write.send(Message::Text(notice.to_string())).await?;
                         }
-                        Err(_) => {
-                            if verbose {
-                                eprintln!("Request timeout after 30s for cassette: {:?}", path);
+                        (Some("CLOSE"), Some(sub_id)) => {
+                            if let Some(flag) = cancellations.remove(sub_id) {
+                                flag.store(true, Ordering::SeqCst);
                             }
-                            let notice = json!(["NOTICE", "Request timeout - query took too long"]);
-                            write.send(Message::Text(notice.to_string())).await?;
                         }
+                        _ => {}
                     }
-                    // Cassette is automatically dropped here, freeing all memory
+                }
+                if queue.send((text, cancelled)).is_err() {
+                    break;
                 }
             }
-            Ok(Message::Close(_)) => {
+            Some(Ok(Message::Close(_))) | None => {
                 if verbose {
                     println!("Client disconnected");
                 }
@@ -4410,105 +6120,418 @@ async fn handle_websocket_connection(
         }
     }
 
+    // Stop whatever is still streaming for this client
+    for flag in cancellations.values() {
+        flag.store(true, Ordering::SeqCst);
+    }
+    worker.abort();
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Answer a connection's messages in order against every cassette, forwarding REQ results
+/// as the cassettes stream them and stopping as soon as the subscription is cancelled
+///
+/// With an auth session the client is challenged first, AUTH messages are answered here
+/// and REQ/COUNT are closed with `auth-required:` until the client has authenticated.
+async fn answer_messages<W>(
+    mut pending: tokio::sync::mpsc::UnboundedReceiver<(String, Arc<std::sync::atomic::AtomicBool>)>,
+    mut write: W,
+    cassette_paths: Arc<Vec<PathBuf>>,
+    cache: Arc<preload::CassetteCache>,
+    mute_list: Option<Arc<mute::MuteList>>,
+    sensitive: content_warning::SensitivePolicy,
+    mut auth: Option<cassette_tools::nips::nip42::AuthSession>,
+    validator: Option<response_validation::ResponseValidator>,
+    verbose: bool,
+) -> Result<()>
+where
+    W: SinkExt<Message> + Unpin,
+    W::Error: std::error::Error + Send + Sync + 'static,
+{
+    let mut delivered = SubscriptionTracker::default();
 
-    match &cli.command {
-        Commands::Record { 
-            input_file, 
-            name, 
-            output,
-            generate: _,
-            no_bindings,
-            interactive,
-            verbose,
-            _skip_validation,
-            skip_unicode_check,
-            _nip_11,
-            nip_42,
-            nip_45,
-            nip_50,
-            nip11
-        } => {
-            // Check dependencies before proceeding
-            let dep_check = deps::DependencyCheck::new();
-            dep_check.check_for_record()?;
-            
-            // Set default values if not provided
-            let name_value = name.clone().unwrap_or_else(|| "cassette".to_string());
-            let sanitized_name = sanitize_filename(&name_value);
-            let output_value = output.clone().unwrap_or_else(|| PathBuf::from("./cassettes"));
-            
-            // Either process from file or stdin
-            if let Some(path) = input_file {
-                if !path.exists() {
-                    return Err(anyhow!("Input file doesn't exist: {}", path.display()));
-                }
-                
-                process_events(
-                    path.to_str().unwrap(),
-                    &sanitized_name,
-                    &output_value,
-                    *no_bindings,
-                    *interactive,
-                    *verbose,
-                    !*_skip_validation,
-                    *skip_unicode_check,
-                    *_nip_11,
-                    *nip_42,
-                    *nip_45,
-                    *nip_50,
-                    nip11
-                )?;
-            } else {
-                // No input file, read from stdin
-                println!("Reading events from stdin...");
-                
-                // Create a temp file to store stdin content
-                let temp_dir = tempdir()?;
-                let temp_file_path = temp_dir.path().join("stdin_events.json");
-                let temp_file_path_str = temp_file_path.to_str().unwrap();
-                
-                // Read from stdin line by line (for NDJSON from nak)
-                let stdin = std::io::stdin();
-                let mut temp_file = File::create(&temp_file_path)?;
-                
-                for line in stdin.lock().lines() {
-                    let line = line?;
-                    if !line.trim().is_empty() {
-                        writeln!(temp_file, "{}", line)?;
+    if let Some(session) = &auth {
+        write.send(Message::Text(session.challenge_message().to_string())).await?;
+    }
+
+    while let Some((text, cancelled)) = pending.recv().await {
+        if let Some(session) = auth.as_mut() {
+            let parsed = serde_json::from_str::<Value>(&text).unwrap_or(Value::Null);
+            match (parsed.get(0).and_then(|c| c.as_str()), parsed.get(1).and_then(|s| s.as_str())) {
+                (Some("AUTH"), _) => {
+                    let ok = session.handle_auth(&parsed, chrono::Utc::now().timestamp());
+                    if verbose {
+                        println!("AUTH {}: {}", if ok[2] == true { "accepted" } else { "refused" }, ok[3]);
                     }
+                    write.send(Message::Text(ok.to_string())).await?;
+                    continue;
                 }
-                
-                // Ensure file has content
-                temp_file.flush()?;
-                let metadata = std::fs::metadata(&temp_file_path)?;
-                if metadata.len() == 0 {
-                    return Err(anyhow!("No data received from stdin. Please pipe in events or use an input file."));
+                (Some("REQ" | "COUNT"), Some(sub_id)) => {
+                    if let Err(e) = session.check() {
+                        let closed = cassette_tools::messages::closed(sub_id, &e.reason());
+                        write.send(Message::Text(closed.to_string())).await?;
+                        continue;
+                    }
                 }
-                
-                // Process the temp file
-                process_events(
-                    temp_file_path_str,
-                    &sanitized_name,
-                    &output_value,
-                    *no_bindings,
-                    *interactive,
+                _ => {}
+            }
+        }
+        delivered.observe(&text);
+        
+        // The opt-in field is ours, cassettes would reject it as a tag filter
+        let (text, opted_in) = content_warning::strip_opt_in(&text);
+        let withhold_sensitive = sensitive.withholds(opted_in);
+        
+        // With a mute list, a withheld content warning or response validation, COUNT is answered
+        // by counting the served events of an equivalent REQ
+        let muted_count_sub = (mute_list.is_some() || withhold_sensitive || validator.is_some()).then(|| {
+            let parsed = serde_json::from_str::<Vec<Value>>(&text).ok()?;
+            if parsed.len() >= 2 && parsed[0].as_str() == Some("COUNT") {
+                parsed[1].as_str().map(|sub_id| sub_id.to_string())
+            } else {
+                None
+            }
+        }).flatten();
+        let query = match &muted_count_sub {
+            Some(_) => {
+                let mut parsed: Vec<Value> = serde_json::from_str(&text)?;
+                parsed[0] = json!("REQ");
+                Value::Array(parsed).to_string()
+            }
+            None => text.clone(),
+        };
+        let filters = response_validation::request_filters(&query);
+
+        // Process request against all cassettes
+        for path in cassette_paths.iter() {
+            if cancelled.load(Ordering::SeqCst) {
+                break;
+            }
+
+            // Load cassette on-demand (memory efficient, automatically freed after use)
+            let mut cassette = match cache.load(path) {
+                Ok(c) => c,
+                Err(e) => {
+                    if verbose {
+                        eprintln!("Failed to load cassette {:?}: {}", path, e);
+                    }
+                    continue;
+                }
+            };
+
+            // The loader streams responses over a bounded channel; a cancelled subscription or a
+            // dropped receiver stops its loop and closes the subscription inside the cassette
+            let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(64);
+            let stop = cancelled.clone();
+            let request = query.clone();
+            let scrub = tokio::task::spawn_blocking(move || {
+                let result = cassette.scrub_streaming(&request, |response| {
+                    !stop.load(Ordering::SeqCst) && tx.blocking_send(response).is_ok()
+                });
+                (cassette, result)
+            });
+
+            // Wrap in timeout to prevent infinite loops (30 second timeout)
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+            let source = path.display().to_string();
+            let mut count = 0;
+            let mut closed = None;
+            let mut timed_out = false;
+            loop {
+                let response = match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(response)) => response,
+                    Ok(None) => break,
+                    Err(_) => {
+                        timed_out = true;
+                        break;
+                    }
+                };
+                if cancelled.load(Ordering::SeqCst) {
+                    break;
+                }
+                if mute_list.as_ref().map_or(false, |mute| mute.is_muted_message(&response))
+                    || (withhold_sensitive && content_warning::is_sensitive_message(&response))
+                    || !validator.map_or(true, |v| v.admit_message(&source, &response, &filters)) {
+                    continue;
+                }
+                
+                if muted_count_sub.is_some() {
+                    // A query rejected by the cassette's filter limits stays rejected
+                    if response.starts_with("[\"CLOSED\"") {
+                        closed = Some(response);
+                    } else if response.starts_with("[\"EVENT\"") {
+                        count += 1;
+                    }
+                    continue;
+                }
+                
+                // Send events not already delivered by another cassette
+                if delivered.first_delivery(&response) {
+                    write.send(Message::Text(response)).await?;
+                }
+            }
+            drop(rx);
+
+            if timed_out {
+                // A cassette stuck inside a single call can't be interrupted, so leave it behind
+                if verbose {
+                    eprintln!("Request timeout after 30s for cassette: {:?}", path);
+                }
+                let notice = json!(["NOTICE", "Request timeout - query took too long"]);
+                write.send(Message::Text(notice.to_string())).await?;
+                continue;
+            }
+            match scrub.await {
+                Ok((cassette, Ok(_))) => cache.release(path, cassette, &query),
+                Ok((_, Err(e))) => {
+                    if verbose {
+                        eprintln!("Error processing request: {}", e);
+                    }
+                    let notice = json!(["NOTICE", format!("Error: {}", e)]);
+                    write.send(Message::Text(notice.to_string())).await?;
+                    continue;
+                }
+                Err(e) => {
+                    if verbose {
+                        eprintln!("Task join error: {}", e);
+                    }
+                    let notice = json!(["NOTICE", "Internal error processing request"]);
+                    write.send(Message::Text(notice.to_string())).await?;
+                    continue;
+                }
+            }
+
+            if let Some(sub_id) = &muted_count_sub {
+                let response = closed.unwrap_or_else(|| json!(["COUNT", sub_id, {"count": count, "approximate": false}]).to_string());
+                write.send(Message::Text(response)).await?;
+            }
+            // Cassettes that weren't preloaded are dropped here, freeing all memory
+        }
+        if verbose && cancelled.load(Ordering::SeqCst) {
+            println!("Cancelled: {}", text);
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match &cli.command {
+        Commands::Record { 
+            input_file, 
+            format,
+            append,
+            nostrdb,
+            keep_ephemeral,
+            name, 
+            output,
+            generate: _,
+            no_bindings,
+            interactive,
+            verbose,
+            policy,
+            skip_unicode_check,
+            _nip_11,
+            nip_42,
+            nip_45,
+            nip_50,
+            nip11,
+            build,
+            fetch,
+            watch,
+        } => {
+            // Check dependencies before proceeding
+            if build.needs_toolchain() {
+                deps::DependencyCheck::new().check_for_record()?;
+            }
+            
+            if let Some(dir) = &watch.watch {
+                let name_value = sanitize_filename(name.as_deref().unwrap_or("cassette"));
+                let output_value = output.clone().unwrap_or_else(|| PathBuf::from("./cassettes"));
+                return watch_directory(
+                    dir,
+                    watch,
+                    &name_value,
+                    &output_value,
+                    *no_bindings,
                     *verbose,
-                    !*_skip_validation,
+                    &policy.policy(),
                     *skip_unicode_check,
                     *_nip_11,
                     *nip_42,
                     *nip_45,
                     *nip_50,
-                    nip11
-                )?;
+                    nip11,
+                    build,
+                ).await;
+            }
+            
+            // With --append the cassette is rebuilt in place unless a name or output is given
+            let append_target = append.as_ref().filter(|_| name.is_none() && output.is_none());
+            
+            // Set default values if not provided
+            let name_value = name.clone()
+                .or_else(|| append.as_ref().and_then(|path| path.file_stem()).map(|stem| stem.to_string_lossy().to_string()))
+                .unwrap_or_else(|| "cassette".to_string());
+            let sanitized_name = sanitize_filename(&name_value);
+            let output_value = output.clone()
+                .or_else(|| append.as_ref().map(|path| path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(std::path::Path::new(".")).to_path_buf()))
+                .unwrap_or_else(|| PathBuf::from("./cassettes"));
+            
+            // Either process from relays, file or stdin; temp files are removed once the cassette is built
+            let mut _input_dir = None;
+            let mut _stdin_spool = None;
+            let (input_path, input_format, input_policy) = if !fetch.relays.is_empty() {
+                if input_file.is_some() {
+                    return Err(anyhow!("Use either an input file or --relays, not both"));
+                }
+                let mut filter: Value = match &fetch.filter {
+                    Some(json) => serde_json::from_str(json).map_err(|e| anyhow!("Invalid --filter JSON: {}", e))?,
+                    None => json!({}),
+                };
+                if let Some(filter) = filter.as_object_mut() {
+                    nip19::normalize_filter(filter)?;
+                }
+                let relays = nip19::resolve_relays(&fetch.relays)?;
+                
+                let temp_dir = tempdir()?;
+                let temp_file_path = temp_dir.path().join("relay_events.json");
+                let mut temp_file = File::create(&temp_file_path)?;
+                let mut seen = HashSet::new();
+                if fetch.follow_outbox.is_empty() {
+                    for relay_url in &relays {
+                        let events = fetch_relay_history(relay_url, &filter, fetch, *verbose).await?;
+                        let fresh = write_unseen(events, &mut seen, &mut temp_file)?;
+                        println!("✅ {} new events from {}", fresh, relay_url);
+                    }
+                } else {
+                    let authors = fetch.follow_outbox.iter()
+                        .map(|author| nip19::normalize_pubkey(author))
+                        .collect::<Result<Vec<_>>>()?;
+                    
+                    // The relay lists themselves go into the archive too
+                    let list_filter = json!({"kinds": [outbox::RELAY_LIST_KIND], "authors": authors});
+                    let mut relay_lists = Vec::new();
+                    for relay_url in &relays {
+                        match fetch::fetch_history(relay_url, &list_filter, fetch.page_options(), *verbose).await {
+                            Ok(events) => relay_lists.extend(events),
+                            Err(e) => println!("⚠️  No relay lists from {}: {}", relay_url, e),
+                        }
+                    }
+                    let newest = outbox::newest_relay_lists(&relay_lists);
+                    let plan = outbox::Plan::new(&authors, &newest, &relays, fetch.outbox_relays);
+                    println!("🧭 Found relay lists for {} of {} authors, downloading from {} relays",
+                        newest.len(), authors.len(), plan.relays.len());
+                    if !plan.unlisted.is_empty() {
+                        println!("⚠️  {} authors have no write relays, asking {} for them", plan.unlisted.len(), relays.join(", "));
+                    }
+                    let lists: Vec<Value> = newest.into_values().cloned().collect();
+                    write_unseen(lists, &mut seen, &mut temp_file)?;
+                    
+                    // Other people's relays go down or refuse; the rest of the archive still gets built
+                    for (relay_url, relay_authors) in &plan.relays {
+                        let mut relay_filter = filter.clone();
+                        if let Some(relay_filter) = relay_filter.as_object_mut() {
+                            relay_filter.insert("authors".to_string(), json!(relay_authors));
+                        }
+                        match fetch_relay_history(relay_url, &relay_filter, fetch, *verbose).await {
+                            Ok(events) => {
+                                let fresh = write_unseen(events, &mut seen, &mut temp_file)?;
+                                println!("✅ {} new events from {} ({} authors)", fresh, relay_url, relay_authors.len());
+                            }
+                            Err(e) => println!("⚠️  Skipping {}: {:#}", relay_url, e),
+                        }
+                    }
+                }
+                temp_file.flush()?;
+                if seen.is_empty() {
+                    return Err(anyhow!("No events matched the filter on any relay"));
+                }
+                
+                _input_dir = Some(temp_dir);
+                (temp_file_path, input::InputFormat::Auto, policy.policy())
+            } else if let Some(dir) = nostrdb {
+                println!("🗄️  Importing nostrdb cache {}", dir.display());
+                let temp_dir = tempdir()?;
+                let temp_file_path = temp_dir.path().join("nostrdb_events.json");
+                let mut temp_file = std::io::BufWriter::new(File::create(&temp_file_path)?);
+                let stats = ndb_import::import(dir, *keep_ephemeral, &mut temp_file)?;
+                temp_file.flush()?;
+                println!("✅ {} events imported{}", stats.events,
+                    if stats.ephemeral > 0 { format!(", {} ephemeral events skipped", stats.ephemeral) } else { String::new() });
+                if stats.events == 0 {
+                    return Err(anyhow!("No events found in the nostrdb cache"));
+                }
+                
+                _input_dir = Some(temp_dir);
+                (temp_file_path, input::InputFormat::Auto, policy.policy())
+            } else if let Some(path) = input_file {
+                if !path.exists() {
+                    return Err(anyhow!("Input file doesn't exist: {}", path.display()));
+                }
+                (path.clone(), *format, policy.policy())
+            } else {
+                // No input file, stream stdin into a spool of checked, deduplicated events
+                println!("Reading events from stdin...");
+                let spool = ingest::spool(std::io::stdin().lock(), &policy.policy(), *verbose)?;
+                println!("{}", spool.stats.summary());
+                for line in spool.stats.rejections.summary() {
+                    println!("{}", line);
+                }
+                if spool.stats.read == 0 {
+                    return Err(anyhow!("No data received from stdin. Please pipe in events or use an input file."));
+                }
+                if spool.stats.accepted == 0 {
+                    return Err(anyhow!("No valid events received from stdin"));
+                }
                 
-                // The temp directory will be cleaned up when it goes out of scope
+                // Spooled events already passed the policy, so don't verify them twice
+                let path = spool.path.clone();
+                _stdin_spool = Some(spool);
+                (path, input::InputFormat::Auto, validation::ValidationPolicy::permissive())
+            };
+            
+            let mut nip11_args = nip11.clone();
+            let mut build_args = build.clone();
+            let mut _merged_dir = None;
+            let (input_path, input_format, input_policy) = match append {
+                Some(cassette_path) => {
+                    let (temp_dir, merged_path) = prepare_append(cassette_path, &input_path, input_format, &input_policy, &mut nip11_args, &mut build_args, *verbose)?;
+                    _merged_dir = Some(temp_dir);
+                    // The cassette's own events were checked when it was recorded
+                    (merged_path, input::InputFormat::Auto, validation::ValidationPolicy::permissive())
+                }
+                None => (input_path, input_format, input_policy),
+            };
+            
+            process_events(
+                &input_path.to_string_lossy(),
+                input_format,
+                &sanitized_name,
+                &output_value,
+                *no_bindings,
+                *interactive,
+                *verbose,
+                &input_policy,
+                *skip_unicode_check,
+                *_nip_11,
+                *nip_42,
+                *nip_45,
+                *nip_50,
+                &nip11_args,
+                &build_args
+            )?;
+            
+            if let Some(cassette_path) = append_target {
+                let generated_path = output_value.join(format!("{}.cassette", sanitized_name));
+                if generated_path != *cassette_path {
+                    fs::rename(&generated_path, cassette_path)
+                        .context("Failed to replace the appended cassette")?;
+                }
+                println!("✅ Appended to {}", cassette_path.display());
             }
             
             Ok(())
@@ -4518,6 +6541,11 @@ async fn main() -> Result<()> {
             output,
             name,
             filter,
+            filter_for,
+            exclude,
+            subtract,
+            provenance,
+            jobs,
             kinds,
             authors,
             limit,
@@ -4525,12 +6553,11 @@ async fn main() -> Result<()> {
             until,
             interactive,
             verbose,
+            recompile,
+            policy,
+            redact,
             nip11,
         } => {
-            // Check dependencies before proceeding
-            let dep_check = deps::DependencyCheck::new();
-            dep_check.check_for_dub()?;
-            
             // Check if required parameters are missing
             if cassettes.is_empty() || output.is_none() {
                 if cassettes.is_empty() {
@@ -4546,11 +6573,24 @@ async fn main() -> Result<()> {
                 eprintln!("Options:");
                 eprintln!("  -n, --name <NAME>           Name for the generated cassette");
                 eprintln!("  -f, --filter <JSON>         Filter JSON (can be specified multiple times)");
+                eprintln!("      --filter-for <INPUT> <JSON>  Filter JSON for one input only");
+                eprintln!("      --exclude <JSON>        Leave out events matching this filter");
+                eprintln!("      --subtract              Keep only the first input's events the others lack");
+                eprintln!("      --provenance            Record which input each event came from");
+                eprintln!("  -j, --jobs <N>              Cassettes to compile and read at once");
                 eprintln!("  -k, --kinds <KINDS>         Event kinds to filter");
                 eprintln!("      --authors <AUTHORS>     Authors to filter");
                 eprintln!("  -l, --limit <LIMIT>         Limit number of events");
                 eprintln!("      --since <TIMESTAMP>     Events after timestamp");
                 eprintln!("      --until <TIMESTAMP>     Events before timestamp");
+                eprintln!("      --skip-validation       Accept events without checking id, signature or tags");
+                eprintln!("      --skip-signatures       Check ids and tags but not signatures");
+                eprintln!("      --max-content-bytes <N> Reject events with larger content");
+                eprintln!("      --allow-kinds <KINDS>   Only accept these kinds (comma-separated)");
+                eprintln!("      --deny-kinds <KINDS>    Reject these kinds (comma-separated)");
+                eprintln!("      --strip-tag <NAME>      Remove tags with this name from events");
+                eprintln!("      --redact-content <RE>   Replace regex matches in content with [redacted]");
+                eprintln!("      --recompile             Compile even when an input's player could be reused");
                 eprintln!("  -i, --interactive           Enable interactive mode");
                 eprintln!("  -v, --verbose               Show verbose output");
                 eprintln!("  -h, --help                  Print help\n");
@@ -4560,6 +6600,12 @@ async fn main() -> Result<()> {
                 eprintln!("  ");
                 eprintln!("  # Merge with filters");
                 eprintln!("  cassette dub *.wasm filtered.wasm --kinds 1 --since 1700000000");
+                eprintln!("  ");
+                eprintln!("  # Filter each input on its own, and drop DMs from all of them");
+                eprintln!("  cassette dub a.wasm b.wasm mix.wasm --filter-for a '{{\"kinds\":[1]}}' --exclude '{{\"kinds\":[4]}}'");
+                eprintln!("  ");
+                eprintln!("  # Events of base.wasm missing from updates.wasm");
+                eprintln!("  cassette dub --subtract base.wasm updates.wasm delta.wasm");
                 return Ok(());
             }
             
@@ -4568,6 +6614,8 @@ async fn main() -> Result<()> {
                 output.as_ref().unwrap(),
                 name.as_deref(),
                 filter,
+                filter_for,
+                exclude,
                 kinds,
                 authors,
                 *limit,
@@ -4576,10 +6624,129 @@ async fn main() -> Result<()> {
                 *interactive,
                 *verbose,
                 nip11,
+                &BuildArgs { redact: redact.clone(), ..Default::default() },
+                &policy.policy(),
+                if *subtract { "subtract" } else { "dub" },
+                *subtract,
+                *provenance,
+                !*recompile,
+                *jobs,
             )
         }
-        Commands::Scrub {
+        Commands::Slice {
+            cassette,
+            output,
+            name,
+            filter,
+            kinds,
+            authors,
+            limit,
+            since,
+            until,
+            verbose,
+            build,
+            nip11,
+        } => {
+            if cassette.is_none() || output.is_none() {
+                if cassette.is_none() {
+                    eprintln!("Error: Missing required source cassette\n");
+                } else {
+                    eprintln!("Error: Missing required --output path\n");
+                }
+                eprintln!("Usage: cassette slice <CASSETTE> --output <OUTPUT> [OPTIONS]\n");
+                eprintln!("Extract the events matching a filter into a new cassette\n");
+                eprintln!("Arguments:");
+                eprintln!("  <CASSETTE>  Source cassette file\n");
+                eprintln!("Options:");
+                eprintln!("  -o, --output <OUTPUT>       Output cassette file path (required)");
+                eprintln!("  -n, --name <NAME>           Name for the generated cassette");
+                eprintln!("  -f, --filter <JSON>         Filter JSON (can be specified multiple times)");
+                eprintln!("  -k, --kinds <KINDS>         Event kinds to keep");
+                eprintln!("      --authors <AUTHORS>     Authors to keep (hex or npub)");
+                eprintln!("  -l, --limit <LIMIT>         Limit number of events");
+                eprintln!("      --since <TIMESTAMP>     Events after timestamp");
+                eprintln!("      --until <TIMESTAMP>     Events before timestamp");
+                eprintln!("  -v, --verbose               Show verbose output");
+                eprintln!("  -h, --help                  Print help\n");
+                eprintln!("Examples:");
+                eprintln!("  # Extract long-form posts by one author");
+                eprintln!("  cassette slice archive.cassette --kinds 30023 --authors npub1... -o blog.cassette");
+                return Ok(());
+            }
+            
+            process_slice_command(
+                cassette.as_ref().unwrap(),
+                output.as_ref().unwrap(),
+                name.as_deref(),
+                filter,
+                kinds,
+                authors,
+                *limit,
+                *since,
+                *until,
+                *verbose,
+                build,
+                nip11,
+            )
+        }
+        Commands::Split {
             cassette,
+            by,
+            chunk_events,
+            output,
+            name,
+            verbose,
+            build,
+            nip11,
+        } => {
+            let Some(cassette) = cassette else {
+                eprintln!("Error: Missing required source cassette\n");
+                eprintln!("Usage: cassette split <CASSETTE> --by <KEY> [OPTIONS]\n");
+                eprintln!("Split a cassette into smaller ones, the inverse of dub\n");
+                eprintln!("Options:");
+                eprintln!("      --by <KEY>              One cassette per month, kind or author");
+                eprintln!("      --chunk-events <COUNT>  At most this many events per cassette");
+                eprintln!("  -o, --output <DIR>          Directory for the parts and manifest");
+                eprintln!("  -n, --name <NAME>           Name of the parts and manifest");
+                eprintln!("  -v, --verbose               Show verbose output");
+                eprintln!("  -h, --help                  Print help\n");
+                eprintln!("Examples:");
+                eprintln!("  cassette split big.wasm --by month -o ./out/");
+                return Ok(());
+            };
+            
+            process_split_command(cassette, output, name.as_deref(), *by, *chunk_events, *verbose, build, nip11)
+        }
+        Commands::Inspect { cassette, lineage, provenance, json } => {
+            let Some(cassette) = cassette else {
+                eprintln!("Error: Missing required cassette file\n");
+                eprintln!("Usage: cassette inspect <CASSETTE> [OPTIONS]\n");
+                eprintln!("Options:");
+                eprintln!("      --lineage               Show the chain of cassettes this one was derived from");
+                eprintln!("      --provenance <EVENT_ID> Show which dubbed input an event came from");
+                eprintln!("      --json                  Print the report as JSON");
+                eprintln!("  -h, --help                  Print help");
+                return Ok(());
+            };
+            
+            process_inspect_command(cassette, *lineage, provenance.as_deref(), *json)
+        }
+        Commands::Verify { cassette, pubkey } => process_verify_command(cassette, pubkey.as_deref()),
+        Commands::Validate { cassette, json } => {
+            let Some(cassette) = cassette else {
+                eprintln!("Error: Missing required cassette file\n");
+                eprintln!("Usage: cassette validate <CASSETTE> [OPTIONS]\n");
+                eprintln!("Check a cassette's exports, memory ABI and events; exits nonzero when a check fails\n");
+                eprintln!("Options:");
+                eprintln!("      --json                  Print the report as JSON");
+                eprintln!("  -h, --help                  Print help");
+                return Ok(());
+            };
+            
+            process_validate_command(cassette, *json)
+        }
+        Commands::Scrub {
+            cassettes,
             subscription,
             filter,
             kinds,
@@ -4590,19 +6757,33 @@ async fn main() -> Result<()> {
             output,
             interactive,
             verbose,
-            _skip_validation,
+            policy,
             info,
             count,
             search,
+            d,
+            address,
+            ids,
+            tag,
+            and_tag,
+            offset,
+            cursor,
+            page_size,
+            shuffle,
+            stream,
+            exclude_sensitive,
+            label,
+            labeler,
+            verified_cache,
             nip11,
         } => {
             // Check if cassette is provided
-            if cassette.is_none() {
+            if cassettes.is_empty() {
                 eprintln!("Error: Missing required cassette file\n");
-                eprintln!("Usage: cassette scrub <CASSETTE> [OPTIONS]\n");
+                eprintln!("Usage: cassette scrub <CASSETTES>... [OPTIONS]\n");
                 eprintln!("Scrub through cassette events (send REQ messages and get events)\n");
                 eprintln!("Arguments:");
-                eprintln!("  <CASSETTE>  Path to the cassette WASM file\n");
+                eprintln!("  <CASSETTES>...  Cassette WASM files or globs, or a <name>.segments.json manifest\n");
                 eprintln!("Options:");
                 eprintln!("  -s, --subscription <ID>     Subscription ID (default: sub1)");
                 eprintln!("  -f, --filter <JSON>         Filter JSON (can be specified multiple times)");
@@ -4611,10 +6792,26 @@ async fn main() -> Result<()> {
                 eprintln!("  -l, --limit <LIMIT>         Limit number of events");
                 eprintln!("      --since <TIMESTAMP>     Events after timestamp");
                 eprintln!("      --until <TIMESTAMP>     Events before timestamp");
-                eprintln!("  -o, --output <FORMAT>       Output format: nip01, json, or ndjson (default: nip01)");
+                eprintln!("  -o, --output <FORMAT>       nip01, json, ndjson, table, csv or summary (default: nip01)");
                 eprintln!("      --info                  Show NIP-11 relay information");
                 eprintln!("      --count                 Perform COUNT query (NIP-45)");
                 eprintln!("      --search <QUERY>        Search query for NIP-50");
+                eprintln!("      --d <IDENTIFIER>        Events with this d tag (addressable events)");
+                eprintln!("      --address <K:PUBKEY:D>  The addressable event at this coordinate");
+                eprintln!("      --ids <ID>              Events with this id (hex, note or nevent)");
+                eprintln!("      --tag <NAME=VALUE>      Events with this tag, e.g. e=<id> or t=nostr,zaps");
+                eprintln!("      --and-tag <NAME=VALUES> Events with every one of these tag values (NIP-119)");
+                eprintln!("      --offset <N>            Skip this many results, paging through req_page");
+                eprintln!("      --cursor <CURSOR>       Continue from the \"Next page\" cursor of a paged scrub");
+                eprintln!("      --page-size <N>         Events per req_page call when paging");
+                eprintln!("      --shuffle <SEED>        Output events in a reproducible shuffled order");
+                eprintln!("      --stream                Print events as they are read (nip01 or ndjson)");
+                eprintln!("      --exclude-sensitive     Leave out events with a content-warning tag");
+                eprintln!("      --label <SELECTOR>      Events with a NIP-32 label, e.g. ISO-639-1=en or review/quality>=3");
+                eprintln!("      --labeler <PUBKEY>      Only count labels by this pubkey");
+                eprintln!("      --verified-cache        Remember verified signatures next to the cassette");
+                eprintln!("      --skip-validation       Print events without checking id, signature or tags");
+                eprintln!("      --allow-kinds <KINDS>   Only print these kinds (comma-separated)");
                 eprintln!("  -i, --interactive           Enable interactive mode");
                 eprintln!("  -v, --verbose               Show verbose output");
                 eprintln!("  -h, --help                  Print help\n");
@@ -4627,14 +6824,32 @@ async fn main() -> Result<()> {
                 eprintln!("  ");
                 eprintln!("  # Search events");
                 eprintln!("  cassette scrub my-notes.wasm --search \"bitcoin\"");
+                eprintln!("  ");
+                eprintln!("  # Pipe a large archive with constant memory");
+                eprintln!("  cassette scrub archive.cassette -o ndjson --stream | jq .id");
+                eprintln!("  ");
+                eprintln!("  # Query a directory of cassettes as one");
+                eprintln!("  cassette scrub 'archive/*.cassette' --kinds 1 --limit 20");
                 return Ok(());
             }
             
-            let cassette = cassette.as_ref().unwrap();
+            let cassettes = expand_cassette_globs(cassettes)?;
+            let mut filter = filter.clone();
+            if let Some(addressable) = addressable_filter(d, address.as_deref())? {
+                filter.push(addressable.to_string());
+            }
+            if let Some(tags) = tag_filter(ids, tag, and_tag)? {
+                filter.push(tags.to_string());
+            }
+            let filter = &filter;
+            let single = || match cassettes.as_slice() {
+                [cassette] => Ok(cassette),
+                _ => Err(anyhow!("--info and --count take a single cassette")),
+            };
             
             if *info {
                 // Just show NIP-11 info
-                process_info_command(cassette, nip11)
+                process_info_command(single()?, nip11)
             } else if *count {
                 // Generate random subscription ID if using default
                 let sub_id = if subscription == "sub1" {
@@ -4645,7 +6860,7 @@ async fn main() -> Result<()> {
                 
                 // Perform COUNT query
                 process_count_command(
-                    cassette,
+                    single()?,
                     &sub_id,
                     filter,
                     kinds,
@@ -4665,8 +6880,17 @@ async fn main() -> Result<()> {
                     subscription.clone()
                 };
                 
-                process_req_command(
-                    cassette,
+                // Signatures that verified are skipped for the rest of the session, and on
+                // later scrubs too when they are kept next to the cassette
+                let verified = Arc::new(match (*verified_cache, cassettes.as_slice()) {
+                    (false, _) => verified::VerifiedIds::default(),
+                    (true, [cassette]) => verified::VerifiedIds::load(&verified::sidecar_path(cassette))?,
+                    (true, _) => return Err(anyhow!("--verified-cache keeps its file next to a single cassette")),
+                });
+                let scrub_policy = validation::ValidationPolicy { verified: Some(verified.clone()), ..policy.policy() };
+                
+                let result = process_req_command(
+                    &cassettes,
                     &sub_id,
                     filter,
                     kinds,
@@ -4677,23 +6901,59 @@ async fn main() -> Result<()> {
                     output,
                     *interactive,
                     *verbose,
-                    *_skip_validation,
+                    &scrub_policy,
                     nip11,
                     search.as_deref(),
-                )
+                    *shuffle,
+                    *stream,
+                    *exclude_sensitive,
+                    &label.iter().map(|l| labels::Selector::parse(l)).collect::<Result<Vec<_>>>()?,
+                    labeler,
+                    offset.or(*cursor),
+                    *page_size,
+                );
+                match verified.save() {
+                    Ok(saved) if *verbose && saved > 0 => eprintln!("🔏 {} verified signatures added to the cache", saved),
+                    Err(e) => eprintln!("Warning: {:#}", e),
+                    _ => {}
+                }
+                result
             }
         }
         Commands::Play {
             cassettes,
             relays,
+            to_cassette,
             concurrency,
             throttle,
             timeout,
             dry_run,
+            no_probe,
+            resume,
+            checkpoint,
+            max_retries,
+            backoff,
             interactive: _,
-            verbose: _,
+            verbose,
+            playback,
             nip11,
         } => {
+            if let Some(output) = to_cassette {
+                if cassettes.is_empty() {
+                    return Err(anyhow!("No cassettes specified"));
+                }
+                return process_play_to_cassette(
+                    cassettes,
+                    output,
+                    throttle.unwrap_or(0),
+                    *timeout,
+                    *dry_run,
+                    *verbose,
+                    playback,
+                    nip11,
+                ).await;
+            }
+            
             // Check if required parameters are missing
             if cassettes.is_empty() || relays.is_empty() {
                 if cassettes.is_empty() {
@@ -4707,10 +6967,19 @@ async fn main() -> Result<()> {
                 eprintln!("  <CASSETTES...>  Input cassette files to broadcast\n");
                 eprintln!("Options:");
                 eprintln!("  -r, --relays <RELAYS>       Target relay URLs (required)");
+                eprintln!("      --to-cassette <DIR>     Re-ingest into a deck output directory instead of relays");
                 eprintln!("  -c, --concurrency <N>       Max concurrent connections (default: 5)");
                 eprintln!("  -t, --throttle <MS>         Delay between events in ms (default: 100)");
+                eprintln!("      --realtime              Pace events by their original created_at deltas");
+                eprintln!("      --speed <FACTOR>        Replay the original timeline at a multiple, e.g. 10x");
+                eprintln!("      --shuffle <SEED>        Send events in a reproducible shuffled order");
                 eprintln!("      --timeout <SECS>        Connection timeout in seconds (default: 30)");
                 eprintln!("      --dry-run               Preview without sending");
+                eprintln!("      --no-probe              Skip the NIP-11 limit checks before sending");
+                eprintln!("      --resume                Continue a stopped playback from its checkpoint");
+                eprintln!("      --checkpoint <FILE>     Progress file (default: <first cassette>.play.json)");
+                eprintln!("      --max-retries <N>       Resends of a rate-limited event (default: 5)");
+                eprintln!("      --backoff <MS>          First delay after a rate limit, doubling (default: 1000)");
                 eprintln!("  -i, --interactive           Enable interactive mode");
                 eprintln!("  -v, --verbose               Show verbose output");
                 eprintln!("  -h, --help                  Print help\n");
@@ -4723,6 +6992,9 @@ async fn main() -> Result<()> {
                 eprintln!("  ");
                 eprintln!("  # Test with dry-run");
                 eprintln!("  cassette play archive.wasm --relays ws://localhost:7000 --dry-run");
+                eprintln!("  ");
+                eprintln!("  # Migrate old cassettes into a deck archive");
+                eprintln!("  cassette play old/*.cassette --to-cassette ./deck");
                 return Ok(());
             }
             
@@ -4730,10 +7002,20 @@ async fn main() -> Result<()> {
                 cassettes,
                 relays,
                 *concurrency,
-                *throttle,
+                throttle.unwrap_or(100),
                 *timeout,
                 *dry_run,
+                !*no_probe,
+                *verbose,
+                playback,
                 nip11,
+                checkpoint.as_deref(),
+                *resume,
+                retry::RetryPolicy {
+                    max_attempts: max_retries.saturating_add(1),
+                    base: Duration::from_millis(*backoff),
+                    max: retry::MAX_BACKOFF.max(Duration::from_millis(*backoff)),
+                },
             ).await
         }
         Commands::DeprecatedPlay {
@@ -4748,7 +7030,7 @@ async fn main() -> Result<()> {
             output,
             interactive,
             verbose,
-            _skip_validation,
+            policy,
             info,
             count,
             search,
@@ -4804,7 +7086,7 @@ async fn main() -> Result<()> {
                 };
                 
                 process_req_command(
-                    cassette,
+                    std::slice::from_ref(cassette),
                     &sub_id,
                     filter,
                     kinds,
@@ -4815,9 +7097,16 @@ async fn main() -> Result<()> {
                     output,
                     *interactive,
                     *verbose,
-                    *_skip_validation,
+                    &policy.policy(),
                     nip11,
                     search.as_deref(),
+                    None,
+                    false,
+                    false,
+                    &[],
+                    &[],
+                    None,
+                    None,
                 )
             }
         }
@@ -4830,6 +7119,13 @@ async fn main() -> Result<()> {
             tls_cert,
             tls_key,
             verbose,
+            sensitive,
+            require_auth,
+            relay_url,
+            nip11,
+            mute,
+            validation,
+            preload,
         } => {
             // Check if required parameters are missing
             if cassettes.is_empty() {
@@ -4844,6 +7140,15 @@ async fn main() -> Result<()> {
                 eprintln!("      --tls                   Enable HTTPS/WSS");
                 eprintln!("      --tls-cert <PATH>       Path to TLS certificate");
                 eprintln!("      --tls-key <PATH>        Path to TLS key");
+                eprintln!("      --mute-list <FILE>      Hide content muted by a kind 10000 mute list");
+                eprintln!("      --mute-pubkey <PUBKEY>  Apply this operator's mute list from the cassettes");
+                eprintln!("      --validate-responses    Re-verify events cassettes return and drop forgeries");
+                eprintln!("      --sensitive <POLICY>    Serve, exclude or opt-in for content-warning events");
+                eprintln!("      --require-auth          Serve only clients that authenticate (NIP-42)");
+                eprintln!("      --relay-url <URL>       Relay URL AUTH events must name (with --require-auth)");
+                eprintln!("      --relay-name <NAME>     NIP-11 fields served over the cassette's own (also --relay-description,");
+                eprintln!("                              --relay-icon, --relay-countries, --posting-policy, --fees, ...)");
+                eprintln!("      --preload[=N]           Compile and warm up cassettes (or the N newest) at startup");
                 eprintln!("  -v, --verbose               Show verbose output");
                 eprintln!("  -h, --help                  Print help\n");
                 eprintln!("Examples:");
@@ -4867,6 +7172,13 @@ async fn main() -> Result<()> {
                 tls_cert.as_deref(),
                 tls_key.as_deref(),
                 *verbose,
+                *sensitive,
+                *require_auth,
+                relay_url.as_deref(),
+                nip11,
+                mute,
+                validation.validator(),
+                preload.preload,
             ).await
         }
         Commands::Deck {
@@ -4878,7 +7190,9 @@ async fn main() -> Result<()> {
             bind,
             event_limit,
             size_limit,
+            max_archive_bytes,
             duration,
+            rotation_overlap,
             filter,
             kinds,
             authors,
@@ -4886,9 +7200,17 @@ async fn main() -> Result<()> {
             nip_45,
             nip_50,
             verbose,
-            _skip_validation,
+            policy,
+            admin_socket,
+            checkpoint,
+            index_store,
+            peers,
             nip11,
+            mute,
+            validation,
         } => {
+            let admin_socket = admin_socket.clone().unwrap_or_else(|| deck_status::default_socket_path(output));
+            let checkpoint = checkpoint.clone().unwrap_or_else(|| checkpoint::default_checkpoint_path(output));
             match mode.as_str() {
                 "relay" => {
                     process_deck_relay_mode(
@@ -4898,16 +7220,27 @@ async fn main() -> Result<()> {
                         bind,
                         *event_limit,
                         *size_limit,
+                        max_archive_bytes.map(|bytes| bytes as u64),
                         *duration,
+                        Duration::from_secs(*rotation_overlap),
                         *_nip_11,
                         *nip_45,
                         *nip_50,
                         *verbose,
-                        *_skip_validation,
+                        &policy.policy(),
+                        &admin_socket,
+                        *index_store,
+                        peers,
                         nip11,
+                        mute,
+                        validation.validator(),
+                        async { let _ = tokio::signal::ctrl_c().await; },
                     ).await
                 }
                 "record" => {
+                    if !peers.is_empty() {
+                        return Err(anyhow!("--peer needs relay mode: a record-mode deck doesn't accept events from peers"));
+                    }
                     if relays.is_empty() {
                         eprintln!("Error: --relays is required for record mode\n");
                         eprintln!("Usage: cassette deck --mode record --relays <RELAYS...> [OPTIONS]\n");
@@ -4921,6 +7254,8 @@ async fn main() -> Result<()> {
                         eprintln!("      --bind <ADDRESS>        Bind address (default: 127.0.0.1)");
                         eprintln!("  -e, --event-limit <N>       Max events per cassette (default: 10000)");
                         eprintln!("  -s, --size-limit <MB>       Max cassette size in MB (default: 100)");
+                        eprintln!("      --max-archive-bytes <N> Stop accepting events once the archive takes N (e.g. 10GB)");
+                        eprintln!("      --max-event-bytes <N>   Reject events larger than N bytes");
                         eprintln!("  -d, --duration <SECS>       Recording duration per cassette (default: 3600)");
                         eprintln!("  -f, --filter <JSON>         Filter JSON for recording");
                         eprintln!("  -k, --kinds <KINDS>         Event kinds to record");
@@ -4928,6 +7263,10 @@ async fn main() -> Result<()> {
                         eprintln!("      --nip-11                Enable NIP-11 support");
                         eprintln!("      --nip-45                Enable NIP-45 (COUNT) support");
                         eprintln!("      --nip-50                Enable NIP-50 (search) support");
+                        eprintln!("      --mute-list <FILE>      Hide content muted by a kind 10000 mute list");
+                        eprintln!("      --mute-pubkey <PUBKEY>  Apply this operator's mute list from the cassettes");
+                        eprintln!("      --validate-responses    Re-verify events cassettes return and drop forgeries");
+                        eprintln!("      --admin-socket <PATH>   Status socket for `cassette deck-status` (default: <OUTPUT>/deck.sock)");
                         eprintln!("  -v, --verbose               Show verbose output");
                         eprintln!("  -h, --help                  Print help\n");
                         eprintln!("Examples:");
@@ -4949,7 +7288,9 @@ async fn main() -> Result<()> {
                         bind,
                         *event_limit,
                         *size_limit,
+                        max_archive_bytes.map(|bytes| bytes as u64),
                         *duration,
+                        Duration::from_secs(*rotation_overlap),
                         filter.as_deref(),
                         kinds,
                         authors,
@@ -4957,8 +7298,13 @@ async fn main() -> Result<()> {
                         *nip_45,
                         *nip_50,
                         *verbose,
-                        *_skip_validation,
+                        &policy.policy(),
+                        &admin_socket,
+                        &checkpoint,
+                        *index_store,
                         nip11,
+                        mute,
+                        validation.validator(),
                     ).await
                 }
                 _ => Err(anyhow!("Invalid mode: {}. Use 'relay' or 'record'", mode))
@@ -4988,216 +7334,124 @@ async fn main() -> Result<()> {
                 *throttle,
                 *timeout,
                 *dry_run,
+                true,
+                false,
+                &PlaybackArgs::default(),
                 nip11,
+                None,
+                false,
+                retry::RetryPolicy::default(),
             ).await
         }
-    }
-}
-
-/// Preprocess events to handle replaceable and addressable replaceable events according to NIP-01
-/// Returns a filtered list of events with only the latest version of each replaceable event
-fn preprocess_events(events: Vec<Value>) -> Vec<Value> {
-    // Maps to track the latest versions of replaceable events
-    // For replaceable events (10000-19999): Key format: "{pubkey}:{kind}"
-    // For addressable replaceable events (30000-39999): Key format: "{pubkey}:{kind}:{d_tag_value}"
-    let mut replaceable_events: HashMap<String, (usize, i64)> = HashMap::new();
-    let mut duplicates_removed = 0;
-    let mut total_replaceable = 0;
-    let mut total_addressable = 0;
-    
-    // First pass: identify all replaceable events and track their indices
-    for (i, event) in events.iter().enumerate() {
-        if let (Some(kind), Some(pubkey), Some(created_at)) = (
-            event.get("kind").and_then(|k| k.as_i64()),
-            event.get("pubkey").and_then(|p| p.as_str()),
-            event.get("created_at").and_then(|t| t.as_i64())
-        ) {
-            // Handle replaceable events (kinds 10000-19999)
-            if kind >= 10000 && kind <= 19999 {
-                total_replaceable += 1;
-                let key = format!("{}:{}", pubkey, kind);
-                
-                // Check if we already have this event type
-                if let Some((_, existing_ts)) = replaceable_events.get(&key) {
-                    if created_at > *existing_ts {
-                        // This is newer, replace the existing one
-                        replaceable_events.insert(key, (i, created_at));
-                    }
-                    duplicates_removed += 1;
-                } else {
-                    // First time seeing this event type
-                    replaceable_events.insert(key, (i, created_at));
-                }
-            }
-            // Handle addressable replaceable events (kinds 30000-39999)
-            else if kind >= 30000 && kind <= 39999 {
-                total_addressable += 1;
-                // Find the 'd' tag value
-                let d_tag_value = event.get("tags")
-                    .and_then(|tags| tags.as_array())
-                    .and_then(|tags_array| {
-                        tags_array.iter()
-                            .find(|tag| tag.as_array()
-                                .and_then(|t| t.get(0).and_then(|t0| t0.as_str()))
-                                .unwrap_or("") == "d"
-                            )
-                            .and_then(|tag| tag.as_array())
-                            .and_then(|tag_array| tag_array.get(1).and_then(|t1| t1.as_str()))
-                    })
-                    .unwrap_or("");
-                
-                let key = format!("{}:{}:{}", pubkey, kind, d_tag_value);
-                
-                // Check if we already have this event type
-                if let Some((_, existing_ts)) = replaceable_events.get(&key) {
-                    if created_at > *existing_ts {
-                        // This is newer, replace the existing one
-                        replaceable_events.insert(key, (i, created_at));
-                    }
-                    duplicates_removed += 1;
-                } else {
-                    // First time seeing this event type
-                    replaceable_events.insert(key, (i, created_at));
-                }
-            }
+        Commands::BuildPlayer { output, verbose } => {
+            let player = player_module(*verbose)?;
+            fs::write(output, &player).with_context(|| format!("Failed to write {}", output.display()))?;
+            println!("✅ Player module written to {} ({} bytes)", output.display(), player.len());
+            Ok(())
         }
-    }
-    
-    // If no replaceable events were found, return the original list
-    if replaceable_events.is_empty() {
-        return events;
-    }
-    
-    // Second pass: build a new list with only the latest events
-    let mut filtered_events = Vec::with_capacity(events.len() - duplicates_removed);
-    let indices_to_keep: Vec<usize> = replaceable_events.values()
-        .map(|(index, _)| *index)
-        .collect();
-    
-    for (i, event) in events.into_iter().enumerate() {
-        // For regular events or the latest version of replaceable events
-        if let Some(kind) = event.get("kind").and_then(|k| k.as_i64()) {
-            if (kind >= 10000 && kind <= 19999) || (kind >= 30000 && kind <= 39999) {
-                // Only keep this replaceable event if it's in our indices_to_keep list
-                if indices_to_keep.contains(&i) {
-                    filtered_events.push(event);
-                }
-            } else {
-                // Non-replaceable event, keep it
-                filtered_events.push(event);
-            }
-        } else {
-            // If kind is missing, keep the event
-            filtered_events.push(event);
+        Commands::DeckStatus { socket, output, timeout, json } => {
+            let socket = socket.clone().unwrap_or_else(|| deck_status::default_socket_path(output));
+            process_deck_status_command(&socket, Duration::from_secs(*timeout), *json).await
+        }
+        Commands::Bench { cassette, filters, iterations, warmup, json } => {
+            let Some(cassette) = cassette else {
+                eprintln!("Error: Missing required cassette file\n");
+                eprintln!("Usage: cassette bench <CASSETTE> [OPTIONS]\n");
+                eprintln!("Time instantiation and queries of a cassette: latency distribution and throughput\n");
+                eprintln!("Options:");
+                eprintln!("      --filters <FILTERS>     JSON file of queries (default: derived from the events)");
+                eprintln!("  -n, --iterations <N>        Timed runs of instantiation and each query [default: 100]");
+                eprintln!("      --warmup <N>            Untimed runs before each measurement [default: 10]");
+                eprintln!("      --json                  Print the report as JSON");
+                eprintln!("  -h, --help                  Print help");
+                return Ok(());
+            };
+            
+            process_bench_command(cassette, filters.as_deref(), *iterations as usize, *warmup as usize, *json)
+        }
+        Commands::Conformance { cassette, against, queries, no_count, timeout, json, verbose } => {
+            process_conformance_command(
+                cassette,
+                against,
+                queries.as_deref(),
+                !*no_count,
+                Duration::from_secs(*timeout),
+                *json,
+                *verbose,
+            ).await
+        }
+        Commands::Export { cassette, output, to, filter, kinds, authors, since, until } => {
+            let Some(cassette) = cassette else {
+                eprintln!("Error: Missing required cassette file\n");
+                eprintln!("Usage: cassette export <CASSETTE> [OPTIONS]\n");
+                eprintln!("Write a cassette's events out as NDJSON, a JSON array or an SQLite database\n");
+                eprintln!("Options:");
+                eprintln!("  -o, --output <OUTPUT>       Output file; .ndjson, .json or .db picks the format");
+                eprintln!("      --to <FORMAT> [PATH]    Output format (ndjson, json, sqlite) and optional file");
+                eprintln!("  -f, --filter <JSON>         Filter JSON selecting the exported events");
+                eprintln!("  -h, --help                  Print help");
+                return Ok(());
+            };
+            
+            let (format, path) = match (to.first(), to.get(1).map(PathBuf::from).or_else(|| output.clone())) {
+                (Some(format), path) => (export::Format::parse(format)?, path),
+                (None, Some(path)) => (export::Format::from_path(&path), Some(path)),
+                (None, None) => (export::Format::Ndjson, None),
+            };
+            let selection = selection_filter(filter.as_deref(), kinds, authors, *since, *until)?;
+            process_export_command(cassette, &selection, format, path.as_deref())
+        }
+        Commands::Stats { command: Some(StatsCommand::Timeline {
+            cassette, bucket, by, top, filter, kinds, authors, since, until, json, width,
+        }), .. } => {
+            let selection = selection_filter(filter.as_deref(), kinds, authors, *since, *until)?;
+            process_timeline_command(cassette, &selection, *bucket, *by, *top, *json, *width)
+        }
+        Commands::Stats { command: None, cassette, bucket, top, filter, kinds, authors, since, until, output } => {
+            let Some(cassette) = cassette else {
+                eprintln!("Error: Missing required cassette file\n");
+                eprintln!("Usage: cassette stats <CASSETTE> [OPTIONS]");
+                eprintln!("       cassette stats timeline <CASSETTE> [OPTIONS]\n");
+                eprintln!("Per-day or per-week event counts, top authors, kinds and tags, and content size\n");
+                eprintln!("Options:");
+                eprintln!("      --bucket <BUCKET>       Bucket width: 1d or 1w [default: 1d]");
+                eprintln!("      --top <TOP>             Authors, kinds and tag names listed [default: 10]");
+                eprintln!("  -f, --filter <JSON>         Filter JSON selecting the counted events");
+                eprintln!("  -o, --output <OUTPUT>       Output format: json or csv [default: json]");
+                eprintln!("  -h, --help                  Print help");
+                return Ok(());
+            };
+            
+            let selection = selection_filter(filter.as_deref(), kinds, authors, *since, *until)?;
+            process_stats_command(cassette, &selection, *bucket, *top, output)
         }
     }
-    
-    println!("  Found {} replaceable events (kinds 10000-19999)", total_replaceable);
+}
+
+/// Preprocess events to handle replaceable and addressable replaceable events according to NIP-01
+/// Returns a filtered list of events with only the latest version of each replaceable event
+fn preprocess_events(events: Vec<Value>) -> Vec<Value> {
+    let total_events = events.len();
+    let total_replaceable = events.iter()
+        .filter(|event| event.get("kind").and_then(|k| k.as_i64()).is_some_and(replaceable::is_replaceable))
+        .count();
+    let total_addressable = events.iter()
+        .filter(|event| event.get("kind").and_then(|k| k.as_i64()).is_some_and(replaceable::is_addressable))
+        .count();
+    
+    // Keep only the latest version of each replaceable and addressable event
+    let events = replaceable::latest_versions(events);
+    
+    println!("  Found {} replaceable events (kinds 0, 3, 10000-19999)", total_replaceable);
     println!("  Found {} addressable replaceable events (kinds 30000-39999)", total_addressable);
-    println!("  Removed {} older versions of replaceable events", duplicates_removed);
+    println!("  Removed {} older versions of replaceable events", total_events - events.len());
     
-    filtered_events
+    events
 }
 
 /// Parse events from file, supporting JSON array, NDJSON, and NIP-01 message formats
-fn parse_events_from_file(input_file: &str) -> Result<Vec<Value>> {
-    let file = File::open(input_file)?;
-    let mut reader = BufReader::new(file);
-    
-    // Read first character to determine format
-    let mut first_char = [0u8; 1];
-    reader.read_exact(&mut first_char)?;
-    
-    // Reset reader to beginning
-    reader.seek(std::io::SeekFrom::Start(0))?;
-    
-    if first_char[0] == b'[' {
-        // Could be JSON array or NIP-01 message
-        let content = std::fs::read_to_string(input_file)?;
-        let parsed: Value = serde_json::from_str(&content)?;
-        
-        if let Some(arr) = parsed.as_array() {
-            // Check if it's a NIP-01 message format ["EVENT", {...}]
-            if arr.len() == 2 && arr[0].as_str() == Some("EVENT") {
-                // Single NIP-01 message, extract the event
-                if let Some(event) = arr.get(1) {
-                    Ok(vec![event.clone()])
-                } else {
-                    Err(anyhow!("Invalid NIP-01 message format"))
-                }
-            } else if arr.len() >= 1 && arr[0].is_object() {
-                // Regular JSON array of events
-                Ok(arr.clone())
-            } else {
-                // Could be an array of NIP-01 messages
-                let mut events = Vec::new();
-                for item in arr {
-                    if let Some(msg_arr) = item.as_array() {
-                        if msg_arr.len() == 2 && msg_arr[0].as_str() == Some("EVENT") {
-                            if let Some(event) = msg_arr.get(1) {
-                                events.push(event.clone());
-                            }
-                        }
-                    } else if item.is_object() {
-                        // Regular event object
-                        events.push(item.clone());
-                    }
-                }
-                Ok(events)
-            }
-        } else {
-            Err(anyhow!("Expected JSON array"))
-        }
-    } else {
-        // Assume NDJSON format (newline-delimited JSON)
-        let mut events = Vec::new();
-        let lines = reader.lines();
-        
-        for (line_num, line_result) in lines.enumerate() {
-            let line = line_result.context("Failed to read line")?;
-            let trimmed = line.trim();
-            
-            // Skip empty lines
-            if trimmed.is_empty() {
-                continue;
-            }
-            
-            // Try to parse as NIP-01 message first
-            if trimmed.starts_with("[") {
-                match serde_json::from_str::<Value>(trimmed) {
-                    Ok(Value::Array(arr)) if arr.len() == 2 && arr[0].as_str() == Some("EVENT") => {
-                        // NIP-01 message format
-                        if let Some(event) = arr.get(1) {
-                            events.push(event.clone());
-                        }
-                    }
-                    _ => {
-                        // Try as regular event
-                        match serde_json::from_str::<Value>(trimmed) {
-                            Ok(event) if event.is_object() => events.push(event),
-                            _ => eprintln!("Warning: Skipping invalid line {}: {}", line_num + 1, trimmed),
-                        }
-                    }
-                }
-            } else {
-                // Parse as regular JSON object
-                match serde_json::from_str::<Value>(trimmed) {
-                    Ok(event) if event.is_object() => events.push(event),
-                    Ok(_) => eprintln!("Warning: Skipping non-object on line {}", line_num + 1),
-                    Err(e) => {
-                        // Skip invalid lines with a warning
-                        eprintln!("Warning: Skipping invalid JSON on line {}: {}", line_num + 1, e);
-                    }
-                }
-            }
-        }
-        
-        if events.is_empty() {
-            return Err(anyhow!("No valid events found in input file"));
-        }
-        
-        Ok(events)
-    }
+fn parse_events_from_file(input_file: &str, format: input::InputFormat) -> Result<Vec<Value>> {
+    input::read_events(std::path::Path::new(input_file), format)
 }
 
 /// Filter out events containing problematic Unicode characters
@@ -5255,21 +7509,78 @@ fn filter_problematic_unicode_events(events: Vec<Value>, verbose: bool) -> (Vec<
     (filtered_events, skipped_events)
 }
 
+/// Position of an event in [0, 1) derived from a hash of its id
+fn sample_position(event: &Value) -> f64 {
+    let id = event.get("id").and_then(|id| id.as_str()).unwrap_or("");
+    let digest = Sha256::digest(id.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes) as f64 / (u64::MAX as f64 + 1.0)
+}
+
+/// Keep a uniform subset of events, selected by hashing event ids so the sample is reproducible.
+/// With `per_kind`, each kind keeps its own share (at least one event) instead of one global cut.
+fn sample_events(events: Vec<Value>, rate: f64, per_kind: bool) -> Vec<Value> {
+    if !per_kind {
+        return events.into_iter()
+            .filter(|event| sample_position(event) < rate)
+            .collect();
+    }
+    
+    let mut by_kind: HashMap<i64, Vec<(f64, usize)>> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        let kind = event.get("kind").and_then(|k| k.as_i64()).unwrap_or(-1);
+        by_kind.entry(kind).or_default().push((sample_position(event), index));
+    }
+    
+    let mut keep = HashSet::new();
+    for positions in by_kind.values_mut() {
+        positions.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let quota = ((positions.len() as f64 * rate).ceil() as usize).max(1);
+        keep.extend(positions.iter().take(quota).map(|(_, index)| *index));
+    }
+    
+    events.into_iter()
+        .enumerate()
+        .filter(|(index, _)| keep.contains(index))
+        .map(|(_, event)| event)
+        .collect()
+}
+
 fn process_events(
     input_file: &str,
+    input_format: input::InputFormat,
     name: &str,
     output_dir: &PathBuf,
     _no_bindings: bool,
     interactive: bool,
     verbose: bool,
-    validate: bool,
+    policy: &validation::ValidationPolicy,
     skip_unicode_check: bool,
     _nip_11: bool,
     nip_42: bool,
     nip_45: bool,
     nip_50: bool,
     nip11_args: &Nip11Args,
+    build_args: &BuildArgs,
 ) -> Result<()> {
+    if let Some(rate) = build_args.sample {
+        if !(rate > 0.0 && rate <= 1.0) {
+            return Err(anyhow!("--sample must be a fraction between 0 and 1, got {}", rate));
+        }
+    }
+    if let Some(key) = &build_args.sign {
+        signing::Signer::load(key)?;
+    }
+    if let Some(recipient) = &build_args.encrypt {
+        nip19::normalize_pubkey(recipient).context("--encrypt takes the recipient's npub or hex pubkey")?;
+    }
+    let split = build_args.split();
+    if split.is_set() && interactive {
+        return Err(anyhow!("--segment-bytes, --split-events and --split-by can't be combined with --interactive"));
+    }
+    let redaction = build_args.redact.rules()?;
+    
     // Initialize interactive UI if enabled
     let mut record_ui = if interactive {
         let ui = ui::record::RecordUI::new();
@@ -5280,7 +7591,7 @@ fn process_events(
     };
 
     // Parse input file (supports both JSON array and NDJSON)
-    let original_events = parse_events_from_file(input_file)?;
+    let original_events = parse_events_from_file(input_file, input_format)?;
     
     // Display statistics (only in verbose mode)
     debugln!(verbose, "=== Cassette CLI - Record Command ===");
@@ -5332,37 +7643,133 @@ fn process_events(
         eprintln!("   To include these events anyway, use the --skip-unicode-check flag.");
     }
     
+    // Check events against the policy before replaceable events are resolved,
+    // so a rejected event can't shadow an older valid one
+    debugln!(verbose, "\n🔍 Validating Nostr events...");
+    // Signature checks dominate large imports, so events are checked on every core
+    let original_count = filtered_events.len();
+    let (filtered_events, rejected) = policy.partition(filtered_events);
+    let mut rejections = validation::RejectionTally::default();
+    for (id, rejection) in &rejected {
+        debugln!(verbose, "❌ Event {} rejected: {}", id, rejection);
+        rejections.add(rejection.rule);
+    }
+    let valid_count = filtered_events.len();
+    let invalid_count = original_count - valid_count;
+    
+    if verbose {
+        println!("✅ Valid events: {}", valid_count);
+        if invalid_count > 0 {
+            println!("❌ Invalid events filtered out: {}", invalid_count);
+        }
+    }
+    
+    if invalid_count > 0 {
+        println!("⚠️  Filtered out {} invalid events", invalid_count);
+        for line in rejections.summary() {
+            println!("{}", line);
+        }
+        fs::create_dir_all(output_dir)?;
+        let report_path = output_dir.join(format!("{}.rejected.ndjson", name));
+        validation::write_rejection_report(&report_path, &rejected)?;
+        println!("📝 Rejected events (id and reason): {}", report_path.display());
+    }
+    
     // Preprocess events to handle replaceable and addressable events
     debugln!(verbose, "\n🔍 Preprocessing events according to NIP-01...");
     let mut processed_events = preprocess_events(filtered_events);
     
-    // Validate events if validation is enabled
-    if validate {
-        debugln!(verbose, "\n🔍 Validating Nostr events...");
-        let original_count = processed_events.len();
-        processed_events = processed_events.into_iter()
-            .filter(|event| validate_nostr_event(event, verbose))
-            .collect();
-        let valid_count = processed_events.len();
-        let invalid_count = original_count - valid_count;
-        
+    // Deletion requests only count once their signatures have been checked above
+    if !build_args.keep_deleted {
+        let before = processed_events.len();
+        processed_events = cassette_tools::nips::nip09::remove_deleted(processed_events);
+        let deleted = before - processed_events.len();
+        if deleted > 0 {
+            println!("🗑️  Dropped {} events deleted by kind 5 deletion requests (NIP-09)", deleted);
+        }
+    }
+    
+    // Lineage and sampling metadata is embedded in the cassette so the subset can be interpreted later
+    let mut cassette_metadata = build_args.metadata.clone();
+    if build_args.deterministic {
+        if let Some(Value::Object(lineage)) = cassette_metadata.get_mut("lineage") {
+            lineage.insert("created_at".to_string(), json!(reproducible::source_date_epoch()));
+        }
+    }
+    
+    // Custom keys add to (and override) the ones inherited from an appended or sliced cassette
+    if !build_args.meta.is_empty() {
+        let mut meta = match cassette_metadata.remove("meta") {
+            Some(Value::Object(meta)) => meta,
+            _ => serde_json::Map::new(),
+        };
+        for (key, value) in &build_args.meta {
+            meta.insert(key.clone(), json!(value));
+        }
+        cassette_metadata.insert("meta".to_string(), Value::Object(meta));
+    }
+    
+    if let Some(mode) = build_args.validate_zaps {
+        let report = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(zaps::validate_receipts(&processed_events, verbose))
+        });
+        if report.receipts > 0 {
+            println!("⚡ Zap receipts: {} checked, {} invalid, {} unverified",
+                report.receipts, report.invalid.len(), report.unverified);
+        }
         if verbose {
-            println!("✅ Valid events: {}", valid_count);
-            if invalid_count > 0 {
-                println!("❌ Invalid events filtered out: {}", invalid_count);
+            for (id, reason) in &report.invalid {
+                println!("  ❌ Zap receipt {}: {}", id, reason);
             }
         }
-        
-        if invalid_count > 0 {
-            println!("⚠️  Filtered out {} invalid events", invalid_count);
+        if mode == zaps::ZapMode::Drop && !report.invalid.is_empty() {
+            let invalid: HashSet<&str> = report.invalid.iter().map(|(id, _)| id.as_str()).collect();
+            processed_events.retain(|event| !event.get("id").and_then(|i| i.as_str()).is_some_and(|id| invalid.contains(id)));
+        }
+        cassette_metadata.insert("zaps".to_string(), report.metadata(mode));
+    }
+    
+    // Rewritten events no longer match their signatures, so they are listed rather than passed off as signed
+    if !redaction.is_empty() {
+        let (redacted, report) = redaction.apply(processed_events);
+        processed_events = redacted;
+        println!("🕶️  Redaction: rewrote {} events (listed as unsigned in the metadata)", report.unsigned.len());
+        if !report.unsigned.is_empty() && build_args.verify == Some(validation::CassetteVerification::Query) {
+            return Err(anyhow!("--verify=query would hide the {} redacted events; use --verify instead", report.unsigned.len()));
+        }
+        cassette_metadata.insert("redaction".to_string(), redaction.metadata(&report));
+    }
+    
+    if let Some(rate) = build_args.sample {
+        let input_count = processed_events.len();
+        processed_events = sample_events(processed_events, rate, build_args.sample_per_kind);
+        println!("🎲 Sampled {} of {} events (rate {}{})", 
+            processed_events.len(), input_count, rate,
+            if build_args.sample_per_kind { ", per kind" } else { "" });
+        
+        cassette_metadata.insert("sampling".to_string(), json!({
+            "rate": rate,
+            "per_kind": build_args.sample_per_kind,
+            "method": "sha256(id)",
+            "input_events": input_count,
+            "sampled_events": processed_events.len()
+        }));
+    }
+    
+    if let Some(budget) = build_args.max_archive_bytes {
+        let (kept, dropped, dropped_bytes) = sizes::keep_newest_within(processed_events, budget);
+        processed_events = kept;
+        if dropped > 0 {
+            println!("✂️  Archive limit of {}: dropped the {} oldest events ({})",
+                sizes::format_bytes(budget as u64), dropped, sizes::format_bytes(dropped_bytes as u64));
         }
     }
     
+    // Embed in NIP-01 order so cassettes built from the same events come out the same
+    processed_events.sort_by(cassette_tools::newest_first);
+    
     debugln!(verbose, "\n📊 Final Event Summary:");
-    debugln!(verbose, "  Total events after preprocessing{}: {}", 
-        if validate { " and validation" } else { "" }, 
-        processed_events.len()
-    );
+    debugln!(verbose, "  Total events after validation and preprocessing: {}", processed_events.len());
     if !skipped_events.is_empty() {
         debugln!(verbose, "  Events skipped due to Unicode issues: {}", skipped_events.len());
     }
@@ -5390,6 +7797,188 @@ fn process_events(
         }
     }
 
+    let parts = if split.is_set() { split.apply(processed_events) } else { vec![(String::new(), processed_events)] };
+    match parts.len() {
+        count if count > 1 => {
+            let total_bytes: usize = parts.iter().flat_map(|(_, events)| events).map(sizes::event_bytes).sum();
+            println!("🧩 Splitting {} of events into {} segments", sizes::format_bytes(total_bytes as u64), count);
+            let mut entries = Vec::new();
+            for (i, (suffix, events)) in parts.iter().enumerate() {
+                let mut metadata = cassette_metadata.clone();
+                metadata.insert("segment".to_string(), segments::segment_metadata(name, i + 1, count, events));
+                let segment_name = segments::segment_name(name, suffix);
+                let path = generate_cassette(&segment_name, output_dir, events, metadata, None, verbose, nip_42, nip_45, nip_50, nip11_args, build_args)?;
+                println!("  {} {} events", path.display(), events.len());
+                entries.push(segments::manifest_entry(&path, events)?);
+            }
+            let manifest_path = output_dir.join(format!("{}.segments.json", name));
+            fs::write(&manifest_path, serde_json::to_string_pretty(&segments::manifest(name, &split, entries))?)?;
+            println!("📑 Segment manifest: {}", manifest_path.display());
+            Ok(())
+        }
+        _ => {
+            let processed_events = parts.into_iter().next().map(|(_, events)| events).unwrap_or_default();
+            generate_cassette(name, output_dir, &processed_events, cassette_metadata, record_ui, verbose, nip_42, nip_45, nip_50, nip11_args, build_args).map(|_| ())
+        }
+    }
+}
+
+/// `record --watch`: build cassettes from the event files dropped into a directory until Ctrl-C
+async fn watch_directory(
+    dir: &PathBuf,
+    watch_args: &WatchArgs,
+    name: &str,
+    output_dir: &PathBuf,
+    no_bindings: bool,
+    verbose: bool,
+    policy: &validation::ValidationPolicy,
+    skip_unicode_check: bool,
+    nip_11: bool,
+    nip_42: bool,
+    nip_45: bool,
+    nip_50: bool,
+    nip11_args: &Nip11Args,
+    build_args: &BuildArgs,
+) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(anyhow!("Watched directory doesn't exist: {}", dir.display()));
+    }
+    fs::create_dir_all(output_dir)?;
+    let limits = watch_args.limits();
+    let mut watcher = watch::Watcher::new(dir, &watch::state_path(output_dir, name))?;
+    let mut buffer = watch::Buffer::default();
+    let mut rejections = validation::RejectionTally::default();
+    println!("👀 Watching {} for event files (Ctrl-C to stop)", dir.display());
+    println!("📊 Rotation: {} events / {} MB / {} seconds", watch_args.event_limit, watch_args.size_limit, watch_args.duration);
+    
+    let mut stopping = false;
+    while !stopping {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(watch_args.poll_interval.max(1))) => {}
+            _ = tokio::signal::ctrl_c() => {
+                println!("\n⏹️  Stopping, building the buffered events...");
+                stopping = true;
+            }
+        }
+        
+        for path in watcher.poll()? {
+            let events = match input::read_events(&path, input::InputFormat::Auto) {
+                Ok(events) => events,
+                Err(e) => {
+                    eprintln!("⚠️  Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let (accepted, rejected) = policy.partition(events);
+            for (_, rejection) in &rejected {
+                rejections.add(rejection.rule);
+            }
+            let added = buffer.extend(accepted);
+            println!("📥 {}: {} new events{}", path.display(), added,
+                if rejected.is_empty() { String::new() } else { format!(", {} rejected", rejected.len()) });
+        }
+        
+        if buffer.is_full(&limits) || (stopping && !buffer.is_empty()) {
+            let events = buffer.take();
+            let cassette_name = watch::cassette_name(name);
+            println!("📼 Rotating cassette: {} ({} events)", cassette_name, events.len());
+            let temp_dir = tempdir()?;
+            let events_path = temp_dir.path().join("watch_events.json");
+            fs::write(&events_path, serde_json::to_string(&events)?)?;
+            // Events were checked as their files were read
+            process_events(
+                &events_path.to_string_lossy(),
+                input::InputFormat::Json,
+                &cassette_name,
+                output_dir,
+                no_bindings,
+                false,
+                verbose,
+                &validation::ValidationPolicy::permissive(),
+                skip_unicode_check,
+                nip_11,
+                nip_42,
+                nip_45,
+                nip_50,
+                nip11_args,
+                build_args,
+            )?;
+            watcher.commit()?;
+        }
+    }
+    for line in rejections.summary() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Download a relay's history for `record --relays`, sized to the limits in its NIP-11 document
+async fn fetch_relay_history(relay_url: &str, filter: &Value, fetch: &FetchArgs, verbose: bool) -> Result<Vec<Value>> {
+    let mut page_options = fetch.page_options();
+    if !fetch.no_probe {
+        if let Some(info) = relay_info::probe(relay_url, verbose).await {
+            for warning in info.read_warnings(filter) {
+                println!("⚠️  {} {}", relay_url, warning);
+            }
+            // Stay under the relay's own limit instead of learning it from refusals
+            if let Some(max_limit) = info.limitation.max_limit.filter(|max| *max > 0 && *max < page_options.max_limit) {
+                if verbose {
+                    println!("  📏 {} caps limit at {}", relay_url, max_limit);
+                }
+                page_options.max_limit = max_limit;
+            }
+        }
+    }
+    println!("📡 Downloading history from {}", relay_url);
+    fetch::fetch_history(relay_url, filter, page_options, verbose).await
+}
+
+/// Write the events whose ids weren't seen yet as NDJSON, returning how many
+fn write_unseen(events: Vec<Value>, seen: &mut HashSet<String>, out: &mut impl Write) -> Result<usize> {
+    let before = seen.len();
+    for event in events {
+        let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+        if seen.insert(id) {
+            writeln!(out, "{}", event)?;
+        }
+    }
+    Ok(seen.len() - before)
+}
+
+/// zstd level for `record --compress`: slow to record, but cassettes are written once and copied often
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// Compile one cassette from processed events
+fn generate_cassette(
+    name: &str,
+    output_dir: &PathBuf,
+    processed_events: &[Value],
+    mut cassette_metadata: serde_json::Map<String, Value>,
+    record_ui: Option<ui::record::RecordUI>,
+    verbose: bool,
+    nip_42: bool,
+    nip_45: bool,
+    nip_50: bool,
+    nip11_args: &Nip11Args,
+    build_args: &BuildArgs,
+) -> Result<PathBuf> {
+    // Working queries for UIs, derived from what actually went into the cassette; encrypted
+    // cassettes leave them out, along with the indexes, since they'd give the contents away
+    let encrypted = build_args.encrypt.is_some();
+    let query_examples = if encrypted { Vec::new() } else { examples::example_queries(processed_events, nip_45, nip_50) };
+    if !query_examples.is_empty() {
+        cassette_metadata.insert("examples".to_string(), json!(query_examples));
+    }
+    
+    // The manifest signs the events exactly as they are embedded
+    if let Some(key) = &build_args.sign {
+        let signer = signing::Signer::load(key)?;
+        let created_at = if build_args.deterministic { reproducible::source_date_epoch() } else { Utc::now().timestamp() };
+        let manifest = signer.sign_manifest(name, processed_events, created_at, build_args.deterministic)?;
+        println!("🔏 Signed manifest by {} (payload {})", signer.pubkey(), manifest["tags"][2][1].as_str().unwrap_or_default());
+        cassette_metadata.insert("signed_manifest".to_string(), manifest);
+    }
+    
     // Generate metadata
     let cassette_created = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
     let event_count = processed_events.len();
@@ -5413,6 +8002,54 @@ fn process_events(
     let mut events_file = File::create(&events_json_path)?;
     let events_json_string = serde_json::to_string(&processed_events).unwrap();
     events_file.write_all(events_json_string.as_bytes())?;
+    
+    // Sidecar payloads leave only code and indexes in the module
+    let payload_bytes = if build_args.payload_sidecar {
+        let sidecar = payload::write_sidecar(output_dir, &events_json_string)?;
+        debugln!(verbose, "  Payload: {} ({} bytes)", output_dir.join(sidecar["file"].as_str().unwrap_or_default()).display(), events_json_string.len());
+        cassette_metadata.insert("payload".to_string(), sidecar);
+        Some(events_json_string.len())
+    } else {
+        None
+    };
+    
+    // Compressed events are embedded from a file next to lib.rs rather than inline
+    let compressed_bytes = if build_args.compress {
+        let compressed = zstd::encode_all(events_json_string.as_bytes(), COMPRESSION_LEVEL)
+            .context("Failed to compress events")?;
+        fs::write(src_dir.join("events.json.zst"), &compressed)?;
+        let stats = cassette_tools::compress::stats(compressed.len(), events_json_string.len());
+        println!("🗜️  Compressed events from {} to {} bytes ({}x)", events_json_string.len(), compressed.len(), stats["ratio"]);
+        cassette_metadata.insert("compression".to_string(), stats);
+        Some(compressed.len())
+    } else {
+        None
+    };
+
+    // Encrypted events are embedded like compressed ones, as a file next to lib.rs
+    let encrypted_bytes = if let Some(recipient) = &build_args.encrypt {
+        let recipient = nip19::normalize_pubkey(recipient)?;
+        let recipient_bytes: [u8; 32] = hex::decode(&recipient)?.try_into()
+            .map_err(|_| anyhow!("Invalid recipient pubkey {}", recipient))?;
+        // Deterministic builds derive the ephemeral key and nonce from what is sealed, so the
+        // same events and recipient give the same cassette
+        let (ephemeral, nonce): ([u8; 32], [u8; 32]) = if build_args.deterministic {
+            let seed = |label: &[u8]| -> [u8; 32] {
+                Sha256::new().chain_update(label).chain_update(&recipient_bytes).chain_update(events_json_string.as_bytes()).finalize().into()
+            };
+            (seed(b"cassette-encrypt-key"), seed(b"cassette-encrypt-nonce"))
+        } else {
+            (secp256k1::rand::random(), secp256k1::rand::random())
+        };
+        let sealed = cassette_tools::encrypt::seal(events_json_string.as_bytes(), &recipient_bytes, &ephemeral, &nonce)
+            .map_err(|e| anyhow!("Failed to encrypt events: {}", e))?;
+        fs::write(src_dir.join("events.json.enc"), &sealed)?;
+        println!("🔒 Encrypted {} events to {}", event_count, recipient);
+        cassette_metadata.insert("encryption".to_string(), cassette_tools::encrypt::info(&recipient));
+        Some(sealed.len())
+    } else {
+        None
+    };
 
     // Initialize generator with output path and name
     let mut generator = generator::CassetteGenerator::new(
@@ -5425,11 +8062,35 @@ fn process_events(
     generator.set_var("event_count", &event_count.to_string());
     
     // Properly escape the JSON for template insertion
-    generator.set_var("events_json", &events_json_string);
+    match (payload_bytes, encrypted_bytes, compressed_bytes) {
+        (Some(bytes), _, _) => {
+            generator.set_var("events_json", "[]");
+            generator.set_var("payload_bytes", &bytes.to_string());
+        }
+        (None, Some(bytes), _) => {
+            generator.set_var("events_json", "[]");
+            generator.set_var("encrypted_bytes", &bytes.to_string());
+        }
+        (None, None, Some(bytes)) => {
+            generator.set_var("events_json", "[]");
+            generator.set_var("compressed_bytes", &bytes.to_string());
+        }
+        (None, None, None) => generator.set_var("events_json", &events_json_string),
+    }
     
     // Build features array based on NIP flags
     // Always include nip11 since info function should always be available
-    let mut features = vec!["default".to_string(), "nip11".to_string()];
+    // Minimal builds leave out cassette-tools defaults (schema machinery)
+    let mut features = if build_args.minimal {
+        vec!["nip11".to_string()]
+    } else {
+        vec!["default".to_string(), "nip11".to_string()]
+    };
+    if !build_args.keep_deleted {
+        features.push("nip09".to_string());
+    }
+    // Expired events are kept and hidden once the host's clock passes their expiration
+    features.push("nip40".to_string());
     if nip_42 {
         features.push("nip42".to_string());
     }
@@ -5439,42 +8100,138 @@ fn process_events(
     if nip_50 {
         features.push("nip50".to_string());
     }
+    if build_args.writable {
+        features.push("writable".to_string());
+    }
+    if build_args.compress {
+        features.push("compress".to_string());
+    }
+    // Writable cassettes check the signatures of written events, so nobody can post a
+    // newer version of another author's replaceable events
+    if build_args.verify.is_some() || build_args.writable {
+        features.push("verify".to_string());
+    }
+    if encrypted {
+        features.push("encrypt".to_string());
+    }
     
     // Convert features vector to JSON array format for template
     let features_json = serde_json::to_string(&features)?;
     generator.set_var("features_array", &features_json);
     
-    // Set relay metadata for NIP-11 embedding
-    if let Some(relay_name) = &nip11_args.relay_name {
-        generator.set_var("relay_name", relay_name);
+    // Set relay metadata for NIP-11 embedding (only compiled builds put it in a raw string)
+    let relay_info = nip11_args.document()?;
+    generator.set_var("relay_info", &if build_args.fast { relay_info } else { escape_json_for_raw_string(&relay_info) });
+    
+    // Add version from Cargo.toml
+    generator.set_var("version", env!("CARGO_PKG_VERSION"));
+    
+    if build_args.minimal {
+        debugln!(verbose, "  Minimal build: schema machinery and debug logging stripped");
+        generator.set_var("minimal", "true");
     }
-    if let Some(relay_description) = &nip11_args.relay_description {
-        generator.set_var("relay_description", relay_description);
+    
+    if build_args.verify == Some(validation::CassetteVerification::Query) {
+        generator.set_var("verify_on_query", "true");
     }
-    if let Some(relay_pubkey) = &nip11_args.relay_pubkey {
-        generator.set_var("relay_pubkey", relay_pubkey);
+    
+    if build_args.component {
+        debugln!(verbose, "  Component build: exporting the cassette WIT world");
+        generator.set_var("component", "true");
     }
-    if let Some(relay_contact) = &nip11_args.relay_contact {
-        generator.set_var("relay_contact", relay_contact);
+    
+    if build_args.deterministic {
+        debugln!(verbose, "  Deterministic build: build paths remapped, one codegen unit");
+        generator.set_var("deterministic", "true");
     }
     
-    // Add version from Cargo.toml
-    generator.set_var("version", env!("CARGO_PKG_VERSION"));
-
-    // Set verbose mode on generator
-    generator.set_verbose(verbose);
+    // Recorded for inspect, which can't tell from a compiled module which indexes it has
+    let mut indexes = Vec::new();
+    if !build_args.no_index && !encrypted {
+        indexes.push("events");
+        if nip_50 {
+            indexes.push("text");
+        }
+    }
+    if build_args.languages && !encrypted {
+        indexes.push("languages");
+    }
+    cassette_metadata.insert("indexes".to_string(), json!(indexes));
     
-    // Generate the cassette with compilation progress
-    let result = if let Some(ref ui) = record_ui {
-        // Interactive mode - show compilation progress
-        let total_events = processed_events.len() as u64;
-        #[cfg(feature = "deck")]
-        {
-            generator.generate_with_embedded_tools()
+    if !cassette_metadata.is_empty() {
+        // User --meta values end up in the template's raw string literal
+        let cassette_metadata = Value::Object(cassette_metadata).to_string();
+        generator.set_var("cassette_metadata", &if build_args.fast { cassette_metadata } else { escape_json_for_raw_string(&cassette_metadata) });
+    }
+    
+    // The table lists event ids, which encrypted cassettes keep to themselves
+    if let Some(provenance) = build_args.provenance.as_ref().filter(|_| !encrypted) {
+        let provenance = provenance.to_string();
+        generator.set_var("provenance", &if build_args.fast { provenance } else { escape_json_for_raw_string(&provenance) });
+    }
+    
+    // Only the limits given on the command line are embedded; the rest keep cassette-tools defaults
+    let mut filter_limits = serde_json::Map::new();
+    for (key, limit) in [
+        ("max_filters", build_args.max_filters),
+        ("max_tag_filters", build_args.max_tag_filters),
+        ("max_filter_values", build_args.max_filter_values),
+    ] {
+        if let Some(limit) = limit {
+            filter_limits.insert(key.to_string(), json!(limit));
         }
-        #[cfg(not(feature = "deck"))]
-        {
-            generator.generate_with_callback(Some(|| {
+    }
+    if !filter_limits.is_empty() {
+        debugln!(verbose, "  Filter limits: {}", Value::Object(filter_limits.clone()));
+        generator.set_var("filter_limits", &Value::Object(filter_limits).to_string());
+    }
+    
+    if build_args.languages && !encrypted {
+        let segments = language::build_language_segments(&processed_events);
+        if verbose {
+            println!("\n🌐 Language Segments:");
+            for (lang, ids) in &segments {
+                println!("  {}: {} events", lang, ids.len());
+            }
+        }
+        generator.set_var("language_segments", &serde_json::to_string(&segments)?);
+    }
+    
+    // Positions refer to the order of events_json, which the sidecar payload keeps too
+    if !build_args.no_index && !encrypted {
+        let index = cassette_tools::EventIndex::from_events(processed_events);
+        debugln!(verbose, "  Index: {} kinds, {} authors, {} tag names",
+            index.kinds.len(), index.authors.len(), index.tags.len());
+        generator.set_var("event_index", &index.to_json());
+        
+        if nip_50 {
+            let text_index = cassette_tools::TextIndex::from_events(processed_events);
+            debugln!(verbose, "  Text index: {} tokens", text_index.tokens.len());
+            generator.set_var("text_index", &text_index.to_json());
+        }
+    }
+
+    // Set verbose mode on generator
+    generator.set_verbose(verbose);
+    generator.set_cache(!build_args.no_cache);
+    
+    // Generate the cassette with compilation progress
+    let result = if build_args.fast {
+        debugln!(verbose, "  Fast build: injecting the events into the player");
+        match &build_args.player {
+            Some(player) => generator.generate_fast(player),
+            None => player_module(verbose).and_then(|player| generator.generate_fast(&player)),
+        }
+    } else if let Some(ref ui) = record_ui {
+        // Interactive mode - show compilation progress
+        let total_events = processed_events.len() as u64;
+        #[cfg(feature = "deck")]
+        {
+            generator.generate_with_embedded_tools()
+        }
+        #[cfg(not(feature = "deck"))]
+        {
+            generator.generate_with_callback(Some(|| {
                 ui.show_compilation(total_events)?;
                 Ok(())
             }))
@@ -5501,10 +8258,13 @@ fn process_events(
             } else {
                 debugln!(verbose, "  ✅ WASM module generated successfully!");
                 debugln!(verbose, "  Output: {}", wasm_path.display());
+                if build_args.deterministic {
+                    println!("🔒 {} sha256 {}", wasm_path.display(), hex::encode(Sha256::digest(fs::read(&wasm_path)?)));
+                }
                 debugln!(verbose, "\n✅ Cassette creation complete!");
                 debugln!(verbose, "  You can now load this WebAssembly module into the Boombox server.");
             }
-            Ok(())
+            Ok(wasm_path)
         },
         Err(e) => {
             if let Some(ui) = record_ui {
@@ -5515,6 +8275,35 @@ fn process_events(
         }
     }
 }
+/// The player module `record --fast` injects events into, compiled and cached on first use
+/// when this CLI wasn't built with one
+fn player_module(verbose: bool) -> Result<Vec<u8>> {
+    player::module(|path| {
+        println!("🔨 Compiling the cassette player once; later --fast builds reuse it");
+        deps::DependencyCheck::new().check_for_record()?;
+        
+        let temp_dir = tempdir()?;
+        let mut generator = generator::CassetteGenerator::new(
+            temp_dir.path().join("out"),
+            "cassette-player",
+            &temp_dir.path().join("project"),
+        );
+        generator.set_var("player", "true");
+        generator.set_var("event_count", "0");
+        generator.set_var("events_json", "[]");
+        generator.set_var("version", env!("CARGO_PKG_VERSION"));
+        generator.set_var("features_array", &serde_json::to_string(&player::FEATURES)?);
+        generator.set_verbose(verbose);
+        
+        #[cfg(feature = "deck")]
+        let built = generator.generate_with_embedded_tools()?;
+        #[cfg(not(feature = "deck"))]
+        let built = generator.generate()?;
+        fs::copy(&built, path).context("Failed to copy the compiled player")?;
+        Ok(())
+    })
+}
+
 // Play command implementation
 
 #[derive(Clone)]
@@ -5524,6 +8313,43 @@ struct RelayStatus {
     total: usize,
     successful: usize,
     failed: usize,
+    /// Rate-limited events waiting to be sent again
+    retrying: usize,
+    rejections: retry::Rejections,
+}
+
+/// How publishes to a relay are spaced out
+#[derive(Clone)]
+enum Pacing {
+    /// Fixed delay after each event
+    Throttle(tokio::time::Duration),
+    /// Send each event at its offset from the start of playback
+    Timeline(Vec<tokio::time::Duration>),
+}
+
+/// Offsets from the start of playback for each event, scaled from their created_at deltas
+fn timeline_offsets(events: &[Value], speed: f64) -> Vec<tokio::time::Duration> {
+    let created_at = |event: &Value| event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0);
+    let start = events.iter().map(created_at).min().unwrap_or(0);
+    
+    events.iter()
+        .map(|event| {
+            let delta = (created_at(event) - start).max(0) as f64;
+            tokio::time::Duration::from_secs_f64(delta / speed)
+        })
+        .collect()
+}
+
+/// Reorder events pseudo-randomly by hashing each id with the seed, so the
+/// order is reproducible and independent of the order events were read in
+fn shuffle_events(events: &mut [Value], seed: u64) {
+    events.sort_by_cached_key(|event| {
+        let id = event.get("id").and_then(|id| id.as_str()).unwrap_or("");
+        let mut hasher = Sha256::new();
+        hasher.update(seed.to_le_bytes());
+        hasher.update(id.as_bytes());
+        hasher.finalize()
+    });
 }
 
 async fn process_play_command(
@@ -5533,7 +8359,13 @@ async fn process_play_command(
     throttle_ms: u64,
     timeout_secs: u64,
     dry_run: bool,
+    probe: bool,
+    verbose: bool,
+    playback_args: &PlaybackArgs,
     nip11_args: &Nip11Args,
+    checkpoint_path: Option<&std::path::Path>,
+    resume: bool,
+    retry_policy: retry::RetryPolicy,
 ) -> Result<()> {
     if cassette_paths.is_empty() {
         return Err(anyhow!("No cassettes specified"));
@@ -5542,6 +8374,7 @@ async fn process_play_command(
     if relay_urls.is_empty() {
         return Err(anyhow!("No relays specified"));
     }
+    let relay_urls = &nip19::resolve_relays(relay_urls)?;
     
     println!("🎯 Playing events from {} cassette(s) to {} relay(s)", 
         cassette_paths.len(), relay_urls.len());
@@ -5584,14 +8417,80 @@ async fn process_play_command(
     
     println!("\n📊 Total unique events to play: {}", all_events.len());
     
+    // Timeline pacing replays events in the order they were created
+    let pacing = match playback_args.timeline_speed() {
+        Some(speed) => {
+            all_events.sort_by_key(|event| event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0));
+            let offsets = timeline_offsets(&all_events, speed);
+            let duration = offsets.last().copied().unwrap_or_default();
+            println!("⏱️  Replaying original timeline at {}x (~{}s)", speed, duration.as_secs());
+            Pacing::Timeline(offsets)
+        }
+        None => Pacing::Throttle(tokio::time::Duration::from_millis(throttle_ms)),
+    };
+    
+    if let Some(seed) = playback_args.shuffle {
+        shuffle_events(&mut all_events, seed);
+        println!("🔀 Shuffled event order with seed {}", seed);
+    }
+    
+    // Check each relay's published limits so refusals show up here and not as failed OKs
+    let mut relay_limits = vec![None; relay_urls.len()];
+    if probe {
+        println!("\n🔎 Checking relay capabilities...");
+        let now = relay_info::unix_now();
+        for (idx, relay_url) in relay_urls.iter().enumerate() {
+            let Some(info) = relay_info::probe(relay_url, verbose).await else { continue };
+            for warning in info.write_warnings() {
+                println!("  ⚠️  {} {}", relay_url, warning);
+            }
+            let mut refused: HashMap<&str, usize> = HashMap::new();
+            for event in &all_events {
+                if let Some(reason) = info.limitation.rejects(event, now) {
+                    *refused.entry(reason).or_default() += 1;
+                }
+            }
+            if !refused.is_empty() {
+                let total: usize = refused.values().sum();
+                let reasons: Vec<String> = refused.iter().map(|(reason, n)| format!("{} {}", n, reason)).collect();
+                println!("  ⚠️  {} would refuse {} event(s) ({}), skipping them", relay_url, total, reasons.join(", "));
+            }
+            relay_limits[idx] = Some(info.limitation);
+        }
+    }
+    
+    // Each relay's progress through the playlist is kept so a stopped playback can resume
+    let checkpoint_path = checkpoint_path.map(PathBuf::from)
+        .unwrap_or_else(|| checkpoint::default_play_checkpoint_path(&cassette_paths[0]));
+    let checkpoint = Arc::new(if resume {
+        checkpoint::PlayCheckpoint::load(&checkpoint_path)?
+    } else {
+        checkpoint::PlayCheckpoint::start(&checkpoint_path)
+    });
+    let starts = relay_urls.iter()
+        .map(|relay_url| checkpoint.resume_at(relay_url, &all_events))
+        .collect::<Result<Vec<_>>>()?;
+    if resume {
+        println!("\n↩️  Resuming from {}", checkpoint_path.display());
+        for (relay_url, &start) in relay_urls.iter().zip(&starts) {
+            if start == all_events.len() {
+                println!("  {} already answered every event", relay_url);
+            } else {
+                println!("  {} continues at event {} of {}", relay_url, start + 1, all_events.len());
+            }
+        }
+    }
+    
     // Initialize relay status tracking
     let relay_statuses = Arc::new(Mutex::new(
-        relay_urls.iter().enumerate().map(|(_idx, url)| RelayStatus {
+        relay_urls.iter().zip(&starts).map(|(url, start)| RelayStatus {
             url: url.clone(),
             connected: false,
-            total: all_events.len(),
+            total: all_events.len() - start,
             successful: 0,
             failed: 0,
+            retrying: 0,
+            rejections: retry::Rejections::default(),
         }).collect::<Vec<_>>()
     ));
     
@@ -5611,17 +8510,25 @@ async fn process_play_command(
     // Broadcast to all relays concurrently
     let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency));
     let timeout = tokio::time::Duration::from_secs(timeout_secs);
-    let throttle = tokio::time::Duration::from_millis(throttle_ms);
     
     let tasks: Vec<_> = relay_urls.iter().enumerate().map(|(idx, relay_url)| {
-        let events = all_events.clone();
+        let start = starts[idx];
+        let events = all_events[start..].to_vec();
         let relay_url = relay_url.clone();
         let statuses = relay_statuses.clone();
         let semaphore = semaphore.clone();
+        let pacing = pacing.clone();
+        let limits = relay_limits[idx].take();
+        let checkpoint = checkpoint.clone();
         
         tokio::spawn(async move {
             let _permit = semaphore.acquire().await.unwrap();
-            play_to_relay(idx, relay_url, events, statuses, timeout, throttle).await
+            let result = play_to_relay(idx, relay_url, events, start, statuses, timeout, pacing, limits, retry_policy, &checkpoint).await;
+            // Whatever the relay got through, even when it dropped halfway
+            if let Err(e) = checkpoint.save() {
+                eprintln!("\n⚠️  {:#}", e);
+            }
+            result
         })
     }).collect();
     
@@ -5667,72 +8574,141 @@ async fn process_play_command(
             status.url, status.successful, status.total, success_rate);
     }
     
+    // What each relay refused for good, and why
+    if statuses.iter().any(|status| status.rejections.total() > 0) {
+        println!("\n❌ Rejected events:");
+        for status in statuses.iter().filter(|status| status.rejections.total() > 0) {
+            println!("  {} ({} rejected)", status.url, status.rejections.total());
+            for line in status.rejections.report(verbose) {
+                println!("  {}", line);
+            }
+        }
+    }
+    
     // Check for errors
-    for result in results {
-        if let Err(e) = result {
-            eprintln!("\n⚠️  Task error: {}", e);
+    let mut stopped = false;
+    for (relay_url, result) in relay_urls.iter().zip(results) {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                stopped = true;
+                eprintln!("\n⚠️  {} stopped: {:#}", relay_url, e);
+            }
+            Err(e) => {
+                stopped = true;
+                eprintln!("\n⚠️  Task error: {}", e);
+            }
         }
     }
     
+    if stopped {
+        println!("\n↩️  Progress saved to {}; run the same command with --resume to continue", checkpoint.path().display());
+    } else {
+        // Every relay answered every event: nothing left to resume
+        let _ = fs::remove_file(checkpoint.path());
+    }
+    
     Ok(())
 }
 
+/// Play cassettes into a deck output directory: a relay-mode deck runs on a loopback port
+/// for the duration of the playback and saves what it accepted before returning
+async fn process_play_to_cassette(
+    cassette_paths: &[PathBuf],
+    output_dir: &PathBuf,
+    throttle_ms: u64,
+    timeout_secs: u64,
+    dry_run: bool,
+    verbose: bool,
+    playback_args: &PlaybackArgs,
+    nip11_args: &Nip11Args,
+) -> Result<()> {
+    if dry_run {
+        let target = vec![output_dir.display().to_string()];
+        return process_play_command(cassette_paths, &target, 1, throttle_ms, timeout_secs, true, false, verbose, playback_args, nip11_args, None, false, retry::RetryPolicy::default()).await;
+    }
+    
+    let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+    let relay_url = format!("ws://127.0.0.1:{}", port);
+    let admin_socket = deck_status::default_socket_path(output_dir);
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let mute_args = MuteArgs::default();
+    let policy = validation::ValidationPolicy::default();
+    
+    let deck = process_deck_relay_mode(
+        "deck",
+        output_dir,
+        port,
+        "127.0.0.1",
+        10000,
+        100,
+        None,
+        0,
+        Duration::ZERO,
+        false,
+        false,
+        false,
+        verbose,
+        &policy,
+        &admin_socket,
+        deck_storage::Backend::Memory,
+        &[],
+        nip11_args,
+        &mute_args,
+        None,
+        async { let _ = stopped.await; },
+    );
+    let play = async {
+        let result = async {
+            // The deck loads the directory's cassettes before it listens
+            let ready = async {
+                while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(timeout_secs), ready).await
+                .map_err(|_| anyhow!("Deck for {} did not start", output_dir.display()))?;
+            process_play_command(cassette_paths, std::slice::from_ref(&relay_url), 1, throttle_ms, timeout_secs, false, false, verbose, playback_args, nip11_args, None, false, retry::RetryPolicy::default()).await
+        }.await;
+        let _ = stop.send(());
+        result
+    };
+    
+    let (deck_result, play_result) = tokio::join!(deck, play);
+    play_result?;
+    deck_result
+}
+
+/// Events per `req_page` call when extracting a whole cassette
+const EXTRACT_PAGE_SIZE: u32 = 1000;
+
 /// Extract all events from a cassette
 fn extract_all_events_from_cassette(cassette_path: &std::path::PathBuf, nip11_args: &Nip11Args) -> Result<Vec<Value>> {
-    use wasmtime::{Store, Module, Instance};
-    
     let engine = wasmtime::Engine::default();
     let module = Module::from_file(&engine, cassette_path)?;
-    let mut store = Store::new(&engine, ());
-    let instance = Instance::new(&mut store, &module, &[])?;
+    let (mut store, guest) = guest::GuestApi::open(&engine, &module, cassette_path)?;
     
     // Set NIP-11 info if provided
-    load_cassette_with_nip11(&mut store, &instance, nip11_args)?;
-    
-    let memory = instance.get_memory(&mut store, "memory")
-        .ok_or_else(|| anyhow!("No memory export found"))?;
-    
-    let alloc_func = instance
-        .get_typed_func::<i32, i32>(&mut store, "alloc_buffer")
-        .or_else(|_| instance.get_typed_func::<i32, i32>(&mut store, "alloc_string"))?;
-    let send_func = instance.get_typed_func::<(i32, i32), i32>(&mut store, "send")
-        .or_else(|_| instance.get_typed_func::<(i32, i32), i32>(&mut store, "req"))?;
-    let dealloc_func = instance.get_typed_func::<(i32, i32), ()>(&mut store, "dealloc_string").ok();
+    load_cassette_with_nip11(&mut store, &guest, nip11_args)?;
     
     // Request all events
     let req_message = json!(["REQ", "play-extract", {}]);
     let req_string = serde_json::to_string(&req_message)?;
-    let req_bytes = req_string.as_bytes();
+    
+    // Cassettes with the paging export hand over the events a page at a time
+    if let Some(mut page) = guest.req_page(&mut store, &req_string, 0, EXTRACT_PAGE_SIZE)? {
+        let mut events = std::mem::take(&mut page.events);
+        while let Some(cursor) = page.next_cursor {
+            page = guest.req_page(&mut store, &req_string, cursor, EXTRACT_PAGE_SIZE)?
+                .ok_or_else(|| anyhow!("Cassette stopped paging"))?;
+            events.append(&mut page.events);
+        }
+        return Ok(events);
+    }
     
     let mut events = Vec::new();
     
-    loop {
-        let req_ptr = alloc_func.call(&mut store, req_bytes.len() as i32)?;
-        if req_ptr == 0 {
-            break;
-        }
-        
-        memory.write(&mut store, req_ptr as usize, req_bytes)?;
-        let result_ptr = send_func.call(&mut store, (req_ptr, req_bytes.len() as i32))?;
-        
-        if let Some(dealloc) = &dealloc_func {
-            dealloc.call(&mut store, (req_ptr, req_bytes.len() as i32))?;
-        }
-        
-        if result_ptr == 0 {
-            break;
-        }
-        
-        let result = read_string_from_memory(&mut store, &instance, &memory, result_ptr)?;
-        
-        if let Ok(get_size_func) = instance.get_typed_func::<i32, i32>(&mut store, "get_allocation_size") {
-            if let Ok(size) = get_size_func.call(&mut store, result_ptr) {
-                if let Some(dealloc) = &dealloc_func {
-                    let _ = dealloc.call(&mut store, (result_ptr, size));
-                }
-            }
-        }
-        
+    while let Some(result) = guest.send(&mut store, &req_string)? {
         let parsed: Value = serde_json::from_str(&result)?;
         if let Some(arr) = parsed.as_array() {
             if arr.len() >= 2 {
@@ -5752,14 +8728,478 @@ fn extract_all_events_from_cassette(cassette_path: &std::path::PathBuf, nip11_ar
     Ok(events)
 }
 
+/// Send the same queries to a cassette and a reference relay and report where they diverge
+/// Count a cassette's matching events into a day or week timeline
+/// The filter `stats`, `stats timeline` and `export` select events with
+fn selection_filter(
+    filter: Option<&str>,
+    kinds: &[i64],
+    authors: &[String],
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Value> {
+    let mut selection = match filter {
+        Some(filter) => serde_json::from_str(filter).context("Failed to parse filter JSON")?,
+        None => serde_json::Map::new(),
+    };
+    nip19::normalize_filter(&mut selection)?;
+    if !kinds.is_empty() {
+        selection.insert("kinds".to_string(), json!(kinds));
+    }
+    if !authors.is_empty() {
+        let authors = authors.iter()
+            .map(|author| nip19::normalize_pubkey(author))
+            .collect::<Result<Vec<_>>>()?;
+        selection.insert("authors".to_string(), json!(authors));
+    }
+    if let Some(since) = since {
+        selection.insert("since".to_string(), json!(since));
+    }
+    if let Some(until) = until {
+        selection.insert("until".to_string(), json!(until));
+    }
+    Ok(Value::Object(selection))
+}
+
+/// Every event of a cassette or segmented archive matching `filter`
+fn selected_events(cassette_path: &PathBuf, filter: &Value) -> Result<Vec<Value>> {
+    if segments::is_manifest(cassette_path) {
+        let mut archive = SegmentedCassette::open(&cassette_path.to_string_lossy(), false)?;
+        Ok(archive.events(&filter.to_string())?.iter()
+            .map(|event| serde_json::from_str(event))
+            .collect::<Result<Vec<Value>, _>>()?)
+    } else {
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, cassette_path)
+            .with_context(|| format!("Failed to load cassette {}", cassette_path.display()))?;
+        collect_cassette_events(cassette_path, &module, &engine, "select", std::slice::from_ref(filter), false)
+    }
+}
+
+fn process_export_command(
+    cassette_path: &PathBuf,
+    filter: &Value,
+    format: export::Format,
+    output: Option<&std::path::Path>,
+) -> Result<()> {
+    let events = selected_events(cassette_path, filter)?;
+    
+    if format == export::Format::Sqlite {
+        let path = output.ok_or_else(|| anyhow!("--to sqlite needs a database file, e.g. --to sqlite events.db"))?;
+        let inserted = export::write_sqlite(&events, path)?;
+        eprintln!(
+            "Exported {} events to {} ({} already there)",
+            inserted,
+            path.display(),
+            events.len() - inserted
+        );
+        return Ok(());
+    }
+    
+    let write = |out: &mut dyn Write| match format {
+        export::Format::Json => export::write_json(&events, out),
+        _ => export::write_ndjson(&events, out),
+    };
+    match output {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let mut out = std::io::BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()?;
+            eprintln!("Exported {} events to {}", events.len(), path.display());
+        }
+        None => write(&mut std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+fn process_stats_command(
+    cassette_path: &PathBuf,
+    filter: &Value,
+    bucket: timeline::Bucket,
+    top: usize,
+    output_format: &str,
+) -> Result<()> {
+    if !matches!(output_format, "json" | "csv") {
+        return Err(anyhow!("Unsupported output format '{}', use json or csv", output_format));
+    }
+    
+    let digest = timeline::Digest::from_events(&selected_events(cassette_path, filter)?, bucket, top);
+    if output_format == "csv" {
+        for line in digest.csv() {
+            println!("{}", line);
+        }
+    } else {
+        println!("{}", serde_json::to_string_pretty(&digest.to_json())?);
+    }
+    Ok(())
+}
+
+fn process_timeline_command(
+    cassette_path: &PathBuf,
+    filter: &Value,
+    bucket: timeline::Bucket,
+    group_by: Option<timeline::GroupBy>,
+    top: usize,
+    json_output: bool,
+    width: usize,
+) -> Result<()> {
+    // Only created_at, kind and pubkey are needed, but a single pass over the events
+    // still beats one COUNT per bucket and series
+    let events = selected_events(cassette_path, filter)?;
+    
+    let timeline = timeline::Timeline::from_events(&events, bucket, group_by, top);
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&timeline.to_json())?);
+    } else {
+        for line in timeline.sparklines(width) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+fn process_bench_command(
+    cassette_path: &PathBuf,
+    filters_path: Option<&std::path::Path>,
+    iterations: usize,
+    warmup: usize,
+    json_output: bool,
+) -> Result<()> {
+    let engine = Engine::default();
+    let started = std::time::Instant::now();
+    let module = Module::from_file(&engine, cassette_path)
+        .with_context(|| format!("Failed to load cassette {}", cassette_path.display()))?;
+    let compile = started.elapsed();
+    
+    let queries = match filters_path {
+        Some(path) => conformance::load_corpus(path)?,
+        None => {
+            let events = collect_cassette_events(cassette_path, &module, &engine, "bench-corpus", &[], false)?;
+            conformance::default_corpus(&events)
+        }
+    };
+    if !json_output {
+        eprintln!("Benchmarking {} queries, {} runs each after {} warm-up runs...", queries.len(), iterations, warmup);
+    }
+    
+    let (samples, _) = bench::time(warmup, iterations, || guest::GuestApi::open(&engine, &module, cassette_path))?;
+    let instantiate = bench::Distribution::from_samples(&samples);
+    
+    // Queries share one instance, as they would behind a loader
+    let (mut store, guest) = guest::GuestApi::open(&engine, &module, cassette_path)?;
+    let mut results = Vec::with_capacity(queries.len());
+    for query in queries {
+        let (samples, events) = bench::time(warmup, iterations, || bench::run_query(&mut store, &guest, &query.filters))?;
+        results.push(bench::QueryBench {
+            events: events.unwrap_or(0),
+            latency: bench::Distribution::from_samples(&samples),
+            query,
+        });
+    }
+    
+    let report = bench::Report {
+        cassette: cassette_path.display().to_string(),
+        compile_us: compile.as_micros() as u64,
+        instantiate,
+        queries: results,
+    };
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+    } else {
+        for line in report.lines() {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+async fn process_conformance_command(
+    cassette_path: &PathBuf,
+    relay_url: &str,
+    queries_path: Option<&std::path::Path>,
+    compare_count: bool,
+    query_timeout: Duration,
+    json_output: bool,
+    verbose: bool,
+) -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::from_file(&engine, cassette_path)
+        .with_context(|| format!("Failed to load cassette {}", cassette_path.display()))?;
+
+    let queries = match queries_path {
+        Some(path) => conformance::load_corpus(path)?,
+        None => {
+            let events = collect_cassette_events(cassette_path, &module, &engine, "conformance-corpus", &[], false)?;
+            conformance::default_corpus(&events)
+        }
+    };
+
+    let (ws_stream, _) = connect_async(relay_url).await
+        .with_context(|| format!("Failed to connect to {}", relay_url))?;
+    let (mut write, mut read) = ws_stream.split();
+    if !json_output {
+        println!("🔬 Comparing {} against {} ({} queries)", cassette_path.display(), relay_url, queries.len());
+    }
+
+    let mut reports = Vec::new();
+    // Once the relay fails to answer a COUNT, don't wait out the timeout on every query
+    let mut count_unsupported: Option<String> = None;
+    for (i, query) in queries.into_iter().enumerate() {
+        let sub_id = format!("conformance-{}", i);
+
+        let relay_events = relay_req(&mut write, &mut read, &sub_id, &query.filters, query_timeout).await
+            .with_context(|| format!("Relay did not answer REQ for \"{}\"", query.label))?;
+        let cassette_events = collect_cassette_events(cassette_path, &module, &engine, &sub_id, &query.filters, false)?;
+        let req = conformance::diff_events(&cassette_events, &relay_events);
+
+        let count = if compare_count {
+            let relay_count = match &count_unsupported {
+                Some(reason) => Err(reason.clone()),
+                None => relay_count(&mut write, &mut read, &sub_id, &query.filters, query_timeout).await,
+            };
+            if let Err(reason) = &relay_count {
+                count_unsupported = Some(reason.clone());
+            }
+            let cassette_count = cassette_count(cassette_path, &module, &engine, &sub_id, &query.filters)?;
+            Some(conformance::CountDiff::compare(cassette_count, relay_count))
+        } else {
+            None
+        };
+
+        let report = conformance::QueryReport { query, req, count };
+        if !json_output {
+            print_conformance_report(&report, verbose);
+        }
+        reports.push(report);
+    }
+    let _ = write.send(Message::Close(None)).await;
+
+    let diverged = reports.iter().filter(|r| !r.is_match()).count();
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        println!("\n{}/{} queries match", reports.len() - diverged, reports.len());
+    }
+    if diverged > 0 {
+        return Err(anyhow!("{} of {} queries diverged from {}", diverged, reports.len(), relay_url));
+    }
+    Ok(())
+}
+
+fn print_conformance_report(report: &conformance::QueryReport, verbose: bool) {
+    let req = &report.req;
+    if report.is_match() {
+        println!("✅ {} ({} events)", report.query.label, req.cassette);
+    } else {
+        println!("❌ {}", report.query.label);
+        println!("   filters: {}", Value::Array(report.query.filters.clone()));
+    }
+    if !req.is_match() {
+        println!("   REQ: cassette returned {}, relay returned {} ({} missing, {} extra, {} duplicated)",
+            req.cassette, req.relay, req.missing.len(), req.extra.len(), req.duplicates.len());
+        if verbose {
+            for id in &req.missing {
+                println!("     - missing {}", id);
+            }
+            for id in &req.extra {
+                println!("     + extra   {}", id);
+            }
+            for id in &req.duplicates {
+                println!("     * dup     {}", id);
+            }
+        }
+    }
+    match &report.count {
+        Some(conformance::CountDiff::Mismatch { cassette, relay }) => {
+            let cassette = cassette.map_or("no answer".to_string(), |c| c.to_string());
+            println!("   COUNT: cassette {}, relay {}", cassette, relay);
+        }
+        Some(conformance::CountDiff::Unsupported { reason }) if verbose => {
+            println!("   COUNT: not compared ({})", reason);
+        }
+        _ => {}
+    }
+}
+
+/// Serve status snapshots of a deck on its admin socket
+#[cfg(unix)]
+async fn spawn_deck_admin_socket(
+    path: &std::path::Path,
+    monitor: Arc<deck_status::DeckMonitor>,
+    recording_state: Arc<RwLock<RecordingState>>,
+    active_cassettes: Arc<RwLock<Vec<(PathBuf, Module, Engine)>>>,
+) -> Result<()> {
+    let listener = deck_status::bind(path).await?;
+    println!("🩺 Status socket: {}", path.display());
+    tokio::spawn(async move {
+        deck_status::serve(listener, || async {
+            let (buffer, started) = {
+                let state = recording_state.read().await;
+                let buffer = deck_status::BufferStatus {
+                    events: state.event_count,
+                    bytes: state.current_size,
+                    compiling: state.is_compiling,
+                    ..Default::default()
+                };
+                (buffer, state.start_time)
+            };
+            let paths: Vec<PathBuf> = active_cassettes.read().await.iter().map(|(path, _, _)| path.clone()).collect();
+            monitor.snapshot(buffer, started, &paths)
+        }).await
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn spawn_deck_admin_socket(
+    _path: &std::path::Path,
+    _monitor: Arc<deck_status::DeckMonitor>,
+    _recording_state: Arc<RwLock<RecordingState>>,
+    _active_cassettes: Arc<RwLock<Vec<(PathBuf, Module, Engine)>>>,
+) -> Result<()> {
+    eprintln!("⚠️  The deck admin socket requires a unix platform; deck-status is unavailable");
+    Ok(())
+}
+
+/// Print a running deck's status; fails when the deck cannot be reached so cron checks can alert on it
+async fn process_deck_status_command(socket: &std::path::Path, wait: Duration, json_output: bool) -> Result<()> {
+    let status = deck_status::fetch(socket, wait).await?;
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    let buffer = &status.buffer;
+    println!("🎛️  Deck ({} mode), up {}", status.mode, format_duration(status.uptime_secs));
+    println!("📥 Buffer: {:.0}% full ({}/{} events, {}/{} bytes){}",
+        buffer.fill() * 100.0, buffer.events, buffer.event_limit, buffer.bytes, buffer.size_limit_bytes,
+        if buffer.compiling { ", compiling" } else { "" });
+    match status.next_rotation_secs {
+        Some(secs) => println!("⏱️  Next rotation: in {} (sooner if the buffer fills)", format_duration(secs)),
+        None => println!("⏱️  Next rotation: when the buffer fills"),
+    }
+    println!("🔌 Connections: {} active, {} total", status.connections.active, status.connections.total);
+    println!("📼 Cassettes: {}", status.cassettes.len());
+    for cassette in &status.cassettes {
+        println!("   {} ({} bytes)", cassette.path, cassette.size_bytes);
+    }
+    if status.recent_errors.is_empty() {
+        println!("✅ No recent errors");
+    } else {
+        println!("⚠️  Recent errors:");
+        for error in &status.recent_errors {
+            let at = chrono::DateTime::from_timestamp(error.at as i64, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| error.at.to_string());
+            println!("   {} {}", at, error.message);
+        }
+    }
+    Ok(())
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        s if s >= 3600 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Send a REQ to a relay and collect its events until EOSE, then CLOSE the subscription
+async fn relay_req<W, R>(write: &mut W, read: &mut R, sub_id: &str, filters: &[Value], wait: Duration) -> Result<Vec<Value>>
+where
+    W: SinkExt<Message> + Unpin,
+    W::Error: std::error::Error + Send + Sync + 'static,
+    R: StreamExt<Item = std::result::Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let mut req = vec![json!("REQ"), json!(sub_id)];
+    req.extend(filters.iter().cloned());
+    write.send(Message::Text(Value::Array(req).to_string())).await?;
+
+    let mut events = Vec::new();
+    loop {
+        let arr = next_relay_message(read, sub_id, wait).await?;
+        match arr[0].as_str() {
+            Some("EVENT") => events.extend(arr.get(2).cloned()),
+            Some("EOSE") => break,
+            Some("CLOSED") => {
+                return Err(anyhow!("subscription closed: {}", arr.get(2).and_then(|m| m.as_str()).unwrap_or("")));
+            }
+            _ => {}
+        }
+    }
+    write.send(Message::Text(json!(["CLOSE", sub_id]).to_string())).await?;
+    Ok(events)
+}
+
+/// Send a COUNT to a relay; the error describes why the relay gave no count
+async fn relay_count<W, R>(write: &mut W, read: &mut R, sub_id: &str, filters: &[Value], wait: Duration) -> std::result::Result<u64, String>
+where
+    W: SinkExt<Message> + Unpin,
+    W::Error: std::error::Error + Send + Sync + 'static,
+    R: StreamExt<Item = std::result::Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let mut count = vec![json!("COUNT"), json!(sub_id)];
+    count.extend(filters.iter().cloned());
+    write.send(Message::Text(Value::Array(count).to_string())).await.map_err(|e| e.to_string())?;
+
+    let arr = next_relay_message(read, sub_id, wait).await.map_err(|e| e.to_string())?;
+    match arr[0].as_str() {
+        Some("COUNT") => arr.get(2).and_then(|c| c.get("count")).and_then(|c| c.as_u64())
+            .ok_or_else(|| "malformed COUNT response".to_string()),
+        Some("CLOSED") => Err(format!("relay closed COUNT: {}", arr.get(2).and_then(|m| m.as_str()).unwrap_or(""))),
+        _ => Err(format!("unexpected response {}", Value::Array(arr))),
+    }
+}
+
+/// Wait for the next relay message addressed to `sub_id`
+///
+/// NOTICEs aren't tied to a subscription, so they are skipped; the last one is
+/// reported if the relay then never answers.
+async fn next_relay_message<R>(read: &mut R, sub_id: &str, wait: Duration) -> Result<Vec<Value>>
+where
+    R: StreamExt<Item = std::result::Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let mut last_notice: Option<String> = None;
+    loop {
+        let message = timeout(wait, read.next()).await
+            .map_err(|_| match &last_notice {
+                Some(notice) => anyhow!("timed out after {}s (last notice: {})", wait.as_secs(), notice),
+                None => anyhow!("timed out after {}s", wait.as_secs()),
+            })?
+            .ok_or_else(|| anyhow!("relay closed the connection"))??;
+        let Message::Text(text) = message else { continue };
+        let Ok(Value::Array(arr)) = serde_json::from_str::<Value>(&text) else { continue };
+        match (arr.first().and_then(|v| v.as_str()), arr.get(1).and_then(|v| v.as_str())) {
+            (Some("NOTICE"), message) => last_notice = message.map(String::from),
+            (Some(_), Some(id)) if id == sub_id => return Ok(arr),
+            _ => continue,
+        }
+    }
+}
+
+/// Send a COUNT to a cassette, returning None when it does not answer with a count
+fn cassette_count(path: &std::path::Path, module: &Module, engine: &Engine, sub_id: &str, filters: &[Value]) -> Result<Option<u64>> {
+    let (mut store, guest) = guest::GuestApi::open(engine, module, path)?;
+    let mut count = vec![json!("COUNT"), json!(sub_id)];
+    count.extend(filters.iter().cloned());
+    guest.count(&mut store, &Value::Array(count).to_string())
+}
+
 /// Play events to a single relay
 async fn play_to_relay(
     idx: usize,
     relay_url: String,
     events: Vec<Value>,
+    start: usize,
     statuses: Arc<Mutex<Vec<RelayStatus>>>,
     timeout: tokio::time::Duration,
-    throttle: tokio::time::Duration,
+    pacing: Pacing,
+    limits: Option<relay_info::Limitation>,
+    retry_policy: retry::RetryPolicy,
+    checkpoint: &checkpoint::PlayCheckpoint,
 ) -> Result<()> {
     // Connect to relay with timeout
     let ws_stream = tokio::time::timeout(
@@ -5776,9 +9216,58 @@ async fn play_to_relay(
     }
     
     let (mut write, mut read) = ws_stream.0.split();
-    
-    // Send events
-    for event in events {
+    let started = tokio::time::Instant::now();
+    let now = relay_info::unix_now();
+    let ids: Vec<String> = events.iter()
+        .map(|event| event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string())
+        .collect();
+    let mut fresh = (start..).zip(events);
+    let mut retries = retry::RetryQueue::default();
+    let mut backoff = retry::Backoff::new(retry_policy);
+    // Playlist position after the last event sent for the first time
+    let mut sent_fresh = start;
+    let mut settled = 0;
+    
+    // Send events; `position` counts from the start of the playlist, resumed or not.
+    // Rate-limited events due again go before the next new one
+    loop {
+        let (position, event, attempts) = if let Some(retry) = retries.pop_due(tokio::time::Instant::now()) {
+            (retry.position, retry.event, retry.attempts)
+        } else if let Some((position, event)) = fresh.next() {
+            sent_fresh = position + 1;
+            
+            // Events over the relay's published limits count as failed without being sent
+            if let Some(reason) = limits.as_ref().and_then(|limits| limits.rejects(&event, now)) {
+                let mut statuses = statuses.lock().await;
+                statuses[idx].failed += 1;
+                statuses[idx].rejections.add(&ids[position - start], &format!("over the relay's limits: {}", reason));
+                drop(statuses);
+                settled += 1;
+                if retries.is_empty() {
+                    checkpoint.advance(&relay_url, position + 1, &ids[position - start]);
+                }
+                continue;
+            }
+            
+            // Wait for the event's slot on the original timeline, counted from the resumed one
+            if let Pacing::Timeline(offsets) = &pacing {
+                tokio::time::sleep_until(started + (offsets[position] - offsets[start])).await;
+            }
+            (position, event, 0)
+        } else if let Some(retry) = retries.pop() {
+            // Only retries left: wait for the next one
+            tokio::time::sleep_until(retry.due).await;
+            (retry.position, retry.event, retry.attempts)
+        } else {
+            break;
+        };
+        let id = &ids[position - start];
+        
+        // A relay that rate-limits gets publishes spaced by its backoff
+        if !backoff.delay().is_zero() {
+            tokio::time::sleep(backoff.delay()).await;
+        }
+        
         let event_msg = json!(["EVENT", event]);
         let msg_text = serde_json::to_string(&event_msg)?;
         
@@ -5786,21 +9275,45 @@ async fn play_to_relay(
         write.send(Message::Text(msg_text)).await?;
         
         // Wait for OK response
-        let success = wait_for_ok(&mut read).await?;
+        let (success, reason) = wait_for_ok(&mut read).await?;
+        let attempts = attempts + 1;
+        let rate_limited = !success && retry::is_rate_limited(&reason);
         
-        // Update status
-        {
+        if rate_limited && attempts < retry_policy.max_attempts {
+            let delay = backoff.limited();
+            retries.push(retry::Retry { position, event, attempts, due: tokio::time::Instant::now() + delay });
+        } else {
+            if success {
+                backoff.accepted();
+            } else if rate_limited {
+                backoff.limited();
+            }
             let mut statuses = statuses.lock().await;
             if success {
                 statuses[idx].successful += 1;
             } else {
                 statuses[idx].failed += 1;
+                statuses[idx].rejections.add(id, &reason);
             }
+            drop(statuses);
+            settled += 1;
+        }
+        statuses.lock().await[idx].retrying = retries.len();
+        
+        // The checkpoint stops short of the first event still waiting for a retry
+        let acknowledged = retries.first_position().unwrap_or(sent_fresh);
+        if acknowledged > start {
+            checkpoint.advance(&relay_url, acknowledged, &ids[acknowledged - 1 - start]);
+        }
+        if settled > 0 && settled % checkpoint::PLAY_SAVE_EVERY == 0 {
+            checkpoint.save()?;
         }
         
         // Throttle between sends
-        if throttle.as_millis() > 0 {
-            tokio::time::sleep(throttle).await;
+        if let Pacing::Throttle(throttle) = pacing {
+            if throttle.as_millis() > 0 {
+                tokio::time::sleep(throttle).await;
+            }
         }
     }
     
@@ -5810,28 +9323,30 @@ async fn play_to_relay(
     Ok(())
 }
 
-/// Wait for OK response from relay
+/// Wait for the relay's OK response: whether it accepted the event, and its reason
 async fn wait_for_ok(
     read: &mut futures_util::stream::SplitStream<
         tokio_tungstenite::WebSocketStream<
             tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>
         >
     >
-) -> Result<bool> {
+) -> Result<(bool, String)> {
     while let Some(msg) = read.next().await {
         match msg? {
             Message::Text(text) => {
                 if let Ok(parsed) = serde_json::from_str::<Vec<Value>>(&text) {
                     if parsed.len() >= 3 && parsed[0] == "OK" {
                         // Check if success (third element is true)
-                        return Ok(parsed[2].as_bool().unwrap_or(false));
+                        let reason = parsed.get(3).and_then(|r| r.as_str()).unwrap_or("").to_string();
+                        return Ok((parsed[2].as_bool().unwrap_or(false), reason));
                     }
                 }
             }
             _ => {}
         }
     }
-    Ok(false)
+    // Not an answer: the event must not count as acknowledged in the checkpoint
+    Err(anyhow!("Connection closed before the relay answered"))
 }
 
 /// Display relay status with ANSI escape codes
@@ -5851,8 +9366,9 @@ async fn display_relay_status(statuses: &Arc<Mutex<Vec<RelayStatus>>>) {
         };
         
         let connection_status = if status.connected { "🟢" } else { "🔴" };
-        println!("{} {} - {}/{} ({:.1}%)", 
-            connection_status, status.url, status.successful, status.total, progress);
+        let retrying = if status.retrying > 0 { format!(" ({} retrying)", status.retrying) } else { String::new() };
+        println!("{} {} - {}/{} ({:.1}%){}\x1b[K", 
+            connection_status, status.url, status.successful, status.total, progress, retrying);
     }
 }
 
@@ -5860,6 +9376,30 @@ async fn display_relay_status(statuses: &Arc<Mutex<Vec<RelayStatus>>>) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cli_definition() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_cassette_wit_copy() {
+        // The CLI keeps its own copy of the WIT so it builds outside the repository
+        let wit = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../wit/cassette.wit");
+        if let Ok(wit) = std::fs::read_to_string(wit) {
+            assert_eq!(wit, include_str!("templates/cassette.wit"), "cli/src/templates/cassette.wit is out of date");
+        }
+    }
+
+    #[test]
+    fn test_escape_json_for_raw_string() {
+        let metadata = json!({"title": "notes \"### and ###", "####": 1}).to_string();
+        let escaped = escape_json_for_raw_string(&metadata);
+        assert!(!escaped.contains("\"###"), "would end the template's r###\"...\"### literal");
+        assert_eq!(serde_json::from_str::<Value>(&escaped).unwrap(), serde_json::from_str::<Value>(&metadata).unwrap());
+        assert_eq!(escape_json_for_raw_string("{\"a\":\"#\"}"), "{\"a\":\"#\"}");
+    }
+
     #[test]
     fn test_sanitize_filename() {
         // Basic cases
@@ -5891,4 +9431,227 @@ mod tests {
         assert_eq!(sanitize_filename("Nostr (Notes & Other Stuff)"), "nostr-notes-other-stuff");
         assert_eq!(sanitize_filename("My Archive 2024"), "my-archive-2024");
     }
+
+    #[test]
+    fn test_nip11_args() {
+        let cli = Cli::try_parse_from([
+            "cassette", "listen", "a.wasm",
+            "--relay-name", "Quoted \"archive\"",
+            "--relay-countries", "CA,US",
+            "--fees", r#"{"admission":[{"amount":1000,"unit":"msats"}]}"#,
+            "--limitation", r#"{"payment_required":true}"#,
+        ]).unwrap();
+        let Commands::Listen { nip11, .. } = cli.command else { panic!("not listen") };
+        
+        let document: Value = serde_json::from_str(&nip11.document().unwrap()).unwrap();
+        assert_eq!(document, json!({
+            "name": "Quoted \"archive\"",
+            "relay_countries": ["CA", "US"],
+            "fees": {"admission": [{"amount": 1000, "unit": "msats"}]},
+            "limitation": {"payment_required": true}
+        }));
+        
+        let served: Value = serde_json::from_str(&overlay_relay_info(
+            r#"{"name":"x","supported_nips":[1],"limitation":{"max_filters":10}}"#,
+            &nip11.relay_info().unwrap(),
+        )).unwrap();
+        assert_eq!(served["supported_nips"], json!([1]));
+        assert_eq!(served["limitation"], json!({"max_filters": 10, "payment_required": true}));
+        
+        let bad = Nip11Args { retention: Some("{".to_string()), ..Nip11Args::default() };
+        assert!(bad.relay_info().unwrap_err().to_string().contains("--retention"));
+    }
+
+    #[test]
+    fn test_parse_meta() {
+        assert_eq!(parse_meta("source=wss://relay.example/?a=b"), Ok(("source".to_string(), "wss://relay.example/?a=b".to_string())));
+        assert_eq!(parse_meta("note="), Ok(("note".to_string(), String::new())));
+        assert!(parse_meta("curator").is_err());
+        assert!(parse_meta("=value").is_err());
+    }
+
+    #[test]
+    fn test_addressable_filter() {
+        let pubkey = "8ac20c99a80a2c09e785533106b26a285ba4580d8f86030223cf4f2805414655";
+        assert_eq!(
+            addressable_filter(&[], Some(&format!("30023:{}:notes:2024", pubkey))).unwrap(),
+            Some(json!({"kinds": [30023], "authors": [pubkey], "#d": ["notes:2024"]}))
+        );
+        assert_eq!(addressable_filter(&["a".to_string(), "b".to_string()], None).unwrap(), Some(json!({"#d": ["a", "b"]})));
+        assert_eq!(addressable_filter(&[], None).unwrap(), None);
+        assert!(addressable_filter(&[], Some(&format!("1:{}:x", pubkey))).is_err());
+        assert!(addressable_filter(&[], Some("30023:only-two")).is_err());
+    }
+    
+    #[test]
+    fn test_expand_cassette_globs() {
+        let dir = tempdir().unwrap();
+        for name in ["a.cassette", "b.wasm", "notes.txt"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let pattern = dir.path().join("*");
+        let paths = expand_cassette_globs(&[pattern, PathBuf::from("not-yet.cassette")]).unwrap();
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains(&dir.path().join("a.cassette")));
+        assert!(paths.contains(&dir.path().join("b.wasm")));
+        assert!(paths.contains(&PathBuf::from("not-yet.cassette")));
+        assert!(expand_cassette_globs(&[dir.path().join("*.json")]).is_err());
+    }
+    
+    #[test]
+    fn test_tag_filter() {
+        let id = "5c83da77af1dec6d7289834998ad7aafbd9e2191396d75ec3cc27f5a77226f36";
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            tag_filter(&strings(&[id]), &strings(&[&format!("e={}", id), "t=nostr,zaps", "t=art"]), &strings(&["t=bitcoin, lightning"])).unwrap(),
+            Some(json!({"ids": [id], "#e": [id], "#t": ["nostr", "zaps", "art"], "&t": ["bitcoin", "lightning"]}))
+        );
+        assert_eq!(tag_filter(&[], &[], &[]).unwrap(), None);
+        assert!(tag_filter(&[], &strings(&["e"]), &[]).is_err());
+        assert!(tag_filter(&[], &[], &strings(&["=x"])).is_err());
+    }
+    
+    #[test]
+    fn test_sample_events() {
+        let events: Vec<Value> = (0..1000)
+            .map(|i| json!({"id": format!("{:064x}", i), "kind": if i < 990 { 1 } else { 7 }}))
+            .collect();
+
+        // Global sampling is roughly uniform and reproducible
+        let sampled = sample_events(events.clone(), 0.1, false);
+        assert!(sampled.len() > 50 && sampled.len() < 150, "got {}", sampled.len());
+        assert_eq!(sampled, sample_events(events.clone(), 0.1, false));
+
+        // Per-kind sampling keeps each kind's share, including rare kinds
+        let sampled = sample_events(events, 0.1, true);
+        let kind_count = |kind: i64| sampled.iter().filter(|e| e["kind"] == kind).count();
+        assert_eq!(kind_count(1), 99);
+        assert_eq!(kind_count(7), 1);
+    }
+
+    #[test]
+    fn test_timeline_offsets() {
+        use std::time::Duration;
+
+        let events = vec![
+            json!({"created_at": 1000}),
+            json!({"created_at": 1010}),
+            json!({"created_at": 1100}),
+        ];
+        assert_eq!(
+            timeline_offsets(&events, 1.0),
+            vec![Duration::from_secs(0), Duration::from_secs(10), Duration::from_secs(100)]
+        );
+        assert_eq!(
+            timeline_offsets(&events, 10.0),
+            vec![Duration::from_secs(0), Duration::from_secs(1), Duration::from_secs(10)]
+        );
+
+        assert_eq!(parse_speed("10x"), Ok(10.0));
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        assert!(parse_speed("0x").is_err());
+        assert!(parse_speed("fast").is_err());
+    }
+
+    #[test]
+    fn test_shuffle_events() {
+        let events: Vec<Value> = (0..50).map(|i| json!({"id": format!("{:064x}", i)})).collect();
+
+        let mut shuffled = events.clone();
+        shuffle_events(&mut shuffled, 42);
+        assert_ne!(shuffled, events);
+
+        // Same seed gives the same order regardless of input order
+        let mut reversed: Vec<Value> = events.iter().rev().cloned().collect();
+        shuffle_events(&mut reversed, 42);
+        assert_eq!(reversed, shuffled);
+
+        let mut other = events.clone();
+        shuffle_events(&mut other, 7);
+        assert_ne!(other, shuffled);
+    }
+
+    #[test]
+    fn test_subscription_tracker() {
+        let event = |sub: &str, id: &str| json!(["EVENT", sub, {"id": id}]).to_string();
+        let mut delivered = SubscriptionTracker::default();
+
+        delivered.observe(r#"["REQ","a",{}]"#);
+        delivered.observe(r#"["REQ","b",{}]"#);
+        assert!(delivered.first_delivery(&event("a", "1")));
+        assert!(!delivered.first_delivery(&event("a", "1")), "second cassette returned the same event");
+        assert!(delivered.first_delivery(&event("b", "1")), "subscriptions are tracked separately");
+        assert!(delivered.first_delivery(r#"["EOSE","a"]"#));
+        assert!(delivered.first_delivery(r#"["EOSE","a"]"#));
+
+        // Re-sending a REQ replaces the subscription, so its events are delivered again
+        delivered.observe(r#"["REQ","a",{"kinds":[1]}]"#);
+        assert!(delivered.first_delivery(&event("a", "1")));
+        delivered.observe(r#"["CLOSE","b"]"#);
+        assert!(delivered.subscriptions.get("b").is_none());
+    }
+
+    #[test]
+    fn test_release_rotated_keeps_overlap() {
+        let cut = SystemTime::now();
+        let mut state = RecordingState {
+            current_events: Vec::new(),
+            event_count: 0,
+            start_time: cut,
+            current_size: 0,
+            is_compiling: true,
+            received_at: HashMap::new(),
+            carried_over: HashSet::new(),
+            archived_bytes: 0,
+            archive_budget: None,
+        };
+        for (id, age) in [("old", 60), ("near", 5), ("late", 0)] {
+            state.current_events.push(json!({"id": id}));
+            state.received_at.insert(id.to_string(), cut - Duration::from_secs(age));
+        }
+        // "late" arrived while the cassette was compiling, so it isn't part of the rotation
+        let rotated = state.current_events[..2].to_vec();
+
+        state.release_rotated(&rotated, cut, Duration::from_secs(10));
+        let ids: Vec<&str> = state.current_events.iter().filter_map(|e| e["id"].as_str()).collect();
+        assert_eq!(ids, vec!["near", "late"]);
+        assert_eq!(state.event_count, 1, "the carried-over event doesn't count towards the limits");
+        assert!(!state.received_at.contains_key("old"));
+
+        // The next rotation writes the carried-over event once more and releases it, even within the window
+        let rotated = state.current_events.clone();
+        state.release_rotated(&rotated, cut, Duration::from_secs(10));
+        let ids: Vec<&str> = state.current_events.iter().filter_map(|e| e["id"].as_str()).collect();
+        assert_eq!(ids, vec!["late"]);
+
+        let rotated = state.current_events.clone();
+        state.release_rotated(&rotated, cut + Duration::from_secs(60), Duration::from_secs(10));
+        assert!(state.current_events.is_empty());
+        assert_eq!(state.current_size, 0);
+    }
+
+    #[test]
+    fn test_deck_event_store() {
+        let mut store = DeckEventStore::new(Box::new(deck_storage::MemoryStorage::default()));
+        let profile = json!({"id": "p1", "kind": 0, "pubkey": "alice", "tags": []});
+        let note = json!({"id": "n1", "kind": 1, "pubkey": "alice", "tags": []});
+
+        assert_eq!(store.add_event(&profile, "client").unwrap(), (true, None));
+        assert_eq!(store.add_event(&note, "wss://relay.example").unwrap(), (true, None));
+        assert_eq!(store.add_event(&note, "client").unwrap(), (false, None));
+        assert_eq!(store.archived().unwrap(), 0, "buffered ids stay out of the storage");
+
+        store.archive(std::slice::from_ref(&note)).unwrap();
+        assert_eq!(store.archived().unwrap(), 1);
+        assert!(store.contains("n1").unwrap());
+
+        // A failed rotation forgets its ids so the events can be sent again
+        store.forget(std::slice::from_ref(&profile));
+        assert!(!store.contains("p1").unwrap());
+
+        let newer = json!({"id": "p2", "kind": 0, "pubkey": "alice", "tags": []});
+        assert_eq!(store.add_event(&newer, "client").unwrap(), (true, Some("p1".to_string())));
+        let article = json!({"id": "a1", "kind": 30023, "pubkey": "alice", "tags": [["d", "post"]]});
+        assert_eq!(replaceable::address(&article).as_deref(), Some("30023:alice:post"));
+    }
 }
\ No newline at end of file
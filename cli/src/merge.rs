@@ -0,0 +1,264 @@
+//! Streaming merge of several cassettes' events for `dub`
+//!
+//! Cassettes serve their events newest first, so the inputs are merged like
+//! sorted runs: a heap holds the next event of every input and the newest of
+//! them is handed out, pulling the following one from the same input. Only
+//! those heads and the ids and replaceable addresses seen so far stay in
+//! memory, never every input's events at once. Duplicates are dropped as they
+//! stream past, and so are older versions of replaceable and addressable
+//! events: newest first, the first version of an address to come by is its
+//! latest. An input that isn't sorted still merges, only the result isn't
+//! fully sorted and an older version can get through, which `record` drops
+//! when it builds the cassette. Inputs can be given their own filters
+//! (`dub --filter-for`): events an input's filters don't match are skipped as
+//! they are read, before they can stand in for a copy another input keeps.
+//!
+//! Subtracting (`dub --subtract`) keeps only what the first input adds to the
+//! others. Copies of one event sort next to each other, so when the first
+//! input's copy comes out of the heap any other input's copy is right behind
+//! it; it is dropped then, as are the first input's versions of replaceable
+//! events another input has a newer version of.
+//!
+//! `prefetch` moves an input onto a thread of its own that reads ahead into a
+//! bounded buffer, so the inputs of a parallel dub (`--jobs`) are drained at
+//! the same time while the merge only ever waits for the slowest head.
+
+use anyhow::Result;
+use cassette_tools::{newest_first, replaceable, Filter};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The next event of an input
+struct Head {
+    event: Value,
+    source: usize,
+}
+
+impl Ord for Head {
+    // The heap pops its greatest entry: the newest event, from the first input on a tie
+    fn cmp(&self, other: &Self) -> Ordering {
+        newest_first(&other.event, &self.event).then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// Newest-first merge of event streams, yielding each event with the index of its input
+pub struct Merge<I> {
+    sources: Vec<I>,
+    // Filters of each input, any of which an event must match; none keeps every event
+    filters: Vec<Vec<Filter>>,
+    heap: BinaryHeap<Head>,
+    started: bool,
+    subtract: bool,
+    ids: HashSet<String>,
+    addresses: HashSet<String>,
+    /// Events read from each input
+    pub read: Vec<usize>,
+    /// Events dropped because an input already gave them
+    pub duplicates: usize,
+    /// Older versions of replaceable and addressable events dropped
+    pub superseded: usize,
+    /// Events of the first input dropped because another input has them, when subtracting
+    pub subtracted: usize,
+}
+
+impl<I: Iterator<Item = Result<Value>>> Merge<I> {
+    pub fn new(sources: Vec<I>) -> Self {
+        Self {
+            read: vec![0; sources.len()],
+            sources,
+            filters: Vec::new(),
+            heap: BinaryHeap::new(),
+            started: false,
+            subtract: false,
+            ids: HashSet::new(),
+            addresses: HashSet::new(),
+            duplicates: 0,
+            superseded: 0,
+            subtracted: 0,
+        }
+    }
+
+    /// Keep only the events of each input that match one of its filters
+    pub fn with_filters(mut self, filters: Vec<Vec<Filter>>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Yield only the first input's events that no other input has
+    pub fn subtracting(mut self) -> Self {
+        self.subtract = true;
+        self
+    }
+
+    fn keeps(&self, source: usize, event: &Value) -> bool {
+        let filters = self.filters.get(source).map(Vec::as_slice).unwrap_or_default();
+        filters.is_empty() || filters.iter().any(|filter| filter.matches(event))
+    }
+
+    fn pull(&mut self, source: usize) -> Result<()> {
+        while let Some(event) = self.sources[source].next().transpose()? {
+            self.read[source] += 1;
+            if self.keeps(source, &event) {
+                self.heap.push(Head { event, source });
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Result<Option<(usize, Value)>> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                self.pull(source)?;
+            }
+        }
+        while let Some(Head { event, source }) = self.heap.pop() {
+            self.pull(source)?;
+            let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("");
+            if !self.ids.insert(id.to_string()) {
+                self.duplicates += 1;
+                continue;
+            }
+            if let Some(address) = replaceable::address(&event) {
+                if !self.addresses.insert(address) {
+                    self.superseded += 1;
+                    continue;
+                }
+            }
+            if self.subtract {
+                if source != 0 {
+                    continue;
+                }
+                if self.heap.peek().is_some_and(|next| next.event.get("id") == event.get("id")) {
+                    self.subtracted += 1;
+                    continue;
+                }
+            }
+            return Ok(Some((source, event)));
+        }
+        Ok(None)
+    }
+}
+
+/// The items of `source`, read on a thread of their own at most `buffer` ahead of the
+/// consumer; the thread stops after an error or once the consumer is dropped
+pub fn prefetch<I>(source: I, buffer: usize) -> impl Iterator<Item = Result<Value>>
+where
+    I: Iterator<Item = Result<Value>> + Send + 'static,
+{
+    let (sender, receiver) = std::sync::mpsc::sync_channel(buffer);
+    std::thread::spawn(move || {
+        for item in source {
+            let failed = item.is_err();
+            if sender.send(item).is_err() || failed {
+                break;
+            }
+        }
+    });
+    receiver.into_iter()
+}
+
+/// Index of the input `name` refers to: its path as given, file name or file stem
+pub fn input_index(inputs: &[PathBuf], name: &str) -> Option<usize> {
+    inputs.iter().position(|input| input.as_path() == Path::new(name))
+        .or_else(|| inputs.iter().position(|input| input.file_name().is_some_and(|n| n == name)))
+        .or_else(|| inputs.iter().position(|input| input.file_stem().is_some_and(|n| n == name)))
+}
+
+impl<I: Iterator<Item = Result<Value>>> Iterator for Merge<I> {
+    type Item = Result<(usize, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        let event = |id: &str, created_at: i64, kind: i64| json!({"id": id, "pubkey": "alice", "created_at": created_at, "kind": kind, "tags": []});
+        let a = vec![event("a3", 30, 1), event("profile-new", 20, 0), event("a1", 10, 1)];
+        let b = vec![event("b4", 40, 1), event("a3", 30, 1), event("profile-old", 15, 0), event("b0", 0, 1)];
+        let sources = vec![a.into_iter().map(Ok), b.into_iter().map(Ok)];
+
+        let mut merge = Merge::new(sources);
+        let merged: Vec<(usize, String)> = merge.by_ref()
+            .map(|item| item.map(|(source, event)| (source, event["id"].as_str().unwrap().to_string())))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(merged, vec![
+            (1, "b4".to_string()),
+            (0, "a3".to_string()),
+            (0, "profile-new".to_string()),
+            (0, "a1".to_string()),
+            (1, "b0".to_string()),
+        ]);
+        assert_eq!(merge.read, vec![3, 4]);
+        assert_eq!((merge.duplicates, merge.superseded), (1, 1));
+
+        let failing: Vec<Box<dyn Iterator<Item = Result<Value>>>> = vec![
+            Box::new(std::iter::once(Ok(event("x", 1, 1)))),
+            Box::new(std::iter::once(Err(anyhow::anyhow!("cassette trapped")))),
+        ];
+        assert!(Merge::new(failing).collect::<Result<Vec<_>>>().is_err());
+
+        // An input's filter skips its events before they can shadow another input's copy
+        let a = vec![event("shared", 30, 7), event("a1", 10, 1)];
+        let b = vec![event("shared", 30, 7), event("b1", 20, 7)];
+        let kinds = |kinds: Value| Filter::from_json(&json!({"kinds": kinds})).unwrap();
+        let mut merge = Merge::new(vec![a.into_iter().map(Ok), b.into_iter().map(Ok)])
+            .with_filters(vec![vec![kinds(json!([1]))], vec![]]);
+        let merged: Vec<(usize, Value)> = merge.by_ref().collect::<Result<_>>().unwrap();
+        let ids: Vec<(usize, &str)> = merged.iter().map(|(source, event)| (*source, event["id"].as_str().unwrap())).collect();
+        assert_eq!(ids, vec![(1, "shared"), (1, "b1"), (0, "a1")]);
+        assert_eq!((merge.read, merge.duplicates), (vec![2, 2], 0));
+
+        // Subtracting keeps what only the first input has, and none of its outdated versions
+        let base = vec![event("new", 50, 1), event("profile-old", 20, 0), event("both", 10, 1)];
+        let updates = vec![event("profile-new", 30, 0), event("both", 10, 1), event("extra", 5, 1)];
+        let mut merge = Merge::new(vec![base.into_iter().map(Ok), updates.into_iter().map(Ok)]).subtracting();
+        let delta: Vec<String> = merge.by_ref()
+            .map(|item| item.map(|(_, event)| event["id"].as_str().unwrap().to_string()))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(delta, vec!["new".to_string()]);
+        assert_eq!((merge.subtracted, merge.superseded), (1, 1));
+
+        // Read ahead on threads, the inputs still merge in order
+        let a: Vec<Value> = (0..100).rev().map(|i| event(&format!("a{}", i), i * 2, 1)).collect();
+        let b: Vec<Value> = (0..100).rev().map(|i| event(&format!("b{}", i), i * 2 + 1, 1)).collect();
+        let prefetched = vec![prefetch(a.into_iter().map(Ok), 4), prefetch(b.into_iter().map(Ok), 4)];
+        let times: Vec<i64> = Merge::new(prefetched)
+            .map(|item| item.map(|(_, event)| event["created_at"].as_i64().unwrap()))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(times, (0..200).rev().collect::<Vec<_>>());
+
+        let inputs = vec![PathBuf::from("archive/alice.cassette"), PathBuf::from("bob.wasm")];
+        assert_eq!(input_index(&inputs, "alice"), Some(0));
+        assert_eq!(input_index(&inputs, "bob.wasm"), Some(1));
+        assert_eq!(input_index(&inputs, "archive/alice.cassette"), Some(0));
+        assert_eq!(input_index(&inputs, "carol"), None);
+    }
+}
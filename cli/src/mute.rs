@@ -0,0 +1,146 @@
+//! Mute-list (NIP-51 kind 10000) moderation for served cassettes
+//!
+//! A mute list's public tags are applied to every query response: muted
+//! pubkeys (`p`), threads (`e`), hashtags (`t`) and words (`word`).
+
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Kind of a NIP-51 mute list
+pub const MUTE_LIST_KIND: i64 = 10000;
+
+#[derive(Debug, Default, Clone)]
+pub struct MuteList {
+    pubkeys: HashSet<String>,
+    event_ids: HashSet<String>,
+    hashtags: HashSet<String>,
+    words: Vec<String>,
+}
+
+impl MuteList {
+    /// Build a mute list from the public tags of a kind 10000 event
+    pub fn from_event(event: &Value) -> Self {
+        let mut mute_list = Self::default();
+
+        let tags = event.get("tags").and_then(|t| t.as_array());
+        for tag in tags.into_iter().flatten().filter_map(|t| t.as_array()) {
+            let (Some(name), Some(value)) = (
+                tag.first().and_then(|n| n.as_str()),
+                tag.get(1).and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+
+            match name {
+                "p" => { mute_list.pubkeys.insert(value.to_string()); }
+                "e" => { mute_list.event_ids.insert(value.to_string()); }
+                "t" => { mute_list.hashtags.insert(value.to_lowercase()); }
+                "word" => mute_list.words.push(value.to_lowercase()),
+                _ => {}
+            }
+        }
+
+        mute_list
+    }
+
+    /// Total number of muted entries
+    pub fn len(&self) -> usize {
+        self.pubkeys.len() + self.event_ids.len() + self.hashtags.len() + self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check whether an event is hidden by this mute list
+    pub fn is_muted(&self, event: &Value) -> bool {
+        if let Some(pubkey) = event.get("pubkey").and_then(|p| p.as_str()) {
+            if self.pubkeys.contains(pubkey) {
+                return true;
+            }
+        }
+
+        if let Some(id) = event.get("id").and_then(|i| i.as_str()) {
+            if self.event_ids.contains(id) {
+                return true;
+            }
+        }
+
+        // Replies within muted threads and events carrying muted hashtags
+        let tags = event.get("tags").and_then(|t| t.as_array());
+        for tag in tags.into_iter().flatten().filter_map(|t| t.as_array()) {
+            let name = tag.first().and_then(|n| n.as_str()).unwrap_or("");
+            let value = tag.get(1).and_then(|v| v.as_str()).unwrap_or("");
+            match name {
+                "e" if self.event_ids.contains(value) => return true,
+                "t" if self.hashtags.contains(&value.to_lowercase()) => return true,
+                _ => {}
+            }
+        }
+
+        if !self.words.is_empty() {
+            let content = event.get("content").and_then(|c| c.as_str()).unwrap_or("").to_lowercase();
+            if self.words.iter().any(|word| content.contains(word.as_str())) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Check whether a relay message is an EVENT carrying a muted event
+    pub fn is_muted_message(&self, message: &str) -> bool {
+        match serde_json::from_str::<Vec<Value>>(message) {
+            Ok(parsed) if parsed.len() >= 3 && parsed[0].as_str() == Some("EVENT") => {
+                self.is_muted(&parsed[2])
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Pick the newest kind 10000 event, optionally restricted to one author
+pub fn latest_mute_list_event<'a>(events: &'a [Value], pubkey: Option<&str>) -> Option<&'a Value> {
+    events.iter()
+        .filter(|e| e.get("kind").and_then(|k| k.as_i64()) == Some(MUTE_LIST_KIND))
+        .filter(|e| pubkey.map_or(true, |pk| e.get("pubkey").and_then(|p| p.as_str()) == Some(pk)))
+        .max_by_key(|e| e.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mute_list() -> MuteList {
+        MuteList::from_event(&json!({
+            "kind": 10000,
+            "tags": [["p", "spammer"], ["e", "thread"], ["t", "NSFW"], ["word", "Airdrop"]]
+        }))
+    }
+
+    #[test]
+    fn test_is_muted() {
+        let mute = mute_list();
+        assert_eq!(mute.len(), 4);
+        assert!(mute.is_muted(&json!({"id": "1", "pubkey": "spammer", "tags": [], "content": ""})));
+        assert!(mute.is_muted(&json!({"id": "thread", "pubkey": "a", "tags": [], "content": ""})));
+        assert!(mute.is_muted(&json!({"id": "2", "pubkey": "a", "tags": [["e", "thread"]], "content": ""})));
+        assert!(mute.is_muted(&json!({"id": "3", "pubkey": "a", "tags": [["t", "nsfw"]], "content": ""})));
+        assert!(mute.is_muted(&json!({"id": "4", "pubkey": "a", "tags": [], "content": "free AIRDROP now"})));
+        assert!(!mute.is_muted(&json!({"id": "5", "pubkey": "a", "tags": [["t", "nostr"]], "content": "gm"})));
+    }
+
+    #[test]
+    fn test_latest_mute_list_event() {
+        let events = vec![
+            json!({"kind": 10000, "pubkey": "op", "created_at": 1}),
+            json!({"kind": 10000, "pubkey": "op", "created_at": 5}),
+            json!({"kind": 10000, "pubkey": "other", "created_at": 9}),
+            json!({"kind": 1, "pubkey": "op", "created_at": 10}),
+        ];
+        assert_eq!(latest_mute_list_event(&events, Some("op")).unwrap()["created_at"], 5);
+        assert_eq!(latest_mute_list_event(&events, None).unwrap()["created_at"], 9);
+        assert!(latest_mute_list_event(&events, Some("nobody")).is_none());
+    }
+}
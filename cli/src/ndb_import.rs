@@ -0,0 +1,148 @@
+//! Importing a nostrdb cache for `record --nostrdb`
+//!
+//! Damus, notedeck and other nostrdb clients keep every note they've seen in
+//! an LMDB directory. The importer opens it and walks the created_at index
+//! newest first, a page at a time: each page asks for notes `until` the
+//! oldest one of the previous page, and notes at the boundary are written
+//! once. Ephemeral kinds (20000-29999) are skipped unless asked for, since
+//! they were never meant to outlive their delivery. nostrdb is a C library,
+//! so reading it needs the CLI built with the `nostrdb` feature.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+/// Notes asked for in one query
+pub const PAGE_SIZE: usize = 1000;
+
+/// What the import found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Events written
+    pub events: usize,
+    /// Ephemeral events left out
+    pub ephemeral: usize,
+}
+
+fn is_ephemeral(kind: i64) -> bool {
+    (20000..30000).contains(&kind)
+}
+
+fn created_at(event: &Value) -> u64 {
+    event.get("created_at").and_then(|c| c.as_u64()).unwrap_or(0)
+}
+
+/// Walk a store newest first through `page(until, limit)`, writing each event once as NDJSON
+pub fn walk<W: Write>(
+    mut page: impl FnMut(Option<u64>, usize) -> Result<Vec<Value>>,
+    keep_ephemeral: bool,
+    out: &mut W,
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut seen = HashSet::new();
+    let mut until = None;
+    loop {
+        let events = page(until, PAGE_SIZE)?;
+        let full = events.len() >= PAGE_SIZE;
+        let Some(oldest) = events.iter().map(created_at).min() else {
+            break;
+        };
+        let mut new = 0;
+        for event in events {
+            let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+            if !seen.insert(id) {
+                continue;
+            }
+            new += 1;
+            if !keep_ephemeral && event.get("kind").and_then(|k| k.as_i64()).is_some_and(is_ephemeral) {
+                stats.ephemeral += 1;
+                continue;
+            }
+            serde_json::to_writer(&mut *out, &event)?;
+            out.write_all(b"\n")?;
+            stats.events += 1;
+        }
+        if !full || oldest == 0 {
+            break;
+        }
+        // A full page of one second's notes can't move the window, so step past that second
+        until = Some(if new == 0 { oldest - 1 } else { oldest });
+    }
+    Ok(stats)
+}
+
+/// Write the events of the nostrdb directory `dir` to `out` as NDJSON
+#[cfg(feature = "nostrdb")]
+pub fn import<W: Write>(dir: &Path, keep_ephemeral: bool, out: &mut W) -> Result<ImportStats> {
+    use anyhow::anyhow;
+
+    if !dir.join("data.mdb").exists() {
+        return Err(anyhow!("{} is not a nostrdb directory (no data.mdb)", dir.display()));
+    }
+    let ndb = nostrdb::Ndb::new(&dir.to_string_lossy(), &nostrdb::Config::new())
+        .map_err(|e| anyhow!("Failed to open nostrdb at {}: {:?}", dir.display(), e))?;
+    let txn = nostrdb::Transaction::new(&ndb)
+        .map_err(|e| anyhow!("Failed to read nostrdb: {:?}", e))?;
+
+    walk(|until, limit| {
+        let mut filter = nostrdb::Filter::new().limit(limit as u64);
+        if let Some(until) = until {
+            filter = filter.until(until);
+        }
+        let results = ndb.query(&txn, &[filter.build()], limit as i32)
+            .map_err(|e| anyhow!("nostrdb query failed: {:?}", e))?;
+        results.iter()
+            .map(|result| {
+                let json = result.note.json().map_err(|e| anyhow!("Failed to serialize a nostrdb note: {:?}", e))?;
+                Ok(serde_json::from_str(&json)?)
+            })
+            .collect()
+    }, keep_ephemeral, out)
+}
+
+/// Write the events of the nostrdb directory `dir` to `out` as NDJSON
+#[cfg(not(feature = "nostrdb"))]
+pub fn import<W: Write>(dir: &Path, _keep_ephemeral: bool, _out: &mut W) -> Result<ImportStats> {
+    Err(anyhow::anyhow!(
+        "--nostrdb needs the CLI built with the nostrdb feature (can't read {})",
+        dir.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_walk() {
+        // Two full pages' worth of notes, several sharing a second, plus an ephemeral one
+        let mut store: Vec<Value> = (0..PAGE_SIZE + 10)
+            .map(|i| json!({"id": format!("note-{}", i), "kind": 1, "created_at": (i / 3) as u64 + 1}))
+            .collect();
+        store.push(json!({"id": "typing", "kind": 20001, "created_at": 5}));
+        store.sort_by_key(|event| std::cmp::Reverse(created_at(event)));
+
+        let page = |until: Option<u64>, limit: usize| -> Result<Vec<Value>> {
+            Ok(store.iter()
+                .filter(|event| until.map_or(true, |until| created_at(event) <= until))
+                .take(limit)
+                .cloned()
+                .collect())
+        };
+
+        let mut out = Vec::new();
+        let stats = walk(page, false, &mut out).unwrap();
+        assert_eq!(stats, ImportStats { events: PAGE_SIZE + 10, ephemeral: 1 });
+        let written: Vec<Value> = String::from_utf8(out).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(written.windows(2).all(|pair| created_at(&pair[0]) >= created_at(&pair[1])));
+
+        let mut out = Vec::new();
+        assert_eq!(walk(page, true, &mut out).unwrap().ephemeral, 0);
+    }
+}
@@ -0,0 +1,302 @@
+//! Minimal NIP-19 support for accepting bech32 entities wherever hex is expected
+//!
+//! Pubkeys may be given as `npub` or `nprofile`, event ids as `note` or
+//! `nevent`, and addresses as `naddr`, each optionally behind a NIP-21
+//! `nostr:` prefix. The relay hints carried by `nprofile`, `nevent` and
+//! `naddr` can stand in for relay URLs.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// A decoded NIP-19 entity with the relay hints it carries
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entity {
+    /// `npub` or `nprofile`
+    Pubkey { pubkey: String, relays: Vec<String> },
+    /// `note` or `nevent`
+    Event { id: String, author: Option<String>, kind: Option<u32>, relays: Vec<String> },
+    /// `naddr`
+    Address { kind: u32, pubkey: String, identifier: String, relays: Vec<String> },
+}
+
+impl Entity {
+    pub fn relays(&self) -> &[String] {
+        match self {
+            Entity::Pubkey { relays, .. } | Entity::Event { relays, .. } | Entity::Address { relays, .. } => relays,
+        }
+    }
+}
+
+/// Drop a NIP-21 `nostr:` prefix
+pub fn strip_uri(s: &str) -> &str {
+    s.strip_prefix("nostr:").unwrap_or(s)
+}
+
+/// Whether a string looks like a bech32 entity this module can decode
+fn is_entity(s: &str) -> bool {
+    ["npub1", "nprofile1", "note1", "nevent1", "naddr1"].iter().any(|prefix| s.starts_with(prefix))
+}
+
+/// Decode an `npub`, `nprofile`, `note`, `nevent` or `naddr`, with or without `nostr:`
+pub fn decode(entity: &str) -> Result<Entity> {
+    let (hrp, data) = decode_bech32(strip_uri(entity))?;
+    match hrp.as_str() {
+        "npub" => Ok(Entity::Pubkey { pubkey: hex32(&data, "npub")?, relays: Vec::new() }),
+        "note" => Ok(Entity::Event { id: hex32(&data, "note")?, author: None, kind: None, relays: Vec::new() }),
+        "nprofile" | "nevent" | "naddr" => {
+            let mut special = None;
+            let mut relays = Vec::new();
+            let mut author = None;
+            let mut kind = None;
+            for (tag, value) in tlvs(&data)? {
+                match tag {
+                    0 => special = Some(value),
+                    1 => relays.push(String::from_utf8(value.to_vec()).map_err(|_| anyhow!("Invalid relay in {}", hrp))?),
+                    2 => author = Some(hex32(value, "author")?),
+                    3 => {
+                        let bytes: [u8; 4] = value.try_into().map_err(|_| anyhow!("Invalid kind in {}", hrp))?;
+                        kind = Some(u32::from_be_bytes(bytes));
+                    }
+                    // Unknown TLVs are ignored (NIP-19)
+                    _ => {}
+                }
+            }
+            let special = special.ok_or_else(|| anyhow!("{} has no special TLV", hrp))?;
+            match hrp.as_str() {
+                "nprofile" => Ok(Entity::Pubkey { pubkey: hex32(special, "nprofile")?, relays }),
+                "nevent" => Ok(Entity::Event { id: hex32(special, "nevent")?, author, kind, relays }),
+                _ => Ok(Entity::Address {
+                    kind: kind.ok_or_else(|| anyhow!("naddr has no kind"))?,
+                    pubkey: author.ok_or_else(|| anyhow!("naddr has no author"))?,
+                    identifier: String::from_utf8(special.to_vec()).map_err(|_| anyhow!("Invalid identifier in naddr"))?,
+                    relays,
+                }),
+            }
+        }
+        _ => Err(anyhow!("Unsupported NIP-19 entity: {}", hrp)),
+    }
+}
+
+/// Accept a pubkey as hex, `npub` or `nprofile`, returning lowercase hex
+pub fn normalize_pubkey(key: &str) -> Result<String> {
+    let key = strip_uri(key);
+    if !is_entity(key) {
+        return Ok(key.to_lowercase());
+    }
+    match decode(key)? {
+        Entity::Pubkey { pubkey, .. } => Ok(pubkey),
+        _ => Err(anyhow!("Expected a pubkey (hex, npub or nprofile), got {}", key)),
+    }
+}
+
+/// Accept an event id as hex, `note` or `nevent`, returning lowercase hex
+pub fn normalize_event_id(id: &str) -> Result<String> {
+    let id = strip_uri(id);
+    if !is_entity(id) {
+        return Ok(id.to_lowercase());
+    }
+    match decode(id)? {
+        Entity::Event { id, .. } => Ok(id),
+        _ => Err(anyhow!("Expected an event id (hex, note or nevent), got {}", id)),
+    }
+}
+
+/// Rewrite bech32 values in a filter's `ids`, `authors`, `#e`, `#p` and `#a` lists (and
+/// their `&` forms) to hex (`kind:pubkey:d-identifier` for `#a`)
+pub fn normalize_filter(filter: &mut serde_json::Map<String, Value>) -> Result<()> {
+    for (key, values) in filter.iter_mut() {
+        let normalize: fn(&str) -> Result<String> = match key.as_str() {
+            "ids" | "#e" | "&e" => normalize_event_id,
+            "authors" | "#p" | "&p" => normalize_pubkey,
+            "#a" | "&a" => normalize_coordinate,
+            _ => continue,
+        };
+        for value in values.as_array_mut().into_iter().flatten() {
+            if let Some(text) = value.as_str() {
+                *value = Value::String(normalize(text)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Accept an address as a `kind:pubkey:d-identifier` coordinate or an `naddr`
+pub fn normalize_coordinate(address: &str) -> Result<String> {
+    let address = strip_uri(address);
+    if !address.starts_with("naddr1") {
+        return Ok(address.to_string());
+    }
+    match decode(address)? {
+        Entity::Address { kind, pubkey, identifier, .. } => Ok(format!("{}:{}:{}", kind, pubkey, identifier)),
+        _ => unreachable!("naddr always decodes to an address"),
+    }
+}
+
+/// Relay URLs from a list of URLs and NIP-19 entities, replacing each entity with its
+/// relay hints; duplicates are dropped
+pub fn resolve_relays(inputs: &[String]) -> Result<Vec<String>> {
+    let mut relays: Vec<String> = Vec::new();
+    for input in inputs {
+        let input = strip_uri(input);
+        let resolved = if is_entity(input) {
+            let entity = decode(input)?;
+            if entity.relays().is_empty() {
+                return Err(anyhow!("{} carries no relay hints", input));
+            }
+            entity.relays().to_vec()
+        } else {
+            vec![input.to_string()]
+        };
+        for relay in resolved {
+            if !relays.contains(&relay) {
+                relays.push(relay);
+            }
+        }
+    }
+    Ok(relays)
+}
+
+/// Decode an `npub` bech32 string into a 32-byte hex pubkey
+pub fn decode_npub(npub: &str) -> Result<String> {
+    let (hrp, data) = decode_bech32(npub)?;
+    if hrp != "npub" {
+        return Err(anyhow!("Expected an npub, got {}", hrp));
+    }
+    hex32(&data, "npub")
+}
+
+fn hex32(data: &[u8], what: &str) -> Result<String> {
+    if data.len() != 32 {
+        return Err(anyhow!("Invalid {} length: {} bytes", what, data.len()));
+    }
+    Ok(hex::encode(data))
+}
+
+/// Split NIP-19 type-length-value records
+fn tlvs(mut data: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 2 || data.len() < 2 + data[1] as usize {
+            return Err(anyhow!("Truncated TLV record"));
+        }
+        let (tag, length) = (data[0], data[1] as usize);
+        records.push((tag, &data[2..2 + length]));
+        data = &data[2 + length..];
+    }
+    Ok(records)
+}
+
+/// Decode a bech32 string into its human-readable part and data bytes
+pub fn decode_bech32(s: &str) -> Result<(String, Vec<u8>)> {
+    let (hrp, words) = decode_words(s)?;
+    Ok((hrp, convert_bits(&words)?))
+}
+
+/// Decode a bech32 string into its human-readable part and 5-bit data words, checksum removed
+///
+/// Unlike NIP-19 entities, lightning invoices pack tagged fields at word
+/// boundaries, so they are read from the words rather than the bytes.
+pub fn decode_words(s: &str) -> Result<(String, Vec<u8>)> {
+    let s = s.to_lowercase();
+    let separator = s.rfind('1').ok_or_else(|| anyhow!("Invalid bech32 string: {}", s))?;
+    let (hrp, encoded) = (&s[..separator], &s[separator + 1..]);
+    if hrp.is_empty() || encoded.len() < 6 {
+        return Err(anyhow!("Invalid bech32 string: {}", s));
+    }
+
+    let values = encoded.bytes()
+        .map(|c| CHARSET.iter().position(|&x| x == c).map(|v| v as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow!("Invalid bech32 character in {}", s))?;
+
+    let mut checked: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    checked.push(0);
+    checked.extend(hrp.bytes().map(|c| c & 31));
+    checked.extend(&values);
+    if polymod(&checked) != 1 {
+        return Err(anyhow!("Invalid bech32 checksum in {}", s));
+    }
+
+    Ok((hrp.to_string(), values[..values.len() - 6].to_vec()))
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut chk: u32 = 1;
+    for &v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ v as u32;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+/// Regroup 5-bit values into bytes, rejecting non-zero padding
+pub fn convert_bits(values: &[u8]) -> Result<Vec<u8>> {
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    let mut out = Vec::new();
+    for &v in values {
+        acc = (acc << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || (acc << (8 - bits)) as u8 != 0 {
+        return Err(anyhow!("Invalid bech32 padding"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_npub() {
+        // Test vector from NIP-19
+        let npub = "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg";
+        let hex = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+        assert_eq!(decode_npub(npub).unwrap(), hex);
+        assert_eq!(normalize_pubkey(hex).unwrap(), hex);
+        assert!(decode_npub("npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptx").is_err());
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        let pubkey = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+        let id = "b9f5441e45ca39179320e0031cfb18e34078673dcc3d3e3a3b3a981760aa5696";
+        // nprofile test vector from NIP-19
+        let nprofile = "nprofile1qqsrhuxx8l9ex335q7he0f09aej04zpazpl0ne2cgukyawd24mayt8gpp4mhxue69uhhytnc9e3k7mgpz4mhxue69uhkg6nzv9ejuumpv34kytnrdaksjlyr9p";
+        let nevent = "nevent1qqstna2yrezu5wghjvswqqculvvwxsrcvu7uc0f78gan4xqhvz49d9spp4mhxue69uhkummn9ekx7mqzyqalp33lewf5vdq847t6te0wvnags0gs0mu72kz8938tn24wlfze6qcyqqqqqqgav4eej";
+        let naddr = "naddr1qq9x67fdv9e8g6trd3jsz9rhwden5te0wfjkccte9ejxzmt4wvhxjmczyqalp33lewf5vdq847t6te0wvnags0gs0mu72kz8938tn24wlfze6qcyqqq823chdwlc0";
+        let note = "note1h865g8j9egu30yequqp3e7ccudq8seeaes7nuw3m82vpwc9226tqtudlvp";
+
+        assert_eq!(normalize_pubkey(&format!("nostr:{}", nprofile)).unwrap(), pubkey);
+        assert_eq!(normalize_event_id(note).unwrap(), id);
+        assert_eq!(decode(nevent).unwrap(), Entity::Event {
+            id: id.to_string(),
+            author: Some(pubkey.to_string()),
+            kind: Some(1),
+            relays: vec!["wss://nos.lol".to_string()],
+        });
+        assert_eq!(normalize_coordinate(naddr).unwrap(), format!("30023:{}:my-article", pubkey));
+        assert!(normalize_pubkey(note).is_err());
+
+        let mut filter = serde_json::json!({"ids": [nevent], "#p": [nprofile], "kinds": [1]});
+        normalize_filter(filter.as_object_mut().unwrap()).unwrap();
+        assert_eq!(filter, serde_json::json!({"ids": [id], "#p": [pubkey], "kinds": [1]}));
+
+        let relays = resolve_relays(&["wss://nos.lol/".to_string(), format!("nostr:{}", nprofile), nevent.to_string()]).unwrap();
+        assert_eq!(relays, vec!["wss://nos.lol/", "wss://r.x.com", "wss://djbas.sadkb.com", "wss://nos.lol"]);
+        assert!(resolve_relays(&[note.to_string()]).is_err());
+    }
+}
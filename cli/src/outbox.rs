@@ -0,0 +1,144 @@
+//! Outbox-model downloads for `record --follow-outbox`
+//!
+//! Authors publish to the relays in their NIP-65 relay list (kind 10002):
+//! `r` tags without a marker or marked `write` are where their events land.
+//! Instead of taking whatever the `--relays` happen to hold, the relay lists
+//! of the followed authors are looked up on those relays first, and each
+//! author's history is then downloaded from their own write relays, a few
+//! per author. Authors who share a relay are asked for in one filter; authors
+//! without a relay list fall back to the `--relays`.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Kind of a NIP-65 relay list
+pub const RELAY_LIST_KIND: i64 = 10002;
+
+/// Relay URL in the form relay lists are compared in: trimmed, lowercase scheme and host, no trailing slash
+pub fn normalize_relay(url: &str) -> Option<String> {
+    let url = url.trim().trim_end_matches('/');
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_lowercase();
+    if (scheme != "wss" && scheme != "ws") || rest.is_empty() {
+        return None;
+    }
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    Some(if path.is_empty() {
+        format!("{}://{}", scheme, host.to_lowercase())
+    } else {
+        format!("{}://{}/{}", scheme, host.to_lowercase(), path)
+    })
+}
+
+/// Write relays of a relay list event, in the order listed
+pub fn write_relays(relay_list: &Value) -> Vec<String> {
+    let mut relays = Vec::new();
+    let tags = relay_list.get("tags").and_then(|t| t.as_array()).into_iter().flatten();
+    for tag in tags.filter_map(|tag| tag.as_array()) {
+        if tag.first().and_then(|n| n.as_str()) != Some("r") {
+            continue;
+        }
+        let marker = tag.get(2).and_then(|m| m.as_str());
+        if marker.is_some_and(|m| m != "write") {
+            continue;
+        }
+        if let Some(relay) = tag.get(1).and_then(|r| r.as_str()).and_then(normalize_relay) {
+            if !relays.contains(&relay) {
+                relays.push(relay);
+            }
+        }
+    }
+    relays
+}
+
+/// The newest relay list of each author among `events`
+pub fn newest_relay_lists(events: &[Value]) -> HashMap<String, &Value> {
+    let mut lists: HashMap<String, &Value> = HashMap::new();
+    for event in events {
+        if event.get("kind").and_then(|k| k.as_i64()) != Some(RELAY_LIST_KIND) {
+            continue;
+        }
+        let Some(pubkey) = event.get("pubkey").and_then(|p| p.as_str()) else { continue };
+        let created_at = |e: &Value| e.get("created_at").and_then(|c| c.as_i64()).unwrap_or(0);
+        match lists.get(pubkey) {
+            Some(current) if created_at(current) >= created_at(event) => {}
+            _ => {
+                lists.insert(pubkey.to_string(), event);
+            }
+        }
+    }
+    lists
+}
+
+/// Which authors to ask each relay for
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Relay URL to the authors whose history is downloaded from it
+    pub relays: BTreeMap<String, BTreeSet<String>>,
+    /// Authors without a relay list (or without write relays), asked for on the fallback relays
+    pub unlisted: BTreeSet<String>,
+}
+
+impl Plan {
+    /// Route every author to up to `per_author` of their write relays, or to `fallback`
+    pub fn new(authors: &[String], relay_lists: &HashMap<String, &Value>, fallback: &[String], per_author: usize) -> Self {
+        let mut plan = Self::default();
+        for author in authors {
+            let relays: Vec<String> = relay_lists.get(author)
+                .map(|list| write_relays(list).into_iter().take(per_author.max(1)).collect())
+                .unwrap_or_default();
+            if relays.is_empty() {
+                plan.unlisted.insert(author.clone());
+                for relay in fallback {
+                    plan.relays.entry(relay.clone()).or_default().insert(author.clone());
+                }
+            }
+            for relay in relays {
+                plan.relays.entry(relay).or_default().insert(author.clone());
+            }
+        }
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_plan() {
+        let list = |pubkey: &str, created_at: i64, tags: Value| {
+            json!({"kind": RELAY_LIST_KIND, "pubkey": pubkey, "created_at": created_at, "tags": tags})
+        };
+        let events = vec![
+            list("alice", 1, json!([["r", "wss://old.example"]])),
+            list("alice", 2, json!([
+                ["r", "wss://Alice.Example/"],
+                ["r", "wss://inbox.example", "read"],
+                ["r", "wss://both.example"],
+                ["r", "wss://outbox.example", "write"],
+                ["r", "https://not-a-relay.example"],
+            ])),
+            list("bob", 5, json!([["r", "wss://both.example/"], ["r", "wss://bob.example", "write"]])),
+            json!({"kind": 1, "pubkey": "carol", "created_at": 9, "tags": [["r", "wss://carol.example"]]}),
+        ];
+        let lists = newest_relay_lists(&events);
+        assert_eq!(write_relays(lists["alice"]), vec!["wss://alice.example", "wss://both.example", "wss://outbox.example"]);
+        assert!(!lists.contains_key("carol"), "only kind 10002 events are relay lists");
+
+        let authors = vec!["alice".to_string(), "bob".to_string(), "carol".to_string()];
+        let plan = Plan::new(&authors, &lists, &["wss://index.example".to_string()], 2);
+        let routed: Vec<(&str, Vec<&str>)> = plan.relays.iter()
+            .map(|(relay, authors)| (relay.as_str(), authors.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(routed, vec![
+            ("wss://alice.example", vec!["alice"]),
+            ("wss://bob.example", vec!["bob"]),
+            ("wss://both.example", vec!["alice", "bob"]),
+            ("wss://index.example", vec!["carol"]),
+        ]);
+        assert_eq!(plan.unlisted.into_iter().collect::<Vec<_>>(), vec!["carol"]);
+        assert_eq!(normalize_relay("wss://Relay.Example/Path/"), Some("wss://relay.example/Path".to_string()));
+    }
+}
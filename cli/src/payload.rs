@@ -0,0 +1,90 @@
+//! Event payloads stored beside the cassette
+//!
+//! With `record --payload-sidecar` the cassette keeps its code and indexes but
+//! not the events: those go to a `<sha256>.payload` file in the output
+//! directory, named after its own hash so identical archives share one file
+//! and caches can key on the name. File name, hash and size are embedded under
+//! `cassette.payload` in the NIP-11 info; hosts read them from `info()`, check
+//! the file against them and hand the bytes to the guest's `load_payload`.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Write the serialized events to their content-addressed file and return the
+/// `cassette.payload` metadata describing it
+pub fn write_sidecar(output_dir: &Path, events_json: &str) -> Result<Value> {
+    let sha256 = hex::encode(Sha256::digest(events_json.as_bytes()));
+    let file = format!("{}.payload", sha256);
+    let path = output_dir.join(&file);
+    // Same name, same bytes: an existing file is already the right one
+    if !path.exists() {
+        std::fs::create_dir_all(output_dir)?;
+        std::fs::write(&path, events_json)
+            .with_context(|| format!("Failed to write payload {}", path.display()))?;
+    }
+    Ok(json!({
+        "file": file,
+        "sha256": sha256,
+        "bytes": events_json.len(),
+    }))
+}
+
+/// The `cassette.payload` record of a cassette's info, `None` when its events are compiled in
+pub fn sidecar_metadata(info: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(info).ok()?
+        .pointer("/cassette/payload")
+        .cloned()
+}
+
+/// Read the sidecar a cassette's info points to and check it against the embedded hash
+///
+/// Reading and checking the file is the loader's `read_payload`. Verified
+/// payloads are kept per hash, so instantiating the same cassette again doesn't
+/// re-read the file.
+pub fn read_verified(cassette_path: &Path, info: &str) -> Result<Arc<String>> {
+    static VERIFIED: OnceLock<Mutex<HashMap<String, Arc<String>>>> = OnceLock::new();
+
+    let sha256 = sidecar_metadata(info)
+        .and_then(|metadata| metadata.get("sha256")?.as_str().map(str::to_string))
+        .ok_or_else(|| anyhow!("{} expects a payload but its info doesn't describe one", cassette_path.display()))?;
+
+    let verified = VERIFIED.get_or_init(Default::default);
+    if let Some(payload) = verified.lock().unwrap().get(&sha256) {
+        return Ok(payload.clone());
+    }
+
+    let payload = Arc::new(cassette_loader::read_payload(cassette_path, info)?);
+    verified.lock().unwrap().insert(sha256, payload.clone());
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = dir.path().join("notes.wasm");
+        let events = r#"[{"id":"a","created_at":1}]"#;
+
+        let metadata = write_sidecar(dir.path(), events).unwrap();
+        assert_eq!(metadata["bytes"], events.len());
+        let info = json!({"name": "notes", "cassette": {"payload": metadata}}).to_string();
+        assert_eq!(sidecar_metadata(&info).unwrap()["bytes"], events.len());
+        assert_eq!(read_verified(&cassette, &info).unwrap().as_str(), events);
+        assert!(sidecar_metadata(r#"{"cassette":{}}"#).is_none());
+        assert!(read_verified(&cassette, r#"{"cassette":{}}"#).is_err());
+
+        // A payload that doesn't hash to what the cassette expects is refused
+        let tampered = r#"[{"id":"b","created_at":2}]"#;
+        let other = write_sidecar(dir.path(), tampered).unwrap();
+        std::fs::write(dir.path().join(other["file"].as_str().unwrap()), events).unwrap();
+        let info = json!({"cassette": {"payload": other}}).to_string();
+        assert!(read_verified(&cassette, &info).is_err());
+    }
+}
@@ -0,0 +1,483 @@
+//! Cassettes built without compiling
+//!
+//! `record --fast` doesn't render and compile a crate per cassette. It takes
+//! the player: the cassette template compiled once with every query feature
+//! and no events, reading what a normal build embeds as constants from a
+//! recording at run time instead. The recording (relay info, metadata, limits,
+//! indexes and events, in `FIELDS` order) is appended to a copy of the player
+//! as a data segment in fresh pages past the end of its initial memory, and
+//! the player's slot, found by its magic in the data section, is pointed at
+//! it. The allocator only takes pages from `memory.grow`, so it never hands
+//! out the recording's pages.
+//!
+//! The same layout lets `dub` skip compilation too: a cassette built this way
+//! gives its recording back with `recording`, and `eject` takes the recording
+//! out again, leaving the player it was built from ready for another one.
+//!
+//! Release builds embed the player (`CASSETTE_PLAYER_WASM` at build time);
+//! otherwise it is compiled on first use and cached per CLI version.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Template variables stored in a recording, in the order the player reads them;
+/// recordings made before a field was added end before it
+pub const FIELDS: [&str; 9] = [
+    "relay_info",
+    "cassette_metadata",
+    "filter_limits",
+    "event_index",
+    "text_index",
+    "language_segments",
+    "verify_on_query",
+    "events_json",
+    "provenance",
+];
+
+/// Cassette-tools features the player is compiled with
+pub const FEATURES: [&str; 7] = ["default", "nip11", "nip09", "nip40", "nip45", "nip50", "verify"];
+
+/// Marks the player's slot; must match PLAYER_SLOT in the cassette template
+const SLOT_MAGIC: &[u8; 16] = b"cassette-player!";
+
+const WASM_HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+const MEMORY_SECTION: u8 = 5;
+const DATA_SECTION: u8 = 11;
+const DATA_COUNT_SECTION: u8 = 12;
+const PAGE_BYTES: u64 = 65536;
+const MAX_PAGES: u64 = 65536;
+
+#[cfg(embedded_player)]
+const EMBEDDED: Option<&[u8]> = Some(include_bytes!(env!("CASSETTE_PLAYER_PATH")));
+#[cfg(not(embedded_player))]
+const EMBEDDED: Option<&[u8]> = None;
+
+/// Where a player compiled on first use is kept
+pub fn cache_path() -> PathBuf {
+    crate::compile_cache::cache_dir().join(format!("player-{}.wasm", env!("CARGO_PKG_VERSION")))
+}
+
+/// Whether a player is at hand without compiling one
+pub fn available() -> bool {
+    EMBEDDED.is_some() || cache_path().exists()
+}
+
+/// The embedded player, else the cached one, else one compiled by `build` into the cache
+pub fn module(build: impl FnOnce(&Path) -> Result<()>) -> Result<Vec<u8>> {
+    if let Some(player) = EMBEDDED {
+        return Ok(player.to_vec());
+    }
+    let path = cache_path();
+    if !path.exists() {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        // Build next to the cache entry and move it in, so an interrupted build leaves nothing behind
+        let partial = tempfile::NamedTempFile::new_in(dir)?;
+        build(partial.path())?;
+        partial.persist(&path).map_err(|e| anyhow!("Failed to cache the player: {}", e))?;
+    }
+    std::fs::read(&path).with_context(|| format!("Failed to read the player {}", path.display()))
+}
+
+/// Lay out a recording: each field as a little-endian u32 length and its bytes
+pub fn encode_recording<'a>(fields: impl IntoIterator<Item = &'a str>) -> Result<Vec<u8>> {
+    let mut recording = Vec::new();
+    for field in fields {
+        let len = u32::try_from(field.len()).map_err(|_| anyhow!("Recording field of {} bytes is too large", field.len()))?;
+        recording.extend_from_slice(&len.to_le_bytes());
+        recording.extend_from_slice(field.as_bytes());
+    }
+    Ok(recording)
+}
+
+/// Read a recording back into its fields
+pub fn decode_recording(recording: &[u8]) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < recording.len() {
+        let len = recording.get(pos..pos + 4)
+            .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .ok_or_else(|| anyhow!("Truncated recording"))?;
+        pos += 4;
+        let field = recording.get(pos..pos + len).ok_or_else(|| anyhow!("Truncated recording"))?;
+        fields.push(String::from_utf8(field.to_vec()).map_err(|_| anyhow!("Recording field is not UTF-8"))?);
+        pos += len;
+    }
+    Ok(fields)
+}
+
+/// The sections of a core module as (id, payload)
+fn sections(module: &[u8]) -> Result<Vec<(u8, Vec<u8>)>> {
+    if module.get(..8) != Some(&WASM_HEADER[..]) {
+        return Err(anyhow!("Not a core WebAssembly module"));
+    }
+    let mut sections = Vec::new();
+    let mut pos = 8;
+    while pos < module.len() {
+        let id = module[pos];
+        pos += 1;
+        let size = read_leb(module, &mut pos)? as usize;
+        let payload = module.get(pos..pos + size).ok_or_else(|| anyhow!("Truncated module"))?;
+        sections.push((id, payload.to_vec()));
+        pos += size;
+    }
+    Ok(sections)
+}
+
+fn assemble(sections: Vec<(u8, Vec<u8>)>) -> Vec<u8> {
+    let mut module = WASM_HEADER.to_vec();
+    for (id, payload) in sections {
+        module.push(id);
+        write_leb(&mut module, payload.len() as u64);
+        module.extend_from_slice(&payload);
+    }
+    module
+}
+
+/// A data segment: where its entry spans in the data section, its memory offset
+/// when it is an active `i32.const` one, and where its bytes are
+struct Segment {
+    entry: std::ops::Range<usize>,
+    offset: Option<u64>,
+    bytes: std::ops::Range<usize>,
+}
+
+fn segments(data: &[u8]) -> Result<Vec<Segment>> {
+    let mut pos = 0;
+    let count = read_leb(data, &mut pos)?;
+    let mut segments = Vec::new();
+    for _ in 0..count {
+        let start = pos;
+        let offset = match read_leb(data, &mut pos)? {
+            // Passive
+            1 => None,
+            flags @ (0 | 2) => {
+                if flags == 2 {
+                    read_leb(data, &mut pos)?;
+                }
+                let offset = match data.get(pos) {
+                    Some(0x41) => {
+                        pos += 1;
+                        Some(read_sleb(data, &mut pos)? as u32 as u64)
+                    }
+                    Some(0x23) => {
+                        pos += 1;
+                        read_leb(data, &mut pos)?;
+                        None
+                    }
+                    _ => return Err(anyhow!("Unsupported data segment offset")),
+                };
+                if data.get(pos) != Some(&0x0b) {
+                    return Err(anyhow!("Unsupported data segment offset"));
+                }
+                pos += 1;
+                offset
+            }
+            _ => return Err(anyhow!("Unknown data segment kind")),
+        };
+        let len = read_leb(data, &mut pos)? as usize;
+        if data.len() < pos + len {
+            return Err(anyhow!("Truncated data segment"));
+        }
+        segments.push(Segment { entry: start..pos + len, offset, bytes: pos..pos + len });
+        pos += len;
+    }
+    Ok(segments)
+}
+
+/// Position of the slot's address and length in the data section, and what it holds
+fn find_slot(data: &[u8]) -> Result<(usize, Option<(u64, usize)>)> {
+    let mut found = data.windows(SLOT_MAGIC.len()).enumerate()
+        .filter(|(_, window)| *window == SLOT_MAGIC)
+        .map(|(at, _)| at + SLOT_MAGIC.len());
+    let at = found.next().ok_or_else(|| anyhow!("Not a cassette player: no recording slot found"))?;
+    if found.next().is_some() {
+        return Err(anyhow!("The player has more than one recording slot"));
+    }
+    let slot = data.get(at..at + 8).ok_or_else(|| anyhow!("Truncated recording slot"))?;
+    if slot == [0xff; 8] {
+        return Ok((at, None));
+    }
+    let offset = u32::from_le_bytes([slot[0], slot[1], slot[2], slot[3]]) as u64;
+    let len = u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]) as usize;
+    Ok((at, Some((offset, len))))
+}
+
+/// The segment holding the recording the slot points at
+fn recording_segment(data: &[u8], offset: u64, len: usize) -> Result<Segment> {
+    segments(data)?.into_iter()
+        .find(|segment| segment.offset == Some(offset) && segment.bytes.len() == len)
+        .ok_or_else(|| anyhow!("The recording slot points outside the module's data"))
+}
+
+/// The recording fields (in `FIELDS` order) of a cassette built from the player;
+/// `None` for compiled cassettes and the empty player
+pub fn recording(cassette: &[u8]) -> Result<Option<Vec<String>>> {
+    let sections = sections(cassette)?;
+    let Some((_, data)) = sections.iter().find(|(id, _)| *id == DATA_SECTION) else { return Ok(None) };
+    let Ok((_, Some((offset, len)))) = find_slot(data) else { return Ok(None) };
+    let segment = recording_segment(data, offset, len)?;
+    decode_recording(&data[segment.bytes]).map(Some)
+}
+
+/// The player a cassette was built from, with its recording taken out so another can be injected
+pub fn eject(cassette: &[u8]) -> Result<Vec<u8>> {
+    let mut sections = sections(cassette)?;
+    let (_, data) = sections.iter_mut()
+        .find(|(id, _)| *id == DATA_SECTION)
+        .ok_or_else(|| anyhow!("The cassette has no data section"))?;
+    let (at, Some((offset, len))) = find_slot(data)? else {
+        return Ok(cassette.to_vec());
+    };
+    let segment = recording_segment(data, offset, len)?;
+    data[at..at + 8].copy_from_slice(&[0xff; 8]);
+    let mut pos = 0;
+    let count = read_leb(data, &mut pos)?;
+    let mut patched = Vec::new();
+    write_leb(&mut patched, count - 1);
+    patched.extend_from_slice(&data[pos..segment.entry.start]);
+    patched.extend_from_slice(&data[segment.entry.end..]);
+    *data = patched;
+
+    // The recording was given pages of its own past the player's memory
+    let (_, memory) = sections.iter_mut()
+        .find(|(id, _)| *id == MEMORY_SECTION)
+        .ok_or_else(|| anyhow!("The cassette doesn't define its own memory"))?;
+    shrink_memory(memory, offset / PAGE_BYTES)?;
+
+    if let Some((_, count)) = sections.iter_mut().find(|(id, _)| *id == DATA_COUNT_SECTION) {
+        let segments = read_leb(count, &mut 0)?;
+        count.clear();
+        write_leb(count, segments.saturating_sub(1));
+    }
+    Ok(assemble(sections))
+}
+
+/// Set the minimum of the only memory back to `pages`
+fn shrink_memory(section: &mut Vec<u8>, pages: u64) -> Result<()> {
+    let mut pos = 0;
+    if read_leb(section, &mut pos)? != 1 {
+        return Err(anyhow!("The player must define exactly one memory"));
+    }
+    let flags = *section.get(pos).ok_or_else(|| anyhow!("Truncated memory section"))?;
+    pos += 1;
+    read_leb(section, &mut pos)?;
+    let mut patched = Vec::new();
+    write_leb(&mut patched, 1);
+    patched.push(flags);
+    write_leb(&mut patched, pages);
+    patched.extend_from_slice(&section[pos..]);
+    *section = patched;
+    Ok(())
+}
+
+/// Copy a player with `recording` added as a data segment and its slot pointing at it
+pub fn inject(player: &[u8], recording: &[u8]) -> Result<Vec<u8>> {
+    let mut sections = sections(player)
+        .map_err(|e| anyhow!("The player is not usable: {}", e))?;
+
+    let pages = (recording.len() as u64).div_ceil(PAGE_BYTES);
+    let (_, memory) = sections.iter_mut()
+        .find(|(id, _)| *id == MEMORY_SECTION)
+        .ok_or_else(|| anyhow!("The player doesn't define its own memory"))?;
+    let offset = grow_memory(memory, pages)?;
+
+    let (_, data) = sections.iter_mut()
+        .find(|(id, _)| *id == DATA_SECTION)
+        .ok_or_else(|| anyhow!("The player has no data section"))?;
+    point_slot(data, offset, recording.len())?;
+    append_segment(data, offset, recording)?;
+
+    // Modules using bulk memory declare their segment count up front
+    if let Some((_, count)) = sections.iter_mut().find(|(id, _)| *id == DATA_COUNT_SECTION) {
+        let segments = read_leb(count, &mut 0)?;
+        count.clear();
+        write_leb(count, segments + 1);
+    }
+
+    Ok(assemble(sections))
+}
+
+/// Raise the minimum (and if needed maximum) of the only memory by `pages`,
+/// returning the byte offset of the first new page
+fn grow_memory(section: &mut Vec<u8>, pages: u64) -> Result<u64> {
+    let mut pos = 0;
+    if read_leb(section, &mut pos)? != 1 {
+        return Err(anyhow!("The player must define exactly one memory"));
+    }
+    let flags = *section.get(pos).ok_or_else(|| anyhow!("Truncated memory section"))?;
+    pos += 1;
+    if flags > 1 {
+        return Err(anyhow!("Shared and 64-bit player memories aren't supported"));
+    }
+    let min = read_leb(section, &mut pos)?;
+    let max = if flags == 1 { Some(read_leb(section, &mut pos)?) } else { None };
+    let grown = min + pages;
+    if grown > MAX_PAGES {
+        return Err(anyhow!("The recording doesn't fit in a 4 GiB cassette memory"));
+    }
+
+    let mut patched = Vec::new();
+    write_leb(&mut patched, 1);
+    patched.push(flags);
+    write_leb(&mut patched, grown);
+    if let Some(max) = max {
+        write_leb(&mut patched, max.max(grown));
+    }
+    patched.extend_from_slice(&section[pos..]);
+    *section = patched;
+    Ok(min * PAGE_BYTES)
+}
+
+/// Write the recording's address and length after the slot magic
+fn point_slot(section: &mut [u8], offset: u64, len: usize) -> Result<()> {
+    let (at, held) = find_slot(section)?;
+    if held.is_some() {
+        return Err(anyhow!("The player already holds a recording"));
+    }
+    let len = u32::try_from(len).map_err(|_| anyhow!("The recording is larger than 4 GiB"))?;
+    section[at..at + 4].copy_from_slice(&(offset as u32).to_le_bytes());
+    section[at + 4..at + 8].copy_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// Add an active segment holding `bytes` at `offset` of memory 0
+fn append_segment(section: &mut Vec<u8>, offset: u64, bytes: &[u8]) -> Result<()> {
+    let mut pos = 0;
+    let segments = read_leb(section, &mut pos)?;
+    let mut patched = Vec::new();
+    write_leb(&mut patched, segments + 1);
+    patched.extend_from_slice(&section[pos..]);
+    // i32.const offset; end
+    patched.push(0x00);
+    patched.push(0x41);
+    write_sleb(&mut patched, offset as u32 as i32 as i64);
+    patched.push(0x0b);
+    write_leb(&mut patched, bytes.len() as u64);
+    patched.extend_from_slice(bytes);
+    *section = patched;
+    Ok(())
+}
+
+fn read_leb(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or_else(|| anyhow!("Truncated player module"))?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(anyhow!("Malformed integer in player module"));
+        }
+    }
+}
+
+fn read_sleb(bytes: &[u8], pos: &mut usize) -> Result<i64> {
+    let mut value = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or_else(|| anyhow!("Truncated player module"))?;
+        *pos += 1;
+        value |= i64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                value |= -1 << shift;
+            }
+            return Ok(value);
+        }
+        if shift >= 64 {
+            return Err(anyhow!("Malformed integer in player module"));
+        }
+    }
+}
+
+fn write_leb(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(out: &mut Vec<u8>, id: u8, payload: &[u8]) {
+        out.push(id);
+        write_leb(out, payload.len() as u64);
+        out.extend_from_slice(payload);
+    }
+
+    // A module with one page of memory and one data segment holding an empty slot at 1024
+    fn player() -> Vec<u8> {
+        let mut slot = SLOT_MAGIC.to_vec();
+        slot.extend_from_slice(&[0xff; 8]);
+        let mut data = vec![1, 0x00, 0x41];
+        write_sleb(&mut data, 1024);
+        data.push(0x0b);
+        write_leb(&mut data, slot.len() as u64);
+        data.extend_from_slice(&slot);
+
+        let mut module = WASM_HEADER.to_vec();
+        section(&mut module, MEMORY_SECTION, &[1, 0x00, 1]);
+        // export "memory" as memory 0
+        section(&mut module, 7, &[1, 6, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0]);
+        section(&mut module, DATA_COUNT_SECTION, &[1]);
+        section(&mut module, DATA_SECTION, &data);
+        module
+    }
+
+    #[test]
+    fn test_inject() {
+        let recording = encode_recording(["{}", r#"[{"id":"a"}]"#]).unwrap();
+        assert_eq!(&recording[..6], &[2, 0, 0, 0, b'{', b'}']);
+
+        let cassette = inject(&player(), &recording).unwrap();
+        let engine = wasmtime::Engine::default();
+        let module = wasmtime::Module::new(&engine, &cassette).unwrap();
+        let mut store = wasmtime::Store::new(&engine, ());
+        let instance = wasmtime::Instance::new(&mut store, &module, &[]).unwrap();
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        let memory = memory.data(&store);
+
+        // The recording lands on the first new page and the slot points at it
+        assert_eq!(memory.len() as u64, 2 * PAGE_BYTES);
+        assert_eq!(&memory[1024 + 16..1024 + 20], &(PAGE_BYTES as u32).to_le_bytes());
+        assert_eq!(&memory[1024 + 20..1024 + 24], &(recording.len() as u32).to_le_bytes());
+        assert_eq!(&memory[PAGE_BYTES as usize..PAGE_BYTES as usize + recording.len()], &recording[..]);
+
+        // A cassette can't be turned into another one
+        assert!(inject(&cassette, &recording).is_err());
+        assert!(inject(b"not wasm", &recording).is_err());
+
+        // ...until its recording is taken out again
+        assert_eq!(super::recording(&cassette).unwrap(), Some(vec!["{}".to_string(), r#"[{"id":"a"}]"#.to_string()]));
+        assert_eq!(super::recording(&player()).unwrap(), None);
+        let ejected = eject(&cassette).unwrap();
+        assert_eq!(ejected, player());
+        let rewritten = inject(&ejected, &encode_recording(["{}", "[]"]).unwrap()).unwrap();
+        assert_eq!(super::recording(&rewritten).unwrap().unwrap()[1], "[]");
+    }
+}
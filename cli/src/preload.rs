@@ -0,0 +1,140 @@
+//! Cassette warm-up at server start
+//!
+//! Without `--preload`, listen compiles a cassette every time a query reaches
+//! it, so the first query (and every query after it) pays the multi-second
+//! cost of compiling a large module. Preloaded cassettes are compiled once,
+//! instantiated and warmed before the server accepts connections. Warming
+//! calls the cassette's `warm` export, which parses its events once for the
+//! life of the instance (older cassettes are asked a trivial REQ instead).
+//! Warmed instances are kept and handed back out after each query, so queries
+//! on preloaded cassettes skip both compiling and parsing.
+
+use anyhow::{Context, Result};
+use cassette_loader::{Cassette, CompiledCassette};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+/// Idle warmed instances kept per preloaded cassette
+const IDLE_INSTANCES: usize = 4;
+
+/// Query run against each preloaded cassette to trigger its lazy initialization
+pub fn warmup_request() -> Value {
+    json!(["REQ", "preload", {"limit": 1}])
+}
+
+/// The cassettes to preload: all of them for a limit of 0, otherwise the
+/// `limit` most recently modified
+pub fn select(paths: &[PathBuf], limit: usize) -> Vec<PathBuf> {
+    if limit == 0 || limit >= paths.len() {
+        return paths.to_vec();
+    }
+    let mut by_age: Vec<(SystemTime, &PathBuf)> = paths.iter()
+        .map(|path| {
+            let modified = std::fs::metadata(path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, path)
+        })
+        .collect();
+    by_age.sort_by(|a, b| b.0.cmp(&a.0));
+    by_age.into_iter().take(limit).map(|(_, path)| path.clone()).collect()
+}
+
+/// Compiled modules of the preloaded cassettes and their idle warmed instances;
+/// the rest are still loaded per query
+#[derive(Default)]
+pub struct CassetteCache {
+    compiled: HashMap<PathBuf, CompiledCassette>,
+    idle: Mutex<HashMap<PathBuf, Vec<Cassette>>>,
+}
+
+/// Parse a cassette's events, through a warm-up query for cassettes without `warm`
+fn warm(cassette: &mut Cassette) -> Result<()> {
+    if cassette.warm()?.is_none() {
+        cassette.scrub(&warmup_request().to_string())?;
+    }
+    Ok(())
+}
+
+impl CassetteCache {
+    /// Compile and warm up each cassette
+    pub fn preload(paths: &[PathBuf], verbose: bool) -> Result<Self> {
+        let started = Instant::now();
+        let mut compiled = HashMap::new();
+        let mut idle = HashMap::new();
+        for path in paths {
+            let cassette_started = Instant::now();
+            let module = CompiledCassette::compile(&path.to_string_lossy())
+                .with_context(|| format!("Failed to preload {}", path.display()))?;
+            let mut cassette = module.instantiate(false)?;
+            warm(&mut cassette)
+                .with_context(|| format!("Warm-up failed for {}", path.display()))?;
+            if verbose {
+                println!("  🔥 {} ({} ms)", path.display(), cassette_started.elapsed().as_millis());
+            }
+            compiled.insert(path.clone(), module);
+            idle.insert(path.clone(), vec![cassette]);
+        }
+        if !paths.is_empty() {
+            println!("🔥 Preloaded {} cassette(s) in {} ms", paths.len(), started.elapsed().as_millis());
+        }
+        Ok(Self { compiled, idle: Mutex::new(idle) })
+    }
+
+    /// An idle warmed instance of the cassette when there is one, else a fresh
+    /// instance, from the compiled module when it was preloaded
+    pub fn load(&self, path: &Path) -> Result<Cassette> {
+        if let Some(cassette) = self.idle.lock().unwrap().get_mut(path).and_then(|idle| idle.pop()) {
+            return Ok(cassette);
+        }
+        match self.compiled.get(path) {
+            Some(module) => module.instantiate(false),
+            None => Cassette::load(&path.to_string_lossy(), false),
+        }
+    }
+
+    /// Keep an instance of a preloaded cassette for the next query once `request` is
+    /// answered; its subscription is closed first so the next client starts afresh
+    pub fn release(&self, path: &Path, mut cassette: Cassette, request: &str) {
+        if !self.compiled.contains_key(path) {
+            return;
+        }
+        let subscription = serde_json::from_str::<Vec<Value>>(request).ok()
+            .filter(|parsed| parsed.len() >= 2 && parsed[0] == "REQ")
+            .and_then(|parsed| parsed[1].as_str().map(String::from));
+        if let Some(subscription) = subscription {
+            if cassette.scrub(&json!(["CLOSE", subscription]).to_string()).is_err() {
+                return;
+            }
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let instances = idle.entry(path.to_path_buf()).or_default();
+        if instances.len() < IDLE_INSTANCES {
+            instances.push(cassette);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_select_most_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let paths: Vec<PathBuf> = (0..3).map(|i| {
+            let path = dir.path().join(format!("{}.cassette", i));
+            let file = std::fs::File::create(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(60 * (3 - i))).unwrap();
+            path
+        }).collect();
+
+        assert_eq!(select(&paths, 0), paths);
+        assert_eq!(select(&paths, 5), paths);
+        assert_eq!(select(&paths, 2), vec![paths[2].clone(), paths[1].clone()]);
+        assert!(CassetteCache::default().load(&dir.path().join("missing.cassette")).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Record-time redaction for privacy-scrubbed public archives
+//!
+//! `--strip-tag` removes every tag of a name and `--redact-content` replaces
+//! regex matches in the content; whole kinds are left out by `--deny-kinds`
+//! in the validation policy instead. A rewritten event keeps its id and signature so references to it still
+//! resolve, but they no longer match it: the cassette metadata lists such
+//! events as unsigned instead of letting them pass for authentic ones, and
+//! `verify_events()` reports them as invalid. Rules run after validation and
+//! deletion handling, which need the original events.
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// What redacted content is replaced with
+pub const REPLACEMENT: &str = "[redacted]";
+
+/// Redaction rules from the command line
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    strip_tags: HashSet<String>,
+    patterns: Vec<Regex>,
+}
+
+/// What the rules did to an archive
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Ids of events that were rewritten
+    pub unsigned: Vec<String>,
+}
+
+impl Rules {
+    pub fn new(strip_tags: &[String], patterns: &[String]) -> Result<Self> {
+        let patterns = patterns.iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| anyhow!("Invalid --redact-content pattern '{}': {}", pattern, e)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            strip_tags: strip_tags.iter().cloned().collect(),
+            patterns,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strip_tags.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Rewrite events, reporting which ones no longer match their signature
+    pub fn apply(&self, mut events: Vec<Value>) -> (Vec<Value>, Report) {
+        let mut report = Report::default();
+        for event in &mut events {
+            if self.rewrite(event) {
+                report.unsigned.push(event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string());
+            }
+        }
+        (events, report)
+    }
+
+    /// Strip tags and redact content in place, returning whether anything changed
+    fn rewrite(&self, event: &mut Value) -> bool {
+        let mut changed = false;
+        if !self.strip_tags.is_empty() {
+            if let Some(tags) = event.get_mut("tags").and_then(|t| t.as_array_mut()) {
+                let before = tags.len();
+                tags.retain(|tag| !tag.get(0).and_then(|n| n.as_str()).is_some_and(|name| self.strip_tags.contains(name)));
+                changed |= tags.len() != before;
+            }
+        }
+        if let Some(Value::String(content)) = event.get_mut("content") {
+            for pattern in &self.patterns {
+                if pattern.is_match(content) {
+                    *content = pattern.replace_all(content, REPLACEMENT).into_owned();
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Cassette metadata describing the rules and the events they rewrote; patterns themselves are
+    /// left out since they may spell out what was being hidden
+    pub fn metadata(&self, report: &Report) -> Value {
+        let mut strip_tags: Vec<&String> = self.strip_tags.iter().collect();
+        strip_tags.sort();
+        json!({
+            "strip_tags": strip_tags,
+            "content_patterns": self.patterns.len(),
+            "replacement": REPLACEMENT,
+            "unsigned": report.unsigned,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str, kind: i64, tags: Value, content: &str) -> Value {
+        json!({"id": id, "pubkey": "alice", "kind": kind, "created_at": 1, "tags": tags, "content": content, "sig": "sig"})
+    }
+
+    #[test]
+    fn test_apply() {
+        let rules = Rules::new(&["client".to_string()], &[r"\+?\d{3}-\d{4}".to_string()]).unwrap();
+        let events = vec![
+            event("tagged", 1, json!([["client", "x"], ["t", "nostr"]]), "hello"),
+            event("phone", 1, json!([]), "call 555-1234 or 555-9876"),
+            event("clean", 1, json!([["t", "nostr"]]), "hello"),
+        ];
+
+        let (events, report) = rules.apply(events);
+        assert_eq!(report, Report { unsigned: vec!["tagged".to_string(), "phone".to_string()] });
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["tags"], json!([["t", "nostr"]]));
+        assert_eq!(events[1]["content"], "call [redacted] or [redacted]");
+        assert_eq!(events[2], event("clean", 1, json!([["t", "nostr"]]), "hello"));
+
+        assert!(Rules::new(&[], &[]).unwrap().is_empty());
+        assert!(Rules::new(&[], &["(".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! NIP-11 capability probing of the relays `record` and `play` talk to
+//!
+//! Relays publish their limits in the NIP-11 document (`limitation`), and
+//! most of them reject what breaks those limits with a terse NOTICE or a
+//! failed OK. Fetching the document first lets the commands warn up front,
+//! skip events a relay would refuse anyway and size requests to fit.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// The parts of a NIP-11 document that change how we talk to a relay
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RelayInfo {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub software: Option<String>,
+    #[serde(default)]
+    pub supported_nips: Vec<u64>,
+    #[serde(default)]
+    pub limitation: Limitation,
+}
+
+/// NIP-11 `limitation` object
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Limitation {
+    pub max_message_length: Option<usize>,
+    pub max_limit: Option<usize>,
+    pub max_event_tags: Option<usize>,
+    pub max_content_length: Option<usize>,
+    /// Oldest accepted created_at, in seconds before now
+    pub created_at_lower_limit: Option<i64>,
+    /// Newest accepted created_at, in seconds after now
+    pub created_at_upper_limit: Option<i64>,
+    #[serde(default)]
+    pub auth_required: bool,
+    #[serde(default)]
+    pub payment_required: bool,
+    #[serde(default)]
+    pub restricted_writes: bool,
+}
+
+impl Limitation {
+    /// Why the relay would refuse to store this event, if it would
+    pub fn rejects(&self, event: &Value, now: i64) -> Option<&'static str> {
+        let message_length = serde_json::json!(["EVENT", event]).to_string().len();
+        if self.max_message_length.map_or(false, |max| message_length > max) {
+            return Some("message too long");
+        }
+        let content_length = event.get("content").and_then(|c| c.as_str()).map_or(0, |c| c.chars().count());
+        if self.max_content_length.map_or(false, |max| content_length > max) {
+            return Some("content too long");
+        }
+        let tags = event.get("tags").and_then(|t| t.as_array()).map_or(0, |t| t.len());
+        if self.max_event_tags.map_or(false, |max| tags > max) {
+            return Some("too many tags");
+        }
+        let created_at = event.get("created_at").and_then(|c| c.as_i64()).unwrap_or(now);
+        if self.created_at_lower_limit.map_or(false, |limit| created_at < now - limit) {
+            return Some("created_at too old");
+        }
+        if self.created_at_upper_limit.map_or(false, |limit| created_at > now + limit) {
+            return Some("created_at too far in the future");
+        }
+        None
+    }
+}
+
+impl RelayInfo {
+    /// Problems a relay's document announces for publishing events to it
+    pub fn write_warnings(&self) -> Vec<String> {
+        let mut warnings = self.access_warnings();
+        if self.limitation.restricted_writes {
+            warnings.push("restricts writes, events may be refused unless the relay accepts this author".to_string());
+        }
+        warnings
+    }
+
+    /// Problems a relay's document announces for reading with this filter
+    pub fn read_warnings(&self, filter: &Value) -> Vec<String> {
+        let mut warnings = self.access_warnings();
+        if filter.get("search").is_some() && !self.supported_nips.is_empty() && !self.supported_nips.contains(&50) {
+            warnings.push("does not list NIP-50, the search filter will likely be ignored or refused".to_string());
+        }
+        warnings
+    }
+
+    fn access_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.limitation.auth_required {
+            warnings.push("requires NIP-42 authentication, which this command does not perform".to_string());
+        }
+        if self.limitation.payment_required {
+            warnings.push("requires payment, requests from unpaid keys will be refused".to_string());
+        }
+        warnings
+    }
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Fetch a relay's NIP-11 document over http(s) on the relay's own address
+pub async fn fetch(relay_url: &str, wait: Duration) -> Result<RelayInfo> {
+    let url = if let Some(rest) = relay_url.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = relay_url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        return Err(anyhow!("Not a relay URL: {}", relay_url));
+    };
+    let body = get(&url, "application/nostr+json", wait).await
+        .with_context(|| format!("Failed to fetch NIP-11 from {}", relay_url))?;
+    serde_json::from_str(&body).with_context(|| format!("{} did not return a NIP-11 document", relay_url))
+}
+
+/// GET an http(s) URL and return the body of a 2xx response
+pub async fn get(url: &str, accept: &str, wait: Duration) -> Result<String> {
+    let (secure, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(anyhow!("Not an http(s) URL: {}", url));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let host = authority.rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok())
+        .map_or(authority, |(host, _)| host);
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:{}", authority, if secure { 443 } else { 80 })
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: {}\r\nConnection: close\r\n\r\n",
+        path, authority, accept
+    );
+
+    let exchange = async {
+        let tcp = TcpStream::connect(&address).await?;
+        if secure {
+            let mut roots = tokio_rustls::rustls::RootCertStore::empty();
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                tokio_rustls::rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+            }));
+            let config = tokio_rustls::rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let server_name = tokio_rustls::rustls::ServerName::try_from(host)
+                .map_err(|_| anyhow!("Invalid host name {}", host))?;
+            let tls = tokio_rustls::TlsConnector::from(Arc::new(config)).connect(server_name, tcp).await?;
+            http_get(tls, &request).await
+        } else {
+            http_get(tcp, &request).await
+        }
+    };
+    tokio::time::timeout(wait, exchange).await
+        .map_err(|_| anyhow!("Timed out fetching {}", url))?
+}
+
+async fn http_get<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &str) -> Result<String> {
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    // Servers closing TLS without close_notify still sent the whole body
+    if let Err(e) = stream.read_to_end(&mut response).await {
+        if response.is_empty() {
+            return Err(e.into());
+        }
+    }
+    // The body is dechunked as bytes and decoded after, since chunks may split a character
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(|| anyhow!("Malformed HTTP response"))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+    let status = head.lines().next().unwrap_or("");
+    if !status.split_whitespace().nth(1).map_or(false, |code| code.starts_with('2')) {
+        return Err(anyhow!("HTTP {}", status));
+    }
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    let body = if chunked { dechunk(body) } else { body.to_vec() };
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(line_end) = body.windows(2).position(|w| w == b"\r\n") {
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let Ok(size) = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16) else { break };
+        let rest = &body[line_end + 2..];
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        out.extend_from_slice(&rest[..size]);
+        body = &rest[size..];
+        while let Some(next) = body.strip_prefix(b"\r\n") {
+            body = next;
+        }
+    }
+    out
+}
+
+/// Fetch and report on a relay before using it; a relay without a document gets no checks
+pub async fn probe(relay_url: &str, verbose: bool) -> Option<RelayInfo> {
+    match fetch(relay_url, Duration::from_secs(5)).await {
+        Ok(info) => {
+            if verbose {
+                let name = info.name.as_deref().or(info.software.as_deref()).unwrap_or("unnamed relay");
+                println!("🔎 {}: {} (NIPs {:?})", relay_url, name, info.supported_nips);
+            }
+            Some(info)
+        }
+        Err(e) => {
+            if verbose {
+                println!("🔎 {}: no relay information ({}), skipping capability checks", relay_url, e);
+            }
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_limitation_checks() {
+        let info: RelayInfo = serde_json::from_value(json!({
+            "name": "strict",
+            "supported_nips": [1, 11],
+            "limitation": {
+                "max_message_length": 300, "max_content_length": 20, "max_event_tags": 2,
+                "created_at_lower_limit": 3600, "auth_required": true, "restricted_writes": true
+            }
+        })).unwrap();
+        let now = 1_000_000;
+        let event = |content: &str, tags: usize, created_at: i64| json!({
+            "id": "a", "content": content, "created_at": created_at, "tags": vec![json!(["t", "x"]); tags]
+        });
+        let limits = &info.limitation;
+        assert_eq!(limits.rejects(&event("hi", 1, now), now), None);
+        assert_eq!(limits.rejects(&event(&"x".repeat(21), 1, now), now), Some("content too long"));
+        assert_eq!(limits.rejects(&event("hi", 3, now), now), Some("too many tags"));
+        assert_eq!(limits.rejects(&event("hi", 1, now - 7200), now), Some("created_at too old"));
+        assert_eq!(limits.rejects(&event(&"x".repeat(400), 0, now), now), Some("message too long"));
+
+        assert_eq!(info.write_warnings().len(), 2);
+        assert_eq!(info.read_warnings(&json!({"search": "nostr"})).len(), 2);
+        assert_eq!(dechunk(b"5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"), b"hello world");
+        // A chunk boundary inside a character
+        assert_eq!(dechunk(b"2\r\n\xc3\xa9\r\n1\r\n\xc3\r\n1\r\n\xa9\r\n0\r\n\r\n"), "\u{e9}\u{e9}".as_bytes());
+    }
+}
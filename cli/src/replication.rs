@@ -0,0 +1,193 @@
+//! Cross-deck replication for relay-mode decks
+//!
+//! A deck started with `--peer ws://other-deck` forwards every event it
+//! accepts to that peer as `["EVENT", <event>, {"via": [<deck ids>]}]`. The
+//! `via` list is the event's provenance: the ids of the decks it has passed
+//! through, oldest first. A deck that finds its own id in the list drops the
+//! event instead of storing and forwarding it again, so two decks peering with
+//! each other form an HA pair without echoing events back and forth. Each
+//! peer gets its own outbox, which queues events while the peer is down and
+//! drains once it reconnects.
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Events queued per peer before new ones are dropped
+const OUTBOX_CAPACITY: usize = 100_000;
+
+/// Longest wait between reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// File holding the deck's replication id
+pub fn deck_id_path(output_dir: &Path) -> PathBuf {
+    output_dir.join("deck-id")
+}
+
+/// The deck's replication id, created on first start and kept across restarts
+pub fn load_deck_id(output_dir: &Path) -> Result<String> {
+    let path = deck_id_path(output_dir);
+    if let Ok(id) = fs::read_to_string(&path) {
+        let id = id.trim();
+        if !id.is_empty() {
+            return Ok(id.to_string());
+        }
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    fs::write(&path, &id).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(id)
+}
+
+/// The `via` list of an EVENT message: empty for events from clients
+pub fn parse_via(message: &[Value]) -> Result<Vec<String>, String> {
+    let Some(provenance) = message.get(2) else {
+        return Ok(Vec::new());
+    };
+    provenance.get("via")
+        .and_then(|via| via.as_array())
+        .and_then(|via| via.iter().map(|id| id.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| "third element must be {\"via\": [<deck ids>]}".to_string())
+}
+
+/// Origin recorded in the event index: `client`, or the deck that forwarded the event
+pub fn origin(via: &[String]) -> String {
+    via.last().map_or_else(|| "client".to_string(), |id| format!("deck:{}", id))
+}
+
+/// Outboxes of the peers a deck replicates to
+pub struct Replicator {
+    deck_id: String,
+    peers: Vec<(String, mpsc::Sender<String>)>,
+}
+
+impl Replicator {
+    /// Spawn one forwarding task per peer URL
+    pub fn start(deck_id: String, peers: &[String], verbose: bool) -> Self {
+        let peers = peers.iter()
+            .map(|url| {
+                let (outbox, queued) = mpsc::channel(OUTBOX_CAPACITY);
+                tokio::spawn(run_peer(url.clone(), queued, verbose));
+                (url.clone(), outbox)
+            })
+            .collect();
+        Self { deck_id, peers }
+    }
+
+    pub fn deck_id(&self) -> &str {
+        &self.deck_id
+    }
+
+    /// Whether an event has already passed through this deck
+    pub fn has_seen(&self, via: &[String]) -> bool {
+        via.iter().any(|id| *id == self.deck_id)
+    }
+
+    /// Queue an accepted event for every peer, adding this deck to its provenance
+    pub fn forward(&self, event: &Value, via: &[String]) {
+        let mut via = via.to_vec();
+        via.push(self.deck_id.clone());
+        let message = json!(["EVENT", event, {"via": via}]).to_string();
+        for (url, outbox) in &self.peers {
+            if outbox.try_send(message.clone()).is_err() {
+                eprintln!("⚠️  Replication outbox for {} is full, event not forwarded", url);
+            }
+        }
+    }
+}
+
+/// Keep a connection to a peer and send it the queued events, reconnecting with backoff
+async fn run_peer(url: String, mut queued: mpsc::Receiver<String>, verbose: bool) {
+    let mut backoff = Duration::from_secs(1);
+    // An event whose send failed goes out first after reconnecting
+    let mut unsent: Option<String> = None;
+
+    loop {
+        let (mut write, mut read) = match connect_async(url.as_str()).await {
+            Ok((stream, _)) => stream.split(),
+            Err(e) => {
+                if verbose {
+                    eprintln!("⚠️  Peer {} unreachable, retrying in {:?}: {}", url, backoff, e);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        println!("🔁 Replicating to peer {}", url);
+        backoff = Duration::from_secs(1);
+
+        loop {
+            if let Some(message) = unsent.take() {
+                if write.send(Message::Text(message.clone())).await.is_err() {
+                    unsent = Some(message);
+                    break;
+                }
+            }
+            tokio::select! {
+                message = queued.recv() => match message {
+                    Some(message) => unsent = Some(message),
+                    // The deck is shutting down
+                    None => return,
+                },
+                reply = read.next() => match reply {
+                    Some(Ok(Message::Text(text))) => {
+                        if verbose {
+                            println!("🔁 {} answered: {}", url, text);
+                        }
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        let _ = write.send(Message::Pong(data)).await;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        eprintln!("⚠️  Lost connection to peer {}, reconnecting", url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = load_deck_id(dir.path()).unwrap();
+        assert_eq!(load_deck_id(dir.path()).unwrap(), id, "the id survives restarts");
+
+        let event = json!({"id": "e1"});
+        assert_eq!(parse_via(&[json!("EVENT"), event.clone()]).unwrap(), Vec::<String>::new());
+        let via = parse_via(&[json!("EVENT"), event.clone(), json!({"via": ["a", "b"]})]).unwrap();
+        assert_eq!(via, vec!["a", "b"]);
+        assert!(parse_via(&[json!("EVENT"), event, json!({"via": [1]})]).is_err());
+
+        assert_eq!(origin(&[]), "client");
+        assert_eq!(origin(&via), "deck:b");
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let replicator = Replicator::start("deck-a".to_string(), &[url], false);
+        assert!(replicator.has_seen(&["deck-b".to_string(), "deck-a".to_string()]));
+        replicator.forward(&json!({"id": "e1"}), &["deck-b".to_string()]);
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut peer = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let Some(Ok(Message::Text(text))) = peer.next().await else {
+            panic!("no event forwarded");
+        };
+        let message: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(message, json!(["EVENT", {"id": "e1"}, {"via": ["deck-b", "deck-a"]}]));
+    }
+}
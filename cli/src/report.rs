@@ -0,0 +1,162 @@
+//! Human-readable `scrub` output: a table, CSV, or a summary of what matched
+//!
+//! The table shows one line per event with its content cut down to a preview,
+//! CSV keeps every field whole for spreadsheets, and the summary counts events
+//! per kind and author between the oldest and newest timestamp.
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Characters of content the table shows
+const PREVIEW_CHARS: usize = 60;
+
+/// Authors the summary lists before folding the rest into one line
+const TOP_AUTHORS: usize = 10;
+
+/// A timestamp as UTC date and time
+pub fn time(created_at: i64) -> String {
+    chrono::DateTime::from_timestamp(created_at, 0)
+        .map_or_else(|| created_at.to_string(), |time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn field<'a>(event: &'a Value, key: &str) -> &'a str {
+    event.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn created_at(event: &Value) -> i64 {
+    event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0)
+}
+
+fn kind(event: &Value) -> i64 {
+    event.get("kind").and_then(|k| k.as_i64()).unwrap_or(-1)
+}
+
+/// Content on one line, cut to `chars` characters
+pub fn preview(content: &str, chars: usize) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= chars {
+        return line;
+    }
+    let cut: String = line.chars().take(chars.saturating_sub(1)).collect();
+    format!("{}…", cut)
+}
+
+/// One line per event: time, kind, shortened pubkey and a content preview
+pub fn table(events: &[Value]) -> Vec<String> {
+    let width = events.iter().map(|e| kind(e).to_string().len()).max().unwrap_or(0).max(4);
+    let mut lines = vec![format!("{:<19}  {:>width$}  {:<16}  {}", "CREATED_AT", "KIND", "PUBKEY", "CONTENT")];
+    for event in events {
+        let pubkey: String = field(event, "pubkey").chars().take(16).collect();
+        lines.push(format!(
+            "{:<19}  {:>width$}  {:<16}  {}",
+            time(created_at(event)),
+            kind(event),
+            pubkey,
+            preview(field(event, "content"), PREVIEW_CHARS)
+        ));
+    }
+    lines
+}
+
+/// Quote a CSV field when it holds a separator, quote or line break (RFC 4180)
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A header and one row per event; tags are kept as their JSON
+pub fn csv(events: &[Value]) -> Vec<String> {
+    let mut lines = vec!["id,created_at,kind,pubkey,content,tags".to_string()];
+    for event in events {
+        let tags = event.get("tags").map(Value::to_string).unwrap_or_else(|| "[]".to_string());
+        let row = [
+            field(event, "id").to_string(),
+            created_at(event).to_string(),
+            kind(event).to_string(),
+            field(event, "pubkey").to_string(),
+            field(event, "content").to_string(),
+            tags,
+        ];
+        lines.push(row.iter().map(|value| csv_field(value)).collect::<Vec<_>>().join(","));
+    }
+    lines
+}
+
+/// Counts sorted by count, most first, ties by key
+fn ranked<K: Ord + Clone>(counts: HashMap<K, usize>) -> Vec<(K, usize)> {
+    let mut counts: Vec<(K, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Event count, time range and counts per kind and per author
+pub fn summary(events: &[Value]) -> Vec<String> {
+    let mut lines = vec![format!("Events: {}", events.len())];
+    if events.is_empty() {
+        return lines;
+    }
+    let oldest = events.iter().map(created_at).min().unwrap_or(0);
+    let newest = events.iter().map(created_at).max().unwrap_or(0);
+    lines.push(format!("Oldest: {} ({})", time(oldest), oldest));
+    lines.push(format!("Newest: {} ({})", time(newest), newest));
+
+    let mut kinds: HashMap<i64, usize> = HashMap::new();
+    let mut authors: HashMap<String, usize> = HashMap::new();
+    for event in events {
+        *kinds.entry(kind(event)).or_default() += 1;
+        *authors.entry(field(event, "pubkey").to_string()).or_default() += 1;
+    }
+
+    lines.push(format!("Kinds ({}):", kinds.len()));
+    for (kind, count) in ranked(kinds) {
+        lines.push(format!("  {:>8}  {}", count, kind));
+    }
+
+    let authors = ranked(authors);
+    lines.push(format!("Authors ({}):", authors.len()));
+    for (pubkey, count) in authors.iter().take(TOP_AUTHORS) {
+        lines.push(format!("  {:>8}  {}", count, pubkey));
+    }
+    if authors.len() > TOP_AUTHORS {
+        let rest: usize = authors[TOP_AUTHORS..].iter().map(|(_, count)| count).sum();
+        lines.push(format!("  {:>8}  ({} more authors)", rest, authors.len() - TOP_AUTHORS));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_report_formats() {
+        let events = vec![
+            json!({"id": "aa", "pubkey": "alice", "created_at": 1_704_067_200, "kind": 1, "tags": [["t", "nostr"]], "content": "hello,\n\"world\""}),
+            json!({"id": "bb", "pubkey": "bob", "created_at": 1_704_153_600, "kind": 7, "tags": [], "content": "+"}),
+            json!({"id": "cc", "pubkey": "alice", "created_at": 1_704_070_800, "kind": 1, "tags": [], "content": "x".repeat(100)}),
+        ];
+
+        let table = table(&events);
+        assert_eq!(table.len(), 4);
+        assert!(table[1].starts_with("2024-01-01 00:00:00     1  alice"));
+        assert!(table[1].ends_with("hello, \"world\""));
+        assert!(table[3].ends_with(&format!("{}…", "x".repeat(PREVIEW_CHARS - 1))));
+
+        let csv = csv(&events);
+        assert_eq!(csv[0], "id,created_at,kind,pubkey,content,tags");
+        assert_eq!(csv[1], "aa,1704067200,1,alice,\"hello,\n\"\"world\"\"\",\"[[\"\"t\"\",\"\"nostr\"\"]]\"");
+        assert_eq!(csv[2], "bb,1704153600,7,bob,+,[]");
+
+        let summary = summary(&events);
+        assert_eq!(summary[0], "Events: 3");
+        assert_eq!(summary[1], "Oldest: 2024-01-01 00:00:00 (1704067200)");
+        assert_eq!(summary[2], "Newest: 2024-01-02 00:00:00 (1704153600)");
+        assert_eq!(&summary[3..6], &["Kinds (2):", "         2  1", "         1  7"]);
+        assert_eq!(&summary[6..], &["Authors (2):", "         2  alice", "         1  bob"]);
+        assert_eq!(super::summary(&[]), vec!["Events: 0".to_string()]);
+    }
+}
@@ -0,0 +1,85 @@
+//! Reproducible cassette builds for `record --deterministic`
+//!
+//! The same events already render the same crate: they are embedded in NIP-01
+//! order (newest first, ties by id) with sorted keys, and the indexes and
+//! metadata serialize in key order. What differs between two builds is where
+//! they ran and when. Deterministic builds remap the temp project, the
+//! cassette-tools checkout, the target directory and the cargo home out of
+//! the paths rustc embeds, compile with one codegen unit, and stamp the
+//! lineage with `SOURCE_DATE_EPOCH` (0 when unset) instead of the clock. The
+//! toolchain and the versions cargo resolves still have to match for two
+//! builds to agree.
+
+use std::path::{Path, PathBuf};
+
+/// Stable stand-ins for the build's directories
+const PROJECT_PREFIX: &str = "/cassette";
+const TOOLS_PREFIX: &str = "/cassette-tools";
+const TARGET_PREFIX: &str = "/target";
+const CARGO_PREFIX: &str = "/cargo";
+
+/// Timestamp stamped into deterministic builds
+pub fn source_date_epoch() -> i64 {
+    std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+}
+
+/// Environment for the cargo build of a deterministic cassette
+pub fn cargo_env(project_dir: &Path, tools_dir: &Path, target_dir: &Path) -> Vec<(&'static str, String)> {
+    let mut remaps = vec![(project_dir.to_path_buf(), PROJECT_PREFIX)];
+    // Directories inside the project are covered by the project remap
+    if tools_dir.is_absolute() && !tools_dir.starts_with(project_dir) {
+        remaps.push((tools_dir.to_path_buf(), TOOLS_PREFIX));
+    }
+    if !target_dir.starts_with(project_dir) {
+        remaps.push((target_dir.to_path_buf(), TARGET_PREFIX));
+    }
+    if let Some(cargo_home) = cargo_home() {
+        remaps.push((cargo_home, CARGO_PREFIX));
+    }
+
+    let mut flags: Vec<String> = std::env::var("RUSTFLAGS").ok()
+        .map(|flags| flags.split_whitespace().map(String::from).collect())
+        .unwrap_or_default();
+    for (from, to) in remaps {
+        flags.push(format!("--remap-path-prefix={}={}", from.display(), to));
+    }
+    vec![
+        ("RUSTFLAGS", flags.join(" ")),
+        ("CARGO_PROFILE_RELEASE_CODEGEN_UNITS", "1".to_string()),
+        ("CARGO_INCREMENTAL", "0".to_string()),
+        ("SOURCE_DATE_EPOCH", source_date_epoch().to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cargo_env() {
+        let env = cargo_env(Path::new("/tmp/.tmpAbC/project"), Path::new("/home/me/cassette/cassette-tools"), Path::new("/home/me/.cache/cassette/target"));
+        let rustflags = &env.iter().find(|(key, _)| *key == "RUSTFLAGS").unwrap().1;
+        assert!(rustflags.contains("--remap-path-prefix=/tmp/.tmpAbC/project=/cassette"));
+        assert!(rustflags.contains("--remap-path-prefix=/home/me/cassette/cassette-tools=/cassette-tools"));
+        assert!(rustflags.contains("--remap-path-prefix=/home/me/.cache/cassette/target=/target"));
+
+        // Tools unpacked into the compile cache live outside the project, under $HOME
+        let cached = cargo_env(Path::new("/tmp/p"), Path::new("/home/me/.cache/cassette/cassette-tools-0.9.2"), Path::new("/tmp/p/target"));
+        let rustflags = &cached.iter().find(|(key, _)| *key == "RUSTFLAGS").unwrap().1;
+        assert!(rustflags.contains("--remap-path-prefix=/home/me/.cache/cassette/cassette-tools-0.9.2=/cassette-tools"));
+        assert!(!rustflags.contains("=/target"));
+
+        let extracted = cargo_env(Path::new("/tmp/p"), Path::new("/tmp/p/cassette-tools"), Path::new("/tmp/p/target"));
+        let rustflags = &extracted.iter().find(|(key, _)| *key == "RUSTFLAGS").unwrap().1;
+        assert!(!rustflags.contains("=/cassette-tools"));
+        assert!(env.contains(&("CARGO_PROFILE_RELEASE_CODEGEN_UNITS", "1".to_string())));
+    }
+}
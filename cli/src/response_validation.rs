@@ -0,0 +1,123 @@
+//! Host-side re-validation of the events cassettes return
+//!
+//! A cassette is an opaque wasm module, so a third-party cassette can answer
+//! a REQ with events it made up. With `--validate-responses` the servers
+//! recompute every returned event's id, verify its schnorr signature and
+//! check that it matches the subscription's filters before passing it on.
+
+use serde_json::Value;
+
+/// What to do with an event that fails validation
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Withhold the event from the client
+    Drop,
+    /// Serve the event anyway and only log it
+    Flag,
+}
+
+/// Why a returned event was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Missing fields, an id that doesn't hash from the content, or a bad signature
+    InvalidEvent,
+    /// The event doesn't match any of the subscription's filters
+    FilterMismatch,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::InvalidEvent => write!(f, "invalid id or signature"),
+            Violation::FilterMismatch => write!(f, "does not match the subscription filters"),
+        }
+    }
+}
+
+/// Check one event a cassette returned for a subscription with these filters
+pub fn check_event(event: &Value, filters: &[Value]) -> Result<(), Violation> {
+    if crate::validation::ValidationPolicy::default().check(event).is_err() {
+        return Err(Violation::InvalidEvent);
+    }
+    if !filters.is_empty() && !crate::Filter::matches_any(&crate::parse_filters(filters), event) {
+        return Err(Violation::FilterMismatch);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseValidator {
+    mode: ValidationMode,
+}
+
+impl ResponseValidator {
+    pub fn new(mode: ValidationMode) -> Self {
+        Self { mode }
+    }
+
+    /// Whether an event from `source` should be served; violations are logged either way
+    pub fn admit(&self, source: &str, event: &Value, filters: &[Value]) -> bool {
+        let Err(violation) = check_event(event, filters) else {
+            return true;
+        };
+        let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("?");
+        match self.mode {
+            ValidationMode::Drop => eprintln!("🛡️  Dropped event {} from {}: {}", id, source, violation),
+            ValidationMode::Flag => eprintln!("🚩 Event {} from {} failed validation: {}", id, source, violation),
+        }
+        self.mode == ValidationMode::Flag
+    }
+
+    /// Same as `admit` for a raw relay message; anything but an EVENT passes through
+    pub fn admit_message(&self, source: &str, message: &str, filters: &[Value]) -> bool {
+        match serde_json::from_str::<Vec<Value>>(message) {
+            Ok(parsed) if parsed.len() >= 3 && parsed[0].as_str() == Some("EVENT") => {
+                self.admit(source, &parsed[2], filters)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Filters of a REQ or COUNT message, for checking the events returned for it
+pub fn request_filters(message: &str) -> Vec<Value> {
+    match serde_json::from_str::<Vec<Value>>(message) {
+        Ok(parsed) if matches!(parsed.first().and_then(|c| c.as_str()), Some("REQ") | Some("COUNT")) => {
+            parsed.into_iter().skip(2).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_event() {
+        let event = json!({
+            "kind": 7,
+            "id": "2a6b2a94d05974d2e390c95856df86f3742c0a1b65d6cca9f6705b41ae9ace47",
+            "pubkey": "8ac20c99a80a2c09e785533106b26a285ba4580d8f86030223cf4f2805414655",
+            "created_at": 1753605454,
+            "tags": [["e", "cdfb95a8409b30b156bf35d98e3bf5b5c3669e522b3b82450f8c94d43c5f7db9"], ["p", "3731e169d0b3816f4a4a9ac84c66aa423d4f4ea0f78b56a1df90609b85be0885"]],
+            "content": "+",
+            "sig": "dab8f13d9c6eb6dd238a9e847d2b57ae43d8f5787b50f34e7c1a99909b1375c4acef3ce668b5085ae1bd0dd37c4e55de78dc43818823b6a2b0a86237f79ee1b5"
+        });
+        assert_eq!(check_event(&event, &[json!({"kinds": [7]})]), Ok(()));
+        assert_eq!(check_event(&event, &[json!({"kinds": [1]})]), Err(Violation::FilterMismatch));
+
+        let mut forged = event.clone();
+        forged["content"] = json!("-");
+        assert_eq!(check_event(&forged, &[json!({"kinds": [7]})]), Err(Violation::InvalidEvent));
+
+        let message = json!(["EVENT", "sub", forged]).to_string();
+        assert!(!ResponseValidator::new(ValidationMode::Drop).admit_message("test", &message, &[]));
+        assert!(ResponseValidator::new(ValidationMode::Flag).admit_message("test", &message, &[]));
+        assert!(ResponseValidator::new(ValidationMode::Drop).admit_message("test", r#"["EOSE","sub"]"#, &[]));
+
+        assert_eq!(request_filters(r#"["REQ","sub",{"kinds":[7]},{"limit":1}]"#), vec![json!({"kinds": [7]}), json!({"limit": 1})]);
+        assert!(request_filters(r#"["CLOSE","sub"]"#).is_empty());
+    }
+}
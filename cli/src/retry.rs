@@ -0,0 +1,211 @@
+//! Backing off and retrying rate-limited publishes in `play`
+//!
+//! A relay that answers `["OK", id, false, "rate-limited: ..."]` (the NIP-01
+//! machine-readable prefix) hasn't refused the event, it wants it later. The
+//! event goes to the relay's retry queue, due after the relay's backoff delay,
+//! which doubles with every rate limit up to a ceiling and is also waited
+//! before each send while it lasts, so the publish rate adapts to the relay.
+//! Accepted events halve it again, until it drops below the base delay and
+//! sends go back to the normal pace. Events still rate-limited after the last
+//! attempt, and events the relay refuses for any other reason, end up in the
+//! relay's rejections, grouped by reason for the final report.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Longest delay between publishes to a rate-limiting relay
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Event ids listed per reason in the final report unless verbose
+const SHOWN_IDS: usize = 5;
+
+/// Whether an OK message's reason asks to publish again later
+pub fn is_rate_limited(reason: &str) -> bool {
+    reason.trim_start().starts_with("rate-limited")
+}
+
+/// How rate-limited publishes are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Sends of one event, the first included
+    pub max_attempts: u32,
+    /// Delay after the first rate limit
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_attempts: 5, base: Duration::from_secs(1), max: MAX_BACKOFF }
+    }
+}
+
+/// A relay's current delay between publishes
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy, delay: Duration::ZERO }
+    }
+
+    /// Delay to wait before the next publish; zero while the relay isn't limiting
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// The relay rate-limited a publish: double the delay, starting from the base
+    pub fn limited(&mut self) -> Duration {
+        self.delay = (self.delay * 2).clamp(self.policy.base, self.policy.max);
+        self.delay
+    }
+
+    /// The relay accepted a publish: halve the delay, back to none below the base
+    pub fn accepted(&mut self) {
+        self.delay /= 2;
+        if self.delay < self.policy.base {
+            self.delay = Duration::ZERO;
+        }
+    }
+}
+
+/// An event waiting to be published again
+#[derive(Debug, Clone)]
+pub struct Retry {
+    /// Position of the event in the playlist
+    pub position: usize,
+    pub event: Value,
+    /// Sends so far
+    pub attempts: u32,
+    pub due: Instant,
+}
+
+/// Rate-limited events of a relay, oldest due first
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    queue: VecDeque<Retry>,
+}
+
+impl RetryQueue {
+    pub fn push(&mut self, retry: Retry) {
+        // Delays only grow while retries pile up, so the queue stays ordered by due time
+        let at = self.queue.iter().position(|queued| queued.due > retry.due).unwrap_or(self.queue.len());
+        self.queue.insert(at, retry);
+    }
+
+    /// The first retry due at `now`
+    pub fn pop_due(&mut self, now: Instant) -> Option<Retry> {
+        if self.queue.front()?.due <= now {
+            self.queue.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// The next retry, whether due or not
+    pub fn pop(&mut self) -> Option<Retry> {
+        self.queue.pop_front()
+    }
+
+    /// Lowest playlist position still waiting, which a checkpoint mustn't pass
+    pub fn first_position(&self) -> Option<usize> {
+        self.queue.iter().map(|retry| retry.position).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// Events a relay refused for good, by reason
+#[derive(Debug, Default, Clone)]
+pub struct Rejections {
+    by_reason: BTreeMap<String, Vec<String>>,
+}
+
+impl Rejections {
+    pub fn add(&mut self, id: &str, reason: &str) {
+        let reason = if reason.is_empty() { "no reason given" } else { reason };
+        self.by_reason.entry(reason.to_string()).or_default().push(id.to_string());
+    }
+
+    pub fn total(&self) -> usize {
+        self.by_reason.values().map(Vec::len).sum()
+    }
+
+    /// Each reason with its count, most common first, and the ids it was given for
+    pub fn report(&self, verbose: bool) -> Vec<String> {
+        let mut reasons: Vec<(&String, &Vec<String>)> = self.by_reason.iter().collect();
+        reasons.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+        let mut lines = Vec::new();
+        for (reason, ids) in reasons {
+            lines.push(format!("{:>8}  {}", ids.len(), reason));
+            let shown = if verbose { ids.len() } else { SHOWN_IDS };
+            for id in ids.iter().take(shown) {
+                lines.push(format!("          {}", id));
+            }
+            if ids.len() > shown {
+                lines.push(format!("          … {} more (see -v)", ids.len() - shown));
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_backoff_and_retries() {
+        assert!(is_rate_limited("rate-limited: slow down"));
+        assert!(!is_rate_limited("blocked: rate-limited elsewhere"));
+
+        let policy = RetryPolicy { max_attempts: 3, base: Duration::from_millis(500), max: Duration::from_secs(3) };
+        let mut backoff = Backoff::new(policy);
+        assert_eq!(backoff.delay(), Duration::ZERO);
+        assert_eq!(backoff.limited(), Duration::from_millis(500));
+        assert_eq!(backoff.limited(), Duration::from_secs(1));
+        assert_eq!(backoff.limited(), Duration::from_secs(2));
+        assert_eq!(backoff.limited(), Duration::from_secs(3), "capped at the maximum");
+        backoff.accepted();
+        assert_eq!(backoff.delay(), Duration::from_millis(1500));
+        backoff.accepted();
+        backoff.accepted();
+        assert_eq!(backoff.delay(), Duration::ZERO, "below the base, back to the normal pace");
+
+        let now = Instant::now();
+        let retry = |position: usize, due: Instant| Retry { position, event: json!({"id": position.to_string()}), attempts: 1, due };
+        let mut queue = RetryQueue::default();
+        queue.push(retry(7, now + Duration::from_secs(2)));
+        queue.push(retry(3, now + Duration::from_secs(1)));
+        assert_eq!(queue.first_position(), Some(3));
+        assert!(queue.pop_due(now).is_none());
+        assert_eq!(queue.pop_due(now + Duration::from_secs(1)).unwrap().position, 3);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pop().unwrap().position, 7);
+        assert!(queue.is_empty());
+
+        let mut rejections = Rejections::default();
+        for id in ["a", "b", "c", "d", "e", "f"] {
+            rejections.add(id, "blocked: spam");
+        }
+        rejections.add("g", "");
+        assert_eq!(rejections.total(), 7);
+        let report = rejections.report(false);
+        assert_eq!(report[0], "       6  blocked: spam");
+        assert_eq!(report[6], "          … 1 more (see -v)");
+        assert_eq!(report[7], "       1  no reason given");
+        assert_eq!(rejections.report(true).len(), 9);
+    }
+}
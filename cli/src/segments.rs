@@ -0,0 +1,276 @@
+//! Archives split across several cassettes
+//!
+//! Every event of a cassette lives in one wasm module, so one cassette can't
+//! grow past what a single linear memory (and the compiler) can take. With
+//! `record --segment-bytes` (or `--split-size`) the archive is cut into
+//! time-ordered segments, each a complete cassette covering an unbroken
+//! `since`..`until` window, plus a `<name>.segments.json` manifest.
+//! `--split-events` caps the events per segment instead, and `--split-by`
+//! groups events by kind, month or author first, cutting each group further
+//! when a size or count is given too. `cassette split` cuts an existing
+//! cassette the same way. Loaders open the manifest and query the
+//! segments as one cassette, skipping the ones a filter's time range or kinds
+//! can't reach; the deck simply serves every segment in its directory.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Manifest layout version
+pub const MANIFEST_VERSION: u64 = 1;
+
+fn created_at(event: &Value) -> i64 {
+    event.get("created_at").and_then(|c| c.as_i64()).unwrap_or(0)
+}
+
+fn kind(event: &Value) -> i64 {
+    event.get("kind").and_then(|k| k.as_i64()).unwrap_or(-1)
+}
+
+/// What events are grouped by before any size or count limit applies
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy {
+    /// One segment set per event kind
+    Kind,
+    /// One segment set per calendar month (UTC) of created_at
+    Month,
+    /// One segment set per author pubkey
+    Author,
+}
+
+impl SplitBy {
+    fn name(self) -> &'static str {
+        match self {
+            SplitBy::Kind => "kind",
+            SplitBy::Month => "month",
+            SplitBy::Author => "author",
+        }
+    }
+}
+
+/// How `record` cuts an archive into segments
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Split {
+    pub by: Option<SplitBy>,
+    pub max_bytes: Option<usize>,
+    pub max_events: Option<usize>,
+}
+
+impl Split {
+    /// Whether any splitting was asked for
+    pub fn is_set(&self) -> bool {
+        self.by.is_some() || self.max_bytes.is_some() || self.max_events.is_some()
+    }
+
+    /// How the segments were cut, recorded in the manifest
+    pub fn describe(&self) -> Value {
+        json!({
+            "by": self.by.map(SplitBy::name),
+            "max_bytes": self.max_bytes,
+            "max_events": self.max_events,
+        })
+    }
+
+    /// Cut events into segments, each with the suffix of its file name, newest first within groups
+    pub fn apply(&self, events: Vec<Value>) -> Vec<(String, Vec<Value>)> {
+        let groups = match self.by {
+            Some(by) => group_by(events, by),
+            None => vec![(String::new(), events)],
+        };
+        let mut segments = Vec::new();
+        for (key, events) in groups {
+            let mut parts = match (self.max_bytes, self.max_events) {
+                (Some(max_bytes), _) => split_by_bytes(events, max_bytes),
+                (None, Some(max_events)) => split_by_events(events, max_events),
+                (None, None) => {
+                    let mut events = events;
+                    events.sort_by(cassette_tools::newest_first);
+                    vec![events]
+                }
+            };
+            // A byte budget can still leave too many events in one segment
+            if let (Some(_), Some(max_events)) = (self.max_bytes, self.max_events) {
+                parts = parts.into_iter().flat_map(|part| split_by_events(part, max_events)).collect();
+            }
+            let numbered = parts.len() > 1 || key.is_empty();
+            for (i, part) in parts.into_iter().enumerate() {
+                let suffix = match (key.is_empty(), numbered) {
+                    (true, _) => part_suffix(i + 1),
+                    (false, true) => format!("{}-{}", key, part_suffix(i + 1)),
+                    (false, false) => key.clone(),
+                };
+                segments.push((suffix, part));
+            }
+        }
+        segments
+    }
+}
+
+/// Events grouped by kind (ascending), month (newest first) or author (by pubkey), keyed by their suffix
+fn group_by(events: Vec<Value>, by: SplitBy) -> Vec<(String, Vec<Value>)> {
+    let mut groups: BTreeMap<(i64, String), Vec<Value>> = BTreeMap::new();
+    for event in events {
+        let key = match by {
+            SplitBy::Kind => (kind(&event), format!("kind{}", kind(&event))),
+            SplitBy::Month => {
+                let month = chrono::DateTime::from_timestamp(created_at(&event), 0)
+                    .map_or_else(|| "undated".to_string(), |time| time.format("%Y-%m").to_string());
+                (0, month)
+            }
+            SplitBy::Author => {
+                let pubkey = event.get("pubkey").and_then(|p| p.as_str()).unwrap_or("unknown");
+                (0, pubkey.to_string())
+            }
+        };
+        groups.entry(key).or_default().push(event);
+    }
+    let mut groups: Vec<(String, Vec<Value>)> = groups.into_iter().map(|((_, key), events)| (key, events)).collect();
+    if by == SplitBy::Month {
+        groups.reverse();
+    }
+    groups
+}
+
+/// Whether a path names a segment manifest rather than a cassette
+pub fn is_manifest(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".segments.json")
+}
+
+/// Cut events into newest-first runs of at most `max_bytes` of serialized JSON each;
+/// an event larger than the budget gets a segment of its own
+pub fn split_by_bytes(mut events: Vec<Value>, max_bytes: usize) -> Vec<Vec<Value>> {
+    events.sort_by(cassette_tools::newest_first);
+    let mut segments: Vec<Vec<Value>> = Vec::new();
+    let mut used = 0;
+    for event in events {
+        let bytes = crate::sizes::event_bytes(&event);
+        match segments.last_mut() {
+            Some(segment) if used + bytes <= max_bytes => segment.push(event),
+            _ => {
+                segments.push(vec![event]);
+                used = 0;
+            }
+        }
+        used += bytes;
+    }
+    segments
+}
+
+/// Cut events into newest-first runs of at most `max_events` each
+pub fn split_by_events(mut events: Vec<Value>, max_events: usize) -> Vec<Vec<Value>> {
+    events.sort_by(cassette_tools::newest_first);
+    let mut segments = Vec::new();
+    let mut events = events.into_iter().peekable();
+    while events.peek().is_some() {
+        segments.push(events.by_ref().take(max_events.max(1)).collect());
+    }
+    segments
+}
+
+/// Suffix of the `index`th (1-based) segment of a run
+pub fn part_suffix(index: usize) -> String {
+    format!("part{:03}", index)
+}
+
+/// File name of the segment with `suffix` in the set `name`
+pub fn segment_name(name: &str, suffix: &str) -> String {
+    format!("{}-{}", name, suffix)
+}
+
+/// Time window and position of a segment, embedded under `cassette.segment`
+pub fn segment_metadata(set: &str, index: usize, count: usize, events: &[Value]) -> Value {
+    json!({
+        "set": set,
+        "index": index,
+        "count": count,
+        "since": events.iter().map(created_at).min(),
+        "until": events.iter().map(created_at).max(),
+    })
+}
+
+/// Manifest entry for a built segment, with the hash of the cassette file
+pub fn manifest_entry(cassette: &Path, events: &[Value]) -> Result<Value> {
+    let bytes = std::fs::read(cassette)?;
+    Ok(json!({
+        "file": cassette.file_name().map(|f| f.to_string_lossy().to_string()),
+        "events": events.len(),
+        "bytes": bytes.len(),
+        "since": events.iter().map(created_at).min(),
+        "until": events.iter().map(created_at).max(),
+        "kinds": events.iter().map(kind).collect::<BTreeSet<_>>(),
+        "sha256": hex::encode(Sha256::digest(&bytes)),
+    }))
+}
+
+/// The `<name>.segments.json` document, segments newest first
+pub fn manifest(name: &str, split: &Split, entries: Vec<Value>) -> Value {
+    let events: u64 = entries.iter().filter_map(|e| e.get("events").and_then(|n| n.as_u64())).sum();
+    json!({
+        "version": MANIFEST_VERSION,
+        "name": name,
+        "events": events,
+        "split": split.describe(),
+        "segments": entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_by_bytes() {
+        let event = |created_at: i64, content: &str| json!({"created_at": created_at, "content": content});
+        let events = vec![event(1, "a"), event(3, "c"), event(2, "b"), event(4, &"x".repeat(200))];
+        let small = crate::sizes::event_bytes(&events[0]);
+
+        let segments = split_by_bytes(events.clone(), small * 2);
+        let windows: Vec<Vec<i64>> = segments.iter().map(|s| s.iter().map(created_at).collect()).collect();
+        // The oversized event stands alone, the rest fill segments newest first
+        assert_eq!(windows, vec![vec![4], vec![3, 2], vec![1]]);
+
+        assert_eq!(split_by_bytes(events, usize::MAX).len(), 1);
+        assert_eq!(segment_name("notes", &part_suffix(2)), "notes-part002");
+        assert_eq!(segment_metadata("notes", 2, 3, &segments[1])["since"], 2);
+        assert!(is_manifest(Path::new("out/notes.segments.json")));
+        assert!(!is_manifest(Path::new("out/notes-part001.wasm")));
+        let split = Split { by: Some(SplitBy::Month), ..Default::default() };
+        let manifest = manifest("notes", &split, vec![json!({"events": 2}), json!({"events": 1})]);
+        assert_eq!(manifest["events"], 3);
+        assert_eq!(manifest["split"]["by"], "month");
+    }
+
+    #[test]
+    fn test_split() {
+        let event = |kind: i64, created_at: i64| json!({"kind": kind, "created_at": created_at, "content": ""});
+        // 2024-01-31, 2024-02-01 and 2024-02-02 (UTC)
+        let (january, february, later) = (1706659200, 1706745600, 1706832000);
+        let events = vec![event(1, january), event(7, february), event(1, later), event(1, february)];
+
+        let suffixes = |split: Split| -> Vec<(String, usize)> {
+            split.apply(events.clone()).into_iter().map(|(suffix, part)| (suffix, part.len())).collect()
+        };
+        let by_events = Split { max_events: Some(3), ..Default::default() };
+        assert_eq!(suffixes(by_events), vec![("part001".to_string(), 3), ("part002".to_string(), 1)]);
+
+        let by_kind = Split { by: Some(SplitBy::Kind), ..Default::default() };
+        assert_eq!(suffixes(by_kind), vec![("kind1".to_string(), 3), ("kind7".to_string(), 1)]);
+
+        let by_month = Split { by: Some(SplitBy::Month), max_events: Some(2), ..Default::default() };
+        assert_eq!(suffixes(by_month), vec![
+            ("2024-02-part001".to_string(), 2),
+            ("2024-02-part002".to_string(), 1),
+            ("2024-01".to_string(), 1),
+        ]);
+
+        let authored = vec![json!({"pubkey": "bob", "created_at": 1}), json!({"pubkey": "alice", "created_at": 2}), json!({"pubkey": "bob", "created_at": 3})];
+        let by_author = Split { by: Some(SplitBy::Author), ..Default::default() }.apply(authored);
+        let by_author: Vec<(&str, usize)> = by_author.iter().map(|(suffix, part)| (suffix.as_str(), part.len())).collect();
+        assert_eq!(by_author, vec![("alice", 1), ("bob", 2)]);
+
+        let parts = Split { by: Some(SplitBy::Kind), ..Default::default() }.apply(events);
+        assert_eq!(parts[0].1.iter().map(created_at).collect::<Vec<_>>(), vec![later, february, january]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Signing cassettes for `record --sign`
+//!
+//! The creator's key signs a NIP-98-style manifest over the cassette's event
+//! set (see `cassette_loader::signature` for its layout and the canonical
+//! hash), which is embedded under `cassette.signed_manifest`. `cassette
+//! verify` and the loaders' `verify_manifest()` check it. Keys are given as
+//! an nsec or hex secret key, or as a file holding one, which keeps the key
+//! out of the process list and shell history. Keys that unlock cassettes
+//! recorded with `--encrypt` are read the same way.
+
+use anyhow::{anyhow, Context, Result};
+use cassette_loader::signature;
+use secp256k1::{KeyPair, Message, SECP256K1};
+use serde_json::Value;
+use std::path::Path;
+
+/// A creator key
+pub struct Signer {
+    keypair: KeyPair,
+}
+
+/// Secret key bytes from an nsec or 64 hex characters
+fn parse_secret(key: &str) -> Option<Vec<u8>> {
+    let key = key.trim();
+    if key.starts_with("nsec1") {
+        let (hrp, data) = crate::nip19::decode_bech32(key).ok()?;
+        return (hrp == "nsec" && data.len() == 32).then_some(data);
+    }
+    hex::decode(key).ok().filter(|bytes| bytes.len() == 32)
+}
+
+/// Secret key given as an nsec, hex, or the path of a file holding either
+pub fn load_secret(key: &str) -> Result<Vec<u8>> {
+    match parse_secret(key) {
+        Some(secret) => Ok(secret),
+        None => {
+            let contents = std::fs::read_to_string(Path::new(key))
+                .with_context(|| format!("Expected an nsec, a hex secret key or a key file; can't read {}", key))?;
+            parse_secret(&contents).ok_or_else(|| anyhow!("{} holds no nsec or hex secret key", key))
+        }
+    }
+}
+
+impl Signer {
+    /// Load a key given as an nsec, hex, or the path of a file holding either
+    pub fn load(key: &str) -> Result<Self> {
+        let secret = load_secret(key)?;
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &secret)
+            .map_err(|e| anyhow!("Invalid secret key: {}", e))?;
+        Ok(Self { keypair })
+    }
+
+    /// Hex pubkey the manifests are signed by
+    pub fn pubkey(&self) -> String {
+        self.keypair.x_only_public_key().0.to_string()
+    }
+
+    /// Signed manifest event for a cassette holding `events`; deterministic builds sign without
+    /// auxiliary randomness, so the same key, events and time give the same signature
+    pub fn sign_manifest(&self, name: &str, events: &[Value], created_at: i64, deterministic: bool) -> Result<Value> {
+        let payload = signature::event_set_hash(events);
+        let mut manifest = signature::manifest_template(&self.pubkey(), created_at, name, &payload, events.len());
+        let id = signature::event_id(&self.pubkey(), created_at, signature::MANIFEST_KIND, &manifest["tags"], "");
+        let message = Message::from_slice(&hex::decode(&id)?)?;
+        let sig = if deterministic {
+            SECP256K1.sign_schnorr_no_aux_rand(&message, &self.keypair)
+        } else {
+            SECP256K1.sign_schnorr(&message, &self.keypair)
+        };
+        manifest["id"] = Value::String(id);
+        manifest["sig"] = Value::String(sig.to_string());
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sign_manifest() {
+        // NIP-19 test vector
+        let nsec = "nsec1vl029mgpspedva04g90vltkh6fvh240zqtv9k0t9af8935ke9laqsnlfe5";
+        let hex_key = "67dea2ed018072d675f5415ecfaed7d2597555e202d85b3d65ea4e58d2d92ffa";
+        let signer = Signer::load(nsec).unwrap();
+        assert_eq!(signer.pubkey(), Signer::load(hex_key).unwrap().pubkey());
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("creator.key");
+        std::fs::write(&key_file, format!("{}\n", nsec)).unwrap();
+        assert_eq!(Signer::load(&key_file.to_string_lossy()).unwrap().pubkey(), signer.pubkey());
+        assert!(Signer::load("npub1nothing").is_err());
+
+        let events = vec![json!({"id": "aa", "pubkey": "p", "created_at": 1, "kind": 1, "tags": [], "content": "hi", "sig": "s"})];
+        let manifest = signer.sign_manifest("archive", &events, 1700000000, true).unwrap();
+        let signed = signature::check_manifest(&manifest).unwrap();
+        assert_eq!(signed.pubkey, signer.pubkey());
+        assert_eq!((signed.name.as_str(), signed.events, signed.created_at), ("archive", 1, 1700000000));
+        assert_eq!(signed.payload, signature::event_set_hash(&events));
+        assert_eq!(manifest, signer.sign_manifest("archive", &events, 1700000000, true).unwrap());
+
+        let mut forged = manifest.clone();
+        forged["tags"][2][1] = json!("00".repeat(32));
+        assert!(signature::check_manifest(&forged).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Byte accounting for events and archives
+//!
+//! A handful of large events (kind 1063 file metadata, kind 30078 app data)
+//! can make up most of a cassette. `inspect` breaks the serialized event
+//! bytes down by kind, and `record` and the deck can cap what a single event
+//! or the whole archive may take.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Serialized events of one kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KindBytes {
+    pub kind: i64,
+    pub events: usize,
+    pub bytes: usize,
+    pub largest: usize,
+}
+
+/// Serialized size of an event as it is stored in a cassette
+pub fn event_bytes(event: &Value) -> usize {
+    serde_json::to_string(event).map_or(0, |s| s.len())
+}
+
+/// Bytes per kind, largest share first
+pub fn kind_histogram(events: &[Value]) -> Vec<KindBytes> {
+    let mut by_kind: HashMap<i64, KindBytes> = HashMap::new();
+    for event in events {
+        let kind = event.get("kind").and_then(|k| k.as_i64()).unwrap_or(-1);
+        let bytes = event_bytes(event);
+        let entry = by_kind.entry(kind).or_insert(KindBytes { kind, events: 0, bytes: 0, largest: 0 });
+        entry.events += 1;
+        entry.bytes += bytes;
+        entry.largest = entry.largest.max(bytes);
+    }
+    let mut rows: Vec<KindBytes> = by_kind.into_values().collect();
+    rows.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.kind.cmp(&b.kind)));
+    rows
+}
+
+/// One line per kind with a bar scaled to the share of all bytes; kinds past `top` are summed up
+pub fn format_histogram(rows: &[KindBytes], top: usize) -> Vec<String> {
+    let total: usize = rows.iter().map(|r| r.bytes).sum::<usize>().max(1);
+    let mut lines: Vec<String> = rows.iter().take(top).map(|row| {
+        let share = row.bytes as f64 / total as f64;
+        format!(
+            "kind {:<6} {:>7} events {:>10} {:>5.1}% {:<20} largest {}",
+            row.kind,
+            row.events,
+            format_bytes(row.bytes as u64),
+            share * 100.0,
+            "█".repeat((share * 20.0).round() as usize),
+            format_bytes(row.largest as u64),
+        )
+    }).collect();
+    if rows.len() > top {
+        let rest = &rows[top..];
+        lines.push(format!(
+            "… {} more kinds, {} events, {}",
+            rest.len(),
+            rest.iter().map(|r| r.events).sum::<usize>(),
+            format_bytes(rest.iter().map(|r| r.bytes as u64).sum()),
+        ));
+    }
+    lines
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
+
+/// Parse a byte count with an optional binary suffix, e.g. `65536`, `64k` or `1.5MB`
+pub fn parse_bytes(s: &str) -> Result<usize, String> {
+    let trimmed = s.trim().to_ascii_lowercase();
+    let number = trimmed.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match trimmed[number.len()..].trim_end_matches("ib").trim_end_matches('b') {
+        "" => 1,
+        "k" => 1024,
+        "m" => 1024 * 1024,
+        "g" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid size '{}', expected bytes like 65536, 64k or 2MB", s)),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok((value * multiplier as f64) as usize),
+        _ => Err(format!("invalid size '{}', expected bytes like 65536, 64k or 2MB", s)),
+    }
+}
+
+/// Keep the newest events that fit in `budget` bytes, so the archive covers an unbroken
+/// recent window; returns the kept events and the number and bytes of the dropped ones
+pub fn keep_newest_within(mut events: Vec<Value>, budget: usize) -> (Vec<Value>, usize, usize) {
+    events.sort_by(cassette_tools::newest_first);
+    let mut used = 0;
+    let mut full = false;
+    let mut dropped = 0;
+    let mut dropped_bytes = 0;
+    events.retain(|event| {
+        let bytes = event_bytes(event);
+        full |= used + bytes > budget;
+        if full {
+            dropped += 1;
+            dropped_bytes += bytes;
+        } else {
+            used += bytes;
+        }
+        !full
+    });
+    (events, dropped, dropped_bytes)
+}
+
+/// Size on disk of the cassettes already in a directory
+pub fn cassette_dir_bytes(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    entries.filter_map(|entry| entry.ok())
+        .filter(|entry| crate::is_cassette_file(&entry.path()))
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_histogram_and_budget() {
+        let event = |kind: i64, created_at: i64, content: &str| json!({"kind": kind, "created_at": created_at, "content": content});
+        let events = vec![
+            event(1, 10, "hi"),
+            event(1063, 20, &"x".repeat(500)),
+            event(1, 30, "hello"),
+        ];
+        let rows = kind_histogram(&events);
+        assert_eq!(rows.iter().map(|r| (r.kind, r.events)).collect::<Vec<_>>(), vec![(1063, 1), (1, 2)]);
+        assert_eq!(rows[1].largest, event_bytes(&events[2]));
+        let lines = format_histogram(&rows, 1);
+        assert!(lines[0].starts_with("kind 1063"));
+        assert!(lines[1].starts_with("… 1 more kinds, 2 events"));
+
+        // The oldest event doesn't fit next to the big one
+        let budget = event_bytes(&events[1]) + event_bytes(&events[2]);
+        let (kept, dropped, dropped_bytes) = keep_newest_within(events.clone(), budget);
+        assert_eq!(kept.iter().map(|e| e["created_at"].as_i64().unwrap()).collect::<Vec<_>>(), vec![30, 20]);
+        assert_eq!((dropped, dropped_bytes), (1, event_bytes(&events[0])));
+
+        assert_eq!(format_bytes(2048), "2.0 KB");
+        assert_eq!(parse_bytes("64k"), Ok(65536));
+        assert_eq!(parse_bytes("1.5MB"), Ok(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_bytes("512"), Ok(512));
+        assert_eq!(parse_bytes("2KiB"), Ok(2048));
+        assert!(parse_bytes("lots").is_err());
+    }
+}
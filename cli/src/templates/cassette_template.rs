@@ -1,34 +1,145 @@
 // Import consistent memory management functions from cassette_tools
 extern crate cassette_tools;
-use cassette_tools::{string_to_ptr, ptr_to_string, implement_info};
-use serde::{Serialize, Deserialize};
+use cassette_tools::{string_to_ptr, ptr_to_string, implement_info, messages, newest_first, CassetteError, ChunkedResponse, Count, EventIndex, Filter, FilterLimits, LatestVersions, NostrEvent, Page};
 use serde_json::{json, Value};
 use std::cell::RefCell;
 
-// Embed relay metadata at compile time
-const EMBEDDED_RELAY_INFO: &str = r#"{
-{{#if relay_name}}"name": "{{relay_name}}",{{/if}}
-{{#if relay_description}}"description": "{{relay_description}}",{{/if}}
-{{#if relay_pubkey}}"pubkey": "{{relay_pubkey}}",{{/if}}
-{{#if relay_contact}}"contact": "{{relay_contact}}",{{/if}}
-"software": "@sandwichfarm/cassette",
-"version": "{{version}}",
-"supported_nips": []
-}"#;
-
-// Custom info function that includes embedded relay metadata
+{{#if player}}
+// A player module: `record --fast` copies it, appends a recording as a new data
+// segment and points PLAYER_SLOT at it, so one precompiled module serves any
+// set of events. The magic lets the CLI find the slot in the data section; a
+// slot of all ones means nothing was injected.
+#[repr(C)]
+struct PlayerSlot {
+    magic: [u8; 16],
+    ptr: u32,
+    len: u32,
+}
+
+#[used]
+static PLAYER_SLOT: PlayerSlot = PlayerSlot { magic: *b"cassette-player!", ptr: u32::MAX, len: u32::MAX };
+
+// Positions of the recording fields, matching the order the CLI writes them in
+const RELAY_INFO_FIELD: usize = 0;
+const METADATA_FIELD: usize = 1;
+const FILTER_LIMITS_FIELD: usize = 2;
+const EVENT_INDEX_FIELD: usize = 3;
+#[cfg(feature = "nip50")]
+const TEXT_INDEX_FIELD: usize = 4;
+const LANGUAGE_SEGMENTS_FIELD: usize = 5;
+#[cfg(feature = "verify")]
+const VERIFY_FIELD: usize = 6;
+const EVENTS_FIELD: usize = 7;
+const PROVENANCE_FIELD: usize = 8;
+
+// One field of the injected recording, None when empty: each is a
+// little-endian u32 length followed by that many bytes of UTF-8
+fn player_field(index: usize) -> Option<&'static str> {
+    // Volatile so the compiler can't fold in the placeholder the module was built with
+    let slot = unsafe { std::ptr::read_volatile(&PLAYER_SLOT) };
+    if slot.ptr == u32::MAX {
+        return None;
+    }
+    let mut data: &'static [u8] = unsafe { std::slice::from_raw_parts(slot.ptr as usize as *const u8, slot.len as usize) };
+    for field in 0..=index {
+        let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        let bytes = data.get(4..4 + len)?;
+        if field == index {
+            return std::str::from_utf8(bytes).ok().filter(|field| !field.is_empty());
+        }
+        data = &data[4 + len..];
+    }
+    None
+}
+{{/if}}
+
+// Relay metadata embedded at record time (the NIP-11 fields given to record)
+fn embedded_relay_info() -> &'static str {
+    {{#if player}}player_field(RELAY_INFO_FIELD).unwrap_or("{}"){{else}}r###"{{#if relay_info}}{{relay_info}}{{else}}{}{{/if}}"###{{/if}}
+}
+
+// The CLI version that recorded this cassette
+const CASSETTE_VERSION: &str = "{{version}}";
+
+// Cassette metadata embedded by the CLI at record time
+fn cassette_metadata() -> &'static str {
+    {{#if player}}player_field(METADATA_FIELD).unwrap_or("{}"){{else}}r###"{{#if cassette_metadata}}{{cassette_metadata}}{{else}}{}{{/if}}"###{{/if}}
+}
+
+// Filter complexity limits set at record time (cassette-tools defaults when empty)
+fn filter_limits() -> FilterLimits {
+    FilterLimits::from_json({{#if player}}player_field(FILTER_LIMITS_FIELD).unwrap_or("{}"){{else}}r###"{{#if filter_limits}}{{filter_limits}}{{else}}{}{{/if}}"###{{/if}})
+}
+
+// Kind, author and tag index built at record time (full scans when empty)
+fn event_index_json() -> &'static str {
+    {{#if player}}player_field(EVENT_INDEX_FIELD).unwrap_or("{}"){{else}}r###"{{#if event_index}}{{event_index}}{{else}}{}{{/if}}"###{{/if}}
+}
+
+// Content token index for NIP-50 search (scans every event's content when empty)
+#[cfg(feature = "nip50")]
+fn text_index_json() -> &'static str {
+    {{#if player}}player_field(TEXT_INDEX_FIELD).unwrap_or("{}"){{else}}r###"{{#if text_index}}{{text_index}}{{else}}{}{{/if}}"###{{/if}}
+}
+
+// Whether queries skip events whose id or signature doesn't check out before
+// the host calls set_verify_on_query (`record --verify=query`)
+#[cfg(feature = "verify")]
+fn verify_on_query() -> bool {
+    {{#if player}}player_field(VERIFY_FIELD) == Some("true"){{else}}{{#if verify_on_query}}true{{else}}false{{/if}}{{/if}}
+}
+
+// Which dub input each event came from (`dub --provenance`); empty for other cassettes
+fn provenance_json() -> &'static str {
+    {{#if player}}player_field(PROVENANCE_FIELD).unwrap_or("{}"){{else}}r###"{{#if provenance}}{{provenance}}{{else}}{}{{/if}}"###{{/if}}
+}
+
+// Custom info function that includes embedded relay metadata (exported through the
+// WIT world instead in component cassettes, whose `info` export has the same name)
 #[cfg(feature = "nip11")]
-#[no_mangle]
+{{#if component}}{{else}}#[no_mangle]{{/if}}
 pub extern "C" fn info() -> *mut u8 {
     // Parse the embedded relay info and ensure supported_nips is populated
-    let mut relay_info: serde_json::Map<String, serde_json::Value> = serde_json::from_str(EMBEDDED_RELAY_INFO)
+    let mut relay_info: serde_json::Map<String, serde_json::Value> = serde_json::from_str(embedded_relay_info())
         .unwrap_or_else(|_| serde_json::Map::new());
     
-    // Always update supported_nips with current build features
+    // Fields the host passed to set_info when loading the cassette win
+    if let Some(host_info) = cassette_tools::nips::nip11::host_info() {
+        host_info.overlay(&mut relay_info);
+    }
+    
+    // Always update supported_nips, software and version from the build
     relay_info.insert(
         "supported_nips".to_string(), 
         serde_json::json!(cassette_tools::nips::build_supported_nips())
     );
+    relay_info.insert("software".to_string(), serde_json::json!("@sandwichfarm/cassette"));
+    relay_info.insert("version".to_string(), serde_json::json!(CASSETTE_VERSION));
+    
+    // Advertise the languages this cassette has segments for, unless given
+    let language_tags: Vec<String> = load_language_segments().into_keys()
+        .filter(|lang| lang != "und")
+        .collect();
+    if !language_tags.is_empty() && !relay_info.contains_key("language_tags") {
+        relay_info.insert("language_tags".to_string(), serde_json::json!(language_tags));
+    }
+    
+    // Advertise the filter complexity limits enforced on REQ/COUNT
+    let mut limitation = relay_info.get("limitation")
+        .and_then(|l| l.as_object())
+        .cloned()
+        .unwrap_or_default();
+    filter_limits().advertise(&mut limitation);
+    if !limitation.is_empty() {
+        relay_info.insert("limitation".to_string(), serde_json::Value::Object(limitation));
+    }
+    
+    // Recording metadata (sampling parameters, etc.) embedded by the CLI
+    if let Ok(serde_json::Value::Object(metadata)) = serde_json::from_str(cassette_metadata()) {
+        if !metadata.is_empty() {
+            relay_info.insert("cassette".to_string(), serde_json::Value::Object(metadata));
+        }
+    }
     
     let json_str = serde_json::to_string(&relay_info).unwrap_or_else(|_| "{}".to_string());
     string_to_ptr(json_str)
@@ -40,44 +151,21 @@ pub extern "C" fn info() -> *mut u8 {
 // extern crate cassette_cli;
 // use cassette_cli::{req as req_impl, close as close_impl};
 
-// Core types that match NIP-01 exactly
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct Filter {
-    ids: Option<Vec<String>>,
-    authors: Option<Vec<String>>,
-    kinds: Option<Vec<i64>>,
-    #[serde(flatten)]
-    tag_filters: std::collections::HashMap<String, Vec<String>>,
-    since: Option<i64>,
-    until: Option<i64>,
-    limit: Option<usize>,
-    // NIP-50: Search field
-    search: Option<String>,
-}
-
-// Custom deserialization helpers to ensure NIP-119 tag filters are correctly parsed
-// impl Filter {
-//     // Helper method to check if a key is a NIP-119 AND filter
-//     fn is_nip119_and_filter(key: &str) -> bool {
-//         key.starts_with('&') && key.len() > 1
-//     }
-
-//     // Helper method to check if a key is a regular tag filter
-//     fn is_regular_tag_filter(key: &str) -> bool {
-//         key.starts_with('#') && key.len() == 2
-//     }
-// }
-
-#[derive(Serialize, Deserialize, Clone)]
-struct Note {
-    id: String,
-    pubkey: String,
-    created_at: i64,
-    #[serde(default)]
-    kind: i64,
-    tags: Vec<Vec<String>>,
-    content: String,
-    sig: String,
+// Parse a filter, including the language segment extension (ISO-639-1 codes
+// assigned at record time)
+fn parse_filter(value: &Value) -> Result<Filter, CassetteError> {
+    let filter = Filter::from_json(value)?;
+    if let Some(languages) = filter.extensions.get("languages") {
+        if !languages.as_array().map_or(false, |l| l.iter().all(|lang| lang.is_string())) {
+            return Err(CassetteError::InvalidFilter("'languages' filter must be an array of strings".to_string()));
+        }
+    }
+    Ok(filter)
+}
+
+fn filter_languages(filter: &Filter) -> Option<Vec<&str>> {
+    let languages = filter.extensions.get("languages")?.as_array()?;
+    Some(languages.iter().filter_map(|lang| lang.as_str()).collect())
 }
 
 // Events embedded by CLI during build
@@ -99,10 +187,126 @@ const EVENTS: &str = r#"[{
     "sig": "test_sig"
 }]"#;
 
+{{#if payload_bytes}}
+// Events live in a sidecar payload file; the host checks it against
+// `cassette.payload` in info() and hands it over through load_payload()
+const PAYLOAD_BYTES: usize = {{payload_bytes}};
+
+thread_local! {
+    static PAYLOAD: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) };
+}
+
+#[no_mangle]
+pub extern "C" fn load_payload(ptr: *const u8, len: usize) -> i32 {
+    if PAYLOAD.with(|p| p.get()).is_some() {
+        return 1;
+    }
+    if ptr.is_null() || len != PAYLOAD_BYTES {
+        return 0;
+    }
+    // The host's buffer is taken over rather than copied, so the payload is only held once
+    let bytes = cassette_tools::claim_buffer(ptr as *mut u8, len)
+        .unwrap_or_else(|| unsafe { std::slice::from_raw_parts(ptr, len) }.into());
+    match String::from_utf8(bytes.into_vec()) {
+        Ok(payload) => {
+            // Loaded once and kept for the life of the instance
+            PAYLOAD.with(|p| p.set(Some(Box::leak(payload.into_boxed_str()))));
+            1
+        }
+        Err(_) => 0,
+    }
+}
+
+fn events_json() -> Result<&'static str, CassetteError> {
+    PAYLOAD.with(|p| p.get())
+        .ok_or_else(|| CassetteError::Internal("Events payload not loaded: the host must pass the sidecar file to load_payload".to_string()))
+}
+{{else}}{{#if encrypted_bytes}}
+// Events embedded sealed to one recipient (record --encrypt); the cassette
+// serves nothing until the host passes the recipient's key to unlock()
+const ENCRYPTED_EVENTS: &[u8] = include_bytes!("events.json.enc");
+
+thread_local! {
+    static UNLOCKED: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) };
+}
+
+#[no_mangle]
+pub extern "C" fn unlock(ptr: *const u8, len: usize) -> i32 {
+    if UNLOCKED.with(|u| u.get()).is_some() {
+        return 1;
+    }
+    if ptr.is_null() {
+        return 0;
+    }
+    let key = unsafe { std::slice::from_raw_parts(ptr, len) };
+    let Ok(key) = std::str::from_utf8(key) else { return 0 };
+    match cassette_tools::encrypt::unlock(ENCRYPTED_EVENTS, key).map(String::from_utf8) {
+        Ok(Ok(events)) => {
+            // Decrypted once and kept for the life of the instance
+            UNLOCKED.with(|u| u.set(Some(Box::leak(events.into_boxed_str()))));
+            1
+        }
+        _ => 0,
+    }
+}
+
+fn events_json() -> Result<&'static str, CassetteError> {
+    UNLOCKED.with(|u| u.get())
+        .ok_or_else(|| CassetteError::Internal("Cassette is locked: the host must pass the recipient's key to unlock".to_string()))
+}
+{{else}}{{#if compressed_bytes}}
+// Events embedded as one zstd frame (record --compress); they are decompressed
+// and parsed in a single pass on the first query
+const COMPRESSED_EVENTS: &[u8] = include_bytes!("events.json.zst");
+{{else}}{{#if player}}
+fn events_json() -> Result<&'static str, CassetteError> {
+    player_field(EVENTS_FIELD)
+        .ok_or_else(|| CassetteError::Internal("No recording injected: build cassettes from the player with record --fast".to_string()))
+}
+{{else}}
+fn events_json() -> Result<&'static str, CassetteError> {
+    Ok(EVENTS)
+}
+{{/if}}{{/if}}{{/if}}{{/if}}
+
+// Language segments embedded by CLI during build (language code -> event ids)
+fn language_segments_json() -> &'static str {
+    {{#if player}}player_field(LANGUAGE_SEGMENTS_FIELD).unwrap_or("{}"){{else}}r###"{{#if language_segments}}{{language_segments}}{{else}}{}{{/if}}"###{{/if}}
+}
+
+fn load_language_segments() -> std::collections::BTreeMap<String, Vec<String>> {
+    serde_json::from_str(language_segments_json()).unwrap_or_default()
+}
+
+// Invert the segments into an event id -> language lookup
+fn load_event_languages() -> std::collections::HashMap<String, String> {
+    let mut languages = std::collections::HashMap::new();
+    for (lang, ids) in load_language_segments() {
+        for id in ids {
+            languages.insert(id, lang.clone());
+        }
+    }
+    languages
+}
+
+// A filter that only selects by language can be answered from the segment index
+fn is_language_only_filter(filter: &Filter) -> bool {
+    filter_languages(filter).is_some()
+        && filter.ids.is_none()
+        && filter.authors.is_none()
+        && filter.kinds.is_none()
+        && filter.since.is_none()
+        && filter.until.is_none()
+        && filter.limit.is_none()
+        && filter.search.is_none()
+        && filter.tags.is_empty()
+        && filter.extensions.len() == 1
+}
+
 // Subscription state
 #[derive(Clone)]
 struct SubscriptionState {
-    events: Vec<Note>,
+    events: Vec<NostrEvent>,
     current_index: usize,
     eose_sent: bool,
 }
@@ -110,82 +314,438 @@ struct SubscriptionState {
 // Streaming state management - supports multiple concurrent subscriptions
 thread_local! {
     static SUBSCRIPTIONS: RefCell<std::collections::HashMap<String, SubscriptionState>> = RefCell::new(std::collections::HashMap::new());
+    static EVENT_LANGUAGES: std::collections::HashMap<String, String> = load_event_languages();
+    static PARSED_EVENTS: RefCell<Option<std::rc::Rc<Vec<NostrEvent>>>> = RefCell::new(None);
+    static INDEX: Option<EventIndex> = EventIndex::from_json(event_index_json());
+    static PROVENANCE: Option<cassette_tools::Provenance> = cassette_tools::Provenance::from_json(provenance_json());
+    #[cfg(feature = "nip50")]
+    static TEXT_INDEX_DATA: Option<cassette_tools::TextIndex> = cassette_tools::TextIndex::from_json(text_index_json());
+    static LATEST: RefCell<LatestVersions> = RefCell::default();
+    #[cfg(feature = "nip09")]
+    static DELETIONS: RefCell<cassette_tools::nips::nip09::Deletions> = RefCell::default();
+    #[cfg(feature = "verify")]
+    static VERIFYING: std::cell::Cell<bool> = std::cell::Cell::new(verify_on_query());
+    #[cfg(feature = "verify")]
+    static SIGNATURES: RefCell<cassette_tools::verify::SignatureCache> = RefCell::default();
+    #[cfg(feature = "writable")]
+    static WRITTEN: RefCell<Option<cassette_tools::EventStore>> = RefCell::new(None);
+    #[cfg(not(feature = "minimal"))]
     static DEBUG_MSGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
 }
 
-// New primary entry point for all NIP-01 messages
-#[no_mangle]
-pub extern "C" fn scrub(ptr: *const u8, len: usize) -> *mut u8 {
-    if ptr.is_null() {
-        return string_to_ptr(json!(["NOTICE", "Error: Null request pointer"]).to_string());
-    }
+// Debug logging is compiled out of minimal builds to keep the formatting machinery out of the binary
+macro_rules! debug_msg {
+    ($($arg:tt)*) => {
+        #[cfg(not(feature = "minimal"))]
+        DEBUG_MSGS.with(|msgs| msgs.borrow_mut().push(format!($($arg)*)));
+    };
+}
 
-    // Get the request string from the pointer
-    let request_str = ptr_to_string(ptr, len);
-    
-    // Add debug log
-    DEBUG_MSGS.with(|msgs| {
-        let mut msgs = msgs.borrow_mut();
-        msgs.push(format!("Request received: {}", request_str));
-    });
+// Parse the events on first use and keep them for the life of the instance, so
+// every REQ round, COUNT and later subscription reuses one parse
+fn parsed_events() -> Result<std::rc::Rc<Vec<NostrEvent>>, CassetteError> {
+    if let Some(events) = PARSED_EVENTS.with(|parsed| parsed.borrow().clone()) {
+        return Ok(events);
+    }
     
-    // Parse the message to check if it's COUNT or REQ
-    let msg = match serde_json::from_str::<Value>(&request_str) {
-        Ok(v) => v,
+    {{#if compressed_bytes}}
+    let events: Vec<NostrEvent> = cassette_tools::compress::read_json(COMPRESSED_EVENTS)?;
+    {{else}}
+    let events_json = events_json()?;
+    let events: Vec<NostrEvent> = match serde_json::from_str(events_json) {
+        Ok(notes) => notes,
         Err(e) => {
-            // Log parsing error
+            debug_msg!("Failed to parse embedded events: {}", e);
+            
+            // Print the problematic JSON for debugging
+            #[cfg(not(feature = "minimal"))]
             DEBUG_MSGS.with(|msgs| {
                 let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("JSON parse error: {} in: {}", e, request_str));
+                // Only print the first 200 chars to avoid overflowing logs
+                let preview = if events_json.len() > 200 {
+                    format!("{}...(truncated)", &events_json[0..200])
+                } else {
+                    events_json.to_string()
+                };
+                msgs.push(format!("Events JSON: {}", preview));
+                
+                // Attempt to analyze the first few characters
+                let first_few = events_json.chars().take(20).collect::<String>();
+                msgs.push(format!("First 20 chars: {:?}", first_few));
+                
+                // Check if it appears to be a JSON array
+                if !events_json.trim().starts_with('[') {
+                    msgs.push("Error: Events JSON doesn't start with '[' character".to_string());
+                }
             });
-            return string_to_ptr(json!(["NOTICE", format!("Invalid JSON: {}", e)]).to_string());
+            
+            // Detailed error information including the exact error position
+            return Err(CassetteError::Internal(format!("Failed to load events: {} at position {}", e, e.column())));
         }
     };
+    {{/if}}
+    
+    // Older versions of replaceable events are dropped at record time; queries
+    // check again so stale versions never leak out
+    LATEST.with(|latest| *latest.borrow_mut() = LatestVersions::from_events(&events));
+    
+    // The CLI already drops deleted events at record time; queries check again
+    // so event sets that skipped that step still honour NIP-09
+    #[cfg(feature = "nip09")]
+    DELETIONS.with(|deletions| {
+        *deletions.borrow_mut() = cassette_tools::nips::nip09::Deletions::from_events(&events);
+    });
+    
+    let events = std::rc::Rc::new(events);
+    PARSED_EVENTS.with(|parsed| *parsed.borrow_mut() = Some(events.clone()));
+    Ok(events)
+}
 
-    // Validate message format
-    if !msg.is_array() {
-        DEBUG_MSGS.with(|msgs| {
-            let mut msgs = msgs.borrow_mut();
-            msgs.push(format!("Message is not an array: {}", msg));
-        });
-        return string_to_ptr(json!(["NOTICE", "Message must be an array"]).to_string());
-    }
+// The events that can match any of the filters: the index candidates when every
+// filter is narrowed down by kinds, authors, tags or search terms, otherwise all of them
+fn candidate_events<'a>(events: &'a [NostrEvent], filters: &[Filter]) -> Vec<&'a NostrEvent> {
+    let positions = INDEX.with(|index| {
+        let index = index.as_ref().filter(|index| index.events == events.len());
+        let mut positions = Vec::new();
+        for filter in filters {
+            let candidates = index.and_then(|index| index.filter_candidates(filter));
+            positions.extend(narrow_by_search(events, filter, candidates)?);
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        Some(positions)
+    });
     
-    let arr = msg.as_array().unwrap();
-    if arr.is_empty() {
-        return string_to_ptr(json!(["NOTICE", "Empty message array"]).to_string());
+    match positions {
+        Some(positions) => {
+            debug_msg!("Index narrowed {} events down to {}", events.len(), positions.len());
+            positions.into_iter().map(|position| &events[position as usize]).collect()
+        }
+        None => events.iter().collect(),
     }
-    
-    // Check command type
-    let command = arr[0].as_str().unwrap_or("");
-    match command {
-        "EVENT" => handle_event_command(&arr),
-        "COUNT" => handle_count_command(&arr),
-        "REQ" => handle_req_command(&arr),
-        "CLOSE" => handle_close_command(&arr),
-        _ => {
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("Unknown command: {}", command));
-            });
-            string_to_ptr(json!(["NOTICE", format!("Unknown command: {}", command)]).to_string())
+}
+
+// Sorted positions of the embedded events matching any of the filters. Filters the
+// index covers completely are answered from it and only checked for visibility;
+// the rest get the full match on their index candidates, or on every event
+fn matching_positions(events: &[NostrEvent], filters: &[Filter]) -> Vec<u32> {
+    INDEX.with(|index| {
+        let index = index.as_ref().filter(|index| index.events == events.len());
+        let mut positions = Vec::new();
+        for filter in filters {
+            if let Some(exact) = index.and_then(|index| index.exact_positions(filter)) {
+                positions.extend(exact.into_iter().filter(|&position| is_shown(&events[position as usize], filter)));
+                continue;
+            }
+            let candidates = index.and_then(|index| index.filter_candidates(filter))
+                .unwrap_or_else(|| (0..events.len() as u32).collect());
+            positions.extend(candidates.into_iter().filter(|&position| matches_filter(&events[position as usize], filter)));
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    })
+}
+
+// Intersect a filter's index candidates with the events holding its search terms
+#[cfg(feature = "nip50")]
+fn narrow_by_search(events: &[NostrEvent], filter: &Filter, candidates: Option<Vec<u32>>) -> Option<Vec<u32>> {
+    let Some(search) = &filter.search else {
+        return candidates;
+    };
+    let matches = TEXT_INDEX_DATA.with(|index| {
+        let index = index.as_ref().filter(|index| index.events == events.len())?;
+        Some(index.search(&cassette_tools::nips::nip50::parse_search_query(search).terms))
+    });
+    match (candidates, matches) {
+        (Some(mut candidates), Some(matches)) => {
+            candidates.retain(|position| matches.binary_search(position).is_ok());
+            Some(candidates)
+        }
+        (candidates, matches) => candidates.or(matches),
+    }
+}
+
+#[cfg(not(feature = "nip50"))]
+fn narrow_by_search(_events: &[NostrEvent], _filter: &Filter, candidates: Option<Vec<u32>>) -> Option<Vec<u32>> {
+    candidates
+}
+
+// Events accepted through EVENT messages since the instance started
+#[cfg(feature = "writable")]
+fn written_events() -> Vec<NostrEvent> {
+    WRITTEN.with(|written| written.borrow().as_ref().map_or_else(Vec::new, |store| store.events().to_vec()))
+}
+
+#[cfg(not(feature = "writable"))]
+fn written_events() -> Vec<NostrEvent> {
+    Vec::new()
+}
+
+// Parse the events ahead of the first query so servers can pay for it at preload
+// time; returns the number of events, or -1 when they can't be loaded
+#[no_mangle]
+pub extern "C" fn warm() -> i32 {
+    install_panic_hook();
+    match parsed_events() {
+        Ok(events) => events.len() as i32,
+        Err(e) => {
+            debug_msg!("Warm-up failed: {}", e);
+            -1
         }
     }
 }
 
+// The dub input an event came from, `{"name": ..., "sha256": ...}`, given the
+// event's id; null for ids the table doesn't list and cassettes without one
+#[no_mangle]
+pub extern "C" fn provenance(ptr: *const u8, len: usize) -> *mut u8 {
+    guarded(|| {
+        if ptr.is_null() {
+            return std::ptr::null_mut();
+        }
+        let event_id = ptr_to_string(ptr, len);
+        PROVENANCE.with(|provenance| {
+            match provenance.as_ref().and_then(|provenance| provenance.source(event_id.trim())) {
+                Some(source) => string_to_ptr(source.to_string()),
+                None => std::ptr::null_mut(),
+            }
+        })
+    })
+}
+
+// Hosts pass the current unix time before querying so expired events (NIP-40)
+// can be hidden; the wasm target has no clock of its own
+#[cfg(feature = "nip40")]
+#[no_mangle]
+pub extern "C" fn set_current_time(now: i64) {
+    cassette_tools::nips::nip40::set_current_time(now);
+}
+
+// Check the id and signature of every event the cassette holds, recorded and
+// written: a Verification object, or a NOTICE when the events can't be loaded
+#[cfg(feature = "verify")]
+#[no_mangle]
+pub extern "C" fn verify_events() -> *mut u8 {
+    guarded(|| {
+        match parsed_events() {
+            Ok(events) => {
+                let written = written_events();
+                let verification = cassette_tools::Verification::of(events.iter().chain(written.iter()));
+                string_to_ptr(serde_json::to_string(&verification).unwrap_or_default())
+            }
+            Err(e) => string_to_ptr(e.notice().to_string()),
+        }
+    })
+}
+
+// Hosts that don't trust the cassette's origin turn this on to have queries skip
+// events that fail verify_events' checks; each event is checked once per instance
+#[cfg(feature = "verify")]
+#[no_mangle]
+pub extern "C" fn set_verify_on_query(enabled: i32) {
+    VERIFYING.with(|verifying| verifying.set(enabled != 0));
+}
+
+thread_local! {
+    // What the last panic said, kept for `last_panic`
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Run an export's handler so that a panic in it answers with an internal error
+// NOTICE instead of trapping the instance. wasm32 has no unwinding, so there a
+// panic still traps; the hook keeps its message and hosts read the NOTICE back
+// through `last_panic`
+fn guarded(handler: impl FnOnce() -> *mut u8) -> *mut u8 {
+    install_panic_hook();
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(handler))
+        .unwrap_or_else(|_| last_panic())
+}
+
+// Record every panic's message and location before the default hook runs
+fn install_panic_hook() {
+    static HOOK: std::sync::Once = std::sync::Once::new();
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |panic| {
+            let payload = panic.payload();
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".to_string());
+            let message = match panic.location() {
+                Some(location) => format!("{} at {}:{}", message, location.file(), location.line()),
+                None => message,
+            };
+            LAST_PANIC.with(|last| last.replace(Some(message)));
+            previous(panic);
+        }));
+    });
+}
+
+// The NOTICE for the last panic, taking it so it is reported once; null when
+// nothing has panicked since
+#[no_mangle]
+pub extern "C" fn last_panic() -> *mut u8 {
+    match LAST_PANIC.with(|last| last.take()) {
+        Some(message) => string_to_ptr(json!(["NOTICE", format!("internal error: {}", message)]).to_string()),
+        None => std::ptr::null_mut(),
+    }
+}
+
+// New primary entry point for all NIP-01 messages
+#[no_mangle]
+pub extern "C" fn scrub(ptr: *const u8, len: usize) -> *mut u8 {
+    guarded(|| {
+        if ptr.is_null() {
+            return string_to_ptr(json!(["NOTICE", "Error: Null request pointer"]).to_string());
+        }
+
+        // Get the request string from the pointer
+        let request_str = ptr_to_string(ptr, len);
+    
+        // Add debug log
+        debug_msg!("Request received: {}", request_str);
+    
+        // Parse the message to check if it's COUNT or REQ
+        let msg = match serde_json::from_str::<Value>(&request_str) {
+            Ok(v) => v,
+            Err(e) => {
+                // Log parsing error
+                debug_msg!("JSON parse error: {} in: {}", e, request_str);
+                return string_to_ptr(json!(["NOTICE", format!("Invalid JSON: {}", e)]).to_string());
+            }
+        };
+
+        // Validate message format
+        if !msg.is_array() {
+            debug_msg!("Message is not an array: {}", msg);
+            return string_to_ptr(json!(["NOTICE", "Message must be an array"]).to_string());
+        }
+    
+        let arr = msg.as_array().unwrap();
+        if arr.is_empty() {
+            return string_to_ptr(json!(["NOTICE", "Empty message array"]).to_string());
+        }
+    
+        // Check command type
+        let command = arr[0].as_str().unwrap_or("");
+        match command {
+            "EVENT" => handle_event_command(&arr),
+            "COUNT" => handle_count_command(&arr),
+            "REQ" => handle_req_command(&arr),
+            "CLOSE" => handle_close_command(&arr),
+            _ => {
+                debug_msg!("Unknown command: {}", command);
+                string_to_ptr(json!(["NOTICE", format!("Unknown command: {}", command)]).to_string())
+            }
+        }
+    })
+}
+
 // Deprecated: Use 'scrub' instead. This function is kept for backward compatibility.
 #[no_mangle]
 pub extern "C" fn send(ptr: *const u8, len: usize) -> *mut u8 {
     // Log deprecation warning
-    DEBUG_MSGS.with(|msgs| {
-        let mut msgs = msgs.borrow_mut();
-        msgs.push("WARNING: 'send' is deprecated. Please use 'scrub' instead.".to_string());
-    });
+    debug_msg!("WARNING: 'send' is deprecated. Please use 'scrub' instead.");
     
     // Call the new scrub function
     scrub(ptr, len)
 }
 
+// Answer a REQ with one bounded page of its results, starting at `cursor` (0 for
+// the first page): `{"events": [...], "next_cursor": n}`, `next_cursor` null after
+// the last page, or the CLOSED message refusing the REQ. Every call runs the query
+// afresh, so hosts can hold a cursor without the cassette holding a subscription.
+#[no_mangle]
+pub extern "C" fn req_page(ptr: *const u8, len: usize, cursor: u64, page_size: u32) -> *mut u8 {
+    guarded(|| {
+        if ptr.is_null() {
+            return string_to_ptr(json!(["NOTICE", "Error: Null request pointer"]).to_string());
+        }
+    
+        let request_str = ptr_to_string(ptr, len);
+        let msg = match serde_json::from_str::<Value>(&request_str) {
+            Ok(v) => v,
+            Err(e) => return string_to_ptr(json!(["NOTICE", format!("Invalid JSON: {}", e)]).to_string()),
+        };
+        let arr = match msg.as_array() {
+            Some(arr) if arr.len() >= 3 && arr[0] == "REQ" => arr,
+            _ => return string_to_ptr(json!(["NOTICE", "req_page expects a REQ with at least one filter"]).to_string()),
+        };
+    
+        let subscription_id = arr[1].as_str().unwrap_or("");
+        match query_events(subscription_id, &arr[2..]) {
+            Ok(events) => {
+                let page = Page::of(&events, cursor, page_size as usize);
+                debug_msg!("Page at {} of {}: {} events", cursor, subscription_id, page.events.len());
+                string_to_ptr(serde_json::to_string(&page).unwrap_or_default())
+            }
+            Err(closed) => string_to_ptr(closed),
+        }
+    })
+}
+
+thread_local! {
+    // The response result_next_chunk is handing out
+    static RESULT: RefCell<Option<ChunkedResponse>> = const { RefCell::new(None) };
+}
+
+// Queue the whole answer to a message for result_next_chunk to hand out in chunks
+// of at most `chunk_bytes` (0 for the default): a REQ's events and EOSE, or the one
+// message any other command is answered with. Like req_page it runs the query
+// afresh without holding a subscription. Returns how many messages were queued,
+// replacing any response still queued, or -1 for a null pointer.
+#[no_mangle]
+pub extern "C" fn result_begin(ptr: *const u8, len: usize, chunk_bytes: u32) -> i32 {
+    if ptr.is_null() {
+        return -1;
+    }
+    install_panic_hook();
+    
+    let request_str = ptr_to_string(ptr, len);
+    let arr = match serde_json::from_str::<Value>(&request_str) {
+        Ok(Value::Array(arr)) => arr,
+        _ => Vec::new(),
+    };
+    let chunk_bytes = chunk_bytes as usize;
+    let (queued, response) = match arr.first().and_then(|c| c.as_str()) {
+        Some("REQ") if arr.len() >= 3 => {
+            let subscription_id = arr[1].as_str().unwrap_or("");
+            match query_events(subscription_id, &arr[2..]) {
+                Ok(events) => (events.len() + 1, ChunkedResponse::req(subscription_id, events, chunk_bytes)),
+                Err(closed) => (1, ChunkedResponse::new([closed], chunk_bytes)),
+            }
+        }
+        _ => (1, ChunkedResponse::new([cassette_tools::take_string(scrub(ptr, len))], chunk_bytes)),
+    };
+    debug_msg!("Queued {} messages in chunks of {} bytes", queued, chunk_bytes);
+    RESULT.with(|result| result.replace(Some(response)));
+    queued as i32
+}
+
+// The next chunk of the queued response, newline-separated messages; null once
+// it is drained
+#[no_mangle]
+pub extern "C" fn result_next_chunk() -> *mut u8 {
+    guarded(|| {
+        let chunk = RESULT.with(|result| {
+            let mut result = result.borrow_mut();
+            let chunk = result.as_mut().and_then(|response| response.next());
+            if chunk.is_none() {
+                *result = None;
+            }
+            chunk
+        });
+        chunk.map_or(std::ptr::null_mut(), string_to_ptr)
+    })
+}
+
+// Drop the rest of a queued response the host stopped reading
+#[no_mangle]
+pub extern "C" fn result_end() {
+    RESULT.with(|result| result.replace(None));
+}
+
 // Handle EVENT command
 fn handle_event_command(arr: &[Value]) -> *mut u8 {
     if arr.len() < 2 {
@@ -199,8 +759,38 @@ fn handle_event_command(arr: &[Value]) -> *mut u8 {
         String::new()
     };
     
-    // Return OK with error message for read-only relay
-    string_to_ptr(json!(["OK", event_id, false, "error: relay is read-only"]).to_string())
+    // Reject with an OK message unless the cassette was recorded writable
+    #[cfg(not(feature = "writable"))]
+    return string_to_ptr(messages::ok(&event_id, false, messages::READ_ONLY).to_string());
+    
+    #[cfg(feature = "writable")]
+    {
+        let event = match NostrEvent::from_value(&arr[1]) {
+            Ok(event) => event,
+            Err(e) => return string_to_ptr(messages::ok(&event_id, false, &e.reason()).to_string()),
+        };
+        let recorded = match parsed_events() {
+            Ok(events) => events,
+            Err(e) => return string_to_ptr(messages::ok(&event_id, false, &e.reason()).to_string()),
+        };
+        
+        // The buffer knows the recorded events, so duplicates and stale versions of
+        // them are turned away too
+        let result = WRITTEN.with(|written| {
+            written.borrow_mut()
+                .get_or_insert_with(|| cassette_tools::EventStore::over(&recorded))
+                .insert(event.clone())
+        });
+        match result {
+            Ok(()) => {
+                // A newer version hides the recorded one from later queries
+                LATEST.with(|latest| latest.borrow_mut().insert(&event));
+                debug_msg!("Stored written event {}", event_id);
+                string_to_ptr(messages::ok(&event_id, true, "").to_string())
+            }
+            Err(e) => string_to_ptr(messages::ok(&event_id, false, &e.reason()).to_string()),
+        }
+    }
 }
 
 // Handle COUNT command
@@ -214,152 +804,159 @@ fn handle_count_command(arr: &[Value]) -> *mut u8 {
         return string_to_ptr(json!(["NOTICE", "Invalid subscription ID"]).to_string());
     }
     
+    if let Some(closed) = filter_limits().closed_message(&subscription_id, &arr[2..]) {
+        return string_to_ptr(closed);
+    }
+    
     // Parse filters
     let mut filters = Vec::new();
     for f in &arr[2..] {
-        match serde_json::from_value::<Filter>(f.clone()) {
+        match parse_filter(f) {
             Ok(filter) => filters.push(filter),
             Err(e) => {
-                DEBUG_MSGS.with(|msgs| {
-                    let mut msgs = msgs.borrow_mut();
-                    msgs.push(format!("Filter parse error in COUNT: {} in: {}", e, f));
-                });
+                debug_msg!("Filter parse error in COUNT: {} in: {}", e, f);
+                return string_to_ptr(messages::closed(&subscription_id, &e.reason()).to_string());
             }
         }
     }
     
+    let written = written_events();
+    
+    // Fast path: language-only filters are counted straight from the segment sizes
+    if written.is_empty() && !filters.is_empty() && filters.iter().all(is_language_only_filter) {
+        let mut languages: Vec<String> = filters.iter()
+            .flat_map(|f| filter_languages(f).into_iter().flatten())
+            .map(|lang| lang.to_lowercase())
+            .collect();
+        languages.sort();
+        languages.dedup();
+        
+        let segments = load_language_segments();
+        let count: usize = languages.iter()
+            .filter_map(|lang| segments.get(lang))
+            .map(|ids| ids.len())
+            .sum();
+        
+        // Segment sizes are fixed at record time and can't see deleted or expired events
+        let count = Count {
+            count: count as u64,
+            approximate: cfg!(any(feature = "nip09", feature = "nip40")),
+            by_kind: None,
+        };
+        return string_to_ptr(json!(["COUNT", subscription_id, count.to_json()]).to_string());
+    }
+    
     // Load and parse events
-    let events: Vec<Note> = match serde_json::from_str(EVENTS) {
-        Ok(notes) => notes,
-        Err(_) => Vec::new(),
+    let events = match parsed_events() {
+        Ok(events) => events,
+        Err(e) => return string_to_ptr(messages::closed(&subscription_id, &e.reason()).to_string()),
     };
     
-    // Count matching events
-    let mut count = 0usize;
-    'count_loop: for event in &events {
-        for filter in &filters {
-            if matches_filter(event, filter) {
-                count += 1;
-                continue 'count_loop;
-            }
+    // Count matching events by position, without collecting them
+    let mut count = Count::for_filters(&filters);
+    for position in matching_positions(&events, &filters) {
+        count.add(events[position as usize].kind);
+    }
+    for event in &written {
+        if filters.iter().any(|filter| matches_filter(event, filter)) {
+            count.add(event.kind);
         }
     }
     
     // Return COUNT response according to NIP-45
-    string_to_ptr(json!(["COUNT", subscription_id, {
-        "count": count
-    }]).to_string())
+    string_to_ptr(json!(["COUNT", subscription_id, count.to_json()]).to_string())
 }
 
-// Handle REQ command  
-fn handle_req_command(arr: &[Value]) -> *mut u8 {
-    if arr.len() < 3 {
-        return string_to_ptr(json!(["NOTICE", "REQ must contain at least command, id, and filter"]).to_string());
-    }
-    
-    let subscription_id = arr[1].as_str().unwrap_or("").to_string();
-    if subscription_id.is_empty() {
-        DEBUG_MSGS.with(|msgs| {
-            let mut msgs = msgs.borrow_mut();
-            msgs.push("Empty subscription ID".to_string());
-        });
-        return string_to_ptr(json!(["NOTICE", "Invalid subscription ID"]).to_string());
+// The events a REQ's filters select, in the order they are served; the error is
+// the CLOSED message refusing the REQ
+fn query_events(subscription_id: &str, filter_values: &[Value]) -> Result<Vec<NostrEvent>, String> {
+    if let Some(closed) = filter_limits().closed_message(subscription_id, filter_values) {
+        debug_msg!("REQ {} exceeds filter limits", subscription_id);
+        return Err(closed);
     }
     
     // Parse filters
     let mut filters = Vec::new();
-    for f in &arr[2..] {
-        match serde_json::from_value::<Filter>(f.clone()) {
+    for f in filter_values {
+        match parse_filter(f) {
             Ok(filter) => filters.push(filter),
             Err(e) => {
-                DEBUG_MSGS.with(|msgs| {
-                    let mut msgs = msgs.borrow_mut();
-                    msgs.push(format!("Filter parse error: {} in: {}", e, f));
-                });
-                // Continue with any valid filters
+                debug_msg!("Filter parse error: {} in: {}", e, f);
+                return Err(messages::closed(subscription_id, &e.reason()).to_string());
             }
         }
     }
 
     // Load and parse events
-    let events: Vec<Note> = match serde_json::from_str(EVENTS) {
-        Ok(notes) => notes,
-        Err(e) => {
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("Failed to parse embedded events: {}", e));
-            });
-            
-            // Print the problematic JSON for debugging
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                // Only print the first 200 chars to avoid overflowing logs
-                let preview = if EVENTS.len() > 200 {
-                    format!("{}...(truncated)", &EVENTS[0..200])
-                } else {
-                    EVENTS.to_string()
-                };
-                msgs.push(format!("Events JSON: {}", preview));
-                
-                // Attempt to analyze the first few characters
-                let first_few = EVENTS.chars().take(20).collect::<String>();
-                msgs.push(format!("First 20 chars: {:?}", first_few));
-                
-                // Check if it appears to be a JSON array
-                if !EVENTS.trim().starts_with('[') {
-                    msgs.push("Error: Events JSON doesn't start with '[' character".to_string());
-                }
-            });
-            
-            // Return a notice with more detailed error information including the exact error position
-            let error_msg = format!("Failed to load events: {} at position {}", e, e.column());
-            return string_to_ptr(json!(["NOTICE", error_msg]).to_string())
-        }
+    let events = match parsed_events() {
+        Ok(events) => events,
+        Err(e) => return Err(messages::closed(subscription_id, &e.reason()).to_string()),
     };
-    // Apply filters (NIP-01: filters are OR'd together, conditions within a filter are AND'd)
+    // Each filter selects its own matches among the recorded events and any written
+    // since, in result order, and keeps its first `limit` of them; the union is what the
+    // REQ returns (NIP-01: filters are OR'd together, conditions within a filter are AND'd)
+    let written = written_events();
+    let mut seen = std::collections::HashSet::new();
     let mut matching_events = Vec::new();
-    
-    'event_loop: for event in events {
-        for filter in &filters {
-            if matches_filter(&event, filter) {
-                matching_events.push(event.clone());
-                continue 'event_loop;
-            }
+    for filter in &filters {
+        let mut matches: Vec<&NostrEvent> = candidate_events(&events, std::slice::from_ref(filter)).into_iter()
+            .chain(&written)
+            .filter(|event| matches_filter(event, filter))
+            .collect();
+        sort_results(&mut matches, filter.search.as_deref());
+        if let Some(limit) = filter.limit {
+            matches.truncate(limit);
         }
+        matching_events.extend(matches.into_iter().filter(|event| seen.insert(event.id.as_str())));
     }
-
-    // Check if any filter has a search query (NIP-50)
-    let has_search_query = filters.iter().any(|f| f.search.is_some());
     
-    if has_search_query {
-        // NIP-50: Sort by search relevance (highest score first)
-        #[cfg(feature = "nip50")]
-        {
-            // Get the first search query for scoring
-            let search_query = filters.iter()
-                .find_map(|f| f.search.as_ref())
-                .cloned()
-                .unwrap_or_default();
-                
-            matching_events.sort_by(|a, b| {
-                let score_a = score_event_for_search(a, &search_query);
-                let score_b = score_event_for_search(b, &search_query);
-                score_b.partial_cmp(&score_a).unwrap_or(std::cmp::Ordering::Equal)
-            });
-        }
-    } else {
-        // Default: Sort by created_at in reverse order (newest first)
-        matching_events.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    // The union is ranked by the first search query when there is one (NIP-50)
+    sort_results(&mut matching_events, filters.iter().find_map(|f| f.search.as_deref()));
+    Ok(matching_events.into_iter().cloned().collect())
+}
+
+// Put results in NIP-01 order, newest first with the lowest id first within the same
+// second, or ranked by relevance to a search query unless it asks for `order:created_at`
+#[cfg(feature = "nip50")]
+fn sort_results(events: &mut Vec<&NostrEvent>, search: Option<&str>) {
+    let query = search.map(cassette_tools::nips::nip50::parse_search_query)
+        .filter(|query| !query.by_created_at());
+    let Some(query) = query else {
+        events.sort_by(|a, b| newest_first(*a, *b));
+        return;
+    };
+    // Score each event once; equal scores keep NIP-01 order
+    let mut scored: Vec<(f32, &NostrEvent)> = events.drain(..)
+        .map(|event| (cassette_tools::nips::nip50::score_content(&event.content, &query), event))
+        .collect();
+    scored.sort_by(|(score_a, a), (score_b, b)| {
+        score_b.partial_cmp(score_a).unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| newest_first(*a, *b))
+    });
+    events.extend(scored.into_iter().map(|(_, event)| event));
+}
+
+#[cfg(not(feature = "nip50"))]
+fn sort_results(events: &mut Vec<&NostrEvent>, _search: Option<&str>) {
+    events.sort_by(|a, b| newest_first(*a, *b));
+}
+
+// Handle REQ command  
+fn handle_req_command(arr: &[Value]) -> *mut u8 {
+    if arr.len() < 3 {
+        return string_to_ptr(json!(["NOTICE", "REQ must contain at least command, id, and filter"]).to_string());
     }
     
-    // Apply limit if specified - find the highest limit across all filters
-    let max_limit = filters.iter()
-        .filter_map(|f| f.limit)
-        .max();
-        
-    if let Some(limit) = max_limit {
-        matching_events.truncate(limit);
+    let subscription_id = arr[1].as_str().unwrap_or("").to_string();
+    if subscription_id.is_empty() {
+        debug_msg!("Empty subscription ID");
+        return string_to_ptr(json!(["NOTICE", "Invalid subscription ID"]).to_string());
     }
+    
+    let matching_events = match query_events(&subscription_id, &arr[2..]) {
+        Ok(events) => events,
+        Err(closed) => return string_to_ptr(closed),
+    };
 
     // Update or create subscription state
     SUBSCRIPTIONS.with(|subs| {
@@ -413,10 +1010,7 @@ fn handle_close_command(arr: &[Value]) -> *mut u8 {
     
     let subscription_id = arr[1].as_str().unwrap_or("").to_string();
     if subscription_id.is_empty() {
-        DEBUG_MSGS.with(|msgs| {
-            let mut msgs = msgs.borrow_mut();
-            msgs.push("Empty subscription ID in CLOSE".to_string());
-        });
+        debug_msg!("Empty subscription ID in CLOSE");
         return string_to_ptr(json!(["NOTICE", "Invalid subscription ID"]).to_string());
     }
     
@@ -425,195 +1019,113 @@ fn handle_close_command(arr: &[Value]) -> *mut u8 {
         subs.borrow_mut().remove(&subscription_id);
     });
     
-    // Respond with a simple notice
-    string_to_ptr(json!(["NOTICE", "Subscription closed"]).to_string())
+    // Confirm with CLOSED; an empty reason means the client asked for it
+    string_to_ptr(messages::closed(&subscription_id, "").to_string())
 }
 
 // Helper function to check if an event matches a filter according to NIP-01
-fn matches_filter(event: &Note, filter: &Filter) -> bool {
-    // Check IDs
-    if let Some(ids) = &filter.ids {
-        if !ids.contains(&event.id) {
-            return false;
-        }
-    }
+fn matches_filter(event: &NostrEvent, filter: &Filter) -> bool {
+    filter.matches(event) && is_shown(event, filter)
+}
 
-    // Check authors
-    if let Some(authors) = &filter.authors {
-        if !authors.contains(&event.pubkey) {
-            return false;
-        }
+// Whether an event the filter's NIP-01 constraints select is served: it must be the
+// latest version, not deleted or expired, in one of the filter's languages and,
+// when verifying on query, correctly signed
+fn is_shown(event: &NostrEvent, filter: &Filter) -> bool {
+    if !LATEST.with(|latest| latest.borrow().is_latest(event)) {
+        return false;
     }
 
-    // Check kinds
-    if let Some(kinds) = &filter.kinds {
-        if !kinds.contains(&event.kind) {
-            return false;
-        }
+    #[cfg(feature = "nip09")]
+    if DELETIONS.with(|deletions| deletions.borrow().is_deleted(event)) {
+        return false;
     }
 
-    // Check timestamps
-    if let Some(since) = filter.since {
-        if event.created_at < since {
-            return false;
-        }
-    }
-    if let Some(until) = filter.until {
-        if event.created_at > until {
-            return false;
-        }
+    #[cfg(feature = "nip40")]
+    if cassette_tools::nips::nip40::is_expired_now(event) {
+        return false;
     }
 
-    // Check tag filters
-    for (key, values) in &filter.tag_filters {
-        // Log the tag filter for debugging
-        DEBUG_MSGS.with(|msgs| {
-            let mut msgs = msgs.borrow_mut();
-            msgs.push(format!("Checking tag filter: {} with values: {:?}", key, values));
+    // Check language segments
+    if let Some(languages) = filter_languages(filter) {
+        let in_segment = EVENT_LANGUAGES.with(|event_languages| {
+            event_languages.get(&event.id)
+                .map_or(false, |lang| languages.iter().any(|l| l.eq_ignore_ascii_case(lang)))
         });
-        
-        if key.starts_with('&') {
-            // NIP-119: All tag values must be present
-            let tag_name = &key[1..];
-            
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("NIP-119 AND filter for tag '{}' with values: {:?}", tag_name, values));
-            });
-            
-            let tag_values: Vec<String> = event.tags.iter()
-                .filter(|t| t.get(0).map_or(false, |n| n == tag_name))
-                .filter_map(|t| t.get(1).cloned())
-                .collect();
-                
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("Event has tag values: {:?}", tag_values));
-            });
-
-            // For NIP-119 AND semantics, all requested values must be present
-            if !values.iter().all(|v| tag_values.contains(v)) {
-                DEBUG_MSGS.with(|msgs| {
-                    let mut msgs = msgs.borrow_mut();
-                    msgs.push(format!("NIP-119 filter failed - not all values present"));
-                });
-                return false;
-            }
-            
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("NIP-119 filter matched"));
-            });
-        } else if key.starts_with('#') {
-            // Regular tag filter: Any value must match
-            let tag_name = &key[1..];
-            
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("Regular tag filter for tag '{}' with values: {:?}", tag_name, values));
-            });
-            
-            let tag_values: Vec<String> = event.tags.iter()
-                .filter(|t| t.get(0).map_or(false, |n| n == tag_name))
-                .filter_map(|t| t.get(1).cloned())
-                .collect();
-                
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("Event has tag values: {:?}", tag_values));
-            });
-
-            // For regular OR semantics, at least one value must be present
-            if !values.iter().any(|v| tag_values.contains(v)) {
-                DEBUG_MSGS.with(|msgs| {
-                    let mut msgs = msgs.borrow_mut();
-                    msgs.push(format!("Regular tag filter failed - no matching values"));
-                });
-                return false;
-            }
-            
-            DEBUG_MSGS.with(|msgs| {
-                let mut msgs = msgs.borrow_mut();
-                msgs.push(format!("Regular tag filter matched"));
-            });
+        if !in_segment {
+            return false;
         }
     }
 
-    // Check search query (NIP-50)
-    #[cfg(feature = "nip50")]
-    if let Some(search_query) = &filter.search {
-        if !matches_search_query(event, search_query) {
-            return false;
-        }
+    #[cfg(feature = "verify")]
+    if VERIFYING.with(|verifying| verifying.get()) && !SIGNATURES.with(|signatures| signatures.borrow_mut().is_valid(event)) {
+        return false;
     }
 
-    // If we've passed all conditions, the event matches the filter
     true
 }
 
-// NIP-50 search functionality
-#[cfg(feature = "nip50")]
-fn matches_search_query(event: &Note, search_query: &str) -> bool {
-    // Convert to serde_json::Value for compatibility with nip50 module
-    let event_json = serde_json::json!({
-        "id": event.id,
-        "pubkey": event.pubkey,
-        "created_at": event.created_at,
-        "kind": event.kind,
-        "tags": event.tags,
-        "content": event.content,
-        "sig": event.sig
-    });
-    
-    // Use the nip50 module's scoring function
-    cassette_tools::nips::nip50::score_event(&event_json, 
-        &cassette_tools::nips::nip50::parse_search_query(search_query)) > 0.0
-}
-
-#[cfg(feature = "nip50")]
-fn score_event_for_search(event: &Note, search_query: &str) -> f32 {
-    // Convert to serde_json::Value for compatibility with nip50 module
-    let event_json = serde_json::json!({
-        "id": event.id,
-        "pubkey": event.pubkey,
-        "created_at": event.created_at,
-        "kind": event.kind,
-        "tags": event.tags,
-        "content": event.content,
-        "sig": event.sig
-    });
-    
-    // Use the nip50 module's scoring function
-    cassette_tools::nips::nip50::score_event(&event_json, 
-        &cassette_tools::nips::nip50::parse_search_query(search_query))
-}
-
 // Note: Memory management functions are already exported by cassette_tools
 // We don't need to re-export them here to avoid duplicate symbols
 
 // Export a function to continue streaming events for active subscriptions
 #[no_mangle]  
 pub extern "C" fn next() -> *mut u8 {
-    // Check all active subscriptions and return the next pending event
-    SUBSCRIPTIONS.with(|subs| {
-        let mut subs = subs.borrow_mut();
+    guarded(|| {
+        // Check all active subscriptions and return the next pending event
+        SUBSCRIPTIONS.with(|subs| {
+            let mut subs = subs.borrow_mut();
         
-        // Find a subscription with pending events
-        for (sub_id, state) in subs.iter_mut() {
-            if state.current_index < state.events.len() {
-                // Return next event for this subscription
-                let response = json!(["EVENT", sub_id.clone(), &state.events[state.current_index]]);
-                state.current_index += 1;
-                return string_to_ptr(response.to_string());
-            } else if !state.eose_sent {
-                // Send EOSE for this subscription
-                state.eose_sent = true;
-                return string_to_ptr(json!(["EOSE", sub_id.clone()]).to_string());
+            // Find a subscription with pending events
+            for (sub_id, state) in subs.iter_mut() {
+                if state.current_index < state.events.len() {
+                    // Return next event for this subscription
+                    let response = json!(["EVENT", sub_id.clone(), &state.events[state.current_index]]);
+                    state.current_index += 1;
+                    return string_to_ptr(response.to_string());
+                } else if !state.eose_sent {
+                    // Send EOSE for this subscription
+                    state.eose_sent = true;
+                    return string_to_ptr(json!(["EOSE", sub_id.clone()]).to_string());
+                }
             }
-        }
         
-        // No pending events in any subscription
-        string_to_ptr(json!(["NOTICE", "No pending events"]).to_string())
+            // No pending events in any subscription
+            string_to_ptr(json!(["NOTICE", "No pending events"]).to_string())
+        })
     })
 }
 
+{{#if component}}
+// Component-model interface (`record --component`): the WIT world in wit/cassette.wit,
+// answered by the same handlers as the core exports
+mod component {
+    wit_bindgen::generate!({ world: "cassette", path: "wit" });
+
+    struct Cassette;
+
+    impl Guest for Cassette {
+        fn req(message: String) -> String {
+            answer(&message)
+        }
+
+        fn event(message: String) -> String {
+            answer(&message)
+        }
+
+        fn close(message: String) -> String {
+            answer(&message)
+        }
+
+        fn info() -> String {
+            cassette_tools::take_string(super::info())
+        }
+    }
+
+    fn answer(message: &str) -> String {
+        cassette_tools::take_string(super::scrub(message.as_ptr(), message.len()))
+    }
+
+    export!(Cassette);
+}
+{{/if}}
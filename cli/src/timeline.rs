@@ -0,0 +1,378 @@
+//! Events-per-day/week statistics for `cassette stats` and `cassette stats timeline`
+//!
+//! Matching events are counted into fixed calendar buckets (UTC days, or ISO
+//! weeks starting on Monday) from the oldest to the newest one, empty buckets
+//! included, optionally split into one series per kind or author. A digest
+//! adds content sizes and the top authors, kinds and tag names to the series.
+
+use chrono::DateTime;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+const DAY: i64 = 86_400;
+
+/// Bucket width of a timeline
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    Day,
+    Week,
+}
+
+impl Bucket {
+    /// Index of the bucket holding a timestamp
+    fn index(&self, created_at: i64) -> i64 {
+        let day = created_at.div_euclid(DAY);
+        match self {
+            Bucket::Day => day,
+            // 1970-01-01 was a Thursday, so shift by 3 days to start weeks on Monday
+            Bucket::Week => (day + 3).div_euclid(7),
+        }
+    }
+
+    /// First second of a bucket
+    fn start(&self, index: i64) -> i64 {
+        match self {
+            Bucket::Day => index * DAY,
+            Bucket::Week => (index * 7 - 3) * DAY,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+        }
+    }
+}
+
+/// A bucket width as `1d`/`day` or `1w`/`7d`/`week`
+pub fn parse_bucket(value: &str) -> Result<Bucket, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1d" | "d" | "day" => Ok(Bucket::Day),
+        "1w" | "7d" | "w" | "week" => Ok(Bucket::Week),
+        _ => Err(format!("unsupported bucket '{}', use 1d or 1w", value)),
+    }
+}
+
+/// What each series of a timeline counts
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Kind,
+    Author,
+}
+
+#[derive(Debug)]
+pub struct Timeline {
+    bucket: Bucket,
+    /// Index of the first bucket
+    first: i64,
+    /// Series name and count per bucket, largest total first
+    series: Vec<(String, Vec<u64>)>,
+}
+
+impl Timeline {
+    /// Count events into buckets; with `group_by`, the `top` largest series are kept and the rest
+    /// summed into `other`
+    pub fn from_events(events: &[Value], bucket: Bucket, group_by: Option<GroupBy>, top: usize) -> Self {
+        let stamped: Vec<(i64, String)> = events.iter()
+            .filter_map(|event| {
+                let created_at = event.get("created_at")?.as_i64()?;
+                let key = match group_by {
+                    None => "all".to_string(),
+                    Some(GroupBy::Kind) => event.get("kind")?.as_i64()?.to_string(),
+                    Some(GroupBy::Author) => event.get("pubkey")?.as_str()?.to_string(),
+                };
+                Some((bucket.index(created_at), key))
+            })
+            .collect();
+
+        let Some(first) = stamped.iter().map(|(index, _)| *index).min() else {
+            return Self { bucket, first: 0, series: Vec::new() };
+        };
+        let last = stamped.iter().map(|(index, _)| *index).max().unwrap_or(first);
+        let width = (last - first + 1) as usize;
+
+        let mut counts: HashMap<String, Vec<u64>> = HashMap::new();
+        for (index, key) in stamped {
+            counts.entry(key).or_insert_with(|| vec![0; width])[(index - first) as usize] += 1;
+        }
+        let mut series: Vec<(String, Vec<u64>)> = counts.into_iter().collect();
+        series.sort_by(|(a_key, a), (b_key, b)| {
+            b.iter().sum::<u64>().cmp(&a.iter().sum::<u64>()).then_with(|| a_key.cmp(b_key))
+        });
+
+        if series.len() > top.max(1) {
+            let rest = series.split_off(top.max(1));
+            let mut other = vec![0; width];
+            for (_, counts) in rest {
+                for (total, count) in other.iter_mut().zip(counts) {
+                    *total += count;
+                }
+            }
+            series.push(("other".to_string(), other));
+        }
+
+        Self { bucket, first, series }
+    }
+
+    fn labels(&self) -> Vec<String> {
+        let width = self.series.first().map_or(0, |(_, counts)| counts.len());
+        (0..width as i64)
+            .map(|offset| date(self.bucket.start(self.first + offset)))
+            .collect()
+    }
+
+    /// `{"bucket", "buckets": [start dates], "series": {name: [counts]}}`
+    pub fn to_json(&self) -> Value {
+        let series: Map<String, Value> = self.series.iter()
+            .map(|(name, counts)| (name.clone(), json!(counts)))
+            .collect();
+        json!({
+            "bucket": self.bucket.name(),
+            "buckets": self.labels(),
+            "series": series,
+        })
+    }
+
+    /// One sparkline per series, merging neighbouring buckets to fit `width` columns
+    pub fn sparklines(&self, width: usize) -> Vec<String> {
+        let labels = self.labels();
+        let (Some(from), Some(to)) = (labels.first(), labels.last()) else {
+            return vec!["No events".to_string()];
+        };
+        let name_width = self.series.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+
+        let mut lines = vec![format!("{} to {}, {} {}(s)", from, to, labels.len(), self.bucket.name())];
+        for (name, counts) in &self.series {
+            let per_column = counts.len().div_ceil(width.max(1));
+            let columns: Vec<u64> = counts.chunks(per_column).map(|chunk| chunk.iter().sum()).collect();
+            lines.push(format!("{:<name_width$}  {}  {}", name, sparkline(&columns), counts.iter().sum::<u64>()));
+        }
+        lines
+    }
+}
+
+fn sparkline(values: &[u64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().max().unwrap_or(0);
+    values.iter()
+        .map(|&value| match value {
+            0 => ' ',
+            _ => BARS[(value * 7 / max) as usize],
+        })
+        .collect()
+}
+
+/// Events and content bytes of one group of events
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub events: u64,
+    pub content_bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, content_bytes: u64) {
+        self.events += 1;
+        self.content_bytes += content_bytes;
+    }
+}
+
+/// Per-bucket counts plus the top authors, kinds and tag names of a set of events
+#[derive(Debug)]
+pub struct Digest {
+    bucket: Bucket,
+    first: i64,
+    total: Tally,
+    buckets: Vec<Tally>,
+    authors: Vec<(String, Tally)>,
+    kinds: Vec<(String, Tally)>,
+    /// Tag names, counting each event once however often it uses the name
+    tags: Vec<(String, Tally)>,
+}
+
+/// Largest groups first, ties by key, cut to `top`
+fn top_groups(groups: HashMap<String, Tally>, top: usize) -> Vec<(String, Tally)> {
+    let mut groups: Vec<(String, Tally)> = groups.into_iter().collect();
+    groups.sort_by(|(a_key, a), (b_key, b)| b.events.cmp(&a.events).then_with(|| a_key.cmp(b_key)));
+    groups.truncate(top);
+    groups
+}
+
+impl Digest {
+    pub fn from_events(events: &[Value], bucket: Bucket, top: usize) -> Self {
+        let first = events.iter()
+            .filter_map(|event| event.get("created_at")?.as_i64())
+            .map(|created_at| bucket.index(created_at))
+            .min()
+            .unwrap_or(0);
+
+        let mut total = Tally::default();
+        let mut buckets: Vec<Tally> = Vec::new();
+        let mut authors: HashMap<String, Tally> = HashMap::new();
+        let mut kinds: HashMap<String, Tally> = HashMap::new();
+        let mut tags: HashMap<String, Tally> = HashMap::new();
+        for event in events {
+            let Some(created_at) = event.get("created_at").and_then(|t| t.as_i64()) else { continue };
+            let bytes = event.get("content").and_then(|c| c.as_str()).map_or(0, |c| c.len() as u64);
+            total.add(bytes);
+
+            let index = (bucket.index(created_at) - first) as usize;
+            if buckets.len() <= index {
+                buckets.resize(index + 1, Tally::default());
+            }
+            buckets[index].add(bytes);
+
+            if let Some(pubkey) = event.get("pubkey").and_then(|p| p.as_str()) {
+                authors.entry(pubkey.to_string()).or_default().add(bytes);
+            }
+            if let Some(kind) = event.get("kind").and_then(|k| k.as_i64()) {
+                kinds.entry(kind.to_string()).or_default().add(bytes);
+            }
+            let names: std::collections::BTreeSet<&str> = event.get("tags")
+                .and_then(|t| t.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|tag| tag.get(0)?.as_str())
+                .collect();
+            for name in names {
+                tags.entry(name.to_string()).or_default().add(bytes);
+            }
+        }
+
+        Self {
+            bucket,
+            first,
+            total,
+            buckets,
+            authors: top_groups(authors, top),
+            kinds: top_groups(kinds, top),
+            tags: top_groups(tags, top),
+        }
+    }
+
+    fn labels(&self) -> Vec<String> {
+        (0..self.buckets.len() as i64)
+            .map(|offset| date(self.bucket.start(self.first + offset)))
+            .collect()
+    }
+
+    /// Every group as `(section, key, tally)`: the total, each bucket, then the top
+    /// authors, kinds and tags
+    fn rows(&self) -> Vec<(&'static str, String, Tally)> {
+        let mut rows = vec![("total", String::new(), self.total)];
+        rows.extend(self.labels().into_iter().zip(self.buckets.iter().copied()).map(|(date, tally)| (self.bucket.name(), date, tally)));
+        for (section, groups) in [("author", &self.authors), ("kind", &self.kinds), ("tag", &self.tags)] {
+            rows.extend(groups.iter().map(|(key, tally)| (section, key.clone(), *tally)));
+        }
+        rows
+    }
+
+    /// `{"bucket", "events", "content_bytes", "series": [{"start", "events", "content_bytes"}],
+    /// "authors": [...], "kinds": [...], "tags": [...]}`
+    pub fn to_json(&self) -> Value {
+        let groups = |groups: &[(String, Tally)], key: &str| -> Value {
+            groups.iter()
+                .map(|(name, tally)| json!({key: name, "events": tally.events, "content_bytes": tally.content_bytes}))
+                .collect()
+        };
+        let series: Vec<Value> = self.labels().into_iter().zip(&self.buckets)
+            .map(|(start, tally)| json!({"start": start, "events": tally.events, "content_bytes": tally.content_bytes}))
+            .collect();
+        json!({
+            "bucket": self.bucket.name(),
+            "events": self.total.events,
+            "content_bytes": self.total.content_bytes,
+            "series": series,
+            "authors": groups(&self.authors, "pubkey"),
+            "kinds": groups(&self.kinds, "kind"),
+            "tags": groups(&self.tags, "tag"),
+        })
+    }
+
+    /// One row per group in long format, `section,key,events,content_bytes`, so a
+    /// spreadsheet can pivot on the section
+    pub fn csv(&self) -> Vec<String> {
+        let mut lines = vec!["section,key,events,content_bytes".to_string()];
+        for (section, key, tally) in self.rows() {
+            lines.push(format!("{},{},{},{}", section, crate::report::csv_field(&key), tally.events, tally.content_bytes));
+        }
+        lines
+    }
+}
+
+fn date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map_or_else(|| timestamp.to_string(), |date| date.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_buckets() {
+        // 2024-01-01 is a Monday
+        let monday = 1_704_067_200;
+        let events = vec![
+            json!({"created_at": monday + 10, "kind": 1, "pubkey": "a"}),
+            json!({"created_at": monday + 20, "kind": 1, "pubkey": "b"}),
+            json!({"created_at": monday + 2 * DAY, "kind": 7, "pubkey": "a"}),
+            json!({"created_at": monday + 8 * DAY, "kind": 1, "pubkey": "c"}),
+        ];
+
+        let daily = Timeline::from_events(&events, Bucket::Day, None, 10).to_json();
+        assert_eq!(daily["buckets"][0], "2024-01-01");
+        assert_eq!(daily["series"]["all"], json!([2, 0, 1, 0, 0, 0, 0, 0, 1]));
+
+        let weekly = Timeline::from_events(&events, Bucket::Week, Some(GroupBy::Kind), 10).to_json();
+        assert_eq!(weekly["buckets"], json!(["2024-01-01", "2024-01-08"]));
+        assert_eq!(weekly["series"]["1"], json!([2, 1]));
+        assert_eq!(weekly["series"]["7"], json!([1, 0]));
+
+        let authors = Timeline::from_events(&events, Bucket::Week, Some(GroupBy::Author), 1).to_json();
+        assert_eq!(authors["series"]["a"], json!([2, 0]));
+        assert_eq!(authors["series"]["other"], json!([1, 1]));
+
+        let lines = Timeline::from_events(&events, Bucket::Day, None, 10).sparklines(3);
+        assert_eq!(lines[1], "all  █ ▃  4");
+    }
+
+    #[test]
+    fn test_digest() {
+        let monday = 1_704_067_200;
+        let events = vec![
+            json!({"created_at": monday + 10, "kind": 1, "pubkey": "a", "content": "hello", "tags": [["t", "x"], ["t", "y"]]}),
+            json!({"created_at": monday + 20, "kind": 7, "pubkey": "b", "content": "+", "tags": [["e", "id"], ["p", "a"]]}),
+            json!({"created_at": monday + 2 * DAY, "kind": 1, "pubkey": "a", "content": "é", "tags": [["t", "z"]]}),
+        ];
+        assert_eq!(parse_bucket("1d"), Ok(Bucket::Day));
+        assert_eq!(parse_bucket("7d"), Ok(Bucket::Week));
+        assert!(parse_bucket("1h").is_err());
+
+        let digest = Digest::from_events(&events, Bucket::Day, 2).to_json();
+        assert_eq!(digest["events"], 3);
+        assert_eq!(digest["content_bytes"], 8);
+        assert_eq!(digest["series"], json!([
+            {"start": "2024-01-01", "events": 2, "content_bytes": 6},
+            {"start": "2024-01-02", "events": 0, "content_bytes": 0},
+            {"start": "2024-01-03", "events": 1, "content_bytes": 2},
+        ]));
+        assert_eq!(digest["authors"][0], json!({"pubkey": "a", "events": 2, "content_bytes": 7}));
+        assert_eq!(digest["kinds"][1], json!({"kind": "7", "events": 1, "content_bytes": 1}));
+        assert_eq!(digest["tags"], json!([
+            {"tag": "t", "events": 2, "content_bytes": 7},
+            {"tag": "e", "events": 1, "content_bytes": 1},
+        ]));
+
+        let csv = Digest::from_events(&events, Bucket::Week, 1).csv();
+        assert_eq!(csv, vec![
+            "section,key,events,content_bytes",
+            "total,,3,8",
+            "week,2024-01-01,3,8",
+            "author,a,2,7",
+            "kind,1,2,7",
+            "tag,t,2,7",
+        ]);
+        assert_eq!(Digest::from_events(&[], Bucket::Day, 10).to_json()["series"], json!([]));
+    }
+}
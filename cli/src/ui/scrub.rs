@@ -4,9 +4,12 @@ use crossterm::{
     style::{Color, Print, ResetColor, SetForegroundColor},
     terminal::{self, Clear, ClearType},
 };
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde_json::Value;
 use std::io::{self, Write};
 use std::collections::{HashMap, HashSet};
 use super::{colors, components::Counter, animations::TapeReel};
+use crate::report;
 
 pub struct ScrubUI {
     tape_reel: TapeReel,
@@ -646,7 +649,7 @@ impl ScrubUI {
         y += 1; // Extra spacing
         
         // Continue prompt - centered
-        let prompt_text = "Press q to exit";
+        let prompt_text = "Press any key to browse the tape, q to exit";
         let prompt_x = (term_width.saturating_sub(prompt_text.len())) / 2;
         execute!(
             stdout,
@@ -672,4 +675,299 @@ impl ScrubUI {
         io::stdout().flush()?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Events moved by PageUp/PageDown
+const PAGE: usize = 10;
+
+/// What typed keys go to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prompt {
+    /// Keys move through the tape
+    None,
+    /// A timestamp to jump to
+    Seek(String),
+    /// The filter to scrub with next
+    Filter(String),
+}
+
+/// What the caller has to do after a key
+#[derive(Debug, PartialEq)]
+pub enum DeckAction {
+    Redraw,
+    /// Read the cassette again with this filter and `load` the answer
+    Requery(Value),
+    Quit,
+}
+
+/// The scrubbed events as a tape: a position to move back and forth from,
+/// jumps to a timestamp, a filter to edit and re-run, and a detail pane
+pub struct ScrubDeck {
+    events: Vec<Value>,
+    position: usize,
+    filter: Value,
+    prompt: Prompt,
+    detail: bool,
+    message: Option<String>,
+}
+
+impl ScrubDeck {
+    pub fn new(events: Vec<Value>, filter: Value) -> Self {
+        Self { events, position: 0, filter, prompt: Prompt::None, detail: false, message: None }
+    }
+
+    /// Put the events a re-run filter returned on the tape, from the start
+    pub fn load(&mut self, filter: Value, events: Vec<Value>) {
+        self.message = Some(format!("{} events", events.len()));
+        self.events = events;
+        self.filter = filter;
+        self.position = 0;
+    }
+
+    /// Show a problem on the status line, e.g. a filter the cassette rejected
+    pub fn notify(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn current(&self) -> Option<&Value> {
+        self.events.get(self.position)
+    }
+
+    fn step(&mut self, forward: bool, by: usize) {
+        let last = self.events.len().saturating_sub(1);
+        self.position = if forward { self.position.saturating_add(by).min(last) } else { self.position.saturating_sub(by) };
+    }
+
+    /// Move to the newest event at or before `timestamp`, or the oldest one if all are newer
+    fn seek(&mut self, timestamp: i64) {
+        let created_at = |event: &Value| event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0);
+        let position = self.events.iter()
+            .enumerate()
+            .filter(|(_, event)| created_at(event) <= timestamp)
+            .max_by(|(a, x), (b, y)| created_at(x).cmp(&created_at(y)).then_with(|| b.cmp(a)))
+            .map(|(i, _)| i);
+        self.position = position.unwrap_or_else(|| {
+            self.events.iter().enumerate().min_by_key(|(_, event)| created_at(event)).map_or(0, |(i, _)| i)
+        });
+    }
+
+    pub fn handle(&mut self, key: KeyEvent) -> DeckAction {
+        if let Prompt::Seek(input) | Prompt::Filter(input) = &mut self.prompt {
+            match key.code {
+                KeyCode::Esc => self.prompt = Prompt::None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => return self.submit(),
+                _ => {}
+            }
+            return DeckAction::Redraw;
+        }
+        self.message = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return DeckAction::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return DeckAction::Quit,
+            KeyCode::Right | KeyCode::Down | KeyCode::Char('l') | KeyCode::Char('j') => self.step(true, 1),
+            KeyCode::Left | KeyCode::Up | KeyCode::Char('h') | KeyCode::Char('k') => self.step(false, 1),
+            KeyCode::PageDown => self.step(true, PAGE),
+            KeyCode::PageUp => self.step(false, PAGE),
+            KeyCode::Home => self.position = 0,
+            KeyCode::End => self.step(true, usize::MAX),
+            KeyCode::Enter | KeyCode::Char('d') => self.detail = !self.detail,
+            KeyCode::Char('t') => self.prompt = Prompt::Seek(String::new()),
+            KeyCode::Char('f') | KeyCode::Char('/') => self.prompt = Prompt::Filter(self.filter.to_string()),
+            _ => {}
+        }
+        DeckAction::Redraw
+    }
+
+    fn submit(&mut self) -> DeckAction {
+        match std::mem::replace(&mut self.prompt, Prompt::None) {
+            Prompt::Seek(input) => match parse_time(&input) {
+                Some(timestamp) => self.seek(timestamp),
+                None => self.message = Some(format!("Not a timestamp or date: {}", input)),
+            },
+            Prompt::Filter(input) => match serde_json::from_str::<Value>(&input) {
+                Ok(filter) if filter.is_object() => return DeckAction::Requery(filter),
+                _ => {
+                    self.message = Some("The filter must be a JSON object".to_string());
+                    self.prompt = Prompt::Filter(input);
+                }
+            },
+            Prompt::None => {}
+        }
+        DeckAction::Redraw
+    }
+}
+
+/// A unix timestamp, or a UTC date as YYYY-MM-DD with an optional HH:MM[:SS]
+pub fn parse_time(input: &str) -> Option<i64> {
+    let input = input.trim();
+    if let Ok(timestamp) = input.parse::<i64>() {
+        return Some(timestamp);
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = chrono::NaiveDateTime::parse_from_str(input, format) {
+            return Some(time.and_utc().timestamp());
+        }
+    }
+    chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d").ok()
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .map(|time| time.and_utc().timestamp())
+}
+
+impl ScrubUI {
+    /// Draw the deck: the tape around the current event, the filter, the detail pane
+    /// of the current event when open, and the prompt or key help at the bottom
+    pub fn show_deck(&self, deck: &ScrubDeck) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        execute!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
+
+        let total = deck.events.len();
+        let position = if total == 0 { 0 } else { deck.position + 1 };
+        let bar_width = 30;
+        let filled = if total == 0 { 0 } else { position * bar_width / total };
+        execute!(
+            stdout,
+            cursor::MoveTo(1, 0),
+            SetForegroundColor(colors::ACCENT_GREEN),
+            Print("▶ CASSETTE DECK  "),
+            Print("█".repeat(filled)),
+            SetForegroundColor(colors::DARK_GRAY),
+            Print("─".repeat(bar_width - filled)),
+            SetForegroundColor(colors::MEDIUM_GRAY),
+            Print(format!("  {}/{}", position, total)),
+            cursor::MoveTo(1, 1),
+            Print(format!("Filter: {}", report::preview(&deck.filter.to_string(), width.saturating_sub(10)))),
+            ResetColor
+        )?;
+
+        // The tape takes the top of the screen, or all of it with the detail pane closed
+        let bottom = height.saturating_sub(2);
+        let tape_rows = if deck.detail { (bottom.saturating_sub(3) / 3).max(3) } else { bottom.saturating_sub(3) };
+        let first = deck.position.saturating_sub(tape_rows / 2).min(total.saturating_sub(tape_rows));
+        for (row, (i, event)) in deck.events.iter().enumerate().skip(first).take(tape_rows).enumerate() {
+            let kind = event.get("kind").and_then(|k| k.as_u64()).unwrap_or(0);
+            let created_at = event.get("created_at").and_then(|t| t.as_i64()).unwrap_or(0);
+            let pubkey: String = event.get("pubkey").and_then(|p| p.as_str()).unwrap_or("").chars().take(8).collect();
+            let content = event.get("content").and_then(|c| c.as_str()).unwrap_or("");
+            let line = format!(
+                "{} {}  {:>5}  {}  {}",
+                if i == deck.position { "▶" } else { " " },
+                report::time(created_at),
+                kind,
+                pubkey,
+                content
+            );
+            let color = if i == deck.position { colors::event_kind_color(kind) } else { colors::MEDIUM_GRAY };
+            execute!(
+                stdout,
+                cursor::MoveTo(1, (row + 3) as u16),
+                SetForegroundColor(color),
+                Print(report::preview(&line, width.saturating_sub(2))),
+                ResetColor
+            )?;
+        }
+
+        if deck.detail {
+            let top = tape_rows + 4;
+            let event = deck.current().map(|e| serde_json::to_string_pretty(e).unwrap_or_default()).unwrap_or_default();
+            execute!(
+                stdout,
+                cursor::MoveTo(1, (top - 1) as u16),
+                SetForegroundColor(colors::OP1_ORANGE),
+                Print("📝 EVENT"),
+                ResetColor
+            )?;
+            let lines = event.lines().flat_map(|line| {
+                let chars: Vec<char> = line.chars().collect();
+                chars.chunks(width.saturating_sub(2).max(1)).map(|c| c.iter().collect::<String>()).collect::<Vec<_>>()
+            });
+            for (row, line) in lines.take(bottom.saturating_sub(top)).enumerate() {
+                execute!(
+                    stdout,
+                    cursor::MoveTo(1, (top + row) as u16),
+                    SetForegroundColor(colors::FOREGROUND),
+                    Print(line),
+                    ResetColor
+                )?;
+            }
+        }
+
+        let (status, color) = match (&deck.prompt, &deck.message) {
+            (Prompt::Seek(input), _) => (format!("Jump to (timestamp or YYYY-MM-DD[ HH:MM]): {}█", input), colors::OP1_ORANGE),
+            (Prompt::Filter(input), message) => {
+                let note = message.as_ref().map(|m| format!(" ({})", m)).unwrap_or_default();
+                (format!("Filter{}: {}█", note, input), colors::OP1_ORANGE)
+            }
+            (Prompt::None, Some(message)) => (message.clone(), colors::ACCENT_YELLOW),
+            (Prompt::None, None) => (
+                "←/→ step  PgUp/PgDn page  Home/End  t jump to time  f edit filter  d details  q quit".to_string(),
+                colors::DARK_GRAY,
+            ),
+        };
+        execute!(
+            stdout,
+            cursor::MoveTo(1, height.saturating_sub(1) as u16),
+            SetForegroundColor(color),
+            Print(report::preview(&status, width.saturating_sub(2))),
+            ResetColor
+        )?;
+        stdout.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_scrub_deck() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let events: Vec<Value> = [400, 300, 200, 100].iter().map(|t| json!({"created_at": t, "kind": 1})).collect();
+        let mut deck = ScrubDeck::new(events, json!({"kinds": [1]}));
+
+        deck.handle(key(KeyCode::Right));
+        deck.handle(key(KeyCode::Right));
+        assert_eq!(deck.position, 2);
+        deck.handle(key(KeyCode::Left));
+        assert_eq!(deck.position, 1);
+        deck.handle(key(KeyCode::PageDown));
+        assert_eq!(deck.position, 3);
+        deck.handle(key(KeyCode::Home));
+        assert_eq!(deck.position, 0);
+
+        // Jump to the newest event at or before the timestamp
+        for code in [KeyCode::Char('t'), KeyCode::Char('2'), KeyCode::Char('5'), KeyCode::Char('0'), KeyCode::Enter] {
+            deck.handle(key(code));
+        }
+        assert_eq!(deck.position, 2);
+        deck.seek(50);
+        assert_eq!(deck.position, 3);
+
+        deck.handle(key(KeyCode::Char('d')));
+        assert!(deck.detail);
+
+        // The filter prompt starts from the current filter and re-runs once it parses
+        deck.handle(key(KeyCode::Char('f')));
+        assert_eq!(deck.prompt, Prompt::Filter(r#"{"kinds":[1]}"#.to_string()));
+        deck.prompt = Prompt::Filter("{\"kinds\": [7".to_string());
+        assert_eq!(deck.handle(key(KeyCode::Enter)), DeckAction::Redraw);
+        assert!(matches!(deck.prompt, Prompt::Filter(_)));
+        deck.handle(key(KeyCode::Char(']')));
+        deck.handle(key(KeyCode::Char('}')));
+        assert_eq!(deck.handle(key(KeyCode::Enter)), DeckAction::Requery(json!({"kinds": [7]})));
+        deck.load(json!({"kinds": [7]}), vec![]);
+        assert!(deck.current().is_none());
+        assert_eq!(deck.handle(key(KeyCode::Char('q'))), DeckAction::Quit);
+
+        assert_eq!(parse_time("1700000000"), Some(1_700_000_000));
+        assert_eq!(parse_time("2024-01-01 00:00"), Some(1_704_067_200));
+        assert_eq!(parse_time("2024-01-01"), Some(1_704_153_599));
+        assert_eq!(parse_time("yesterday"), None);
+    }
+}
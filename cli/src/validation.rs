@@ -0,0 +1,446 @@
+//! Which events the CLI accepts into (and out of) cassettes
+//!
+//! `record`, `dub`, the deck and `scrub` all check events against one
+//! `ValidationPolicy`: the id must hash from the event, the schnorr signature
+//! must verify, tags must be arrays of strings, and optionally the content
+//! and the whole event must stay under a size, the kind must be on an allow
+//! list and off a deny list, and the author likewise. `PolicyArgs` in main.rs
+//! builds it from the same flags on every command. Each refusal names the
+//! `Rule` it broke, so imports can report how many events each rule dropped.
+//! Large imports are checked on every core with `partition`, which keeps the
+//! input order and returns the refused events' ids for a report file. A policy
+//! holding a `VerifiedIds` set skips signatures that already verified, and
+//! `verify_ahead` checks a stream's events on every core a batch at a time
+//! ahead of the one-by-one `admit`, which then finds them in the set.
+//!
+//! `record --verify` compiles the same id and signature checks into the
+//! cassette itself, for consumers who only have the wasm module.
+
+use anyhow::Result;
+use cassette_tools::NostrEvent;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::verified::VerifiedIds;
+
+/// Events `verify_ahead` reads and checks at once
+pub const VERIFY_BATCH: usize = 256;
+
+/// Which signature checks `record --verify` compiles into a cassette
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteVerification {
+    /// Export verify_events() and set_verify_on_query() for hosts to call
+    Exports,
+    /// Also skip events that fail the checks on every query, from the start
+    Query,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationPolicy {
+    /// Recompute the id from the event's fields
+    pub verify_id: bool,
+    /// Verify the schnorr signature over the id
+    pub verify_sig: bool,
+    /// Require `tags` to be an array of non-empty string arrays
+    pub check_tags: bool,
+    /// Largest accepted `content`, in bytes
+    pub max_content_bytes: Option<usize>,
+    /// Largest accepted serialized event, in bytes
+    pub max_event_bytes: Option<usize>,
+    /// Only these kinds are accepted, when set
+    pub kinds: Option<HashSet<i64>>,
+    /// Kinds that are always refused
+    pub denied_kinds: HashSet<i64>,
+    /// Only events by these pubkeys (lowercase hex) are accepted, when set
+    pub authors: Option<HashSet<String>>,
+    /// Pubkeys (lowercase hex) whose events are always refused
+    pub denied_authors: HashSet<String>,
+    /// Signatures that already verified, skipped and added to when set
+    pub verified: Option<Arc<VerifiedIds>>,
+}
+
+/// The check an event failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rule {
+    NotAnEvent,
+    KindNotAllowed,
+    KindDenied,
+    AuthorNotAllowed,
+    AuthorDenied,
+    ContentSize,
+    EventSize,
+    Malformed,
+    Tags,
+    Id,
+    Signature,
+}
+
+impl Rule {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rule::NotAnEvent => "not an event",
+            Rule::KindNotAllowed => "kind not in --allow-kinds",
+            Rule::KindDenied => "kind in --deny-kinds",
+            Rule::AuthorNotAllowed => "author not in --allow-pubkey",
+            Rule::AuthorDenied => "author in --deny-pubkey",
+            Rule::ContentSize => "content over --max-content-bytes",
+            Rule::EventSize => "event over --max-event-bytes",
+            Rule::Malformed => "missing or mistyped fields",
+            Rule::Tags => "malformed tags",
+            Rule::Id => "id mismatch",
+            Rule::Signature => "invalid signature",
+        }
+    }
+}
+
+/// Why an event was refused
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub rule: Rule,
+    pub reason: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+fn reject(rule: Rule, reason: impl Into<String>) -> Rejection {
+    Rejection { rule, reason: reason.into() }
+}
+
+/// Write refused events as NDJSON lines of id, rule and reason
+pub fn write_rejection_report(path: &Path, rejected: &[(String, Rejection)]) -> Result<()> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for (id, rejection) in rejected {
+        serde_json::to_writer(&mut out, &json!({"id": id, "rule": rejection.rule.label(), "reason": rejection.reason}))?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Events refused per rule over an import
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RejectionTally {
+    counts: BTreeMap<Rule, usize>,
+}
+
+impl RejectionTally {
+    pub fn add(&mut self, rule: Rule) {
+        *self.counts.entry(rule).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// One line per rule that dropped anything, most first
+    pub fn summary(&self) -> Vec<String> {
+        let mut counts: Vec<(&Rule, &usize)> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        counts.into_iter().map(|(rule, count)| format!("{:>8}  {}", count, rule.label())).collect()
+    }
+}
+
+impl Default for ValidationPolicy {
+    /// Every check on, no size or kind restrictions
+    fn default() -> Self {
+        Self {
+            verify_id: true,
+            verify_sig: true,
+            check_tags: true,
+            max_content_bytes: None,
+            max_event_bytes: None,
+            kinds: None,
+            denied_kinds: HashSet::new(),
+            authors: None,
+            denied_authors: HashSet::new(),
+            verified: None,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// Accepts anything that is an event object
+    pub fn permissive() -> Self {
+        Self { verify_id: false, verify_sig: false, check_tags: false, ..Self::default() }
+    }
+
+    /// Why the event is refused, if it is
+    pub fn check(&self, event: &Value) -> Result<()> {
+        Ok(self.verdict(event)?)
+    }
+
+    /// The rule the event breaks, if any
+    pub fn verdict(&self, event: &Value) -> std::result::Result<(), Rejection> {
+        let object = event.as_object().ok_or_else(|| reject(Rule::NotAnEvent, "event must be an object"))?;
+
+        let kind = event.get("kind").and_then(|k| k.as_i64());
+        if let Some(kind) = kind {
+            if self.kinds.as_ref().is_some_and(|kinds| !kinds.contains(&kind)) {
+                return Err(reject(Rule::KindNotAllowed, format!("kind {} is not allowed", kind)));
+            }
+            if self.denied_kinds.contains(&kind) {
+                return Err(reject(Rule::KindDenied, format!("kind {} is denied", kind)));
+            }
+        }
+        if self.authors.is_some() || !self.denied_authors.is_empty() {
+            let pubkey = event.get("pubkey").and_then(|p| p.as_str()).unwrap_or("").to_lowercase();
+            if self.authors.as_ref().is_some_and(|authors| !authors.contains(&pubkey)) {
+                return Err(reject(Rule::AuthorNotAllowed, format!("author {} is not allowed", pubkey)));
+            }
+            if self.denied_authors.contains(&pubkey) {
+                return Err(reject(Rule::AuthorDenied, format!("author {} is denied", pubkey)));
+            }
+        }
+        let content = event.get("content").and_then(|c| c.as_str()).unwrap_or("");
+        if let Some(max) = self.max_content_bytes {
+            if content.len() > max {
+                return Err(reject(Rule::ContentSize, format!("content is {} bytes, more than {}", content.len(), max)));
+            }
+        }
+        if let Some(max) = self.max_event_bytes {
+            let bytes = crate::sizes::event_bytes(event);
+            if bytes > max {
+                return Err(reject(Rule::EventSize, format!("event is {} bytes, more than {}", bytes, max)));
+            }
+        }
+        if !(self.verify_id || self.verify_sig || self.check_tags) {
+            return Ok(());
+        }
+
+        for field in ["id", "pubkey", "created_at", "kind", "tags", "content", "sig"] {
+            if !object.contains_key(field) {
+                return Err(reject(Rule::Malformed, format!("missing field {}", field)));
+            }
+        }
+        if !["id", "pubkey", "sig"].iter().all(|field| event[field].is_string()) {
+            return Err(reject(Rule::Malformed, "id, pubkey and sig must be strings"));
+        }
+        let (Some(_), Some(_), Some(tags), Some(_)) = (
+            event["created_at"].as_i64(),
+            kind,
+            event["tags"].as_array(),
+            event["content"].as_str(),
+        ) else {
+            return Err(reject(Rule::Malformed, "created_at, kind, tags or content has the wrong type"));
+        };
+
+        if self.check_tags {
+            let well_formed = tags.iter().all(|tag| {
+                tag.as_array().map_or(false, |items| !items.is_empty() && items.iter().all(|item| item.is_string()))
+            });
+            if !well_formed {
+                return Err(reject(Rule::Tags, "tags must be non-empty arrays of strings"));
+            }
+        }
+
+        if self.verify_id || self.verify_sig {
+            let typed = NostrEvent::from_value(event).map_err(|e| reject(Rule::Malformed, e.to_string()))?;
+            if self.verify_id && !typed.verify_id() {
+                return Err(reject(Rule::Id, format!("id does not match the event (computed {})", typed.compute_id())));
+            }
+            let verified = self.verified.as_ref().is_some_and(|verified| verified.contains(event));
+            if self.verify_sig && !verified {
+                typed.verify_signature().map_err(|e| reject(Rule::Signature, e.to_string()))?;
+                if let Some(verified) = &self.verified {
+                    verified.insert(event);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check events in parallel, returning the accepted ones in input order and the id and
+    /// rejection of each refused one
+    pub fn partition(&self, events: Vec<Value>) -> (Vec<Value>, Vec<(String, Rejection)>) {
+        let verdicts: Vec<(Value, std::result::Result<(), Rejection>)> = events.into_par_iter()
+            .map(|event| {
+                let verdict = self.verdict(&event);
+                (event, verdict)
+            })
+            .collect();
+        let mut accepted = Vec::with_capacity(verdicts.len());
+        let mut rejected = Vec::new();
+        for (event, verdict) in verdicts {
+            match verdict {
+                Ok(()) => accepted.push(event),
+                Err(rejection) => {
+                    let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("?").to_string();
+                    rejected.push((id, rejection));
+                }
+            }
+        }
+        (accepted, rejected)
+    }
+
+    /// Check a stream's events on every core, `VERIFY_BATCH` at a time, before they are
+    /// yielded; with a `verified` set, `admit` then only repeats the cheap checks
+    pub fn verify_ahead<I>(&self, events: I) -> VerifyAhead<'_, I>
+    where
+        I: Iterator<Item = Result<Value>>,
+    {
+        VerifyAhead { policy: self, events, batch: VecDeque::new(), error: None }
+    }
+
+    /// `check` as a filter, explaining refusals when verbose
+    pub fn admit(&self, event: &Value, verbose: bool) -> bool {
+        self.admit_counting(event, verbose, &mut RejectionTally::default())
+    }
+
+    /// `admit`, adding refusals to `tally` under the rule they broke
+    pub fn admit_counting(&self, event: &Value, verbose: bool, tally: &mut RejectionTally) -> bool {
+        match self.verdict(event) {
+            Ok(()) => true,
+            Err(rejection) => {
+                if verbose {
+                    let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("?");
+                    println!("❌ Event {} rejected: {}", id, rejection);
+                }
+                tally.add(rejection.rule);
+                false
+            }
+        }
+    }
+}
+
+/// Events of a stream, checked a batch at a time; see `ValidationPolicy::verify_ahead`
+pub struct VerifyAhead<'a, I> {
+    policy: &'a ValidationPolicy,
+    events: I,
+    batch: VecDeque<Value>,
+    /// An error reading the stream, yielded after the events before it
+    error: Option<anyhow::Error>,
+}
+
+impl<I: Iterator<Item = Result<Value>>> Iterator for VerifyAhead<'_, I> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty() && self.error.is_none() {
+            for event in self.events.by_ref().take(VERIFY_BATCH) {
+                match event {
+                    Ok(event) => self.batch.push_back(event),
+                    Err(e) => {
+                        self.error = Some(e);
+                        break;
+                    }
+                }
+            }
+            // Without a set to remember them in, the checks would have to run again
+            if self.policy.verify_sig && self.policy.verified.is_some() {
+                self.batch.make_contiguous().par_iter().for_each(|event| {
+                    let _ = self.policy.verdict(event);
+                });
+            }
+        }
+        match self.batch.pop_front() {
+            Some(event) => Some(Ok(event)),
+            None => self.error.take().map(Err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_policy_checks() {
+        let event = json!({
+            "kind": 7,
+            "id": "2a6b2a94d05974d2e390c95856df86f3742c0a1b65d6cca9f6705b41ae9ace47",
+            "pubkey": "8ac20c99a80a2c09e785533106b26a285ba4580d8f86030223cf4f2805414655",
+            "created_at": 1753605454,
+            "tags": [["e", "cdfb95a8409b30b156bf35d98e3bf5b5c3669e522b3b82450f8c94d43c5f7db9"], ["p", "3731e169d0b3816f4a4a9ac84c66aa423d4f4ea0f78b56a1df90609b85be0885"]],
+            "content": "+",
+            "sig": "dab8f13d9c6eb6dd238a9e847d2b57ae43d8f5787b50f34e7c1a99909b1375c4acef3ce668b5085ae1bd0dd37c4e55de78dc43818823b6a2b0a86237f79ee1b5"
+        });
+        let strict = ValidationPolicy::default();
+        assert!(strict.check(&event).is_ok());
+
+        let mut forged = event.clone();
+        forged["content"] = json!("-");
+        assert!(strict.check(&forged).is_err());
+        assert!(ValidationPolicy::permissive().check(&forged).is_ok());
+
+        let mut bad_sig = event.clone();
+        bad_sig["sig"] = json!("00".repeat(64));
+        assert!(strict.check(&bad_sig).is_err());
+        assert!(ValidationPolicy { verify_sig: false, ..strict.clone() }.check(&bad_sig).is_ok());
+
+        let mut bad_tags = event.clone();
+        bad_tags["tags"] = json!([["e", 1]]);
+        assert!(ValidationPolicy { verify_id: false, verify_sig: false, ..strict.clone() }.check(&bad_tags).is_err());
+
+        let limited = ValidationPolicy { kinds: Some(HashSet::from([1])), ..ValidationPolicy::permissive() };
+        assert!(limited.check(&event).is_err());
+        let small = ValidationPolicy { max_content_bytes: Some(0), ..ValidationPolicy::permissive() };
+        assert!(small.check(&event).is_err());
+        let tiny = ValidationPolicy { max_event_bytes: Some(100), ..ValidationPolicy::permissive() };
+        assert!(tiny.check(&event).is_err());
+        assert!(strict.check(&json!("not an event")).is_err());
+
+        let author = "8ac20c99a80a2c09e785533106b26a285ba4580d8f86030223cf4f2805414655".to_string();
+        let community = ValidationPolicy { authors: Some(HashSet::from([author.clone()])), ..ValidationPolicy::permissive() };
+        assert!(community.check(&event).is_ok());
+        let denied = ValidationPolicy { denied_authors: HashSet::from([author]), ..ValidationPolicy::permissive() };
+        let no_reactions = ValidationPolicy { denied_kinds: HashSet::from([7]), ..ValidationPolicy::permissive() };
+
+        let mut tally = RejectionTally::default();
+        for policy in [&denied, &no_reactions, &no_reactions, &tiny, &strict] {
+            policy.admit_counting(&event, false, &mut tally);
+        }
+        policy_rule(&strict, &bad_sig, Rule::Signature);
+        policy_rule(&strict, &forged, Rule::Id);
+        assert_eq!(tally.total(), 4);
+        assert_eq!(tally.summary()[0], format!("{:>8}  {}", 2, Rule::KindDenied.label()));
+
+        let cached = ValidationPolicy { verified: Some(Arc::new(VerifiedIds::default())), ..strict.clone() };
+        let stream = vec![Ok(event.clone()), Ok(bad_sig.clone()), Err(anyhow::anyhow!("read failed"))];
+        let checked: Vec<Result<Value>> = cached.verify_ahead(stream.into_iter()).collect();
+        assert!(checked[0].is_ok() && checked[1].is_ok() && checked[2].is_err());
+        let verified = cached.verified.as_ref().unwrap();
+        assert!(verified.contains(&event));
+        assert!(!verified.contains(&bad_sig));
+        assert!(cached.check(&event).is_ok());
+        policy_rule(&cached, &bad_sig, Rule::Signature);
+        policy_rule(&cached, &forged, Rule::Id);
+    }
+
+    #[test]
+    fn test_partition() {
+        let events = (0..100)
+            .map(|i| json!({"id": format!("e{}", i), "kind": i % 3, "content": ""}))
+            .collect::<Vec<_>>();
+        let policy = ValidationPolicy { denied_kinds: HashSet::from([2]), ..ValidationPolicy::permissive() };
+        let (accepted, rejected) = policy.partition(events);
+        let ids: Vec<String> = accepted.iter().map(|event| event["id"].as_str().unwrap().to_string()).collect();
+        assert_eq!(ids, (0..100).filter(|i| i % 3 != 2).map(|i| format!("e{}", i)).collect::<Vec<_>>());
+        assert_eq!(rejected.len(), 33);
+        assert_eq!(rejected[0].0, "e2");
+        assert!(rejected.iter().all(|(_, rejection)| rejection.rule == Rule::KindDenied));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rejected.ndjson");
+        write_rejection_report(&path, &rejected).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(report.lines().count(), 33);
+        let first: Value = serde_json::from_str(report.lines().next().unwrap()).unwrap();
+        assert_eq!(first, json!({"id": "e2", "rule": "kind in --deny-kinds", "reason": "kind 2 is denied"}));
+    }
+
+    fn policy_rule(policy: &ValidationPolicy, event: &Value, rule: Rule) {
+        assert_eq!(policy.verdict(event).unwrap_err().rule, rule);
+    }
+}
@@ -0,0 +1,131 @@
+//! Signature checks remembered across queries for `scrub`
+//!
+//! Verifying schnorr signatures dominates scrubbing a large answer, and a
+//! session asks the same cassette for the same events again (the interactive
+//! deck requeries on every filter edit). Each entry is the SHA-256 of the id,
+//! pubkey and sig an event passed with, so an event only skips the check when
+//! all three match one that verified. With `scrub --verified-cache` the entries
+//! are kept next to the cassette in `<cassette>.verified`, one hex hash per line,
+//! and the ones found during a run are appended when it ends. The file is
+//! trusted like the cassette beside it: whoever can write it can vouch for
+//! events.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+/// The cache file kept next to a cassette
+pub fn sidecar_path(cassette_path: &Path) -> PathBuf {
+    let mut name = cassette_path.as_os_str().to_owned();
+    name.push(".verified");
+    PathBuf::from(name)
+}
+
+/// What a signature check depends on; `None` for events missing a field
+fn key(event: &Value) -> Option<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    for field in ["id", "pubkey", "sig"] {
+        hasher.update(event.get(field)?.as_str()?.as_bytes());
+        hasher.update([0]);
+    }
+    Some(hasher.finalize().into())
+}
+
+/// Events whose signature verified, shared by every check of a session
+#[derive(Debug, Default)]
+pub struct VerifiedIds {
+    known: RwLock<HashSet<[u8; 32]>>,
+    /// Entries not yet in the file
+    added: Mutex<Vec<[u8; 32]>>,
+    /// File the entries are loaded from and saved to, for persisted caches
+    path: Option<PathBuf>,
+}
+
+impl VerifiedIds {
+    /// A cache kept in `path`; a missing file starts it empty and lines that
+    /// aren't a hash are skipped
+    pub fn load(path: &Path) -> Result<Self> {
+        let known: HashSet<[u8; 32]> = match std::fs::read_to_string(path) {
+            Ok(contents) => contents.lines()
+                .filter_map(|line| hex::decode(line.trim()).ok()?.try_into().ok())
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read verified cache {}", path.display())),
+        };
+        Ok(Self { known: RwLock::new(known), added: Mutex::default(), path: Some(path.to_path_buf()) })
+    }
+
+    pub fn contains(&self, event: &Value) -> bool {
+        key(event).is_some_and(|key| self.known.read().unwrap().contains(&key))
+    }
+
+    /// Remember that an event's signature verified
+    pub fn insert(&self, event: &Value) {
+        let Some(key) = key(event) else { return };
+        if self.known.write().unwrap().insert(key) {
+            self.added.lock().unwrap().push(key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.known.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append the entries found since loading to the file; returns how many were written
+    pub fn save(&self) -> Result<usize> {
+        let Some(path) = &self.path else { return Ok(0) };
+        let added = std::mem::take(&mut *self.added.lock().unwrap());
+        if added.is_empty() {
+            return Ok(0);
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)
+            .with_context(|| format!("Failed to open verified cache {}", path.display()))?;
+        let lines: String = added.iter().map(|key| format!("{}\n", hex::encode(key))).collect();
+        file.write_all(lines.as_bytes())
+            .with_context(|| format!("Failed to write verified cache {}", path.display()))?;
+        Ok(added.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_verified_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = dir.path().join("notes.cassette");
+        let path = sidecar_path(&cassette);
+        assert_eq!(path, dir.path().join("notes.cassette.verified"));
+
+        let event = json!({"id": "aa", "pubkey": "bb", "sig": "cc", "kind": 1});
+        let forged = json!({"id": "aa", "pubkey": "bd", "sig": "cc", "kind": 1});
+        let cache = VerifiedIds::load(&path).unwrap();
+        assert!(cache.is_empty());
+        cache.insert(&event);
+        cache.insert(&event);
+        cache.insert(&json!({"id": "no signature"}));
+        assert!(cache.contains(&event));
+        assert!(!cache.contains(&forged), "another pubkey for the same id and sig");
+        assert_eq!(cache.save().unwrap(), 1);
+        assert_eq!(cache.save().unwrap(), 0, "nothing new since the last save");
+
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"not a hash\n").unwrap();
+        let reloaded = VerifiedIds::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert!(reloaded.contains(&event));
+
+        let session = VerifiedIds::default();
+        session.insert(&event);
+        assert_eq!(session.save().unwrap(), 0, "session caches have no file");
+    }
+}
@@ -0,0 +1,222 @@
+//! Continuous recording from a directory for `record --watch`
+//!
+//! Log shippers drop event files into a directory; the watcher picks up each
+//! file once its size and modification time hold still for a poll, adds its
+//! events to a buffer and builds a cassette whenever the buffer reaches the
+//! same event, size or age limits a record-mode deck rotates at. Hidden files
+//! and `.tmp`/`.part` files are left alone, so writers can rename finished
+//! files into place; a file that changes after it was read is read again in
+//! full. The names of files whose events made it into a cassette are kept in
+//! `<name>.watch.json` in the output directory: after a restart those are
+//! skipped, while files that were only buffered are read again.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Size and modification time a file was last seen with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    pub bytes: u64,
+    pub modified: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    Some(Stamp { bytes: metadata.len(), modified })
+}
+
+/// Whether a directory entry is an event file rather than one still being written or a hidden one
+fn is_candidate(name: &str) -> bool {
+    !name.starts_with('.') && !name.ends_with(".tmp") && !name.ends_with(".part")
+}
+
+/// State file used for a watched recording named `name`
+pub fn state_path(output_dir: &Path, name: &str) -> PathBuf {
+    output_dir.join(format!("{}.watch.json", name))
+}
+
+/// Files of a watched directory, ready or done
+pub struct Watcher {
+    dir: PathBuf,
+    state_path: PathBuf,
+    /// Files whose events are in a cassette
+    done: BTreeMap<String, Stamp>,
+    /// Files read into the buffer but not built yet
+    buffered: BTreeMap<String, Stamp>,
+    /// Files seen once, waiting for their stamp to hold still
+    pending: HashMap<String, Stamp>,
+}
+
+impl Watcher {
+    /// Watch `dir`, skipping the files a previous run already built into cassettes
+    pub fn new(dir: &Path, state_path: &Path) -> Result<Self> {
+        let done = match std::fs::read_to_string(state_path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid watch state {}", state_path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read watch state {}", state_path.display())),
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            state_path: state_path.to_path_buf(),
+            done,
+            buffered: BTreeMap::new(),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Files that held still since the last poll, oldest name first; each is handed out once
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>> {
+        let mut seen = HashMap::new();
+        for entry in std::fs::read_dir(&self.dir).with_context(|| format!("Failed to read {}", self.dir.display()))? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if !is_candidate(&name) {
+                continue;
+            }
+            if let Some(stamp) = stamp(&path) {
+                seen.insert(name, stamp);
+            }
+        }
+
+        let mut ready: Vec<String> = seen.iter()
+            .filter(|(name, stamp)| {
+                self.pending.get(*name) == Some(stamp)
+                    && self.done.get(*name) != Some(stamp)
+                    && self.buffered.get(*name) != Some(stamp)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        ready.sort();
+        for name in &ready {
+            self.buffered.insert(name.clone(), seen[name]);
+        }
+        self.pending = seen;
+        Ok(ready.into_iter().map(|name| self.dir.join(name)).collect())
+    }
+
+    /// Remember the buffered files once their events are in a cassette
+    pub fn commit(&mut self) -> Result<()> {
+        self.done.append(&mut self.buffered);
+        // Forget files that have since been removed from the directory
+        let pending = &self.pending;
+        self.done.retain(|name, _| pending.contains_key(name));
+        let tmp = self.state_path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.done)?)
+            .with_context(|| format!("Failed to write watch state {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.state_path)
+            .with_context(|| format!("Failed to replace watch state {}", self.state_path.display()))
+    }
+}
+
+/// When a buffer is built into a cassette, as in a record-mode deck
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub events: usize,
+    pub bytes: usize,
+    /// Seconds since the first buffered event; 0 means no time limit
+    pub seconds: u64,
+}
+
+/// Events waiting for the next cassette
+#[derive(Default)]
+pub struct Buffer {
+    pub events: Vec<Value>,
+    ids: HashSet<String>,
+    bytes: usize,
+    started: Option<Instant>,
+}
+
+impl Buffer {
+    /// Add events, skipping ids already buffered; returns how many were new
+    pub fn extend(&mut self, events: Vec<Value>) -> usize {
+        let before = self.events.len();
+        for event in events {
+            let id = event.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+            if self.ids.insert(id) {
+                self.bytes += crate::sizes::event_bytes(&event);
+                self.events.push(event);
+            }
+        }
+        if self.events.len() > before {
+            self.started.get_or_insert_with(Instant::now);
+        }
+        self.events.len() - before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Whether the buffer reached any of the limits
+    pub fn is_full(&self, limits: &Limits) -> bool {
+        !self.is_empty()
+            && (self.events.len() >= limits.events
+                || self.bytes >= limits.bytes
+                || (limits.seconds > 0 && self.started.is_some_and(|start| start.elapsed().as_secs() >= limits.seconds)))
+    }
+
+    /// Hand the buffered events over for a build and start afresh
+    pub fn take(&mut self) -> Vec<Value> {
+        std::mem::take(self).events
+    }
+}
+
+/// Name of a cassette rotated out at this moment
+pub fn cassette_name(name: &str) -> String {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    format!("{}-{}", name, timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_watcher() {
+        let incoming = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let state = state_path(output.path(), "logs");
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect()
+        };
+
+        std::fs::write(incoming.path().join("a.jsonl"), "{}\n").unwrap();
+        std::fs::write(incoming.path().join("b.jsonl.part"), "{}\n").unwrap();
+        let mut watcher = Watcher::new(incoming.path(), &state).unwrap();
+        // A file is only ready once it held still for a poll
+        assert!(watcher.poll().unwrap().is_empty());
+        assert_eq!(names(watcher.poll().unwrap()), vec!["a.jsonl"]);
+        assert!(watcher.poll().unwrap().is_empty(), "handed out once");
+
+        // Buffered but not built: a restart reads it again
+        let mut restarted = Watcher::new(incoming.path(), &state).unwrap();
+        restarted.poll().unwrap();
+        assert_eq!(names(restarted.poll().unwrap()), vec!["a.jsonl"]);
+
+        watcher.commit().unwrap();
+        let mut restarted = Watcher::new(incoming.path(), &state).unwrap();
+        restarted.poll().unwrap();
+        assert!(restarted.poll().unwrap().is_empty());
+
+        let mut buffer = Buffer::default();
+        let limits = Limits { events: 2, bytes: usize::MAX, seconds: 0 };
+        assert_eq!(buffer.extend(vec![json!({"id": "x"}), json!({"id": "x"})]), 1);
+        assert!(!buffer.is_full(&limits));
+        buffer.extend(vec![json!({"id": "y"})]);
+        assert!(buffer.is_full(&limits));
+        assert_eq!(buffer.take().len(), 2);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.extend(vec![json!({"id": "x"})]), 1, "a new cassette starts with no ids");
+    }
+}
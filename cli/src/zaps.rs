@@ -0,0 +1,272 @@
+//! Zap receipt (NIP-57 kind 9735) validation for `record --validate-zaps`
+//!
+//! Anyone can publish a kind 9735 event, so an archive used for payment
+//! analytics has to tell real receipts from forged ones. A receipt is checked
+//! offline against the zap request in its `description` tag (invoice amount,
+//! description hash, recipient) and online against the recipient's LNURL
+//! server, whose `nostrPubkey` must be the receipt's signer. Receipts whose
+//! server can't be reached are counted as unverified, not invalid.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::nip19;
+
+/// Kind of a zap receipt
+pub const ZAP_RECEIPT_KIND: i64 = 9735;
+
+/// Kind of the zap request embedded in a receipt
+const ZAP_REQUEST_KIND: i64 = 9734;
+
+/// BOLT11 tagged field holding the sha256 of the invoice description
+const DESCRIPTION_HASH_FIELD: u8 = 23;
+
+/// What to do with a receipt that fails validation
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZapMode {
+    /// Keep the receipt and list it in the cassette metadata
+    Flag,
+    /// Leave the receipt out of the cassette (it is still listed in the metadata)
+    Drop,
+}
+
+/// Outcome of checking the receipts of an archive
+#[derive(Debug, Default)]
+pub struct ZapReport {
+    pub receipts: usize,
+    /// Id and reason of each invalid receipt
+    pub invalid: Vec<(String, String)>,
+    /// Receipts whose LNURL server couldn't be asked for its pubkey
+    pub unverified: usize,
+}
+
+impl ZapReport {
+    /// The `zaps` record embedded in the cassette metadata
+    pub fn metadata(&self, mode: ZapMode) -> Value {
+        json!({
+            "receipts": self.receipts,
+            "invalid": self.invalid.iter()
+                .map(|(id, reason)| json!({"id": id, "reason": reason}))
+                .collect::<Vec<_>>(),
+            "unverified": self.unverified,
+            "dropped": mode == ZapMode::Drop,
+        })
+    }
+}
+
+/// Check every zap receipt among `events`
+///
+/// Recipients' LNURL endpoints come from the zap request's `lnurl` tag or the
+/// recipient's kind 0 profile in the same archive; each server is asked once.
+pub async fn validate_receipts(events: &[Value], verbose: bool) -> ZapReport {
+    let profiles: HashMap<&str, &Value> = events.iter()
+        .filter(|event| event.get("kind").and_then(|k| k.as_i64()) == Some(0))
+        .filter_map(|event| Some((event.get("pubkey")?.as_str()?, event)))
+        .collect();
+    let mut server_pubkeys: HashMap<String, Option<String>> = HashMap::new();
+    let mut report = ZapReport::default();
+
+    for receipt in events.iter().filter(|e| e.get("kind").and_then(|k| k.as_i64()) == Some(ZAP_RECEIPT_KIND)) {
+        report.receipts += 1;
+        let id = receipt.get("id").and_then(|i| i.as_str()).unwrap_or("?").to_string();
+
+        let request = match check_receipt(receipt) {
+            Ok(request) => request,
+            Err(reason) => {
+                report.invalid.push((id, reason));
+                continue;
+            }
+        };
+        let Some(endpoint) = lnurl_endpoint(&request, &profiles) else {
+            report.unverified += 1;
+            continue;
+        };
+
+        if !server_pubkeys.contains_key(&endpoint) {
+            let pubkey = match server_pubkey(&endpoint).await {
+                Ok(pubkey) => Some(pubkey),
+                Err(e) => {
+                    if verbose {
+                        eprintln!("⚡ Can't verify zaps through {}: {}", endpoint, e);
+                    }
+                    None
+                }
+            };
+            server_pubkeys.insert(endpoint.clone(), pubkey);
+        }
+        let signer = receipt.get("pubkey").and_then(|p| p.as_str()).unwrap_or("");
+        match &server_pubkeys[&endpoint] {
+            Some(pubkey) if pubkey == signer => {}
+            Some(pubkey) => report.invalid.push((id, format!("signed by {} instead of the LNURL server's {}", signer, pubkey))),
+            None => report.unverified += 1,
+        }
+    }
+
+    report
+}
+
+/// The offline checks of one receipt; returns its zap request
+fn check_receipt(receipt: &Value) -> Result<Value, String> {
+    let bolt11 = tag_value(receipt, "bolt11").ok_or("missing bolt11 tag")?;
+    let invoice = Invoice::parse(bolt11).map_err(|e| format!("unreadable bolt11: {}", e))?;
+    let description = tag_value(receipt, "description").ok_or("missing description tag")?;
+    let request: Value = serde_json::from_str(description)
+        .ok()
+        .filter(|request: &Value| request.get("kind").and_then(|k| k.as_i64()) == Some(ZAP_REQUEST_KIND))
+        .ok_or("description is not a zap request")?;
+
+    if let Some(amount) = tag_value(&request, "amount") {
+        let amount: u64 = amount.parse().map_err(|_| "zap request amount is not a number")?;
+        if invoice.msats != Some(amount) {
+            let invoiced = invoice.msats.map_or("no amount".to_string(), |msats| format!("{} msats", msats));
+            return Err(format!("bolt11 has {}, the zap request asked for {} msats", invoiced, amount));
+        }
+    }
+    if let Some(hash) = &invoice.description_hash {
+        if Sha256::digest(description.as_bytes()).as_slice() != hash.as_slice() {
+            return Err("bolt11 description hash does not commit to the zap request".to_string());
+        }
+    }
+    if tag_value(receipt, "p") != tag_value(&request, "p") {
+        return Err("recipient does not match the zap request".to_string());
+    }
+
+    Ok(request)
+}
+
+/// The parts of a BOLT11 invoice a receipt is checked against
+#[derive(Debug, PartialEq)]
+struct Invoice {
+    msats: Option<u64>,
+    description_hash: Option<Vec<u8>>,
+}
+
+impl Invoice {
+    fn parse(bolt11: &str) -> Result<Self> {
+        let (hrp, words) = nip19::decode_words(bolt11)?;
+        let msats = amount_msats(&hrp)?;
+
+        // 7 words of timestamp, then tagged fields, then a 104-word signature
+        if words.len() < 7 + 104 {
+            return Err(anyhow!("invoice too short"));
+        }
+        let mut fields = &words[7..words.len() - 104];
+        let mut description_hash = None;
+        while fields.len() >= 3 {
+            let (tag, length) = (fields[0], fields[1] as usize * 32 + fields[2] as usize);
+            let data = fields.get(3..3 + length).ok_or_else(|| anyhow!("truncated invoice field"))?;
+            if tag == DESCRIPTION_HASH_FIELD && length == 52 {
+                description_hash = Some(nip19::convert_bits(data)?);
+            }
+            fields = &fields[3 + length..];
+        }
+
+        Ok(Self { msats, description_hash })
+    }
+}
+
+/// Amount of an invoice from its human-readable part (`lnbc2500u` is 250,000,000 msats)
+fn amount_msats(hrp: &str) -> Result<Option<u64>> {
+    let rest = hrp.strip_prefix("ln").ok_or_else(|| anyhow!("not a lightning invoice"))?;
+    let amount = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let Some(last) = amount.chars().last() else {
+        return Ok(None);
+    };
+    let (digits, multiplier) = if last.is_ascii_digit() {
+        (amount, None)
+    } else {
+        (&amount[..amount.len() - 1], Some(last))
+    };
+    let value: u64 = digits.parse().map_err(|_| anyhow!("invalid amount {}", amount))?;
+
+    // 1 BTC is 10^11 msats
+    let msats = match multiplier {
+        None => value.checked_mul(100_000_000_000),
+        Some('m') => value.checked_mul(100_000_000),
+        Some('u') => value.checked_mul(100_000),
+        Some('n') => value.checked_mul(100),
+        Some('p') if value % 10 == 0 => Some(value / 10),
+        _ => None,
+    };
+    msats.map(Some).ok_or_else(|| anyhow!("invalid amount {}", amount))
+}
+
+/// The LNURL-pay endpoint of a zap request's recipient
+fn lnurl_endpoint(request: &Value, profiles: &HashMap<&str, &Value>) -> Option<String> {
+    if let Some(lnurl) = tag_value(request, "lnurl") {
+        return decode_lnurl(lnurl);
+    }
+
+    let recipient = tag_value(request, "p")?;
+    let profile: Value = serde_json::from_str(profiles.get(recipient)?.get("content")?.as_str()?).ok()?;
+    if let Some((name, domain)) = profile.get("lud16").and_then(|l| l.as_str()).and_then(|l| l.split_once('@')) {
+        return Some(format!("https://{}/.well-known/lnurlp/{}", domain, name));
+    }
+    decode_lnurl(profile.get("lud06")?.as_str()?)
+}
+
+fn decode_lnurl(lnurl: &str) -> Option<String> {
+    let (hrp, bytes) = nip19::decode_bech32(lnurl).ok()?;
+    (hrp == "lnurl").then(|| String::from_utf8(bytes).ok()).flatten()
+}
+
+/// Ask an LNURL-pay endpoint for the pubkey it signs zap receipts with
+async fn server_pubkey(endpoint: &str) -> Result<String> {
+    let body = crate::relay_info::get(endpoint, "application/json", Duration::from_secs(5)).await?;
+    let response: Value = serde_json::from_str(&body).map_err(|_| anyhow!("not an LNURL-pay response"))?;
+    if response.get("allowsNostr").and_then(|a| a.as_bool()) != Some(true) {
+        return Err(anyhow!("server doesn't support zaps"));
+    }
+    response.get("nostrPubkey").and_then(|p| p.as_str())
+        .map(|p| p.to_string())
+        .ok_or_else(|| anyhow!("server has no nostrPubkey"))
+}
+
+/// First value of the first tag with this name
+fn tag_value<'a>(event: &'a Value, name: &str) -> Option<&'a str> {
+    event.get("tags")?.as_array()?.iter()
+        .filter_map(|tag| tag.as_array())
+        .find(|tag| tag.first().and_then(|n| n.as_str()) == Some(name))
+        .and_then(|tag| tag.get(1)?.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 21u invoice whose description hash commits to REQUEST; zeroed signature
+    const BOLT11: &str = "lnbc21u1pj48ugqhp54d33as96qv4hrl88j4n89kuu5xaf7cd697k0zudlmh56dnun5ecqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq28hn7r";
+    const REQUEST: &str = r#"{"kind":9734,"pubkey":"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa","created_at":1700000000,"tags":[["p","bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"],["amount","2100000"],["relays","wss://relay.example"]],"content":"","id":"cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc","sig":"dddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd"}"#;
+
+    fn receipt(bolt11: &str, description: &str, recipient: &str) -> Value {
+        json!({"id": "r", "kind": 9735, "pubkey": "e".repeat(64), "tags": [
+            ["p", recipient], ["bolt11", bolt11], ["description", description]]})
+    }
+
+    #[test]
+    fn test_receipt_checks() {
+        assert_eq!(amount_msats("lnbc2500u").unwrap(), Some(250_000_000));
+        assert_eq!(amount_msats("lnbcrt10n").unwrap(), Some(1_000));
+        assert_eq!(amount_msats("lntb").unwrap(), None);
+        assert!(amount_msats("lnbc15p").is_err());
+
+        let recipient = "b".repeat(64);
+        let request = check_receipt(&receipt(BOLT11, REQUEST, &recipient)).unwrap();
+        assert_eq!(tag_value(&request, "amount"), Some("2100000"));
+
+        // A request for another amount, a rewritten description or another recipient don't pass
+        let cheaper = REQUEST.replace("2100000", "1000");
+        assert!(check_receipt(&receipt(BOLT11, &cheaper, &recipient)).unwrap_err().contains("asked for 1000 msats"));
+        let edited = REQUEST.replace("wss://relay.example", "wss://other.example");
+        assert!(check_receipt(&receipt(BOLT11, &edited, &recipient)).unwrap_err().contains("description hash"));
+        assert!(check_receipt(&receipt(BOLT11, REQUEST, &"f".repeat(64))).unwrap_err().contains("recipient"));
+        assert!(check_receipt(&receipt("lnbc1invalid", REQUEST, &recipient)).unwrap_err().contains("bolt11"));
+
+        let profile = json!({"kind": 0, "pubkey": recipient, "content": r#"{"lud16":"alice@pay.example"}"#});
+        let profiles = HashMap::from([(recipient.as_str(), &profile)]);
+        assert_eq!(lnurl_endpoint(&request, &profiles).as_deref(), Some("https://pay.example/.well-known/lnurlp/alice"));
+    }
+}
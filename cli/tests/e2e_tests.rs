@@ -0,0 +1,239 @@
+//! End-to-end tests of the record → scrub → dub → listen lifecycle
+//!
+//! These drive the real `cassette` binary: cassettes are generated from the
+//! fixtures in `tests/fixtures/e2e/` with the CLI's embedded cassette-tools and
+//! compiled for wasm32, so they need the `wasm32-unknown-unknown` target and
+//! take a while. Run them with:
+//!
+//! ```bash
+//! cargo test --features e2e --test e2e_tests
+//! ```
+#![cfg(feature = "e2e")]
+
+use futures_util::{SinkExt, StreamExt};
+use sha2::Digest;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const ALICE: &str = "e771af0b05c8e95fcdf6feb3500544d2fb1ccd384788e9f490bb3ee28e8ed66f";
+const BOB: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/e2e").join(name)
+}
+
+/// Run the CLI to completion and return its stdout, failing the test on a non-zero exit
+fn cassette(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_cassette"))
+        .args(args)
+        .output()
+        .expect("failed to run cassette");
+    assert!(
+        output.status.success(),
+        "cassette {} failed:\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn record(input: &Path, name: &str, output_dir: &Path) -> PathBuf {
+    cassette(&[
+        "record",
+        input.to_str().unwrap(),
+        "--name", name,
+        "--output", output_dir.to_str().unwrap(),
+        "--nip-45",
+    ]);
+    let path = output_dir.join(format!("{}.cassette", name));
+    assert!(path.exists(), "record did not write {}", path.display());
+    path
+}
+
+/// Events a scrub returns, as parsed from its ndjson output
+fn scrub(cassette_path: &Path, args: &[&str]) -> Vec<Value> {
+    let mut full = vec!["scrub", cassette_path.to_str().unwrap(), "--output", "ndjson"];
+    full.extend_from_slice(args);
+    cassette(&full).lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).expect("scrub printed invalid JSON"))
+        .collect()
+}
+
+fn count(cassette_path: &Path, args: &[&str]) -> u64 {
+    let mut full = vec!["scrub", cassette_path.to_str().unwrap(), "--count"];
+    full.extend_from_slice(args);
+    let stdout = cassette(&full);
+    let start = stdout.find('{').expect("scrub --count printed no count");
+    let counted: Value = serde_json::from_str(&stdout[start..]).expect("scrub --count printed invalid JSON");
+    counted["count"].as_u64().expect("count is not a number")
+}
+
+fn authors(events: &[Value]) -> Vec<&str> {
+    let mut authors: Vec<&str> = events.iter().map(|e| e["pubkey"].as_str().unwrap()).collect();
+    authors.sort();
+    authors.dedup();
+    authors
+}
+
+/// A `cassette listen` process, killed when dropped
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Start `cassette listen` on a free port and wait until it accepts connections
+async fn listen(cassette_path: &Path, args: &[&str]) -> (Server, String) {
+    let port = free_port();
+    let server = Server(
+        Command::new(env!("CARGO_BIN_EXE_cassette"))
+            .args(["listen", cassette_path.to_str().unwrap(), "--port", &port.to_string()])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start listen"),
+    );
+    let url = format!("ws://127.0.0.1:{}", port);
+    let started = Instant::now();
+    while connect_async(url.as_str()).await.is_err() {
+        assert!(started.elapsed() < Duration::from_secs(30), "listen did not come up on {}", url);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    (server, url)
+}
+
+/// Send one message and collect the replies up to the one that ends it (EOSE, COUNT, CLOSED)
+async fn query(url: &str, message: Value) -> Vec<Value> {
+    let (mut socket, _) = connect_async(url).await.expect("failed to connect to listen");
+    socket.send(Message::Text(message.to_string())).await.unwrap();
+    let mut replies = Vec::new();
+    while let Some(reply) = tokio::time::timeout(Duration::from_secs(30), socket.next()).await.expect("listen stopped answering") {
+        let Message::Text(text) = reply.unwrap() else {
+            continue;
+        };
+        let reply: Value = serde_json::from_str(&text).unwrap();
+        let done = matches!(reply[0].as_str(), Some("EOSE" | "COUNT" | "CLOSED"));
+        replies.push(reply);
+        if done {
+            break;
+        }
+    }
+    replies
+}
+
+#[tokio::test]
+async fn test_record_to_serve_lifecycle() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // record
+    let alice = record(&fixture("notes-a.json"), "alice", dir.path());
+    let bob = record(&fixture("notes-b.json"), "bob", dir.path());
+
+    // scrub
+    let events = scrub(&alice, &[]);
+    assert_eq!(events.len(), 4);
+    assert_eq!(authors(&events), vec![ALICE]);
+    assert_eq!(scrub(&alice, &["--limit", "2"]).len(), 2);
+    assert_eq!(scrub(&bob, &["--kinds", "7"]).len(), 1);
+    assert_eq!(scrub(&bob, &["--filter", &json!({"#t": ["value1"]}).to_string()]).len(), 1);
+
+    // count
+    assert_eq!(count(&alice, &[]), 4);
+    assert_eq!(count(&bob, &["--kinds", "1"]), 1);
+
+    // dub
+    let mixed = dir.path().join("mixed.cassette");
+    cassette(&["dub", alice.to_str().unwrap(), bob.to_str().unwrap(), mixed.to_str().unwrap(), "--name", "mixed"]);
+    assert_eq!(scrub(&mixed, &[]).len(), 6);
+    assert_eq!(count(&mixed, &["--kinds", "1"]), 5);
+
+    // listen
+    let (_server, url) = listen(&mixed, &[]).await;
+
+    let replies = query(&url, json!(["REQ", "e2e", {"authors": [BOB, ALICE], "kinds": [1]}])).await;
+    let (eose, events) = replies.split_last().unwrap();
+    assert_eq!(eose, &json!(["EOSE", "e2e"]));
+    let events: Vec<Value> = events.iter()
+        .map(|reply| {
+            assert_eq!(reply[0], "EVENT");
+            reply[2].clone()
+        })
+        .collect();
+    assert_eq!(events.len(), 5);
+    assert_eq!(authors(&events), vec![BOB, ALICE]);
+
+    let replies = query(&url, json!(["COUNT", "e2e-count", {"authors": [BOB]}])).await;
+    assert_eq!(replies, vec![json!(["COUNT", "e2e-count", {"count": 1, "approximate": false}])]);
+}
+
+/// The next message on a connection, as JSON
+async fn receive<S>(socket: &mut S) -> Value
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let reply = tokio::time::timeout(Duration::from_secs(30), socket.next()).await.expect("listen stopped answering");
+    serde_json::from_str(&reply.unwrap().unwrap().into_text().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_listen_require_auth() {
+    use secp256k1::{KeyPair, Message as SignedMessage, Secp256k1};
+
+    let dir = tempfile::tempdir().unwrap();
+    let alice = record(&fixture("notes-a.json"), "alice", dir.path());
+    let (_server, url) = listen(&alice, &["--require-auth"]).await;
+
+    let (mut socket, _) = connect_async(url.as_str()).await.unwrap();
+    let challenge = receive(&mut socket).await;
+    assert_eq!(challenge[0], "AUTH");
+    let challenge = challenge[1].as_str().unwrap().to_string();
+
+    socket.send(Message::Text(json!(["REQ", "early", {}]).to_string())).await.unwrap();
+    let closed = receive(&mut socket).await;
+    assert_eq!(closed[0], "CLOSED");
+    assert!(closed[2].as_str().unwrap().starts_with("auth-required:"));
+
+    // A kind 22242 event signed over the challenge and this relay's URL
+    let secp = Secp256k1::new();
+    let keypair = KeyPair::from_seckey_slice(&secp, &[7u8; 32]).unwrap();
+    let mut event = json!({
+        "pubkey": hex::encode(keypair.x_only_public_key().0.serialize()),
+        "created_at": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+        "kind": 22242,
+        "tags": [["relay", url], ["challenge", challenge]],
+        "content": "",
+    });
+    let serialized = json!([0, event["pubkey"], event["created_at"], event["kind"], event["tags"], event["content"]]);
+    let id = sha2::Sha256::digest(serialized.to_string().as_bytes());
+    let signature = secp.sign_schnorr_no_aux_rand(&SignedMessage::from_slice(&id).unwrap(), &keypair);
+    event["id"] = json!(hex::encode(id));
+    event["sig"] = json!(hex::encode(signature.as_ref()));
+
+    socket.send(Message::Text(json!(["AUTH", event]).to_string())).await.unwrap();
+    let ok = receive(&mut socket).await;
+    assert_eq!(ok, json!(["OK", event["id"], true, ""]));
+
+    socket.send(Message::Text(json!(["REQ", "after", {"kinds": [1]}]).to_string())).await.unwrap();
+    let mut served = 0;
+    loop {
+        let reply = receive(&mut socket).await;
+        match reply[0].as_str() {
+            Some("EVENT") => served += 1,
+            Some("EOSE") => break,
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+    assert!(served > 0);
+}
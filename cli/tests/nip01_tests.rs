@@ -1,119 +1,26 @@
 // NIP-01 implementation tests
-use serde::{Deserialize, Serialize};
-use serde_json::json;
+use cassette_tools::Filter;
+use serde_json::{json, Value};
 
-// Define the same Note struct as in the main code
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct Note {
-    id: String,
-    pubkey: String,
-    created_at: i64,
-    kind: i64,
-    tags: Vec<Vec<String>>,
-    content: String,
-    sig: String,
-}
-
-// Define the Filter struct as in the main code
-#[derive(Debug, Deserialize)]
-struct Filter {
-    #[serde(default)]
-    ids: Option<Vec<String>>,
-    #[serde(default)]
-    authors: Option<Vec<String>>,
-    #[serde(default)]
-    kinds: Option<Vec<i64>>,
-    #[serde(default, flatten)]
-    tag_filters: std::collections::HashMap<String, Vec<String>>,
-    #[serde(default)]
-    since: Option<i64>,
-    #[serde(default)]
-    until: Option<i64>,
-    #[serde(default)]
-    limit: Option<usize>,
-}
-
-// Implement the filter matching functionality directly in the test file
-fn matches_filter(event: &Note, filter: &Filter) -> bool {
-    // Check for ID match
-    if let Some(ids) = &filter.ids {
-        if !ids.iter().any(|id| id == &event.id) {
-            return false;
-        }
-    }
-
-    // Check for author match
-    if let Some(authors) = &filter.authors {
-        if !authors.iter().any(|author| author == &event.pubkey) {
-            return false;
-        }
-    }
-
-    // Check for kind match
-    if let Some(kinds) = &filter.kinds {
-        if !kinds.iter().any(|kind| *kind == event.kind) {
-            return false;
-        }
-    }
-
-    // Check for time range match
-    if let Some(since) = filter.since {
-        if event.created_at < since {
-            return false;
-        }
-    }
-
-    if let Some(until) = filter.until {
-        if event.created_at > until {
-            return false;
-        }
-    }
-
-    // Check for tag filters
-    for (key, values) in &filter.tag_filters {
-        if key.starts_with('&') && key.len() == 2 {
-            // NIP-1.9 tag filter: All values must match
-            let tag_name = &key[1..];
-            let tag_values: Vec<_> = event.tags.iter()
-                .filter(|t| t.get(0).map_or(false, |n| n == tag_name))
-                .filter_map(|t| t.get(1))
-                .collect();
-
-            if !values.iter().all(|v| tag_values.contains(&v)) {
-                return false;
-            }
-        } else if key.starts_with('#') && key.len() == 2 {
-            // Regular tag filter: Any value must match
-            let tag_name = &key[1..];
-            let tag_values: Vec<_> = event.tags.iter()
-                .filter(|t| t.get(0).map_or(false, |n| n == tag_name))
-                .filter_map(|t| t.get(1))
-                .collect();
-
-            if !values.iter().any(|v| tag_values.contains(&v)) {
-                return false;
-            }
-        }
-    }
-
-    true
+fn parse(value: Value) -> Filter {
+    Filter::from_json(&value).expect("valid filter")
 }
 
 // Helper function to create a test note
-fn create_test_note() -> Note {
-    Note {
-        id: "test_id".to_string(),
-        pubkey: "test_pubkey".to_string(),
-        created_at: 1234567890,
-        kind: 1,
-        tags: vec![
-            vec!["t".to_string(), "tag1".to_string()],
-            vec!["t".to_string(), "tag2".to_string()],
-            vec!["p".to_string(), "pubkey1".to_string()],
+fn create_test_note() -> Value {
+    json!({
+        "id": "test_id",
+        "pubkey": "test_pubkey",
+        "created_at": 1234567890,
+        "kind": 1,
+        "tags": [
+            ["t", "tag1"],
+            ["t", "tag2"],
+            ["p", "pubkey1"]
         ],
-        content: "test content".to_string(),
-        sig: "test_sig".to_string(),
-    }
+        "content": "test content",
+        "sig": "test_sig"
+    })
 }
 
 #[test]
@@ -121,28 +28,12 @@ fn test_id_filter() {
     let note = create_test_note();
     
     // Test with matching ID
-    let filter = Filter {
-        ids: Some(vec!["test_id".to_string()]),
-        authors: None,
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(matches_filter(&note, &filter), "Note should match filter with matching ID");
+    let filter = parse(json!({"ids": ["test_id"]}));
+    assert!(filter.matches(&note), "Note should match filter with matching ID");
     
     // Test with non-matching ID
-    let filter = Filter {
-        ids: Some(vec!["wrong_id".to_string()]),
-        authors: None,
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter with non-matching ID");
+    let filter = parse(json!({"ids": ["wrong_id"]}));
+    assert!(!filter.matches(&note), "Note should not match filter with non-matching ID");
 }
 
 #[test]
@@ -150,28 +41,12 @@ fn test_author_filter() {
     let note = create_test_note();
     
     // Test with matching author
-    let filter = Filter {
-        ids: None,
-        authors: Some(vec!["test_pubkey".to_string()]),
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(matches_filter(&note, &filter), "Note should match filter with matching author");
+    let filter = parse(json!({"authors": ["test_pubkey"]}));
+    assert!(filter.matches(&note), "Note should match filter with matching author");
     
     // Test with non-matching author
-    let filter = Filter {
-        ids: None,
-        authors: Some(vec!["wrong_pubkey".to_string()]),
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter with non-matching author");
+    let filter = parse(json!({"authors": ["wrong_pubkey"]}));
+    assert!(!filter.matches(&note), "Note should not match filter with non-matching author");
 }
 
 #[test]
@@ -179,28 +54,12 @@ fn test_kind_filter() {
     let note = create_test_note();
     
     // Test with matching kind
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: Some(vec![1]),
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(matches_filter(&note, &filter), "Note should match filter with matching kind");
+    let filter = parse(json!({"kinds": [1]}));
+    assert!(filter.matches(&note), "Note should match filter with matching kind");
     
     // Test with non-matching kind
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: Some(vec![2]),
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter with non-matching kind");
+    let filter = parse(json!({"kinds": [2]}));
+    assert!(!filter.matches(&note), "Note should not match filter with non-matching kind");
 }
 
 #[test]
@@ -208,40 +67,16 @@ fn test_timestamp_filters() {
     let note = create_test_note();
     
     // Test with matching time range
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: Some(1234567889),
-        until: Some(1234567891),
-        limit: None,
-    };
-    assert!(matches_filter(&note, &filter), "Note should match filter with matching time range");
+    let filter = parse(json!({"since": 1234567889, "until": 1234567891}));
+    assert!(filter.matches(&note), "Note should match filter with matching time range");
     
     // Test with out-of-range timestamp (too early)
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: Some(1234567891),
-        until: None,
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter with timestamp before since");
+    let filter = parse(json!({"since": 1234567891}));
+    assert!(!filter.matches(&note), "Note should not match filter with timestamp before since");
     
     // Test with out-of-range timestamp (too late)
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters: std::collections::HashMap::new(),
-        since: None,
-        until: Some(1234567889),
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter with timestamp after until");
+    let filter = parse(json!({"until": 1234567889}));
+    assert!(!filter.matches(&note), "Note should not match filter with timestamp after until");
 }
 
 #[test]
@@ -249,64 +84,20 @@ fn test_tag_filters() {
     let note = create_test_note();
     
     // Test with matching tag
-    let mut tag_filters = std::collections::HashMap::new();
-    tag_filters.insert("#t".to_string(), vec!["tag1".to_string()]);
-    
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters,
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(matches_filter(&note, &filter), "Note should match filter with matching tag");
+    let filter = parse(json!({"#t": ["tag1"]}));
+    assert!(filter.matches(&note), "Note should match filter with matching tag");
     
     // Test with non-matching tag
-    let mut tag_filters = std::collections::HashMap::new();
-    tag_filters.insert("#t".to_string(), vec!["nonexistent".to_string()]);
-    
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters,
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter with non-matching tag");
+    let filter = parse(json!({"#t": ["nonexistent"]}));
+    assert!(!filter.matches(&note), "Note should not match filter with non-matching tag");
     
     // Test with NIP-1.9 tag filter (all values must match)
-    let mut tag_filters = std::collections::HashMap::new();
-    tag_filters.insert("&t".to_string(), vec!["tag1".to_string(), "tag2".to_string()]);
-    
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters,
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(matches_filter(&note, &filter), "Note should match filter with all specified tag values");
+    let filter = parse(json!({"&t": ["tag1", "tag2"]}));
+    assert!(filter.matches(&note), "Note should match filter with all specified tag values");
     
     // Test with NIP-1.9 tag filter (not all values match)
-    let mut tag_filters = std::collections::HashMap::new();
-    tag_filters.insert("&t".to_string(), vec!["tag1".to_string(), "nonexistent".to_string()]);
-    
-    let filter = Filter {
-        ids: None,
-        authors: None,
-        kinds: None,
-        tag_filters,
-        since: None,
-        until: None,
-        limit: None,
-    };
-    assert!(!matches_filter(&note, &filter), "Note should not match filter when not all tag values match");
+    let filter = parse(json!({"&t": ["tag1", "nonexistent"]}));
+    assert!(!filter.matches(&note), "Note should not match filter when not all tag values match");
 }
 
 #[test]